@@ -10,8 +10,10 @@ use anyhow::Result;
 use narwhal_config::Parameters as ConsensusParameters;
 use once_cell::sync::OnceCell;
 use rand::rngs::OsRng;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use std::collections::{BTreeMap, HashSet};
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
@@ -20,6 +22,7 @@ use std::time::Duration;
 use std::usize;
 use sui_keys::keypair_file::{read_authority_keypair_from_file, read_keypair_from_file};
 use sui_protocol_config::SupportedProtocolVersions;
+use sui_storage::object_store::retention::RetentionPolicy;
 use sui_storage::object_store::ObjectStoreConfig;
 use sui_types::base_types::{ObjectID, SuiAddress};
 use sui_types::crypto::AuthorityPublicKeyBytes;
@@ -27,6 +30,7 @@ use sui_types::crypto::KeypairTraits;
 use sui_types::crypto::NetworkKeyPair;
 use sui_types::crypto::SuiKeyPair;
 use sui_types::crypto::{get_key_pair_from_rng, AccountKeyPair, AuthorityKeyPair};
+use sui_types::messages_checkpoint::CheckpointSequenceNumber;
 use sui_types::multiaddr::Multiaddr;
 use tracing::info;
 
@@ -236,6 +240,14 @@ impl NodeConfig {
         self.db_path.join("archive")
     }
 
+    pub fn staging_path(&self) -> PathBuf {
+        self.db_checkpoint_config
+            .staging
+            .as_ref()
+            .and_then(|staging| staging.path.clone())
+            .unwrap_or_else(|| self.db_path.join("staging"))
+    }
+
     pub fn network_address(&self) -> &Multiaddr {
         &self.network_address
     }
@@ -268,6 +280,125 @@ impl NodeConfig {
             })
             .collect()
     }
+
+    /// Configurations where local pruning deletes data before it is backed up or archived
+    pub fn retention_conflicts(&self) -> Vec<RetentionConflict> {
+        check_retention_compatibility(
+            &self.authority_store_pruning_config,
+            &self.db_checkpoint_config,
+            &self.state_archive_write_config,
+            &self.state_archive_read_config,
+        )
+    }
+}
+
+/// Configuration combinations where local pruning permanently deletes data before the backup
+/// or archival pipeline has captured it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetentionConflict {
+    /// Checkpoint contents are pruned while no state archive captures them
+    CheckpointPruningWithoutArchive {
+        num_epochs_to_retain_for_checkpoints: u64,
+    },
+    /// A state archive is written, but the pruner doesn't wait for it before deleting
+    /// checkpoint contents
+    ArchiveNotUsedForPruningWatermark {
+        num_epochs_to_retain_for_checkpoints: u64,
+    },
+    /// Uploaded db checkpoints are pruned before upload while no state archive captures the
+    /// pruned history
+    PrunedDbCheckpointsWithoutArchive { num_epochs_to_retain: u64 },
+    /// Pruning is asked to wait for db checkpoint uploads which the node never performs, the
+    /// watermark is ignored
+    BackupWatermarkWithoutUploads,
+}
+
+impl std::fmt::Display for RetentionConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetentionConflict::CheckpointPruningWithoutArchive {
+                num_epochs_to_retain_for_checkpoints,
+            } => write!(
+                f,
+                "Checkpoint contents older than {num_epochs_to_retain_for_checkpoints} epochs are pruned but no state archive is configured, pruned history is lost"
+            ),
+            RetentionConflict::ArchiveNotUsedForPruningWatermark {
+                num_epochs_to_retain_for_checkpoints,
+            } => write!(
+                f,
+                "Checkpoint contents older than {num_epochs_to_retain_for_checkpoints} epochs are pruned without waiting for the state archive, set use-for-pruning-watermark on a state archive read config"
+            ),
+            RetentionConflict::PrunedDbCheckpointsWithoutArchive {
+                num_epochs_to_retain,
+            } => write!(
+                f,
+                "DB checkpoints are pruned to the last {num_epochs_to_retain} epochs before upload but no state archive is configured, object history is not captured by any backup"
+            ),
+            RetentionConflict::BackupWatermarkWithoutUploads => write!(
+                f,
+                "use-for-pruning-watermark is set but the node doesn't upload db checkpoints in-process, pruning doesn't wait for uploads"
+            ),
+        }
+    }
+}
+
+/// Flags configurations where local pruning deletes data before the backup or archival
+/// pipeline has captured it
+pub fn check_retention_compatibility(
+    pruning_config: &AuthorityStorePruningConfig,
+    db_checkpoint_config: &DBCheckpointConfig,
+    state_archive_write_config: &StateArchiveConfig,
+    state_archive_read_config: &[StateArchiveConfig],
+) -> Vec<RetentionConflict> {
+    let mut conflicts = vec![];
+    let archive_written = state_archive_write_config.object_store_config.is_some();
+    let archive_watermark = state_archive_read_config
+        .iter()
+        .any(|config| config.object_store_config.is_some() && config.use_for_pruning_watermark);
+    if let Some(num_epochs_to_retain_for_checkpoints) =
+        pruning_config.num_epochs_to_retain_for_checkpoints
+    {
+        if !archive_written && !archive_watermark {
+            conflicts.push(RetentionConflict::CheckpointPruningWithoutArchive {
+                num_epochs_to_retain_for_checkpoints,
+            });
+        } else if !archive_watermark {
+            conflicts.push(RetentionConflict::ArchiveNotUsedForPruningWatermark {
+                num_epochs_to_retain_for_checkpoints,
+            });
+        }
+    }
+    let uploads_db_checkpoints = db_checkpoint_config.perform_db_checkpoints_at_epoch_end
+        && db_checkpoint_config.object_store_config.is_some()
+        && !db_checkpoint_config.verify_only();
+    let pre_upload_pruning_config = db_checkpoint_config.pre_upload_pruning_config(pruning_config);
+    // Epochs of history kept by the first table pruned before upload
+    let num_epochs_retained_before_upload = if pre_upload_pruning_config.num_epochs_to_retain
+        != u64::MAX
+        && !db_checkpoint_config
+            .pre_upload_skip_tables()
+            .contains("objects")
+    {
+        Some(pre_upload_pruning_config.num_epochs_to_retain)
+    } else {
+        pre_upload_pruning_config.num_epochs_to_retain_for_checkpoints
+    };
+    if let Some(num_epochs_to_retain) = num_epochs_retained_before_upload {
+        if uploads_db_checkpoints
+            && db_checkpoint_config.prune_and_compact_before_upload()
+            && !archive_written
+        {
+            conflicts.push(RetentionConflict::PrunedDbCheckpointsWithoutArchive {
+                num_epochs_to_retain,
+            });
+        }
+    }
+    if db_checkpoint_config.use_for_pruning_watermark()
+        && !(uploads_db_checkpoints && !db_checkpoint_config.run_out_of_process.unwrap_or(false))
+    {
+        conflicts.push(RetentionConflict::BackupWatermarkWithoutUploads);
+    }
+    conflicts
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -337,6 +468,11 @@ pub struct CheckpointExecutorConfig {
     /// If unspecified, this will default to `10`.
     #[serde(default = "default_local_execution_timeout_sec")]
     pub local_execution_timeout_sec: u64,
+
+    /// Stop executing checkpoints after this one, e.g. to inspect the state of a db restored
+    /// to a precise point in chain history. Newer checkpoints are still synced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_at_checkpoint: Option<CheckpointSequenceNumber>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -451,6 +587,7 @@ impl Default for CheckpointExecutorConfig {
         Self {
             checkpoint_execution_max_concurrency: default_checkpoint_execution_max_concurrency(),
             local_execution_timeout_sec: default_local_execution_timeout_sec(),
+            stop_at_checkpoint: None,
         }
     }
 }
@@ -576,6 +713,699 @@ pub struct DBCheckpointConfig {
     pub perform_index_db_checkpoints_at_epoch_end: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prune_and_compact_before_upload: Option<bool>,
+    /// Named preset which supplies defaults for every upload knob below that is left unset.
+    /// Explicitly configured values always take precedence over the preset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<DBCheckpointPreset>,
+    /// Time interval (in seconds) to check for presence of new db checkpoints to upload
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_interval_s: Option<u64>,
+    /// Number of files copied concurrently to the remote store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_concurrency: Option<usize>,
+    /// Number of most recent uploaded db checkpoints to keep on local disk after upload
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_local_epochs_to_retain: Option<usize>,
+    /// Compare the remote copy of an epoch against the local checkpoint before marking the
+    /// upload as successful
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_after_upload: Option<bool>,
+    /// Layout of the epoch contents in the remote store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_layout: Option<DBCheckpointUploadLayout>,
+    /// Enables the audit log in the bucket, recording this identity as the actor of every
+    /// upload and retention delete
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_actor: Option<String>,
+    /// Leaves uploading db checkpoints to the standalone `sui-db-backup` binary. The node keeps
+    /// taking db checkpoints at epoch end but doesn't start the upload handler in-process.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_out_of_process: Option<bool>,
+    /// Host level limits for pruning, compaction and upload work, so that backup spikes don't
+    /// starve or OOM a co-located validator
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_limits: Option<BackupResourceLimits>,
+    /// Layout of the local db checkpoints when they are produced by external snapshot tooling
+    /// instead of the node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_layout: Option<DBCheckpointSourceLayout>,
+    /// Takes db checkpoints at epoch end with a filesystem snapshot of the live db instead of
+    /// RocksDB checkpoints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_backend: Option<DBSnapshotBackend>,
+    /// Time (in seconds) after an upload during which a success marker missing from the
+    /// remote store is re-checked with backoff before the epoch is reported as missing. Covers
+    /// eventually consistent stores whose listings lag behind recent writes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consistency_grace_period_s: Option<u64>,
+    /// Computes file digests from the same read that uploads the file instead of in a separate
+    /// pass after pruning and compaction, halving local reads for big epochs. Files changed on
+    /// disk between compaction and upload are then uploaded as is instead of being detected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_pass_digests: Option<bool>,
+    /// Time (in seconds) after which the upload of an epoch is suspended until the next
+    /// interval. Its progress is kept, so that a single huge epoch doesn't hold the loop and
+    /// starve garbage collection, metrics and the uploads of newer epochs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch_upload_deadline_s: Option<u64>,
+    /// Free disk space (in bytes) below which the db checkpoint of an epoch is not taken while
+    /// earlier ones are still waiting for upload, so that a stalled upload can't fill up the
+    /// disk and take the node down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_free_disk_bytes_with_pending_uploads: Option<u64>,
+    /// Writes the manifest and the audit record of an uploaded epoch as the body of its
+    /// success marker instead of as separate objects, saving requests on stores that charge per
+    /// request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consolidate_epoch_metadata: Option<bool>,
+    /// Number of most recent epochs with per-epoch upload status and duration gauges. The
+    /// gauges of older epochs are removed to bound the number of metric series.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch_metrics_window: Option<usize>,
+    /// Maximum number of threads digesting and chunking db checkpoint files at once, half of
+    /// the cores by default. These run off the async executors so that backups don't starve
+    /// the tasks of the node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest_parallelism: Option<usize>,
+    /// Number of files above which the manifest of an epoch is split into shards listed by an
+    /// index, so that epochs with very many files are never read as a single manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest_shard_size: Option<usize>,
+    /// Which uploaded epochs to keep in the remote store. Unset keeps every epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_retention: Option<RetentionPolicy>,
+    /// Holds back pruning of the live db until the db checkpoint of the pruned epochs is
+    /// confirmed in the remote store, so that pruned data always has a remote copy. Ignored
+    /// unless the upload handler runs in-process.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_for_pruning_watermark: Option<bool>,
+    /// Time (in seconds) between two checks of a sample of the files of the db checkpoints
+    /// kept on local disk against the digests recorded after compaction, to catch disk
+    /// corruption before the files are needed for a restore. Unset disables the checks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_scrub_interval_s: Option<u64>,
+    /// Number of files checked by every local scrub
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_scrub_sample_size: Option<usize>,
+    /// Where restores, smoke tests and benchmarks of db checkpoints keep their temporary files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staging: Option<StagingConfig>,
+    /// Never upload, and instead continuously check the completeness and integrity of the epochs
+    /// uploaded to `object-store-config` by another node, e.g. the other node of an HA pair.
+    /// The node doesn't take db checkpoints at epoch end in this mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_only: Option<bool>,
+    /// Pruning of db checkpoints before upload, distinct from the live pruning of the node. Only
+    /// used with `prune-and-compact-before-upload`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_upload_pruning: Option<PreUploadPruningConfig>,
+    /// Compression of the files uploaded to the remote store. Restores decompress them
+    /// transparently. Ignored with the `content-defined-chunks` and `mirrored-with-sums`
+    /// layouts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<DBCheckpointCompression>,
+    /// Adapts the number of concurrent writes to the remote store, starting from
+    /// `upload-concurrency`, to the throttling and latency of the store. Unset keeps it fixed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adaptive_concurrency: Option<AdaptiveConcurrencyConfig>,
+    /// Encrypts the files uploaded to the remote store with AES-256-GCM before they leave the
+    /// host. Restores decrypt them with the same key. Not supported with the
+    /// `content-defined-chunks` and `mirrored-with-sums` layouts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<DBCheckpointEncryptionConfig>,
+    /// Caps the bandwidth of the downloads of restores from the remote store, in bytes per
+    /// second, so that a restore on a shared host doesn't starve other nodes of the network.
+    /// Unset or zero doesn't limit restores.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_rate_bytes_per_sec: Option<u64>,
+    /// Tokens allowed on the storage admin endpoints, each with the actions it may perform.
+    /// The endpoints are not served without tokens.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_tokens: Vec<StorageAdminToken>,
+    /// Also download every file checked by `verify-after-upload` and in verify-only mode, and
+    /// compare its checksum with the manifest, so that contents corrupted on the way to the
+    /// remote store are caught before the success marker. Doubles the transfer of uploads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_remote_checksums: Option<bool>,
+    /// Caps the bandwidth of the writes of uploads to the remote store, in bytes per second,
+    /// so that uploading a large db checkpoint doesn't saturate the network of the node and
+    /// slow down consensus. Unset or zero doesn't limit uploads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_rate_bytes_per_sec: Option<u64>,
+    /// Bytes the content checks of `verify-remote-checksums` may download per day in
+    /// verify-only mode, so that the cost of continuous verification stays predictable. Epochs
+    /// which don't fit are checked over the following days. Unset or zero doesn't limit them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_budget_bytes_per_day: Option<u64>,
+    /// Name recorded with the network peer id of the node in the manifest of every uploaded
+    /// epoch, to tell apart the nodes uploading to a shared bucket, e.g. the host name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub producer_name: Option<String>,
+    /// Further object stores every db checkpoint is uploaded to next to `object_store_config`,
+    /// e.g. a bucket in another cloud for disaster recovery. Each one is uploaded to and tracked
+    /// independently, and local db checkpoints are only garbage collected once in all of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replica_object_store_configs: Vec<ObjectStoreConfig>,
+    /// Endpoints notified of uploads, failures and garbage collections of db checkpoints
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<DBCheckpointWebhookConfig>,
+    /// Sync the files and directories of restored db checkpoints to disk before the restore
+    /// completes, so that the restored db survives a crash of the host right after it. Defaults
+    /// to true, turn it off for scratch restores such as restore drills.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_fsync: Option<bool>,
+    /// Epochs uploaded to the remote store, every epoch when unset. Epochs outside of them are
+    /// neither uploaded nor reported missing, e.g. those before a node joined the backups, and
+    /// their local db checkpoints are garbage collected as skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_epochs: Option<UploadEpochsConfig>,
+    /// Number of consecutive failed uploads of an epoch after which it is quarantined: skipped
+    /// by the following upload passes, so that newer epochs are uploaded meanwhile, for
+    /// `upload_quarantine_retry_s`. Only failures of the epoch itself count, not those of the
+    /// remote store. Unset or zero retries failed epochs forever.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_quarantine_threshold: Option<u32>,
+    /// Seconds after which a quarantined epoch is uploaded again, and quarantined again if the
+    /// upload fails. 3600 when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_quarantine_retry_s: Option<u64>,
+    /// Seconds the remote store has to answer the probe starting every upload pass, after which
+    /// it is considered unreachable and the pass is skipped. 10 when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination_probe_timeout_s: Option<u64>,
+    /// Free bytes on the disk of the local db checkpoints below which the uploaded ones are
+    /// deleted right away, oldest first and including those kept by
+    /// `num_local_epochs_to_retain`, until as many bytes are free again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_pressure_min_free_bytes: Option<u64>,
+    /// Seconds between two garbage collections of the uploaded local db checkpoints. 30 when
+    /// unset, at least 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc_interval_s: Option<u64>,
+    /// Only log and report the local db checkpoints garbage collection would delete, e.g. to
+    /// validate the markers it waits for before letting it delete anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gc_dry_run: Option<bool>,
+    /// Retention of each of `replica_object_store_configs`, in the same order, so that e.g. a
+    /// cloud bucket keeps the latest epochs while a NAS keeps all of them with an empty policy.
+    /// Replicas past the end of the list follow `remote_retention`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replica_remote_retention: Vec<RetentionPolicy>,
+    /// Seconds garbage collection waits for the replicas to upload a db checkpoint once it is
+    /// uploaded to `object_store_config`. Past it, the db checkpoint is deleted without the
+    /// copies of the replicas still missing it, so that an outage of a replica doesn't fill the
+    /// disk. 86400 when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replica_gc_wait_s: Option<u64>,
+    /// Seconds an upload attempt waits for the pruning and compaction of its db checkpoint,
+    /// which runs on a blocking thread. Past it, the upload of the epoch is deferred while the
+    /// job keeps running, and the epochs already pruned are uploaded meanwhile. No limit when
+    /// unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prune_and_compact_timeout_s: Option<u64>,
+    /// Watch the directory of the local db checkpoints, so that a new epoch is uploaded as soon
+    /// as its db checkpoint is taken rather than on the next upload interval. Only supported on
+    /// Linux, elsewhere the handler keeps polling every `upload_interval_s`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watch_input_dir: Option<bool>,
+    /// Seconds for which the files of a db checkpoint must be left unmodified before it is
+    /// uploaded, for db checkpoints written in place by external tooling which doesn't mark
+    /// them with a `CREATION_IN_PROGRESS` file while writing them. 0 when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creation_settle_s: Option<u64>,
+    /// Order in which the missing epochs are uploaded, oldest first when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_order: Option<DBCheckpointUploadOrder>,
+    /// Size of the files packed into each archive with the `tar-archives` upload layout, between
+    /// 1 MiB and 1 GiB, 64 MiB when unset. Archives are held in memory while uploaded. Files
+    /// larger than this are uploaded on their own, uncompressed, as with the `mirrored` layout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_size_bytes: Option<u64>,
+    /// OpenTelemetry collector the spans and key metrics of the handler are exported to, by
+    /// binaries built with the `backup-otlp` feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp_exporter: Option<DBCheckpointOtlpConfig>,
+}
+
+/// Directory holding the temporary files of restores, smoke tests and benchmarks of db
+/// checkpoints. Directories left behind by interrupted runs are removed automatically.
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StagingConfig {
+    /// Defaults to `staging` under the db path of the node. Restored dbs are moved out of it
+    /// with a rename, so it must be on the same filesystem as the db path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Maximum number of bytes held by all the staging directories together
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// Age (in seconds) past which a staging directory is removed even if the process owning
+    /// it still looks alive, 7 days by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_s: Option<u64>,
+}
+
+/// Overrides of the live pruning config of the node applied to db checkpoints before upload,
+/// e.g. to keep effects in backups while the live node prunes them
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PreUploadPruningConfig {
+    /// Number of epochs to keep the latest version of objects for, the live
+    /// `num-epochs-to-retain` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_epochs_to_retain: Option<u64>,
+    /// Number of epochs to keep transactions, effects, events and checkpoint contents for.
+    /// Unlike the objects, these are only pruned before upload when set here.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_epochs_to_retain_for_checkpoints: Option<u64>,
+    /// Tables (column families) left untouched by pruning before upload, e.g. `effects` and
+    /// `events`. `objects` skips object pruning altogether.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_tables: Option<Vec<String>>,
+}
+
+/// Bounds of the upload concurrency adapted to the remote store. The concurrency is halved
+/// whenever the store throttles a write, and raised by one after as many writes as the current
+/// concurrency completed faster than the target latency.
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AdaptiveConcurrencyConfig {
+    /// Lowest concurrency throttling backs off to, 1 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_concurrency: Option<usize>,
+    /// Highest concurrency fast writes ramp up to, four times `upload-concurrency` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Writes of a single file or chunk slower than this (in milliseconds) don't raise the
+    /// concurrency, 2000 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_latency_ms: Option<u64>,
+}
+
+/// Key of the client-side encryption of uploaded db checkpoints. Exactly one of `key-path` and
+/// `kms-encrypted-key` must be set. Manifests, success markers and audit records are stored in
+/// the clear, so the names and sizes of the files remain visible to readers of the bucket. The
+/// digests of the files in the manifest are sealed with the key.
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DBCheckpointEncryptionConfig {
+    /// Name of the key, recorded in the manifest of every epoch encrypted with it so that
+    /// restores with another key fail before downloading anything, e.g. after a key rotation
+    pub key_id: String,
+    /// File holding the hex encoded 32 byte key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<PathBuf>,
+    /// Base64 encoded 32 byte key encrypted with AWS KMS, e.g. the `CiphertextBlob` of
+    /// `aws kms generate-data-key --key-spec AES_256`. It is decrypted with KMS on first use,
+    /// with the region and credentials of the environment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kms_encrypted_key: Option<String>,
+}
+
+/// Action on the storage admin endpoints, granted to tokens one by one so that status reads can
+/// be handed out without the right to change how the node stores its data
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageAdminAction {
+    /// Read the health of the stores and the bandwidth limit of restores
+    ReadStatus,
+    /// Change the bandwidth limit of a running restore
+    SetRestoreRate,
+    /// Start a scan and upload of the db checkpoints right away
+    TriggerUpload,
+    /// Pause and resume the uploads of the db checkpoints
+    PauseUploads,
+    /// Delete uploaded db checkpoints from the remote store
+    DeleteRemoteEpochs,
+    /// Pause and resume the garbage collection of local db checkpoints and the remote retention
+    PauseGc,
+}
+
+/// Token of the storage admin endpoints, sent as `Authorization: Bearer <token>`
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StorageAdminToken {
+    /// Name of the holder of the token, logged with the changes it makes
+    pub name: String,
+    /// Hex encoded sha256 digest of the token, e.g. from `echo -n <token> | sha256sum`, so that
+    /// the token can't be read from the config
+    pub token_sha256: String,
+    pub actions: Vec<StorageAdminAction>,
+}
+
+/// Events of the db checkpoint handler sent to webhooks
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackupNotificationKind {
+    UploadStarted,
+    UploadCompleted,
+    /// An upload, or a verification in verify-only mode, failed and is retried on the next
+    /// interval
+    Error,
+    /// Local db checkpoints were deleted
+    GcPerformed,
+    LocalCorruptionDetected,
+    /// An epoch which was complete in the remote store lost its success marker
+    FirstMissingEpochRegressed,
+}
+
+/// Endpoint receiving a JSON body with an HTTP POST for every event of the db checkpoint handler
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DBCheckpointWebhookConfig {
+    /// `http` or `https` URL of the endpoint, webhooks with another one are ignored
+    pub url: String,
+    /// Events sent to the endpoint, every one of them when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<BackupNotificationKind>,
+    /// Headers sent with every request, e.g. the `Authorization` the endpoint checks
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl DBCheckpointWebhookConfig {
+    pub fn notifies(&self, kind: BackupNotificationKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// Collector receiving the spans of the uploads, failures and garbage collections of the db
+/// checkpoint handler along with its key metrics, over OTLP/HTTP with JSON encoding
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DBCheckpointOtlpConfig {
+    /// Base URL of the collector, e.g. `http://localhost:4318`, which `/v1/traces` and
+    /// `/v1/metrics` are appended to
+    pub endpoint: String,
+    /// Seconds between two exports, 60 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub export_interval_s: Option<u64>,
+    /// Headers sent with every export, e.g. the API key of a hosted collector
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl DBCheckpointOtlpConfig {
+    pub fn export_interval(&self) -> Duration {
+        Duration::from_secs(self.export_interval_s.unwrap_or(60).max(1))
+    }
+}
+
+/// Epochs of the db checkpoints uploaded to the remote store. An epoch is uploaded when it is
+/// within the range and, if `epochs` isn't empty, listed in it.
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct UploadEpochsConfig {
+    /// First epoch uploaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_epoch: Option<u32>,
+    /// Last epoch uploaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_epoch: Option<u32>,
+    /// Only epochs uploaded, every epoch of the range when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub epochs: Vec<u32>,
+}
+
+impl UploadEpochsConfig {
+    pub fn contains(&self, epoch: u32) -> bool {
+        self.start_epoch.map_or(true, |start| epoch >= start)
+            && self.end_epoch.map_or(true, |end| epoch <= end)
+            && (self.epochs.is_empty() || self.epochs.contains(&epoch))
+    }
+}
+
+/// Filesystem snapshot used to take db checkpoints at epoch end. A snapshot is atomic across
+/// all the dbs of the node and takes constant time regardless of the db size. RocksDB recovers
+/// it from its write ahead logs, as after a power loss. The snapshot is writable and exposed as
+/// `epoch_N` under `checkpoint-path`, so it is pruned, uploaded and garbage collected like a
+/// RocksDB checkpoint.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DBSnapshotBackend {
+    /// `dataset` is the ZFS dataset mounted at the live db directory. Every epoch is
+    /// snapshotted as `<dataset>@epoch_N` and cloned into `<dataset>-epoch_N`, which is mounted
+    /// at `checkpoint-path/epoch_N`.
+    Zfs { dataset: String },
+    /// `subvolume` is the btrfs subvolume holding the live db. `checkpoint-path` must be on the
+    /// same btrfs filesystem.
+    Btrfs { subvolume: PathBuf },
+}
+
+/// Describes local db checkpoints written by external tooling, e.g. ZFS snapshots exposed under
+/// `<mountpoint>/.zfs/snapshot/<name>` or LVM snapshots mounted side by side. Uploads always
+/// use `epoch_N` directories in the remote store.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DBCheckpointSourceLayout {
+    /// Regex matched against the names of the directories under `checkpoint-path`. Its first
+    /// capture group must be the epoch of the db checkpoint.
+    pub dir_pattern: String,
+    /// Path of the db checkpoint inside every matched directory, e.g. `db/live` for snapshots
+    /// of a whole volume
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub db_subdir: Option<PathBuf>,
+    /// Set when the matched directories are read-only snapshots owned by the tooling. Upload
+    /// markers and checksums are then kept under this directory instead, and the db
+    /// checkpoints are never pruned before upload nor garbage collected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_path: Option<PathBuf>,
+}
+
+/// Self imposed limits for backup work. Scheduling priorities apply to the backup worker
+/// thread on Linux. Memory and open file limits are process wide and only applied by the
+/// standalone `sui-db-backup` binary.
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupResourceLimits {
+    /// Maximum memory of the backup process, in bytes, set as the `memory.max` of its cgroup v2.
+    /// The memory controller of that cgroup must be delegated to the process. Page cache is
+    /// reclaimed first and the process is killed past the limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory_bytes: Option<u64>,
+    /// Maximum number of file descriptors open in the backup process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_open_files: Option<u64>,
+    /// Niceness of backup work, from -20 (highest priority) to 19 (lowest)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    /// IO scheduling class of backup work
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_priority_class: Option<IoPriorityClass>,
+    /// IO priority within the best-effort class, from 0 (highest) to 7 (lowest)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_priority_level: Option<u8>,
+    /// Ceiling on the combined flush and compaction writes of the live perpetual db and of
+    /// the pruning of db checkpoints, in bytes per second. Both share one RocksDB rate limiter,
+    /// which serves flushes of the live db ahead of compactions. With `run-out-of-process`, the
+    /// node and the backup process are capped separately.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_write_rate_bytes_per_sec: Option<u64>,
+}
+
+impl BackupResourceLimits {
+    /// Whether any limit applies to the scheduling of the backup worker thread
+    pub fn has_thread_priorities(&self) -> bool {
+        self.nice.is_some() || self.io_priority_class.is_some()
+    }
+    /// Whether any process wide limit is set
+    pub fn has_process_limits(&self) -> bool {
+        self.max_memory_bytes.is_some() || self.max_open_files.is_some()
+    }
+}
+
+/// IO scheduling classes (see ionice(1))
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoPriorityClass {
+    BestEffort,
+    /// Only gets disk time when no other process needs it
+    Idle,
+}
+
+/// Key layout used for db checkpoint files in the remote store.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum DBCheckpointUploadLayout {
+    /// Remote keys mirror the local db checkpoint directory
+    #[default]
+    Mirrored,
+    /// Files are distributed under hashed key prefixes inside the epoch directory to avoid
+    /// per-prefix request rate limits on very large epochs. The epoch manifest maps every
+    /// logical file name to its remote key.
+    HashedSharded,
+    /// Files are split into content defined chunks which are stored once, keyed by digest,
+    /// under the shared `chunks/` prefix. Chunks are deduplicated across epochs even when
+    /// compaction rewrites SST files. The epoch manifest lists the chunks of every file.
+    ContentDefinedChunks,
+    /// Remote keys mirror the local db checkpoint directory, including empty files, and every
+    /// epoch directory holds a `SHA256.sum` file in `sha256sum` format. Tools like rclone,
+    /// restic or `sha256sum -c` can verify and mirror the bucket without reading the manifest.
+    MirroredWithSums,
+    /// Files are packed into a few tar archives compressed with zstd per epoch directory, so
+    /// that an epoch of thousands of small files takes a few requests to upload. Every file is
+    /// an independent zstd frame in its archive, whose offset the epoch manifest records.
+    TarArchives,
+}
+
+/// Compression of the db checkpoint files in the remote store.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum DBCheckpointCompression {
+    /// Files are uploaded as they are on local disk
+    #[default]
+    None,
+    /// Files are compressed with zstd, and their key gets a `.zst` suffix
+    Zstd,
+    /// Files are compressed with zstd as independent frames, whose offsets are recorded in the
+    /// manifest so that parts of a file can be read and verified without downloading all of
+    /// it, e.g. by restores resuming a large file. Their key gets a `.zst` suffix.
+    ZstdSeekable,
+}
+
+/// Order in which an upload pass goes through the missing epochs
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum DBCheckpointUploadOrder {
+    /// Epochs are uploaded in the order they were taken
+    #[default]
+    OldestFirst,
+    /// The latest epoch is uploaded first so that it is available for restores as soon as
+    /// possible, older missing epochs are backfilled after it, newest to oldest
+    NewestFirst,
+}
+
+/// Presets for the db checkpoint upload pipeline, tuned for the common deployment shapes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DBCheckpointPreset {
+    /// Validator uploading its own checkpoints: keep disk and network usage low so that
+    /// uploads never compete with consensus.
+    ValidatorMinimal,
+    /// Fullnode keeping full history: upload checkpoints unpruned and verify every epoch.
+    FullnodeArchival,
+    /// Dedicated backup host: upload as fast as possible and verify every epoch.
+    BackupSidecar,
+}
+
+impl DBCheckpointPreset {
+    fn upload_interval_s(&self) -> u64 {
+        match self {
+            DBCheckpointPreset::ValidatorMinimal => 600,
+            DBCheckpointPreset::FullnodeArchival => 60,
+            DBCheckpointPreset::BackupSidecar => 30,
+        }
+    }
+    fn prune_and_compact_before_upload(&self) -> bool {
+        match self {
+            DBCheckpointPreset::ValidatorMinimal => true,
+            DBCheckpointPreset::FullnodeArchival => false,
+            DBCheckpointPreset::BackupSidecar => true,
+        }
+    }
+    fn upload_concurrency(&self) -> usize {
+        match self {
+            DBCheckpointPreset::ValidatorMinimal => 4,
+            DBCheckpointPreset::FullnodeArchival => 20,
+            DBCheckpointPreset::BackupSidecar => 50,
+        }
+    }
+    fn num_local_epochs_to_retain(&self) -> usize {
+        match self {
+            DBCheckpointPreset::ValidatorMinimal => 0,
+            DBCheckpointPreset::FullnodeArchival => 2,
+            DBCheckpointPreset::BackupSidecar => 0,
+        }
+    }
+    fn verify_after_upload(&self) -> bool {
+        match self {
+            DBCheckpointPreset::ValidatorMinimal => false,
+            DBCheckpointPreset::FullnodeArchival => true,
+            DBCheckpointPreset::BackupSidecar => true,
+        }
+    }
+}
+
+impl DBCheckpointConfig {
+    pub fn upload_interval_s(&self) -> u64 {
+        self.upload_interval_s
+            .or_else(|| self.preset.map(|p| p.upload_interval_s()))
+            .unwrap_or(60)
+    }
+    pub fn prune_and_compact_before_upload(&self) -> bool {
+        self.prune_and_compact_before_upload
+            .or_else(|| self.preset.map(|p| p.prune_and_compact_before_upload()))
+            .unwrap_or(true)
+    }
+    pub fn upload_concurrency(&self) -> NonZeroUsize {
+        let concurrency = self
+            .upload_concurrency
+            .or_else(|| self.preset.map(|p| p.upload_concurrency()))
+            .unwrap_or(20);
+        NonZeroUsize::new(concurrency).unwrap_or(NonZeroUsize::new(1).unwrap())
+    }
+    pub fn num_local_epochs_to_retain(&self) -> usize {
+        self.num_local_epochs_to_retain
+            .or_else(|| self.preset.map(|p| p.num_local_epochs_to_retain()))
+            .unwrap_or(0)
+    }
+    pub fn verify_after_upload(&self) -> bool {
+        self.verify_after_upload
+            .or_else(|| self.preset.map(|p| p.verify_after_upload()))
+            .unwrap_or(false)
+    }
+    pub fn use_for_pruning_watermark(&self) -> bool {
+        self.use_for_pruning_watermark.unwrap_or(false)
+    }
+    pub fn verify_only(&self) -> bool {
+        self.verify_only.unwrap_or(false)
+    }
+    /// Pruning config applied to db checkpoints before upload, `live` with the overrides of
+    /// `pre_upload_pruning`
+    pub fn pre_upload_pruning_config(
+        &self,
+        live: &AuthorityStorePruningConfig,
+    ) -> AuthorityStorePruningConfig {
+        let overrides = self.pre_upload_pruning.clone().unwrap_or_default();
+        AuthorityStorePruningConfig {
+            num_epochs_to_retain: overrides
+                .num_epochs_to_retain
+                .unwrap_or(live.num_epochs_to_retain),
+            num_epochs_to_retain_for_checkpoints: overrides.num_epochs_to_retain_for_checkpoints,
+            ..*live
+        }
+    }
+    /// Tables left untouched by pruning before upload
+    pub fn pre_upload_skip_tables(&self) -> HashSet<String> {
+        self.pre_upload_pruning
+            .as_ref()
+            .and_then(|pruning| pruning.skip_tables.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .collect()
+    }
+    pub fn restore_fsync(&self) -> bool {
+        self.restore_fsync.unwrap_or(true)
+    }
+    pub fn destination_probe_timeout_s(&self) -> u64 {
+        self.destination_probe_timeout_s.unwrap_or(10)
+    }
+    pub fn upload_quarantine_retry_s(&self) -> u64 {
+        self.upload_quarantine_retry_s.unwrap_or(3600)
+    }
+    pub fn gc_interval_s(&self) -> u64 {
+        self.gc_interval_s.unwrap_or(30).max(1)
+    }
+    pub fn replica_gc_wait_s(&self) -> u64 {
+        self.replica_gc_wait_s.unwrap_or(86400)
+    }
+    /// Retention of the replica at `index` of `replica_object_store_configs`
+    pub fn replica_remote_retention(&self, index: usize) -> Option<RetentionPolicy> {
+        self.replica_remote_retention
+            .get(index)
+            .or(self.remote_retention.as_ref())
+            .cloned()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -790,7 +1620,11 @@ mod tests {
     use sui_keys::keypair_file::{write_authority_keypair_to_file, write_keypair_to_file};
     use sui_types::crypto::{get_key_pair_from_rng, AuthorityKeyPair, NetworkKeyPair, SuiKeyPair};
 
-    use super::Genesis;
+    use super::{
+        check_retention_compatibility, AuthorityStorePruningConfig, BackupResourceLimits,
+        DBCheckpointConfig, DBCheckpointPreset, Genesis, IoPriorityClass, RetentionConflict,
+        StateArchiveConfig,
+    };
     use crate::NodeConfig;
 
     #[test]
@@ -846,4 +1680,145 @@ mod tests {
             worker_key_pair.public()
         );
     }
+
+    #[test]
+    fn db_checkpoint_preset_with_overrides() {
+        let config: DBCheckpointConfig =
+            serde_yaml::from_str("preset: validator-minimal\nupload-concurrency: 8\n").unwrap();
+        assert_eq!(config.preset, Some(DBCheckpointPreset::ValidatorMinimal));
+        assert_eq!(config.upload_interval_s(), 600);
+        assert!(config.prune_and_compact_before_upload());
+        assert_eq!(config.upload_concurrency().get(), 8);
+        assert!(!config.verify_after_upload());
+
+        let config = DBCheckpointConfig::default();
+        assert_eq!(config.upload_interval_s(), 60);
+        assert!(config.prune_and_compact_before_upload());
+        assert_eq!(config.upload_concurrency().get(), 20);
+        assert_eq!(config.num_local_epochs_to_retain(), 0);
+        assert_eq!(config.gc_interval_s(), 30);
+
+        // Zero would make the garbage collection interval panic
+        let config: DBCheckpointConfig = serde_yaml::from_str("gc-interval-s: 0\n").unwrap();
+        assert_eq!(config.gc_interval_s(), 1);
+    }
+
+    #[test]
+    fn backup_resource_limits() {
+        let config: DBCheckpointConfig = serde_yaml::from_str(
+            "resource-limits:\n  nice: 10\n  io-priority-class: idle\n  max-open-files: 4096\n  shared-write-rate-bytes-per-sec: 104857600\n",
+        )
+        .unwrap();
+        let limits = config.resource_limits.unwrap();
+        assert_eq!(
+            limits.shared_write_rate_bytes_per_sec,
+            Some(100 * 1024 * 1024)
+        );
+        assert_eq!(limits.io_priority_class, Some(IoPriorityClass::Idle));
+        assert!(limits.has_thread_priorities());
+        assert!(limits.has_process_limits());
+        assert!(!BackupResourceLimits::default().has_thread_priorities());
+    }
+
+    #[test]
+    fn retention_conflicts() {
+        let remote_store = sui_storage::object_store::ObjectStoreConfig::default();
+        let pruning_config = AuthorityStorePruningConfig {
+            num_epochs_to_retain_for_checkpoints: Some(2),
+            ..Default::default()
+        };
+        let db_checkpoint_config = DBCheckpointConfig {
+            perform_db_checkpoints_at_epoch_end: true,
+            object_store_config: Some(remote_store.clone()),
+            ..Default::default()
+        };
+        let no_archive = StateArchiveConfig::default();
+        let conflicts =
+            check_retention_compatibility(&pruning_config, &db_checkpoint_config, &no_archive, &[]);
+        assert_eq!(
+            conflicts,
+            vec![
+                RetentionConflict::CheckpointPruningWithoutArchive {
+                    num_epochs_to_retain_for_checkpoints: 2
+                },
+                RetentionConflict::PrunedDbCheckpointsWithoutArchive {
+                    num_epochs_to_retain: pruning_config.num_epochs_to_retain
+                },
+            ]
+        );
+
+        let archive = StateArchiveConfig {
+            object_store_config: Some(remote_store),
+            concurrency: 5,
+            use_for_pruning_watermark: false,
+        };
+        let conflicts = check_retention_compatibility(
+            &pruning_config,
+            &db_checkpoint_config,
+            &archive,
+            &[archive.clone()],
+        );
+        assert_eq!(
+            conflicts,
+            vec![RetentionConflict::ArchiveNotUsedForPruningWatermark {
+                num_epochs_to_retain_for_checkpoints: 2
+            }]
+        );
+
+        let watermark = StateArchiveConfig {
+            use_for_pruning_watermark: true,
+            ..archive.clone()
+        };
+        assert!(check_retention_compatibility(
+            &pruning_config,
+            &db_checkpoint_config,
+            &archive,
+            &[watermark],
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn pre_upload_pruning_overrides() {
+        let config: DBCheckpointConfig = serde_yaml::from_str(
+            "pre-upload-pruning:\n  num-epochs-to-retain-for-checkpoints: 5\n  skip-tables: [effects, events]\n",
+        )
+        .unwrap();
+        let live = AuthorityStorePruningConfig {
+            num_epochs_to_retain: 1,
+            num_epochs_to_retain_for_checkpoints: Some(2),
+            ..Default::default()
+        };
+        let pruning_config = config.pre_upload_pruning_config(&live);
+        assert_eq!(pruning_config.num_epochs_to_retain, 1);
+        assert_eq!(pruning_config.num_epochs_to_retain_for_checkpoints, Some(5));
+        let skip_tables = config.pre_upload_skip_tables();
+        assert!(skip_tables.contains("effects") && skip_tables.contains("events"));
+
+        // Checkpoint data of backups is only pruned when asked for
+        let pruning_config = DBCheckpointConfig::default().pre_upload_pruning_config(&live);
+        assert_eq!(pruning_config.num_epochs_to_retain_for_checkpoints, None);
+        assert!(DBCheckpointConfig::default()
+            .pre_upload_skip_tables()
+            .is_empty());
+    }
+
+    #[test]
+    fn replica_remote_retention() {
+        let config: DBCheckpointConfig = serde_yaml::from_str(
+            "remote-retention:\n  keep-last: 30\nreplica-remote-retention:\n  - {}\n",
+        )
+        .unwrap();
+        // The first replica keeps every epoch, the second one follows the output store
+        let replica_retention = config.replica_remote_retention(0).unwrap();
+        assert!(!replica_retention.is_enabled());
+        assert_eq!(
+            config.replica_remote_retention(1).unwrap().keep_last,
+            Some(30)
+        );
+        assert_eq!(
+            DBCheckpointConfig::default().replica_remote_retention(0),
+            None
+        );
+    }
 }
@@ -54,7 +54,7 @@ use sui_archival::reader::ArchiveReaderBalancer;
 use sui_config::certificate_deny_config::CertificateDenyConfig;
 use sui_config::genesis::Genesis;
 use sui_config::node::{
-    AuthorityStorePruningConfig, DBCheckpointConfig, ExpensiveSafetyCheckConfig,
+    AuthorityStorePruningConfig, DBCheckpointConfig, DBSnapshotBackend, ExpensiveSafetyCheckConfig,
 };
 use sui_config::transaction_deny_config::TransactionDenyConfig;
 use sui_framework::{BuiltInFramework, SystemPackage};
@@ -125,6 +125,10 @@ use crate::authority::epoch_start_configuration::EpochStartConfigTrait;
 use crate::authority::epoch_start_configuration::EpochStartConfiguration;
 use crate::checkpoints::checkpoint_executor::CheckpointExecutor;
 use crate::checkpoints::CheckpointStore;
+use crate::db_checkpoint_handler::backup_watermark::BackupWatermark;
+use crate::db_checkpoint_handler::expected::ExpectedFiles;
+use crate::db_checkpoint_handler::fs_snapshot::create_fs_snapshot;
+use crate::db_checkpoint_handler::headroom::skip_db_checkpoint_reason;
 use crate::epoch::committee_store::CommitteeStore;
 use crate::event_handler::SubscriptionHandler;
 use crate::execution_driver::execution_process;
@@ -1962,6 +1966,7 @@ impl AuthorityState {
         indirect_objects_threshold: usize,
         debug_dump_config: StateDebugDumpConfig,
         archive_readers: ArchiveReaderBalancer,
+        backup_watermark: Option<BackupWatermark>,
     ) -> Arc<Self> {
         Self::check_protocol_version(supported_protocol_versions, epoch_store.protocol_version());
 
@@ -1986,6 +1991,7 @@ impl AuthorityState {
             prometheus_registry,
             indirect_objects_threshold,
             archive_readers,
+            backup_watermark,
         );
         let state = Arc::new(AuthorityState {
             name,
@@ -2038,6 +2044,7 @@ impl AuthorityState {
             metrics,
             config.indirect_objects_threshold,
             archive_readers,
+            None,
         )
         .await
     }
@@ -2146,11 +2153,29 @@ impl AuthorityState {
                 let current_epoch = cur_epoch_store.epoch();
                 let epoch_checkpoint_path =
                     checkpoint_path.join(format!("epoch_{}", current_epoch));
-                self.checkpoint_all_dbs(
-                    &epoch_checkpoint_path,
-                    cur_epoch_store,
-                    checkpoint_indexes,
-                )?;
+                let min_free_bytes = self
+                    .db_checkpoint_config
+                    .min_free_disk_bytes_with_pending_uploads;
+                // Taken anyway if the headroom can't be checked
+                let skip_reason = min_free_bytes.and_then(|min_free_bytes| {
+                    skip_db_checkpoint_reason(checkpoint_path, min_free_bytes)
+                        .map_err(|err| warn!("Failed to check disk headroom: {err:?}"))
+                        .ok()
+                        .flatten()
+                });
+                match (&self.db_checkpoint_config.snapshot_backend, skip_reason) {
+                    (_, Some(reason)) => {
+                        warn!("Skipping db checkpoint for epoch: {current_epoch}, {reason}")
+                    }
+                    (Some(backend), None) => {
+                        self.snapshot_all_dbs(backend, &epoch_checkpoint_path, current_epoch)?
+                    }
+                    (None, None) => self.checkpoint_all_dbs(
+                        &epoch_checkpoint_path,
+                        cur_epoch_store,
+                        checkpoint_indexes,
+                    )?,
+                }
             }
         }
         let new_epoch = new_committee.epoch;
@@ -2404,11 +2429,32 @@ impl AuthorityState {
             }
         }
 
+        // Lets the uploader detect a checkpoint which is later altered or copied partially
+        ExpectedFiles::write(&checkpoint_path_tmp)
+            .map_err(|e| SuiError::FileIOError(e.to_string()))?;
+
         fs::rename(checkpoint_path_tmp, checkpoint_path)
             .map_err(|e| SuiError::FileIOError(e.to_string()))?;
         Ok(())
     }
 
+    /// Takes the db checkpoint of `epoch` as a filesystem snapshot of all the live dbs
+    pub fn snapshot_all_dbs(
+        &self,
+        backend: &DBSnapshotBackend,
+        checkpoint_path: &Path,
+        epoch: EpochId,
+    ) -> SuiResult {
+        let _metrics_guard = self.metrics.db_checkpoint_latency.start_timer();
+        if checkpoint_path.exists() {
+            info!("Skipping db snapshot as it already exists for epoch: {epoch}");
+            return Ok(());
+        }
+        create_fs_snapshot(backend, epoch, checkpoint_path)
+            .and_then(|_| ExpectedFiles::write(checkpoint_path))
+            .map_err(|e| SuiError::FileIOError(e.to_string()))
+    }
+
     /// Load the current epoch store. This can change during reconfiguration. To ensure that
     /// we never end up accessing different epoch stores in a single task, we need to make sure
     /// that this is called once per task. Each call needs to be carefully audited to ensure it is
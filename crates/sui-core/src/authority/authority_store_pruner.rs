@@ -3,6 +3,7 @@
 
 use crate::authority::authority_store_types::{ObjectContentDigest, StoreData, StoreObject};
 use crate::checkpoints::{CheckpointStore, CheckpointWatermark};
+use crate::db_checkpoint_handler::backup_watermark::BackupWatermark;
 use anyhow::anyhow;
 use mysten_metrics::{monitored_scope, spawn_monitored_task};
 use once_cell::sync::Lazy;
@@ -49,6 +50,19 @@ static PERIODIC_PRUNING_TABLES: Lazy<HashSet<String>> = Lazy::new(|| {
     .map(|cf| cf.to_string())
     .collect()
 });
+/// Tables deleted from by pruning, which pruning of db checkpoints before upload can be told to
+/// leave untouched
+pub const PRUNABLE_TABLES: &[&str] = &[
+    "objects",
+    "transactions",
+    "executed_effects",
+    "executed_transactions_to_checkpoint",
+    "effects",
+    "events",
+    "checkpoint_content",
+    "checkpoint_sequence_by_contents_digest",
+    "checkpoint_by_digest",
+];
 pub struct AuthorityStorePruner {
     _objects_pruner_cancel_handle: oneshot::Sender<()>,
 }
@@ -61,21 +75,27 @@ pub struct AuthorityStorePruningMetrics {
 
 impl AuthorityStorePruningMetrics {
     pub fn new(registry: &Registry) -> Arc<Self> {
+        Self::new_with_prefix(registry, "")
+    }
+
+    /// Metrics with names starting with `prefix`, so that pruners other than the one of the
+    /// live db can register them in the same registry
+    pub fn new_with_prefix(registry: &Registry, prefix: &str) -> Arc<Self> {
         let this = Self {
             last_pruned_checkpoint: register_int_gauge_with_registry!(
-                "last_pruned_checkpoint",
+                format!("{prefix}last_pruned_checkpoint"),
                 "Last pruned checkpoint",
                 registry
             )
             .unwrap(),
             num_pruned_objects: register_int_counter_with_registry!(
-                "num_pruned_objects",
+                format!("{prefix}num_pruned_objects"),
                 "Number of pruned objects",
                 registry
             )
             .unwrap(),
             last_pruned_effects_checkpoint: register_int_gauge_with_registry!(
-                "last_pruned_effects_checkpoint",
+                format!("{prefix}last_pruned_effects_checkpoint"),
                 "Last pruned effects checkpoint",
                 registry
             )
@@ -179,8 +199,10 @@ impl AuthorityStorePruner {
         checkpoint_content_to_prune: Vec<CheckpointContents>,
         effects_to_prune: &Vec<TransactionEffects>,
         metrics: Arc<AuthorityStorePruningMetrics>,
+        skip_tables: &HashSet<String>,
     ) -> anyhow::Result<()> {
         let _scope = monitored_scope("EffectsLivePruner");
+        let prunes = |table: &str| !skip_tables.contains(table);
 
         let mut perpetual_batch = perpetual_db.objects.batch();
         let transactions = checkpoint_content_to_prune
@@ -189,21 +211,27 @@ impl AuthorityStorePruner {
         for transaction_digest in transactions {
             if let Some(next_digest) = transaction_digest.next_lexicographical() {
                 debug!("Pruning transaction {:?}", transaction_digest);
-                perpetual_batch.delete_range(
-                    &perpetual_db.transactions,
-                    &transaction_digest,
-                    &next_digest,
-                )?;
-                perpetual_batch.delete_range(
-                    &perpetual_db.executed_effects,
-                    &transaction_digest,
-                    &next_digest,
-                )?;
-                perpetual_batch.delete_range(
-                    &perpetual_db.executed_transactions_to_checkpoint,
-                    &transaction_digest,
-                    &next_digest,
-                )?;
+                if prunes("transactions") {
+                    perpetual_batch.delete_range(
+                        &perpetual_db.transactions,
+                        &transaction_digest,
+                        &next_digest,
+                    )?;
+                }
+                if prunes("executed_effects") {
+                    perpetual_batch.delete_range(
+                        &perpetual_db.executed_effects,
+                        &transaction_digest,
+                        &next_digest,
+                    )?;
+                }
+                if prunes("executed_transactions_to_checkpoint") {
+                    perpetual_batch.delete_range(
+                        &perpetual_db.executed_transactions_to_checkpoint,
+                        &transaction_digest,
+                        &next_digest,
+                    )?;
+                }
             }
         }
 
@@ -211,19 +239,23 @@ impl AuthorityStorePruner {
             let effects_digest = effects.digest();
             debug!("Pruning effects {:?}", effects_digest);
             if let Some(next_digest) = effects.digest().next_lexicographical() {
-                perpetual_batch.delete_range(
-                    &perpetual_db.effects,
-                    &effects_digest,
-                    &next_digest,
-                )?;
+                if prunes("effects") {
+                    perpetual_batch.delete_range(
+                        &perpetual_db.effects,
+                        &effects_digest,
+                        &next_digest,
+                    )?;
+                }
             }
             if let Some(event_digest) = effects.events_digest() {
                 if let Some(next_digest) = event_digest.next_lexicographical() {
-                    perpetual_batch.delete_range(
-                        &perpetual_db.events,
-                        &(*event_digest, 0),
-                        &(next_digest, 0),
-                    )?;
+                    if prunes("events") {
+                        perpetual_batch.delete_range(
+                            &perpetual_db.events,
+                            &(*event_digest, 0),
+                            &(next_digest, 0),
+                        )?;
+                    }
                 }
             }
         }
@@ -234,20 +266,27 @@ impl AuthorityStorePruner {
             let content_digest = *checkpoint_content.digest();
             if let Some(next_digest) = content_digest.next_lexicographical() {
                 debug!("Pruning checkpoint_content {:?}", content_digest);
-                checkpoints_batch.delete_range(
-                    &checkpoint_db.checkpoint_content,
-                    &content_digest,
-                    &next_digest,
-                )?;
-                checkpoints_batch.delete_range(
-                    &checkpoint_db.checkpoint_sequence_by_contents_digest,
-                    &content_digest,
-                    &next_digest,
-                )?;
+                if prunes("checkpoint_content") {
+                    checkpoints_batch.delete_range(
+                        &checkpoint_db.checkpoint_content,
+                        &content_digest,
+                        &next_digest,
+                    )?;
+                }
+                if prunes("checkpoint_sequence_by_contents_digest") {
+                    checkpoints_batch.delete_range(
+                        &checkpoint_db.checkpoint_sequence_by_contents_digest,
+                        &content_digest,
+                        &next_digest,
+                    )?;
+                }
             }
         }
         for checkpoint_digest in checkpoints_to_prune {
             if let Some(next_digest) = checkpoint_digest.next_lexicographical() {
+                if !prunes("checkpoint_by_digest") {
+                    continue;
+                }
                 checkpoints_batch.delete_range(
                     &checkpoint_db.checkpoint_by_digest,
                     &checkpoint_digest,
@@ -271,6 +310,26 @@ impl AuthorityStorePruner {
         Ok(())
     }
 
+    /// Caps `max_eligible_checkpoint` at the last checkpoint backed up remotely when pruning
+    /// waits for backups. Returns `None` while nothing is backed up yet.
+    fn cap_at_backup_watermark(
+        max_eligible_checkpoint: CheckpointSequenceNumber,
+        checkpoint_store: &CheckpointStore,
+        backup_watermark: Option<&BackupWatermark>,
+    ) -> anyhow::Result<Option<CheckpointSequenceNumber>> {
+        let Some(backup_watermark) = backup_watermark else {
+            return Ok(Some(max_eligible_checkpoint));
+        };
+        let max_backed_up_checkpoint =
+            backup_watermark.max_prunable_checkpoint(checkpoint_store)?;
+        info!(
+            "Backed up epoch: {:?}, last backed up checkpoint: {:?}",
+            backup_watermark.backed_up_epoch(),
+            max_backed_up_checkpoint
+        );
+        Ok(max_backed_up_checkpoint.map(|checkpoint| min(checkpoint, max_eligible_checkpoint)))
+    }
+
     /// Prunes old data based on effects from all checkpoints from epochs eligible for pruning
     pub async fn prune_objects_for_eligible_epochs(
         perpetual_db: &Arc<AuthorityPerpetualTables>,
@@ -279,11 +338,21 @@ impl AuthorityStorePruner {
         config: AuthorityStorePruningConfig,
         metrics: Arc<AuthorityStorePruningMetrics>,
         indirect_objects_threshold: usize,
+        backup_watermark: Option<&BackupWatermark>,
     ) -> anyhow::Result<()> {
         let max_eligible_checkpoint_number = checkpoint_store
             .get_highest_executed_checkpoint()?
             .map(|c| *c.sequence_number())
             .unwrap_or_default();
+        let Some(max_eligible_checkpoint_number) = Self::cap_at_backup_watermark(
+            max_eligible_checkpoint_number,
+            checkpoint_store,
+            backup_watermark,
+        )?
+        else {
+            info!("No epoch is backed up remotely yet, not pruning objects");
+            return Ok(());
+        };
         let pruned_checkpoint_number = perpetual_db.get_highest_pruned_checkpoint()?;
         Self::prune_for_eligible_epochs(
             perpetual_db,
@@ -296,6 +365,7 @@ impl AuthorityStorePruner {
             config,
             metrics.clone(),
             indirect_objects_threshold,
+            &HashSet::new(),
         )
         .await
     }
@@ -308,6 +378,7 @@ impl AuthorityStorePruner {
         metrics: Arc<AuthorityStorePruningMetrics>,
         indirect_objects_threshold: usize,
         archive_readers: ArchiveReaderBalancer,
+        backup_watermark: Option<&BackupWatermark>,
     ) -> anyhow::Result<()> {
         let pruned_checkpoint_number =
             checkpoint_store.get_highest_pruned_checkpoint_seq_number()?;
@@ -317,6 +388,15 @@ impl AuthorityStorePruner {
             .unwrap_or(u64::MAX);
         let highest_pruned_checkpoint = perpetual_db.get_highest_pruned_checkpoint()?;
         info!("Latest archived checkpoint: {latest_archived_checkpoint}, highest pruned checkpoint: {highest_pruned_checkpoint}");
+        let Some(max_eligible_checkpoint) = Self::cap_at_backup_watermark(
+            min(highest_pruned_checkpoint, latest_archived_checkpoint),
+            checkpoint_store,
+            backup_watermark,
+        )?
+        else {
+            info!("No epoch is backed up remotely yet, not pruning checkpoints");
+            return Ok(());
+        };
         Self::prune_for_eligible_epochs(
             perpetual_db,
             checkpoint_store,
@@ -325,11 +405,45 @@ impl AuthorityStorePruner {
                 .num_epochs_to_retain_for_checkpoints()
                 .ok_or_else(|| anyhow!("config value not set"))?,
             pruned_checkpoint_number,
-            min(highest_pruned_checkpoint, latest_archived_checkpoint),
+            max_eligible_checkpoint,
             objects_lock_table,
             config,
             metrics.clone(),
             indirect_objects_threshold,
+            &HashSet::new(),
+        )
+        .await
+    }
+
+    /// Prunes the checkpoint data of a db checkpoint before upload, leaving `skip_tables`
+    /// untouched. Pruning objects reads the effects of their checkpoints, so only checkpoints
+    /// whose objects are already pruned are eligible.
+    pub async fn prune_db_checkpoint_checkpoints(
+        perpetual_db: &Arc<AuthorityPerpetualTables>,
+        checkpoint_store: &Arc<CheckpointStore>,
+        objects_lock_table: &Arc<RwLockTable<ObjectContentDigest>>,
+        config: AuthorityStorePruningConfig,
+        metrics: Arc<AuthorityStorePruningMetrics>,
+        skip_tables: &HashSet<String>,
+    ) -> anyhow::Result<()> {
+        let Some(num_epochs_to_retain) = config.num_epochs_to_retain_for_checkpoints() else {
+            return Ok(());
+        };
+        let pruned_checkpoint_number =
+            checkpoint_store.get_highest_pruned_checkpoint_seq_number()?;
+        let highest_pruned_checkpoint = perpetual_db.get_highest_pruned_checkpoint()?;
+        Self::prune_for_eligible_epochs(
+            perpetual_db,
+            checkpoint_store,
+            PruningMode::Checkpoints,
+            num_epochs_to_retain,
+            pruned_checkpoint_number,
+            highest_pruned_checkpoint,
+            objects_lock_table,
+            config,
+            metrics,
+            0,
+            skip_tables,
         )
         .await
     }
@@ -346,6 +460,7 @@ impl AuthorityStorePruner {
         config: AuthorityStorePruningConfig,
         metrics: Arc<AuthorityStorePruningMetrics>,
         indirect_objects_threshold: usize,
+        skip_tables: &HashSet<String>,
     ) -> anyhow::Result<()> {
         let mut checkpoint_number = starting_checkpoint_number;
         let current_epoch = checkpoint_store
@@ -404,6 +519,7 @@ impl AuthorityStorePruner {
                         checkpoint_content_to_prune,
                         &effects_to_prune,
                         metrics.clone(),
+                        skip_tables,
                     )?,
                 };
                 checkpoints_to_prune = vec![];
@@ -432,6 +548,7 @@ impl AuthorityStorePruner {
                     checkpoint_content_to_prune,
                     &effects_to_prune,
                     metrics.clone(),
+                    skip_tables,
                 )?,
             };
         }
@@ -486,6 +603,7 @@ impl AuthorityStorePruner {
         metrics: Arc<AuthorityStorePruningMetrics>,
         indirect_objects_threshold: usize,
         archive_readers: ArchiveReaderBalancer,
+        backup_watermark: Option<BackupWatermark>,
     ) -> Sender<()> {
         let (sender, mut recv) = tokio::sync::oneshot::channel();
         debug!(
@@ -536,12 +654,12 @@ impl AuthorityStorePruner {
             loop {
                 tokio::select! {
                     _ = objects_prune_interval.tick(), if config.num_epochs_to_retain != u64::MAX => {
-                        if let Err(err) = Self::prune_objects_for_eligible_epochs(&perpetual_db, &checkpoint_store, &objects_lock_table, config, metrics.clone(), indirect_objects_threshold).await {
+                        if let Err(err) = Self::prune_objects_for_eligible_epochs(&perpetual_db, &checkpoint_store, &objects_lock_table, config, metrics.clone(), indirect_objects_threshold, backup_watermark.as_ref()).await {
                             error!("Failed to prune objects: {:?}", err);
                         }
                     },
                     _ = checkpoints_prune_interval.tick(), if !matches!(config.num_epochs_to_retain_for_checkpoints(), None | Some(u64::MAX) | Some(0)) => {
-                        if let Err(err) = Self::prune_checkpoints_for_eligible_epochs(&perpetual_db, &checkpoint_store, &objects_lock_table, config, metrics.clone(), indirect_objects_threshold, archive_readers.clone()).await {
+                        if let Err(err) = Self::prune_checkpoints_for_eligible_epochs(&perpetual_db, &checkpoint_store, &objects_lock_table, config, metrics.clone(), indirect_objects_threshold, archive_readers.clone(), backup_watermark.as_ref()).await {
                             error!("Failed to prune checkpoints: {:?}", err);
                         }
                     },
@@ -561,6 +679,7 @@ impl AuthorityStorePruner {
         registry: &Registry,
         indirect_objects_threshold: usize,
         archive_readers: ArchiveReaderBalancer,
+        backup_watermark: Option<BackupWatermark>,
     ) -> Self {
         AuthorityStorePruner {
             _objects_pruner_cancel_handle: Self::setup_pruning(
@@ -572,6 +691,7 @@ impl AuthorityStorePruner {
                 AuthorityStorePruningMetrics::new(registry),
                 indirect_objects_threshold,
                 archive_readers,
+                backup_watermark,
             ),
         }
     }
@@ -598,11 +718,16 @@ mod tests {
         get_store_object_pair, ObjectContentDigest, StoreData, StoreObject, StoreObjectPair,
         StoreObjectWrapper,
     };
+    use crate::checkpoints::CheckpointStore;
+    use crate::db_checkpoint_handler::backup_watermark::BackupWatermark;
     use prometheus::Registry;
     use sui_storage::mutex_table::RwLockTable;
+    use sui_swarm_config::test_utils::CommitteeFixture;
     use sui_types::base_types::ObjectDigest;
+    use sui_types::committee::ProtocolVersion;
     use sui_types::effects::TransactionEffects;
     use sui_types::effects::TransactionEffectsAPI;
+    use sui_types::messages_checkpoint::EndOfEpochData;
     use sui_types::{
         base_types::{ObjectID, SequenceNumber},
         object::Object,
@@ -763,6 +888,68 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_cap_at_backup_watermark() -> Result<(), anyhow::Error> {
+        let path = tempfile::tempdir()?;
+        let checkpoint_store = CheckpointStore::new(path.path());
+        // Pruning is not capped unless it waits for backups
+        assert_eq!(
+            AuthorityStorePruner::cap_at_backup_watermark(100, &checkpoint_store, None)?,
+            Some(100)
+        );
+        let backup_watermark = BackupWatermark::default();
+        // Nothing is pruned before a first epoch is backed up
+        assert_eq!(
+            AuthorityStorePruner::cap_at_backup_watermark(
+                100,
+                &checkpoint_store,
+                Some(&backup_watermark)
+            )?,
+            None
+        );
+
+        let committee = CommitteeFixture::generate(rand::rngs::OsRng, 0, 4);
+        let (checkpoints, _, _, _) = committee.make_empty_checkpoints(5, None);
+        let (last_checkpoint, _, end_of_epoch_checkpoint) = committee.make_end_of_epoch_checkpoint(
+            checkpoints.last().cloned().unwrap(),
+            Some(EndOfEpochData {
+                next_epoch_committee: committee.committee().voting_rights.clone(),
+                next_epoch_protocol_version: ProtocolVersion::MIN,
+                epoch_commitments: vec![],
+            }),
+        );
+        checkpoint_store.insert_verified_checkpoint(&end_of_epoch_checkpoint)?;
+        backup_watermark.confirm(0);
+        // Pruning stops at the last checkpoint of the backed up epoch
+        assert_eq!(
+            AuthorityStorePruner::cap_at_backup_watermark(
+                100,
+                &checkpoint_store,
+                Some(&backup_watermark)
+            )?,
+            Some(last_checkpoint)
+        );
+        assert_eq!(
+            AuthorityStorePruner::cap_at_backup_watermark(
+                2,
+                &checkpoint_store,
+                Some(&backup_watermark)
+            )?,
+            Some(2)
+        );
+        // Nor past an epoch whose last checkpoint is not known locally
+        backup_watermark.confirm(1);
+        assert_eq!(
+            AuthorityStorePruner::cap_at_backup_watermark(
+                100,
+                &checkpoint_store,
+                Some(&backup_watermark)
+            )?,
+            None
+        );
+        Ok(())
+    }
+
     #[cfg(not(target_env = "msvc"))]
     #[tokio::test]
     async fn test_db_size_after_compaction() -> Result<(), anyhow::Error> {
@@ -235,6 +235,7 @@ impl<'a> TestAuthorityBuilder<'a> {
                 dump_file_directory: Some(tempdir().unwrap().into_path()),
             },
             ArchiveReaderBalancer::default(),
+            None,
         )
         .await;
         // For any type of local testing that does not actually spawn a node, the checkpoint executor
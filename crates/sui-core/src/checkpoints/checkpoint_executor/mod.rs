@@ -300,6 +300,13 @@ impl CheckpointExecutor {
         while *next_to_schedule <= *latest_synced_checkpoint.sequence_number()
             && pending.len() < self.config.checkpoint_execution_max_concurrency
         {
+            if matches!(self.config.stop_at_checkpoint, Some(stop) if *next_to_schedule > stop) {
+                debug!(
+                    "Not scheduling checkpoint {:?}, execution stops at checkpoint {:?}",
+                    next_to_schedule, self.config.stop_at_checkpoint
+                );
+                return;
+            }
             let checkpoint = self
                 .checkpoint_store
                 .get_checkpoint_by_sequence_number(*next_to_schedule)
@@ -100,6 +100,45 @@ pub async fn test_checkpoint_executor_crash_recovery() {
     executor_handle.abort();
 }
 
+/// Test that checkpoint executor doesn't execute synced checkpoints past the configured
+/// stop checkpoint
+#[tokio::test]
+pub async fn test_checkpoint_executor_stop_at_checkpoint() {
+    let buffer_size = num_cpus::get() * 2;
+    let tempdir = tempdir().unwrap();
+    let checkpoint_store = CheckpointStore::new(tempdir.path());
+
+    let (state, mut executor, _accumulator, checkpoint_sender, committee): (
+        Arc<AuthorityState>,
+        CheckpointExecutor,
+        Arc<StateAccumulator>,
+        Sender<VerifiedCheckpoint>,
+        CommitteeFixture,
+    ) = init_executor_test(buffer_size, checkpoint_store.clone()).await;
+    executor.config.stop_at_checkpoint = Some(buffer_size as u64);
+
+    let _ = sync_new_checkpoints(
+        &checkpoint_store,
+        &checkpoint_sender,
+        2 * buffer_size,
+        None,
+        &committee,
+    );
+
+    let epoch_store = state.epoch_store_for_testing().clone();
+    let executor_handle =
+        spawn_monitored_task!(async move { executor.run_epoch(epoch_store).await });
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    let highest_executed = checkpoint_store
+        .get_highest_executed_checkpoint_seq_number()
+        .unwrap()
+        .expect("Expected highest executed to not be None");
+    assert_eq!(highest_executed, buffer_size as u64);
+
+    executor_handle.abort();
+}
+
 /// Test that checkpoint execution correctly signals end of epoch after
 /// receiving last checkpoint of epoch, then resumes executing cehckpoints
 /// from the next epoch if called after reconfig
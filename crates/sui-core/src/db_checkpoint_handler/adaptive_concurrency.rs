@@ -0,0 +1,385 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Concurrency of the writes of uploads to the remote store, adapted to the store in the
+//! manner of TCP congestion control: additive increase while writes are fast, multiplicative
+//! decrease when the store throttles them. Uploads thereby settle close to what each bucket
+//! backend sustains, without tuning `upload-concurrency` for every one of them. Their
+//! bandwidth may be limited too, see `DBCheckpointConfig::upload_rate_bytes_per_sec`.
+
+use crate::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use anyhow::Result;
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use parking_lot::Mutex;
+use reqwest::StatusCode;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sui_config::node::AdaptiveConcurrencyConfig;
+use sui_storage::object_store::util::put;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+pub const DEFAULT_TARGET_LATENCY_MS: u64 = 2000;
+/// Default upper bound of the concurrency, as a multiple of the initial one
+const DEFAULT_MAX_CONCURRENCY_FACTOR: usize = 4;
+/// Attempts of a write throttled by the remote store before the upload fails
+const MAX_THROTTLED_ATTEMPTS: u32 = 5;
+/// Delay before retrying a throttled write, multiplied by the number of attempts so far
+const THROTTLED_RETRY_DELAY: Duration = Duration::from_millis(200);
+/// HTTP statuses object stores answer throttled requests with, S3 answering `SlowDown` with
+/// a 503
+const THROTTLING_STATUSES: &[StatusCode] = &[
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::SERVICE_UNAVAILABLE,
+];
+
+/// Whether `err` is the remote store rejecting a request because of its request rate, as told
+/// by the status of the HTTP response the error originates from
+pub fn is_throttling_error(err: &object_store::Error) -> bool {
+    let object_store::Error::Generic { source, .. } = err else {
+        return false;
+    };
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(source.as_ref());
+    while let Some(err) = source {
+        if let Some(status) = err
+            .downcast_ref::<reqwest::Error>()
+            .and_then(|err| err.status())
+        {
+            return THROTTLING_STATUSES.contains(&status);
+        }
+        source = err.source();
+    }
+    false
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WriteOutcome {
+    Completed(Duration),
+    Throttled,
+    /// Failed for another reason or cancelled, which says nothing about the store load
+    Other,
+}
+
+struct LimiterState {
+    limit: usize,
+    in_flight: usize,
+    /// Writes completed under the target latency since the limit last changed
+    fast_writes: usize,
+    /// Number of decreases of the limit, so that the writes in flight when the store starts
+    /// throttling lower it only once
+    generation: u64,
+}
+
+pub struct AdaptiveConcurrency {
+    min: usize,
+    max: usize,
+    target_latency: Duration,
+    state: Mutex<LimiterState>,
+    notify: Notify,
+    /// Bandwidth the writes draw from, unlimited by default
+    bandwidth: BandwidthLimiter,
+}
+
+/// Allows a write to the remote store while held
+pub struct Permit<'a> {
+    limiter: &'a AdaptiveConcurrency,
+    generation: u64,
+    outcome: WriteOutcome,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.release(self.generation, self.outcome);
+    }
+}
+
+impl AdaptiveConcurrency {
+    /// Starts at `initial` and adapts within the bounds of `config`
+    pub fn new(initial: NonZeroUsize, config: &AdaptiveConcurrencyConfig) -> Self {
+        let min = config.min_concurrency.unwrap_or(1).max(1);
+        let max = config
+            .max_concurrency
+            .unwrap_or(initial.get() * DEFAULT_MAX_CONCURRENCY_FACTOR)
+            .max(min);
+        Self::with_bounds(
+            initial.get().clamp(min, max),
+            min,
+            max,
+            Duration::from_millis(
+                config
+                    .target_latency_ms
+                    .unwrap_or(DEFAULT_TARGET_LATENCY_MS),
+            ),
+        )
+    }
+    /// Never adapts, `concurrency` writes run at most at any time
+    pub fn fixed(concurrency: NonZeroUsize) -> Self {
+        Self::with_bounds(
+            concurrency.get(),
+            concurrency.get(),
+            concurrency.get(),
+            Duration::ZERO,
+        )
+    }
+    fn with_bounds(initial: usize, min: usize, max: usize, target_latency: Duration) -> Self {
+        AdaptiveConcurrency {
+            min,
+            max,
+            target_latency,
+            state: Mutex::new(LimiterState {
+                limit: initial,
+                in_flight: 0,
+                fast_writes: 0,
+                generation: 0,
+            }),
+            notify: Notify::new(),
+            bandwidth: BandwidthLimiter::default(),
+        }
+    }
+    pub fn with_bandwidth_limiter(mut self, bandwidth: BandwidthLimiter) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+    /// Current number of writes allowed to run at once
+    pub fn limit(&self) -> NonZeroUsize {
+        NonZeroUsize::new(self.state.lock().limit).unwrap()
+    }
+    /// Number of writes the limit can ever reach, to size the streams feeding the limiter
+    pub fn max(&self) -> NonZeroUsize {
+        NonZeroUsize::new(self.max).unwrap()
+    }
+    /// Waits for a write to be allowed. Taken before reading the contents of the write with
+    /// `put_with_permit`, so that the files waiting for a write aren't held in memory.
+    pub async fn acquire(&self) -> Permit<'_> {
+        loop {
+            // Registered before checking, so that a release in between isn't missed
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return Permit {
+                        limiter: self,
+                        generation: state.generation,
+                        outcome: WriteOutcome::Other,
+                    };
+                }
+            }
+            notified.await;
+        }
+    }
+    fn release(&self, generation: u64, outcome: WriteOutcome) {
+        let mut state = self.state.lock();
+        state.in_flight -= 1;
+        let mut freed = 1;
+        match outcome {
+            WriteOutcome::Throttled if generation == state.generation => {
+                let limit = (state.limit / 2).max(self.min);
+                if limit != state.limit {
+                    info!(
+                        "Remote store throttled uploads, lowering concurrency from {} to {limit}",
+                        state.limit
+                    );
+                }
+                state.limit = limit;
+                state.fast_writes = 0;
+                state.generation += 1;
+            }
+            WriteOutcome::Completed(latency) if latency <= self.target_latency => {
+                state.fast_writes += 1;
+                if state.fast_writes >= state.limit && state.limit < self.max {
+                    state.limit += 1;
+                    state.fast_writes = 0;
+                    freed += 1;
+                }
+            }
+            _ => {}
+        }
+        drop(state);
+        for _ in 0..freed {
+            self.notify.notify_one();
+        }
+    }
+    /// Writes `bytes` to `location` in `store` once a write is allowed. Throttled writes are
+    /// retried after lowering the limit, other failures with the retries of `put`.
+    pub async fn put(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        store: Arc<DynObjectStore>,
+    ) -> Result<()> {
+        // Empty files are skipped by `put`
+        if bytes.is_empty() {
+            return Ok(put(location, bytes, store).await?);
+        }
+        // Waits for bandwidth before taking a permit, so that a limited write doesn't hold
+        // back the ones behind it
+        self.bandwidth.consume(bytes.len()).await;
+        let permit = self.acquire().await;
+        self.write(permit, location, bytes, store).await
+    }
+    /// Writes `bytes` to `location` in `store` under `permit`, taken with `acquire` before
+    /// reading them. The bandwidth of the write is drawn while holding the permit.
+    pub async fn put_with_permit(
+        &self,
+        permit: Permit<'_>,
+        location: &Path,
+        bytes: Bytes,
+        store: Arc<DynObjectStore>,
+    ) -> Result<()> {
+        if bytes.is_empty() {
+            drop(permit);
+            return Ok(put(location, bytes, store).await?);
+        }
+        self.bandwidth.consume(bytes.len()).await;
+        self.write(permit, location, bytes, store).await
+    }
+    async fn write(
+        &self,
+        mut permit: Permit<'_>,
+        location: &Path,
+        bytes: Bytes,
+        store: Arc<DynObjectStore>,
+    ) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            let start = Instant::now();
+            match store.put(location, bytes.clone()).await {
+                Ok(()) => {
+                    permit.outcome = WriteOutcome::Completed(start.elapsed());
+                    return Ok(());
+                }
+                Err(err) if is_throttling_error(&err) => {
+                    permit.outcome = WriteOutcome::Throttled;
+                    if attempt >= MAX_THROTTLED_ATTEMPTS {
+                        return Err(err.into());
+                    }
+                    drop(permit);
+                    warn!("Write of {location} throttled by the remote store, attempt: {attempt}");
+                    tokio::time::sleep(THROTTLED_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                    permit = self.acquire().await;
+                }
+                // Retried while still counted against the limit
+                Err(_) => return Ok(put(location, bytes, store).await?),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_throttling_error, AdaptiveConcurrency, WriteOutcome};
+    use crate::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+    use bytes::Bytes;
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use sui_config::node::AdaptiveConcurrencyConfig;
+
+    #[tokio::test]
+    async fn test_aimd_limit() {
+        let limiter = AdaptiveConcurrency::new(
+            NonZeroUsize::new(4).unwrap(),
+            &AdaptiveConcurrencyConfig {
+                min_concurrency: Some(2),
+                max_concurrency: Some(6),
+                target_latency_ms: Some(100),
+            },
+        );
+        // Throttled writes in flight together lower the limit once
+        let permits = vec![limiter.acquire().await, limiter.acquire().await];
+        for mut permit in permits {
+            permit.outcome = WriteOutcome::Throttled;
+        }
+        assert_eq!(limiter.limit().get(), 2);
+        let mut permit = limiter.acquire().await;
+        permit.outcome = WriteOutcome::Throttled;
+        drop(permit);
+        assert_eq!(limiter.limit().get(), 2);
+
+        // Slow writes never raise the limit, a window of fast ones does
+        for _ in 0..4 {
+            let mut permit = limiter.acquire().await;
+            permit.outcome = WriteOutcome::Completed(Duration::from_secs(1));
+        }
+        assert_eq!(limiter.limit().get(), 2);
+        for _ in 0..20 {
+            let mut permit = limiter.acquire().await;
+            permit.outcome = WriteOutcome::Completed(Duration::from_millis(10));
+        }
+        assert_eq!(limiter.limit().get(), 6);
+
+        let fixed = AdaptiveConcurrency::fixed(NonZeroUsize::new(3).unwrap());
+        let mut permit = fixed.acquire().await;
+        permit.outcome = WriteOutcome::Throttled;
+        drop(permit);
+        assert_eq!(fixed.limit().get(), 3);
+    }
+
+    #[test]
+    fn test_throttling_error() {
+        // Messages mentioning throttling aren't mistaken for a throttled request
+        let err = object_store::Error::Generic {
+            store: "S3",
+            source: "Status(503) SlowDown".into(),
+        };
+        assert!(!is_throttling_error(&err));
+        let err = object_store::Error::NotFound {
+            path: "Throttled".to_string(),
+            source: "Too Many Requests".into(),
+        };
+        assert!(!is_throttling_error(&err));
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_bounded_by_limit() {
+        let limiter = Arc::new(AdaptiveConcurrency::fixed(NonZeroUsize::new(3).unwrap()));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..12)
+            .map(|_| {
+                let (limiter, in_flight, max_in_flight) =
+                    (limiter.clone(), in_flight.clone(), max_in_flight.clone());
+                tokio::spawn(async move {
+                    let _permit = limiter.acquire().await;
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn test_bandwidth_limited_writes() -> anyhow::Result<()> {
+        let store = Arc::new(InMemory::new());
+        let limiter = AdaptiveConcurrency::fixed(NonZeroUsize::new(3).unwrap())
+            .with_bandwidth_limiter(BandwidthLimiter::new(Some(1000)));
+        let start = tokio::time::Instant::now();
+        for index in 0..3 {
+            limiter
+                .put(
+                    &Path::from(format!("file_{index}")),
+                    Bytes::from(vec![0u8; 500]),
+                    store.clone(),
+                )
+                .await?;
+        }
+        // The first write starts right away, the others wait for the bytes before them
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(limiter.limit().get(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Authorization of the storage admin endpoints, served by the node and by sui-tool while it
+//! restores a node, see `DBCheckpointConfig::admin_tokens`. Every token is granted actions one
+//! by one, so that a token reading status can't change how the node stores its data. Without
+//! tokens every request is denied, the endpoints are not served at all then.
+
+use crate::db_checkpoint_handler::manifest::sha256_hex;
+use std::fmt;
+use sui_config::node::{StorageAdminAction, StorageAdminToken};
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessDenied {
+    /// No token, or a token which isn't configured, or no tokens are configured at all
+    Unauthenticated,
+    /// A configured token which isn't granted the action
+    Forbidden,
+}
+
+impl fmt::Display for AccessDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessDenied::Unauthenticated => write!(f, "missing or unknown admin token"),
+            AccessDenied::Forbidden => write!(f, "admin token not allowed to perform this action"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StorageAdminAuth {
+    tokens: Vec<StorageAdminToken>,
+}
+
+impl StorageAdminAuth {
+    pub fn new(tokens: Vec<StorageAdminToken>) -> Self {
+        StorageAdminAuth { tokens }
+    }
+    /// Whether tokens are configured, no request is authorized otherwise
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+    /// Checks that the token in `authorization`, the value of the `Authorization` header of
+    /// the request, may perform `action`. Returns the name of its holder.
+    pub fn authorize(
+        &self,
+        authorization: Option<&str>,
+        action: StorageAdminAction,
+    ) -> Result<&str, AccessDenied> {
+        let token = authorization
+            .and_then(|authorization| authorization.strip_prefix(BEARER_PREFIX))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or(AccessDenied::Unauthenticated)?;
+        let digest = sha256_hex(token.as_bytes());
+        let holder = self
+            .tokens
+            .iter()
+            .find(|holder| holder.token_sha256.eq_ignore_ascii_case(&digest))
+            .ok_or(AccessDenied::Unauthenticated)?;
+        if holder.actions.contains(&action) {
+            Ok(&holder.name)
+        } else {
+            Err(AccessDenied::Forbidden)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessDenied, StorageAdminAuth};
+    use crate::db_checkpoint_handler::manifest::sha256_hex;
+    use sui_config::node::{StorageAdminAction, StorageAdminToken};
+
+    #[test]
+    fn test_authorize_actions() {
+        let disabled = StorageAdminAuth::default();
+        for authorization in [None, Some("Bearer "), Some("Bearer token")] {
+            assert_eq!(
+                disabled.authorize(authorization, StorageAdminAction::ReadStatus),
+                Err(AccessDenied::Unauthenticated)
+            );
+        }
+
+        let auth = StorageAdminAuth::new(vec![
+            StorageAdminToken {
+                name: "dashboard".to_string(),
+                token_sha256: sha256_hex(b"read-token"),
+                actions: vec![StorageAdminAction::ReadStatus],
+            },
+            StorageAdminToken {
+                name: "oncall".to_string(),
+                token_sha256: sha256_hex(b"oncall-token").to_uppercase(),
+                actions: vec![
+                    StorageAdminAction::ReadStatus,
+                    StorageAdminAction::SetRestoreRate,
+                ],
+            },
+        ]);
+        assert_eq!(
+            auth.authorize(Some("Bearer read-token"), StorageAdminAction::ReadStatus),
+            Ok("dashboard")
+        );
+        assert_eq!(
+            auth.authorize(
+                Some("Bearer read-token"),
+                StorageAdminAction::SetRestoreRate
+            ),
+            Err(AccessDenied::Forbidden)
+        );
+        assert_eq!(
+            auth.authorize(
+                Some("Bearer oncall-token"),
+                StorageAdminAction::SetRestoreRate
+            ),
+            Ok("oncall")
+        );
+        for authorization in [
+            None,
+            Some("read-token"),
+            Some("Bearer "),
+            Some("Bearer other"),
+        ] {
+            assert_eq!(
+                auth.authorize(authorization, StorageAdminAction::ReadStatus),
+                Err(AccessDenied::Unauthenticated)
+            );
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Archives of the `TarArchives` upload layout, which packs the files of an epoch into a few
+//! tar archives compressed with zstd instead of uploading each of them as an object:
+//!
+//! ```text
+//! epoch_N/
+//!  ├─ archive_00000000.tar.zst
+//!  ├─ archive_00001532.tar.zst
+//!  └─ MANIFEST
+//! ```
+//!
+//! Archives are named after the index in the manifest of their first file, so that an upload
+//! resumed after the epoch upload deadline names them the same. Every file is a ustar member
+//! compressed as an independent zstd frame, and the manifest records the offset and size of
+//! the frame of each file: single files are read with a ranged request, while the archive as a
+//! whole is a regular zstd stream of a regular tar archive, which `zstd -d | tar -x` extracts.
+//! Files larger than an archive are uploaded uncompressed under their own path instead, as with
+//! the `Mirrored` layout, and the manifest records them without an archive offset, so that no
+//! archive exceeds the configured size.
+
+use crate::db_checkpoint_handler::manifest::FileEntry;
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use std::io::Read;
+use std::sync::Arc;
+use sui_storage::FileCompression;
+
+/// Size of the files packed in an archive when not configured
+pub const DEFAULT_ARCHIVE_SIZE_BYTES: u64 = 64 << 20;
+/// Sizes of the files packed in an archive which can be configured, archives are held in memory
+/// while uploaded
+pub const ARCHIVE_SIZE_BYTES_RANGE: std::ops::RangeInclusive<u64> = (1 << 20)..=(1 << 30);
+pub const ARCHIVE_SUFFIX: &str = ".tar.zst";
+const BLOCK_SIZE: usize = 512;
+const NAME_SIZE: usize = 100;
+const PREFIX_SIZE: usize = 155;
+const SIZE_FIELD: std::ops::Range<usize> = 124..136;
+const CHECKSUM_FIELD: std::ops::Range<usize> = 148..156;
+const PREFIX_OFFSET: usize = 345;
+
+/// Key of the archive of the files of `epoch_dir` starting at index `first_file` of the
+/// manifest
+pub fn archive_path(epoch_dir: &Path, first_file: usize) -> Path {
+    epoch_dir.child(format!("archive_{first_file:08}{ARCHIVE_SUFFIX}"))
+}
+
+/// Whether `file` is packed into an archive of `target_size`, rather than uploaded on its own
+/// for being larger than a whole archive
+pub fn is_archived(file: &FileEntry, target_size: u64) -> bool {
+    file.size as u64 <= target_size
+}
+
+/// Index of the file after the last one of the batch starting at `start`: either the files of
+/// an archive, until their size reaches `target_size`, or a single file larger than that
+pub fn archive_end(files: &[FileEntry], start: usize, target_size: u64) -> usize {
+    if !is_archived(&files[start], target_size) {
+        return start + 1;
+    }
+    let mut size = 0;
+    let mut end = start;
+    while end < files.len() && size + files[end].size as u64 <= target_size {
+        size += files[end].size as u64;
+        end += 1;
+    }
+    end
+}
+
+/// Tar member of the file at `path`, compressed as a single zstd frame
+pub fn compress_member(path: &str, contents: &[u8]) -> Result<Bytes> {
+    let mut member = tar_header(path, contents.len())?;
+    member.extend_from_slice(contents);
+    member.resize(padded(member.len()), 0);
+    let mut compressed = vec![];
+    FileCompression::zstd_compress(&mut &member[..], &mut compressed)?;
+    Ok(Bytes::from(compressed))
+}
+
+/// The two zero blocks ending a tar archive, compressed as a single zstd frame
+pub fn end_of_archive() -> Result<Bytes> {
+    let mut compressed = vec![];
+    FileCompression::zstd_compress(&mut &[0u8; 2 * BLOCK_SIZE][..], &mut compressed)?;
+    Ok(Bytes::from(compressed))
+}
+
+/// Reads the compressed frame holding the tar member of `file` from its archive in `store`
+pub async fn read_member(file: &FileEntry, store: Arc<DynObjectStore>) -> Result<Bytes> {
+    let (Some(offset), Some(compressed_size)) = (file.archive_offset, file.compressed_size) else {
+        return Err(anyhow!("{} is not stored in an archive", file.path));
+    };
+    let archive = Path::from(file.remote_path.as_str());
+    Ok(store
+        .get_range(&archive, offset..offset + compressed_size)
+        .await?)
+}
+
+/// Contents of `file` from the compressed frame of its tar member. The header of the member
+/// must name the file and its size, the contents are left for the caller to verify.
+pub fn extract_member(file: &FileEntry, frame: Bytes) -> Result<Bytes> {
+    let mut contents = Vec::with_capacity(file.size);
+    member_reader(file, FileCompression::Zstd.bytes_decompress(frame)?)?
+        .read_to_end(&mut contents)
+        .with_context(|| format!("Failed to decompress archive member of {}", file.path))?;
+    if contents.len() < file.size {
+        return Err(anyhow!("Truncated archive member of {}", file.path));
+    }
+    Ok(Bytes::from(contents))
+}
+
+/// Reader of the contents of `file` from `member`, the decompressed tar member of the file,
+/// once its header is checked to name the file and its size
+pub fn member_reader<R: Read>(file: &FileEntry, mut member: R) -> Result<std::io::Take<R>> {
+    let mut header = [0u8; BLOCK_SIZE];
+    member
+        .read_exact(&mut header)
+        .with_context(|| format!("Truncated archive member of {}", file.path))?;
+    if parse_octal(&header[CHECKSUM_FIELD])? != checksum(&header) {
+        return Err(anyhow!("Invalid header of archive member of {}", file.path));
+    }
+    let path = member_path(&header);
+    let size = parse_size(&header[SIZE_FIELD])?;
+    if path != file.path || size != file.size as u64 {
+        return Err(anyhow!(
+            "Archive member {path} of {size} bytes found in place of {} of {} bytes",
+            file.path,
+            file.size
+        ));
+    }
+    Ok(member.take(size))
+}
+
+/// ustar header of a regular file, with the metadata left out so that the same db
+/// checkpoint always packs into the same archives
+fn tar_header(path: &str, size: usize) -> Result<Vec<u8>> {
+    let mut header = vec![0u8; BLOCK_SIZE];
+    let (prefix, name) = split_path(path)?;
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[PREFIX_OFFSET..PREFIX_OFFSET + prefix.len()].copy_from_slice(prefix.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    write_size(&mut header[SIZE_FIELD], size as u64);
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    let checksum = format!("{:06o}\0 ", checksum(&header));
+    header[CHECKSUM_FIELD].copy_from_slice(checksum.as_bytes());
+    Ok(header)
+}
+
+/// Splits `path` into the prefix and name fields of a ustar header
+fn split_path(path: &str) -> Result<(&str, &str)> {
+    if path.len() <= NAME_SIZE {
+        return Ok(("", path));
+    }
+    path.match_indices('/')
+        .map(|(index, _)| (&path[..index], &path[index + 1..]))
+        .find(|(prefix, name)| {
+            prefix.len() <= PREFIX_SIZE && !name.is_empty() && name.len() <= NAME_SIZE
+        })
+        .ok_or_else(|| anyhow!("Path {path} is too long for a tar archive"))
+}
+
+fn member_path(header: &[u8]) -> String {
+    let field = |bytes: &[u8]| {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    };
+    let name = field(&header[..NAME_SIZE]);
+    let prefix = field(&header[PREFIX_OFFSET..PREFIX_OFFSET + PREFIX_SIZE]);
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// Sum of the bytes of `header`, counting its checksum field as spaces
+fn checksum(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(index, b)| {
+            if CHECKSUM_FIELD.contains(&index) {
+                b' ' as u64
+            } else {
+                *b as u64
+            }
+        })
+        .sum()
+}
+
+/// Writes `size` in octal, or in the base-256 extension of GNU tar for files of 8 GiB and more
+fn write_size(field: &mut [u8], size: u64) {
+    if size < 1 << 33 {
+        field.copy_from_slice(format!("{size:011o}\0").as_bytes());
+    } else {
+        field.fill(0);
+        field[4..].copy_from_slice(&size.to_be_bytes());
+        field[0] = 0x80;
+    }
+}
+
+fn parse_size(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(u64::from_be_bytes(field[4..].try_into()?));
+    }
+    parse_octal(field)
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64> {
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(digits, 8).with_context(|| format!("Invalid octal field: {digits:?}"))
+}
+
+fn padded(len: usize) -> usize {
+    (len + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{archive_end, compress_member, end_of_archive, extract_member, is_archived};
+    use super::{member_path, parse_size, tar_header, write_size, BLOCK_SIZE};
+    use crate::db_checkpoint_handler::manifest::{sha3_hex, FileEntry};
+    use bytes::Bytes;
+    use std::io::Read;
+    use sui_storage::FileCompression;
+
+    fn file_entry(path: &str, contents: &[u8]) -> FileEntry {
+        FileEntry {
+            path: path.to_string(),
+            remote_path: String::new(),
+            size: contents.len(),
+            sha3_digest: sha3_hex(contents),
+            chunks: vec![],
+            sha256_digest: None,
+            compressed_size: None,
+            frames: vec![],
+            archive_offset: None,
+        }
+    }
+
+    #[test]
+    fn test_archive_members() -> anyhow::Result<()> {
+        let long_path = format!("store/{}/000123.sst", "perpetual".repeat(12));
+        let files = vec![
+            file_entry("file1", b"Lorem ipsum"),
+            file_entry(&long_path, b"dolor sit amet"),
+            file_entry("store/empty", b""),
+        ];
+        let contents: [&[u8]; 3] = [b"Lorem ipsum", b"dolor sit amet", b""];
+        let mut archive = vec![];
+        for (file, contents) in files.iter().zip(contents) {
+            let frame = compress_member(&file.path, contents)?;
+            assert_eq!(extract_member(file, frame.clone())?, contents);
+            archive.extend_from_slice(&frame);
+        }
+        // Frames of another file are refused
+        let frame = compress_member("file1", b"Lorem ipsum")?;
+        assert!(extract_member(&files[1], frame).is_err());
+        archive.extend_from_slice(&end_of_archive()?);
+
+        // The archive decompresses as a whole into a tar archive
+        let mut tar = vec![];
+        FileCompression::Zstd
+            .bytes_decompress(Bytes::from(archive))?
+            .read_to_end(&mut tar)?;
+        let mut offset = 0;
+        for (file, contents) in files.iter().zip(contents) {
+            let header = &tar[offset..offset + BLOCK_SIZE];
+            assert_eq!(member_path(header), file.path);
+            assert_eq!(parse_size(&header[124..136])?, contents.len() as u64);
+            offset += BLOCK_SIZE;
+            assert_eq!(&tar[offset..offset + contents.len()], contents);
+            offset += (contents.len() + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+        }
+        assert_eq!(tar[offset..], [0u8; 2 * BLOCK_SIZE]);
+
+        assert!(tar_header(&"a".repeat(101), 0).is_err());
+        let mut field = [0u8; 12];
+        write_size(&mut field, 10 << 30);
+        assert_eq!(parse_size(&field)?, 10 << 30);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_end() {
+        let files: Vec<FileEntry> = [4, 4, 10, 1, 1]
+            .iter()
+            .map(|size| file_entry("file", &vec![0; *size]))
+            .collect();
+        assert_eq!(archive_end(&files, 0, 8), 2);
+        // Larger than an archive, uploaded on its own
+        assert!(!is_archived(&files[2], 8));
+        assert_eq!(archive_end(&files, 2, 8), 3);
+        assert_eq!(archive_end(&files, 3, 8), 5);
+        assert_eq!(archive_end(&files, 0, 100), 5);
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Audit log of the backup history of a bucket. Object stores have no append operation, so
+//! the log is made of immutable records, one object per event, under the `audit/` prefix:
+//!
+//! ```text
+//! audit/
+//!  ├─ epoch_10/
+//!  │   ├─ 00000001690000000000_upload_3f2a9c01.json
+//!  │   └─ 00000001690000600000_retention_delete_8b1e0d42.json
+//!  └─ epoch_11/
+//!      └─ ...
+//! ```
+//!
+//! Record keys start with a zero padded timestamp so that listing returns the records of an
+//! epoch in chronological order. Records are never rewritten or deleted by the node. The upload
+//! records of epochs uploaded with consolidated metadata are embedded in their success marker,
+//! and moved into the log when the epoch is uploaded again.
+
+use crate::db_checkpoint_handler::manifest::{read_epoch_metadata, MANIFEST_FILENAME};
+use anyhow::Result;
+use bytes::Bytes;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sui_storage::object_store::util::{list_epoch_dirs, put};
+
+pub const AUDIT_DIR: &str = "audit";
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// Epoch was uploaded to the bucket
+    Upload,
+    /// Uploaded epoch was removed from local disk by retention
+    RetentionDelete,
+    /// Epoch was restored from the bucket to local disk
+    Restore,
+    /// Uploaded epoch was removed from the bucket by retention
+    RemoteRetentionDelete,
+    /// Uploaded epoch was removed from the bucket by an operator
+    RemoteAdminDelete,
+}
+
+impl AuditAction {
+    fn name(&self) -> &'static str {
+        match self {
+            AuditAction::Upload => "upload",
+            AuditAction::RetentionDelete => "retention_delete",
+            AuditAction::Restore => "restore",
+            AuditAction::RemoteRetentionDelete => "remote_retention_delete",
+            AuditAction::RemoteAdminDelete => "remote_admin_delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct AuditRecord {
+    pub timestamp_ms: u64,
+    /// Identity of the node or operator performing the action
+    pub actor: String,
+    pub action: AuditAction,
+    pub epoch: u32,
+    /// Hex encoded sha3 digest of the epoch MANIFEST the action applies to
+    pub manifest_sha3_digest: Option<String>,
+}
+
+/// Writes audit records to the bucket holding the db checkpoints
+#[derive(Clone)]
+pub struct AuditLog {
+    store: Arc<DynObjectStore>,
+    actor: String,
+}
+
+impl AuditLog {
+    pub fn new(store: Arc<DynObjectStore>, actor: String) -> Self {
+        AuditLog { store, actor }
+    }
+    pub async fn record(&self, action: AuditAction, epoch: u32) -> Result<AuditRecord> {
+        let epoch_dir = Path::from(format!("epoch_{}", epoch));
+        // The manifest is absent for epochs uploaded before manifests were introduced
+        let manifest_sha3_digest = manifest_sha3_digest(&epoch_dir, self.store.clone())
+            .await
+            .ok();
+        let record = self.new_record(action, epoch, manifest_sha3_digest)?;
+        self.append(&record).await?;
+        Ok(record)
+    }
+    /// Writes `record` into the log. Writing the same record again leaves the log unchanged.
+    pub async fn append(&self, record: &AuditRecord) -> Result<()> {
+        let bytes = Bytes::from(serde_json::to_vec(record)?);
+        // The digest suffix keeps records from different actors at the same instant apart
+        let key = Path::from(format!(
+            "{}/epoch_{}/{:020}_{}_{}.json",
+            AUDIT_DIR,
+            record.epoch,
+            record.timestamp_ms,
+            record.action.name(),
+            &Hex::encode(Sha3_256::digest(&bytes).digest)[..8]
+        ));
+        put(&key, bytes, self.store.clone()).await?;
+        Ok(())
+    }
+    /// Builds the record of `action` on `epoch` without writing it, to be embedded into the
+    /// metadata of the epoch
+    pub fn new_record(
+        &self,
+        action: AuditAction,
+        epoch: u32,
+        manifest_sha3_digest: Option<String>,
+    ) -> Result<AuditRecord> {
+        Ok(AuditRecord {
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+            actor: self.actor.clone(),
+            action,
+            epoch,
+            manifest_sha3_digest,
+        })
+    }
+}
+
+/// Hex encoded sha3 digest of the MANIFEST of `epoch_dir` as stored in `store`
+pub async fn manifest_sha3_digest(epoch_dir: &Path, store: Arc<DynObjectStore>) -> Result<String> {
+    match store.get(&epoch_dir.child(MANIFEST_FILENAME)).await {
+        Ok(result) => Ok(Hex::encode(Sha3_256::digest(&result.bytes().await?).digest)),
+        Err(object_store::Error::NotFound { .. }) => Ok(read_epoch_metadata(epoch_dir, store)
+            .await?
+            .manifest_sha3_digest),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Reads the audit records of the bucket, optionally only those of `epoch`, oldest first
+pub async fn read_audit_log(
+    store: Arc<DynObjectStore>,
+    epoch: Option<u32>,
+) -> Result<Vec<AuditRecord>> {
+    let prefix = match epoch {
+        Some(epoch) => Path::from(format!("{}/epoch_{}", AUDIT_DIR, epoch)),
+        None => Path::from(AUDIT_DIR),
+    };
+    let mut records = vec![];
+    let mut entries = store.list(Some(&prefix)).await?;
+    while let Some(entry) = entries.next().await {
+        let object_metadata = entry?;
+        let bytes = store.get(&object_metadata.location).await?.bytes().await?;
+        records.push(serde_json::from_slice::<AuditRecord>(&bytes)?);
+    }
+    // Uploads of epochs with consolidated metadata are recorded in their success marker
+    let epoch_dirs = match epoch {
+        Some(epoch) => vec![Path::from(format!("epoch_{}", epoch))],
+        None => list_epoch_dirs(store.clone(), None)
+            .await?
+            .into_values()
+            .collect(),
+    };
+    for epoch_dir in epoch_dirs {
+        if let Ok(metadata) = read_epoch_metadata(&epoch_dir, store.clone()).await {
+            records.extend(metadata.audit_record);
+        }
+    }
+    records.sort_by_key(|record| (record.timestamp_ms, record.epoch));
+    Ok(records)
+}
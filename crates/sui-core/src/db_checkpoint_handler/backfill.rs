@@ -0,0 +1,361 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Backfill of the manifests of epochs uploaded by versions which didn't write one. Restores,
+//! verification and retention read the file list of an epoch from its manifest, so legacy
+//! epochs are otherwise treated as incomplete. The files of a legacy epoch are verified
+//! against the local copy of the db checkpoint when it is still around, or trusted on the
+//! strength of its success marker otherwise, before the manifest is written. An epoch which
+//! fails to backfill is reported and left untouched, without stopping the others.
+
+use crate::db_checkpoint_handler::digest_pool::DigestPool;
+use crate::db_checkpoint_handler::expected::EXPECTED_FILENAME;
+use crate::db_checkpoint_handler::manifest::{
+    stream_sha3_hex, write_manifest, write_manifest_shards, EpochManifest,
+    DEFAULT_MANIFEST_SHARD_SIZE, LOCAL_CHECKSUMS_FILENAME, MANIFEST_FILENAME,
+    UPLOAD_PROGRESS_FILENAME,
+};
+use crate::db_checkpoint_handler::{SUCCESS_MARKER, UPLOAD_COMPLETED_MARKER};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use sui_config::node::DBCheckpointUploadLayout;
+use sui_storage::object_store::util::{list_epoch_dirs, put};
+use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+use tracing::{info, warn};
+
+/// Files of a local db checkpoint which legacy uploads never copied to the remote store
+const LOCAL_ONLY_FILES: &[&str] = &[
+    SUCCESS_MARKER,
+    UPLOAD_COMPLETED_MARKER,
+    EXPECTED_FILENAME,
+    LOCAL_CHECKSUMS_FILENAME,
+    UPLOAD_PROGRESS_FILENAME,
+];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackfillSource {
+    /// Every remote file matched the local copy of the db checkpoint
+    LocalCopy,
+    /// No local copy, the digests were computed from the remote files of an epoch with a
+    /// success marker
+    RemoteDigests,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackfillReport {
+    /// Legacy epochs given a manifest, with what their files were verified against
+    pub backfilled: BTreeMap<u32, BackfillSource>,
+    /// Backfilled epochs which were also missing their success marker
+    pub markers_written: Vec<u32>,
+    /// Legacy epochs which couldn't be verified, with the reason. They are left untouched.
+    pub unverified: BTreeMap<u32, String>,
+    /// Epochs whose backfill failed, e.g. on a read error, with the error. They are left
+    /// untouched for the next run.
+    pub failed: BTreeMap<u32, String>,
+    /// Whether nothing was written to the remote store
+    pub dry_run: bool,
+}
+
+/// Writes a manifest for every epoch of `remote_store` without one, and the success marker of
+/// the epochs whose files all match the local copy under `local_dir`. Only computes the report
+/// when `dry_run` is set.
+pub async fn backfill_legacy_epochs(
+    remote_store: Arc<DynObjectStore>,
+    local_dir: Option<&std::path::Path>,
+    dry_run: bool,
+) -> Result<BackfillReport> {
+    let local_store = local_dir
+        .map(|local_dir| {
+            ObjectStoreConfig {
+                object_store: Some(ObjectStoreType::File),
+                directory: Some(local_dir.to_path_buf()),
+                ..Default::default()
+            }
+            .make()
+        })
+        .transpose()?;
+    let pool = DigestPool::default();
+    let mut report = BackfillReport {
+        dry_run,
+        ..Default::default()
+    };
+    for (epoch, remote_dir) in list_epoch_dirs(remote_store.clone(), None).await? {
+        let local = match (local_dir, &local_store) {
+            (Some(local_dir), Some(local_store))
+                if local_dir.join(remote_dir.as_ref()).is_dir() =>
+            {
+                Some((local_dir, local_store.clone()))
+            }
+            _ => None,
+        };
+        match backfill_epoch(
+            epoch,
+            &remote_dir,
+            remote_store.clone(),
+            local,
+            &pool,
+            dry_run,
+        )
+        .await
+        {
+            Ok(EpochBackfill::HasManifest) => {}
+            Ok(EpochBackfill::Unverified(reason)) => {
+                report.unverified.insert(epoch, reason);
+            }
+            Ok(EpochBackfill::Backfilled {
+                source,
+                marker_written,
+            }) => {
+                if marker_written {
+                    report.markers_written.push(epoch);
+                }
+                report.backfilled.insert(epoch, source);
+            }
+            Err(err) => {
+                warn!("Failed to backfill legacy db checkpoint for epoch: {epoch}: {err:?}");
+                report.failed.insert(epoch, format!("{err:#}"));
+            }
+        }
+    }
+    Ok(report)
+}
+
+enum EpochBackfill {
+    /// The epoch already has a manifest
+    HasManifest,
+    Unverified(String),
+    Backfilled {
+        source: BackfillSource,
+        marker_written: bool,
+    },
+}
+
+/// Writes the manifest of the legacy epoch in `remote_dir`, verified against its local copy in
+/// `local` when there is one
+async fn backfill_epoch(
+    epoch: u32,
+    remote_dir: &Path,
+    remote_store: Arc<DynObjectStore>,
+    local: Option<(&std::path::Path, Arc<DynObjectStore>)>,
+    pool: &DigestPool,
+    dry_run: bool,
+) -> Result<EpochBackfill> {
+    // Transient errors must not get an epoch with a manifest mistaken for a legacy one
+    match remote_store
+        .head(&remote_dir.child(MANIFEST_FILENAME))
+        .await
+    {
+        Ok(_) => return Ok(EpochBackfill::HasManifest),
+        Err(object_store::Error::NotFound { .. }) => {}
+        Err(err) => return Err(err.into()),
+    }
+    let has_marker = match remote_store.head(&remote_dir.child(SUCCESS_MARKER)).await {
+        // Enriched markers hold the manifest of the epoch
+        Ok(marker) if marker.size > 0 => return Ok(EpochBackfill::HasManifest),
+        Ok(_) => true,
+        Err(object_store::Error::NotFound { .. }) => false,
+        Err(err) => return Err(err.into()),
+    };
+    let mut manifest = EpochManifest::from_local_dir(
+        epoch,
+        remote_dir,
+        remote_dir,
+        remote_store.clone(),
+        DBCheckpointUploadLayout::Mirrored,
+        &[SUCCESS_MARKER],
+    )
+    .await?;
+    // Legacy uploads didn't record the storage schema, restores migrate them to be safe
+    manifest.schema_version = None;
+    for file in manifest.files.iter_mut() {
+        let location = Path::from(file.remote_path.as_str());
+        let (size, sha3_digest) = stream_sha3_hex(&location, remote_store.clone()).await?;
+        if size != file.size {
+            return Err(anyhow!(
+                "Size of {} changed while backfilling epoch: {epoch}",
+                file.remote_path
+            ));
+        }
+        file.sha3_digest = sha3_digest;
+    }
+    let source = match local {
+        Some((local_dir, local_store)) => {
+            let mut local = EpochManifest::from_local_dir(
+                epoch,
+                remote_dir,
+                remote_dir,
+                local_store,
+                DBCheckpointUploadLayout::Mirrored,
+                LOCAL_ONLY_FILES,
+            )
+            .await?;
+            local
+                .compute_sha3_digests(remote_dir, local_dir, pool)
+                .await?;
+            if let Err(err) = matches_local_copy(&manifest, &local) {
+                warn!(
+                    "Legacy db checkpoint for epoch: {epoch} doesn't match its local copy: {err}"
+                );
+                return Ok(EpochBackfill::Unverified(err.to_string()));
+            }
+            BackfillSource::LocalCopy
+        }
+        None if has_marker => BackfillSource::RemoteDigests,
+        None => {
+            return Ok(EpochBackfill::Unverified(
+                "No success marker and no local copy to verify against".to_string(),
+            ))
+        }
+    };
+    if !dry_run {
+        let index = write_manifest_shards(
+            &manifest,
+            remote_dir,
+            NonZeroUsize::new(DEFAULT_MANIFEST_SHARD_SIZE).unwrap(),
+            remote_store.clone(),
+        )
+        .await?;
+        write_manifest(&index, remote_dir, remote_store.clone()).await?;
+        if !has_marker {
+            put(
+                &remote_dir.child(SUCCESS_MARKER),
+                Bytes::new(),
+                remote_store.clone(),
+            )
+            .await?;
+        }
+        info!("Backfilled manifest of legacy db checkpoint for epoch: {epoch}");
+    }
+    Ok(EpochBackfill::Backfilled {
+        source,
+        marker_written: !has_marker,
+    })
+}
+
+/// Checks that the remote files of `manifest` are exactly the non empty files of `local`, with
+/// the same contents
+fn matches_local_copy(manifest: &EpochManifest, local: &EpochManifest) -> Result<()> {
+    for file in local.files.iter() {
+        match manifest.file(&file.path) {
+            Some(remote) if remote.sha3_digest == file.sha3_digest => {}
+            Some(_) => return Err(anyhow!("Checksum mismatch for {}", file.path)),
+            // Empty files are not copied by every version
+            None if file.size == 0 => {}
+            None => return Err(anyhow!("Missing {} in remote store", file.path)),
+        }
+    }
+    if let Some(file) = manifest
+        .files
+        .iter()
+        .find(|file| local.file(&file.path).is_none())
+    {
+        return Err(anyhow!("Unexpected {} in remote store", file.path));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backfill_legacy_epochs, BackfillSource};
+    use crate::db_checkpoint_handler::manifest::{read_manifest, MANIFEST_FILENAME};
+    use crate::db_checkpoint_handler::SUCCESS_MARKER;
+    use std::fs;
+    use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_backfill_legacy_epochs() -> anyhow::Result<()> {
+        let local_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let remote_store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_dir.path().to_path_buf()),
+            ..Default::default()
+        }
+        .make()?;
+        // Epoch 0 was fully uploaded and its local copy garbage collected, epoch 1 was fully
+        // uploaded but lost its marker, epoch 2 is a partial upload, epoch 3 diverged and the
+        // local copy of epoch 4 can't be listed
+        for epoch in 0..5 {
+            let remote_epoch = remote_dir.path().join(format!("epoch_{epoch}"));
+            fs::create_dir_all(remote_epoch.join("store"))?;
+            fs::write(remote_epoch.join("store/file1"), b"Lorem ipsum")?;
+            fs::write(remote_epoch.join("file2"), b"Lorem ipsum")?;
+            if epoch > 0 {
+                let local_epoch = local_dir.path().join(format!("epoch_{epoch}"));
+                fs::create_dir_all(local_epoch.join("store"))?;
+                fs::write(local_epoch.join("store/file1"), b"Lorem ipsum")?;
+                fs::write(local_epoch.join("file2"), b"Lorem ipsum")?;
+            }
+        }
+        fs::write(remote_dir.path().join("epoch_0").join(SUCCESS_MARKER), b"")?;
+        fs::write(remote_dir.path().join("epoch_4").join(SUCCESS_MARKER), b"")?;
+        std::os::unix::fs::symlink(".", local_dir.path().join("epoch_4/store/loop"))?;
+        fs::remove_file(remote_dir.path().join("epoch_2/file2"))?;
+        fs::write(remote_dir.path().join("epoch_3/file2"), b"Lorem ipsun")?;
+
+        let report =
+            backfill_legacy_epochs(remote_store.clone(), Some(local_dir.path()), true).await?;
+        assert_eq!(report.backfilled.len(), 2);
+        assert_eq!(report.failed.keys().collect::<Vec<_>>(), vec![&4]);
+        assert!(!remote_dir
+            .path()
+            .join("epoch_0")
+            .join(MANIFEST_FILENAME)
+            .exists());
+
+        let report =
+            backfill_legacy_epochs(remote_store.clone(), Some(local_dir.path()), false).await?;
+        assert_eq!(
+            report.backfilled.into_iter().collect::<Vec<_>>(),
+            vec![
+                (0, BackfillSource::RemoteDigests),
+                (1, BackfillSource::LocalCopy)
+            ]
+        );
+        assert_eq!(report.markers_written, vec![1]);
+        assert_eq!(report.unverified.keys().collect::<Vec<_>>(), vec![&2, &3]);
+        assert_eq!(report.failed.keys().collect::<Vec<_>>(), vec![&4]);
+        assert!(!remote_dir
+            .path()
+            .join("epoch_4")
+            .join(MANIFEST_FILENAME)
+            .exists());
+        assert!(remote_dir
+            .path()
+            .join("epoch_1")
+            .join(SUCCESS_MARKER)
+            .exists());
+        assert!(!remote_dir
+            .path()
+            .join("epoch_2")
+            .join(SUCCESS_MARKER)
+            .exists());
+        let manifest = read_manifest(
+            &object_store::path::Path::from("epoch_1"),
+            remote_store.clone(),
+        )
+        .await?;
+        assert_eq!(manifest.num_files(), 2);
+        assert_eq!(manifest.schema_version, None);
+        assert_eq!(manifest.files[1].remote_path, "epoch_1/store/file1");
+
+        // Epochs with a manifest are left alone, the failed one is retried
+        let report = backfill_legacy_epochs(remote_store, None, false).await?;
+        assert_eq!(
+            report.backfilled.into_iter().collect::<Vec<_>>(),
+            vec![(4, BackfillSource::RemoteDigests)]
+        );
+        assert_eq!(report.unverified.keys().collect::<Vec<_>>(), vec![&2, &3]);
+        assert!(report.failed.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Highest epoch whose db checkpoint is confirmed in the remote store. The upload handler
+//! raises it and the pruner of the live db holds back to it, so that any data pruned locally
+//! still has a copy in the bucket.
+
+use crate::checkpoints::CheckpointStore;
+use anyhow::Result;
+use std::sync::Arc;
+use sui_types::base_types::EpochId;
+use sui_types::messages_checkpoint::CheckpointSequenceNumber;
+use tokio::sync::watch;
+
+#[derive(Clone)]
+pub struct BackupWatermark {
+    sender: Arc<watch::Sender<Option<EpochId>>>,
+}
+
+impl Default for BackupWatermark {
+    fn default() -> Self {
+        BackupWatermark {
+            sender: Arc::new(watch::channel(None).0),
+        }
+    }
+}
+
+impl BackupWatermark {
+    /// Records that the db checkpoint of `epoch` is in the remote store. The watermark never
+    /// goes backwards.
+    pub fn confirm(&self, epoch: EpochId) {
+        self.sender.send_if_modified(|backed_up_epoch| {
+            if backed_up_epoch.map_or(true, |backed_up_epoch| epoch > backed_up_epoch) {
+                *backed_up_epoch = Some(epoch);
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Highest epoch whose db checkpoint is in the remote store, if any
+    pub fn backed_up_epoch(&self) -> Option<EpochId> {
+        *self.sender.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<EpochId>> {
+        self.sender.subscribe()
+    }
+
+    /// Last checkpoint of the backed up epoch, `None` while no epoch is backed up or the last
+    /// checkpoint of the epoch is not known locally
+    pub fn max_prunable_checkpoint(
+        &self,
+        checkpoint_store: &CheckpointStore,
+    ) -> Result<Option<CheckpointSequenceNumber>> {
+        let Some(epoch) = self.backed_up_epoch() else {
+            return Ok(None);
+        };
+        Ok(checkpoint_store
+            .get_epoch_last_checkpoint(epoch)?
+            .map(|checkpoint| *checkpoint.sequence_number()))
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bandwidth limit of the downloads of a restore, see
+//! `DBCheckpointConfig::restore_rate_bytes_per_sec`, or of the uploads of db checkpoints, see
+//! `DBCheckpointConfig::upload_rate_bytes_per_sec`. Every transfer draws from the same limit,
+//! which can be changed while they run, so that a restore on a host shared with other nodes
+//! leaves them enough of the network, and an upload doesn't starve consensus of it.
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+struct LimiterState {
+    bytes_per_sec: Option<u64>,
+    /// Time until which the downloads started so far use up the bandwidth
+    next_free: Instant,
+}
+
+struct LimiterInner {
+    state: Mutex<LimiterState>,
+    /// Wakes up the downloads waiting for bandwidth when the limit changes
+    changed: Notify,
+}
+
+/// Handle to a bandwidth limit shared by the transfers it limits and whoever adjusts it
+#[derive(Clone)]
+pub struct BandwidthLimiter {
+    inner: Arc<LimiterInner>,
+}
+
+impl Default for BandwidthLimiter {
+    /// No limit
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        BandwidthLimiter {
+            inner: Arc::new(LimiterInner {
+                state: Mutex::new(LimiterState {
+                    bytes_per_sec: bytes_per_sec.filter(|rate| *rate > 0),
+                    next_free: Instant::now(),
+                }),
+                changed: Notify::new(),
+            }),
+        }
+    }
+    /// Current limit, `None` when transfers are not limited
+    pub fn limit(&self) -> Option<u64> {
+        self.inner.state.lock().bytes_per_sec
+    }
+    /// Changes the limit, `None` or zero lifts it. Applies to the transfers waiting for
+    /// bandwidth right away.
+    pub fn set_limit(&self, bytes_per_sec: Option<u64>) {
+        {
+            let mut state = self.inner.state.lock();
+            state.bytes_per_sec = bytes_per_sec.filter(|rate| *rate > 0);
+            state.next_free = Instant::now();
+        }
+        self.inner.changed.notify_waiters();
+    }
+    /// Waits until a transfer of `bytes` fits in the limit. Transfers which stream are drawn
+    /// part by part as they go, a single draw for a large file would let it burst past the
+    /// limit and only hold back the transfers after it.
+    pub async fn consume(&self, bytes: usize) {
+        loop {
+            // Registered before checking, so that a change in between isn't missed
+            let changed = self.inner.changed.notified();
+            let wait = {
+                let mut state = self.inner.state.lock();
+                let Some(bytes_per_sec) = state.bytes_per_sec else {
+                    return;
+                };
+                let now = Instant::now();
+                if state.next_free <= now {
+                    state.next_free =
+                        now + Duration::from_secs_f64(bytes as f64 / bytes_per_sec as f64);
+                    return;
+                }
+                state.next_free - now
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = changed => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BandwidthLimiter;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn test_bandwidth_limit() {
+        let limiter = BandwidthLimiter::new(Some(1000));
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.consume(500).await;
+        }
+        // The first download starts right away, the others wait for the bytes before them
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        // Lifting the limit releases a waiting download
+        limiter.consume(10_000).await;
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.consume(500).await }
+        });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(!waiting.is_finished());
+        limiter.set_limit(None);
+        waiting.await.unwrap();
+        assert_eq!(limiter.limit(), None);
+        assert!(start.elapsed() < Duration::from_secs(4));
+
+        let unlimited = BandwidthLimiter::new(Some(0));
+        let start = Instant::now();
+        unlimited.consume(usize::MAX).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Upload of a synthetic epoch through the full upload pipeline of `DBCheckpointHandler`, for
+//! operators to size bandwidth and tune upload settings against a bucket before real epochs
+//! arrive.
+
+use crate::db_checkpoint_handler::source::remote_epoch_dir;
+use crate::db_checkpoint_handler::{DBCheckpointHandler, UploadOutcome};
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use prometheus::Registry;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+use sui_config::node::{AuthorityStorePruningConfig, DBCheckpointConfig};
+use sui_storage::object_store::util::delete_recursively;
+use sui_storage::object_store::ObjectStoreConfig;
+use tracing::info;
+
+/// Epoch of the synthetic db checkpoint when not set, far above any real epoch so that the
+/// benchmark never overwrites a real backup
+pub const DEFAULT_BENCHMARK_EPOCH: u32 = u32::MAX;
+const WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UploadBenchmarkReport {
+    pub epoch: u32,
+    pub num_files: usize,
+    pub total_bytes: u64,
+    /// False if the epoch upload deadline suspended the upload
+    pub completed: bool,
+    pub duration_ms: u64,
+    pub bytes_per_sec: u64,
+}
+
+/// Writes `num_files` files of random contents, `total_bytes` in total, into a db checkpoint
+/// directory for `epoch` under `checkpoint_path`. Random contents keep compression and
+/// deduplication from flattering the results.
+pub fn generate_synthetic_epoch(
+    checkpoint_path: &Path,
+    epoch: u32,
+    num_files: usize,
+    total_bytes: u64,
+) -> Result<()> {
+    if num_files == 0 {
+        return Err(anyhow!("A synthetic epoch needs at least one file"));
+    }
+    // Not laid out as a db, so that nothing tries to open it as one
+    let data_dir = checkpoint_path
+        .join(format!("epoch_{epoch}"))
+        .join("synthetic");
+    fs::create_dir_all(&data_dir)?;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; WRITE_BUFFER_SIZE];
+    let file_size = total_bytes / num_files as u64;
+    for i in 0..num_files {
+        let mut remaining = if i == num_files - 1 {
+            total_bytes - file_size * (num_files as u64 - 1)
+        } else {
+            file_size
+        };
+        let mut file = fs::File::create(data_dir.join(format!("{:06}.sst", i + 1)))?;
+        while remaining > 0 {
+            let len = remaining.min(WRITE_BUFFER_SIZE as u64) as usize;
+            rng.fill_bytes(&mut buf[..len]);
+            file.write_all(&buf[..len])?;
+            remaining -= len as u64;
+        }
+    }
+    Ok(())
+}
+
+/// Uploads a synthetic epoch of `num_files` files, `total_bytes` in total, generated under
+/// `work_dir` to the store of `output_store_config` with the upload settings of
+/// `db_checkpoint_config`. The uploaded epoch is deleted from the store afterwards unless
+/// `keep` is set. Fails if the store already holds `epoch`.
+pub async fn run_upload_benchmark(
+    work_dir: &Path,
+    output_store_config: &ObjectStoreConfig,
+    db_checkpoint_config: &DBCheckpointConfig,
+    epoch: u32,
+    num_files: usize,
+    total_bytes: u64,
+    keep: bool,
+) -> Result<UploadBenchmarkReport> {
+    let db_checkpoint_config = DBCheckpointConfig {
+        // Synthetic files are not a db
+        prune_and_compact_before_upload: Some(false),
+        // Benchmark uploads are not backups
+        audit_actor: None,
+        source_layout: None,
+        ..db_checkpoint_config.clone()
+    };
+    let handler = DBCheckpointHandler::new(
+        work_dir,
+        output_store_config,
+        &db_checkpoint_config,
+        0,
+        AuthorityStorePruningConfig::default(),
+        &Registry::default(),
+    )?;
+    let remote_dir = remote_epoch_dir(epoch);
+    if handler
+        .output_object_store
+        .list(Some(&remote_dir))
+        .await?
+        .next()
+        .await
+        .is_some()
+    {
+        return Err(anyhow!(
+            "Remote store already holds {remote_dir}, pick another benchmark epoch"
+        ));
+    }
+    info!("Generating synthetic epoch: {epoch} of {num_files} files and {total_bytes} bytes");
+    generate_synthetic_epoch(work_dir, epoch, num_files, total_bytes)?;
+    let local = handler
+        .source
+        .list(handler.input_object_store.clone())
+        .await?
+        .remove(&epoch)
+        .ok_or_else(|| {
+            anyhow!(
+                "Synthetic epoch: {epoch} not found in {}",
+                work_dir.display()
+            )
+        })?;
+
+    let start = Instant::now();
+    let result = handler.upload_db_checkpoint(&local).await;
+    let duration = start.elapsed();
+    if !keep {
+        delete_recursively(
+            &remote_dir,
+            handler.output_object_store.clone(),
+            handler.upload_concurrency,
+        )
+        .await?;
+    }
+    let outcome = result?;
+    let report = UploadBenchmarkReport {
+        epoch,
+        num_files,
+        total_bytes,
+        completed: outcome == UploadOutcome::Completed,
+        duration_ms: duration.as_millis() as u64,
+        bytes_per_sec: (total_bytes as f64 / duration.as_secs_f64().max(0.001)) as u64,
+    };
+    info!("Upload benchmark finished: {:?}", report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_upload_benchmark;
+    use std::fs;
+    use sui_config::node::DBCheckpointConfig;
+    use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_upload_benchmark() -> anyhow::Result<()> {
+        let work_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let output_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let report = run_upload_benchmark(
+            work_dir.path(),
+            &output_store_config,
+            &DBCheckpointConfig::default(),
+            7,
+            3,
+            10_000,
+            true,
+        )
+        .await?;
+        assert!(report.completed);
+        assert_eq!(report.num_files, 3);
+        let uploaded = remote_dir.path().join("epoch_7/synthetic");
+        let sizes: Vec<u64> = ["000001.sst", "000002.sst", "000003.sst"]
+            .iter()
+            .map(|file| fs::metadata(uploaded.join(file)).unwrap().len())
+            .collect();
+        assert_eq!(sizes, vec![3333, 3333, 3334]);
+
+        // A store already holding the epoch is left alone
+        let work_dir = TempDir::new()?;
+        assert!(run_upload_benchmark(
+            work_dir.path(),
+            &output_store_config,
+            &DBCheckpointConfig::default(),
+            7,
+            1,
+            100,
+            false,
+        )
+        .await
+        .is_err());
+        assert!(uploaded.join("000001.sst").exists());
+
+        let work_dir = TempDir::new()?;
+        run_upload_benchmark(
+            work_dir.path(),
+            &output_store_config,
+            &DBCheckpointConfig::default(),
+            8,
+            1,
+            100,
+            false,
+        )
+        .await?;
+        assert!(!remote_dir.path().join("epoch_8").join("MANIFEST").exists());
+        Ok(())
+    }
+}
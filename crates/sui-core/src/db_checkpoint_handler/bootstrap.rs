@@ -0,0 +1,312 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bootstrap planner for first boot of a node. Every configured bucket is probed for its latest
+//! complete db checkpoint, the download time of each candidate is estimated from its size and
+//! the throughput measured against the bucket, and the fastest safe path (restoring a db
+//! checkpoint, or state syncing from peers) is selected. The decision, along with every
+//! candidate considered, is written next to the db so operators can see why a source was picked.
+
+use crate::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use crate::db_checkpoint_handler::chunking::chunk_path;
+use crate::db_checkpoint_handler::encryption::EncryptionKey;
+use crate::db_checkpoint_handler::manifest::{read_published_manifest, EpochManifest};
+use crate::db_checkpoint_handler::restorer::{DBCheckpointRestorer, RestoreProgress};
+use anyhow::{anyhow, Context, Result};
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sui_storage::object_store::ObjectStoreConfig;
+use sui_types::digests::ChainIdentifier;
+use tracing::{info, warn};
+
+pub const BOOTSTRAP_DECISION_FILENAME: &str = "BOOTSTRAP_DECISION.json";
+/// Number of bytes downloaded from a bucket to measure its throughput
+const PROBE_SIZE: usize = 4 * 1024 * 1024;
+
+/// What peers report about the network, used to estimate how long catching up takes.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PeerSyncEstimate {
+    /// Current epoch of the network
+    pub current_epoch: u32,
+    /// Time needed to sync one epoch worth of checkpoints from peers
+    pub per_epoch_sync_duration_ms: u64,
+}
+
+impl PeerSyncEstimate {
+    fn catch_up_duration(&self, from_epoch: u32) -> Duration {
+        let epochs = self.current_epoch.saturating_sub(from_epoch) as u64;
+        Duration::from_millis(epochs * self.per_epoch_sync_duration_ms)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapSource {
+    /// Restore the db checkpoint of `epoch` from `bucket`, then catch up from peers
+    DbCheckpoint { bucket: String, epoch: u32 },
+    /// State sync from genesis from peers
+    StateSync,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BootstrapCandidate {
+    pub source: BootstrapSource,
+    pub size_bytes: usize,
+    /// Measured download throughput of the bucket, in bytes per second
+    pub throughput_bps: Option<u64>,
+    pub estimated_duration_ms: u64,
+    /// Why the candidate can't be used, if it is unsafe
+    pub rejected: Option<String>,
+}
+
+/// Decision of the planner
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BootstrapPlan {
+    pub decided_at_ms: u64,
+    pub chosen: BootstrapSource,
+    pub estimated_duration_ms: u64,
+    pub candidates: Vec<BootstrapCandidate>,
+}
+
+impl BootstrapPlan {
+    /// Records the decision in `dir` for operators
+    pub fn write_to(&self, dir: &std::path::Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(
+            dir.join(BOOTSTRAP_DECISION_FILENAME),
+            serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+pub struct BootstrapPlanner {
+    buckets: Vec<ObjectStoreConfig>,
+    peer_sync: Option<PeerSyncEstimate>,
+    /// Db checkpoints older than this many epochs behind the network are considered unsafe,
+    /// since peers may have pruned the history needed to catch up from them
+    max_epoch_lag: u32,
+    download_concurrency: NonZeroUsize,
+    progress: RestoreProgress,
+    /// Chain of the node, db checkpoints labeled with another chain are rejected
+    chain_identifier: Option<ChainIdentifier>,
+    staging_area: Option<StagingArea>,
+    /// Key of encrypted db checkpoints, those encrypted with another key are rejected
+    encryption_key: Option<EncryptionKey>,
+    bandwidth_limiter: BandwidthLimiter,
+    /// Whether the restored db is synced to disk, see `DBCheckpointRestorer::with_fsync`
+    fsync: bool,
+}
+
+impl BootstrapPlanner {
+    pub fn new(
+        buckets: Vec<ObjectStoreConfig>,
+        peer_sync: Option<PeerSyncEstimate>,
+        max_epoch_lag: u32,
+        download_concurrency: NonZeroUsize,
+    ) -> Self {
+        BootstrapPlanner {
+            buckets,
+            peer_sync,
+            max_epoch_lag,
+            download_concurrency,
+            progress: RestoreProgress::default(),
+            chain_identifier: None,
+            staging_area: None,
+            encryption_key: None,
+            bandwidth_limiter: BandwidthLimiter::default(),
+            fsync: true,
+        }
+    }
+    /// Report the progress of the db checkpoint download through `progress`, so that a
+    /// bootstrapping node doesn't look hung
+    pub fn with_progress(mut self, progress: RestoreProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+    /// Reject db checkpoints which can't be served by a node of the chain `chain_identifier`
+    pub fn with_chain_identifier(mut self, chain_identifier: ChainIdentifier) -> Self {
+        self.chain_identifier = Some(chain_identifier);
+        self
+    }
+    /// Restore db checkpoints through `staging_area`, so that an interrupted bootstrap doesn't
+    /// leave a partial db in place
+    pub fn with_staging_area(mut self, staging_area: StagingArea) -> Self {
+        self.staging_area = Some(staging_area);
+        self
+    }
+    /// Decrypt db checkpoints encrypted with `encryption_key` when restoring them
+    pub fn with_encryption_key(mut self, encryption_key: EncryptionKey) -> Self {
+        self.encryption_key = Some(encryption_key);
+        self
+    }
+    /// Limit the bandwidth of the db checkpoint download with `bandwidth_limiter`, download
+    /// times are estimated within the limit
+    pub fn with_bandwidth_limiter(mut self, bandwidth_limiter: BandwidthLimiter) -> Self {
+        self.bandwidth_limiter = bandwidth_limiter;
+        self
+    }
+    /// Whether to sync the restored db checkpoint to disk before the bootstrap completes, on by
+    /// default
+    pub fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+    /// Evaluates every source and picks the fastest safe one
+    pub async fn plan(&self) -> Result<BootstrapPlan> {
+        let mut candidates = vec![];
+        for config in self.buckets.iter() {
+            let store = config.make()?;
+            let bucket = store.to_string();
+            match self.evaluate_bucket(store).await {
+                Ok(candidate) => candidates.push(candidate),
+                Err(err) => warn!("Skipping bucket {bucket} for bootstrap: {:?}", err),
+            }
+        }
+        if let Some(peer_sync) = &self.peer_sync {
+            candidates.push(BootstrapCandidate {
+                source: BootstrapSource::StateSync,
+                size_bytes: 0,
+                throughput_bps: None,
+                estimated_duration_ms: peer_sync.catch_up_duration(0).as_millis() as u64,
+                rejected: None,
+            });
+        }
+        let best = candidates
+            .iter()
+            .filter(|c| c.rejected.is_none())
+            .min_by_key(|c| c.estimated_duration_ms)
+            .ok_or_else(|| anyhow!("No safe bootstrap source available: {:?}", candidates))?;
+        let plan = BootstrapPlan {
+            decided_at_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+            chosen: best.source.clone(),
+            estimated_duration_ms: best.estimated_duration_ms,
+            candidates: candidates.clone(),
+        };
+        info!(
+            "Selected bootstrap source: {:?}, estimated duration: {:?}",
+            plan.chosen,
+            Duration::from_millis(plan.estimated_duration_ms)
+        );
+        Ok(plan)
+    }
+    /// Executes `plan`, restoring the chosen db checkpoint into `db_path` if any, and records
+    /// the decision there
+    pub async fn execute(&self, plan: &BootstrapPlan, db_path: &std::path::Path) -> Result<()> {
+        if let BootstrapSource::DbCheckpoint { epoch, .. } = &plan.chosen {
+            // Every bucket serves as a replica so that corrupted files get repaired
+            let mut restorer = DBCheckpointRestorer::new(&self.buckets, self.download_concurrency)?
+                .with_progress(self.progress.clone())
+                .with_bandwidth_limiter(self.bandwidth_limiter.clone())
+                .with_fsync(self.fsync);
+            if let Some(chain_identifier) = self.chain_identifier {
+                restorer = restorer.with_chain_identifier(chain_identifier);
+            }
+            if let Some(staging_area) = &self.staging_area {
+                restorer = restorer.with_staging_area(staging_area.clone());
+            }
+            if let Some(encryption_key) = &self.encryption_key {
+                restorer = restorer.with_encryption_key(encryption_key.clone());
+            }
+            let report = restorer.restore_epoch(*epoch, db_path).await?;
+            info!(
+                "Restored db checkpoint for epoch: {epoch}, files: {}, repaired: {}",
+                report.served_by.len(),
+                report.corrupted.len()
+            );
+        }
+        plan.write_to(db_path)
+    }
+    async fn evaluate_bucket(&self, store: Arc<DynObjectStore>) -> Result<BootstrapCandidate> {
+        let bucket = store.to_string();
+        let (epoch, manifest) = latest_complete_epoch(store.clone())
+            .await?
+            .ok_or_else(|| anyhow!("No complete db checkpoint"))?;
+        let mut throughput_bps = measure_throughput(&manifest, store).await?;
+        if let Some(limit) = self.bandwidth_limiter.limit() {
+            throughput_bps = throughput_bps.min(limit);
+        }
+        let size_bytes = manifest.total_size();
+        let download = Duration::from_secs_f64(size_bytes as f64 / throughput_bps.max(1) as f64);
+        let mut estimated = download;
+        let mut rejected = None;
+        if let Some(peer_sync) = &self.peer_sync {
+            estimated += peer_sync.catch_up_duration(epoch);
+            let lag = peer_sync.current_epoch.saturating_sub(epoch);
+            if lag > self.max_epoch_lag {
+                rejected = Some(format!(
+                    "Epoch {epoch} is {lag} epochs behind the network, more than {}",
+                    self.max_epoch_lag
+                ));
+            }
+        }
+        if let Some(key_id) = &manifest.encryption_key_id {
+            if self.encryption_key.as_ref().map(EncryptionKey::key_id) != Some(key_id.as_str()) {
+                rejected = Some(format!(
+                    "Encrypted with key {key_id}, which the node doesn't hold"
+                ));
+            }
+        }
+        if let (Some(chain_identifier), Some(labels)) = (&self.chain_identifier, &manifest.labels) {
+            // Takes precedence, a db checkpoint of another chain is never usable
+            if let Err(err) = labels.verify(chain_identifier) {
+                rejected = Some(err.to_string());
+            }
+        }
+        Ok(BootstrapCandidate {
+            source: BootstrapSource::DbCheckpoint { bucket, epoch },
+            size_bytes,
+            throughput_bps: Some(throughput_bps),
+            estimated_duration_ms: estimated.as_millis() as u64,
+            rejected,
+        })
+    }
+}
+
+/// Latest epoch published with a readable manifest in `store`
+async fn latest_complete_epoch(store: Arc<DynObjectStore>) -> Result<Option<(u32, EpochManifest)>> {
+    let entries = store.list_with_delimiter(None).await?;
+    let mut epochs: Vec<u32> = entries
+        .common_prefixes
+        .iter()
+        .filter_map(|entry| entry.filename()?.strip_prefix("epoch_")?.parse().ok())
+        .collect();
+    epochs.sort_unstable();
+    for epoch in epochs.into_iter().rev() {
+        let epoch_dir = Path::from(format!("epoch_{}", epoch));
+        match read_published_manifest(&epoch_dir, store.clone()).await {
+            Ok(Some(manifest)) => return Ok(Some((epoch, manifest))),
+            Ok(None) => {}
+            Err(err) => warn!("Skipping {epoch_dir} without readable manifest: {:?}", err),
+        }
+    }
+    Ok(None)
+}
+
+/// Downloads the beginning of the largest file of the epoch and returns the observed
+/// throughput in bytes per second
+async fn measure_throughput(manifest: &EpochManifest, store: Arc<DynObjectStore>) -> Result<u64> {
+    let file = manifest
+        .files
+        .iter()
+        .max_by_key(|f| f.size)
+        .filter(|f| f.size > 0)
+        .context("No file to measure throughput with")?;
+    let (path, size) = match file.chunks.first() {
+        Some(chunk) => (chunk_path(&chunk.sha3_digest), chunk.size),
+        None => (
+            Path::from(file.remote_path.as_str()),
+            file.compressed_size.unwrap_or(file.size),
+        ),
+    };
+    let start = Instant::now();
+    let bytes = store
+        .get_range(&path, 0..std::cmp::min(size, PROBE_SIZE))
+        .await?;
+    let elapsed = start.elapsed().as_secs_f64().max(0.001);
+    Ok((bytes.len() as f64 / elapsed) as u64)
+}
@@ -0,0 +1,404 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Consolidation of old uploaded epochs into range bundles for long-term archival. Every epoch
+//! uploads thousands of files, which years of history turn into millions of objects to pay for
+//! and to list. A bundle packs the objects of each epoch of a range into a single object, and
+//! lists the position of every file of the range in one combined manifest:
+//!
+//! ```text
+//! bundles/
+//!  └─ epochs_0_99/
+//!      ├─ epoch_0.pack
+//!      ├─ ...
+//!      ├─ epoch_99.pack
+//!      └─ BUNDLE_MANIFEST
+//! ```
+//!
+//! Objects are packed as stored, compressed or encrypted, and the manifest is written last, so
+//! a bundle without one is incomplete and ignored. The `epoch_N` directories of a range are
+//! only deleted once its manifest is written. Recent epochs stay granular, and a bundled epoch
+//! is copied back into its directory to be restored.
+
+use crate::db_checkpoint_handler::SUCCESS_MARKER;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use sui_storage::object_store::util::{delete_recursively, list_epoch_dirs, put};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+/// Prefix of the range bundles in the remote store
+pub const BUNDLES_DIR: &str = "bundles";
+pub const BUNDLE_MANIFEST_FILENAME: &str = "BUNDLE_MANIFEST";
+/// Concurrent deletes of the files of bundled epochs
+const DELETE_CONCURRENCY: usize = 20;
+
+/// Position of a file of an epoch in the pack of the epoch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BundledFile {
+    /// Path of the object relative to the `epoch_N` directory, as stored
+    pub path: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BundledEpoch {
+    /// Name of the pack of the epoch in the bundle directory
+    pub pack: String,
+    pub files: Vec<BundledFile>,
+}
+
+impl BundledEpoch {
+    pub fn size(&self) -> usize {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+/// Combined manifest of the epochs of a bundle. Epochs of the range missing from the bucket
+/// when it was bundled, e.g. deleted by retention, are missing from it too.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BundleManifest {
+    pub first_epoch: u32,
+    pub last_epoch: u32,
+    pub epochs: BTreeMap<u32, BundledEpoch>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BundleReport {
+    /// Bundles written, by their first epoch, with the epochs moved into them
+    pub bundled: BTreeMap<u32, Vec<u32>>,
+    /// Ranges left granular, by their first epoch, with the reason
+    pub skipped: BTreeMap<u32, String>,
+    /// Whether nothing was written to the remote store
+    pub dry_run: bool,
+}
+
+pub fn bundle_dir(first_epoch: u32, last_epoch: u32) -> Path {
+    Path::from(BUNDLES_DIR).child(format!("epochs_{first_epoch}_{last_epoch}"))
+}
+
+/// Moves the complete epochs of `store` older than its latest `keep_recent_epochs` ones into
+/// bundles of `epochs_per_bundle` epochs. A range is only bundled once all of it is old enough,
+/// and left granular when any of its epochs is incomplete. Only computes the report when
+/// `dry_run` is set.
+pub async fn bundle_old_epochs(
+    store: Arc<DynObjectStore>,
+    epochs_per_bundle: u32,
+    keep_recent_epochs: u32,
+    dry_run: bool,
+) -> Result<BundleReport> {
+    if epochs_per_bundle == 0 {
+        return Err(anyhow!("Bundles must hold at least one epoch"));
+    }
+    let mut report = BundleReport {
+        dry_run,
+        ..Default::default()
+    };
+    let epoch_dirs = list_epoch_dirs(store.clone(), None).await?;
+    let Some(latest_epoch) = epoch_dirs.keys().next_back().cloned() else {
+        return Ok(report);
+    };
+    // The latest epoch always stays granular, uploads carry on from it
+    let cutoff = latest_epoch.saturating_sub(keep_recent_epochs.saturating_sub(1));
+    let mut ranges: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for epoch in epoch_dirs.keys() {
+        let first_epoch = epoch - epoch % epochs_per_bundle;
+        let last_epoch = first_epoch.saturating_add(epochs_per_bundle - 1);
+        if last_epoch < cutoff {
+            ranges.entry(first_epoch).or_default().push(*epoch);
+        }
+    }
+    for (first_epoch, epochs) in ranges {
+        let last_epoch = first_epoch + (epochs_per_bundle - 1);
+        let dir = bundle_dir(first_epoch, last_epoch);
+        // Left behind by a run interrupted after writing the manifest
+        if let Some(manifest) = read_bundle_manifest(&dir, store.clone()).await? {
+            let bundled: Vec<u32> = epochs
+                .into_iter()
+                .filter(|epoch| manifest.epochs.contains_key(epoch))
+                .collect();
+            if !dry_run {
+                delete_epoch_dirs(&bundled, &epoch_dirs, store.clone()).await?;
+            }
+            if !bundled.is_empty() {
+                report.bundled.insert(first_epoch, bundled);
+            }
+            continue;
+        }
+        let mut incomplete = vec![];
+        for epoch in epochs.iter() {
+            match store.head(&epoch_dirs[epoch].child(SUCCESS_MARKER)).await {
+                Ok(_) => {}
+                Err(object_store::Error::NotFound { .. }) => incomplete.push(*epoch),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if !incomplete.is_empty() {
+            report.skipped.insert(
+                first_epoch,
+                format!("Epochs {incomplete:?} have no {SUCCESS_MARKER} marker"),
+            );
+            continue;
+        }
+        if !dry_run {
+            let mut manifest = BundleManifest {
+                first_epoch,
+                last_epoch,
+                epochs: BTreeMap::new(),
+            };
+            for epoch in epochs.iter() {
+                info!("Packing epoch: {epoch} into bundle: {dir}");
+                let bundled_epoch =
+                    write_pack(&epoch_dirs[epoch], &dir, *epoch, store.clone()).await?;
+                manifest.epochs.insert(*epoch, bundled_epoch);
+            }
+            put(
+                &dir.child(BUNDLE_MANIFEST_FILENAME),
+                Bytes::from(serde_json::to_vec(&manifest)?),
+                store.clone(),
+            )
+            .await?;
+            delete_epoch_dirs(&epochs, &epoch_dirs, store.clone()).await?;
+        }
+        report.bundled.insert(first_epoch, epochs);
+    }
+    Ok(report)
+}
+
+/// Manifest of the bundle in `dir`, `None` if the bundle is missing or incomplete
+pub async fn read_bundle_manifest(
+    dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<Option<BundleManifest>> {
+    // Without the retries of `util::get`, which take a missing manifest for a transient error
+    match store.get(&dir.child(BUNDLE_MANIFEST_FILENAME)).await {
+        Ok(result) => Ok(Some(serde_json::from_slice(&result.bytes().await?)?)),
+        Err(object_store::Error::NotFound { .. }) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Manifests of the complete bundles of `store`
+pub async fn list_bundles(store: Arc<DynObjectStore>) -> Result<Vec<BundleManifest>> {
+    let entries = store
+        .list_with_delimiter(Some(&Path::from(BUNDLES_DIR)))
+        .await?;
+    let mut manifests = vec![];
+    for dir in entries.common_prefixes {
+        if let Some(manifest) = read_bundle_manifest(&dir, store.clone()).await? {
+            manifests.push(manifest);
+        }
+    }
+    Ok(manifests)
+}
+
+/// Epochs of `store` held by its bundles
+pub async fn bundled_epochs(store: Arc<DynObjectStore>) -> Result<BTreeSet<u32>> {
+    Ok(list_bundles(store)
+        .await?
+        .into_iter()
+        .flat_map(|manifest| manifest.epochs.into_keys())
+        .collect())
+}
+
+/// Copies `epoch` out of its bundle back into its `epoch_N` directory, from which it is
+/// restored as any other epoch. The bundle is left untouched. Returns the number of files.
+pub async fn unbundle_epoch(store: Arc<DynObjectStore>, epoch: u32) -> Result<usize> {
+    let (dir, bundled_epoch) = list_bundles(store.clone())
+        .await?
+        .into_iter()
+        .find_map(|manifest| {
+            let dir = bundle_dir(manifest.first_epoch, manifest.last_epoch);
+            manifest
+                .epochs
+                .get(&epoch)
+                .cloned()
+                .map(|bundled_epoch| (dir, bundled_epoch))
+        })
+        .ok_or_else(|| anyhow!("Epoch {epoch} is not bundled"))?;
+    let pack = dir.child(bundled_epoch.pack.as_str());
+    let epoch_dir = Path::from(format!("epoch_{epoch}"));
+    // The success marker goes last, so that an interrupted copy isn't taken for a complete
+    // epoch
+    let (markers, files): (Vec<&BundledFile>, Vec<&BundledFile>) = bundled_epoch
+        .files
+        .iter()
+        .partition(|file| file.path == SUCCESS_MARKER);
+    for file in files.into_iter().chain(markers) {
+        let bytes = store
+            .get_range(&pack, file.offset..file.offset + file.size)
+            .await?;
+        put(
+            &remote_file_path(&epoch_dir, &file.path),
+            bytes,
+            store.clone(),
+        )
+        .await?;
+    }
+    info!(
+        "Unbundled {} files of epoch: {epoch} from {dir}",
+        bundled_epoch.files.len()
+    );
+    Ok(bundled_epoch.files.len())
+}
+
+fn remote_file_path(epoch_dir: &Path, path: &str) -> Path {
+    Path::from(format!("{epoch_dir}/{path}"))
+}
+
+/// Streams the objects of `epoch_dir` one after the other into the pack of `epoch` in `dir`
+async fn write_pack(
+    epoch_dir: &Path,
+    dir: &Path,
+    epoch: u32,
+    store: Arc<DynObjectStore>,
+) -> Result<BundledEpoch> {
+    let mut locations = vec![];
+    let mut listing = store.list(Some(epoch_dir)).await?;
+    while let Some(object) = listing.next().await {
+        locations.push(object?.location);
+    }
+    locations.sort();
+    let pack_name = format!("epoch_{epoch}.pack");
+    let pack = dir.child(pack_name.as_str());
+    let (multipart_id, mut writer) = store.put_multipart(&pack).await?;
+    let written = async {
+        let mut files = vec![];
+        let mut offset = 0;
+        for location in locations {
+            let path = location
+                .as_ref()
+                .strip_prefix(&format!("{epoch_dir}/"))
+                .ok_or_else(|| anyhow!("{location} is not under {epoch_dir}"))?
+                .to_string();
+            let mut stream = store.get(&location).await?.into_stream();
+            let mut size = 0;
+            while let Some(bytes) = stream.next().await {
+                let bytes = bytes?;
+                writer.write_all(&bytes).await?;
+                size += bytes.len();
+            }
+            files.push(BundledFile { path, offset, size });
+            offset += size;
+        }
+        writer.shutdown().await?;
+        Ok::<_, anyhow::Error>(files)
+    }
+    .await;
+    let files = match written {
+        Ok(files) => files,
+        Err(err) => {
+            let _ = store.abort_multipart(&pack, &multipart_id).await;
+            return Err(err);
+        }
+    };
+    let bundled_epoch = BundledEpoch {
+        pack: pack_name,
+        files,
+    };
+    let stored_size = store.head(&pack).await?.size;
+    if stored_size != bundled_epoch.size() {
+        return Err(anyhow!(
+            "Pack {pack} holds {stored_size} bytes, expected {}",
+            bundled_epoch.size()
+        ));
+    }
+    Ok(bundled_epoch)
+}
+
+async fn delete_epoch_dirs(
+    epochs: &[u32],
+    epoch_dirs: &BTreeMap<u32, Path>,
+    store: Arc<DynObjectStore>,
+) -> Result<()> {
+    for epoch in epochs {
+        if let Some(epoch_dir) = epoch_dirs.get(epoch) {
+            info!("Deleting bundled epoch dir: {epoch_dir} for epoch: {epoch}");
+            delete_recursively(
+                epoch_dir,
+                store.clone(),
+                NonZeroUsize::new(DELETE_CONCURRENCY).unwrap(),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bundle_dir, bundle_old_epochs, bundled_epochs, unbundle_epoch};
+    use crate::db_checkpoint_handler::SUCCESS_MARKER;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_bundle_old_epochs() -> anyhow::Result<()> {
+        let remote_dir = TempDir::new()?;
+        for epoch in 0..6 {
+            let epoch_dir = remote_dir.path().join(format!("epoch_{epoch}"));
+            fs::create_dir_all(epoch_dir.join("store"))?;
+            fs::write(epoch_dir.join("file1"), format!("Lorem ipsum {epoch}"))?;
+            fs::write(epoch_dir.join("store").join("file2"), b"dolor sit amet")?;
+            // Epoch 3 is incomplete
+            if epoch != 3 {
+                fs::write(epoch_dir.join(SUCCESS_MARKER), b"success")?;
+            }
+        }
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_dir.path().to_path_buf()),
+            ..Default::default()
+        }
+        .make()?;
+
+        let report = bundle_old_epochs(store.clone(), 2, 2, true).await?;
+        assert_eq!(report.bundled.into_keys().collect::<Vec<_>>(), vec![0]);
+        assert!(report.skipped.contains_key(&2));
+        assert!(remote_dir.path().join("epoch_0").exists());
+
+        let report = bundle_old_epochs(store.clone(), 2, 2, false).await?;
+        assert_eq!(report.bundled.get(&0), Some(&vec![0, 1]));
+        // Epochs 4 and 5 are recent
+        assert_eq!(report.bundled.len(), 1);
+        assert!(!remote_dir.path().join("epoch_0").exists());
+        assert!(!remote_dir.path().join("epoch_1").exists());
+        assert!(remote_dir.path().join("epoch_2").exists());
+        assert_eq!(bundled_epochs(store.clone()).await?, BTreeSet::from([0, 1]));
+        // Already bundled
+        assert!(bundle_old_epochs(store.clone(), 2, 2, false)
+            .await?
+            .bundled
+            .is_empty());
+
+        assert_eq!(unbundle_epoch(store.clone(), 1).await?, 3);
+        let epoch_dir = remote_dir.path().join("epoch_1");
+        assert_eq!(fs::read(epoch_dir.join("file1"))?, b"Lorem ipsum 1");
+        assert_eq!(
+            fs::read(epoch_dir.join("store").join("file2"))?,
+            b"dolor sit amet"
+        );
+        assert!(epoch_dir.join(SUCCESS_MARKER).exists());
+        assert!(unbundle_epoch(store.clone(), 2).await.is_err());
+        assert!(remote_dir
+            .path()
+            .join(bundle_dir(0, 1).to_string())
+            .join("epoch_0.pack")
+            .exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Content defined chunking (CDC) for db checkpoint uploads. Files are split at boundaries
+//! derived from their contents with a gear rolling hash, so an SST file which RocksDB rewrites
+//! during compaction still shares most of its chunks with the previous version. Chunks are
+//! stored once, keyed by their digest, under the shared `chunks/` prefix of the remote store,
+//! and the epoch manifest lists the chunks making up every file.
+
+use crate::db_checkpoint_handler::adaptive_concurrency::AdaptiveConcurrency;
+use crate::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use crate::db_checkpoint_handler::digest_pool::DigestPool;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::{DynObjectStore, Error};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+pub const CHUNKS_DIR: &str = "chunks";
+/// Chunks are never cut before this many bytes, unless the file ends
+pub const MIN_CHUNK_SIZE: usize = 256 * 1024;
+/// Target average chunk size, must be a power of two
+pub const AVG_CHUNK_SIZE: usize = 1024 * 1024;
+/// Chunks are always cut at this many bytes
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// A chunk of a file stored in the shared chunk area.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct ChunkEntry {
+    /// Hex encoded sha3 digest of the chunk, which is also its key in the chunk area
+    pub sha3_digest: String,
+    pub size: usize,
+}
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64 with a fixed seed, the table must never change or chunk boundaries
+    // (and thus dedup against previously uploaded chunks) would shift
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x5375_6942_6163_6b75;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+/// Returns the chunk boundaries of `data`
+pub fn chunk_boundaries(data: &[u8]) -> Vec<Range<usize>> {
+    let mask = (AVG_CHUNK_SIZE - 1) as u64;
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < data.len() {
+        let end = std::cmp::min(start + MAX_CHUNK_SIZE, data.len());
+        let mut cut = end;
+        let mut hash: u64 = 0;
+        let mut i = start + MIN_CHUNK_SIZE;
+        while i < end {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & mask == 0 {
+                cut = i + 1;
+                break;
+            }
+            i += 1;
+        }
+        chunks.push(start..cut);
+        start = cut;
+    }
+    chunks
+}
+
+/// Splits `data` into its chunks, with their digests
+pub fn split_chunks(data: &Bytes) -> Vec<(ChunkEntry, Bytes)> {
+    chunk_boundaries(data)
+        .into_iter()
+        .map(|range| {
+            let bytes = data.slice(range);
+            let entry = ChunkEntry {
+                sha3_digest: Hex::encode(Sha3_256::digest(&bytes).digest),
+                size: bytes.len(),
+            };
+            (entry, bytes)
+        })
+        .collect()
+}
+
+pub fn chunk_path(sha3_digest: &str) -> Path {
+    Path::from(format!(
+        "{}/{}/{}",
+        CHUNKS_DIR,
+        &sha3_digest[..2],
+        sha3_digest
+    ))
+}
+
+/// Uploads the chunks of `data` which are not already present in `store`. Returns the list of
+/// chunks making up `data` and the number of bytes actually uploaded.
+pub async fn upload_chunks(
+    data: Bytes,
+    store: Arc<DynObjectStore>,
+    limiter: &AdaptiveConcurrency,
+    pool: &DigestPool,
+) -> Result<(Vec<ChunkEntry>, usize)> {
+    let chunks = pool.run(move || split_chunks(&data)).await?;
+    let results: Vec<Result<usize>> = futures::stream::iter(chunks.iter())
+        .map(|(entry, bytes)| {
+            let store = store.clone();
+            async move {
+                let path = chunk_path(&entry.sha3_digest);
+                match store.head(&path).await {
+                    Ok(meta) if meta.size == entry.size => return Ok(0),
+                    Ok(_) | Err(Error::NotFound { .. }) => {}
+                    Err(err) => return Err(err.into()),
+                }
+                limiter.put(&path, bytes.clone(), store).await?;
+                Ok(entry.size)
+            }
+        })
+        .buffer_unordered(limiter.max().get())
+        .collect()
+        .await;
+    let mut uploaded_bytes = 0;
+    for result in results {
+        uploaded_bytes += result?;
+    }
+    Ok((
+        chunks.into_iter().map(|(entry, _)| entry).collect(),
+        uploaded_bytes,
+    ))
+}
+
+/// Checks that every chunk is present in `store` with the expected size
+pub async fn verify_chunks(chunks: &[ChunkEntry], store: Arc<DynObjectStore>) -> Result<()> {
+    for chunk in chunks {
+        let meta = store.head(&chunk_path(&chunk.sha3_digest)).await?;
+        if meta.size != chunk.size {
+            return Err(anyhow!(
+                "Size mismatch for chunk {}, expected: {}, remote: {}",
+                chunk.sha3_digest,
+                chunk.size,
+                meta.size
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Number of `chunks` missing from `store`, or present with another size, checking up to
+/// `concurrency` of them at once
+pub async fn count_missing_chunks(
+    chunks: &[ChunkEntry],
+    store: Arc<DynObjectStore>,
+    concurrency: usize,
+) -> Result<usize> {
+    let results: Vec<Result<bool>> = futures::stream::iter(chunks)
+        .map(|chunk| {
+            let store = store.clone();
+            async move {
+                match store.head(&chunk_path(&chunk.sha3_digest)).await {
+                    Ok(meta) => Ok(meta.size != chunk.size),
+                    Err(Error::NotFound { .. }) => Ok(true),
+                    Err(err) => Err(err.into()),
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    let mut missing = 0;
+    for result in results {
+        missing += result? as usize;
+    }
+    Ok(missing)
+}
+
+/// Downloads a file from its chunks and reassembles it in `writer` one chunk at a time,
+/// verifying every chunk digest. The bandwidth of each chunk is drawn from `bandwidth_limiter`
+/// before it is downloaded. Returns the time the request of the first chunk took to respond.
+pub async fn download_chunks<W: AsyncWrite + Unpin>(
+    chunks: &[ChunkEntry],
+    store: Arc<DynObjectStore>,
+    bandwidth_limiter: &BandwidthLimiter,
+    writer: &mut W,
+) -> Result<Duration> {
+    let mut latency = None;
+    for chunk in chunks {
+        bandwidth_limiter.consume(chunk.size).await;
+        let start = Instant::now();
+        let result = store.get(&chunk_path(&chunk.sha3_digest)).await?;
+        latency.get_or_insert(start.elapsed());
+        let bytes = result.bytes().await?;
+        let sha3_digest = Hex::encode(Sha3_256::digest(&bytes).digest);
+        if sha3_digest != chunk.sha3_digest {
+            return Err(anyhow!(
+                "Checksum mismatch for chunk {}, actual: {}",
+                chunk.sha3_digest,
+                sha3_digest
+            ));
+        }
+        writer.write_all(&bytes).await?;
+    }
+    Ok(latency.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunk_boundaries, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE};
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_chunk_boundaries_are_content_defined() {
+        let mut rng = rand::rngs::StdRng::from_seed([7; 32]);
+        let data: Vec<u8> = (0..8 * MAX_CHUNK_SIZE).map(|_| rng.gen()).collect();
+        let chunks = chunk_boundaries(&data);
+        assert_eq!(chunks.first().unwrap().start, 0);
+        assert_eq!(chunks.last().unwrap().end, data.len());
+        for window in chunks.windows(2) {
+            assert_eq!(window[0].end, window[1].start);
+            assert!(window[0].len() >= MIN_CHUNK_SIZE);
+            assert!(window[0].len() <= MAX_CHUNK_SIZE);
+        }
+        // Prepending data only changes the chunks around the insertion point
+        let mut shifted = vec![1u8; 1000];
+        shifted.extend_from_slice(&data);
+        let shifted_chunks = chunk_boundaries(&shifted);
+        let original: Vec<&[u8]> = chunks.iter().map(|r| &data[r.clone()]).collect();
+        let shared = shifted_chunks
+            .iter()
+            .filter(|r| original.contains(&&shifted[(*r).clone()]))
+            .count();
+        assert!(shared >= chunks.len() - 2);
+    }
+}
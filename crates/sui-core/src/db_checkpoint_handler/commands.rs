@@ -0,0 +1,167 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Commands sent to a running handler between the passes of its upload interval, e.g. by the
+//! admin server of the node when an operator doesn't want to wait for the next pass.
+
+use crate::db_checkpoint_handler::status::{BackupStatus, BackupStatusHandle};
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+/// Commands waiting for the handler. An upload requested while a command is pending is merged
+/// into it, or left to the next interval, other commands are refused.
+pub const BACKUP_COMMANDS_CAPACITY: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupCommand {
+    /// Scan the remote store for missing epochs and upload them right away, or verify them in
+    /// verify-only mode
+    UploadNow,
+    /// Delete the db checkpoint of the epoch from the remote store, without uploading it again
+    DeleteRemoteEpoch(u32),
+}
+
+/// Sends commands to a handler, see `DBCheckpointHandler::commander`
+#[derive(Clone)]
+pub struct BackupCommander {
+    sender: mpsc::Sender<BackupCommand>,
+    status: BackupStatusHandle,
+    /// Commanders of the replicas of the output store, paused and resumed along with it
+    replicas: Vec<BackupCommander>,
+}
+
+impl BackupCommander {
+    pub fn new(sender: mpsc::Sender<BackupCommand>, status: BackupStatusHandle) -> Self {
+        BackupCommander {
+            sender,
+            status,
+            replicas: vec![],
+        }
+    }
+    pub fn with_replicas(mut self, replicas: Vec<BackupCommander>) -> Self {
+        self.replicas = replicas;
+        self
+    }
+    /// Starts an upload pass as soon as the handler is done with the current one. Does nothing
+    /// more when a pass was already requested and hasn't started yet.
+    pub fn upload_now(&self) -> Result<()> {
+        match self.sender.try_send(BackupCommand::UploadNow) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Closed(_)) => Err(anyhow!("Db checkpoint handler is not running")),
+        }
+    }
+    /// Stops uploads until `resume`, e.g. during maintenance of the network to the remote
+    /// store. The upload of the current epoch completes, the following ones wait. Local db
+    /// checkpoints are still garbage collected once uploaded.
+    pub fn pause(&self) {
+        self.status.set_paused(true);
+        for replica in self.replicas.iter() {
+            replica.pause();
+        }
+    }
+    /// Resumes paused uploads, starting with a pass for the epochs missed meanwhile
+    pub fn resume(&self) -> Result<()> {
+        self.status.set_paused(false);
+        for replica in self.replicas.iter() {
+            replica.status.set_paused(false);
+        }
+        // Passes of the replicas are started along with the one of the output store
+        self.upload_now()
+    }
+    /// Deletes the db checkpoint of `epoch` from the remote store once the handler is done with
+    /// the current pass, the replicas keep theirs. Fails rather than waiting when another
+    /// command is pending.
+    pub fn delete_remote_epoch(&self, epoch: u32) -> Result<()> {
+        match self
+            .sender
+            .try_send(BackupCommand::DeleteRemoteEpoch(epoch))
+        {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(anyhow!(
+                "Db checkpoint handler is busy with another command, retry later"
+            )),
+            Err(TrySendError::Closed(_)) => Err(anyhow!("Db checkpoint handler is not running")),
+        }
+    }
+    /// Stops the garbage collection of uploaded local db checkpoints and the remote retention
+    /// until `resume_gc`, e.g. while an operator inspects them. Disk pressure relief still
+    /// deletes uploaded local db checkpoints.
+    pub fn pause_gc(&self) {
+        self.status.set_gc_paused(true);
+        for replica in self.replicas.iter() {
+            replica.pause_gc();
+        }
+    }
+    pub fn resume_gc(&self) {
+        self.status.set_gc_paused(false);
+        for replica in self.replicas.iter() {
+            replica.resume_gc();
+        }
+    }
+    pub fn status(&self) -> BackupStatus {
+        self.status.status()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackupCommand, BackupCommander, BACKUP_COMMANDS_CAPACITY};
+    use crate::db_checkpoint_handler::status::BackupStatusHandle;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_upload_now() {
+        let (sender, mut receiver) = mpsc::channel(BACKUP_COMMANDS_CAPACITY);
+        let commander = BackupCommander::new(sender, BackupStatusHandle::default());
+        commander.upload_now().unwrap();
+        // Merged into the pending pass
+        commander.clone().upload_now().unwrap();
+        assert_eq!(receiver.recv().await, Some(BackupCommand::UploadNow));
+        assert!(receiver.try_recv().is_err());
+
+        drop(receiver);
+        assert!(commander.upload_now().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let (sender, mut receiver) = mpsc::channel(BACKUP_COMMANDS_CAPACITY);
+        let (replica_sender, _replica_receiver) = mpsc::channel(BACKUP_COMMANDS_CAPACITY);
+        let replica = BackupCommander::new(replica_sender, BackupStatusHandle::default());
+        let commander = BackupCommander::new(sender, BackupStatusHandle::default())
+            .with_replicas(vec![replica.clone()]);
+        commander.pause();
+        assert!(commander.status().paused);
+        assert!(replica.status().paused);
+        assert!(receiver.try_recv().is_err());
+
+        commander.resume().unwrap();
+        assert!(!commander.status().paused);
+        assert!(!replica.status().paused);
+        // Catches up on the epochs missed while paused
+        assert_eq!(receiver.recv().await, Some(BackupCommand::UploadNow));
+
+        commander.pause_gc();
+        assert!(commander.status().gc_paused);
+        assert!(replica.status().gc_paused);
+        assert!(!commander.status().paused);
+        commander.resume_gc();
+        assert!(!commander.status().gc_paused);
+        assert!(!replica.status().gc_paused);
+    }
+
+    #[tokio::test]
+    async fn test_delete_remote_epoch() {
+        let (sender, mut receiver) = mpsc::channel(BACKUP_COMMANDS_CAPACITY);
+        let commander = BackupCommander::new(sender, BackupStatusHandle::default());
+        commander.delete_remote_epoch(3).unwrap();
+        // Not merged into the pending command
+        assert!(commander.delete_remote_epoch(4).is_err());
+        assert_eq!(
+            receiver.recv().await,
+            Some(BackupCommand::DeleteRemoteEpoch(3))
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
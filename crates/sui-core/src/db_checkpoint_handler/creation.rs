@@ -0,0 +1,153 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Estimate of whether a db checkpoint is done being created, so that a directory still being
+//! written is never uploaded. The node takes its db checkpoints in a temporary directory which
+//! is renamed into place once complete, but external snapshot tooling and copies into the
+//! checkpoint path may expose the directory while writing it. Such tooling marks the directory
+//! with a `CREATION_IN_PROGRESS` file until it's done. Without the marker, a directory whose
+//! files were modified within the settle time is assumed to still be written. Only the files of
+//! the db checkpoint count: the directory itself is modified whenever the handler writes its
+//! markers next to them.
+
+use crate::db_checkpoint_handler::expected::{is_excluded_file, ExpectedFiles};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Present at the top of a db checkpoint while it is being created
+pub const CREATION_IN_PROGRESS_MARKER: &str = "CREATION_IN_PROGRESS";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreationEstimate {
+    /// Whether the db checkpoint holds the creation in progress marker
+    pub marked_in_progress: bool,
+    /// Time since the latest modification of the db checkpoint
+    pub quiet_for: Duration,
+    /// Fraction of the bytes of the expected files present, if the db checkpoint lists them
+    pub completeness: Option<f64>,
+}
+
+impl CreationEstimate {
+    /// Estimates how far the creation of the db checkpoint at `dir` went, skipping the top
+    /// level files named in `excluded` and their per replica copies
+    pub fn of_dir(dir: &Path, excluded: &[&str]) -> Result<Self> {
+        let mut latest_modified = UNIX_EPOCH;
+        let mut sizes = BTreeMap::new();
+        add_dir(dir, "", excluded, &mut latest_modified, &mut sizes)?;
+        let completeness = ExpectedFiles::read(dir)?.map(|expected| {
+            let expected_bytes: u64 = expected.files.values().sum();
+            let present_bytes: u64 = expected
+                .files
+                .iter()
+                .map(|(path, size)| sizes.get(path).map_or(0, |actual| (*actual).min(*size)))
+                .sum();
+            if expected_bytes == 0 {
+                1.0
+            } else {
+                present_bytes as f64 / expected_bytes as f64
+            }
+        });
+        Ok(CreationEstimate {
+            marked_in_progress: dir.join(CREATION_IN_PROGRESS_MARKER).exists(),
+            // Modified in the future counts as just modified
+            quiet_for: SystemTime::now()
+                .duration_since(latest_modified)
+                .unwrap_or_default(),
+            completeness,
+        })
+    }
+    /// Whether the db checkpoint looks still written to, given the time for which its files
+    /// must be left unmodified once created
+    pub fn in_progress(&self, settle: Duration) -> bool {
+        self.marked_in_progress || self.quiet_for < settle
+    }
+}
+
+fn add_dir(
+    dir: &Path,
+    prefix: &str,
+    excluded: &[&str],
+    latest_modified: &mut SystemTime,
+    sizes: &mut BTreeMap<String, u64>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if prefix.is_empty() && is_excluded_file(excluded, &name) {
+            continue;
+        }
+        let path = format!("{prefix}{name}");
+        let metadata = entry.metadata()?;
+        *latest_modified = (*latest_modified).max(metadata.modified()?);
+        if metadata.is_dir() {
+            add_dir(
+                &entry.path(),
+                &format!("{path}/"),
+                excluded,
+                latest_modified,
+                sizes,
+            )?;
+        } else {
+            sizes.insert(path, metadata.len());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CreationEstimate, CREATION_IN_PROGRESS_MARKER};
+    use crate::db_checkpoint_handler::expected::{ExpectedFiles, EXPECTED_FILENAME};
+    use crate::db_checkpoint_handler::{REPLICA_SUFFIX, UPLOAD_COMPLETED_MARKER};
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_creation_estimate() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        fs::write(dir.path().join("file1"), b"Lorem ipsum")?;
+        fs::write(dir.path().join("file2"), b"Lorem ipsum")?;
+        ExpectedFiles::write(dir.path())?;
+        let estimate = CreationEstimate::of_dir(dir.path(), &[EXPECTED_FILENAME])?;
+        assert_eq!(estimate.completeness, Some(1.0));
+        assert!(!estimate.in_progress(Duration::ZERO));
+        // Just written
+        assert!(estimate.in_progress(Duration::from_secs(3600)));
+
+        fs::write(dir.path().join("file2"), b"Lorem")?;
+        fs::write(dir.path().join(CREATION_IN_PROGRESS_MARKER), b"")?;
+        let estimate = CreationEstimate::of_dir(dir.path(), &[EXPECTED_FILENAME])?;
+        assert_eq!(estimate.completeness, Some(16.0 / 22.0));
+        assert!(estimate.in_progress(Duration::ZERO));
+
+        fs::remove_file(dir.path().join(EXPECTED_FILENAME))?;
+        fs::remove_file(dir.path().join(CREATION_IN_PROGRESS_MARKER))?;
+        let estimate = CreationEstimate::of_dir(dir.path(), &[])?;
+        assert_eq!(estimate.completeness, None);
+        assert!(!estimate.in_progress(Duration::ZERO));
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_files_do_not_delay_creation() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        // Just written, like the directory holding them
+        fs::write(dir.path().join(UPLOAD_COMPLETED_MARKER), b"success")?;
+        fs::write(
+            dir.path()
+                .join(format!("{UPLOAD_COMPLETED_MARKER}{REPLICA_SUFFIX}1")),
+            b"success",
+        )?;
+        let estimate = CreationEstimate::of_dir(dir.path(), &[UPLOAD_COMPLETED_MARKER])?;
+        assert!(!estimate.in_progress(Duration::from_secs(3600)));
+
+        fs::write(dir.path().join("file1"), b"Lorem ipsum")?;
+        let estimate = CreationEstimate::of_dir(dir.path(), &[UPLOAD_COMPLETED_MARKER])?;
+        assert!(estimate.in_progress(Duration::from_secs(3600)));
+        Ok(())
+    }
+}
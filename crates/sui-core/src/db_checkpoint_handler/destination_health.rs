@@ -0,0 +1,108 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Health of the destinations holding copies of the db checkpoints, scored from the outcomes
+//! and latencies of the reads they served. Restores try the healthiest destination first and
+//! fail over to the next ones, so a replica which starts failing or slowing down in the middle
+//! of a restore stops being tried first for the remaining files.
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Weight of the latest read in the moving average of latencies
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DestinationHealth {
+    pub successes: u64,
+    /// Reads which failed or returned corrupted contents
+    pub failures: u64,
+    /// Exponential moving average of the latencies of successful reads, in seconds
+    pub latency_s: Option<f64>,
+}
+
+impl DestinationHealth {
+    /// Share of the reads which succeeded. Counts one extra success, so that a destination
+    /// without reads yet ranks above one which failed.
+    pub fn success_rate(&self) -> f64 {
+        (self.successes + 1) as f64 / (self.successes + self.failures + 1) as f64
+    }
+    /// Higher is healthier, the success rate discounted by the latency
+    pub fn score(&self) -> f64 {
+        self.success_rate() / (1.0 + self.latency_s.unwrap_or_default())
+    }
+    fn record_success(&mut self, latency: Duration) {
+        self.successes += 1;
+        let latency = latency.as_secs_f64();
+        self.latency_s = Some(match self.latency_s {
+            Some(average) => average + LATENCY_SMOOTHING * (latency - average),
+            None => latency,
+        });
+    }
+}
+
+/// Health of every destination, by index. Clones share the same scores.
+#[derive(Debug, Clone)]
+pub struct DestinationHealthTracker {
+    destinations: Arc<Mutex<Vec<DestinationHealth>>>,
+}
+
+impl DestinationHealthTracker {
+    pub fn new(num_destinations: usize) -> Self {
+        DestinationHealthTracker {
+            destinations: Arc::new(Mutex::new(vec![
+                DestinationHealth::default();
+                num_destinations
+            ])),
+        }
+    }
+    pub fn record_success(&self, index: usize, latency: Duration) {
+        self.destinations.lock()[index].record_success(latency);
+    }
+    pub fn record_failure(&self, index: usize) {
+        self.destinations.lock()[index].failures += 1;
+    }
+    pub fn health(&self, index: usize) -> DestinationHealth {
+        self.destinations.lock()[index].clone()
+    }
+    /// Sorts `items` by the health of their destination, healthiest first. Destinations with
+    /// the same score keep their order.
+    pub fn sort_by_health<T>(&self, items: &mut [T], index: impl Fn(&T) -> usize) {
+        let destinations = self.destinations.lock();
+        items.sort_by(|a, b| {
+            destinations[index(b)]
+                .score()
+                .total_cmp(&destinations[index(a)].score())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DestinationHealthTracker;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sort_by_health() {
+        let tracker = DestinationHealthTracker::new(3);
+        let mut destinations = vec![0, 1, 2];
+        // Nothing known yet
+        tracker.sort_by_health(&mut destinations, |index| *index);
+        assert_eq!(destinations, vec![0, 1, 2]);
+
+        tracker.record_success(0, Duration::from_millis(500));
+        tracker.record_success(1, Duration::from_millis(100));
+        tracker.record_success(2, Duration::from_millis(100));
+        tracker.sort_by_health(&mut destinations, |index| *index);
+        assert_eq!(destinations, vec![1, 2, 0]);
+
+        // Failing destinations fall behind slower ones
+        tracker.record_failure(1);
+        tracker.record_failure(1);
+        tracker.sort_by_health(&mut destinations, |index| *index);
+        assert_eq!(destinations, vec![2, 0, 1]);
+        assert_eq!(tracker.health(1).successes, 1);
+        assert_eq!(tracker.health(1).failures, 2);
+    }
+}
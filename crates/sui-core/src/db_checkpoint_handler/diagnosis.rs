@@ -0,0 +1,165 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Classification of backup pipeline failures into their probable cause, logged with a
+//! remediation hint so that operators can act on a failure without reading the handler code.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbableCause {
+    CredentialsExpired,
+    CredentialsInvalid,
+    AccessDenied,
+    BucketNotFound,
+    DiskFull,
+    RateLimited,
+    Network,
+    Unknown,
+}
+
+/// Lowercase fragments of the error messages of the object store providers and of the OS,
+/// checked in order against the whole error chain
+const PATTERNS: &[(ProbableCause, &[&str])] = &[
+    (
+        ProbableCause::CredentialsExpired,
+        &[
+            "expiredtoken",
+            "token has expired",
+            "token is expired",
+            "requestexpired",
+        ],
+    ),
+    (
+        ProbableCause::CredentialsInvalid,
+        &[
+            "invalidaccesskeyid",
+            "signaturedoesnotmatch",
+            "invalidclienttokenid",
+            "authenticationfailed",
+            "401 unauthorized",
+        ],
+    ),
+    (
+        ProbableCause::BucketNotFound,
+        &["nosuchbucket", "bucket does not exist", "containernotfound"],
+    ),
+    (
+        ProbableCause::AccessDenied,
+        &["accessdenied", "403 forbidden", "permission denied"],
+    ),
+    (
+        ProbableCause::DiskFull,
+        &["no space left on device", "disk quota exceeded"],
+    ),
+    (
+        ProbableCause::RateLimited,
+        &[
+            "slowdown",
+            "slow down",
+            "too many requests",
+            "toomanyrequests",
+        ],
+    ),
+    (
+        ProbableCause::Network,
+        &[
+            "connection refused",
+            "connection reset",
+            "dns error",
+            "timed out",
+            "error trying to connect",
+        ],
+    ),
+];
+
+impl ProbableCause {
+    pub fn classify(err: &anyhow::Error) -> Self {
+        let message = format!("{:#}", err).to_lowercase();
+        PATTERNS
+            .iter()
+            .find(|(_, fragments)| fragments.iter().any(|f| message.contains(f)))
+            .map(|(cause, _)| *cause)
+            .unwrap_or(ProbableCause::Unknown)
+    }
+    pub fn remediation(&self) -> &'static str {
+        match self {
+            ProbableCause::CredentialsExpired => {
+                "Refresh the object store credentials of db-checkpoint-config and restart the node"
+            }
+            ProbableCause::CredentialsInvalid => {
+                "Check the access key id and secret of db-checkpoint-config"
+            }
+            ProbableCause::AccessDenied => {
+                "Grant the credentials read, write, list and delete access to the bucket, or fix the ownership of the local checkpoint path"
+            }
+            ProbableCause::BucketNotFound => {
+                "Create the bucket or fix the bucket name and region of db-checkpoint-config"
+            }
+            ProbableCause::DiskFull => {
+                "Free up space on the disk of the checkpoint path or lower num-epochs-to-retain"
+            }
+            ProbableCause::RateLimited => {
+                "Lower object-store-connection-limit or use the hashed-sharded upload layout"
+            }
+            ProbableCause::Network => {
+                "Check connectivity from the node to the object store endpoint"
+            }
+            ProbableCause::Unknown => "See the error chain for details",
+        }
+    }
+}
+
+impl fmt::Display for ProbableCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            ProbableCause::CredentialsExpired => "credentials expired",
+            ProbableCause::CredentialsInvalid => "invalid credentials",
+            ProbableCause::AccessDenied => "access denied",
+            ProbableCause::BucketNotFound => "bucket not found",
+            ProbableCause::DiskFull => "disk full",
+            ProbableCause::RateLimited => "rate limited by object store",
+            ProbableCause::Network => "network failure",
+            ProbableCause::Unknown => "unknown",
+        };
+        write!(f, "{description}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProbableCause;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_classify() {
+        let classify = |err: anyhow::Error| ProbableCause::classify(&err);
+        assert_eq!(
+            classify(
+                anyhow!(
+                    "Error performing put request: response error \"<Code>ExpiredToken</Code>\""
+                )
+                .context("Failed to upload file")
+            ),
+            ProbableCause::CredentialsExpired
+        );
+        assert_eq!(
+            classify(anyhow!("<Code>NoSuchBucket</Code>")),
+            ProbableCause::BucketNotFound
+        );
+        assert_eq!(
+            classify(std::io::Error::from_raw_os_error(28).into()),
+            ProbableCause::DiskFull
+        );
+        assert_eq!(
+            classify(anyhow!(
+                "Generic S3 error: 403 Forbidden <Code>AccessDenied</Code>"
+            )),
+            ProbableCause::AccessDenied
+        );
+        assert_eq!(
+            classify(anyhow!("Checksum mismatch for file1")),
+            ProbableCause::Unknown
+        );
+    }
+}
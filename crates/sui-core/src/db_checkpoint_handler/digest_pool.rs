@@ -0,0 +1,88 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bounded set of blocking threads for the CPU heavy work of backups: digests of file contents
+//! and content defined chunking. Running it on the async executors would stall every other
+//! task of the node for as long as a multi-gigabyte file takes to digest.
+
+use crate::db_checkpoint_handler::self_usage::UsageRecorder;
+use anyhow::Result;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+#[derive(Clone)]
+pub struct DigestPool {
+    permits: Arc<Semaphore>,
+    /// Records the resources used by the jobs, if set
+    usage: Option<UsageRecorder>,
+}
+
+impl DigestPool {
+    /// Pool running at most `parallelism` jobs at a time
+    pub fn new(parallelism: NonZeroUsize) -> Self {
+        DigestPool {
+            permits: Arc::new(Semaphore::new(parallelism.get())),
+            usage: None,
+        }
+    }
+    /// Pool sharing the permits of this one which records the resources used by its jobs
+    /// through `usage`
+    pub fn with_usage(mut self, usage: UsageRecorder) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+    /// Runs `job` on a blocking thread once one of the permits of the pool is free
+    pub async fn run<T, F>(&self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let _permit = self.permits.clone().acquire_owned().await?;
+        let usage = self.usage.clone();
+        Ok(tokio::task::spawn_blocking(move || match usage {
+            Some(usage) => usage.measure(job),
+            None => job(),
+        })
+        .await?)
+    }
+}
+
+impl Default for DigestPool {
+    /// Half of the cores, leaving the others to the node
+    fn default() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        DigestPool::new(NonZeroUsize::new((cores / 2).max(1)).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DigestPool;
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_digest_pool_bounds_parallelism() -> anyhow::Result<()> {
+        let pool = DigestPool::new(NonZeroUsize::new(2).unwrap());
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let jobs = (0..8).map(|i| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            pool.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                i * 2
+            })
+        });
+        let results = futures::future::try_join_all(jobs).await?;
+        assert_eq!(results, (0..8).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+        Ok(())
+    }
+}
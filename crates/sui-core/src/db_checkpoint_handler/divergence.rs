@@ -0,0 +1,211 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Comparison of the tables of a db checkpoint against the live db, which only grows past the
+//! db checkpoint for append-only tables. Entries of the db checkpoint missing from the live db
+//! or with a different value point at a bug in the creation of the db checkpoint, which is
+//! better caught before the backup is relied upon.
+
+use anyhow::{anyhow, Context, Result};
+use fastcrypto::encoding::{Encoding, Hex};
+use rocksdb::{IteratorMode, Options, DB};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+use sui_storage::object_store::util::parse_epoch_dir;
+
+/// Number of divergent keys reported per table
+const MAX_SAMPLE_KEYS: usize = 10;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TableDivergence {
+    pub db: String,
+    pub table: String,
+    /// Entries of the table in the db checkpoint
+    pub checkpoint_entries: u64,
+    pub missing_in_live: u64,
+    pub value_mismatches: u64,
+    /// Hex encoded keys of the first divergent entries
+    pub sample_keys: Vec<String>,
+}
+
+impl TableDivergence {
+    pub fn is_diverged(&self) -> bool {
+        self.missing_in_live > 0 || self.value_mismatches > 0
+    }
+    fn record(&mut self, key: &[u8]) {
+        if self.sample_keys.len() < MAX_SAMPLE_KEYS {
+            self.sample_keys.push(Hex::encode(key));
+        }
+    }
+}
+
+/// The most recent `epoch_N` db checkpoint under `checkpoint_path`
+pub fn latest_epoch_checkpoint(checkpoint_path: &Path) -> Result<Option<(u64, PathBuf)>> {
+    let mut latest = None;
+    for entry in fs::read_dir(checkpoint_path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let Some(epoch) = entry
+            .file_name()
+            .to_str()
+            .and_then(parse_epoch_dir)
+            .map(u64::from)
+        else {
+            continue;
+        };
+        if latest.as_ref().map_or(true, |(latest, _)| epoch > *latest) {
+            latest = Some((epoch, entry.path()));
+        }
+    }
+    Ok(latest)
+}
+
+fn open_read_only(db_path: &Path) -> Result<DB> {
+    let tables = DB::list_cf(&Options::default(), db_path)
+        .with_context(|| format!("Failed to list tables of db at {}", db_path.display()))?;
+    Ok(DB::open_cf_for_read_only(
+        &Options::default(),
+        db_path,
+        &tables,
+        false,
+    )?)
+}
+
+/// Compares `tables` of the db at `db` under `checkpoint_dir` against the same db under
+/// `live_dir`. Both dbs are opened read-only, so the live db may be in use by a running node.
+/// Only tables whose entries are never deleted nor modified should be compared.
+pub fn compare_with_live(
+    checkpoint_dir: &Path,
+    live_dir: &Path,
+    db: &str,
+    tables: &[String],
+) -> Result<Vec<TableDivergence>> {
+    let checkpoint = open_read_only(&checkpoint_dir.join(db))?;
+    let live = open_read_only(&live_dir.join(db))?;
+    let mut divergences = vec![];
+    for table in tables {
+        let checkpoint_cf = checkpoint
+            .cf_handle(table)
+            .ok_or_else(|| anyhow!("No table {table} in db checkpoint of {db}"))?;
+        let live_cf = live
+            .cf_handle(table)
+            .ok_or_else(|| anyhow!("No table {table} in live db of {db}"))?;
+        let mut divergence = TableDivergence {
+            db: db.to_string(),
+            table: table.clone(),
+            ..Default::default()
+        };
+        // Both iterators are in key order, so a single pass over each finds every entry
+        let mut live_entries = live.iterator_cf(&live_cf, IteratorMode::Start).peekable();
+        for item in checkpoint.iterator_cf(&checkpoint_cf, IteratorMode::Start) {
+            let (key, value) = item?;
+            divergence.checkpoint_entries += 1;
+            let matched = loop {
+                match live_entries.peek() {
+                    None => break None,
+                    Some(Err(_)) => {
+                        live_entries.next().transpose()?;
+                    }
+                    Some(Ok((live_key, live_value))) => match live_key[..].cmp(&key[..]) {
+                        Ordering::Less => {
+                            live_entries.next();
+                        }
+                        Ordering::Equal => break Some(live_value[..] == value[..]),
+                        Ordering::Greater => break None,
+                    },
+                }
+            };
+            match matched {
+                Some(true) => {}
+                Some(false) => {
+                    divergence.value_mismatches += 1;
+                    divergence.record(&key);
+                }
+                None => {
+                    divergence.missing_in_live += 1;
+                    divergence.record(&key);
+                }
+            }
+        }
+        divergences.push(divergence);
+    }
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_with_live, latest_epoch_checkpoint};
+    use rocksdb::{Options, DB};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn create_db(path: &Path, entries: &[(&str, &[u8], &[u8])]) {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = DB::open_cf(&options, path, ["objects", "transactions"]).unwrap();
+        for (table, key, value) in entries {
+            db.put_cf(&db.cf_handle(table).unwrap(), key, value)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compare_with_live() -> anyhow::Result<()> {
+        let checkpoint_path = TempDir::new()?;
+        let live_dir = TempDir::new()?;
+        let db = "store/perpetual";
+        fs::create_dir(checkpoint_path.path().join("epoch_9"))?;
+        fs::create_dir(checkpoint_path.path().join("epoch_10"))?;
+        fs::create_dir(checkpoint_path.path().join("epoch_11.tmp"))?;
+        let (epoch, checkpoint_dir) = latest_epoch_checkpoint(checkpoint_path.path())?.unwrap();
+        assert_eq!(epoch, 10);
+
+        create_db(
+            &checkpoint_dir.join(db),
+            &[
+                ("objects", b"a", b"1"),
+                ("objects", b"b", b"2"),
+                ("objects", b"d", b"4"),
+                ("transactions", b"t", b"1"),
+            ],
+        );
+        create_db(
+            &live_dir.path().join(db),
+            &[
+                ("objects", b"a", b"1"),
+                ("objects", b"c", b"3"),
+                ("objects", b"d", b"X"),
+                ("objects", b"e", b"5"),
+                ("transactions", b"s", b"0"),
+                ("transactions", b"t", b"1"),
+                ("transactions", b"u", b"2"),
+            ],
+        );
+        let divergences = compare_with_live(
+            &checkpoint_dir,
+            live_dir.path(),
+            db,
+            &["objects".to_string(), "transactions".to_string()],
+        )?;
+        assert_eq!(divergences[0].checkpoint_entries, 3);
+        assert_eq!(divergences[0].missing_in_live, 1);
+        assert_eq!(divergences[0].value_mismatches, 1);
+        assert_eq!(divergences[0].sample_keys, vec!["62", "64"]);
+        assert!(divergences[0].is_diverged());
+        assert!(!divergences[1].is_diverged());
+        assert!(compare_with_live(
+            &checkpoint_dir,
+            live_dir.path(),
+            db,
+            &["missing".to_string()]
+        )
+        .is_err());
+        Ok(())
+    }
+}
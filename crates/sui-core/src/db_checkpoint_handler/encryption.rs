@@ -0,0 +1,215 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Client-side encryption of the files uploaded to the remote store, see
+//! `DBCheckpointConfig::encryption`. Every file is sealed with AES-256-GCM under a random nonce
+//! stored in front of the ciphertext. The remote key of the file is bound as associated data, so
+//! that encrypted files swapped with each other in the bucket fail to decrypt. The digests of
+//! the files are sealed in the manifest the same way, see `EncryptionKey::seal_digest`.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use rand::RngCore;
+use rusoto_core::Region;
+use rusoto_kms::{DecryptRequest, Kms, KmsClient};
+use std::fmt;
+use sui_config::node::DBCheckpointEncryptionConfig;
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+/// Bytes added to every file by encryption, the nonce and the authentication tag
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+/// Suffix of the keys of encrypted files
+pub const ENCRYPTED_SUFFIX: &str = ".enc";
+/// Prefix of the file digests sealed by `EncryptionKey::seal_digest`
+pub const SEALED_DIGEST_PREFIX: &str = "sealed:";
+
+#[derive(Clone)]
+pub struct EncryptionKey {
+    key_id: String,
+    cipher: Aes256Gcm,
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptionKey {
+    pub fn new(key_id: String, key: &[u8]) -> Result<Self> {
+        if key.len() != KEY_SIZE {
+            return Err(anyhow!(
+                "Encryption key {key_id} must be {KEY_SIZE} bytes, got: {}",
+                key.len()
+            ));
+        }
+        let cipher = Aes256Gcm::new_from_slice(key)
+            .map_err(|_| anyhow!("Invalid encryption key {key_id}"))?;
+        Ok(EncryptionKey { key_id, cipher })
+    }
+    /// Reads the key from its file, or decrypts it with KMS
+    pub async fn load(config: &DBCheckpointEncryptionConfig) -> Result<Self> {
+        let key = match (&config.key_path, &config.kms_encrypted_key) {
+            (Some(key_path), None) => {
+                let key = tokio::fs::read_to_string(key_path).await.with_context(|| {
+                    format!("Failed to read encryption key from {}", key_path.display())
+                })?;
+                Hex::decode(key.trim()).map_err(|err| {
+                    anyhow!(
+                        "Invalid hex encryption key in {}: {err}",
+                        key_path.display()
+                    )
+                })?
+            }
+            (None, Some(encrypted_key)) => decrypt_with_kms(encrypted_key).await?,
+            _ => {
+                return Err(anyhow!(
+                "Exactly one of key-path and kms-encrypted-key must be set for encryption key {}",
+                config.key_id
+            ))
+            }
+        };
+        Self::new(config.key_id.clone(), &key)
+    }
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+    /// Contents of the file at `path` as stored in the remote store: the nonce followed by the
+    /// ciphertext and its tag
+    pub fn encrypt(&self, path: &str, bytes: &[u8]) -> Result<Bytes> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: bytes,
+                    aad: path.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow!("Failed to encrypt {path}"))?;
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(Bytes::from(sealed))
+    }
+    /// Contents of the file at `path` read from the remote store, stored there by `encrypt`
+    pub fn decrypt(&self, path: &str, bytes: &[u8]) -> Result<Bytes> {
+        if bytes.len() < ENCRYPTION_OVERHEAD {
+            return Err(anyhow!("Encrypted {path} is truncated"));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: path.as_bytes(),
+                },
+            )
+            .map(Bytes::from)
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to decrypt {path} with key {}, the key is wrong or the contents were tampered with",
+                    self.key_id
+                )
+            })
+    }
+    /// `digest` of the contents of the file at `path` as recorded in the manifest. Sealed so
+    /// that readers of the bucket can't confirm a guess of the contents of an encrypted file by
+    /// its digest.
+    pub fn seal_digest(&self, path: &str, digest: &str) -> Result<String> {
+        let sealed = self.encrypt(&digest_aad(path), digest.as_bytes())?;
+        Ok(format!("{SEALED_DIGEST_PREFIX}{}", Hex::encode(sealed)))
+    }
+    /// Digest of the file at `path` sealed by `seal_digest`, returned as it is when not sealed
+    pub fn open_digest(&self, path: &str, digest: &str) -> Result<String> {
+        let Some(sealed) = digest.strip_prefix(SEALED_DIGEST_PREFIX) else {
+            return Ok(digest.to_string());
+        };
+        let sealed =
+            Hex::decode(sealed).map_err(|err| anyhow!("Invalid sealed digest of {path}: {err}"))?;
+        let digest = self.decrypt(&digest_aad(path), &sealed)?;
+        String::from_utf8(digest.to_vec()).map_err(|_| anyhow!("Invalid sealed digest of {path}"))
+    }
+}
+
+/// Associated data of the sealed digest of the file at `path`, which never collides with the
+/// remote key of an encrypted file
+fn digest_aad(path: &str) -> String {
+    format!("{SEALED_DIGEST_PREFIX}{path}")
+}
+
+async fn decrypt_with_kms(encrypted_key: &str) -> Result<Vec<u8>> {
+    let ciphertext_blob = Base64::decode(encrypted_key.trim())
+        .map_err(|err| anyhow!("Invalid base64 kms-encrypted-key: {err}"))?;
+    let kms = KmsClient::new(Region::default());
+    let response = kms
+        .decrypt(DecryptRequest {
+            ciphertext_blob: ciphertext_blob.into(),
+            ..Default::default()
+        })
+        .await
+        .context("Failed to decrypt encryption key with KMS")?;
+    response
+        .plaintext
+        .map(|key| key.to_vec())
+        .ok_or_else(|| anyhow!("KMS returned no plaintext for the encryption key"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncryptionKey, ENCRYPTION_OVERHEAD, SEALED_DIGEST_PREFIX};
+    use std::fs;
+    use sui_config::node::DBCheckpointEncryptionConfig;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_encrypt_decrypt() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let key_path = dir.path().join("key");
+        fs::write(&key_path, format!("{}\n", "ab".repeat(32)))?;
+        let key = EncryptionKey::load(&DBCheckpointEncryptionConfig {
+            key_id: "key-1".to_string(),
+            key_path: Some(key_path),
+            kms_encrypted_key: None,
+        })
+        .await?;
+        let path = "epoch_0/store/file1";
+        let sealed = key.encrypt(path, b"Lorem ipsum")?;
+        assert_eq!(sealed.len(), b"Lorem ipsum".len() + ENCRYPTION_OVERHEAD);
+        assert_eq!(&key.decrypt(path, &sealed)?[..], b"Lorem ipsum");
+        // Nonces are never reused
+        assert_ne!(key.encrypt(path, b"Lorem ipsum")?, sealed);
+
+        // Moved files, other keys and tampered contents are rejected
+        assert!(key.decrypt("epoch_0/file2", &sealed).is_err());
+        let other = EncryptionKey::new("key-2".to_string(), &[0xcd; 32])?;
+        assert!(other.decrypt(path, &sealed).is_err());
+        let mut tampered = sealed.to_vec();
+        tampered[20] ^= 1;
+        assert!(key.decrypt(path, &tampered).is_err());
+        assert!(key.decrypt(path, &sealed[..10]).is_err());
+
+        // Sealed digests only open for the file they were sealed for
+        let digest = "ab".repeat(32);
+        let sealed_digest = key.seal_digest("store/file1", &digest)?;
+        assert!(sealed_digest.starts_with(SEALED_DIGEST_PREFIX));
+        assert!(!sealed_digest.contains(&digest));
+        assert_eq!(key.open_digest("store/file1", &sealed_digest)?, digest);
+        assert_eq!(key.open_digest("store/file1", &digest)?, digest);
+        assert!(key.open_digest("store/file2", &sealed_digest).is_err());
+        assert!(other.open_digest("store/file1", &sealed_digest).is_err());
+
+        assert!(EncryptionKey::new("short".to_string(), &[0; 16]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-epoch upload gauges for the most recent epochs only. A gauge labelled by epoch gains a
+//! series every epoch, so the series of the oldest epoch are removed once the window is full
+//! and the number of series stays bounded however long the node runs.
+
+use parking_lot::Mutex;
+use prometheus::{register_int_gauge_vec_with_registry, IntGaugeVec, Registry};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// Number of epochs with upload gauges when not configured
+pub const DEFAULT_EPOCH_METRICS_WINDOW: usize = 10;
+
+/// Value of the upload status gauge of an epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpochUploadStatus {
+    Started = 1,
+    /// The upload passed its deadline and resumes on the next interval
+    Deferred = 2,
+    Failed = 3,
+    Completed = 4,
+}
+
+pub struct EpochWindowMetrics {
+    window: usize,
+    /// Epochs with series in the gauges
+    epochs: Mutex<BTreeSet<u32>>,
+    upload_status: IntGaugeVec,
+    upload_duration_ms: IntGaugeVec,
+    original_bytes: IntGaugeVec,
+    uploaded_bytes: IntGaugeVec,
+}
+
+impl EpochWindowMetrics {
+    pub fn new(registry: &Registry, window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            epochs: Mutex::new(BTreeSet::new()),
+            upload_status: register_int_gauge_vec_with_registry!(
+                "db_checkpoint_epoch_upload_status",
+                "Upload status of the db checkpoint of each recent epoch: 1 started, 2 deferred, 3 failed, 4 completed",
+                &["epoch"],
+                registry
+            )
+            .unwrap(),
+            upload_duration_ms: register_int_gauge_vec_with_registry!(
+                "db_checkpoint_epoch_upload_duration_ms",
+                "Duration of the completed upload of the db checkpoint of each recent epoch",
+                &["epoch"],
+                registry
+            )
+            .unwrap(),
+            original_bytes: register_int_gauge_vec_with_registry!(
+                "db_checkpoint_epoch_original_bytes",
+                "Size of the files of the compressed db checkpoint of each recent epoch on local disk",
+                &["epoch"],
+                registry
+            )
+            .unwrap(),
+            uploaded_bytes: register_int_gauge_vec_with_registry!(
+                "db_checkpoint_epoch_uploaded_bytes",
+                "Size of the files of the compressed db checkpoint of each recent epoch in the remote store",
+                &["epoch"],
+                registry
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Adds `epoch` to the window, evicting the series of the oldest epoch when full. Returns
+    /// false for an epoch older than every epoch of a full window, which gets no series.
+    fn track(&self, epoch: u32) -> bool {
+        let mut epochs = self.epochs.lock();
+        if epochs.contains(&epoch) {
+            return true;
+        }
+        if epochs.len() >= self.window {
+            let oldest = *epochs.first().unwrap();
+            if epoch < oldest {
+                return false;
+            }
+            epochs.remove(&oldest);
+            let label = oldest.to_string();
+            let _ = self.upload_status.remove_label_values(&[&label]);
+            let _ = self.upload_duration_ms.remove_label_values(&[&label]);
+            let _ = self.original_bytes.remove_label_values(&[&label]);
+            let _ = self.uploaded_bytes.remove_label_values(&[&label]);
+        }
+        epochs.insert(epoch);
+        true
+    }
+
+    pub fn set_status(&self, epoch: u32, status: EpochUploadStatus) {
+        if self.track(epoch) {
+            self.upload_status
+                .with_label_values(&[&epoch.to_string()])
+                .set(status as i64);
+        }
+    }
+
+    pub fn set_completed(&self, epoch: u32, duration: Duration) {
+        if self.track(epoch) {
+            let label = epoch.to_string();
+            self.upload_status
+                .with_label_values(&[&label])
+                .set(EpochUploadStatus::Completed as i64);
+            self.upload_duration_ms
+                .with_label_values(&[&label])
+                .set(duration.as_millis() as i64);
+        }
+    }
+
+    /// Records the size of the files of a compressed epoch before and after compression
+    pub fn set_compressed_sizes(&self, epoch: u32, original_bytes: usize, uploaded_bytes: usize) {
+        if self.track(epoch) {
+            let label = epoch.to_string();
+            self.original_bytes
+                .with_label_values(&[&label])
+                .set(original_bytes as i64);
+            self.uploaded_bytes
+                .with_label_values(&[&label])
+                .set(uploaded_bytes as i64);
+        }
+    }
+
+    /// Epochs with series in the gauges
+    pub fn epochs(&self) -> Vec<u32> {
+        self.epochs.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EpochUploadStatus, EpochWindowMetrics};
+    use prometheus::Registry;
+    use std::time::Duration;
+
+    fn epoch_labels(registry: &Registry, name: &str) -> Vec<String> {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.get_name() == name)
+            .flat_map(|family| family.get_metric().to_vec())
+            .map(|metric| metric.get_label()[0].get_value().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_epoch_window_recycles_labels() {
+        let registry = Registry::new();
+        let metrics = EpochWindowMetrics::new(&registry, 2);
+        metrics.set_status(1, EpochUploadStatus::Started);
+        metrics.set_completed(1, Duration::from_millis(1500));
+        metrics.set_compressed_sizes(1, 1000, 250);
+        metrics.set_status(2, EpochUploadStatus::Deferred);
+        assert_eq!(metrics.epochs(), vec![1, 2]);
+
+        metrics.set_status(3, EpochUploadStatus::Failed);
+        assert_eq!(metrics.epochs(), vec![2, 3]);
+        assert_eq!(
+            epoch_labels(&registry, "db_checkpoint_epoch_upload_status"),
+            vec!["2", "3"]
+        );
+        // The duration series of the evicted epoch is removed too
+        assert!(epoch_labels(&registry, "db_checkpoint_epoch_upload_duration_ms").is_empty());
+        assert!(epoch_labels(&registry, "db_checkpoint_epoch_uploaded_bytes").is_empty());
+
+        // An epoch older than the window gets no series
+        metrics.set_completed(1, Duration::from_secs(1));
+        assert_eq!(metrics.epochs(), vec![2, 3]);
+        metrics.set_completed(2, Duration::from_secs(2));
+        assert_eq!(
+            metrics.upload_status.with_label_values(&["2"]).get(),
+            EpochUploadStatus::Completed as i64
+        );
+        assert_eq!(
+            metrics.upload_duration_ms.with_label_values(&["2"]).get(),
+            2000
+        );
+    }
+}
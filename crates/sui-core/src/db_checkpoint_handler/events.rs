@@ -0,0 +1,145 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::Serialize;
+use std::time::Duration;
+use sui_config::node::BackupNotificationKind;
+
+/// Number of events buffered for every subscriber before the oldest ones are dropped
+pub const BACKUP_EVENTS_CAPACITY: usize = 1024;
+
+/// Typed events of the db checkpoint backup pipeline, published to the subscribers of
+/// `DBCheckpointHandler::subscribe` so that services embedding the handler can react to them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupEvent {
+    /// A local db checkpoint that is missing from the remote store was found
+    EpochDiscovered {
+        epoch: u32,
+    },
+    UploadStarted {
+        epoch: u32,
+    },
+    UploadCompleted {
+        epoch: u32,
+        duration: Duration,
+    },
+    /// Local db checkpoints of these epochs were deleted
+    GcPerformed {
+        epochs: Vec<u32>,
+    },
+    /// A file of the db checkpoint of `epoch` kept on local disk is missing or doesn't match
+    /// the digest recorded after compaction anymore
+    LocalCorruptionDetected {
+        epoch: u32,
+        path: String,
+    },
+    /// The first epoch missing from the remote store moved back from `previous` to `current`:
+    /// an epoch that was complete lost its success marker. This points to tampering with the
+    /// bucket or data loss, not to uploads lagging behind.
+    FirstMissingEpochRegressed {
+        previous: u32,
+        current: u32,
+    },
+    /// A step of the pipeline failed and is retried on the next interval. `epoch` is set when
+    /// the failure is specific to one db checkpoint.
+    Error {
+        epoch: Option<u32>,
+        error: String,
+    },
+}
+
+/// JSON body of the requests sent to `DBCheckpointConfig::webhooks`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct BackupNotification {
+    pub event: BackupNotificationKind,
+    /// Name of the node, see `DBCheckpointConfig::producer_name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub producer_name: Option<String>,
+    /// Name of the replica store the event is about, unset for the output store, see
+    /// `DBCheckpointHandler::subscribe_with_replicas`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<u32>,
+    /// Epochs of the garbage collected local db checkpoints
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub epochs: Vec<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// The error, the corrupted file, or the first missing epoch before it regressed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+impl BackupEvent {
+    /// Notification sent to webhooks, `None` for the events they aren't notified of
+    pub fn notification(&self, producer_name: Option<String>) -> Option<BackupNotification> {
+        let mut notification = BackupNotification {
+            event: BackupNotificationKind::Error,
+            producer_name,
+            destination: None,
+            epoch: None,
+            epochs: vec![],
+            duration_ms: None,
+            details: None,
+        };
+        match self {
+            BackupEvent::EpochDiscovered { .. } => return None,
+            BackupEvent::UploadStarted { epoch } => {
+                notification.event = BackupNotificationKind::UploadStarted;
+                notification.epoch = Some(*epoch);
+            }
+            BackupEvent::UploadCompleted { epoch, duration } => {
+                notification.event = BackupNotificationKind::UploadCompleted;
+                notification.epoch = Some(*epoch);
+                notification.duration_ms = Some(duration.as_millis() as u64);
+            }
+            BackupEvent::GcPerformed { epochs } => {
+                notification.event = BackupNotificationKind::GcPerformed;
+                notification.epochs = epochs.clone();
+            }
+            BackupEvent::LocalCorruptionDetected { epoch, path } => {
+                notification.event = BackupNotificationKind::LocalCorruptionDetected;
+                notification.epoch = Some(*epoch);
+                notification.details = Some(path.clone());
+            }
+            BackupEvent::FirstMissingEpochRegressed { previous, current } => {
+                notification.event = BackupNotificationKind::FirstMissingEpochRegressed;
+                notification.epoch = Some(*current);
+                notification.details = Some(format!("First missing epoch was {previous}"));
+            }
+            BackupEvent::Error { epoch, error } => {
+                notification.epoch = *epoch;
+                notification.details = Some(error.clone());
+            }
+        }
+        Some(notification)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BackupEvent;
+    use std::time::Duration;
+
+    #[test]
+    fn test_notification() {
+        let event = BackupEvent::UploadCompleted {
+            epoch: 3,
+            duration: Duration::from_secs(2),
+        };
+        let notification = event.notification(Some("node-a".to_string())).unwrap();
+        assert_eq!(
+            serde_json::to_string(&notification).unwrap(),
+            r#"{"event":"upload-completed","producer_name":"node-a","epoch":3,"duration_ms":2000}"#
+        );
+        let event = BackupEvent::GcPerformed { epochs: vec![0, 1] };
+        assert_eq!(
+            serde_json::to_string(&event.notification(None).unwrap()).unwrap(),
+            r#"{"event":"gc-performed","epochs":[0,1]}"#
+        );
+        assert!(BackupEvent::EpochDiscovered { epoch: 3 }
+            .notification(None)
+            .is_none());
+    }
+}
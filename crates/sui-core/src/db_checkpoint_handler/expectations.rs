@@ -0,0 +1,203 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read results a node serving a restored db checkpoint must reproduce. They are sampled from
+//! the db checkpoint before upload and recorded in the epoch manifest, so that restores can be
+//! validated end to end by querying the restored node over RPC.
+
+use crate::authority::authority_store_tables::{
+    AuthorityPerpetualTables, AuthorityPerpetualTablesReadOnly,
+};
+use crate::authority::authority_store_types::{try_construct_object, StoreData, StoreObject};
+use crate::checkpoints::{CheckpointStore, CheckpointWatermark};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use sui_types::base_types::{ObjectID, ObjectRef, TransactionDigest, VersionNumber};
+use sui_types::messages_checkpoint::{
+    CheckpointDigest, CheckpointSequenceNumber, VerifiedCheckpoint,
+};
+use sui_types::storage::ObjectKey;
+use typed_store::rocks::MetricConf;
+use typed_store::traits::Map;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct RestoreExpectations {
+    /// Highest executed checkpoint of the db checkpoint
+    pub latest_checkpoint: CheckpointSequenceNumber,
+    pub latest_checkpoint_digest: CheckpointDigest,
+    /// Latest references of randomly sampled live objects
+    pub objects: Vec<ObjectRef>,
+    /// Transactions of the latest checkpoints, with the checkpoint that includes them
+    pub transactions: Vec<(TransactionDigest, CheckpointSequenceNumber)>,
+}
+
+impl RestoreExpectations {
+    /// Samples up to `num_samples` objects and transactions from the db checkpoint at
+    /// `db_path`. Returns `None` if it doesn't hold both the perpetual and checkpoint dbs.
+    /// The dbs are opened as secondary instances kept outside of `db_path`, so the files of the
+    /// db checkpoint are left as they were listed and digested.
+    pub fn sample(db_path: &Path, num_samples: usize) -> Result<Option<Self>> {
+        let store_path = db_path.join("store");
+        let checkpoints_path = db_path.join("checkpoints");
+        if !AuthorityPerpetualTables::path(&store_path).exists() || !checkpoints_path.exists() {
+            return Ok(None);
+        }
+        let secondary_dir = tempfile::tempdir()?;
+        let checkpoint_store = CheckpointStore::get_read_only_handle(
+            checkpoints_path,
+            Some(secondary_dir.path().join("checkpoints")),
+            None,
+            MetricConf::default(),
+        );
+        let perpetual_db = AuthorityPerpetualTables::get_read_only_handle(
+            AuthorityPerpetualTables::path(&store_path),
+            Some(secondary_dir.path().join("perpetual")),
+            None,
+            MetricConf::default(),
+        );
+        let Some((_, latest_digest)) = checkpoint_store
+            .watermarks
+            .get(&CheckpointWatermark::HighestExecuted)?
+        else {
+            return Ok(None);
+        };
+        let Some(latest) = checkpoint_store.checkpoint_by_digest.get(&latest_digest)? else {
+            return Ok(None);
+        };
+        let latest: VerifiedCheckpoint = latest.into();
+        let mut transactions = vec![];
+        let mut sequence_number = *latest.sequence_number();
+        while transactions.len() < num_samples {
+            // Older checkpoints may have been pruned
+            let Some(checkpoint) = checkpoint_store
+                .certified_checkpoints
+                .get(&sequence_number)?
+            else {
+                break;
+            };
+            let checkpoint: VerifiedCheckpoint = checkpoint.into();
+            if let Some(contents) = checkpoint_store
+                .checkpoint_content
+                .get(&checkpoint.content_digest)?
+            {
+                for digests in contents.iter().take(num_samples - transactions.len()) {
+                    transactions.push((digests.transaction, sequence_number));
+                }
+            }
+            if sequence_number == 0 {
+                break;
+            }
+            sequence_number -= 1;
+        }
+
+        let mut objects = vec![];
+        for _ in 0..num_samples {
+            let start = ObjectKey(ObjectID::random(), VersionNumber::MIN);
+            let Some((ObjectKey(object_id, _), _)) = perpetual_db
+                .objects
+                .unbounded_iter()
+                .skip_to(&start)?
+                .next()
+            else {
+                continue;
+            };
+            // Deleted and wrapped objects have no live version to compare against
+            if let Some(object_ref) = live_object_ref(&perpetual_db, &object_id)? {
+                if !objects.contains(&object_ref) {
+                    objects.push(object_ref);
+                }
+            }
+        }
+        Ok(Some(RestoreExpectations {
+            latest_checkpoint: *latest.sequence_number(),
+            latest_checkpoint_digest: *latest.digest(),
+            objects,
+            transactions,
+        }))
+    }
+}
+
+/// Reference of the latest version of `object_id`, `None` if it was deleted or wrapped
+fn live_object_ref(
+    perpetual_db: &AuthorityPerpetualTablesReadOnly,
+    object_id: &ObjectID,
+) -> Result<Option<ObjectRef>> {
+    let Some((object_key, store_object)) = perpetual_db
+        .objects
+        .unbounded_iter()
+        .skip_prior_to(&ObjectKey::max_for_id(object_id))?
+        .next()
+    else {
+        return Ok(None);
+    };
+    let StoreObject::Value(store_object) = store_object.migrate().into_inner() else {
+        return Ok(None);
+    };
+    if object_key.0 != *object_id {
+        return Ok(None);
+    }
+    let indirect_object = match &store_object.data {
+        StoreData::IndirectObject(metadata) => perpetual_db
+            .indirect_move_objects
+            .get(&metadata.digest)?
+            .map(|o| o.migrate().into_inner()),
+        _ => None,
+    };
+    let object = try_construct_object(&object_key, store_object, indirect_object)?;
+    Ok(Some(object.compute_object_reference()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestoreExpectations;
+    use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+    use crate::checkpoints::CheckpointStore;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+    use sui_types::base_types::ObjectID;
+    use sui_types::object::Object;
+    use tempfile::TempDir;
+
+    fn modification_times(dir: &Path) -> anyhow::Result<BTreeMap<PathBuf, SystemTime>> {
+        let mut times = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                times.extend(modification_times(&entry.path())?);
+            }
+            times.insert(entry.path(), metadata.modified()?);
+        }
+        Ok(times)
+    }
+
+    #[test]
+    fn test_sample_leaves_db_checkpoint_untouched() -> anyhow::Result<()> {
+        let db_checkpoint = TempDir::new()?;
+        // Dropping the runtime stops the metrics tasks that keep the dbs open
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            let perpetual_db =
+                AuthorityPerpetualTables::open(&db_checkpoint.path().join("store"), None);
+            let object = Object::immutable_with_id_for_testing(ObjectID::random());
+            perpetual_db.insert_object_test_only(object)?;
+            CheckpointStore::new(&db_checkpoint.path().join("checkpoints"));
+            anyhow::Ok(())
+        })?;
+        drop(runtime);
+        let before = modification_times(db_checkpoint.path())?;
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        let expectations =
+            runtime.block_on(async { RestoreExpectations::sample(db_checkpoint.path(), 10) })?;
+        drop(runtime);
+        // No checkpoint was executed
+        assert!(expectations.is_none());
+        assert_eq!(modification_times(db_checkpoint.path())?, before);
+        Ok(())
+    }
+}
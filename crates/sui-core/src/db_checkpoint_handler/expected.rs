@@ -0,0 +1,100 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Listing of the files of a db checkpoint, written by the node right after creating it. The
+//! upload handler checks the db checkpoint against it before uploading, so that partially
+//! created or partially copied checkpoints are never marked as successfully uploaded.
+
+use crate::db_checkpoint_handler::REPLICA_SUFFIX;
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const EXPECTED_FILENAME: &str = "EXPECTED";
+
+/// Whether the top level file `name` of a db checkpoint is one of `excluded`, or the copy of
+/// one written by the handler of a replica
+pub fn is_excluded_file(excluded: &[&str], name: &str) -> bool {
+    excluded.iter().any(|excluded| {
+        let Some(rest) = name.strip_prefix(excluded) else {
+            return false;
+        };
+        rest.is_empty()
+            || rest.strip_prefix(REPLICA_SUFFIX).map_or(false, |index| {
+                !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit())
+            })
+    })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct ExpectedFiles {
+    /// Size of every file, by path relative to the epoch directory
+    pub files: BTreeMap<String, u64>,
+}
+
+impl ExpectedFiles {
+    /// Lists the files under `dir`, skipping the top level files named in `excluded` and their
+    /// copies for replicas
+    pub fn from_dir(dir: &Path, excluded: &[&str]) -> Result<Self> {
+        let mut expected = ExpectedFiles::default();
+        expected.add_dir(dir, "", excluded)?;
+        Ok(expected)
+    }
+    fn add_dir(&mut self, dir: &Path, prefix: &str, excluded: &[&str]) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if prefix.is_empty() && is_excluded_file(excluded, &name) {
+                continue;
+            }
+            let path = format!("{prefix}{name}");
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                self.add_dir(&entry.path(), &format!("{path}/"), excluded)?;
+            } else {
+                self.files.insert(path, metadata.len());
+            }
+        }
+        Ok(())
+    }
+    /// Writes the listing of the files of the db checkpoint at `dir` into it
+    pub fn write(dir: &Path) -> Result<()> {
+        let expected = ExpectedFiles::from_dir(dir, &[EXPECTED_FILENAME])?;
+        fs::write(dir.join(EXPECTED_FILENAME), serde_json::to_vec(&expected)?)?;
+        Ok(())
+    }
+    /// Reads the listing of the db checkpoint at `dir`, `None` if it was created without one
+    pub fn read(dir: &Path) -> Result<Option<Self>> {
+        match fs::read(dir.join(EXPECTED_FILENAME)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+    /// Checks that every expected file is in `actual` with its expected size
+    pub fn verify(&self, actual: &BTreeMap<String, u64>) -> Result<()> {
+        let mismatches: Vec<String> = self
+            .files
+            .iter()
+            .filter_map(|(path, size)| match actual.get(path) {
+                None => Some(format!("{path} is missing")),
+                Some(actual_size) if actual_size != size => {
+                    Some(format!("{path} has size {actual_size}, expected {size}"))
+                }
+                Some(_) => None,
+            })
+            .collect();
+        if !mismatches.is_empty() {
+            return Err(anyhow!(
+                "Incomplete db checkpoint, {} of {} expected files differ: {}",
+                mismatches.len(),
+                self.files.len(),
+                mismatches.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
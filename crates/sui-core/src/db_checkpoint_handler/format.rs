@@ -0,0 +1,169 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Machine-readable description of the files db checkpoint uploads write to the bucket and to
+//! the local db checkpoint directory, with JSON schemas generated from the types they are
+//! serialized from. Tools reading backups without linking this crate can rely on it being
+//! accurate for the release it was generated by.
+
+use crate::db_checkpoint_handler::audit::{AuditRecord, AUDIT_DIR};
+use crate::db_checkpoint_handler::chunking::CHUNKS_DIR;
+use crate::db_checkpoint_handler::expected::{ExpectedFiles, EXPECTED_FILENAME};
+use crate::db_checkpoint_handler::manifest::{
+    EpochManifest, EpochMetadata, FileEntry, UploadProgress, LOCAL_CHECKSUMS_FILENAME,
+    MANIFEST_FILENAME, SUMS_FILENAME, UPLOAD_PROGRESS_FILENAME,
+};
+use crate::db_checkpoint_handler::{SUCCESS_MARKER, UPLOAD_COMPLETED_MARKER};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::Map;
+use serde::Serialize;
+use sui_config::node::DBCheckpointUploadLayout;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileLocation {
+    /// In the bucket, relative to its root or key prefix
+    Remote,
+    /// In the local db checkpoint directory of the node
+    Local,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FileFormat {
+    pub location: FileLocation,
+    /// Key of the file, with `{epoch}` and the other placeholders in braces
+    pub key: String,
+    pub description: String,
+    /// Schema of the JSON contents of the file, absent for empty or non JSON files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Schema>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LayoutFormat {
+    pub layout: DBCheckpointUploadLayout,
+    /// Key of a file of the db checkpoint, with `{path}` its path relative to the epoch
+    /// directory as listed in the manifest
+    pub file_key: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DBCheckpointFormat {
+    /// Version of the release the description was generated by
+    pub version: String,
+    pub files: Vec<FileFormat>,
+    pub layouts: Vec<LayoutFormat>,
+    /// Schemas referenced by the files, by name
+    pub definitions: Map<String, Schema>,
+}
+
+impl DBCheckpointFormat {
+    pub fn new(version: &str) -> Self {
+        let mut gen = SchemaGenerator::new(SchemaSettings::draft07());
+        let files = vec![
+            FileFormat {
+                location: FileLocation::Remote,
+                key: format!("epoch_{{epoch}}/{SUCCESS_MARKER}"),
+                description: "Written last, marks a fully uploaded epoch. Empty, or the metadata of the epoch when uploaded with consolidate-epoch-metadata, in which case the MANIFEST and the audit record of the upload are not written separately".to_string(),
+                schema: Some(gen.subschema_for::<EpochMetadata>()),
+            },
+            FileFormat {
+                location: FileLocation::Remote,
+                key: format!("epoch_{{epoch}}/{MANIFEST_FILENAME}"),
+                description: "Lists every file of the epoch and its key in the bucket. When sharded, lists the shards holding the files instead. Only describes a complete epoch once the epoch has its _SUCCESS marker, a MANIFEST without one is left over by an interrupted upload".to_string(),
+                schema: Some(gen.subschema_for::<EpochManifest>()),
+            },
+            FileFormat {
+                location: FileLocation::Remote,
+                key: format!("epoch_{{epoch}}/{MANIFEST_FILENAME}.{{index:05}}-of-{{count:05}}"),
+                description: "A shard of the file list of a sharded MANIFEST, in path order".to_string(),
+                schema: Some(gen.subschema_for::<Vec<FileEntry>>()),
+            },
+            FileFormat {
+                location: FileLocation::Remote,
+                key: format!("epoch_{{epoch}}/{SUMS_FILENAME}"),
+                description: "sha256sum formatted checksums of the files of the epoch, written with the mirrored-with-sums layout".to_string(),
+                schema: None,
+            },
+            FileFormat {
+                location: FileLocation::Remote,
+                key: format!("{CHUNKS_DIR}/{{sha3_digest[0..2]}}/{{sha3_digest}}"),
+                description: "A content defined chunk of one or more files, shared by all epochs uploaded with the content-defined-chunks layout".to_string(),
+                schema: None,
+            },
+            FileFormat {
+                location: FileLocation::Remote,
+                key: format!("{AUDIT_DIR}/epoch_{{epoch}}/{{timestamp_ms:020}}_{{action}}_{{sha3_hex(record)[0..8]}}.json"),
+                description: "A record of an action on the epoch, written when an audit actor is configured".to_string(),
+                schema: Some(gen.subschema_for::<AuditRecord>()),
+            },
+            FileFormat {
+                location: FileLocation::Local,
+                key: format!("epoch_{{epoch}}/{EXPECTED_FILENAME}"),
+                description: "Files of the db checkpoint as created by the node, checked before upload".to_string(),
+                schema: Some(gen.subschema_for::<ExpectedFiles>()),
+            },
+            FileFormat {
+                location: FileLocation::Local,
+                key: format!("epoch_{{epoch}}/{LOCAL_CHECKSUMS_FILENAME}"),
+                description: "Digests of the files taken after pruning and compaction, checked by uploads and local scrubs".to_string(),
+                schema: Some(gen.subschema_for::<EpochManifest>()),
+            },
+            FileFormat {
+                location: FileLocation::Local,
+                key: format!("epoch_{{epoch}}/{UPLOAD_PROGRESS_FILENAME}"),
+                description: "Progress of an upload paused by the epoch upload deadline".to_string(),
+                schema: Some(gen.subschema_for::<UploadProgress>()),
+            },
+            FileFormat {
+                location: FileLocation::Local,
+                key: format!("epoch_{{epoch}}/{UPLOAD_COMPLETED_MARKER}"),
+                description: "Empty, marks a db checkpoint uploaded successfully and eligible for garbage collection".to_string(),
+                schema: None,
+            },
+        ];
+        let layouts = vec![
+            LayoutFormat {
+                layout: DBCheckpointUploadLayout::Mirrored,
+                file_key: "epoch_{epoch}/{path}".to_string(),
+                description: "Keys mirror the local db checkpoint directory".to_string(),
+            },
+            LayoutFormat {
+                layout: DBCheckpointUploadLayout::HashedSharded,
+                file_key: "epoch_{epoch}/{sha3(path)[0]:02x}/{path}".to_string(),
+                description: "Keys are prefixed with the first byte of the sha3 digest of the path"
+                    .to_string(),
+            },
+            LayoutFormat {
+                layout: DBCheckpointUploadLayout::ContentDefinedChunks,
+                file_key: format!("{CHUNKS_DIR}/{{sha3_digest[0..2]}}/{{sha3_digest}}"),
+                description:
+                    "Files are the concatenation of the chunks listed in their manifest entry"
+                        .to_string(),
+            },
+            LayoutFormat {
+                layout: DBCheckpointUploadLayout::MirroredWithSums,
+                file_key: "epoch_{epoch}/{path}".to_string(),
+                description:
+                    "Keys mirror the local db checkpoint directory, with SHA256.sum checksums"
+                        .to_string(),
+            },
+            LayoutFormat {
+                layout: DBCheckpointUploadLayout::TarArchives,
+                file_key: "epoch_{epoch}/archive_{index_of_first_file:08}.tar.zst".to_string(),
+                description: "Files are tar members in the archive of their manifest entry, each compressed as the zstd frame at `archive_offset`. Files larger than an archive have no `archive_offset` and are uploaded uncompressed at `epoch_{epoch}/{path}`".to_string(),
+            },
+        ];
+        DBCheckpointFormat {
+            version: version.to_string(),
+            files,
+            layouts,
+            definitions: gen.take_definitions(),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Db checkpoints taken as filesystem snapshots of the live db. They replace RocksDB
+//! checkpoints at epoch end when a `DBSnapshotBackend` is configured and are exposed as the
+//! usual `epoch_N` directories, so the upload handler processes them unchanged.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use std::process::Command;
+use sui_config::node::DBSnapshotBackend;
+use tracing::info;
+
+/// Snapshots the live db of `epoch` and exposes it at `epoch_checkpoint_path`
+pub fn create_fs_snapshot(
+    backend: &DBSnapshotBackend,
+    epoch: u64,
+    epoch_checkpoint_path: &Path,
+) -> Result<()> {
+    for args in create_commands(backend, epoch, epoch_checkpoint_path) {
+        run(&args)?;
+    }
+    info!(
+        "Took {:?} snapshot of db for epoch: {epoch} at {}",
+        backend,
+        epoch_checkpoint_path.display()
+    );
+    Ok(())
+}
+
+/// Destroys the snapshot of `epoch` exposed at `epoch_checkpoint_path`
+pub fn destroy_fs_snapshot(
+    backend: &DBSnapshotBackend,
+    epoch: u64,
+    epoch_checkpoint_path: &Path,
+) -> Result<()> {
+    for args in destroy_commands(backend, epoch, epoch_checkpoint_path) {
+        run(&args)?;
+    }
+    Ok(())
+}
+
+/// Whether `epoch_checkpoint_path` is a filesystem snapshot rather than a plain directory, such
+/// as a RocksDB checkpoint taken before the backend was configured. Zfs clones are mounted and
+/// btrfs subvolumes have their own device id, unlike the directories of their parent.
+#[cfg(unix)]
+pub fn is_fs_snapshot(epoch_checkpoint_path: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let Some(parent) = epoch_checkpoint_path.parent() else {
+        return Ok(false);
+    };
+    Ok(std::fs::metadata(epoch_checkpoint_path)?.dev() != std::fs::metadata(parent)?.dev())
+}
+
+#[cfg(not(unix))]
+pub fn is_fs_snapshot(_epoch_checkpoint_path: &Path) -> Result<bool> {
+    // Neither backend is available
+    Ok(false)
+}
+
+fn create_commands(
+    backend: &DBSnapshotBackend,
+    epoch: u64,
+    epoch_checkpoint_path: &Path,
+) -> Vec<Vec<String>> {
+    match backend {
+        DBSnapshotBackend::Zfs { dataset } => {
+            let snapshot = format!("{dataset}@epoch_{epoch}");
+            vec![
+                vec!["zfs".into(), "snapshot".into(), snapshot.clone()],
+                vec![
+                    "zfs".into(),
+                    "clone".into(),
+                    "-o".into(),
+                    format!("mountpoint={}", epoch_checkpoint_path.display()),
+                    snapshot,
+                    format!("{dataset}-epoch_{epoch}"),
+                ],
+            ]
+        }
+        DBSnapshotBackend::Btrfs { subvolume } => vec![vec![
+            "btrfs".into(),
+            "subvolume".into(),
+            "snapshot".into(),
+            subvolume.display().to_string(),
+            epoch_checkpoint_path.display().to_string(),
+        ]],
+    }
+}
+
+fn destroy_commands(
+    backend: &DBSnapshotBackend,
+    epoch: u64,
+    epoch_checkpoint_path: &Path,
+) -> Vec<Vec<String>> {
+    match backend {
+        // The clone depends on the snapshot and must go first
+        DBSnapshotBackend::Zfs { dataset } => vec![
+            vec![
+                "zfs".into(),
+                "destroy".into(),
+                format!("{dataset}-epoch_{epoch}"),
+            ],
+            vec![
+                "zfs".into(),
+                "destroy".into(),
+                format!("{dataset}@epoch_{epoch}"),
+            ],
+        ],
+        DBSnapshotBackend::Btrfs { .. } => vec![vec![
+            "btrfs".into(),
+            "subvolume".into(),
+            "delete".into(),
+            epoch_checkpoint_path.display().to_string(),
+        ]],
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let output = Command::new(&args[0])
+        .args(&args[1..])
+        .output()
+        .with_context(|| format!("Failed to run {}", args[0]))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`{}` failed with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_commands, destroy_commands, is_fs_snapshot};
+    use std::path::Path;
+    use sui_config::node::DBSnapshotBackend;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_plain_dir_is_not_snapshot() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let epoch_checkpoint_path = dir.path().join("epoch_5");
+        std::fs::create_dir(&epoch_checkpoint_path)?;
+        assert!(!is_fs_snapshot(&epoch_checkpoint_path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_commands() {
+        let path = Path::new("/opt/sui/db_checkpoints/epoch_5");
+        let zfs = DBSnapshotBackend::Zfs {
+            dataset: "tank/sui/live".to_string(),
+        };
+        assert_eq!(
+            create_commands(&zfs, 5, path),
+            vec![
+                vec!["zfs", "snapshot", "tank/sui/live@epoch_5"],
+                vec![
+                    "zfs",
+                    "clone",
+                    "-o",
+                    "mountpoint=/opt/sui/db_checkpoints/epoch_5",
+                    "tank/sui/live@epoch_5",
+                    "tank/sui/live-epoch_5"
+                ],
+            ]
+        );
+        assert_eq!(
+            destroy_commands(&zfs, 5, path),
+            vec![
+                vec!["zfs", "destroy", "tank/sui/live-epoch_5"],
+                vec!["zfs", "destroy", "tank/sui/live@epoch_5"],
+            ]
+        );
+
+        let btrfs = DBSnapshotBackend::Btrfs {
+            subvolume: "/opt/sui/db/live".into(),
+        };
+        assert_eq!(
+            create_commands(&btrfs, 5, path),
+            vec![vec![
+                "btrfs",
+                "subvolume",
+                "snapshot",
+                "/opt/sui/db/live",
+                "/opt/sui/db_checkpoints/epoch_5"
+            ]]
+        );
+        assert_eq!(
+            destroy_commands(&btrfs, 5, path),
+            vec![vec![
+                "btrfs",
+                "subvolume",
+                "delete",
+                "/opt/sui/db_checkpoints/epoch_5"
+            ]]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conditions which must all hold before garbage collection deletes a local db checkpoint.
+//! Uploads to the output store and to every replica each mark the db checkpoint when done, a
+//! replica being held back by no longer than `DBCheckpointConfig::replica_gc_wait_s`, and
+//! other subsystems reading local db checkpoints, e.g. a state snapshot uploader or an archive
+//! writer, register conditions of their own with `DBCheckpointHandler::with_gc_readiness`.
+
+use crate::db_checkpoint_handler::source::LocalCheckpoint;
+use anyhow::Result;
+use async_trait::async_trait;
+use object_store::DynObjectStore;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+#[async_trait]
+pub trait GcReadiness: Send + Sync {
+    /// Name of the condition, logged with the epochs it holds back
+    fn name(&self) -> &str;
+    /// Whether the condition lets `local` be deleted
+    async fn is_ready(&self, local: &LocalCheckpoint) -> Result<bool>;
+}
+
+/// Holds once `marker` is written in the state directory of the db checkpoint
+pub struct MarkerReadiness {
+    marker: String,
+    state_store: Arc<DynObjectStore>,
+}
+
+impl MarkerReadiness {
+    pub fn new(marker: impl Into<String>, state_store: Arc<DynObjectStore>) -> Self {
+        MarkerReadiness {
+            marker: marker.into(),
+            state_store,
+        }
+    }
+}
+
+#[async_trait]
+impl GcReadiness for MarkerReadiness {
+    fn name(&self) -> &str {
+        &self.marker
+    }
+    async fn is_ready(&self, local: &LocalCheckpoint) -> Result<bool> {
+        match self
+            .state_store
+            .head(&local.state_dir.child(self.marker.as_str()))
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Holds once the replica wrote `marker`, or once the upload to the output store, marked by
+/// `primary_marker`, is older than `max_wait`, so that a replica which is down doesn't keep
+/// every later db checkpoint on local disk
+pub struct ReplicaReadiness {
+    replica: MarkerReadiness,
+    primary_marker: String,
+    max_wait: Duration,
+    state_store: Arc<DynObjectStore>,
+}
+
+impl ReplicaReadiness {
+    pub fn new(
+        marker: impl Into<String>,
+        primary_marker: impl Into<String>,
+        max_wait: Duration,
+        state_store: Arc<DynObjectStore>,
+    ) -> Self {
+        ReplicaReadiness {
+            replica: MarkerReadiness::new(marker, state_store.clone()),
+            primary_marker: primary_marker.into(),
+            max_wait,
+            state_store,
+        }
+    }
+}
+
+#[async_trait]
+impl GcReadiness for ReplicaReadiness {
+    fn name(&self) -> &str {
+        self.replica.name()
+    }
+    async fn is_ready(&self, local: &LocalCheckpoint) -> Result<bool> {
+        if self.replica.is_ready(local).await? {
+            return Ok(true);
+        }
+        let uploaded = match self
+            .state_store
+            .head(&local.state_dir.child(self.primary_marker.as_str()))
+            .await
+        {
+            Ok(meta) => meta.last_modified,
+            Err(object_store::Error::NotFound { .. }) => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        let waited = (chrono::Utc::now() - uploaded).to_std().unwrap_or_default();
+        if waited < self.max_wait {
+            return Ok(false);
+        }
+        warn!(
+            "Replica hasn't uploaded {} within {:?} of the output store, deleting it without {}",
+            local.state_dir,
+            self.max_wait,
+            self.replica.name()
+        );
+        Ok(true)
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Guard against filling up the disk with db checkpoints taken at epoch end while earlier ones
+//! are still waiting for upload, e.g. when the remote store is unreachable for a while. Keeping
+//! the node alive takes precedence over having more than one pending backup.
+
+use crate::db_checkpoint_handler::UPLOAD_COMPLETED_MARKER;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use sui_storage::object_store::util::parse_epoch_dir;
+
+/// Epochs of the db checkpoints under `checkpoint_path` which aren't uploaded yet
+pub fn pending_uploads(checkpoint_path: &Path) -> Result<Vec<u64>> {
+    let mut pending = vec![];
+    for entry in fs::read_dir(checkpoint_path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name();
+        let Some(epoch) = name.to_str().and_then(parse_epoch_dir).map(u64::from) else {
+            continue;
+        };
+        if !entry.path().join(UPLOAD_COMPLETED_MARKER).exists() {
+            pending.push(epoch);
+        }
+    }
+    pending.sort_unstable();
+    Ok(pending)
+}
+
+/// Space available to unprivileged users on the filesystem holding `path`, in bytes
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // The field types differ across platforms
+    #[allow(clippy::unnecessary_cast)]
+    let available = stat.f_bavail as u64 * stat.f_frsize as u64;
+    Ok(available)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Result<u64> {
+    Err(anyhow::anyhow!(
+        "Free disk space is only available on unix platforms"
+    ))
+}
+
+/// Why a new db checkpoint must not be taken under `checkpoint_path`, `None` if it can be.
+/// It is skipped when earlier db checkpoints are pending upload and less than
+/// `min_free_bytes` are free on the disk.
+pub fn skip_db_checkpoint_reason(
+    checkpoint_path: &Path,
+    min_free_bytes: u64,
+) -> Result<Option<String>> {
+    if !checkpoint_path.exists() {
+        return Ok(None);
+    }
+    let pending = pending_uploads(checkpoint_path)?;
+    if pending.is_empty() {
+        return Ok(None);
+    }
+    let available = available_space(checkpoint_path)?;
+    if available >= min_free_bytes {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "only {available} bytes are free on disk, less than {min_free_bytes}, while db checkpoints of epochs {:?} are not uploaded yet",
+        pending
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pending_uploads, skip_db_checkpoint_reason};
+    use crate::db_checkpoint_handler::UPLOAD_COMPLETED_MARKER;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_skip_db_checkpoint_reason() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_path = checkpoint_dir.path();
+        assert_eq!(skip_db_checkpoint_reason(checkpoint_path, u64::MAX)?, None);
+
+        fs::create_dir(checkpoint_path.join("epoch_1"))?;
+        fs::write(
+            checkpoint_path
+                .join("epoch_1")
+                .join(UPLOAD_COMPLETED_MARKER),
+            b"",
+        )?;
+        fs::create_dir(checkpoint_path.join("epoch_2.tmp"))?;
+        // Nothing pending, the disk space doesn't matter
+        assert_eq!(pending_uploads(checkpoint_path)?, Vec::<u64>::new());
+        assert_eq!(skip_db_checkpoint_reason(checkpoint_path, u64::MAX)?, None);
+
+        fs::create_dir(checkpoint_path.join("epoch_3"))?;
+        fs::create_dir(checkpoint_path.join("epoch_2"))?;
+        assert_eq!(pending_uploads(checkpoint_path)?, vec![2, 3]);
+        assert_eq!(skip_db_checkpoint_reason(checkpoint_path, 0)?, None);
+        assert!(skip_db_checkpoint_reason(checkpoint_path, u64::MAX)?.is_some());
+        Ok(())
+    }
+}
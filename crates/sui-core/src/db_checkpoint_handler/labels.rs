@@ -0,0 +1,158 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Chain and protocol version a db checkpoint was taken on, recorded in the epoch manifest so
+//! that a node refuses to restore the backup of another network, e.g. a testnet backup into a
+//! mainnet deployment.
+
+use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+use crate::authority::epoch_start_configuration::EpochStartConfigTrait;
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use sui_protocol_config::{Chain, ProtocolVersion};
+use sui_types::digests::ChainIdentifier;
+use sui_types::sui_system_state::epoch_start_sui_system_state::EpochStartSystemStateTrait;
+use typed_store::rocks::MetricConf;
+use typed_store::traits::Map;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct CheckpointLabels {
+    /// Digest of the genesis checkpoint of the chain
+    pub chain_identifier: ChainIdentifier,
+    /// Protocol version of the epoch, unknown for read-only snapshot sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u64>,
+}
+
+impl CheckpointLabels {
+    /// Name of the chain, `mainnet`, `testnet` or the genesis checkpoint digest otherwise
+    pub fn chain_name(&self) -> String {
+        match self.chain_identifier.chain() {
+            Chain::Mainnet => "mainnet".to_string(),
+            Chain::Testnet => "testnet".to_string(),
+            Chain::Unknown => format!("custom genesis {}", self.chain_identifier),
+        }
+    }
+    /// Checks that a node of the chain `chain_identifier` running this binary can serve the
+    /// db checkpoint
+    pub fn verify(&self, chain_identifier: &ChainIdentifier) -> Result<()> {
+        if self.chain_identifier != *chain_identifier {
+            let node_chain = CheckpointLabels {
+                chain_identifier: *chain_identifier,
+                protocol_version: None,
+            };
+            return Err(anyhow!(
+                "Db checkpoint was taken on {} but the node runs on {}",
+                self.chain_name(),
+                node_chain.chain_name()
+            ));
+        }
+        if let Some(protocol_version) = self.protocol_version {
+            if protocol_version > ProtocolVersion::MAX.as_u64() {
+                return Err(anyhow!(
+                    "Db checkpoint is at protocol version {protocol_version}, this binary only supports up to {}",
+                    ProtocolVersion::MAX.as_u64()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Protocol version of the epoch of the db checkpoint at `db_path`, `None` if it doesn't hold
+/// the perpetual db. The db is opened as a secondary instance kept outside of `db_path`, which
+/// leaves the files of the db checkpoint untouched.
+pub fn read_protocol_version(db_path: &Path) -> Result<Option<u64>> {
+    let perpetual_path = AuthorityPerpetualTables::path(&db_path.join("store"));
+    if !perpetual_path.exists() {
+        return Ok(None);
+    }
+    let secondary_dir = tempfile::tempdir()?;
+    let perpetual_db = AuthorityPerpetualTables::get_read_only_handle(
+        perpetual_path,
+        Some(secondary_dir.path().to_path_buf()),
+        None,
+        MetricConf::default(),
+    );
+    Ok(perpetual_db
+        .epoch_start_configuration
+        .get(&())?
+        .map(|configuration| {
+            configuration
+                .epoch_start_state()
+                .protocol_version()
+                .as_u64()
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_protocol_version, CheckpointLabels};
+    use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use sui_protocol_config::ProtocolVersion;
+    use sui_types::digests::{
+        get_mainnet_chain_identifier, get_testnet_chain_identifier, ChainIdentifier,
+    };
+    use sui_types::messages_checkpoint::CheckpointDigest;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_protocol_version_leaves_db_untouched() -> anyhow::Result<()> {
+        let db_checkpoint = TempDir::new()?;
+        assert_eq!(read_protocol_version(db_checkpoint.path())?, None);
+        let store_path = db_checkpoint.path().join("store");
+        // Dropping the runtime stops the metrics tasks that keep the db open
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async { AuthorityPerpetualTables::open(&store_path, None) });
+        drop(runtime);
+        let perpetual_path = AuthorityPerpetualTables::path(&store_path);
+        let files = || -> anyhow::Result<BTreeMap<_, _>> {
+            fs::read_dir(&perpetual_path)?
+                .map(|entry| {
+                    let entry = entry?;
+                    Ok((entry.file_name(), entry.metadata()?.modified()?))
+                })
+                .collect()
+        };
+        let before = files()?;
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        let protocol_version =
+            runtime.block_on(async { read_protocol_version(db_checkpoint.path()) })?;
+        drop(runtime);
+        // No epoch was started
+        assert_eq!(protocol_version, None);
+        assert_eq!(files()?, before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_labels() {
+        let mainnet = get_mainnet_chain_identifier();
+        let testnet = CheckpointLabels {
+            chain_identifier: get_testnet_chain_identifier(),
+            protocol_version: Some(1),
+        };
+        assert!(testnet.verify(&get_testnet_chain_identifier()).is_ok());
+        let err = testnet.verify(&mainnet).unwrap_err().to_string();
+        assert!(err.contains("taken on testnet but the node runs on mainnet"));
+
+        let custom = CheckpointLabels {
+            chain_identifier: ChainIdentifier::from(CheckpointDigest::random()),
+            protocol_version: None,
+        };
+        assert!(custom.chain_name().starts_with("custom genesis"));
+        assert!(custom.verify(&mainnet).is_err());
+        assert!(custom.verify(&custom.chain_identifier).is_ok());
+
+        let future = CheckpointLabels {
+            chain_identifier: mainnet,
+            protocol_version: Some(ProtocolVersion::MAX.as_u64() + 1),
+        };
+        assert!(future.verify(&mainnet).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Listing of the contents of an uploaded epoch read from its manifest alone, so that operators
+//! can inspect a backup without downloading any of its files.
+
+use crate::db_checkpoint_handler::manifest::{read_published_manifest, BackupProducer, FileEntry};
+use crate::db_checkpoint_handler::source::remote_epoch_dir;
+use anyhow::{anyhow, Result};
+use object_store::DynObjectStore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use sui_config::node::DBCheckpointUploadLayout;
+
+const INDENT: &str = "  ";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EpochListing {
+    pub epoch: u32,
+    pub num_files: usize,
+    /// Total size of the listed files
+    pub total_size: usize,
+    /// Total size of the listed files in the remote store, when compressed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<usize>,
+    /// Node which uploaded the epoch, if recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub producer: Option<BackupProducer>,
+    /// Listed files in path order
+    pub files: Vec<FileEntry>,
+}
+
+/// Lists the files of `epoch` in `store` from its manifest, once published, only those under
+/// the directory `prefix` of the epoch when set, e.g. `store/perpetual`
+pub async fn list_epoch(
+    store: Arc<DynObjectStore>,
+    epoch: u32,
+    prefix: Option<&str>,
+) -> Result<EpochListing> {
+    let manifest = read_published_manifest(&remote_epoch_dir(epoch), store)
+        .await?
+        .ok_or_else(|| anyhow!("Epoch {epoch} is not complete in the remote store"))?;
+    let prefix = prefix.map(|prefix| format!("{}/", prefix.trim_matches('/')));
+    let mut files: Vec<_> = manifest
+        .files
+        .into_iter()
+        .filter(|file| {
+            prefix
+                .as_ref()
+                .map_or(true, |prefix| file.path.starts_with(prefix.as_str()))
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    // Archives are compressed whatever the compression of the manifest
+    let compressed =
+        manifest.compression.is_some() || manifest.layout == DBCheckpointUploadLayout::TarArchives;
+    let compressed_size = compressed.then(|| {
+        files
+            .iter()
+            .map(|file| file.compressed_size.unwrap_or(file.size))
+            .sum()
+    });
+    Ok(EpochListing {
+        epoch,
+        num_files: files.len(),
+        total_size: files.iter().map(|file| file.size).sum(),
+        compressed_size,
+        producer: manifest.producer,
+        files,
+    })
+}
+
+impl EpochListing {
+    /// The files as an indented tree, directories with the number and total size of the files
+    /// under them, files with their size and sha3 digest
+    pub fn tree(&self) -> String {
+        let mut dirs: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for file in self.files.iter() {
+            for (index, _) in file.path.match_indices('/') {
+                let (num_files, size) = dirs.entry(&file.path[..index]).or_default();
+                *num_files += 1;
+                *size += file.size;
+            }
+        }
+        let mut tree = format!(
+            "epoch_{}: {} files, {} bytes",
+            self.epoch, self.num_files, self.total_size
+        );
+        if let Some(compressed_size) = self.compressed_size {
+            write!(tree, ", {compressed_size} bytes compressed").unwrap();
+        }
+        tree.push('\n');
+        if let Some(producer) = &self.producer {
+            writeln!(tree, "{INDENT}uploaded by {producer}").unwrap();
+        }
+        let mut open_dirs: Vec<&str> = vec![];
+        for file in self.files.iter() {
+            let components: Vec<&str> = file.path.split('/').collect();
+            let (name, parents) = components.split_last().unwrap();
+            let common = open_dirs
+                .iter()
+                .zip(parents.iter())
+                .take_while(|(open, parent)| open == parent)
+                .count();
+            open_dirs.truncate(common);
+            for (depth, dir) in parents.iter().enumerate().skip(common) {
+                let (num_files, size) = dirs[parents[..=depth].join("/").as_str()];
+                writeln!(
+                    tree,
+                    "{}{dir}/  {num_files} files, {size} bytes",
+                    INDENT.repeat(depth + 1)
+                )
+                .unwrap();
+                open_dirs.push(dir);
+            }
+            writeln!(
+                tree,
+                "{}{name}  {} bytes  sha3:{}",
+                INDENT.repeat(parents.len() + 1),
+                file.size,
+                file.sha3_digest
+            )
+            .unwrap();
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::list_epoch;
+    use crate::db_checkpoint_handler::manifest::{write_manifest, BackupProducer, EpochManifest};
+    use crate::db_checkpoint_handler::source::remote_epoch_dir;
+    use crate::db_checkpoint_handler::SUCCESS_MARKER;
+    use std::fs;
+    use sui_config::node::DBCheckpointUploadLayout;
+    use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_list_epoch() -> anyhow::Result<()> {
+        let remote_dir = TempDir::new()?;
+        let epoch_dir = remote_dir.path().join("epoch_3");
+        fs::create_dir_all(epoch_dir.join("store").join("perpetual"))?;
+        fs::write(epoch_dir.join("store/perpetual/000001.sst"), b"Lorem ipsum")?;
+        fs::write(epoch_dir.join("store/perpetual/CURRENT"), b"dolor")?;
+        fs::write(epoch_dir.join("checkpoints.log"), b"sit")?;
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_dir.path().to_path_buf()),
+            ..Default::default()
+        }
+        .make()?;
+        let remote_epoch = remote_epoch_dir(3);
+        let mut manifest = EpochManifest::from_local_dir(
+            3,
+            &remote_epoch,
+            &remote_epoch,
+            store.clone(),
+            DBCheckpointUploadLayout::Mirrored,
+            &[],
+        )
+        .await?;
+        for file in manifest.files.iter_mut() {
+            file.sha3_digest = format!("{:x}", file.size);
+        }
+        manifest.producer = Some(BackupProducer {
+            name: Some("node-a".to_string()),
+            peer_id: "00ff".to_string(),
+        });
+        write_manifest(&manifest, &remote_epoch, store.clone()).await?;
+        // Not listed while the epoch is copied, before its success marker is written
+        assert!(list_epoch(store.clone(), 3, None).await.is_err());
+        fs::write(epoch_dir.join(SUCCESS_MARKER), b"success")?;
+
+        let listing = list_epoch(store.clone(), 3, None).await?;
+        assert_eq!(listing.num_files, 3);
+        assert_eq!(listing.total_size, 19);
+        assert_eq!(listing.compressed_size, None);
+        assert_eq!(
+            listing.tree(),
+            "epoch_3: 3 files, 19 bytes
+  uploaded by node-a (peer id 00ff)
+  checkpoints.log  3 bytes  sha3:3
+  store/  2 files, 16 bytes
+    perpetual/  2 files, 16 bytes
+      000001.sst  11 bytes  sha3:b
+      CURRENT  5 bytes  sha3:5
+"
+        );
+
+        let listing = list_epoch(store.clone(), 3, Some("store/perpetual/")).await?;
+        assert_eq!(
+            listing
+                .files
+                .iter()
+                .map(|file| file.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["store/perpetual/000001.sst", "store/perpetual/CURRENT"]
+        );
+        assert!(list_epoch(store, 4, None).await.is_err());
+        Ok(())
+    }
+}
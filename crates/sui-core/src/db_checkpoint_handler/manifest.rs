@@ -0,0 +1,1068 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::db_checkpoint_handler::audit::AuditRecord;
+use crate::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use crate::db_checkpoint_handler::chunking::{download_chunks, ChunkEntry};
+use crate::db_checkpoint_handler::digest_pool::DigestPool;
+use crate::db_checkpoint_handler::encryption::{EncryptionKey, ENCRYPTED_SUFFIX};
+use crate::db_checkpoint_handler::expectations::RestoreExpectations;
+use crate::db_checkpoint_handler::expected::is_excluded_file;
+use crate::db_checkpoint_handler::labels::CheckpointLabels;
+use crate::db_checkpoint_handler::migration::PERPETUAL_SCHEMA_VERSION;
+use crate::db_checkpoint_handler::SUCCESS_MARKER;
+use anyhow::{anyhow, Context, Result};
+use bytes::{Buf, Bytes};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha256, Sha3_256};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use sui_config::node::{DBCheckpointCompression, DBCheckpointUploadLayout};
+use sui_config::NodeConfig;
+use sui_storage::object_store::decompressing::ZSTD_SUFFIX;
+use sui_storage::object_store::util::{path_to_filesystem, put};
+use sui_storage::{compute_sha3_checksum, FileCompression};
+use tokio::io::AsyncWrite;
+
+pub const MANIFEST_FILENAME: &str = "MANIFEST";
+/// Number of files above which the file list of a manifest is split into shards, when not
+/// configured
+pub const DEFAULT_MANIFEST_SHARD_SIZE: usize = 100_000;
+/// Local copy of the manifest recorded right after pruning and compaction, so that uploads
+/// verify file contents against the digests taken before they sat on disk between phases
+pub const LOCAL_CHECKSUMS_FILENAME: &str = "_CHECKSUMS";
+/// Progress of an upload interrupted by the epoch upload deadline, kept next to the local
+/// checksums so that the next attempt resumes after the files already uploaded
+pub const UPLOAD_PROGRESS_FILENAME: &str = "_UPLOAD_PROGRESS";
+/// Per epoch checksums in `sha256sum` format, written with the `MirroredWithSums` layout
+pub const SUMS_FILENAME: &str = "SHA256.sum";
+/// Size of the contents of the frames of files compressed with
+/// `DBCheckpointCompression::ZstdSeekable`, the last frame of a file holding the rest
+pub const SEEKABLE_FRAME_SIZE: usize = 4 << 20;
+/// Size of the reads of `copy_verified`
+const COPY_BUFFER_SIZE: usize = 1 << 20;
+
+/// A single file of an epoch db checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct FileEntry {
+    /// Path of the file relative to the epoch directory, e.g. `store/perpetual/000123.sst`
+    pub path: String,
+    /// Full key of the file in the remote store, empty when the file is stored as chunks. The
+    /// key of the archive holding the file with the `TarArchives` layout, unless the file is
+    /// larger than an archive.
+    pub remote_path: String,
+    /// Size of the file in bytes
+    pub size: usize,
+    /// Hex encoded sha3 digest of the file contents. Empty in the local checksums when
+    /// digests are taken from the upload read, see `DBCheckpointConfig::single_pass_digests`.
+    /// Sealed with the encryption key in the manifests of encrypted epochs, see
+    /// `EncryptionKey::seal_digest`.
+    pub sha3_digest: String,
+    /// Chunks making up the file, in order, when uploaded with content defined chunking
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ChunkEntry>,
+    /// Hex encoded sha256 digest of the uploaded contents, when uploaded with the
+    /// `MirroredWithSums` layout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256_digest: Option<String>,
+    /// Size of the file in the remote store, when compressed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<usize>,
+    /// Frames of the file in the remote store, in order, when compressed with
+    /// `DBCheckpointCompression::ZstdSeekable`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<CompressedFrame>,
+    /// Offset in the archive of `remote_path` of the zstd frame of `compressed_size` bytes
+    /// holding the tar member of the file, when packed into an archive with the `TarArchives`
+    /// layout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_offset: Option<usize>,
+}
+
+impl FileEntry {
+    /// Seals the digest of the file with `key`, see `EncryptionKey::seal_digest`
+    pub fn seal_digest(&mut self, key: &EncryptionKey) -> Result<()> {
+        self.sha3_digest = key.seal_digest(&self.path, &self.sha3_digest)?;
+        Ok(())
+    }
+    /// Opens the digest of the file sealed with `key`, a digest which isn't sealed is kept
+    pub fn open_digest(&mut self, key: &EncryptionKey) -> Result<()> {
+        self.sha3_digest = key.open_digest(&self.path, &self.sha3_digest)?;
+        Ok(())
+    }
+}
+
+/// Frame of a file compressed with `DBCheckpointCompression::ZstdSeekable`, which decompresses
+/// on its own
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct CompressedFrame {
+    /// Offset of the frame in the file in the remote store
+    pub offset: usize,
+    pub compressed_size: usize,
+    /// Size of the contents of the frame
+    pub size: usize,
+    /// Hex encoded sha3 digest of the contents of the frame
+    pub sha3_digest: String,
+}
+
+/// Per epoch MANIFEST which lists every file of the db checkpoint and where it lives in the
+/// remote store. The manifest is uploaded right before the success marker, so a present
+/// manifest always describes a fully uploaded epoch.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct EpochManifest {
+    pub epoch: u32,
+    pub layout: DBCheckpointUploadLayout,
+    pub files: Vec<FileEntry>,
+    /// Read results a node restored from this epoch must serve
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expectations: Option<RestoreExpectations>,
+    /// Bytes every table of the perpetual db shrank by in pruning and compaction before upload
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pruned_bytes_by_table: BTreeMap<String, i64>,
+    /// Chain and protocol version the db checkpoint was taken on, absent when uploaded by a
+    /// handler that didn't know the chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<CheckpointLabels>,
+    /// Storage schema version of the perpetual db, absent when uploaded by a version which
+    /// didn't record it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// Shards holding the file list of an epoch with more files than fit in one manifest, in
+    /// order. The MANIFEST is then an index with no files of its own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shards: Vec<ManifestShard>,
+    /// Compression of the files in the remote store, absent when they are stored as they are
+    /// on local disk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<DBCheckpointCompression>,
+    /// Id of the key the files are encrypted with in the remote store, absent when they are not
+    /// encrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_key_id: Option<String>,
+    /// Total size of the files of the epoch in the remote store, when compressed. Compared to
+    /// the total size of the files it gives the compression ratio of the epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<usize>,
+    /// Node which uploaded the epoch, absent when uploaded by a version which didn't record it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub producer: Option<BackupProducer>,
+}
+
+/// Identity of the node uploading db checkpoints, so that the epochs of a bucket shared by
+/// several nodes can be traced back to the machine which produced them
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct BackupProducer {
+    /// `producer-name` of the db checkpoint config of the node, if set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Hex encoded network peer id of the node
+    pub peer_id: String,
+}
+
+impl BackupProducer {
+    pub fn from_node_config(config: &NodeConfig) -> Self {
+        BackupProducer {
+            name: config.db_checkpoint_config.producer_name.clone(),
+            peer_id: Hex::encode(config.network_key_pair().public().0.to_bytes()),
+        }
+    }
+}
+
+impl fmt::Display for BackupProducer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name} (peer id {})", self.peer_id),
+            None => write!(f, "peer id {}", self.peer_id),
+        }
+    }
+}
+
+/// A part of the file list of a sharded manifest, stored next to the MANIFEST
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct ManifestShard {
+    /// Name of the shard in the epoch directory, e.g. `MANIFEST.00001-of-00003`
+    pub name: String,
+    pub num_files: usize,
+    /// Total size of the files of the shard
+    pub size: usize,
+    /// Paths of the first and last file of the shard, shards hold files in path order
+    pub first_path: String,
+    pub last_path: String,
+    /// Hex encoded sha3 digest of the shard
+    pub sha3_digest: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct UploadProgress {
+    /// Number of files of the manifest, in order, already uploaded
+    pub uploaded_files: usize,
+    /// Manifest with the digests and chunks of the uploaded files filled in
+    pub manifest: EpochManifest,
+}
+
+/// Metadata of an uploaded epoch written as the body of its success marker with
+/// `DBCheckpointConfig::consolidate_epoch_metadata`, in place of the MANIFEST and the audit
+/// record of the upload
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
+pub struct EpochMetadata {
+    pub manifest: EpochManifest,
+    /// Hex encoded sha3 digest of the manifest as it would be written to the MANIFEST
+    pub manifest_sha3_digest: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_record: Option<AuditRecord>,
+}
+
+impl EpochMetadata {
+    pub fn new(manifest: EpochManifest) -> Result<Self> {
+        let manifest_sha3_digest = sha3_hex(&serde_json::to_vec(&manifest)?);
+        Ok(EpochMetadata {
+            manifest,
+            manifest_sha3_digest,
+            audit_record: None,
+        })
+    }
+}
+
+impl EpochManifest {
+    /// Builds the manifest from the files present under `epoch_dir` in the local store, to be
+    /// uploaded under `remote_dir`. Files named in `excluded` (e.g. local-only markers) and
+    /// their copies for replicas are skipped. Digests are left empty until
+    /// `compute_sha3_digests` or the upload fills them.
+    pub async fn from_local_dir(
+        epoch: u32,
+        epoch_dir: &Path,
+        remote_dir: &Path,
+        local_store: Arc<DynObjectStore>,
+        layout: DBCheckpointUploadLayout,
+        excluded: &[&str],
+    ) -> Result<Self> {
+        let mut files = vec![];
+        let mut entries = local_store.list(Some(epoch_dir)).await?;
+        while let Some(entry) = entries.next().await {
+            let object_metadata = entry?;
+            if let Some(filename) = object_metadata.location.filename() {
+                if is_excluded_file(excluded, filename) {
+                    continue;
+                }
+            }
+            let path = relative_path(epoch_dir, &object_metadata.location)?;
+            let remote_path = remote_path(layout, remote_dir, &path)
+                .map(|p| p.to_string())
+                .unwrap_or_default();
+            files.push(FileEntry {
+                path,
+                remote_path,
+                size: object_metadata.size,
+                sha3_digest: String::new(),
+                chunks: vec![],
+                sha256_digest: None,
+                compressed_size: None,
+                frames: vec![],
+                archive_offset: None,
+            });
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(EpochManifest {
+            epoch,
+            layout,
+            files,
+            expectations: None,
+            pruned_bytes_by_table: BTreeMap::new(),
+            labels: None,
+            schema_version: Some(PERPETUAL_SCHEMA_VERSION),
+            shards: vec![],
+            compression: None,
+            encryption_key_id: None,
+            compressed_size: None,
+            producer: None,
+        })
+    }
+    /// Reads every file under `epoch_dir` in the local store rooted at `local_root` to record
+    /// its sha3 digest, on the threads of `pool`
+    pub async fn compute_sha3_digests(
+        &mut self,
+        epoch_dir: &Path,
+        local_root: &std::path::Path,
+        pool: &DigestPool,
+    ) -> Result<()> {
+        for file in self.files.iter_mut() {
+            let fs_path = path_to_filesystem(
+                local_root.to_path_buf(),
+                &logical_path(epoch_dir, &file.path),
+            )?;
+            let sha3_digest = pool.run(move || compute_sha3_checksum(&fs_path)).await??;
+            file.sha3_digest = Hex::encode(sha3_digest);
+        }
+        Ok(())
+    }
+    pub fn total_size(&self) -> usize {
+        self.files.iter().map(|f| f.size).sum::<usize>()
+            + self.shards.iter().map(|s| s.size).sum::<usize>()
+    }
+    pub fn num_files(&self) -> usize {
+        self.files.len() + self.shards.iter().map(|s| s.num_files).sum::<usize>()
+    }
+    /// Size of the files of the epoch divided by their compressed size, `None` when the files
+    /// are not compressed
+    pub fn compression_ratio(&self) -> Option<f64> {
+        self.compressed_size
+            .map(|compressed_size| self.total_size() as f64 / compressed_size.max(1) as f64)
+    }
+    pub fn file(&self, path: &str) -> Option<&FileEntry> {
+        self.files.iter().find(|f| f.path == path)
+    }
+    /// Re-targets the manifest at `layout`, keeping the recorded digests
+    pub fn with_layout(mut self, layout: DBCheckpointUploadLayout, epoch_dir: &Path) -> Self {
+        for file in self.files.iter_mut() {
+            file.remote_path = remote_path(layout, epoch_dir, &file.path)
+                .map(|p| p.to_string())
+                .unwrap_or_default();
+            file.chunks = vec![];
+            file.sha256_digest = None;
+            file.compressed_size = None;
+            file.frames = vec![];
+            file.archive_offset = None;
+        }
+        self.layout = layout;
+        self.compression = None;
+        self.encryption_key_id = None;
+        self.compressed_size = None;
+        self
+    }
+    /// Sets the compression of the files to upload, suffixing their remote keys to match.
+    /// Files stored as chunks are never compressed.
+    pub fn with_compression(mut self, compression: DBCheckpointCompression) -> Self {
+        if compression == DBCheckpointCompression::None {
+            return self;
+        }
+        for file in self.files.iter_mut() {
+            if !file.remote_path.is_empty() {
+                file.remote_path.push_str(ZSTD_SUFFIX);
+            }
+        }
+        self.compression = Some(compression);
+        self
+    }
+    /// Seals the digests of the files with `key` before the manifest is published
+    pub fn seal_digests(&mut self, key: &EncryptionKey) -> Result<()> {
+        self.files
+            .iter_mut()
+            .try_for_each(|file| file.seal_digest(key))
+    }
+    /// Opens the digests of the files sealed with `key` by `seal_digests`
+    pub fn open_digests(&mut self, key: &EncryptionKey) -> Result<()> {
+        self.files
+            .iter_mut()
+            .try_for_each(|file| file.open_digest(key))
+    }
+    /// Sets the key the files to upload are encrypted with, suffixing their remote keys to
+    /// match. Must be applied after the compression.
+    pub fn with_encryption(mut self, key_id: Option<&str>) -> Self {
+        let Some(key_id) = key_id else {
+            return self;
+        };
+        for file in self.files.iter_mut() {
+            if !file.remote_path.is_empty() {
+                file.remote_path.push_str(ENCRYPTED_SUFFIX);
+            }
+        }
+        self.encryption_key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+/// Path in the store of a file given its path relative to the epoch directory
+pub fn logical_path(epoch_dir: &Path, path: &str) -> Path {
+    Path::from(format!("{}/{}", epoch_dir, path))
+}
+
+/// Key under which a file is stored remotely for the given layout, `None` if the file is
+/// stored as content defined chunks or in an archive instead
+pub fn remote_path(layout: DBCheckpointUploadLayout, epoch_dir: &Path, path: &str) -> Option<Path> {
+    match layout {
+        DBCheckpointUploadLayout::Mirrored | DBCheckpointUploadLayout::MirroredWithSums => {
+            Some(logical_path(epoch_dir, path))
+        }
+        DBCheckpointUploadLayout::HashedSharded => {
+            let shard = Sha3_256::digest(path.as_bytes()).digest[0];
+            Some(Path::from(format!("{}/{:02x}/{}", epoch_dir, shard, path)))
+        }
+        DBCheckpointUploadLayout::ContentDefinedChunks | DBCheckpointUploadLayout::TarArchives => {
+            None
+        }
+    }
+}
+
+fn relative_path(epoch_dir: &Path, location: &Path) -> Result<String> {
+    let parts: Vec<String> = location
+        .prefix_match(epoch_dir)
+        .ok_or_else(|| anyhow!("{location} is not under {epoch_dir}"))?
+        .map(|part| part.as_ref().to_string())
+        .collect();
+    Ok(parts.join("/"))
+}
+
+/// Contents of a file as stored in the remote store with `compression`
+pub fn compress_file(compression: DBCheckpointCompression, bytes: Bytes) -> Result<Bytes> {
+    match compression {
+        DBCheckpointCompression::None => Ok(bytes),
+        DBCheckpointCompression::Zstd => {
+            let mut compressed = vec![];
+            FileCompression::zstd_compress(&mut bytes.reader(), &mut compressed)?;
+            Ok(Bytes::from(compressed))
+        }
+        DBCheckpointCompression::ZstdSeekable => {
+            compress_file_seekable(bytes).map(|(compressed, _)| compressed)
+        }
+    }
+}
+
+/// Contents of a file compressed as independent zstd frames of `SEEKABLE_FRAME_SIZE` bytes,
+/// along with the frames. Concatenated frames are a valid zstd stream, so the file also
+/// decompresses as a whole.
+pub fn compress_file_seekable(bytes: Bytes) -> Result<(Bytes, Vec<CompressedFrame>)> {
+    let mut compressed = vec![];
+    let mut frames = vec![];
+    for contents in bytes.chunks(SEEKABLE_FRAME_SIZE) {
+        let offset = compressed.len();
+        FileCompression::zstd_compress(&mut &contents[..], &mut compressed)?;
+        frames.push(CompressedFrame {
+            offset,
+            compressed_size: compressed.len() - offset,
+            size: contents.len(),
+            sha3_digest: sha3_hex(contents),
+        });
+    }
+    Ok((Bytes::from(compressed), frames))
+}
+
+/// Contents of a file of `size` bytes read from the remote store, stored there with
+/// `compression`. Decompression stops a byte past `size`, so that a corrupt or malicious file
+/// can't expand in memory beyond the size recorded in the manifest.
+pub fn decompress_file(
+    compression: Option<DBCheckpointCompression>,
+    bytes: Bytes,
+    size: usize,
+) -> Result<Bytes> {
+    match compression.unwrap_or_default() {
+        DBCheckpointCompression::None => Ok(bytes),
+        DBCheckpointCompression::Zstd | DBCheckpointCompression::ZstdSeekable => {
+            let mut contents = vec![];
+            FileCompression::Zstd
+                .bytes_decompress(bytes)?
+                .take(size as u64 + 1)
+                .read_to_end(&mut contents)?;
+            if contents.len() > size {
+                return Err(anyhow!(
+                    "Decompressed contents larger than the {size} bytes expected"
+                ));
+            }
+            Ok(Bytes::from(contents))
+        }
+    }
+}
+
+/// Reads `range` of the contents of `file` from `store`. Only the frames overlapping the range
+/// are downloaded when the file is compressed with `DBCheckpointCompression::ZstdSeekable`, and
+/// they are verified against their recorded digests. Files stored uncompressed are read as they
+/// are, since their digest covers the whole file.
+pub async fn read_file_range(
+    store: Arc<DynObjectStore>,
+    file: &FileEntry,
+    range: Range<usize>,
+) -> Result<Bytes> {
+    if range.start > range.end || range.end > file.size {
+        return Err(anyhow!(
+            "Range {range:?} out of bounds of {} of {} bytes",
+            file.path,
+            file.size
+        ));
+    }
+    let remote_path = Path::from(file.remote_path.as_str());
+    if file.frames.is_empty() {
+        if file.compressed_size.is_some() || file.remote_path.is_empty() {
+            return Err(anyhow!("{} is not stored in seekable frames", file.path));
+        }
+        return Ok(store.get_range(&remote_path, range).await?);
+    }
+    // Frames overlapping the range, with the offset of their contents in the file
+    let mut overlapping = vec![];
+    let mut frame_start = 0;
+    for frame in file.frames.iter() {
+        let frame_end = frame_start + frame.size;
+        if frame_start < range.end && range.start < frame_end {
+            overlapping.push((frame_start, frame));
+        }
+        frame_start = frame_end;
+    }
+    let ranges: Vec<Range<usize>> = overlapping
+        .iter()
+        .map(|(_, frame)| frame.offset..frame.offset + frame.compressed_size)
+        .collect();
+    let compressed = store.get_ranges(&remote_path, &ranges).await?;
+    let overlapping: Vec<(usize, CompressedFrame)> = overlapping
+        .into_iter()
+        .map(|(frame_start, frame)| (frame_start, frame.clone()))
+        .collect();
+    let path = file.path.clone();
+    // Frames are decompressed and hashed on a blocking thread, they hold megabytes each
+    tokio::task::spawn_blocking(move || {
+        let mut contents = vec![];
+        for ((frame_start, frame), bytes) in overlapping.into_iter().zip(compressed) {
+            let mut decompressed = vec![];
+            FileCompression::Zstd
+                .bytes_decompress(bytes)?
+                .take(frame.size as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .with_context(|| {
+                    format!("Failed to decompress frame at offset {frame_start} of {path}")
+                })?;
+            let sha3_digest = sha3_hex(&decompressed);
+            if decompressed.len() != frame.size || sha3_digest != frame.sha3_digest {
+                return Err(anyhow!(
+                    "Checksum mismatch for frame at offset {frame_start} of {path}, expected: {}, actual: {}",
+                    frame.sha3_digest,
+                    sha3_digest
+                ));
+            }
+            let start = range.start.saturating_sub(frame_start);
+            let end = (range.end - frame_start).min(frame.size);
+            contents.extend_from_slice(&decompressed[start..end]);
+        }
+        Ok(Bytes::from(contents))
+    })
+    .await?
+}
+
+/// Copies the contents of `file` from `reader` to `writer` a buffer at a time, verifying them
+/// against the size and digest recorded for `file`. Fails as soon as more bytes than recorded
+/// are read, so that a corrupted copy can't fill the disk.
+pub fn copy_verified(
+    file: &FileEntry,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let mut hasher = Sha3_256::default();
+    let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+    let mut size = 0;
+    loop {
+        let read = reader
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read contents of {}", file.path))?;
+        if read == 0 {
+            break;
+        }
+        size += read;
+        if size > file.size {
+            return Err(anyhow!(
+                "Size mismatch for {}, expected: {}, actual: more",
+                file.path,
+                file.size
+            ));
+        }
+        hasher.update(&buf[..read]);
+        writer.write_all(&buf[..read])?;
+    }
+    if size != file.size {
+        return Err(anyhow!(
+            "Size mismatch for {}, expected: {}, actual: {}",
+            file.path,
+            file.size,
+            size
+        ));
+    }
+    let sha3_digest = Hex::encode(hasher.finalize().digest);
+    if sha3_digest != file.sha3_digest {
+        return Err(anyhow!(
+            "Checksum mismatch for {}, expected: {}, actual: {}",
+            file.path,
+            file.sha3_digest,
+            sha3_digest
+        ));
+    }
+    Ok(())
+}
+
+/// Verifies that `bytes` match the size and digest recorded for `file`
+pub fn verify_file_contents(file: &FileEntry, bytes: &[u8]) -> Result<()> {
+    if bytes.len() != file.size {
+        return Err(anyhow!(
+            "Size mismatch for {}, expected: {}, actual: {}",
+            file.path,
+            file.size,
+            bytes.len()
+        ));
+    }
+    let sha3_digest = sha3_hex(bytes);
+    if sha3_digest != file.sha3_digest {
+        return Err(anyhow!(
+            "Checksum mismatch for {}, expected: {}, actual: {}",
+            file.path,
+            file.sha3_digest,
+            sha3_digest
+        ));
+    }
+    Ok(())
+}
+
+/// Verifies `file` as stored in `store` with `compression` like `verify_file_contents`, but
+/// decompresses and hashes its contents as they are downloaded instead of holding the whole
+/// file in memory. Not for archive members or encrypted files, which are only readable whole.
+pub async fn stream_verify_file(
+    file: &FileEntry,
+    compression: Option<DBCheckpointCompression>,
+    store: Arc<DynObjectStore>,
+) -> Result<()> {
+    let mut stream = store
+        .get(&Path::from(file.remote_path.as_str()))
+        .await?
+        .into_stream();
+    let mut contents = SizedSha3Writer::new(file.size);
+    match compression.unwrap_or_default() {
+        DBCheckpointCompression::None => {
+            while let Some(bytes) = stream.next().await {
+                contents.write_all(&bytes?)?;
+            }
+        }
+        DBCheckpointCompression::Zstd | DBCheckpointCompression::ZstdSeekable => {
+            let mut decoder = zstd::stream::write::Decoder::new(&mut contents)?;
+            while let Some(bytes) = stream.next().await {
+                decoder.write_all(&bytes?)?;
+            }
+            decoder.flush()?;
+        }
+    }
+    contents.verify(file)
+}
+
+/// Verifies a file stored as content defined chunks in `store` like `stream_verify_file`,
+/// reassembling it from its chunks one at a time
+pub async fn stream_verify_chunked_file(
+    file: &FileEntry,
+    store: Arc<DynObjectStore>,
+    bandwidth_limiter: &BandwidthLimiter,
+) -> Result<()> {
+    let mut contents = SizedSha3Writer::new(file.size);
+    download_chunks(&file.chunks, store, bandwidth_limiter, &mut contents).await?;
+    contents.verify(file)
+}
+
+/// Hashes the contents written to it, refusing more than `limit` bytes so that a corrupt or
+/// malicious compressed file can't keep the decompression going
+struct SizedSha3Writer {
+    hasher: Sha3_256,
+    size: usize,
+    limit: usize,
+}
+
+impl SizedSha3Writer {
+    fn new(limit: usize) -> Self {
+        SizedSha3Writer {
+            hasher: Sha3_256::default(),
+            size: 0,
+            limit,
+        }
+    }
+    /// Checks the contents written against the size and checksum of `file`
+    fn verify(self, file: &FileEntry) -> Result<()> {
+        if self.size != file.size {
+            return Err(anyhow!(
+                "Size mismatch for {}, expected: {}, actual: {}",
+                file.path,
+                file.size,
+                self.size
+            ));
+        }
+        let sha3_digest = Hex::encode(self.hasher.finalize().digest);
+        if sha3_digest != file.sha3_digest {
+            return Err(anyhow!(
+                "Checksum mismatch for {}, expected: {}, actual: {}",
+                file.path,
+                file.sha3_digest,
+                sha3_digest
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Write for SizedSha3Writer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.size + buf.len() > self.limit {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Decompressed contents larger than the {} bytes expected",
+                    self.limit
+                ),
+            ));
+        }
+        self.hasher.update(buf);
+        self.size += buf.len();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for SizedSha3Writer {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub async fn write_manifest(
+    manifest: &EpochManifest,
+    epoch_dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<()> {
+    let bytes = Bytes::from(serde_json::to_vec(manifest)?);
+    put(&epoch_dir.child(MANIFEST_FILENAME), bytes, store).await?;
+    Ok(())
+}
+
+/// Writes the file list of `manifest` as shards of `shard_size` files into `epoch_dir` when it
+/// holds more files than that, and returns the index to write as the MANIFEST in their place.
+/// Returns the manifest unchanged otherwise.
+pub async fn write_manifest_shards(
+    manifest: &EpochManifest,
+    epoch_dir: &Path,
+    shard_size: NonZeroUsize,
+    store: Arc<DynObjectStore>,
+) -> Result<EpochManifest> {
+    if manifest.files.len() <= shard_size.get() {
+        return Ok(manifest.clone());
+    }
+    let num_shards = (manifest.files.len() + shard_size.get() - 1) / shard_size.get();
+    let mut shards = vec![];
+    for (index, files) in manifest.files.chunks(shard_size.get()).enumerate() {
+        let name = format!("{MANIFEST_FILENAME}.{:05}-of-{:05}", index + 1, num_shards);
+        let bytes = serde_json::to_vec(files)?;
+        shards.push(ManifestShard {
+            name: name.clone(),
+            num_files: files.len(),
+            size: files.iter().map(|f| f.size).sum(),
+            first_path: files[0].path.clone(),
+            last_path: files[files.len() - 1].path.clone(),
+            sha3_digest: sha3_hex(&bytes),
+        });
+        put(
+            &epoch_dir.child(name.as_str()),
+            Bytes::from(bytes),
+            store.clone(),
+        )
+        .await?;
+    }
+    Ok(EpochManifest {
+        files: vec![],
+        shards,
+        ..manifest.clone()
+    })
+}
+
+/// Reads the files of `shard` of the manifest of `epoch_dir`, verified against the digest
+/// recorded in the index
+pub async fn read_manifest_shard(
+    epoch_dir: &Path,
+    shard: &ManifestShard,
+    store: Arc<DynObjectStore>,
+) -> Result<Vec<FileEntry>> {
+    let bytes = store
+        .get(&epoch_dir.child(shard.name.as_str()))
+        .await?
+        .bytes()
+        .await?;
+    let sha3_digest = sha3_hex(&bytes);
+    if sha3_digest != shard.sha3_digest {
+        return Err(anyhow!(
+            "Checksum mismatch for manifest shard {epoch_dir}/{}, expected: {}, actual: {}",
+            shard.name,
+            shard.sha3_digest,
+            sha3_digest
+        ));
+    }
+    let files: Vec<FileEntry> = serde_json::from_slice(&bytes)?;
+    if files.len() != shard.num_files {
+        return Err(anyhow!(
+            "Manifest shard {epoch_dir}/{} lists {} files instead of {}",
+            shard.name,
+            files.len(),
+            shard.num_files
+        ));
+    }
+    Ok(files)
+}
+
+/// Streams the files of the manifest `index` of `epoch_dir`, reading one shard at a time for
+/// sharded manifests
+pub fn manifest_files(
+    epoch_dir: &Path,
+    index: &EpochManifest,
+    store: Arc<DynObjectStore>,
+) -> BoxStream<'static, Result<FileEntry>> {
+    let files = futures::stream::iter(index.files.clone().into_iter().map(Ok));
+    let epoch_dir = epoch_dir.clone();
+    let shard_files = futures::stream::iter(index.shards.clone())
+        .then(move |shard| {
+            let epoch_dir = epoch_dir.clone();
+            let store = store.clone();
+            async move { read_manifest_shard(&epoch_dir, &shard, store).await }
+        })
+        .map_ok(|files| futures::stream::iter(files.into_iter().map(Ok)))
+        .try_flatten();
+    files.chain(shard_files).boxed()
+}
+
+/// Lookup of single files in the manifest of an epoch without reading all of its shards.
+/// The last shard read is kept, as files are mostly looked up in path order.
+pub struct ManifestLookup {
+    epoch_dir: Path,
+    index: EpochManifest,
+    store: Arc<DynObjectStore>,
+    last_shard: Mutex<Option<(usize, Arc<Vec<FileEntry>>)>>,
+}
+
+impl ManifestLookup {
+    pub fn new(epoch_dir: &Path, index: EpochManifest, store: Arc<DynObjectStore>) -> Self {
+        ManifestLookup {
+            epoch_dir: epoch_dir.clone(),
+            index,
+            store,
+            last_shard: Mutex::new(None),
+        }
+    }
+    pub fn index(&self) -> &EpochManifest {
+        &self.index
+    }
+    /// Streams all the files of the manifest
+    pub fn files(&self) -> BoxStream<'static, Result<FileEntry>> {
+        manifest_files(&self.epoch_dir, &self.index, self.store.clone())
+    }
+    /// Entry of the file at `path`, `None` if the manifest doesn't list it
+    pub async fn file(&self, path: &str) -> Result<Option<FileEntry>> {
+        if let Some(file) = self.index.file(path) {
+            return Ok(Some(file.clone()));
+        }
+        let Some(position) = self.index.shards.iter().position(|shard| {
+            shard.first_path.as_str() <= path && path <= shard.last_path.as_str()
+        }) else {
+            return Ok(None);
+        };
+        let cached = self
+            .last_shard
+            .lock()
+            .as_ref()
+            .filter(|(cached, _)| *cached == position)
+            .map(|(_, files)| files.clone());
+        let files = match cached {
+            Some(files) => files,
+            None => {
+                let files = Arc::new(
+                    read_manifest_shard(
+                        &self.epoch_dir,
+                        &self.index.shards[position],
+                        self.store.clone(),
+                    )
+                    .await?,
+                );
+                *self.last_shard.lock() = Some((position, files.clone()));
+                files
+            }
+        };
+        // Shards hold files in path order
+        Ok(files
+            .binary_search_by(|file| file.path.as_str().cmp(path))
+            .ok()
+            .map(|index| files[index].clone()))
+    }
+}
+
+/// Contents of the `sha256sum` compatible sums file of the uploaded files of `manifest`, with
+/// paths relative to the epoch directory
+pub fn sums_file_contents(manifest: &EpochManifest) -> String {
+    manifest
+        .files
+        .iter()
+        .filter_map(|file| {
+            file.sha256_digest
+                .as_ref()
+                .map(|digest| format!("{digest}  {}\n", file.path))
+        })
+        .collect()
+}
+
+pub async fn write_sums_file(
+    manifest: &EpochManifest,
+    epoch_dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<()> {
+    let bytes = Bytes::from(sums_file_contents(manifest));
+    put(&epoch_dir.child(SUMS_FILENAME), bytes, store).await?;
+    Ok(())
+}
+
+/// Hex encoded sha3 digest of `bytes`, as recorded in the manifest
+pub fn sha3_hex(bytes: &[u8]) -> String {
+    Hex::encode(Sha3_256::digest(bytes).digest)
+}
+
+/// Hex encoded sha256 digest of `bytes`, as written to the sums file
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Hex::encode(Sha256::digest(bytes).digest)
+}
+
+/// Size and hex encoded sha3 digest of the object at `location`, read a chunk at a time
+pub async fn stream_sha3_hex(
+    location: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<(usize, String)> {
+    let mut stream = store.get(location).await?.into_stream();
+    let mut hasher = Sha3_256::default();
+    let mut size = 0;
+    while let Some(bytes) = stream.next().await {
+        let bytes = bytes?;
+        size += bytes.len();
+        hasher.update(&bytes);
+    }
+    Ok((size, Hex::encode(hasher.finalize().digest)))
+}
+
+/// Writes the success marker of `epoch_dir` with `metadata` as its body, which makes the
+/// manifest, the audit record and the success of the upload visible at once
+pub async fn write_epoch_metadata(
+    metadata: &EpochMetadata,
+    epoch_dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<()> {
+    let bytes = Bytes::from(serde_json::to_vec(metadata)?);
+    put(&epoch_dir.child(SUCCESS_MARKER), bytes, store).await?;
+    Ok(())
+}
+
+/// Reads the metadata from the success marker of `epoch_dir`. Fails for success markers
+/// written without `consolidate_epoch_metadata`, which have no body.
+pub async fn read_epoch_metadata(
+    epoch_dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<EpochMetadata> {
+    let bytes = store
+        .get(&epoch_dir.child(SUCCESS_MARKER))
+        .await?
+        .bytes()
+        .await?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Reads the manifest of `epoch_dir` with the files of all its shards
+pub async fn read_manifest(epoch_dir: &Path, store: Arc<DynObjectStore>) -> Result<EpochManifest> {
+    let manifest = read_manifest_index(epoch_dir, store.clone()).await?;
+    with_shard_files(epoch_dir, manifest, store).await
+}
+
+/// Reads the manifest of `epoch_dir` with the files of all its shards, `None` unless the epoch
+/// is published, see `read_published_manifest_index`
+pub async fn read_published_manifest(
+    epoch_dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<Option<EpochManifest>> {
+    match read_published_manifest_index(epoch_dir, store.clone()).await? {
+        Some(manifest) => Ok(Some(with_shard_files(epoch_dir, manifest, store).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads the manifest of `epoch_dir` as stored if the epoch is published, `None` otherwise.
+/// The success marker is the last write of an upload, so an epoch is complete exactly when it
+/// has one, whatever files or MANIFEST are visible while it is copied.
+pub async fn read_published_manifest_index(
+    epoch_dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<Option<EpochManifest>> {
+    let marker = match store.get(&epoch_dir.child(SUCCESS_MARKER)).await {
+        Ok(result) => result.bytes().await?,
+        Err(object_store::Error::NotFound { .. }) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // Success markers written with consolidated metadata hold the manifest
+    if let Ok(metadata) = serde_json::from_slice::<EpochMetadata>(&marker) {
+        return Ok(Some(metadata.manifest));
+    }
+    let manifest = read_manifest_index(epoch_dir, store)
+        .await
+        .with_context(|| format!("Published epoch {epoch_dir} has no readable manifest"))?;
+    Ok(Some(manifest))
+}
+
+/// Withdraws the epoch of `epoch_dir` before its files are copied again, so that readers don't
+/// take the files being overwritten for a complete epoch. The success marker goes first, a
+/// stale MANIFEST left alone is never trusted.
+pub async fn unpublish_epoch(epoch_dir: &Path, store: Arc<DynObjectStore>) -> Result<()> {
+    for name in [SUCCESS_MARKER, MANIFEST_FILENAME] {
+        match store.delete(&epoch_dir.child(name)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+async fn with_shard_files(
+    epoch_dir: &Path,
+    mut manifest: EpochManifest,
+    store: Arc<DynObjectStore>,
+) -> Result<EpochManifest> {
+    if !manifest.shards.is_empty() {
+        manifest.files = manifest_files(epoch_dir, &manifest, store)
+            .try_collect()
+            .await?;
+        manifest.shards.clear();
+    }
+    Ok(manifest)
+}
+
+/// Reads the manifest of `epoch_dir` as stored, which only lists its shards when sharded
+pub async fn read_manifest_index(
+    epoch_dir: &Path,
+    store: Arc<DynObjectStore>,
+) -> Result<EpochManifest> {
+    // Not retried, a missing manifest is an expected condition for incomplete epochs
+    match store.get(&epoch_dir.child(MANIFEST_FILENAME)).await {
+        Ok(result) => Ok(serde_json::from_slice(&result.bytes().await?)?),
+        // Epochs uploaded with consolidated metadata keep the manifest in their success marker
+        Err(object_store::Error::NotFound { .. }) => Ok(read_epoch_metadata(epoch_dir, store)
+            .await
+            .with_context(|| format!("No manifest found in {epoch_dir}"))?
+            .manifest),
+        Err(err) => Err(err.into()),
+    }
+}
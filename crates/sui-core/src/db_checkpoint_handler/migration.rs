@@ -0,0 +1,132 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Upgrade of restored db checkpoints to the storage format of this binary. Values of the
+//! versioned tables of the perpetual db are otherwise only migrated lazily at read time, see
+//! `authority_store_types`, which leaves a db restored from an old backup depending on the
+//! read path of every old format. Migrating right after the restore rewrites them once,
+//! before the node starts.
+
+use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::info;
+use typed_store::rocks::DBMap;
+use typed_store::traits::Map;
+
+/// Version of the storage format of the perpetual db written by this binary, recorded in the
+/// epoch manifest. Bump it along with a new variant of a versioned table value, e.g.
+/// `StoreObjectWrapper`, so that restores of older db checkpoints migrate it.
+pub const PERPETUAL_SCHEMA_VERSION: u32 = 1;
+/// Number of migrated entries written per batch
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+/// Whether a db checkpoint recorded with `schema_version` needs migrating after restore.
+/// Db checkpoints uploaded before the version was recorded are migrated to be safe.
+pub fn needs_migration(schema_version: Option<u32>) -> Result<bool> {
+    match schema_version {
+        Some(version) if version > PERPETUAL_SCHEMA_VERSION => Err(anyhow!(
+            "Db checkpoint has storage schema version {version}, this binary only supports up to {PERPETUAL_SCHEMA_VERSION}"
+        )),
+        Some(version) => Ok(version < PERPETUAL_SCHEMA_VERSION),
+        None => Ok(true),
+    }
+}
+
+/// Rewrites the entries of `table` whose value `migrate` changes. Returns the number of
+/// rewritten entries.
+fn migrate_table<K, V>(table: &DBMap<K, V>, migrate: impl Fn(V) -> V) -> Result<u64>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned + Clone + PartialEq,
+{
+    let mut migrated = 0;
+    let mut pending = vec![];
+    for item in table.safe_iter() {
+        let (key, value) = item?;
+        let new_value = migrate(value.clone());
+        if new_value != value {
+            pending.push((key, new_value));
+        }
+        if pending.len() >= MIGRATION_BATCH_SIZE {
+            migrated += pending.len() as u64;
+            let mut batch = table.batch();
+            batch.insert_batch(table, pending.drain(..))?;
+            batch.write()?;
+        }
+    }
+    if !pending.is_empty() {
+        migrated += pending.len() as u64;
+        let mut batch = table.batch();
+        batch.insert_batch(table, pending)?;
+        batch.write()?;
+    }
+    Ok(migrated)
+}
+
+/// Migrates the versioned tables of the perpetual db of the db checkpoint restored at
+/// `db_path` to the latest format. Returns the number of rewritten entries by table.
+pub fn migrate_restored_db(db_path: &Path) -> Result<BTreeMap<String, u64>> {
+    let store_path = db_path.join("store");
+    // Opening a missing db would create it
+    if !AuthorityPerpetualTables::path(&store_path).exists() {
+        return Ok(BTreeMap::new());
+    }
+    let perpetual_db = AuthorityPerpetualTables::open(&store_path, None);
+    let mut migrated = BTreeMap::new();
+    migrated.insert(
+        "objects".to_string(),
+        migrate_table(&perpetual_db.objects, |value| value.migrate())?,
+    );
+    migrated.insert(
+        "indirect_move_objects".to_string(),
+        migrate_table(&perpetual_db.indirect_move_objects, |value| value.migrate())?,
+    );
+    migrated.insert(
+        "owned_object_transaction_locks".to_string(),
+        migrate_table(&perpetual_db.owned_object_transaction_locks, |value| {
+            value.map(|lock| lock.migrate())
+        })?,
+    );
+    info!(
+        "Migrated restored db at {} to storage schema version {PERPETUAL_SCHEMA_VERSION}, rewritten entries by table: {:?}",
+        db_path.display(),
+        migrated
+    );
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate_restored_db, needs_migration, PERPETUAL_SCHEMA_VERSION};
+    use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+    use sui_types::base_types::ObjectID;
+    use sui_types::object::Object;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_migrate_restored_db() -> anyhow::Result<()> {
+        assert!(needs_migration(None)?);
+        assert!(!needs_migration(Some(PERPETUAL_SCHEMA_VERSION))?);
+        assert!(needs_migration(Some(PERPETUAL_SCHEMA_VERSION + 1)).is_err());
+
+        let restored = TempDir::new()?;
+        // Nothing to migrate without a perpetual db
+        assert!(migrate_restored_db(restored.path())?.is_empty());
+
+        let store_path = restored.path().join("store");
+        {
+            let perpetual_db = AuthorityPerpetualTables::open(&store_path, None);
+            let object = Object::immutable_with_id_for_testing(ObjectID::random());
+            perpetual_db.insert_object_test_only(object)?;
+        }
+        // Values already in the latest format are left untouched
+        let migrated = migrate_restored_db(restored.path())?;
+        assert_eq!(migrated.len(), 3);
+        assert!(migrated.values().all(|count| *count == 0));
+        Ok(())
+    }
+}
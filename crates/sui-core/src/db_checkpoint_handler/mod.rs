@@ -0,0 +1,6598 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod adaptive_concurrency;
+pub mod admin_auth;
+pub mod archive;
+pub mod audit;
+pub mod backfill;
+pub mod backup_watermark;
+pub mod bandwidth;
+pub mod benchmark;
+pub mod bootstrap;
+pub mod bundles;
+pub mod chunking;
+pub mod commands;
+pub mod creation;
+pub mod destination_health;
+pub mod diagnosis;
+pub mod digest_pool;
+pub mod divergence;
+pub mod encryption;
+pub mod epoch_window;
+pub mod events;
+pub mod expectations;
+pub mod expected;
+pub mod format;
+pub mod fs_snapshot;
+pub mod gc_readiness;
+pub mod headroom;
+pub mod labels;
+pub mod listing;
+pub mod manifest;
+pub mod migration;
+pub mod prune_worker;
+pub mod recovery;
+pub mod resource_guard;
+pub mod restorer;
+pub mod scrub;
+pub mod self_usage;
+pub mod source;
+pub mod staging;
+pub mod status;
+pub mod table_transfer;
+pub mod telemetry;
+pub mod verification_budget;
+pub mod verifier;
+pub mod watcher;
+
+use crate::authority::authority_store_pruner::{AuthorityStorePruningMetrics, PRUNABLE_TABLES};
+use crate::db_checkpoint_handler::adaptive_concurrency::AdaptiveConcurrency;
+use crate::db_checkpoint_handler::archive::{
+    archive_end, archive_path, compress_member, end_of_archive, extract_member, is_archived,
+    read_member, ARCHIVE_SIZE_BYTES_RANGE, DEFAULT_ARCHIVE_SIZE_BYTES,
+};
+use crate::db_checkpoint_handler::audit::{read_audit_log, AuditAction, AuditLog, AUDIT_DIR};
+use crate::db_checkpoint_handler::backup_watermark::BackupWatermark;
+use crate::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use crate::db_checkpoint_handler::bundles::{bundled_epochs, BUNDLES_DIR};
+use crate::db_checkpoint_handler::chunking::{
+    count_missing_chunks, upload_chunks, verify_chunks, ChunkEntry, CHUNKS_DIR,
+};
+use crate::db_checkpoint_handler::commands::{
+    BackupCommand, BackupCommander, BACKUP_COMMANDS_CAPACITY,
+};
+use crate::db_checkpoint_handler::creation::CreationEstimate;
+use crate::db_checkpoint_handler::diagnosis::ProbableCause;
+use crate::db_checkpoint_handler::digest_pool::DigestPool;
+use crate::db_checkpoint_handler::encryption::{EncryptionKey, ENCRYPTION_OVERHEAD};
+use crate::db_checkpoint_handler::epoch_window::{
+    EpochUploadStatus, EpochWindowMetrics, DEFAULT_EPOCH_METRICS_WINDOW,
+};
+use crate::db_checkpoint_handler::events::{BackupEvent, BACKUP_EVENTS_CAPACITY};
+use crate::db_checkpoint_handler::expectations::RestoreExpectations;
+use crate::db_checkpoint_handler::expected::{ExpectedFiles, EXPECTED_FILENAME};
+use crate::db_checkpoint_handler::fs_snapshot::{destroy_fs_snapshot, is_fs_snapshot};
+use crate::db_checkpoint_handler::gc_readiness::{GcReadiness, MarkerReadiness, ReplicaReadiness};
+use crate::db_checkpoint_handler::headroom::{available_space, pending_uploads};
+use crate::db_checkpoint_handler::labels::{read_protocol_version, CheckpointLabels};
+use crate::db_checkpoint_handler::manifest::{
+    compress_file, compress_file_seekable, decompress_file, logical_path, read_epoch_metadata,
+    read_published_manifest, sha256_hex, sha3_hex, stream_sha3_hex, stream_verify_chunked_file,
+    stream_verify_file, unpublish_epoch, verify_file_contents, write_epoch_metadata,
+    write_manifest, write_manifest_shards, write_sums_file, BackupProducer, CompressedFrame,
+    EpochManifest, EpochMetadata, FileEntry, UploadProgress, DEFAULT_MANIFEST_SHARD_SIZE,
+    LOCAL_CHECKSUMS_FILENAME, UPLOAD_PROGRESS_FILENAME,
+};
+use crate::db_checkpoint_handler::prune_worker::{PruneJob, PruneSettings};
+use crate::db_checkpoint_handler::recovery::recover_interrupted_operations;
+use crate::db_checkpoint_handler::resource_guard::apply_thread_priorities;
+use crate::db_checkpoint_handler::scrub::DEFAULT_LOCAL_SCRUB_SAMPLE_SIZE;
+use crate::db_checkpoint_handler::self_usage::{open_files_under, UsageRecorder};
+use crate::db_checkpoint_handler::source::{remote_epoch_dir, CheckpointSource, LocalCheckpoint};
+use crate::db_checkpoint_handler::staging::StagingArea;
+use crate::db_checkpoint_handler::status::BackupStatusHandle;
+use crate::db_checkpoint_handler::telemetry::{BackupTelemetry, BackupTelemetryEvent};
+use crate::db_checkpoint_handler::verification_budget::VerificationBudget;
+use crate::db_checkpoint_handler::verifier::ContentCoverage;
+use crate::db_checkpoint_handler::watcher::{next_new_epoch, CheckpointWatcher};
+use crate::task_handle::{ShutdownSignal, TaskHandle};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use parking_lot::Mutex;
+use prometheus::{
+    register_histogram_with_registry, register_int_counter_with_registry,
+    register_int_gauge_vec_with_registry, register_int_gauge_with_registry, Histogram, IntCounter,
+    IntGauge, IntGaugeVec, Registry,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sui_config::node::{
+    AuthorityStorePruningConfig, BackupResourceLimits, DBCheckpointCompression, DBCheckpointConfig,
+    DBCheckpointEncryptionConfig, DBCheckpointUploadLayout, DBCheckpointUploadOrder,
+    DBSnapshotBackend, UploadEpochsConfig,
+};
+use sui_storage::object_store::retention::{dir_size, epochs_to_delete, RetentionPolicy};
+use sui_storage::object_store::util::{
+    delete_recursively, get, list_epoch_dirs, missing_epochs_of, path_to_filesystem, put,
+    scan_epoch_dirs, EpochCompleteness,
+};
+use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+use sui_types::digests::ChainIdentifier;
+use tokio::sync::{broadcast, mpsc, OnceCell};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+
+/// Last write of the upload of an epoch, which publishes it, see `read_published_manifest`
+pub const SUCCESS_MARKER: &str = "_SUCCESS";
+pub const TEST_MARKER: &str = "_TEST";
+pub const UPLOAD_COMPLETED_MARKER: &str = "_UPLOAD_COMPLETED";
+/// Suffix of the files the handlers of replicas write next to the ones of the output store,
+/// followed by the number of the replica, e.g. `_UPLOAD_COMPLETED_REPLICA_1`
+pub const REPLICA_SUFFIX: &str = "_REPLICA_";
+/// Number of objects and transactions sampled into the restore expectations of an epoch
+const NUM_EXPECTATION_SAMPLES: usize = 20;
+/// Buckets of the upload durations of epochs, from seconds for small test networks to a day
+const UPLOAD_DURATION_SEC_BUCKETS: &[f64] = &[
+    10.0, 30.0, 60.0, 300.0, 600.0, 1800.0, 3600.0, 7200.0, 14400.0, 28800.0, 86400.0,
+];
+/// Prefix of the metrics of pruning db checkpoints before upload
+pub const PRUNING_METRICS_PREFIX: &str = "db_checkpoint_";
+/// Time between two samples of the files of db checkpoints held open
+const USAGE_SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+/// Key of the object read by the probe of the remote store, which is never written
+const PROBE_FILENAME: &str = "_PROBE";
+/// Time between two checks of the free space on the disk of the local db checkpoints
+const DISK_PRESSURE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Time left for a db checkpoint which showed up in the watched directory to settle before
+/// scanning for it
+const WATCH_SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// Result of the upload of a single file, recorded in its manifest entry
+struct UploadedFile {
+    sha3_digest: String,
+    /// Set when the layout records the digests in a sums file
+    sha256_digest: Option<String>,
+    /// Set when the file was compressed
+    compressed_size: Option<usize>,
+    /// Set when the file was compressed into seekable frames
+    frames: Vec<CompressedFrame>,
+}
+
+/// Replica of the output store, see `DBCheckpointConfig::replica_object_store_configs`
+struct ReplicaDestination {
+    /// Name of the store, the `destination` label of the metrics of the replica
+    name: String,
+    /// Registry of the metrics of the replica
+    registry: Registry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UploadOutcome {
+    Completed,
+    /// The epoch upload deadline or the prune and compact timeout passed, the upload resumes on
+    /// the next interval
+    Deferred,
+}
+
+/// Consecutive failed uploads of an epoch
+#[derive(Debug, Clone, Copy)]
+struct UploadFailures {
+    count: u32,
+    /// Time of the latest of them
+    last: Instant,
+}
+
+/// Whether `err` failed the upload of its epoch only, rather than any upload as the remote
+/// store being unreachable or refusing writes would. Only those count toward quarantine.
+fn is_epoch_specific(err: &anyhow::Error) -> bool {
+    !err.chain().any(|cause| {
+        cause.is::<object_store::Error>() || cause.is::<tokio::time::error::Elapsed>()
+    })
+}
+
+/// A local db checkpoint file changed since its digest was recorded after compaction, only the
+/// epoch of the file is skipped while the other epochs are uploaded
+#[derive(Debug, thiserror::Error)]
+#[error("Corrupted local db checkpoint file: {path}")]
+struct LocalCorruption {
+    path: String,
+}
+
+pub struct DBCheckpointMetrics {
+    pub first_missing_db_checkpoint_epoch: IntGauge,
+    pub first_missing_db_checkpoint_epoch_regressions: IntCounter,
+    pub missing_db_checkpoint_epochs_count: IntGauge,
+    pub bitrot_detected_total: IntCounter,
+    pub local_scrub_verified_files: IntCounter,
+    pub local_corruption_detected_total: IntCounter,
+    pub pruned_bytes_by_table: IntGaugeVec,
+    pub unexpected_remote_entries: IntGauge,
+    pub epoch_window: EpochWindowMetrics,
+    pub verifier_latest_complete_epoch: IntGauge,
+    pub verifier_missing_epochs: IntGauge,
+    pub verifier_failed_epochs: IntGauge,
+    pub verifier_verified_epochs_total: IntCounter,
+    pub verifier_budget_consumed_bytes: IntGauge,
+    pub verifier_content_checked_bytes: IntGauge,
+    pub verifier_content_total_bytes: IntGauge,
+    pub upload_concurrency: IntGauge,
+    pub bytes_uploaded: IntCounter,
+    pub upload_duration_seconds: Histogram,
+    pub upload_failures_total: IntCounter,
+    pub last_uploaded_epoch: IntGauge,
+    pub gc_deleted_epochs_total: IntCounter,
+    pub usage: UsageRecorder,
+    pub open_files: IntGauge,
+    pub upload_resumed_files_total: IntCounter,
+    pub quarantined_epochs: IntGauge,
+    pub destination_unreachable: IntGauge,
+    pub disk_pressure_deleted_epochs_total: IntCounter,
+    pub disk_pressure: IntGauge,
+    pub gc_dry_run_epochs: IntGauge,
+    pub prune_and_compact_phase: IntGaugeVec,
+    pub prune_and_compact_duration_seconds: Histogram,
+    pub prune_and_compact_timeouts_total: IntCounter,
+    pub creation_completeness_percent: IntGauge,
+    pub creation_in_progress_deferrals_total: IntCounter,
+}
+
+impl DBCheckpointMetrics {
+    pub fn new(registry: &Registry, epoch_metrics_window: usize) -> Arc<Self> {
+        let this = Self {
+            first_missing_db_checkpoint_epoch: register_int_gauge_with_registry!(
+                "first_missing_db_checkpoint_epoch",
+                "First epoch for which we have no db checkpoint in remote store",
+                registry
+            )
+            .unwrap(),
+            first_missing_db_checkpoint_epoch_regressions: register_int_counter_with_registry!(
+                "first_missing_db_checkpoint_epoch_regressions_total",
+                "Number of times an epoch already complete in the remote store was found missing again",
+                registry
+            )
+            .unwrap(),
+            missing_db_checkpoint_epochs_count: register_int_gauge_with_registry!(
+                "missing_db_checkpoint_epochs_count",
+                "Number of epochs missing from the remote store below the latest uploaded one",
+                registry
+            )
+            .unwrap(),
+            bitrot_detected_total: register_int_counter_with_registry!(
+                "bitrot_detected_total",
+                "Number of local db checkpoint files whose contents changed between compaction and upload",
+                registry
+            )
+            .unwrap(),
+            local_scrub_verified_files: register_int_counter_with_registry!(
+                "db_checkpoint_local_scrub_verified_files",
+                "Number of files of the db checkpoints kept on local disk checked against their recorded digests",
+                registry
+            )
+            .unwrap(),
+            local_corruption_detected_total: register_int_counter_with_registry!(
+                "db_checkpoint_local_corruption_detected_total",
+                "Number of files of the db checkpoints kept on local disk found missing or altered since compaction",
+                registry
+            )
+            .unwrap(),
+            pruned_bytes_by_table: register_int_gauge_vec_with_registry!(
+                "db_checkpoint_pruned_bytes_by_table",
+                "Bytes each table of the latest db checkpoint shrank by in pruning and compaction before upload",
+                &["table"],
+                registry
+            )
+            .unwrap(),
+            unexpected_remote_entries: register_int_gauge_with_registry!(
+                "db_checkpoint_unexpected_remote_entries",
+                "Number of entries at the root of the remote store which weren't written by the db checkpoint handler",
+                registry
+            )
+            .unwrap(),
+            epoch_window: EpochWindowMetrics::new(registry, epoch_metrics_window),
+            verifier_latest_complete_epoch: register_int_gauge_with_registry!(
+                "db_checkpoint_verifier_latest_complete_epoch",
+                "Latest epoch with a success marker in the remote store verified by a verify-only handler, -1 if none",
+                registry
+            )
+            .unwrap(),
+            verifier_missing_epochs: register_int_gauge_with_registry!(
+                "db_checkpoint_verifier_missing_epochs",
+                "Number of epochs missing from the remote store below the latest complete one",
+                registry
+            )
+            .unwrap(),
+            verifier_failed_epochs: register_int_gauge_with_registry!(
+                "db_checkpoint_verifier_failed_epochs",
+                "Number of complete epochs in the remote store whose files don't match their manifest",
+                registry
+            )
+            .unwrap(),
+            verifier_verified_epochs_total: register_int_counter_with_registry!(
+                "db_checkpoint_verifier_verified_epochs_total",
+                "Number of epochs of the remote store which passed verification",
+                registry
+            )
+            .unwrap(),
+            verifier_budget_consumed_bytes: register_int_gauge_with_registry!(
+                "db_checkpoint_verifier_budget_consumed_bytes",
+                "Bytes downloaded by the content checks of a verify-only handler in the current day of its verification budget",
+                registry
+            )
+            .unwrap(),
+            verifier_content_checked_bytes: register_int_gauge_with_registry!(
+                "db_checkpoint_verifier_content_checked_bytes",
+                "Bytes of the complete epochs in the remote store whose contents a verify-only handler checked against their manifest",
+                registry
+            )
+            .unwrap(),
+            verifier_content_total_bytes: register_int_gauge_with_registry!(
+                "db_checkpoint_verifier_content_total_bytes",
+                "Bytes of the complete epochs in the remote store to be content checked by a verify-only handler",
+                registry
+            )
+            .unwrap(),
+            upload_concurrency: register_int_gauge_with_registry!(
+                "db_checkpoint_upload_concurrency",
+                "Number of writes to the remote store uploads run at once, as adapted to the store",
+                registry
+            )
+            .unwrap(),
+            bytes_uploaded: register_int_counter_with_registry!(
+                "db_checkpoint_bytes_uploaded_total",
+                "Bytes of files and chunks written to the remote store, after compression and encryption",
+                registry
+            )
+            .unwrap(),
+            upload_duration_seconds: register_histogram_with_registry!(
+                "db_checkpoint_upload_duration_seconds",
+                "Duration of the completed uploads of db checkpoints, from the start of their last attempt",
+                UPLOAD_DURATION_SEC_BUCKETS.to_vec(),
+                registry
+            )
+            .unwrap(),
+            upload_failures_total: register_int_counter_with_registry!(
+                "db_checkpoint_upload_failures_total",
+                "Number of failed uploads of db checkpoints, retried on the next interval",
+                registry
+            )
+            .unwrap(),
+            last_uploaded_epoch: register_int_gauge_with_registry!(
+                "db_checkpoint_last_uploaded_epoch",
+                "Latest epoch whose db checkpoint was uploaded by this handler, -1 until one is",
+                registry
+            )
+            .unwrap(),
+            gc_deleted_epochs_total: register_int_counter_with_registry!(
+                "db_checkpoint_gc_deleted_epochs_total",
+                "Number of local db checkpoints deleted after upload",
+                registry
+            )
+            .unwrap(),
+            usage: UsageRecorder::new(registry),
+            open_files: register_int_gauge_with_registry!(
+                "db_checkpoint_handler_open_files",
+                "Number of files of local db checkpoints and staging dirs the process holds open",
+                registry
+            )
+            .unwrap(),
+            upload_resumed_files_total: register_int_counter_with_registry!(
+                "db_checkpoint_upload_resumed_files_total",
+                "Number of files found in the remote store from an interrupted upload and not uploaded again",
+                registry
+            )
+            .unwrap(),
+            quarantined_epochs: register_int_gauge_with_registry!(
+                "db_checkpoint_quarantined_epochs",
+                "Number of epochs skipped by uploads after failing too many times in a row",
+                registry
+            )
+            .unwrap(),
+            destination_unreachable: register_int_gauge_with_registry!(
+                "backup_destination_unreachable",
+                "1 when the remote store didn't answer the probe of the last upload pass, which was skipped",
+                registry
+            )
+            .unwrap(),
+            disk_pressure_deleted_epochs_total: register_int_counter_with_registry!(
+                "db_checkpoint_disk_pressure_deleted_epochs_total",
+                "Number of uploaded local db checkpoints deleted early to free the disk",
+                registry
+            )
+            .unwrap(),
+            disk_pressure: register_int_gauge_with_registry!(
+                "db_checkpoint_disk_pressure",
+                "1 when the disk of the local db checkpoints is low on free space with no uploaded db checkpoint left to delete",
+                registry
+            )
+            .unwrap(),
+            gc_dry_run_epochs: register_int_gauge_with_registry!(
+                "db_checkpoint_gc_dry_run_epochs",
+                "Number of local db checkpoints the last garbage collection would have deleted, in dry-run mode",
+                registry
+            )
+            .unwrap(),
+            prune_and_compact_phase: register_int_gauge_vec_with_registry!(
+                "db_checkpoint_prune_and_compact_phase",
+                "1 for the phase the running pruning and compaction of a db checkpoint is in",
+                &["phase"],
+                registry
+            )
+            .unwrap(),
+            prune_and_compact_duration_seconds: register_histogram_with_registry!(
+                "db_checkpoint_prune_and_compact_duration_seconds",
+                "Duration of the pruning and compaction of db checkpoints before upload",
+                UPLOAD_DURATION_SEC_BUCKETS.to_vec(),
+                registry
+            )
+            .unwrap(),
+            prune_and_compact_timeouts_total: register_int_counter_with_registry!(
+                "db_checkpoint_prune_and_compact_timeouts_total",
+                "Number of pruning and compaction jobs still running when their upload attempt stopped waiting",
+                registry
+            )
+            .unwrap(),
+            creation_completeness_percent: register_int_gauge_with_registry!(
+                "db_checkpoint_creation_completeness_percent",
+                "Percentage of the bytes of its expected files present in the latest db checkpoint checked before upload",
+                registry
+            )
+            .unwrap(),
+            creation_in_progress_deferrals_total: register_int_counter_with_registry!(
+                "db_checkpoint_creation_in_progress_deferrals_total",
+                "Number of upload attempts deferred because their db checkpoint was still being created",
+                registry
+            )
+            .unwrap(),
+        };
+        this.last_uploaded_epoch.set(-1);
+        Arc::new(this)
+    }
+}
+
+pub struct DBCheckpointHandler {
+    /// Directory on local disk where db checkpoints are stored
+    input_object_store: Arc<DynObjectStore>,
+    /// DB checkpoint directory on local filesystem
+    input_root_path: PathBuf,
+    /// Layout of the db checkpoints in the input directory
+    source: CheckpointSource,
+    /// Store of upload markers and checksums, the input store unless it is read-only
+    state_object_store: Arc<DynObjectStore>,
+    /// Bucket on cloud object store where db checkpoints will be copied
+    output_object_store: Arc<DynObjectStore>,
+    /// Time interval to check for presence of new db checkpoint
+    interval: Duration,
+    /// Conditions which must all hold before a local db checkpoint is garbage collected
+    gc_readiness: Vec<Arc<dyn GcReadiness>>,
+    /// Boolean flag to enable/disable object pruning and manual compaction before upload
+    prune_and_compact_before_upload: bool,
+    /// Number of files to copy concurrently to the remote store
+    upload_concurrency: NonZeroUsize,
+    /// Writes of uploads to the remote store, starting at `upload_concurrency`, within the
+    /// upload bandwidth limit
+    upload_limiter: AdaptiveConcurrency,
+    /// Number of most recent uploaded db checkpoints to keep on local disk
+    num_local_epochs_to_retain: usize,
+    /// Boolean flag to enable/disable comparing the remote copy against the local checkpoint
+    /// before the upload is marked successful
+    verify_after_upload: bool,
+    /// Boolean flag to enable/disable downloading the remote copy to compare the checksums of
+    /// its files when verifying it
+    verify_remote_checksums: bool,
+    /// Key layout of the uploaded files in the remote store
+    upload_layout: DBCheckpointUploadLayout,
+    /// Compression of the uploaded files in the remote store
+    compression: DBCheckpointCompression,
+    /// Client-side encryption of the uploaded files, if enabled
+    encryption: Option<DBCheckpointEncryptionConfig>,
+    /// Key of `encryption`, loaded on first use since it may have to be decrypted with KMS
+    encryption_key: OnceCell<EncryptionKey>,
+    /// Indirect object config for pruner
+    indirect_objects_threshold: usize,
+    /// Pruning of the db checkpoints before upload
+    pruning_config: AuthorityStorePruningConfig,
+    /// Tables left untouched by pruning before upload
+    skip_pruning_tables: HashSet<String>,
+    metrics: Arc<DBCheckpointMetrics>,
+    /// Metrics of pruning before upload, registered next to the ones of the handler
+    pruning_metrics: Arc<AuthorityStorePruningMetrics>,
+    telemetry: BackupTelemetry,
+    /// Publishes backup events to the subscribers of the handler
+    events: broadcast::Sender<BackupEvent>,
+    /// Audit log in the output bucket, if enabled
+    audit_log: Option<AuditLog>,
+    /// Scheduling priorities of the thread running the handler loop
+    resource_limits: BackupResourceLimits,
+    /// Filesystem snapshots backing the local db checkpoints, if any
+    snapshot_backend: Option<DBSnapshotBackend>,
+    /// Options of the perpetual db opened for pruning, sharing a rate limiter with the live db
+    pruning_db_options: Option<rocksdb::Options>,
+    /// Time after an upload during which its success marker may not be visible yet
+    consistency_grace_period: Option<Duration>,
+    /// Time at which the success marker of every epoch uploaded within the grace period was
+    /// written
+    recent_uploads: Mutex<BTreeMap<u32, Instant>>,
+    /// First missing epoch found by the previous scan of the remote store
+    first_missing_epoch: Mutex<Option<u32>>,
+    /// Status of the uploads, shared with whoever reports it
+    status: BackupStatusHandle,
+    /// Take file digests from the upload read instead of a separate pass after compaction
+    single_pass_digests: bool,
+    /// Time after which the upload of an epoch is suspended until the next interval
+    epoch_upload_deadline: Option<Duration>,
+    /// Write the manifest and the audit record of an epoch into its success marker
+    consolidate_epoch_metadata: bool,
+    /// Chain of the db checkpoints, recorded in their manifest when set
+    chain_identifier: Option<ChainIdentifier>,
+    /// Node recorded as the producer of the uploaded db checkpoints
+    producer: Option<BackupProducer>,
+    /// Threads digesting and chunking file contents off the async executors
+    digest_pool: DigestPool,
+    /// Number of files above which the manifest of an epoch is split into shards
+    manifest_shard_size: NonZeroUsize,
+    /// Which uploaded epochs to keep in the remote store, if not all of them
+    remote_retention: Option<RetentionPolicy>,
+    /// Raised to every epoch confirmed in the remote store, for the pruner of the live db
+    backup_watermark: Option<BackupWatermark>,
+    /// Time between two checks of a sample of the files of the local db checkpoints
+    local_scrub_interval: Option<Duration>,
+    /// Number of files checked by every local scrub
+    local_scrub_sample_size: usize,
+    /// Staging dirs cleaned up along with the local db checkpoints
+    staging_area: Option<StagingArea>,
+    /// Only verify the epochs uploaded by another node, never write to the remote store
+    verify_only: bool,
+    /// Epochs of the remote store which passed verification in verify-only mode
+    verified_epochs: Mutex<BTreeSet<u32>>,
+    /// Daily budget of the downloads of the content checks in verify-only mode
+    verification_budget: VerificationBudget,
+    /// Progress of the content checks of the epochs of the remote store in verify-only mode
+    content_coverage: Mutex<BTreeMap<u32, ContentCoverage>>,
+    /// Marker written next to a local db checkpoint once it is uploaded to the output store
+    upload_completed_marker: String,
+    /// File recording the progress of a deferred upload to the output store
+    upload_progress_filename: String,
+    /// Set when the output store is a replica. Replicas only upload the db checkpoints already
+    /// pruned and digested for the primary output store, and leave local garbage collection to
+    /// its handler.
+    replica: Option<ReplicaDestination>,
+    /// Handlers of the replicas of the output store, started along with this one
+    replicas: Vec<DBCheckpointHandler>,
+    /// Commands sent to the handler between the passes of its upload interval
+    commands: mpsc::Sender<BackupCommand>,
+    /// Taken by the handler loop when started
+    command_receiver: Option<mpsc::Receiver<BackupCommand>>,
+    /// Epochs uploaded, the others are ignored
+    upload_epochs: UploadEpochsConfig,
+    /// Consecutive failed uploads after which an epoch is quarantined, if any
+    quarantine_threshold: Option<u32>,
+    /// Time after which a quarantined epoch is uploaded again
+    quarantine_retry: Duration,
+    /// Consecutive failed uploads of every epoch whose last upload failed
+    upload_failures: Mutex<BTreeMap<u32, UploadFailures>>,
+    /// Epochs the remote retention policy deleted from the remote store
+    remote_retention_deleted: Mutex<BTreeSet<u32>>,
+    /// Epochs an operator deleted from the remote store, see `BackupCommander::delete_remote_epoch`
+    admin_deleted_epochs: Mutex<BTreeSet<u32>>,
+    /// Time the remote store has to answer the probe starting every upload pass
+    destination_probe_timeout: Duration,
+    /// Free bytes on the disk of the local db checkpoints below which the uploaded ones are
+    /// deleted right away
+    disk_pressure_min_free_bytes: Option<u64>,
+    /// Epochs deleted to free the disk since the current upload pass listed the local db
+    /// checkpoints
+    deleted_under_pressure: Mutex<BTreeSet<u32>>,
+    /// Held while deleting local db checkpoints, which the disk pressure checks do in the
+    /// middle of upload passes
+    gc_lock: tokio::sync::Mutex<()>,
+    /// Time between two garbage collections of the uploaded local db checkpoints
+    gc_interval: Duration,
+    /// Only report the local db checkpoints garbage collection would delete
+    gc_dry_run: bool,
+    /// Time an upload attempt waits for the pruning and compaction of its db checkpoint
+    prune_and_compact_timeout: Option<Duration>,
+    /// Pruning and compaction of a db checkpoint, kept until an upload attempt takes its result
+    prune_job: Mutex<Option<PruneJob>>,
+    /// Scan for db checkpoints to upload as soon as one shows up in `input_root_path`
+    watch_input_dir: bool,
+    /// Time for which the files of a db checkpoint must be left unmodified before upload
+    creation_settle: Duration,
+    /// Order in which upload passes go through the missing epochs
+    upload_order: DBCheckpointUploadOrder,
+    /// Size of the files packed into each archive with the `TarArchives` layout
+    archive_size_bytes: u64,
+    /// Time garbage collection waits for a replica to upload a db checkpoint uploaded to the
+    /// output store, see `DBCheckpointConfig::replica_gc_wait_s`
+    replica_gc_wait: Duration,
+}
+
+impl DBCheckpointHandler {
+    /// Handler uploading the db checkpoints in `input_path` to `output_object_store_config`,
+    /// and to every replica of `db_checkpoint_config` with handlers started along with it
+    pub fn new(
+        input_path: &std::path::Path,
+        output_object_store_config: &ObjectStoreConfig,
+        db_checkpoint_config: &DBCheckpointConfig,
+        indirect_objects_threshold: usize,
+        pruning_config: AuthorityStorePruningConfig,
+        registry: &Registry,
+    ) -> Result<Self> {
+        Self::new_with_metric_labels(
+            input_path,
+            output_object_store_config,
+            db_checkpoint_config,
+            indirect_objects_threshold,
+            pruning_config,
+            registry,
+            HashMap::new(),
+        )
+    }
+    /// Like `new`, with `metric_labels` added to the registries of the replicas next to their
+    /// `destination`, e.g. the labels `registry` was created with
+    pub fn new_with_metric_labels(
+        input_path: &std::path::Path,
+        output_object_store_config: &ObjectStoreConfig,
+        db_checkpoint_config: &DBCheckpointConfig,
+        indirect_objects_threshold: usize,
+        pruning_config: AuthorityStorePruningConfig,
+        registry: &Registry,
+        metric_labels: HashMap<String, String>,
+    ) -> Result<Self> {
+        let mut handler = Self::new_for_destination(
+            input_path,
+            output_object_store_config,
+            db_checkpoint_config,
+            indirect_objects_threshold,
+            pruning_config,
+            registry,
+        )?;
+        if db_checkpoint_config.replica_remote_retention.len()
+            > db_checkpoint_config.replica_object_store_configs.len()
+        {
+            return Err(anyhow!(
+                "{} replica remote retention policies configured for {} replicas",
+                db_checkpoint_config.replica_remote_retention.len(),
+                db_checkpoint_config.replica_object_store_configs.len()
+            ));
+        }
+        for (index, replica_config) in db_checkpoint_config
+            .replica_object_store_configs
+            .iter()
+            .enumerate()
+        {
+            let name = replica_config.make()?.to_string();
+            let mut labels = metric_labels.clone();
+            labels.insert("destination".to_string(), name.clone());
+            let replica_registry = Registry::new_custom(None, Some(labels))?;
+            let mut replica = Self::new_for_destination(
+                input_path,
+                replica_config,
+                db_checkpoint_config,
+                indirect_objects_threshold,
+                pruning_config,
+                &replica_registry,
+            )?
+            .into_replica(index + 1, name, replica_registry);
+            replica.remote_retention = db_checkpoint_config.replica_remote_retention(index);
+            handler.add_replica(replica);
+        }
+        Ok(handler)
+    }
+    fn new_for_destination(
+        input_path: &std::path::Path,
+        output_object_store_config: &ObjectStoreConfig,
+        db_checkpoint_config: &DBCheckpointConfig,
+        indirect_objects_threshold: usize,
+        pruning_config: AuthorityStorePruningConfig,
+        registry: &Registry,
+    ) -> Result<Self> {
+        let input_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(input_path.to_path_buf()),
+            ..Default::default()
+        };
+        let input_object_store = input_store_config.make()?;
+        let (commands, command_receiver) = mpsc::channel(BACKUP_COMMANDS_CAPACITY);
+        let source = db_checkpoint_config
+            .source_layout
+            .as_ref()
+            .map(CheckpointSource::new)
+            .transpose()?
+            .unwrap_or_default();
+        let mut prune_and_compact_before_upload =
+            db_checkpoint_config.prune_and_compact_before_upload();
+        if source.is_read_only() && prune_and_compact_before_upload {
+            warn!("Db checkpoints are read-only snapshots, not pruning them before upload");
+            prune_and_compact_before_upload = false;
+        }
+        let skip_pruning_tables = db_checkpoint_config.pre_upload_skip_tables();
+        for table in skip_pruning_tables.iter() {
+            if !PRUNABLE_TABLES.contains(&table.as_str()) {
+                warn!("Table {table} in skip-tables of pre-upload-pruning is never pruned");
+            }
+        }
+        let upload_layout = db_checkpoint_config.upload_layout.unwrap_or_default();
+        let mut compression = db_checkpoint_config.compression.unwrap_or_default();
+        // Archives are always compressed with zstd, one frame per file
+        if compression != DBCheckpointCompression::None
+            && upload_layout == DBCheckpointUploadLayout::TarArchives
+        {
+            warn!("Archives are compressed as they are packed, not compressing files on their own");
+            compression = DBCheckpointCompression::None;
+        }
+        if compression != DBCheckpointCompression::None
+            && matches!(
+                upload_layout,
+                DBCheckpointUploadLayout::ContentDefinedChunks
+                    | DBCheckpointUploadLayout::MirroredWithSums
+            )
+        {
+            warn!(
+                "Upload layout {:?} doesn't support compression, uploading files uncompressed",
+                upload_layout
+            );
+            compression = DBCheckpointCompression::None;
+        }
+        // Frames are located in the compressed file, which encryption turns into a single blob
+        if compression == DBCheckpointCompression::ZstdSeekable
+            && db_checkpoint_config.encryption.is_some()
+        {
+            warn!(
+                "Encrypted files can't be read in parts, compressing them as a single zstd frame"
+            );
+            compression = DBCheckpointCompression::Zstd;
+        }
+        // Unlike compression, silently uploading in the clear is not an option
+        if db_checkpoint_config.encryption.is_some()
+            && matches!(
+                upload_layout,
+                DBCheckpointUploadLayout::ContentDefinedChunks
+                    | DBCheckpointUploadLayout::MirroredWithSums
+                    | DBCheckpointUploadLayout::TarArchives
+            )
+        {
+            return Err(anyhow!(
+                "Upload layout {:?} doesn't support encryption",
+                upload_layout
+            ));
+        }
+        let archive_size_bytes = db_checkpoint_config
+            .archive_size_bytes
+            .unwrap_or(DEFAULT_ARCHIVE_SIZE_BYTES);
+        if !ARCHIVE_SIZE_BYTES_RANGE.contains(&archive_size_bytes) {
+            return Err(anyhow!(
+                "archive-size-bytes must be between {} and {} bytes, got {archive_size_bytes}",
+                ARCHIVE_SIZE_BYTES_RANGE.start(),
+                ARCHIVE_SIZE_BYTES_RANGE.end()
+            ));
+        }
+        let verify_only = db_checkpoint_config.verify_only();
+        // The bucket belongs to another node, nothing may be written to it
+        let output_object_store = if verify_only {
+            output_object_store_config.read_only().make()?
+        } else {
+            output_object_store_config.make()?
+        };
+        let audit_log = db_checkpoint_config
+            .audit_actor
+            .as_ref()
+            .map(|actor| AuditLog::new(output_object_store.clone(), actor.clone()));
+        let state_object_store = source.state_store(&input_object_store);
+        Ok(DBCheckpointHandler {
+            gc_readiness: vec![Arc::new(MarkerReadiness::new(
+                UPLOAD_COMPLETED_MARKER,
+                state_object_store.clone(),
+            ))],
+            state_object_store,
+            input_object_store,
+            input_root_path: input_path.to_path_buf(),
+            source,
+            output_object_store,
+            interval: Duration::from_secs(db_checkpoint_config.upload_interval_s()),
+            prune_and_compact_before_upload,
+            upload_concurrency: db_checkpoint_config.upload_concurrency(),
+            upload_limiter: match &db_checkpoint_config.adaptive_concurrency {
+                Some(config) => {
+                    AdaptiveConcurrency::new(db_checkpoint_config.upload_concurrency(), config)
+                }
+                None => AdaptiveConcurrency::fixed(db_checkpoint_config.upload_concurrency()),
+            }
+            .with_bandwidth_limiter(BandwidthLimiter::new(
+                db_checkpoint_config.upload_rate_bytes_per_sec,
+            )),
+            num_local_epochs_to_retain: db_checkpoint_config.num_local_epochs_to_retain(),
+            verify_after_upload: db_checkpoint_config.verify_after_upload(),
+            verify_remote_checksums: db_checkpoint_config
+                .verify_remote_checksums
+                .unwrap_or(false),
+            upload_layout,
+            compression,
+            encryption: db_checkpoint_config.encryption.clone(),
+            encryption_key: OnceCell::new(),
+            indirect_objects_threshold,
+            pruning_config: db_checkpoint_config.pre_upload_pruning_config(&pruning_config),
+            skip_pruning_tables,
+            metrics: DBCheckpointMetrics::new(
+                registry,
+                db_checkpoint_config
+                    .epoch_metrics_window
+                    .unwrap_or(DEFAULT_EPOCH_METRICS_WINDOW),
+            ),
+            // Prefixed so as not to collide with the metrics of the pruner of the live db
+            pruning_metrics: AuthorityStorePruningMetrics::new_with_prefix(
+                registry,
+                PRUNING_METRICS_PREFIX,
+            ),
+            telemetry: BackupTelemetry::default(),
+            events: broadcast::channel(BACKUP_EVENTS_CAPACITY).0,
+            audit_log,
+            resource_limits: db_checkpoint_config
+                .resource_limits
+                .clone()
+                .unwrap_or_default(),
+            snapshot_backend: db_checkpoint_config.snapshot_backend.clone(),
+            pruning_db_options: None,
+            consistency_grace_period: db_checkpoint_config
+                .consistency_grace_period_s
+                .map(Duration::from_secs),
+            recent_uploads: Mutex::new(BTreeMap::new()),
+            first_missing_epoch: Mutex::new(None),
+            status: BackupStatusHandle::default(),
+            single_pass_digests: db_checkpoint_config.single_pass_digests.unwrap_or(false),
+            epoch_upload_deadline: db_checkpoint_config
+                .epoch_upload_deadline_s
+                .map(Duration::from_secs),
+            consolidate_epoch_metadata: db_checkpoint_config
+                .consolidate_epoch_metadata
+                .unwrap_or(false),
+            chain_identifier: None,
+            producer: None,
+            digest_pool: db_checkpoint_config
+                .digest_parallelism
+                .and_then(NonZeroUsize::new)
+                .map(DigestPool::new)
+                .unwrap_or_default(),
+            manifest_shard_size: NonZeroUsize::new(
+                db_checkpoint_config
+                    .manifest_shard_size
+                    .unwrap_or(DEFAULT_MANIFEST_SHARD_SIZE),
+            )
+            .ok_or_else(|| anyhow!("manifest-shard-size must be positive"))?,
+            remote_retention: db_checkpoint_config.remote_retention.clone(),
+            backup_watermark: None,
+            local_scrub_interval: db_checkpoint_config
+                .local_scrub_interval_s
+                .map(Duration::from_secs),
+            local_scrub_sample_size: db_checkpoint_config
+                .local_scrub_sample_size
+                .unwrap_or(DEFAULT_LOCAL_SCRUB_SAMPLE_SIZE),
+            staging_area: None,
+            verify_only,
+            verified_epochs: Mutex::new(BTreeSet::new()),
+            verification_budget: VerificationBudget::new(
+                db_checkpoint_config.verification_budget_bytes_per_day,
+            ),
+            content_coverage: Mutex::new(BTreeMap::new()),
+            upload_completed_marker: UPLOAD_COMPLETED_MARKER.to_string(),
+            upload_progress_filename: UPLOAD_PROGRESS_FILENAME.to_string(),
+            replica: None,
+            replicas: vec![],
+            commands,
+            command_receiver: Some(command_receiver),
+            upload_epochs: db_checkpoint_config
+                .upload_epochs
+                .clone()
+                .unwrap_or_default(),
+            quarantine_threshold: db_checkpoint_config
+                .upload_quarantine_threshold
+                .filter(|threshold| *threshold > 0),
+            quarantine_retry: Duration::from_secs(
+                db_checkpoint_config.upload_quarantine_retry_s(),
+            ),
+            upload_failures: Mutex::new(BTreeMap::new()),
+            remote_retention_deleted: Mutex::new(BTreeSet::new()),
+            admin_deleted_epochs: Mutex::new(BTreeSet::new()),
+            destination_probe_timeout: Duration::from_secs(
+                db_checkpoint_config.destination_probe_timeout_s(),
+            ),
+            disk_pressure_min_free_bytes: db_checkpoint_config.disk_pressure_min_free_bytes,
+            deleted_under_pressure: Mutex::new(BTreeSet::new()),
+            gc_lock: tokio::sync::Mutex::new(()),
+            gc_interval: Duration::from_secs(db_checkpoint_config.gc_interval_s()),
+            gc_dry_run: db_checkpoint_config.gc_dry_run.unwrap_or(false),
+            prune_and_compact_timeout: db_checkpoint_config
+                .prune_and_compact_timeout_s
+                .map(Duration::from_secs),
+            prune_job: Mutex::new(None),
+            watch_input_dir: db_checkpoint_config.watch_input_dir.unwrap_or(false),
+            creation_settle: Duration::from_secs(
+                db_checkpoint_config.creation_settle_s.unwrap_or(0),
+            ),
+            upload_order: db_checkpoint_config.upload_order.unwrap_or_default(),
+            archive_size_bytes,
+            replica_gc_wait: Duration::from_secs(db_checkpoint_config.replica_gc_wait_s()),
+        })
+    }
+    pub fn new_for_test(
+        input_object_store_config: &ObjectStoreConfig,
+        output_object_store_config: &ObjectStoreConfig,
+        interval_s: u64,
+        prune_and_compact_before_upload: bool,
+    ) -> Result<Self> {
+        let input_object_store = input_object_store_config.make()?;
+        let (commands, command_receiver) = mpsc::channel(BACKUP_COMMANDS_CAPACITY);
+        let upload_concurrency = DBCheckpointConfig::default().upload_concurrency();
+        Ok(DBCheckpointHandler {
+            gc_readiness: vec![
+                Arc::new(MarkerReadiness::new(
+                    UPLOAD_COMPLETED_MARKER,
+                    input_object_store.clone(),
+                )),
+                Arc::new(MarkerReadiness::new(
+                    TEST_MARKER,
+                    input_object_store.clone(),
+                )),
+            ],
+            state_object_store: input_object_store.clone(),
+            input_object_store,
+            input_root_path: input_object_store_config
+                .directory
+                .as_ref()
+                .unwrap()
+                .clone(),
+            source: CheckpointSource::default(),
+            output_object_store: output_object_store_config.make()?,
+            interval: Duration::from_secs(interval_s),
+            prune_and_compact_before_upload,
+            upload_concurrency,
+            upload_limiter: AdaptiveConcurrency::fixed(upload_concurrency),
+            num_local_epochs_to_retain: 0,
+            verify_after_upload: false,
+            verify_remote_checksums: false,
+            upload_layout: DBCheckpointUploadLayout::Mirrored,
+            compression: DBCheckpointCompression::None,
+            encryption: None,
+            encryption_key: OnceCell::new(),
+            indirect_objects_threshold: 0,
+            pruning_config: AuthorityStorePruningConfig::default(),
+            skip_pruning_tables: HashSet::new(),
+            metrics: DBCheckpointMetrics::new(&Registry::default(), DEFAULT_EPOCH_METRICS_WINDOW),
+            pruning_metrics: AuthorityStorePruningMetrics::new_for_test(),
+            telemetry: BackupTelemetry::default(),
+            events: broadcast::channel(BACKUP_EVENTS_CAPACITY).0,
+            audit_log: None,
+            resource_limits: BackupResourceLimits::default(),
+            snapshot_backend: None,
+            pruning_db_options: None,
+            consistency_grace_period: None,
+            recent_uploads: Mutex::new(BTreeMap::new()),
+            first_missing_epoch: Mutex::new(None),
+            status: BackupStatusHandle::default(),
+            single_pass_digests: false,
+            epoch_upload_deadline: None,
+            consolidate_epoch_metadata: false,
+            chain_identifier: None,
+            producer: None,
+            digest_pool: DigestPool::default(),
+            manifest_shard_size: NonZeroUsize::new(DEFAULT_MANIFEST_SHARD_SIZE).unwrap(),
+            remote_retention: None,
+            backup_watermark: None,
+            local_scrub_interval: None,
+            local_scrub_sample_size: DEFAULT_LOCAL_SCRUB_SAMPLE_SIZE,
+            staging_area: None,
+            verify_only: false,
+            verified_epochs: Mutex::new(BTreeSet::new()),
+            verification_budget: VerificationBudget::default(),
+            content_coverage: Mutex::new(BTreeMap::new()),
+            upload_completed_marker: UPLOAD_COMPLETED_MARKER.to_string(),
+            upload_progress_filename: UPLOAD_PROGRESS_FILENAME.to_string(),
+            replica: None,
+            replicas: vec![],
+            commands,
+            command_receiver: Some(command_receiver),
+            upload_epochs: UploadEpochsConfig::default(),
+            quarantine_threshold: None,
+            quarantine_retry: Duration::from_secs(
+                DBCheckpointConfig::default().upload_quarantine_retry_s(),
+            ),
+            upload_failures: Mutex::new(BTreeMap::new()),
+            remote_retention_deleted: Mutex::new(BTreeSet::new()),
+            admin_deleted_epochs: Mutex::new(BTreeSet::new()),
+            destination_probe_timeout: Duration::from_secs(10),
+            disk_pressure_min_free_bytes: None,
+            deleted_under_pressure: Mutex::new(BTreeSet::new()),
+            gc_lock: tokio::sync::Mutex::new(()),
+            gc_interval: Duration::from_secs(30),
+            gc_dry_run: false,
+            prune_and_compact_timeout: None,
+            prune_job: Mutex::new(None),
+            watch_input_dir: false,
+            creation_settle: Duration::ZERO,
+            upload_order: DBCheckpointUploadOrder::default(),
+            archive_size_bytes: DEFAULT_ARCHIVE_SIZE_BYTES,
+            replica_gc_wait: Duration::from_secs(DBCheckpointConfig::default().replica_gc_wait_s()),
+        })
+    }
+    /// Turns the handler into the one of replica number `index` of the output store, which
+    /// marks its uploads with markers of its own
+    fn into_replica(mut self, index: usize, name: String, registry: Registry) -> Self {
+        self.upload_completed_marker = format!("{UPLOAD_COMPLETED_MARKER}{REPLICA_SUFFIX}{index}");
+        self.upload_progress_filename =
+            format!("{UPLOAD_PROGRESS_FILENAME}{REPLICA_SUFFIX}{index}");
+        self.prune_and_compact_before_upload = false;
+        self.local_scrub_interval = None;
+        // Uploads to replicas are triggered by the handler of the output store
+        self.watch_input_dir = false;
+        self.replica = Some(ReplicaDestination { name, registry });
+        self
+    }
+    /// Uploads to `replica` along with the output store, local db checkpoints are only garbage
+    /// collected once uploaded to both, or once `replica_gc_wait` passed since the upload to
+    /// the output store
+    fn add_replica(&mut self, replica: DBCheckpointHandler) {
+        self.gc_readiness.push(Arc::new(ReplicaReadiness::new(
+            replica.upload_completed_marker.clone(),
+            self.upload_completed_marker.clone(),
+            self.replica_gc_wait,
+            self.state_object_store.clone(),
+        )));
+        self.replicas.push(replica);
+    }
+    /// Registries of the metrics of the replicas of the output store, labelled with their
+    /// `destination`, to be served next to the registry of the handler
+    pub fn replica_registries(&self) -> Vec<Registry> {
+        self.replicas
+            .iter()
+            .filter_map(|replica| replica.replica.as_ref())
+            .map(|replica| replica.registry.clone())
+            .collect()
+    }
+    /// Sends commands to the handler once it is started, e.g. to upload right away. Must be
+    /// called after `with_status`, the commander reads and pauses the uploads of that status.
+    pub fn commander(&self) -> BackupCommander {
+        BackupCommander::new(self.commands.clone(), self.status.clone()).with_replicas(
+            self.replicas
+                .iter()
+                .map(DBCheckpointHandler::commander)
+                .collect(),
+        )
+    }
+    /// Stream of the events published by the handler from now on. Must be called before
+    /// `start`. A subscriber that falls more than `BACKUP_EVENTS_CAPACITY` events behind gets a
+    /// lagged error and skips the oldest ones.
+    pub fn subscribe(&self) -> BroadcastStream<BackupEvent> {
+        BroadcastStream::new(self.events.subscribe())
+    }
+    /// `subscribe` merged with the streams of the events of the replicas, each event coming
+    /// with the name of the replica it is about, `None` for the output store
+    pub fn subscribe_with_replicas(
+        &self,
+    ) -> BoxStream<'static, (Option<String>, Result<BackupEvent, BroadcastStreamRecvError>)> {
+        let mut streams = vec![self.subscribe().map(|event| (None, event)).boxed()];
+        for replica in self.replicas.iter() {
+            let name = replica
+                .replica
+                .as_ref()
+                .map(|destination| destination.name.clone());
+            streams.push(
+                replica
+                    .subscribe()
+                    .map(move |event| (name.clone(), event))
+                    .boxed(),
+            );
+        }
+        futures::stream::select_all(streams).boxed()
+    }
+    fn publish(&self, event: BackupEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+    /// Open the perpetual db of db checkpoints with `options` when pruning them. Passing a clone
+    /// of the options of the live db makes both share its rate limiter.
+    pub fn with_pruning_db_options(mut self, options: rocksdb::Options) -> Self {
+        self.pruning_db_options = Some(options);
+        self
+    }
+    /// Label the uploaded db checkpoints with `chain_identifier`, so that restores into a node
+    /// of another chain are refused
+    pub fn with_chain_identifier(mut self, chain_identifier: ChainIdentifier) -> Self {
+        self.chain_identifier = Some(chain_identifier);
+        self
+    }
+    pub fn with_producer(mut self, producer: BackupProducer) -> Self {
+        self.producer = Some(producer);
+        self
+    }
+    pub fn with_status(mut self, status: BackupStatusHandle) -> Self {
+        self.status = status;
+        self
+    }
+    /// Keep local db checkpoints until `condition` lets them be deleted, on top of their uploads
+    pub fn with_gc_readiness(mut self, condition: Arc<dyn GcReadiness>) -> Self {
+        self.gc_readiness.push(condition);
+        self
+    }
+    pub fn with_backup_watermark(mut self, backup_watermark: BackupWatermark) -> Self {
+        self.backup_watermark = Some(backup_watermark);
+        self
+    }
+    /// Periodically remove the staging dirs left behind by interrupted restores, smoke tests
+    /// and benchmarks in `staging_area`
+    pub fn with_staging_area(mut self, staging_area: StagingArea) -> Self {
+        self.staging_area = Some(staging_area);
+        self
+    }
+    /// Forward backup lifecycle events to the node's telemetry subsystem
+    pub fn with_telemetry(mut self, sender: mpsc::Sender<BackupTelemetryEvent>) -> Self {
+        self.telemetry = BackupTelemetry::new(sender);
+        self
+    }
+    pub fn start(mut self) -> TaskHandle {
+        let (handle, mut signal) = TaskHandle::new();
+        let commands = self
+            .command_receiver
+            .take()
+            .expect("Db checkpoint handler started twice");
+        let replica_commanders: Vec<BackupCommander> = self
+            .replicas
+            .iter()
+            .map(DBCheckpointHandler::commander)
+            .collect();
+        let replicas = std::mem::take(&mut self.replicas);
+        // Replicas run on the runtime of the caller, even when the handler runs on its own
+        let runtime = tokio::runtime::Handle::current();
+        let span = match &self.replica {
+            Some(replica) => info_span!("db_checkpoint_replica", destination = %replica.name),
+            None => Span::none(),
+        };
+        let resource_limits = self.resource_limits.clone();
+        let usage = self.metrics.usage.clone();
+        self.digest_pool = self.digest_pool.clone().with_usage(usage.clone());
+        let run = async move {
+            // Before the replicas start, since they read the same local state
+            self.recover_interrupted_operations().await;
+            let replica_handles: Vec<TaskHandle> = {
+                let _runtime = runtime.enter();
+                replicas
+                    .into_iter()
+                    .map(DBCheckpointHandler::start)
+                    .collect()
+            };
+            self.run(&mut signal, commands, replica_commanders).await;
+            // Replicas stop along with the handler of the output store, which only counts as
+            // stopped once they did
+            for replica_handle in replica_handles {
+                replica_handle.shutdown().await;
+            }
+        };
+        let run = usage.measure_future(run).instrument(span);
+        if resource_limits.has_thread_priorities() {
+            // Run on a dedicated thread so that lowered priorities don't leak into the
+            // runtime shared with the rest of the node
+            std::thread::Builder::new()
+                .name("db-checkpoint-handler".to_string())
+                .spawn(move || {
+                    if let Err(err) = apply_thread_priorities(&resource_limits) {
+                        warn!("Failed to lower priorities of backup worker: {:?}", err);
+                    }
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .expect("Failed to build db checkpoint handler runtime")
+                        .block_on(run);
+                })
+                .expect("Failed to spawn db checkpoint handler thread");
+        } else {
+            tokio::task::spawn(run);
+        }
+        handle
+    }
+    /// Samples the number of files of the local db checkpoints and staging dirs held open
+    fn record_open_files(&self) {
+        let roots: Vec<PathBuf> = std::iter::once(self.input_root_path.clone())
+            .chain(
+                self.staging_area
+                    .as_ref()
+                    .map(|staging_area| staging_area.root().to_path_buf()),
+            )
+            .collect();
+        match open_files_under(&roots) {
+            Ok(Some(count)) => self.metrics.open_files.set(count as i64),
+            Ok(None) => {}
+            Err(err) => debug!("Failed to count open files of db checkpoints: {:?}", err),
+        }
+    }
+    /// Checks with a single cheap request that the remote store answers before an upload pass
+    /// lists and uploads to it, so that an outage shows as such rather than as the errors of
+    /// every request of the pass. Returns whether the store is reachable.
+    async fn probe_destination(&self) -> bool {
+        let probe = tokio::time::timeout(
+            self.destination_probe_timeout,
+            self.output_object_store.head(&Path::from(PROBE_FILENAME)),
+        )
+        .await;
+        let err = match probe {
+            // The probed object doesn't need to exist, only the store needs to answer
+            Ok(Ok(_)) | Ok(Err(object_store::Error::NotFound { .. })) => {
+                self.metrics.destination_unreachable.set(0);
+                return true;
+            }
+            Ok(Err(err)) => anyhow!(err),
+            Err(_) => anyhow!(
+                "Remote store timed out after {:?}",
+                self.destination_probe_timeout
+            ),
+        };
+        let cause = ProbableCause::classify(&err);
+        warn!(probable_cause = %cause, remediation = cause.remediation(), "Skipping upload pass, remote store is unreachable: {:?}", err);
+        // Reported once per outage rather than on every skipped pass
+        if self.metrics.destination_unreachable.get() == 0 {
+            self.publish(BackupEvent::Error {
+                epoch: None,
+                error: format!("Remote store is unreachable: {:?}", err),
+            });
+        }
+        self.metrics.destination_unreachable.set(1);
+        false
+    }
+    /// Runs an upload pass while still checking the free space on the disk, which uploads
+    /// slower than new db checkpoints are taken would fill up meanwhile
+    async fn scan_and_upload_watched(&self, disk_pressure_interval: &mut tokio::time::Interval) {
+        let pass = self.scan_and_upload();
+        tokio::pin!(pass);
+        loop {
+            tokio::select! {
+                _ = &mut pass => return,
+                _ = disk_pressure_interval.tick(), if self.disk_pressure_min_free_bytes.is_some() => {
+                    self.relieve_disk_pressure().await
+                },
+            }
+        }
+    }
+    /// Deletes uploaded local db checkpoints, including those kept by
+    /// `num_local_epochs_to_retain`, while less than `disk_pressure_min_free_bytes` are free on
+    /// their disk. Alerts when that isn't enough.
+    async fn relieve_disk_pressure(&self) {
+        let Some(min_free_bytes) = self.disk_pressure_min_free_bytes else {
+            return;
+        };
+        if self.source.is_read_only() || self.replica.is_some() {
+            // Local db checkpoints are deleted by the snapshot tooling or the primary handler
+            return;
+        }
+        let available = match available_space(&self.input_root_path) {
+            Ok(available) => available,
+            Err(err) => {
+                debug!(
+                    "Failed to read free space of local db checkpoints: {:?}",
+                    err
+                );
+                return;
+            }
+        };
+        if available >= min_free_bytes {
+            self.metrics.disk_pressure.set(0);
+            return;
+        }
+        // Held by the garbage collection of the upload pass this check interrupted, which frees
+        // the disk already and can't go on before this check returns
+        if self.gc_lock.try_lock().is_err() {
+            return;
+        }
+        warn!(
+            "Only {available} bytes are free on the disk of local db checkpoints, less than {min_free_bytes}, deleting uploaded ones"
+        );
+        match self
+            .delete_uploaded_db_checkpoints(0, Some(min_free_bytes))
+            .await
+        {
+            Ok(deleted) => {
+                if !deleted.is_empty() {
+                    info!(
+                        "Deleted uploaded local db checkpoints to free the disk: {:?}",
+                        deleted
+                    );
+                    self.metrics
+                        .disk_pressure_deleted_epochs_total
+                        .inc_by(deleted.len() as u64);
+                    self.deleted_under_pressure.lock().extend(deleted);
+                }
+            }
+            Err(err) => warn!(
+                "Failed to delete uploaded local db checkpoints to free the disk: {:?}",
+                err
+            ),
+        }
+        let available = available_space(&self.input_root_path).unwrap_or(available);
+        if available >= min_free_bytes {
+            self.metrics.disk_pressure.set(0);
+            return;
+        }
+        let pending = pending_uploads(&self.input_root_path).unwrap_or_default();
+        error!(
+            "Only {available} bytes are free on the disk of local db checkpoints, less than {min_free_bytes}, with no uploaded db checkpoint left to delete. Db checkpoints of epochs {:?} are not uploaded yet",
+            pending
+        );
+        // Reported once per episode rather than on every check
+        if self.metrics.disk_pressure.get() == 0 {
+            self.publish(BackupEvent::Error {
+                epoch: None,
+                error: format!(
+                    "Only {available} bytes are free on the disk of local db checkpoints, with no uploaded db checkpoint left to delete"
+                ),
+            });
+        }
+        self.metrics.disk_pressure.set(1);
+    }
+    /// Scans the remote store for missing epochs and uploads them, or verifies the epochs of the
+    /// remote store in verify-only mode
+    async fn scan_and_upload(&self) {
+        if self.status.is_paused() {
+            debug!("Skipping upload pass, uploads are paused");
+            return;
+        }
+        if !self.probe_destination().await {
+            return;
+        }
+        if self.verify_only {
+            // Failed epochs are reported by the verification itself
+            if let Err(err) = self.verify_remote_epochs().await {
+                warn!("Failed to verify remote db checkpoints: {:?}", err);
+            }
+            return;
+        }
+        match self.find_all_missing_checkpoint_epochs().await {
+            Ok(epochs) => {
+                self.record_missing_epochs(&epochs);
+                if let Err(err) = self.upload_db_checkpoints_to_object_store(epochs).await {
+                    let cause = ProbableCause::classify(&err);
+                    error!(probable_cause = %cause, remediation = cause.remediation(), "Failed to upload db checkpoint to remote store with err: {:?}", err);
+                }
+            }
+            Err(err) => {
+                let cause = ProbableCause::classify(&err);
+                error!(probable_cause = %cause, remediation = cause.remediation(), "Failed to find missing db checkpoints with err: {:?}", err);
+                self.publish(BackupEvent::Error {
+                    epoch: None,
+                    error: "Failed to find missing db checkpoints".to_string(),
+                });
+            }
+        }
+    }
+    /// Reconciles the local state left behind by the operations the previous run of the node
+    /// was interrupted in, so that none of them trips over it when started again
+    async fn recover_interrupted_operations(&self) {
+        // Replicas share the local db checkpoints of the handler of the output store
+        if self.replica.is_some() {
+            return;
+        }
+        let input_root_path = self.input_root_path.clone();
+        let state_root = self.source.state_root(&self.input_root_path);
+        let source = self.source.clone();
+        let staging_area = self.staging_area.clone();
+        // Walks every local db checkpoint, which must not stall the runtime
+        let recovered = tokio::task::spawn_blocking(move || {
+            recover_interrupted_operations(
+                &input_root_path,
+                &state_root,
+                &source,
+                staging_area.as_ref(),
+            )
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|recovered| recovered);
+        match recovered {
+            Ok(report) => {
+                if !report.is_empty() {
+                    info!("Recovered from interrupted operations: {:?}", report);
+                }
+            }
+            Err(err) => warn!("Failed to recover from interrupted operations: {:?}", err),
+        }
+    }
+    async fn run(
+        self,
+        signal: &mut ShutdownSignal,
+        mut commands: mpsc::Receiver<BackupCommand>,
+        replica_commanders: Vec<BackupCommander>,
+    ) {
+        let mut interval = tokio::time::interval(self.interval);
+        let mut gc_interval = tokio::time::interval(self.gc_interval);
+        let mut scrub_interval = tokio::time::interval(
+            self.local_scrub_interval
+                .unwrap_or(Duration::from_secs(u32::MAX as u64)),
+        );
+        let mut usage_interval = tokio::time::interval(USAGE_SAMPLE_INTERVAL);
+        let mut disk_pressure_interval = tokio::time::interval(DISK_PRESSURE_CHECK_INTERVAL);
+        let mut watcher = if self.watch_input_dir {
+            match CheckpointWatcher::new(&self.input_root_path) {
+                Ok(watcher) => Some(watcher),
+                Err(err) => {
+                    warn!(
+                        "Failed to watch db checkpoints, polling every {:?} instead: {:?}",
+                        self.interval, err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        info!("DB checkpoint handler loop started");
+        loop {
+            tokio::select! {
+                // Passes can wait hours for pruning and compaction, which must not hold up shutdown
+                _now = interval.tick() => tokio::select! {
+                    _ = self.scan_and_upload_watched(&mut disk_pressure_interval) => {},
+                    _ = signal.recv() => break,
+                },
+                Some(command) = commands.recv() => match command {
+                    BackupCommand::UploadNow => {
+                        info!("Scanning for db checkpoints to upload on demand");
+                        for replica in replica_commanders.iter() {
+                            if let Err(err) = replica.upload_now() {
+                                warn!("Failed to trigger upload to replica: {:?}", err);
+                            }
+                        }
+                        tokio::select! {
+                            _ = self.scan_and_upload_watched(&mut disk_pressure_interval) => {},
+                            _ = signal.recv() => break,
+                        }
+                    }
+                    BackupCommand::DeleteRemoteEpoch(epoch) => {
+                        if let Err(err) = self.delete_remote_epoch(epoch).await {
+                            warn!("Failed to delete remote db checkpoint of epoch: {epoch}: {:?}", err);
+                        }
+                    }
+                },
+                result = next_new_epoch(watcher.as_mut(), &self.source) => match result {
+                    Ok(epoch) => {
+                        info!("Db checkpoint of epoch: {epoch} showed up, scanning for db checkpoints to upload");
+                        tokio::time::sleep(WATCH_SETTLE_DELAY).await;
+                        for replica in replica_commanders.iter() {
+                            if let Err(err) = replica.upload_now() {
+                                warn!("Failed to trigger upload to replica: {:?}", err);
+                            }
+                        }
+                        tokio::select! {
+                            _ = self.scan_and_upload_watched(&mut disk_pressure_interval) => {},
+                            _ = signal.recv() => break,
+                        }
+                        // The epoch was just scanned for, no need to poll right away
+                        interval.reset();
+                    }
+                    Err(err) => {
+                        warn!("Stopped watching db checkpoints, polling every {:?} instead: {:?}", self.interval, err);
+                        watcher = None;
+                    }
+                },
+                _ = gc_interval.tick() => {
+                    match self.garbage_collect_old_db_checkpoints().await {
+                        Ok(deleted) => {
+                            if !deleted.is_empty() {
+                                info!("Garbage collected local db checkpoints: {:?}", deleted);
+                            }
+                        }
+                        Err(err) => {
+                            let cause = ProbableCause::classify(&err);
+                            warn!(probable_cause = %cause, remediation = cause.remediation(), "Failed to garbage collect local db checkpoints: {:?}", err);
+                            self.publish(BackupEvent::Error { epoch: None, error: format!("{:?}", err) });
+                        }
+                    }
+                    match self.apply_remote_retention().await {
+                        Ok(deleted) => {
+                            if !deleted.is_empty() {
+                                info!("Deleted remote db checkpoints by retention: {:?}", deleted);
+                            }
+                        }
+                        Err(err) => {
+                            let cause = ProbableCause::classify(&err);
+                            warn!(probable_cause = %cause, remediation = cause.remediation(), "Failed to apply retention to remote db checkpoints: {:?}", err);
+                            self.publish(BackupEvent::Error { epoch: None, error: format!("{:?}", err) });
+                        }
+                    }
+                    if let Some(staging_area) = &self.staging_area {
+                        if let Err(err) = staging_area.cleanup_abandoned() {
+                            warn!("Failed to clean up abandoned staging dirs: {:?}", err);
+                        }
+                    }
+                },
+                _ = scrub_interval.tick(), if self.local_scrub_interval.is_some() => {
+                    // Corrupted files are reported by the scrub itself
+                    if let Err(err) = self.scrub_local_db_checkpoints().await {
+                        warn!("Failed to scrub local db checkpoints: {:?}", err);
+                    }
+                },
+                _ = usage_interval.tick() => self.record_open_files(),
+                _ = disk_pressure_interval.tick(), if self.disk_pressure_min_free_bytes.is_some() => {
+                    self.relieve_disk_pressure().await
+                },
+                _ = signal.recv() => break,
+            }
+        }
+    }
+    /// Prunes and compacts the db checkpoint at `db_path` on a thread of its own, returning how
+    /// many bytes every table of the perpetual db shrank by, or `None` if it is still running
+    /// when the prune and compact timeout passes. The job keeps running then, and a later upload
+    /// attempt of the epoch picks up its result.
+    async fn prune_and_compact(
+        &self,
+        db_path: PathBuf,
+        epoch: u32,
+    ) -> Result<Option<BTreeMap<String, i64>>> {
+        let result = self
+            .prune_job
+            .lock()
+            .get_or_insert_with(|| PruneJob::spawn(self.prune_settings(), db_path, epoch))
+            .wait(self.prune_and_compact_timeout);
+        let Some(result) = result.await else {
+            if let Some(job) = self.prune_job.lock().as_mut() {
+                if job.mark_timed_out() {
+                    self.metrics.prune_and_compact_timeouts_total.inc();
+                    warn!(
+                        "Pruning and compaction of db checkpoint for epoch: {epoch} still running after {:?}, deferring its upload",
+                        self.prune_and_compact_timeout.unwrap_or_default()
+                    );
+                }
+            }
+            return Ok(None);
+        };
+        self.prune_job.lock().take();
+        result.map(Some)
+    }
+    fn prune_settings(&self) -> PruneSettings {
+        PruneSettings {
+            db_options: self.pruning_db_options.clone(),
+            pruning_config: self.pruning_config,
+            indirect_objects_threshold: self.indirect_objects_threshold,
+            skip_tables: self.skip_pruning_tables.clone(),
+            pruning_metrics: self.pruning_metrics.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+    async fn find_all_missing_checkpoint_epochs(&self) -> Result<Vec<u32>> {
+        let listing = scan_epoch_dirs(self.output_object_store.clone(), None).await?;
+        // Shared buckets may hold lifecycle markers or the data of other tools
+        let unexpected: Vec<&Path> = listing
+            .other_entries
+            .iter()
+            .filter(|entry| {
+                !matches!(
+                    entry.filename(),
+                    Some(CHUNKS_DIR) | Some(AUDIT_DIR) | Some(BUNDLES_DIR)
+                )
+            })
+            .collect();
+        for entry in unexpected.iter() {
+            debug!("Ignoring unexpected entry in remote store: {entry}");
+        }
+        self.metrics
+            .unexpected_remote_entries
+            .set(unexpected.len() as i64);
+        let mut missing_epochs = missing_epochs_of(
+            self.output_object_store.clone(),
+            &listing.epoch_dirs,
+            &EpochCompleteness::Marker(SUCCESS_MARKER.to_string()),
+            self.upload_epochs.start_epoch.unwrap_or(0),
+        )
+        .await?;
+        if let Some(backup_watermark) = &self.backup_watermark {
+            if let Some(epoch) = listing
+                .epoch_dirs
+                .keys()
+                .rev()
+                .find(|epoch| !missing_epochs.contains(epoch))
+            {
+                backup_watermark.confirm(*epoch as u64);
+            }
+        }
+        // Gaps the remote retention left are not missing uploads
+        let retention_gaps = self
+            .remote_retention_gaps(&listing.epoch_dirs, &missing_epochs)
+            .await?;
+        missing_epochs.retain(|epoch| !retention_gaps.contains(epoch));
+        let admin_deleted_epochs = self.admin_deleted_epochs.lock().clone();
+        missing_epochs.retain(|epoch| !admin_deleted_epochs.contains(epoch));
+        // Epochs moved into range bundles are archived rather than missing
+        if listing
+            .other_entries
+            .iter()
+            .any(|entry| entry.filename() == Some(BUNDLES_DIR))
+        {
+            let bundled = bundled_epochs(self.output_object_store.clone()).await?;
+            let next_epoch = missing_epochs.last().cloned();
+            missing_epochs.retain(|epoch| Some(*epoch) == next_epoch || !bundled.contains(epoch));
+        }
+        // Epochs the node doesn't upload would stay missing forever. The epoch after the latest
+        // one in the remote store is kept, later epochs are uploaded from it on.
+        let next_epoch = missing_epochs.last().cloned();
+        missing_epochs
+            .retain(|epoch| Some(*epoch) == next_epoch || self.upload_epochs.contains(*epoch));
+        Ok(self.recheck_recent_uploads(missing_epochs).await)
+    }
+    /// The gaps among `missing_epochs` below the latest remote epoch which the remote retention
+    /// deleted: those the policy deletes anyway, and those deleted by this handler or recorded
+    /// as deleted in the audit log. Other gaps are uploads still missing.
+    async fn remote_retention_gaps(
+        &self,
+        epoch_dirs: &BTreeMap<u32, Path>,
+        missing_epochs: &[u32],
+    ) -> Result<BTreeSet<u32>> {
+        let (Some(policy), Some(latest)) = (&self.remote_retention, epoch_dirs.keys().next_back())
+        else {
+            return Ok(BTreeSet::new());
+        };
+        if !policy.is_enabled() {
+            return Ok(BTreeSet::new());
+        }
+        let gaps: Vec<u32> = missing_epochs
+            .iter()
+            .filter(|epoch| *epoch < latest && !epoch_dirs.contains_key(epoch))
+            .cloned()
+            .collect();
+        if gaps.is_empty() {
+            return Ok(BTreeSet::new());
+        }
+        // Sizes are left out, the byte budget only deletes epochs the audit log records
+        let epochs: BTreeMap<u32, u64> = epoch_dirs
+            .keys()
+            .chain(gaps.iter())
+            .map(|epoch| (*epoch, 0))
+            .collect();
+        let below_floor: BTreeSet<u32> = policy.select_for_deletion(&epochs).into_iter().collect();
+        let mut retention_gaps = BTreeSet::new();
+        for epoch in gaps {
+            if below_floor.contains(&epoch) || self.remote_retention_deleted.lock().contains(&epoch)
+            {
+                retention_gaps.insert(epoch);
+                continue;
+            }
+            let deleted = read_audit_log(self.output_object_store.clone(), Some(epoch))
+                .await?
+                .iter()
+                .any(|record| record.action == AuditAction::RemoteRetentionDelete);
+            if deleted {
+                self.remote_retention_deleted.lock().insert(epoch);
+                retention_gaps.insert(epoch);
+            }
+        }
+        Ok(retention_gaps)
+    }
+    /// Updates the status and metrics of the missing epochs found by an upload pass
+    fn record_missing_epochs(&self, missing_epochs: &[u32]) {
+        self.status.set_missing_epochs(missing_epochs);
+        self.metrics
+            .missing_db_checkpoint_epochs_count
+            .set(self.status.status().num_missing_epochs as i64);
+        self.record_first_missing_epoch(missing_epochs);
+    }
+    /// Updates the first missing epoch. It only moves backwards when an epoch which was complete
+    /// in the remote store lost its success marker, which means the bucket was tampered with or
+    /// lost data rather than uploads lagging behind.
+    fn record_first_missing_epoch(&self, missing_epochs: &[u32]) {
+        let Some(first_missing_epoch) = missing_epochs.first().cloned() else {
+            self.metrics.first_missing_db_checkpoint_epoch.set(0);
+            return;
+        };
+        self.metrics
+            .first_missing_db_checkpoint_epoch
+            .set(first_missing_epoch as i64);
+        let previous = self.first_missing_epoch.lock().replace(first_missing_epoch);
+        let Some(previous) = previous.filter(|previous| first_missing_epoch < *previous) else {
+            return;
+        };
+        self.metrics
+            .first_missing_db_checkpoint_epoch_regressions
+            .inc();
+        error!(
+            "First missing db checkpoint epoch moved back from {previous} to {first_missing_epoch}, epochs already in the remote store were deleted or tampered with"
+        );
+        self.telemetry
+            .emit(BackupTelemetryEvent::FirstMissingEpochRegressed {
+                previous,
+                current: first_missing_epoch,
+            });
+        self.publish(BackupEvent::FirstMissingEpochRegressed {
+            previous,
+            current: first_missing_epoch,
+        });
+    }
+    /// Drops the epochs uploaded within the grace period from `missing_epochs` if their success
+    /// marker becomes visible before the grace period ends
+    async fn recheck_recent_uploads(&self, missing_epochs: Vec<u32>) -> Vec<u32> {
+        let Some(grace_period) = self.consistency_grace_period else {
+            return missing_epochs;
+        };
+        let recent_uploads: BTreeMap<u32, Instant> = {
+            let mut recent_uploads = self.recent_uploads.lock();
+            recent_uploads.retain(|_, uploaded_at| uploaded_at.elapsed() < grace_period);
+            recent_uploads.clone()
+        };
+        let last_missing_epoch = missing_epochs.last().cloned();
+        let mut result = Vec::with_capacity(missing_epochs.len());
+        for epoch in missing_epochs {
+            if let Some(uploaded_at) = recent_uploads.get(&epoch) {
+                let deadline = *uploaded_at + grace_period;
+                if self.wait_for_success_marker(epoch, deadline).await {
+                    info!("Success marker of recently uploaded epoch: {epoch} is visible, not reporting it as missing");
+                    // Uploads resume after the last missing epoch, which must not go backwards
+                    if Some(epoch) == last_missing_epoch {
+                        result.push(epoch + 1);
+                    }
+                    continue;
+                }
+            }
+            result.push(epoch);
+        }
+        result
+    }
+    /// Checks for the success marker of `epoch` with exponential backoff until `deadline`
+    async fn wait_for_success_marker(&self, epoch: u32, deadline: Instant) -> bool {
+        let success_marker = remote_epoch_dir(epoch).child(SUCCESS_MARKER);
+        let mut delay = Duration::from_millis(500);
+        loop {
+            if self.output_object_store.head(&success_marker).await.is_ok() {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            tokio::time::sleep(delay.min(deadline - now)).await;
+            delay *= 2;
+        }
+    }
+    /// Counts a failed upload of `epoch`, returns whether the epoch is quarantined as a result
+    fn record_upload_failure(&self, epoch: u32) -> bool {
+        let mut upload_failures = self.upload_failures.lock();
+        let failures = upload_failures.entry(epoch).or_insert(UploadFailures {
+            count: 0,
+            last: Instant::now(),
+        });
+        failures.count += 1;
+        failures.last = Instant::now();
+        if self
+            .quarantine_threshold
+            .map_or(true, |threshold| failures.count < threshold)
+        {
+            return false;
+        }
+        self.report_quarantined_epochs(&upload_failures);
+        true
+    }
+    /// Clears the failed uploads of `epoch` once it is uploaded
+    fn clear_upload_failures(&self, epoch: u32) {
+        let mut upload_failures = self.upload_failures.lock();
+        if upload_failures.remove(&epoch).is_some() {
+            self.report_quarantined_epochs(&upload_failures);
+        }
+    }
+    fn report_quarantined_epochs(&self, upload_failures: &BTreeMap<u32, UploadFailures>) {
+        let quarantined_epochs = upload_failures
+            .keys()
+            .filter(|epoch| self.is_quarantined_with(upload_failures, **epoch))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.metrics
+            .quarantined_epochs
+            .set(quarantined_epochs.len() as i64);
+        self.status.set_quarantined_epochs(quarantined_epochs);
+    }
+    fn is_quarantined(&self, epoch: u32) -> bool {
+        self.is_quarantined_with(&self.upload_failures.lock(), epoch)
+    }
+    fn is_quarantined_with(
+        &self,
+        upload_failures: &BTreeMap<u32, UploadFailures>,
+        epoch: u32,
+    ) -> bool {
+        match (self.quarantine_threshold, upload_failures.get(&epoch)) {
+            (Some(threshold), Some(failures)) => {
+                failures.count >= threshold && failures.last.elapsed() < self.quarantine_retry
+            }
+            _ => false,
+        }
+    }
+    async fn upload_db_checkpoints_to_object_store(&self, missing_epochs: Vec<u32>) -> Result<()> {
+        let last_missing_epoch = missing_epochs.last().cloned().unwrap_or(0);
+        // Only the epochs deleted after this listing need to be skipped
+        self.deleted_under_pressure.lock().clear();
+        let local_checkpoints_by_epoch = self.source.list(self.input_object_store.clone()).await?;
+        let needs_upload = |epoch: &u32| {
+            (missing_epochs.contains(epoch) || *epoch >= last_missing_epoch)
+                && self.upload_epochs.contains(*epoch)
+        };
+        let mut local_checkpoints: Vec<_> = local_checkpoints_by_epoch.iter().collect();
+        if self.upload_order == DBCheckpointUploadOrder::NewestFirst {
+            local_checkpoints.reverse();
+        }
+        let mut garbage_collected = BTreeSet::new();
+        for (index, &(epoch, local)) in local_checkpoints.iter().enumerate() {
+            if garbage_collected.contains(epoch)
+                || self.deleted_under_pressure.lock().contains(epoch)
+            {
+                continue;
+            }
+            if self.status.is_paused() {
+                info!("Uploads paused, stopping upload pass before epoch: {epoch}");
+                return Ok(());
+            }
+            let mut uploaded = false;
+            if needs_upload(epoch) {
+                if self.is_quarantined(*epoch) {
+                    debug!("Skipping quarantined db checkpoint for epoch: {epoch}");
+                    continue;
+                }
+                if self.replica.is_some() && !self.is_prepared(local).await {
+                    debug!("Db checkpoint for epoch: {epoch} not prepared for upload yet");
+                    continue;
+                }
+                self.publish(BackupEvent::EpochDiscovered { epoch: *epoch });
+                self.telemetry
+                    .emit(BackupTelemetryEvent::EpochUploadStarted { epoch: *epoch });
+                self.publish(BackupEvent::UploadStarted { epoch: *epoch });
+                self.metrics
+                    .epoch_window
+                    .set_status(*epoch, EpochUploadStatus::Started);
+                let start = Instant::now();
+                let outcome = match self.upload_db_checkpoint(local).await {
+                    Ok(outcome) => outcome,
+                    Err(err) => {
+                        self.metrics
+                            .epoch_window
+                            .set_status(*epoch, EpochUploadStatus::Failed);
+                        self.metrics.upload_failures_total.inc();
+                        self.telemetry
+                            .emit(BackupTelemetryEvent::EpochUploadFailed {
+                                epoch: *epoch,
+                                cause: ProbableCause::classify(&err),
+                                error: format!("{:?}", err),
+                            });
+                        self.publish(BackupEvent::Error {
+                            epoch: Some(*epoch),
+                            error: format!("{:?}", err),
+                        });
+                        if is_epoch_specific(&err) && self.record_upload_failure(*epoch) {
+                            error!(
+                                "Quarantined db checkpoint for epoch: {epoch} after {} failed uploads in a row, retrying it in {:?}. Last error: {:?}",
+                                self.quarantine_threshold.unwrap_or_default(),
+                                self.quarantine_retry,
+                                err
+                            );
+                            continue;
+                        }
+                        if err.downcast_ref::<LocalCorruption>().is_some() {
+                            // Bitrot is recorded above, the other epochs don't depend on this one
+                            continue;
+                        }
+                        return Err(err);
+                    }
+                };
+                self.clear_upload_failures(*epoch);
+                if outcome == UploadOutcome::Deferred {
+                    self.metrics
+                        .epoch_window
+                        .set_status(*epoch, EpochUploadStatus::Deferred);
+                    // Newer epochs are uploaded meanwhile, they don't depend on this one
+                    continue;
+                }
+                let duration = start.elapsed();
+                if let Some(backup_watermark) = &self.backup_watermark {
+                    backup_watermark.confirm(*epoch as u64);
+                }
+                self.metrics.epoch_window.set_completed(*epoch, duration);
+                self.metrics
+                    .upload_duration_seconds
+                    .observe(duration.as_secs_f64());
+                // Catch-ups of gaps upload older epochs after newer ones
+                if *epoch as i64 > self.metrics.last_uploaded_epoch.get() {
+                    self.metrics.last_uploaded_epoch.set(*epoch as i64);
+                }
+                self.telemetry
+                    .emit(BackupTelemetryEvent::EpochUploadCompleted {
+                        epoch: *epoch,
+                        duration,
+                    });
+                self.publish(BackupEvent::UploadCompleted {
+                    epoch: *epoch,
+                    duration,
+                });
+                uploaded = true;
+            }
+            // Epochs left out of the uploads are marked as skipped, so that they are garbage
+            // collected like the uploaded ones instead of filling the disk
+            let bytes = if self.upload_epochs.contains(*epoch) {
+                Bytes::from_static(b"success")
+            } else {
+                debug!(
+                    "Marking db checkpoint for epoch: {epoch} outside of upload epochs as skipped"
+                );
+                Bytes::from_static(b"skipped")
+            };
+            let upload_completed_marker =
+                local.state_dir.child(self.upload_completed_marker.as_str());
+            put(
+                &upload_completed_marker,
+                bytes.clone(),
+                self.state_object_store.clone(),
+            )
+            .await?;
+            // Frees the disk of the uploaded epochs while catching up on a backlog, rather than
+            // on the next gc interval after all of them are uploaded
+            if uploaded
+                && local_checkpoints[index + 1..]
+                    .iter()
+                    .any(|(epoch, _)| needs_upload(epoch))
+            {
+                match self.garbage_collect_old_db_checkpoints().await {
+                    Ok(deleted) => {
+                        if !deleted.is_empty() {
+                            info!(
+                                "Garbage collected local db checkpoints during catch-up: {:?}",
+                                deleted
+                            );
+                        }
+                        garbage_collected.extend(deleted);
+                    }
+                    Err(err) => {
+                        warn!(
+                            "Failed to garbage collect local db checkpoints during catch-up: {:?}",
+                            err
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn upload_db_checkpoint(&self, local: &LocalCheckpoint) -> Result<UploadOutcome> {
+        // Fails the upload before anything is written when the key can't be loaded
+        let encryption_key = self.encryption_key().await?;
+        let epoch = local.epoch;
+        let db_path = &local.data_dir;
+        let remote_dir = remote_epoch_dir(epoch);
+        let progress_path = local
+            .state_dir
+            .child(self.upload_progress_filename.as_str());
+        let progress = self.upload_progress(&progress_path).await?;
+        let resumed = progress.is_some();
+        let (mut manifest, uploaded_files) = match progress {
+            Some(progress) => {
+                info!(
+                    "Resuming upload of db checkpoint for epoch: {epoch} after {} of {} files",
+                    progress.uploaded_files,
+                    progress.manifest.files.len()
+                );
+                (progress.manifest, progress.uploaded_files)
+            }
+            None => {
+                let Some(manifest) = self.recorded_checksums(local).await? else {
+                    return Ok(UploadOutcome::Deferred);
+                };
+                (
+                    manifest
+                        .with_compression(self.compression)
+                        .with_encryption(encryption_key.map(EncryptionKey::key_id)),
+                    0,
+                )
+            }
+        };
+        // Left published, e.g. when its success marker wasn't visible yet when the epoch was
+        // found missing, as copying the same contents again would withdraw it for nothing
+        if !resumed
+            && self
+                .published_with_same_contents(&remote_dir, &manifest, encryption_key)
+                .await?
+        {
+            info!("Db checkpoint for epoch: {epoch} is already published with the same contents");
+            return Ok(UploadOutcome::Completed);
+        }
+        // The upload record embedded in the success marker of an earlier upload would be lost
+        // with the marker
+        if let Some(audit_log) = &self.audit_log {
+            if let Ok(metadata) =
+                read_epoch_metadata(&remote_dir, self.output_object_store.clone()).await
+            {
+                if let Some(record) = &metadata.audit_record {
+                    audit_log.append(record).await?;
+                }
+            }
+        }
+        // Left over by an earlier upload of the epoch with other contents
+        unpublish_epoch(&remote_dir, self.output_object_store.clone()).await?;
+        let interrupted = self
+            .interrupted_upload_files(local, &remote_dir)
+            .await
+            .unwrap_or_else(|err| {
+                warn!(
+                    "Failed to list files of interrupted upload of epoch: {epoch}, uploading all of them: {:?}",
+                    err
+                );
+                BTreeMap::new()
+            });
+        if !interrupted.is_empty() {
+            info!(
+                "Found {} files of an interrupted upload of db checkpoint for epoch: {epoch} in remote storage",
+                interrupted.len()
+            );
+        }
+        info!(
+            "Copying db checkpoint for epoch: {epoch} to remote storage, files: {}, bytes: {}",
+            manifest.files.len(),
+            manifest.total_size()
+        );
+        let deadline = self
+            .epoch_upload_deadline
+            .map(|deadline| Instant::now() + deadline);
+        let uploaded_files = self
+            .upload_files(
+                db_path,
+                &mut manifest,
+                uploaded_files,
+                deadline,
+                &interrupted,
+            )
+            .await?;
+        if uploaded_files < manifest.files.len() {
+            info!(
+                "Upload deadline of db checkpoint for epoch: {epoch} passed after {uploaded_files} of {} files, resuming on the next interval",
+                manifest.files.len()
+            );
+            let progress = UploadProgress {
+                uploaded_files,
+                manifest,
+            };
+            put(
+                &progress_path,
+                Bytes::from(serde_json::to_vec(&progress)?),
+                self.state_object_store.clone(),
+            )
+            .await?;
+            return Ok(UploadOutcome::Deferred);
+        }
+        if manifest.compression.is_some()
+            || manifest.layout == DBCheckpointUploadLayout::TarArchives
+        {
+            let compressed_size = manifest
+                .files
+                .iter()
+                .map(|file| file.compressed_size.unwrap_or(file.size))
+                .sum();
+            manifest.compressed_size = Some(compressed_size);
+            self.metrics.epoch_window.set_compressed_sizes(
+                epoch,
+                manifest.total_size(),
+                compressed_size,
+            );
+            info!(
+                "Compressed db checkpoint for epoch: {epoch} from {} to {compressed_size} bytes",
+                manifest.total_size()
+            );
+        }
+        if self.upload_layout == DBCheckpointUploadLayout::MirroredWithSums {
+            write_sums_file(&manifest, &remote_dir, self.output_object_store.clone()).await?;
+        }
+        if self.verify_after_upload {
+            self.verify_remote_checkpoint(&remote_dir, &manifest)
+                .await?;
+        }
+        // Pruned db checkpoints were checked against their listing before pruning
+        if !self.prune_and_compact_before_upload {
+            let local_db_path = path_to_filesystem(self.input_root_path.clone(), db_path)?;
+            if let Some(expected) = ExpectedFiles::read(&local_db_path)? {
+                let uploaded = manifest
+                    .files
+                    .iter()
+                    .map(|file| (file.path.clone(), file.size as u64))
+                    .collect();
+                expected.verify(&uploaded).with_context(|| {
+                    format!("Not marking upload of db checkpoint for epoch: {epoch} as successful")
+                })?;
+            }
+        }
+        // Plain digests next to encrypted files would confirm guesses of their contents
+        if let Some(key) = encryption_key {
+            manifest.seal_digests(key)?;
+        }
+        // Large file lists are written as shards ahead of the index which references them
+        let manifest = write_manifest_shards(
+            &manifest,
+            &remote_dir,
+            self.manifest_shard_size,
+            self.output_object_store.clone(),
+        )
+        .await?;
+        if self.consolidate_epoch_metadata {
+            let mut metadata = EpochMetadata::new(manifest)?;
+            if let Some(audit_log) = &self.audit_log {
+                metadata.audit_record = Some(audit_log.new_record(
+                    AuditAction::Upload,
+                    epoch,
+                    Some(metadata.manifest_sha3_digest.clone()),
+                )?);
+            }
+            write_epoch_metadata(&metadata, &remote_dir, self.output_object_store.clone()).await?;
+        } else {
+            // The manifest is only published once all the files it references are uploaded
+            write_manifest(&manifest, &remote_dir, self.output_object_store.clone()).await?;
+            if let Some(audit_log) = &self.audit_log {
+                // Recorded before the success marker so that every complete upload is audited
+                audit_log.record(AuditAction::Upload, epoch).await?;
+            }
+            // Drop marker in the output directory that upload completed successfully, as the
+            // last write which publishes the epoch
+            let bytes = Bytes::from_static(b"success");
+            let success_marker = remote_dir.child(SUCCESS_MARKER);
+            put(&success_marker, bytes, self.output_object_store.clone()).await?;
+        }
+        if self.consistency_grace_period.is_some() {
+            self.recent_uploads.lock().insert(epoch, Instant::now());
+        }
+        if resumed {
+            self.state_object_store.delete(&progress_path).await?;
+        }
+        Ok(UploadOutcome::Completed)
+    }
+    /// Whether the epoch of `remote_dir` is published with the files of `manifest`, comparing
+    /// their digests once opened with `encryption_key`. Never the case when the digests of
+    /// `manifest` are left for the upload to fill.
+    async fn published_with_same_contents(
+        &self,
+        remote_dir: &Path,
+        manifest: &EpochManifest,
+        encryption_key: Option<&EncryptionKey>,
+    ) -> Result<bool> {
+        if manifest
+            .files
+            .iter()
+            .any(|file| file.size > 0 && file.sha3_digest.is_empty())
+        {
+            return Ok(false);
+        }
+        let Some(mut published) =
+            read_published_manifest(remote_dir, self.output_object_store.clone()).await?
+        else {
+            return Ok(false);
+        };
+        // Digests sealed with another key don't open, the epoch is then uploaded again
+        if let Some(key) = encryption_key {
+            if published.open_digests(key).is_err() {
+                return Ok(false);
+            }
+        }
+        let contents = |manifest: &EpochManifest| -> BTreeMap<String, (usize, String)> {
+            manifest
+                .files
+                .iter()
+                .map(|file| (file.path.clone(), (file.size, file.sha3_digest.clone())))
+                .collect()
+        };
+        Ok(contents(&published) == contents(manifest))
+    }
+    /// Progress of the upload suspended at the deadline, `None` if there is none or it was
+    /// recorded with another layout, compression or encryption key
+    async fn upload_progress(&self, progress_path: &Path) -> Result<Option<UploadProgress>> {
+        let Ok(result) = self.state_object_store.get(progress_path).await else {
+            return Ok(None);
+        };
+        let progress: UploadProgress = serde_json::from_slice(&result.bytes().await?)?;
+        if progress.manifest.layout != self.upload_layout
+            || progress.manifest.compression.unwrap_or_default() != self.compression
+            || progress.manifest.encryption_key_id.as_deref()
+                != self
+                    .encryption
+                    .as_ref()
+                    .map(|config| config.key_id.as_str())
+        {
+            return Ok(None);
+        }
+        Ok(Some(progress))
+    }
+    /// Files of the epoch left in the output store by an interrupted upload of `local`, with
+    /// their sizes. Only files written after the digests of the db checkpoint were recorded
+    /// count, older ones may be the copies of another db checkpoint of the epoch. Their
+    /// contents are only compared with the local ones once their size matches, see
+    /// `is_uploaded_copy`. Encrypted
+    /// copies can't be told apart from those encrypted with an earlier key without reading
+    /// them, chunks are skipped by digest already, and archives are packed again as a whole, so
+    /// none of them is listed.
+    async fn interrupted_upload_files(
+        &self,
+        local: &LocalCheckpoint,
+        remote_dir: &Path,
+    ) -> Result<BTreeMap<Path, usize>> {
+        let mut files = BTreeMap::new();
+        if self.encryption.is_some()
+            || matches!(
+                self.upload_layout,
+                DBCheckpointUploadLayout::ContentDefinedChunks
+                    | DBCheckpointUploadLayout::TarArchives
+            )
+        {
+            return Ok(files);
+        }
+        let recorded_at = self
+            .state_object_store
+            .head(&local.state_dir.child(LOCAL_CHECKSUMS_FILENAME))
+            .await?
+            .last_modified;
+        let mut entries = self.output_object_store.list(Some(remote_dir)).await?;
+        while let Some(entry) = entries.next().await {
+            let object_metadata = entry?;
+            if object_metadata.last_modified >= recorded_at {
+                files.insert(object_metadata.location, object_metadata.size);
+            }
+        }
+        Ok(files)
+    }
+    /// Uploads the files of the manifest from index `uploaded_files` on, filling in their
+    /// digests and chunks. Files found in `interrupted` with the size they would be uploaded
+    /// with are not uploaded again. Stops after the first batch of files that ends past
+    /// `deadline` and returns the number of files uploaded so far.
+    async fn upload_files(
+        &self,
+        db_path: &Path,
+        manifest: &mut EpochManifest,
+        mut uploaded_files: usize,
+        deadline: Option<Instant>,
+        interrupted: &BTreeMap<Path, usize>,
+    ) -> Result<usize> {
+        // Without a deadline all the files are uploaded as a single batch
+        let batch_size = match deadline {
+            Some(_) => self.upload_concurrency.get(),
+            None => manifest.files.len().max(1),
+        };
+        let mut uploaded_chunk_bytes = 0;
+        let previous_chunks = if self.upload_layout
+            == DBCheckpointUploadLayout::ContentDefinedChunks
+            && uploaded_files < manifest.files.len()
+        {
+            self.previous_chunks(manifest.epoch)
+                .await
+                .unwrap_or_else(|err| {
+                    warn!(
+                        "Failed to read the chunks of the previous epoch, chunking every file of epoch: {}: {:?}",
+                        manifest.epoch, err
+                    );
+                    HashMap::new()
+                })
+        } else {
+            HashMap::new()
+        };
+        let remote_dir = remote_epoch_dir(manifest.epoch);
+        while uploaded_files < manifest.files.len() {
+            // Every archive, and every file too large for one, is a batch of its own, so that
+            // uploads resume at an archive
+            let end = match self.upload_layout {
+                DBCheckpointUploadLayout::TarArchives => {
+                    archive_end(&manifest.files, uploaded_files, self.archive_size_bytes)
+                }
+                _ => (uploaded_files + batch_size).min(manifest.files.len()),
+            };
+            let files = &mut manifest.files[uploaded_files..end];
+            if self.upload_layout == DBCheckpointUploadLayout::ContentDefinedChunks {
+                let chunk_bytes = self
+                    .upload_chunked_files(db_path, files, &previous_chunks)
+                    .await?;
+                self.metrics.bytes_uploaded.inc_by(chunk_bytes as u64);
+                uploaded_chunk_bytes += chunk_bytes;
+            } else if self.upload_layout == DBCheckpointUploadLayout::TarArchives
+                && !is_archived(&files[0], self.archive_size_bytes)
+            {
+                // Uploaded as is rather than as an archive of a single file, which would exceed
+                // the size of archives held in memory
+                let file = &mut files[0];
+                file.remote_path = logical_path(&remote_dir, &file.path).to_string();
+                let uploaded = self.upload_file(db_path, file, interrupted).await?;
+                file.sha3_digest = uploaded.sha3_digest;
+            } else if self.upload_layout == DBCheckpointUploadLayout::TarArchives {
+                self.upload_archive(db_path, &remote_dir, uploaded_files, files)
+                    .await?;
+            } else {
+                // Buffered in order so that digests line up with the files of the manifest
+                let results: Vec<Result<UploadedFile>> = futures::stream::iter(files.iter())
+                    .map(|file| self.upload_file(db_path, file, interrupted))
+                    .buffered(self.upload_limiter.max().get())
+                    .collect()
+                    .await;
+                let uploaded = results.into_iter().collect::<Result<Vec<_>>>()?;
+                for (file, uploaded) in files.iter_mut().zip(uploaded) {
+                    file.sha3_digest = uploaded.sha3_digest;
+                    file.sha256_digest = uploaded.sha256_digest;
+                    file.compressed_size = uploaded.compressed_size;
+                    file.frames = uploaded.frames;
+                }
+            }
+            uploaded_files = end;
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                break;
+            }
+        }
+        self.metrics
+            .upload_concurrency
+            .set(self.upload_limiter.limit().get() as i64);
+        if self.upload_layout == DBCheckpointUploadLayout::ContentDefinedChunks {
+            info!(
+                "Uploaded {uploaded_chunk_bytes} new bytes of chunks for epoch: {}, total bytes: {}",
+                manifest.epoch,
+                manifest.total_size()
+            );
+        }
+        Ok(uploaded_files)
+    }
+    /// Whether the digests of the db checkpoint were recorded, after pruning and compaction if
+    /// enabled, by the handler of the primary output store
+    async fn is_prepared(&self, local: &LocalCheckpoint) -> bool {
+        self.state_object_store
+            .head(&local.state_dir.child(LOCAL_CHECKSUMS_FILENAME))
+            .await
+            .is_ok()
+    }
+    /// Returns the manifest of the epoch with the digests recorded right after pruning and
+    /// compaction, `None` while the db checkpoint waits for pruning. The digests are only
+    /// computed on the first upload attempt, later attempts reuse them so that files altered on
+    /// disk in between are detected.
+    async fn recorded_checksums(&self, local: &LocalCheckpoint) -> Result<Option<EpochManifest>> {
+        let epoch = local.epoch;
+        let db_path = &local.data_dir;
+        let remote_dir = remote_epoch_dir(epoch);
+        let checksums_path = local.state_dir.child(LOCAL_CHECKSUMS_FILENAME);
+        if let Ok(result) = self.state_object_store.get(&checksums_path).await {
+            let manifest: EpochManifest = serde_json::from_slice(&result.bytes().await?)?;
+            return Ok(Some(manifest.with_layout(self.upload_layout, &remote_dir)));
+        }
+        // Pruning runs for one epoch at a time, the others wait for it to finish
+        let pruning_epoch = {
+            let mut prune_job = self.prune_job.lock();
+            // The result of a finished job is only kept for the next attempt of its epoch, which
+            // may never come, e.g. once the epoch is deleted. Its epoch prunes again then.
+            if prune_job
+                .as_ref()
+                .map_or(false, |job| job.epoch != epoch && job.is_finished())
+            {
+                prune_job.take();
+            }
+            prune_job.as_ref().map(|job| job.epoch)
+        };
+        if let Some(pruning_epoch) = pruning_epoch.filter(|pruning_epoch| *pruning_epoch != epoch) {
+            info!(
+                "Deferring upload of db checkpoint for epoch: {epoch} until pruning of epoch: {pruning_epoch} finishes"
+            );
+            return Ok(None);
+        }
+        // Convert `db_path` to the local filesystem path to where db checkpoint is stored
+        let local_db_path = path_to_filesystem(self.input_root_path.clone(), db_path)?;
+        // Written next to the db files, without being part of the db checkpoint
+        let excluded = [
+            EXPECTED_FILENAME,
+            UPLOAD_COMPLETED_MARKER,
+            LOCAL_CHECKSUMS_FILENAME,
+            UPLOAD_PROGRESS_FILENAME,
+        ];
+        // A db checkpoint being pruned was found created before the job started
+        if pruning_epoch.is_none() {
+            // Walks every file of the db checkpoint, which must not stall the runtime
+            let dir = local_db_path.clone();
+            let estimate =
+                tokio::task::spawn_blocking(move || CreationEstimate::of_dir(&dir, &excluded))
+                    .await??;
+            if let Some(completeness) = estimate.completeness {
+                self.metrics
+                    .creation_completeness_percent
+                    .set((completeness * 100.0) as i64);
+            }
+            if estimate.in_progress(self.creation_settle) {
+                info!(
+                    "Deferring upload of db checkpoint for epoch: {epoch} while it is being created: {:?}",
+                    estimate
+                );
+                self.metrics.creation_in_progress_deferrals_total.inc();
+                return Ok(None);
+            }
+        }
+        // Checked before pruning and compaction rewrite the files, unless a job already started
+        let expected = match pruning_epoch {
+            Some(_) => None,
+            None => ExpectedFiles::read(&local_db_path)?,
+        };
+        if let Some(expected) = expected {
+            let present = ExpectedFiles::from_dir(&local_db_path, &excluded)?;
+            expected.verify(&present.files)?;
+        }
+        let pruned_bytes_by_table = if self.prune_and_compact_before_upload {
+            // Invoke pruning and compaction on the db checkpoint
+            let Some(pruned_bytes) = self.prune_and_compact(local_db_path.clone(), epoch).await?
+            else {
+                return Ok(None);
+            };
+            pruned_bytes
+        } else {
+            BTreeMap::new()
+        };
+        // The dbs are opened as read-only secondary instances, so their files are left as they
+        // are listed and digested below
+        let protocol_version = if self.chain_identifier.is_none() || self.source.is_read_only() {
+            None
+        } else {
+            let local_db_path = local_db_path.clone();
+            let usage = self.metrics.usage.clone();
+            tokio::task::spawn_blocking(move || {
+                usage.measure(|| read_protocol_version(&local_db_path))
+            })
+            .await?
+            .unwrap_or_else(|err| {
+                warn!(
+                    "Failed to read protocol version of db checkpoint for epoch: {epoch}: {:?}",
+                    err
+                );
+                None
+            })
+        };
+        let expectations = if self.source.is_read_only() {
+            None
+        } else {
+            let usage = self.metrics.usage.clone();
+            tokio::task::spawn_blocking(move || {
+                usage.measure(|| {
+                    RestoreExpectations::sample(&local_db_path, NUM_EXPECTATION_SAMPLES)
+                })
+            })
+            .await?
+            .unwrap_or_else(|err| {
+                warn!(
+                    "Failed to sample restore expectations for epoch: {epoch}: {:?}",
+                    err
+                );
+                None
+            })
+        };
+        let mut manifest = EpochManifest::from_local_dir(
+            epoch,
+            db_path,
+            &remote_dir,
+            self.input_object_store.clone(),
+            self.upload_layout,
+            &[
+                UPLOAD_COMPLETED_MARKER,
+                LOCAL_CHECKSUMS_FILENAME,
+                UPLOAD_PROGRESS_FILENAME,
+                EXPECTED_FILENAME,
+            ],
+        )
+        .await?;
+        if !self.single_pass_digests {
+            manifest
+                .compute_sha3_digests(db_path, &self.input_root_path, &self.digest_pool)
+                .await?;
+        }
+        manifest.expectations = expectations;
+        manifest.pruned_bytes_by_table = pruned_bytes_by_table;
+        manifest.labels = self
+            .chain_identifier
+            .map(|chain_identifier| CheckpointLabels {
+                chain_identifier,
+                protocol_version,
+            });
+        manifest.producer = self.producer.clone();
+        put(
+            &checksums_path,
+            Bytes::from(serde_json::to_vec(&manifest)?),
+            self.state_object_store.clone(),
+        )
+        .await?;
+        Ok(Some(manifest))
+    }
+    /// Reads a local file and verifies it against the digest recorded after compaction, or
+    /// takes its digest from this read when none was recorded. Returns the contents and the
+    /// sha3 digest of the file.
+    async fn read_verified_file(
+        &self,
+        db_path: &Path,
+        file: &FileEntry,
+    ) -> Result<(Bytes, String)> {
+        // Read on a blocking thread of the handler rather than by the local store, so that the
+        // IO is recorded in its usage
+        let local_path = path_to_filesystem(
+            self.input_root_path.clone(),
+            &logical_path(db_path, &file.path),
+        )?;
+        let usage = self.metrics.usage.clone();
+        let bytes = tokio::task::spawn_blocking(move || usage.measure(|| fs::read(local_path)))
+            .await??;
+        let bytes = Bytes::from(bytes);
+        if file.sha3_digest.is_empty() {
+            let contents = bytes.clone();
+            let sha3_digest = self.digest_pool.run(move || sha3_hex(&contents)).await?;
+            return Ok((bytes, sha3_digest));
+        }
+        let (entry, contents) = (file.clone(), bytes.clone());
+        let verified = self
+            .digest_pool
+            .run(move || verify_file_contents(&entry, &contents))
+            .await?;
+        if let Err(err) = verified {
+            self.metrics.bitrot_detected_total.inc();
+            error!("Local db checkpoint file {db_path}/{} changed since compaction, possible disk corruption: {:?}", file.path, err);
+            return Err(err.context(LocalCorruption {
+                path: format!("{db_path}/{}", file.path),
+            }));
+        }
+        Ok((bytes, file.sha3_digest.clone()))
+    }
+    /// Key the uploaded files are encrypted with, `None` when encryption is disabled
+    async fn encryption_key(&self) -> Result<Option<&EncryptionKey>> {
+        let Some(config) = &self.encryption else {
+            return Ok(None);
+        };
+        let key = self
+            .encryption_key
+            .get_or_try_init(|| EncryptionKey::load(config))
+            .await?;
+        Ok(Some(key))
+    }
+    /// Uploads a single file, compressed and encrypted if configured. The file is still read
+    /// and verified when its copy from an interrupted upload is kept.
+    async fn upload_file(
+        &self,
+        db_path: &Path,
+        file: &FileEntry,
+        interrupted: &BTreeMap<Path, usize>,
+    ) -> Result<UploadedFile> {
+        let with_sums = self.upload_layout == DBCheckpointUploadLayout::MirroredWithSums;
+        // Empty files are never copied to the remote store, unless it must be a complete
+        // plain copy of the db checkpoint
+        if file.size == 0 && !with_sums {
+            return Ok(UploadedFile {
+                sha3_digest: sha3_hex(&[]),
+                sha256_digest: None,
+                compressed_size: None,
+                frames: vec![],
+            });
+        }
+        // Taken before reading, so that only the files being written are held in memory
+        let permit = self.upload_limiter.acquire().await;
+        let (bytes, sha3_digest) = self.read_verified_file(db_path, file).await?;
+        let sha256_digest = if with_sums {
+            let contents = bytes.clone();
+            Some(self.digest_pool.run(move || sha256_hex(&contents)).await?)
+        } else {
+            None
+        };
+        let (bytes, compressed_size, frames) = match self.compression {
+            DBCheckpointCompression::None => (bytes, None, vec![]),
+            DBCheckpointCompression::ZstdSeekable => {
+                let (compressed, frames) = self
+                    .digest_pool
+                    .run(move || compress_file_seekable(bytes))
+                    .await??;
+                let compressed_size = compressed.len();
+                (compressed, Some(compressed_size), frames)
+            }
+            compression => {
+                let compressed = self
+                    .digest_pool
+                    .run(move || compress_file(compression, bytes))
+                    .await??;
+                let compressed_size = compressed.len();
+                (compressed, Some(compressed_size), vec![])
+            }
+        };
+        let bytes = match self.encryption_key().await? {
+            Some(key) => {
+                let (key, remote_path) = (key.clone(), file.remote_path.clone());
+                self.digest_pool
+                    .run(move || key.encrypt(&remote_path, &bytes))
+                    .await??
+            }
+            None => bytes,
+        };
+        let uploaded_bytes = bytes.len();
+        let remote_path = Path::from(file.remote_path.as_str());
+        if interrupted.get(&remote_path) == Some(&uploaded_bytes)
+            && self
+                .is_uploaded_copy(&remote_path, &bytes, compressed_size.is_some(), &sha3_digest)
+                .await
+        {
+            self.metrics.upload_resumed_files_total.inc();
+            return Ok(UploadedFile {
+                sha3_digest,
+                sha256_digest,
+                compressed_size,
+                frames,
+            });
+        }
+        self.upload_limiter
+            .put_with_permit(permit, &remote_path, bytes, self.output_object_store.clone())
+            .await?;
+        self.metrics.bytes_uploaded.inc_by(uploaded_bytes as u64);
+        Ok(UploadedFile {
+            sha3_digest,
+            sha256_digest,
+            compressed_size,
+            frames,
+        })
+    }
+    /// Whether the object at `remote_path` holds `bytes`, the contents of a file with digest
+    /// `sha3_digest` as uploaded, compressed or not. Read back in full, as a copy of the same
+    /// size left by an interrupted upload may still hold other contents.
+    async fn is_uploaded_copy(
+        &self,
+        remote_path: &Path,
+        bytes: &Bytes,
+        compressed: bool,
+        sha3_digest: &str,
+    ) -> bool {
+        let uploaded_digest = if compressed {
+            let contents = bytes.clone();
+            match self.digest_pool.run(move || sha3_hex(&contents)).await {
+                Ok(digest) => digest,
+                Err(_) => return false,
+            }
+        } else {
+            sha3_digest.to_string()
+        };
+        match stream_sha3_hex(remote_path, self.output_object_store.clone()).await {
+            Ok((size, digest)) => size == bytes.len() && digest == uploaded_digest,
+            Err(err) => {
+                warn!("Failed to read back {remote_path}, uploading it again: {:?}", err);
+                false
+            }
+        }
+    }
+    /// Chunks of the files of the latest complete epoch before `epoch` in the remote store, by
+    /// sha3 digest of the file
+    async fn previous_chunks(&self, epoch: u32) -> Result<HashMap<String, Vec<ChunkEntry>>> {
+        let epoch_dirs = list_epoch_dirs(self.output_object_store.clone(), None).await?;
+        for previous in epoch_dirs.range(..epoch).map(|(epoch, _)| *epoch).rev() {
+            let epoch_dir = remote_epoch_dir(previous);
+            let Some(manifest) =
+                read_published_manifest(&epoch_dir, self.output_object_store.clone()).await?
+            else {
+                continue;
+            };
+            if manifest.layout != DBCheckpointUploadLayout::ContentDefinedChunks {
+                break;
+            }
+            return Ok(manifest
+                .files
+                .into_iter()
+                .filter(|file| !file.chunks.is_empty())
+                .map(|file| (file.sha3_digest, file.chunks))
+                .collect());
+        }
+        Ok(HashMap::new())
+    }
+    /// Uploads `files` as content defined chunks, skipping chunks already present in the
+    /// shared chunk area, and records their chunk lists. Files whose recorded digest is found in
+    /// `previous_chunks` reuse the chunks of the previous epoch without being read again, as
+    /// long as all of them are still in the chunk area. Returns the number of bytes of new
+    /// chunks uploaded.
+    async fn upload_chunked_files(
+        &self,
+        db_path: &Path,
+        files: &mut [FileEntry],
+        previous_chunks: &HashMap<String, Vec<ChunkEntry>>,
+    ) -> Result<usize> {
+        let mut uploaded_bytes = 0;
+        for file in files.iter_mut() {
+            if file.size == 0 {
+                file.sha3_digest = sha3_hex(&[]);
+                continue;
+            }
+            // Digests are only recorded ahead of the upload without single pass digests
+            if let Some(chunks) = previous_chunks
+                .get(&file.sha3_digest)
+                .filter(|chunks| chunks.iter().map(|chunk| chunk.size).sum::<usize>() == file.size)
+            {
+                let missing = count_missing_chunks(
+                    chunks,
+                    self.output_object_store.clone(),
+                    self.upload_limiter.max().get(),
+                )
+                .await?;
+                if missing == 0 {
+                    file.chunks = chunks.clone();
+                    continue;
+                }
+                warn!(
+                    "{missing} chunks of {} are missing remotely, uploading them again",
+                    file.path
+                );
+            }
+            let (data, sha3_digest) = self.read_verified_file(db_path, file).await?;
+            file.sha3_digest = sha3_digest;
+            let (chunks, uploaded) = upload_chunks(
+                data,
+                self.output_object_store.clone(),
+                &self.upload_limiter,
+                &self.digest_pool,
+            )
+            .await?;
+            file.chunks = chunks;
+            uploaded_bytes += uploaded;
+        }
+        Ok(uploaded_bytes)
+    }
+    /// Packs `files`, the ones of the manifest from index `first_file` on, into a single
+    /// archive in `remote_dir`, and records where the frame of each of them is in it
+    async fn upload_archive(
+        &self,
+        db_path: &Path,
+        remote_dir: &Path,
+        first_file: usize,
+        files: &mut [FileEntry],
+    ) -> Result<()> {
+        let archive = archive_path(remote_dir, first_file);
+        let pending = files.to_vec();
+        // Buffered in order so that frames line up with the files of the manifest. Members are
+        // appended as they are compressed and dropped right away, so that only the archive and
+        // the members in flight are held in memory rather than every member on top of it.
+        let mut members = futures::stream::iter(pending.iter())
+            .map(|file| async move {
+                let (bytes, sha3_digest) = self.read_verified_file(db_path, file).await?;
+                let path = file.path.clone();
+                let member = self
+                    .digest_pool
+                    .run(move || compress_member(&path, &bytes))
+                    .await??;
+                Ok::<_, anyhow::Error>((sha3_digest, member))
+            })
+            .buffered(self.upload_limiter.limit().get());
+        // Compressed members are usually smaller than their files, so the archive rarely grows
+        let mut contents = Vec::with_capacity(files.iter().map(|file| file.size).sum());
+        for file in files.iter_mut() {
+            let (sha3_digest, member) = members
+                .next()
+                .await
+                .ok_or_else(|| anyhow!("Missing archive member of {}", file.path))??;
+            file.sha3_digest = sha3_digest;
+            file.remote_path = archive.to_string();
+            file.archive_offset = Some(contents.len());
+            file.compressed_size = Some(member.len());
+            contents.extend_from_slice(&member);
+        }
+        drop(members);
+        contents.extend_from_slice(&end_of_archive()?);
+        let uploaded_bytes = contents.len();
+        self.upload_limiter
+            .put(
+                &archive,
+                Bytes::from(contents),
+                self.output_object_store.clone(),
+            )
+            .await?;
+        self.metrics.bytes_uploaded.inc_by(uploaded_bytes as u64);
+        info!(
+            "Uploaded archive {archive} of {} files, bytes: {uploaded_bytes}",
+            files.len()
+        );
+        Ok(())
+    }
+    async fn verify_remote_checkpoint(
+        &self,
+        remote_dir: &Path,
+        manifest: &EpochManifest,
+    ) -> Result<()> {
+        let epoch = manifest.epoch;
+        if manifest.layout == DBCheckpointUploadLayout::ContentDefinedChunks {
+            for file in manifest.files.iter() {
+                verify_chunks(&file.chunks, self.output_object_store.clone())
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Invalid {} in remote db checkpoint for epoch: {epoch}",
+                            file.path
+                        )
+                    })?;
+            }
+            // The verify-only role checks contents within its budget, see `verify_remote_epoch`
+            if self.verify_remote_checksums && !self.verify_only {
+                let files: Vec<_> = manifest.files.iter().collect();
+                self.verify_remote_contents(manifest, &files).await?;
+            }
+            info!("Verified remote db checkpoint chunks for epoch: {epoch}");
+            return Ok(());
+        }
+        let remote_files = self
+            .list_file_sizes(self.output_object_store.clone(), remote_dir)
+            .await?;
+        for file in manifest.files.iter() {
+            // Empty files are never copied to the remote store
+            if file.size == 0 {
+                continue;
+            }
+            let remote_path = Path::from(file.remote_path.as_str());
+            let mut expected_size = file.compressed_size.unwrap_or(file.size);
+            if manifest.encryption_key_id.is_some() {
+                expected_size += ENCRYPTION_OVERHEAD;
+            }
+            // Archives hold the frames of several files
+            let holds_file = |remote_size: usize| match file.archive_offset {
+                Some(offset) => remote_size >= offset + expected_size,
+                None => remote_size == expected_size,
+            };
+            match remote_files.get(&remote_path) {
+                Some(remote_size) if holds_file(*remote_size) => {}
+                Some(remote_size) => {
+                    return Err(anyhow!(
+                        "Size mismatch for {} in db checkpoint for epoch: {epoch}, expected: {expected_size}, remote: {remote_size}",
+                        file.path
+                    ));
+                }
+                None => {
+                    return Err(anyhow!(
+                        "Missing {} in remote db checkpoint for epoch: {epoch}",
+                        file.path
+                    ));
+                }
+            }
+        }
+        // The verify-only role checks contents within its budget, see `verify_remote_epoch`
+        if self.verify_remote_checksums && !self.verify_only {
+            let files: Vec<_> = manifest.files.iter().collect();
+            self.verify_remote_contents(manifest, &files).await?;
+        }
+        info!("Verified remote db checkpoint for epoch: {epoch}");
+        Ok(())
+    }
+    /// Downloads `files` of `manifest` from the remote store and checks their contents against
+    /// the digests of the manifest
+    async fn verify_remote_contents(
+        &self,
+        manifest: &EpochManifest,
+        files: &[&FileEntry],
+    ) -> Result<()> {
+        let epoch = manifest.epoch;
+        let key = match &manifest.encryption_key_id {
+            Some(key_id) => match self.encryption_key().await? {
+                Some(key) if key.key_id() == key_id => Some(key.clone()),
+                _ => {
+                    return Err(anyhow!(
+                        "Can't verify checksums of db checkpoint for epoch: {epoch} encrypted with key {key_id}, which the handler doesn't hold"
+                    ))
+                }
+            },
+            None => None,
+        };
+        let compression = manifest.compression;
+        futures::stream::iter(files.iter().copied().filter(|file| file.size > 0))
+            .map(|file| {
+                let key = key.clone();
+                async move {
+                    // Chunks hold the contents as they are, the file is reassembled from them
+                    if !file.chunks.is_empty() {
+                        let (store, unlimited) =
+                            (self.output_object_store.clone(), BandwidthLimiter::default());
+                        return stream_verify_chunked_file(file, store, &unlimited)
+                            .await
+                            .with_context(|| {
+                                format!(
+                                    "Invalid {} in remote db checkpoint for epoch: {epoch}",
+                                    file.path
+                                )
+                            });
+                    }
+                    // Plain files are hashed as they are downloaded, a few large SST files at
+                    // the upload concurrency would otherwise be held in memory at once
+                    if key.is_none() && file.archive_offset.is_none() {
+                        let store = self.output_object_store.clone();
+                        return stream_verify_file(file, compression, store)
+                            .await
+                            .with_context(|| {
+                                format!(
+                                    "Invalid {} in remote db checkpoint for epoch: {epoch}",
+                                    file.path
+                                )
+                            });
+                    }
+                    let bytes = match file.archive_offset {
+                        Some(_) => read_member(file, self.output_object_store.clone()).await?,
+                        None => {
+                            get(
+                                &Path::from(file.remote_path.as_str()),
+                                self.output_object_store.clone(),
+                            )
+                            .await?
+                        }
+                    };
+                    let mut entry = file.clone();
+                    self.digest_pool
+                        .run(move || {
+                            let bytes = match &key {
+                                Some(key) => {
+                                    entry.open_digest(key)?;
+                                    key.decrypt(&entry.remote_path, &bytes)?
+                                }
+                                None => bytes,
+                            };
+                            let contents = match entry.archive_offset {
+                                Some(_) => extract_member(&entry, bytes)?,
+                                None => decompress_file(compression, bytes, entry.size)?,
+                            };
+                            verify_file_contents(&entry, &contents)
+                        })
+                        .await?
+                        .with_context(|| {
+                            format!(
+                                "Invalid {} in remote db checkpoint for epoch: {epoch}",
+                                file.path
+                            )
+                        })
+                }
+            })
+            .buffer_unordered(self.upload_concurrency.get())
+            .try_for_each(|()| futures::future::ready(Ok(())))
+            .await
+    }
+    async fn list_file_sizes(
+        &self,
+        store: Arc<DynObjectStore>,
+        dir: &Path,
+    ) -> Result<BTreeMap<Path, usize>> {
+        let mut file_sizes = BTreeMap::new();
+        let mut entries = store.list(Some(dir)).await?;
+        while let Some(entry) = entries.next().await {
+            let object_metadata = entry?;
+            file_sizes.insert(object_metadata.location, object_metadata.size);
+        }
+        Ok(file_sizes)
+    }
+    /// Whether every gc readiness condition lets `local` be deleted
+    async fn is_ready_for_gc(&self, local: &LocalCheckpoint) -> bool {
+        for condition in self.gc_readiness.iter() {
+            match condition.is_ready(local).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    debug!(
+                        "Not ready for deletion yet: {}, waiting for {}",
+                        local.state_dir,
+                        condition.name()
+                    );
+                    return false;
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to check {} before deleting {}: {:?}",
+                        condition.name(),
+                        local.state_dir,
+                        err
+                    );
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    async fn garbage_collect_old_db_checkpoints(&self) -> Result<Vec<u32>> {
+        if self.status.is_gc_paused() {
+            debug!("Skipping garbage collection, it is paused");
+            return Ok(vec![]);
+        }
+        self.delete_uploaded_db_checkpoints(self.num_local_epochs_to_retain, None)
+            .await
+    }
+    /// Deletes the uploaded local db checkpoints but the latest `num_to_retain` ones, oldest
+    /// first. With `min_free_bytes`, stops once as many bytes are free on their disk.
+    async fn delete_uploaded_db_checkpoints(
+        &self,
+        num_to_retain: usize,
+        min_free_bytes: Option<u64>,
+    ) -> Result<Vec<u32>> {
+        let _guard = self.gc_lock.lock().await;
+        if self.source.is_read_only() {
+            // The snapshot tooling owns the lifecycle of read-only db checkpoints
+            return Ok(vec![]);
+        }
+        if self.replica.is_some() {
+            // Left to the handler of the primary output store, which waits for every replica
+            return Ok(vec![]);
+        }
+        let local_checkpoints_by_epoch = self.source.list(self.input_object_store.clone()).await?;
+        let mut eligible = Vec::new();
+        for (epoch, local) in local_checkpoints_by_epoch.iter() {
+            let path = &local.state_dir;
+            if self.is_ready_for_gc(local).await {
+                eligible.push((*epoch, path));
+            }
+        }
+        // Keep the most recent uploaded checkpoints around on local disk if configured
+        let num_to_delete = eligible.len().saturating_sub(num_to_retain);
+        if self.gc_dry_run {
+            let would_delete: Vec<u32> = eligible
+                .iter()
+                .take(num_to_delete)
+                .map(|(epoch, _)| *epoch)
+                .collect();
+            if min_free_bytes.is_some() {
+                // Nothing is freed, so every eligible db checkpoint would be deleted
+                info!(
+                    "Dry run, would delete db checkpoint dirs to free the disk for epochs: {:?}",
+                    would_delete
+                );
+                return Ok(vec![]);
+            }
+            self.metrics
+                .gc_dry_run_epochs
+                .set(would_delete.len() as i64);
+            // Logged when it changes rather than on every interval
+            if self.status.set_gc_dry_run_epochs(would_delete.clone()) {
+                info!(
+                    "Dry run, would delete db checkpoint dirs for epochs: {:?}",
+                    would_delete
+                );
+            }
+            return Ok(vec![]);
+        }
+        let mut deleted = Vec::new();
+        for (epoch, path) in eligible.into_iter().take(num_to_delete) {
+            if let Some(min_free_bytes) = min_free_bytes {
+                if available_space(&self.input_root_path)? >= min_free_bytes {
+                    break;
+                }
+            }
+            info!("Deleting db checkpoint dir: {path} for epoch: {epoch}");
+            deleted.push(epoch);
+            let local_fs_path = path_to_filesystem(self.input_root_path.clone(), path)?;
+            // Epochs checkpointed by RocksDB, e.g. before the backend was configured, are plain
+            // directories even with a snapshot backend
+            match &self.snapshot_backend {
+                Some(backend) if is_fs_snapshot(&local_fs_path)? => {
+                    destroy_fs_snapshot(backend, epoch as u64, &local_fs_path)?
+                }
+                _ => fs::remove_dir_all(&local_fs_path)?,
+            }
+            if let Some(audit_log) = &self.audit_log {
+                if let Err(err) = audit_log.record(AuditAction::RetentionDelete, epoch).await {
+                    warn!(
+                        "Failed to record retention delete of epoch: {epoch} in audit log: {err:?}"
+                    );
+                }
+            }
+        }
+        if !deleted.is_empty() {
+            self.metrics
+                .gc_deleted_epochs_total
+                .inc_by(deleted.len() as u64);
+            self.telemetry.emit(BackupTelemetryEvent::GcCompleted {
+                epochs: deleted.clone(),
+            });
+            self.publish(BackupEvent::GcPerformed {
+                epochs: deleted.clone(),
+            });
+        }
+        Ok(deleted)
+    }
+    /// Deletes the uploaded epochs which the remote retention policy doesn't keep
+    async fn apply_remote_retention(&self) -> Result<Vec<u32>> {
+        let Some(policy) = &self.remote_retention else {
+            return Ok(vec![]);
+        };
+        if self.status.is_gc_paused() {
+            return Ok(vec![]);
+        }
+        if self.verify_only {
+            // Retention of the bucket is left to the node uploading to it
+            return Ok(vec![]);
+        }
+        let store = self.output_object_store.clone();
+        let to_delete = epochs_to_delete(store.clone(), None, policy, SUCCESS_MARKER, |_, dir| {
+            remote_epoch_size(store.clone(), dir)
+        })
+        .await?;
+        for (epoch, dir) in to_delete.iter() {
+            info!("Deleting remote db checkpoint dir: {dir} for epoch: {epoch}");
+            // Recorded first, while the manifest of the epoch is still there to be digested
+            if let Some(audit_log) = &self.audit_log {
+                if let Err(err) = audit_log
+                    .record(AuditAction::RemoteRetentionDelete, *epoch)
+                    .await
+                {
+                    warn!("Failed to record remote retention delete of epoch: {epoch} in audit log: {err:?}");
+                }
+            }
+            delete_recursively(
+                dir,
+                self.output_object_store.clone(),
+                self.upload_concurrency,
+            )
+            .await?;
+            self.remote_retention_deleted.lock().insert(*epoch);
+        }
+        Ok(to_delete.into_keys().collect())
+    }
+    /// Deletes the uploaded db checkpoint of `epoch` on request of an operator. It isn't
+    /// uploaded again until the handler restarts.
+    async fn delete_remote_epoch(&self, epoch: u32) -> Result<()> {
+        if self.verify_only {
+            return Err(anyhow!("Remote store is not written to in verify-only mode"));
+        }
+        let dir = Path::from(format!("epoch_{}", epoch));
+        info!("Deleting remote db checkpoint dir: {dir} for epoch: {epoch} on request");
+        if let Some(audit_log) = &self.audit_log {
+            if let Err(err) = audit_log
+                .record(AuditAction::RemoteAdminDelete, epoch)
+                .await
+            {
+                warn!("Failed to record admin delete of epoch: {epoch} in audit log: {err:?}");
+            }
+        }
+        delete_recursively(
+            &dir,
+            self.output_object_store.clone(),
+            self.upload_concurrency,
+        )
+        .await?;
+        self.admin_deleted_epochs.lock().insert(epoch);
+        Ok(())
+    }
+    /// Lists the `epoch_N` directories at the root of `store`
+    async fn read_checkpoint_dir(&self, store: Arc<DynObjectStore>) -> Result<BTreeMap<u32, Path>> {
+        list_epoch_dirs(store, None).await
+    }
+}
+
+/// Bytes of the uploaded epoch under `dir`, including the shared chunks its files are stored as
+async fn remote_epoch_size(store: Arc<DynObjectStore>, dir: Path) -> Result<u64> {
+    let mut size = dir_size(store.clone(), &dir).await?;
+    if let Some(manifest) = read_published_manifest(&dir, store).await? {
+        size += manifest
+            .files
+            .iter()
+            .flat_map(|file| file.chunks.iter())
+            .map(|chunk| chunk.size as u64)
+            .sum::<u64>();
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authority::authority_store_pruner::AuthorityStorePruningMetrics;
+    use crate::db_checkpoint_handler::audit::{
+        manifest_sha3_digest, read_audit_log, AuditAction, AuditLog, AUDIT_DIR,
+    };
+    use crate::db_checkpoint_handler::backup_watermark::BackupWatermark;
+    use crate::db_checkpoint_handler::bootstrap::{
+        BootstrapPlan, BootstrapPlanner, BootstrapSource, PeerSyncEstimate,
+        BOOTSTRAP_DECISION_FILENAME,
+    };
+    use crate::db_checkpoint_handler::bundles::bundle_old_epochs;
+    use crate::db_checkpoint_handler::chunking::chunk_path;
+    use crate::db_checkpoint_handler::encryption::{
+        EncryptionKey, ENCRYPTION_OVERHEAD, SEALED_DIGEST_PREFIX,
+    };
+    use crate::db_checkpoint_handler::events::BackupEvent;
+    use crate::db_checkpoint_handler::expected::{ExpectedFiles, EXPECTED_FILENAME};
+    use crate::db_checkpoint_handler::gc_readiness::GcReadiness;
+    use crate::db_checkpoint_handler::listing::list_epoch;
+    use crate::db_checkpoint_handler::manifest::{
+        compress_file, decompress_file, read_epoch_metadata, read_file_range, read_manifest,
+        read_manifest_index, read_published_manifest, sha256_hex, sha3_hex, stream_verify_file,
+        unpublish_epoch, LOCAL_CHECKSUMS_FILENAME, MANIFEST_FILENAME, SEEKABLE_FRAME_SIZE,
+        SUMS_FILENAME, UPLOAD_PROGRESS_FILENAME,
+    };
+    use crate::db_checkpoint_handler::restorer::{DBCheckpointRestorer, RestoreProgress};
+    use crate::db_checkpoint_handler::source::{CheckpointSource, LocalCheckpoint};
+    use crate::db_checkpoint_handler::staging::StagingArea;
+    use crate::db_checkpoint_handler::telemetry::BackupTelemetryEvent;
+    use crate::db_checkpoint_handler::verification_budget::VerificationBudget;
+    use crate::db_checkpoint_handler::{
+        DBCheckpointHandler, SUCCESS_MARKER, TEST_MARKER, UPLOAD_COMPLETED_MARKER,
+    };
+    use anyhow::anyhow;
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use itertools::Itertools;
+    use object_store::path::Path;
+    use prometheus::Registry;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use sui_config::node::{
+        AuthorityStorePruningConfig, DBCheckpointCompression, DBCheckpointConfig,
+        DBCheckpointEncryptionConfig, DBCheckpointSourceLayout, DBCheckpointUploadLayout,
+        DBCheckpointUploadOrder, UploadEpochsConfig,
+    };
+    use sui_storage::object_store::retention::RetentionPolicy;
+    use sui_storage::object_store::util::path_to_filesystem;
+    use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use tempfile::TempDir;
+    use tokio_stream::wrappers::BroadcastStream;
+
+    /// Store of the files of `dir`
+    fn file_store_config(dir: &std::path::Path) -> ObjectStoreConfig {
+        ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(dir.to_path_buf()),
+            ..Default::default()
+        }
+    }
+
+    /// Handler uploading the db checkpoints of `checkpoint_dir` to `remote_dir`
+    fn test_handler(
+        checkpoint_dir: &std::path::Path,
+        remote_dir: &std::path::Path,
+    ) -> anyhow::Result<DBCheckpointHandler> {
+        DBCheckpointHandler::new_for_test(
+            &file_store_config(checkpoint_dir),
+            &file_store_config(remote_dir),
+            10,
+            false,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_basic() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        let file1 = local_epoch0_checkpoint.join("file1");
+        fs::write(file1, b"Lorem ipsum")?;
+        let file2 = local_epoch0_checkpoint.join("file2");
+        fs::write(file2, b"Lorem ipsum")?;
+        let nested_dir = local_epoch0_checkpoint.join("data");
+        fs::create_dir(&nested_dir)?;
+        let file3 = nested_dir.join("file3");
+        fs::write(file3, b"Lorem ipsum")?;
+
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let remote_epoch0_checkpoint = remote_checkpoint_dir_path.join("epoch_0");
+
+        let input_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+        let output_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            10,
+            false,
+        )?;
+        let local_checkpoints_by_epoch = db_checkpoint_handler
+            .read_checkpoint_dir(db_checkpoint_handler.input_object_store.clone())
+            .await?;
+        assert!(!local_checkpoints_by_epoch.is_empty());
+        assert_eq!(*local_checkpoints_by_epoch.first_key_value().unwrap().0, 0);
+        assert_eq!(
+            path_to_filesystem(
+                db_checkpoint_handler.input_root_path.clone(),
+                local_checkpoints_by_epoch.first_key_value().unwrap().1
+            )
+            .unwrap(),
+            std::fs::canonicalize(local_epoch0_checkpoint.clone()).unwrap()
+        );
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        assert!(remote_epoch0_checkpoint.join("file1").exists());
+        assert!(remote_epoch0_checkpoint.join("file2").exists());
+        assert!(remote_epoch0_checkpoint.join("data").join("file3").exists());
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(local_epoch0_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+
+        // Drop an extra gc marker meant only for gc to trigger
+        let test_marker = local_epoch0_checkpoint.join(TEST_MARKER);
+        fs::write(test_marker, b"Lorem ipsum")?;
+        db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+
+        assert!(!local_epoch0_checkpoint.join("file1").exists());
+        assert!(!local_epoch0_checkpoint.join("file1").exists());
+        assert!(!local_epoch0_checkpoint.join("file2").exists());
+        assert!(!local_epoch0_checkpoint.join("data").join("file3").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_metrics() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("data"))?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsum")?;
+        fs::write(
+            local_epoch0_checkpoint.join("data").join("file3"),
+            b"Lorem ipsum",
+        )?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        let metrics = &db_checkpoint_handler.metrics;
+        assert_eq!(metrics.last_uploaded_epoch.get(), 0);
+        assert_eq!(metrics.bytes_uploaded.get(), 33);
+        assert_eq!(metrics.upload_duration_seconds.get_sample_count(), 1);
+        assert_eq!(metrics.upload_failures_total.get(), 0);
+
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+        assert_eq!(metrics.gc_deleted_epochs_total.get(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_and_shutdown() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let input_store_config = file_store_config(checkpoint_dir.path());
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let mut db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            10,
+            false,
+        )?;
+        let replica_checkpoint_dir = TempDir::new()?;
+        let replica_store_config = file_store_config(replica_checkpoint_dir.path());
+        db_checkpoint_handler.add_replica(
+            DBCheckpointHandler::new_for_test(
+                &input_store_config,
+                &replica_store_config,
+                10,
+                false,
+            )?
+            .into_replica(1, "replica".to_string(), Registry::default()),
+        );
+        let mut events = db_checkpoint_handler.subscribe();
+
+        let handle = db_checkpoint_handler.start();
+        assert!(handle.is_running());
+        tokio::time::timeout(Duration::from_secs(30), handle.shutdown()).await?;
+        // The stream of events ends once the handler returned and dropped its sender
+        while events.next().await.is_some() {}
+        Ok(())
+    }
+
+    async fn wait_for_upload(
+        events: &mut BroadcastStream<BackupEvent>,
+        expected: u32,
+    ) -> anyhow::Result<()> {
+        while let Some(event) = events.next().await {
+            if let BackupEvent::UploadCompleted { epoch, .. } = event? {
+                if epoch == expected {
+                    return Ok(());
+                }
+            }
+        }
+        Err(anyhow!("Handler stopped before uploading epoch {expected}"))
+    }
+
+    async fn next_upload_started(events: &mut BroadcastStream<BackupEvent>) -> anyhow::Result<u32> {
+        while let Some(event) = events.next().await {
+            if let BackupEvent::UploadStarted { epoch } = event? {
+                return Ok(epoch);
+            }
+        }
+        Err(anyhow!("Handler stopped before starting an upload"))
+    }
+
+    #[tokio::test]
+    async fn test_upload_now() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let input_store_config = file_store_config(checkpoint_dir.path());
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            3600,
+            false,
+        )?;
+        let mut events = db_checkpoint_handler.subscribe();
+        let commander = db_checkpoint_handler.commander();
+        let handle = db_checkpoint_handler.start();
+        // The first pass starts right away
+        tokio::time::timeout(Duration::from_secs(30), wait_for_upload(&mut events, 0)).await??;
+
+        let local_epoch1_checkpoint = checkpoint_dir.path().join("epoch_1");
+        fs::create_dir(&local_epoch1_checkpoint)?;
+        fs::write(local_epoch1_checkpoint.join("file1"), b"Lorem ipsum")?;
+        // Uploaded well before the next pass of the interval
+        commander.upload_now()?;
+        tokio::time::timeout(Duration::from_secs(30), wait_for_upload(&mut events, 1)).await??;
+        assert!(remote_checkpoint_dir
+            .path()
+            .join("epoch_1")
+            .join(SUCCESS_MARKER)
+            .exists());
+
+        handle.shutdown().await;
+        assert!(commander.upload_now().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pause_uploads() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let input_store_config = file_store_config(checkpoint_dir.path());
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            3600,
+            false,
+        )?;
+        let commander = db_checkpoint_handler.commander();
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+
+        commander.pause();
+        db_checkpoint_handler.scan_and_upload().await;
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(db_checkpoint_handler.status.status().paused);
+        // Passes started before the pause stop before their next epoch
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+
+        commander.resume()?;
+        db_checkpoint_handler.scan_and_upload().await;
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(!commander.status().paused);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_admin_delete_and_pause_gc() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let input_store_config = file_store_config(checkpoint_dir.path());
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            3600,
+            false,
+        )?;
+        let commander = db_checkpoint_handler.commander();
+        db_checkpoint_handler.scan_and_upload().await;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+
+        commander.pause_gc();
+        assert!(db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?
+            .is_empty());
+        assert!(local_epoch0_checkpoint.exists());
+
+        db_checkpoint_handler.delete_remote_epoch(0).await?;
+        assert!(!remote_epoch0_checkpoint.exists());
+        // Not uploaded again while its local copy is kept
+        db_checkpoint_handler.scan_and_upload().await;
+        assert!(!remote_epoch0_checkpoint.exists());
+
+        commander.resume_gc();
+        assert_eq!(
+            db_checkpoint_handler
+                .garbage_collect_old_db_checkpoints()
+                .await?,
+            vec![0]
+        );
+        assert!(!local_epoch0_checkpoint.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replicas() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let replica_checkpoint_dir = TempDir::new()?;
+
+        let input_store_config = file_store_config(checkpoint_dir.path());
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let replica_store_config = file_store_config(replica_checkpoint_dir.path());
+        let mut db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            10,
+            false,
+        )?;
+        let replica_registry = Registry::default();
+        db_checkpoint_handler.add_replica(
+            DBCheckpointHandler::new_for_test(
+                &input_store_config,
+                &replica_store_config,
+                10,
+                false,
+            )?
+            .into_replica(1, "replica".to_string(), replica_registry.clone()),
+        );
+        assert_eq!(db_checkpoint_handler.replica_registries().len(), 1);
+        let replica_handler = &db_checkpoint_handler.replicas[0];
+        let replica_marker = local_epoch0_checkpoint.join("_UPLOAD_COMPLETED_REPLICA_1");
+
+        // The replica waits for the db checkpoint to be prepared for the output store
+        let missing_epochs = replica_handler.find_all_missing_checkpoint_epochs().await?;
+        assert_eq!(missing_epochs, vec![0]);
+        replica_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(!replica_checkpoint_dir.path().join("epoch_0").exists());
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(local_epoch0_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+        assert!(!replica_marker.exists());
+        // Not garbage collected before the replica has it too
+        assert!(db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?
+            .is_empty());
+
+        let missing_epochs = replica_handler.find_all_missing_checkpoint_epochs().await?;
+        replica_handler.record_missing_epochs(&missing_epochs);
+        replica_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        let replica_epoch0_checkpoint = replica_checkpoint_dir.path().join("epoch_0");
+        assert!(replica_epoch0_checkpoint.join("file1").exists());
+        assert!(replica_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(replica_marker.exists());
+        // Missing epochs of the replica are tracked in its own metrics
+        let gathered = replica_registry.gather();
+        assert!(gathered
+            .iter()
+            .any(|family| family.get_name() == "first_missing_db_checkpoint_epoch"));
+        // The replica leaves garbage collection to the handler of the output store
+        assert!(replica_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?
+            .is_empty());
+        assert_eq!(
+            db_checkpoint_handler
+                .garbage_collect_old_db_checkpoints()
+                .await?,
+            vec![0]
+        );
+        assert!(!local_epoch0_checkpoint.join("file1").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replica_outage_does_not_block_gc() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        // Left by the second replica, which is not part of the db checkpoint
+        fs::write(
+            local_epoch0_checkpoint.join("_UPLOAD_COMPLETED_REPLICA_2"),
+            b"success",
+        )?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let replica_checkpoint_dir = TempDir::new()?;
+
+        let input_store_config = file_store_config(checkpoint_dir.path());
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let replica_store_config = file_store_config(replica_checkpoint_dir.path());
+        let new_handler = |replica_gc_wait| -> anyhow::Result<DBCheckpointHandler> {
+            let mut handler = DBCheckpointHandler::new_for_test(
+                &input_store_config,
+                &output_store_config,
+                10,
+                false,
+            )?;
+            handler.replica_gc_wait = replica_gc_wait;
+            handler.add_replica(
+                DBCheckpointHandler::new_for_test(
+                    &input_store_config,
+                    &replica_store_config,
+                    10,
+                    false,
+                )?
+                .into_replica(1, "replica".to_string(), Registry::default()),
+            );
+            Ok(handler)
+        };
+        let default_wait = Duration::from_secs(DBCheckpointConfig::default().replica_gc_wait_s());
+        let db_checkpoint_handler = new_handler(default_wait)?;
+
+        // The replica is down, only the output store gets the db checkpoint
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join("file1").exists());
+        assert!(!remote_epoch0_checkpoint
+            .join("_UPLOAD_COMPLETED_REPLICA_2")
+            .exists());
+        assert!(db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?
+            .is_empty());
+
+        // Past the wait, the db checkpoint is deleted without the copy of the replica
+        let db_checkpoint_handler = new_handler(Duration::ZERO)?;
+        assert_eq!(
+            db_checkpoint_handler
+                .garbage_collect_old_db_checkpoints()
+                .await?,
+            vec![0]
+        );
+        assert!(!local_epoch0_checkpoint.join("file1").exists());
+        assert!(!replica_checkpoint_dir.path().join("epoch_0").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_resumes() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let remote_epoch0_checkpoint = remote_checkpoint_dir_path.join("epoch_0");
+
+        let input_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+        let output_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            10,
+            false,
+        )?;
+
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        let file1 = local_epoch0_checkpoint.join("file1");
+        fs::write(file1, b"Lorem ipsum")?;
+        let file2 = local_epoch0_checkpoint.join("file2");
+        fs::write(file2, b"Lorem ipsum")?;
+        let nested_dir = local_epoch0_checkpoint.join("data");
+        fs::create_dir(&nested_dir)?;
+        let file3 = nested_dir.join("file3");
+        fs::write(file3, b"Lorem ipsum")?;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(remote_epoch0_checkpoint.join("file1").exists());
+        assert!(remote_epoch0_checkpoint.join("file2").exists());
+        assert!(remote_epoch0_checkpoint.join("data").join("file3").exists());
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(local_epoch0_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+
+        // Add a new db checkpoint to the local checkpoint directory
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::create_dir(&local_epoch1_checkpoint)?;
+        let file1 = local_epoch1_checkpoint.join("file1");
+        fs::write(file1, b"Lorem ipsum")?;
+        let file2 = local_epoch1_checkpoint.join("file2");
+        fs::write(file2, b"Lorem ipsum")?;
+        let nested_dir = local_epoch1_checkpoint.join("data");
+        fs::create_dir(&nested_dir)?;
+        let file3 = nested_dir.join("file3");
+        fs::write(file3, b"Lorem ipsum")?;
+
+        // Now delete the success marker from remote checkpointed directory
+        // This is the scenario where uploads stops mid way because system stopped
+        fs::remove_file(remote_epoch0_checkpoint.join(SUCCESS_MARKER))?;
+        // A copy of a file which doesn't match the local one is uploaded again
+        fs::write(remote_epoch0_checkpoint.join("file2"), b"Lorem")?;
+        // Even with the same size
+        fs::write(
+            remote_epoch0_checkpoint.join("data").join("file3"),
+            b"Lorem IPSUM",
+        )?;
+
+        // Checkpoint handler should copy checkpoint for epoch_0 first before copying
+        // epoch_1
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(remote_epoch0_checkpoint.join("file1").exists());
+        assert!(remote_epoch0_checkpoint.join("file2").exists());
+        assert!(remote_epoch0_checkpoint.join("data").join("file3").exists());
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(local_epoch0_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+        // The other file of epoch_0 is kept from the interrupted upload
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .upload_resumed_files_total
+                .get(),
+            1
+        );
+        assert_eq!(
+            fs::read(remote_epoch0_checkpoint.join("file2"))?,
+            b"Lorem ipsum"
+        );
+        assert_eq!(
+            fs::read(remote_epoch0_checkpoint.join("data").join("file3"))?,
+            b"Lorem ipsum"
+        );
+
+        let remote_epoch1_checkpoint = remote_checkpoint_dir_path.join("epoch_1");
+        assert!(remote_epoch1_checkpoint.join("file1").exists());
+        assert!(remote_epoch1_checkpoint.join("file2").exists());
+        assert!(remote_epoch1_checkpoint.join("data").join("file3").exists());
+        assert!(remote_epoch1_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(local_epoch1_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+
+        // Drop an extra gc marker meant only for gc to trigger
+        let test_marker = local_epoch0_checkpoint.join(TEST_MARKER);
+        fs::write(test_marker, b"Lorem ipsum")?;
+        let test_marker = local_epoch1_checkpoint.join(TEST_MARKER);
+        fs::write(test_marker, b"Lorem ipsum")?;
+
+        db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+        assert!(!local_epoch0_checkpoint.join("file1").exists());
+        assert!(!local_epoch0_checkpoint.join("file1").exists());
+        assert!(!local_epoch0_checkpoint.join("file2").exists());
+        assert!(!local_epoch0_checkpoint.join("data").join("file3").exists());
+        assert!(!local_epoch1_checkpoint.join("file1").exists());
+        assert!(!local_epoch1_checkpoint.join("file1").exists());
+        assert!(!local_epoch1_checkpoint.join("file2").exists());
+        assert!(!local_epoch1_checkpoint.join("data").join("file3").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_deadline_splits_epoch() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        for file in ["file1", "file2", "file3"] {
+            fs::write(local_epoch0_checkpoint.join(file), b"Lorem ipsum")?;
+        }
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::create_dir(&local_epoch1_checkpoint)?;
+        fs::write(local_epoch1_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        let remote_epoch1_checkpoint = remote_checkpoint_dir.path().join("epoch_1");
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        // Every interval uploads a single batch of one file per epoch
+        db_checkpoint_handler.upload_concurrency = NonZeroUsize::new(1).unwrap();
+        db_checkpoint_handler.epoch_upload_deadline = Some(Duration::ZERO);
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(remote_epoch0_checkpoint.join("file1").exists());
+        assert!(!remote_epoch0_checkpoint.join("file2").exists());
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(local_epoch0_checkpoint
+            .join(UPLOAD_PROGRESS_FILENAME)
+            .exists());
+        assert!(!local_epoch0_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+        // The newer epoch isn't held back by the suspended one
+        assert!(remote_epoch1_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(local_epoch1_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+
+        for _ in 0..2 {
+            let missing_epochs = db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?;
+            assert_eq!(missing_epochs, vec![0, 2]);
+            db_checkpoint_handler
+                .upload_db_checkpoints_to_object_store(missing_epochs)
+                .await?;
+        }
+        assert!(remote_epoch0_checkpoint.join("file3").exists());
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(!local_epoch0_checkpoint
+            .join(UPLOAD_PROGRESS_FILENAME)
+            .exists());
+        assert!(local_epoch0_checkpoint
+            .join(UPLOAD_COMPLETED_MARKER)
+            .exists());
+        // Digests of the files uploaded in earlier intervals are carried over
+        let manifest = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(manifest.files.len(), 3);
+        assert!(manifest
+            .files
+            .iter()
+            .all(|f| f.sha3_digest == sha3_hex(b"Lorem ipsum")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_epochs() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::create_dir(&local_epoch1_checkpoint)?;
+        // Missing epoch 2
+        let local_epoch3_checkpoint = checkpoint_dir_path.join("epoch_3");
+        fs::create_dir(&local_epoch3_checkpoint)?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+
+        let input_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+
+        let output_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            10,
+            false,
+        )?;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        let first_missing_epoch = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?
+            .first()
+            .cloned()
+            .unwrap();
+        assert_eq!(first_missing_epoch, 2);
+
+        let remote_epoch0_checkpoint = remote_checkpoint_dir_path.join("epoch_0");
+        fs::remove_file(remote_epoch0_checkpoint.join(SUCCESS_MARKER))?;
+
+        let first_missing_epoch = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?
+            .first()
+            .cloned()
+            .unwrap();
+        assert_eq!(first_missing_epoch, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_epochs_status() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        for epoch in [0, 1, 3] {
+            fs::create_dir(checkpoint_dir.path().join(format!("epoch_{epoch}")))?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        fs::remove_file(
+            remote_checkpoint_dir
+                .path()
+                .join("epoch_0")
+                .join(SUCCESS_MARKER),
+        )?;
+
+        // The status lists every gap, not only the first one
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler.record_missing_epochs(&missing_epochs);
+        let status = db_checkpoint_handler.status.status();
+        assert_eq!(status.first_missing_epoch, Some(0));
+        assert_eq!(status.num_missing_epochs, 2);
+        assert_eq!(status.missing_epochs, vec![0, 2]);
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .missing_db_checkpoint_epochs_count
+                .get(),
+            2
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backup_watermark() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in [0, 1] {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{epoch}"));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let backup_watermark = BackupWatermark::default();
+        let db_checkpoint_handler = test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?
+            .with_backup_watermark(backup_watermark.clone());
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(backup_watermark.backed_up_epoch(), None);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(backup_watermark.backed_up_epoch(), Some(1));
+
+        // A fresh watermark is raised from what the remote store already holds
+        let backup_watermark = BackupWatermark::default();
+        let db_checkpoint_handler =
+            db_checkpoint_handler.with_backup_watermark(backup_watermark.clone());
+        fs::remove_file(
+            remote_checkpoint_dir_path
+                .join("epoch_1")
+                .join(SUCCESS_MARKER),
+        )?;
+        db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(backup_watermark.backed_up_epoch(), Some(0));
+        // The watermark never goes backwards
+        backup_watermark.confirm(3);
+        backup_watermark.confirm(2);
+        assert_eq!(backup_watermark.backed_up_epoch(), Some(3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_first_missing_epoch_regression() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in [0, 1] {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{epoch}"));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?.with_telemetry(sender);
+        let mut events = db_checkpoint_handler.subscribe();
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler.record_first_missing_epoch(&missing_epochs);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler.record_first_missing_epoch(&missing_epochs);
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .first_missing_db_checkpoint_epoch
+                .get(),
+            2
+        );
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .first_missing_db_checkpoint_epoch_regressions
+                .get(),
+            0
+        );
+
+        // An uploaded epoch disappears from the bucket
+        fs::remove_file(
+            remote_checkpoint_dir_path
+                .join("epoch_0")
+                .join(SUCCESS_MARKER),
+        )?;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler.record_first_missing_epoch(&missing_epochs);
+        // Reported once, not on every scan until the epoch is uploaded again
+        db_checkpoint_handler.record_first_missing_epoch(&missing_epochs);
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .first_missing_db_checkpoint_epoch
+                .get(),
+            0
+        );
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .first_missing_db_checkpoint_epoch_regressions
+                .get(),
+            1
+        );
+        let mut regressions = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            if let BackupTelemetryEvent::FirstMissingEpochRegressed { previous, current } = event {
+                regressions.push((previous, current));
+            }
+        }
+        assert_eq!(regressions, vec![(2, 0)]);
+        drop(db_checkpoint_handler);
+        let mut regressions = vec![];
+        while let Some(event) = events.next().await {
+            if let BackupEvent::FirstMissingEpochRegressed { previous, current } = event? {
+                regressions.push((previous, current));
+            }
+        }
+        assert_eq!(regressions, vec![(2, 0)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_epochs() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        for epoch in 2..=5 {
+            let local_checkpoint = checkpoint_dir.path().join(format!("epoch_{epoch}"));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.upload_epochs = UploadEpochsConfig {
+            start_epoch: Some(3),
+            end_epoch: Some(4),
+            ..Default::default()
+        };
+        let uploaded = |epoch: u32| {
+            remote_checkpoint_dir
+                .path()
+                .join(format!("epoch_{epoch}"))
+                .join(SUCCESS_MARKER)
+                .exists()
+        };
+
+        // Epochs before the start are not missing
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![3]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(
+            (2..=5).map(uploaded).collect_vec(),
+            vec![false, true, true, false]
+        );
+        let upload_marker = |epoch: u32| {
+            fs::read(
+                checkpoint_dir
+                    .path()
+                    .join(format!("epoch_{epoch}"))
+                    .join(UPLOAD_COMPLETED_MARKER),
+            )
+        };
+        assert_eq!(upload_marker(2)?, b"skipped");
+        assert_eq!(upload_marker(3)?, b"success");
+        // Epochs after the end are never uploaded
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![5]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(!uploaded(5));
+
+        // Only the listed epochs, the gaps between them are not missing
+        db_checkpoint_handler.upload_epochs = UploadEpochsConfig {
+            epochs: vec![2, 5],
+            ..Default::default()
+        };
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![2, 5]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(uploaded(2) && uploaded(5));
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![6]
+        );
+
+        // Epochs outside of the upload epochs don't stay on disk forever
+        fs::create_dir(checkpoint_dir.path().join("epoch_7"))?;
+        fs::write(checkpoint_dir.path().join("epoch_7").join("file1"), b"Lorem ipsum")?;
+        fs::write(checkpoint_dir.path().join("epoch_7").join(TEST_MARKER), b"Lorem ipsum")?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![6])
+            .await?;
+        assert!(!uploaded(7));
+        assert_eq!(upload_marker(7)?, b"skipped");
+        assert_eq!(
+            db_checkpoint_handler
+                .garbage_collect_old_db_checkpoints()
+                .await?,
+            vec![2, 3, 4, 5, 7]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_only() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{epoch}"));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let output_store_config = file_store_config(remote_checkpoint_dir_path);
+        let uploader = test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?;
+        uploader
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+
+        let verifier_dir = TempDir::new()?;
+        let mut verifier = DBCheckpointHandler::new_for_test(
+            &file_store_config(verifier_dir.path()),
+            &output_store_config.read_only(),
+            10,
+            false,
+        )?;
+        verifier.verify_only = true;
+        let report = verifier.verify_remote_epochs().await?;
+        assert_eq!(report.latest_complete_epoch, Some(2));
+        assert!(report.missing_epochs.is_empty());
+        assert_eq!(report.verified_epochs, vec![0, 1, 2]);
+        assert_eq!(verifier.metrics.verifier_latest_complete_epoch.get(), 2);
+
+        // Epochs which passed are not read again
+        let report = verifier.verify_remote_epochs().await?;
+        assert!(report.verified_epochs.is_empty());
+        assert_eq!(verifier.metrics.verifier_verified_epochs_total.get(), 3);
+
+        // A lost epoch and a new epoch with a missing file
+        fs::remove_file(
+            remote_checkpoint_dir_path
+                .join("epoch_1")
+                .join(SUCCESS_MARKER),
+        )?;
+        let local_epoch3_checkpoint = checkpoint_dir_path.join("epoch_3");
+        fs::create_dir(&local_epoch3_checkpoint)?;
+        fs::write(local_epoch3_checkpoint.join("file1"), b"Lorem ipsum")?;
+        uploader
+            .upload_db_checkpoints_to_object_store(vec![3])
+            .await?;
+        fs::remove_file(remote_checkpoint_dir_path.join("epoch_3").join("file1"))?;
+        let report = verifier.verify_remote_epochs().await?;
+        assert_eq!(report.latest_complete_epoch, Some(3));
+        assert_eq!(report.missing_epochs, vec![1]);
+        assert_eq!(
+            report.failed_epochs.keys().cloned().collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(verifier.metrics.verifier_missing_epochs.get(), 1);
+        assert_eq!(verifier.metrics.verifier_failed_epochs.get(), 1);
+        // Nothing was written by the verifier
+        assert_eq!(fs::read_dir(verifier_dir.path())?.count(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verification_budget() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..2 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{epoch}"));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join("file2"), b"dolor")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let uploader = test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        uploader
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+
+        let verifier_dir = TempDir::new()?;
+        let mut verifier = DBCheckpointHandler::new_for_test(
+            &file_store_config(verifier_dir.path()),
+            &output_store_config.read_only(),
+            10,
+            false,
+        )?;
+        verifier.verify_only = true;
+        verifier.verify_remote_checksums = true;
+        verifier.verification_budget = VerificationBudget::new(Some(20));
+        // Epoch 0 fits in the budget of the day, epoch 1 doesn't
+        let report = verifier.verify_remote_epochs().await?;
+        assert_eq!(report.verified_epochs, vec![0]);
+        assert_eq!(report.pending_epochs, vec![1]);
+        assert!(report.failed_epochs.is_empty());
+        assert_eq!(verifier.metrics.verifier_budget_consumed_bytes.get(), 16);
+        assert_eq!(verifier.metrics.verifier_content_checked_bytes.get(), 16);
+        assert_eq!(verifier.metrics.verifier_content_total_bytes.get(), 32);
+        let report = verifier.verify_remote_epochs().await?;
+        assert_eq!(report.pending_epochs, vec![1]);
+
+        // The checks resume with the budget of the next day
+        verifier.verification_budget = VerificationBudget::new(Some(11));
+        let report = verifier.verify_remote_epochs().await?;
+        assert_eq!(report.pending_epochs, vec![1]);
+        assert_eq!(verifier.metrics.verifier_content_checked_bytes.get(), 27);
+        verifier.verification_budget = VerificationBudget::new(Some(11));
+        let report = verifier.verify_remote_epochs().await?;
+        assert_eq!(report.verified_epochs, vec![1]);
+        assert!(report.pending_epochs.is_empty());
+        assert_eq!(verifier.metrics.verifier_budget_consumed_bytes.get(), 5);
+        assert_eq!(verifier.metrics.verifier_content_checked_bytes.get(), 32);
+        assert_eq!(verifier.metrics.verifier_verified_epochs_total.get(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_scrub() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in [0, 1] {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{epoch}"));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join("file2"), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.local_scrub_sample_size = 10;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+        let mut events = db_checkpoint_handler.subscribe();
+
+        // Epochs waiting for upload are left to the upload
+        fs::write(checkpoint_dir_path.join("epoch_1").join("file1"), b"")?;
+        let report = db_checkpoint_handler.scrub_local_db_checkpoints().await?;
+        assert_eq!(report.num_epochs, 1);
+        assert_eq!(report.verified_files, 2);
+        assert_eq!(report.verified_bytes, 22);
+        assert!(report.corrupted.is_empty());
+
+        fs::write(
+            checkpoint_dir_path.join("epoch_0").join("file2"),
+            b"Lorem ipsuM",
+        )?;
+        let report = db_checkpoint_handler.scrub_local_db_checkpoints().await?;
+        assert_eq!(report.corrupted, vec![(0, "file2".to_string())]);
+        assert_eq!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::LocalCorruptionDetected {
+                epoch: 0,
+                path: "file2".to_string()
+            })
+        );
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .local_corruption_detected_total
+                .get(),
+            1
+        );
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .local_scrub_verified_files
+                .get(),
+            4
+        );
+        // Missing files can't be restored either
+        fs::remove_file(checkpoint_dir_path.join("epoch_0").join("file1"))?;
+        let report = db_checkpoint_handler.scrub_local_db_checkpoints().await?;
+        assert_eq!(report.corrupted.len(), 2);
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .local_corruption_detected_total
+                .get(),
+            3
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_remote_entries() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        fs::create_dir(checkpoint_dir_path.join("epoch_0"))?;
+        fs::write(
+            checkpoint_dir_path.join("epoch_0").join("file1"),
+            b"Lorem ipsum",
+        )?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        // Data of other tools sharing the bucket
+        fs::write(remote_checkpoint_dir_path.join("lifecycle-marker"), b"")?;
+        fs::create_dir_all(remote_checkpoint_dir_path.join("epoch_latest"))?;
+        fs::write(
+            remote_checkpoint_dir_path
+                .join("epoch_latest")
+                .join("file1"),
+            b"",
+        )?;
+        fs::create_dir_all(remote_checkpoint_dir_path.join("chunks").join("ab"))?;
+        fs::write(
+            remote_checkpoint_dir_path
+                .join("chunks")
+                .join("ab")
+                .join("abcd"),
+            b"",
+        )?;
+        let db_checkpoint_handler = test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![0]);
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .unexpected_remote_entries
+                .get(),
+            2
+        );
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![1]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_missing_epochs() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch100_checkpoint = checkpoint_dir_path.join("epoch_100");
+        fs::create_dir(&local_epoch100_checkpoint)?;
+        let local_epoch200_checkpoint = checkpoint_dir_path.join("epoch_200");
+        fs::create_dir(&local_epoch200_checkpoint)?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+
+        let input_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+
+        let output_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_checkpoint_dir_path.to_path_buf()),
+            ..Default::default()
+        };
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            10,
+            false,
+        )?;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![0]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        let mut expected_missing_epochs: Vec<u32> = (0..100).collect();
+        expected_missing_epochs.extend((101..200).collect_vec().iter());
+        expected_missing_epochs.push(201);
+        assert_eq!(missing_epochs, expected_missing_epochs);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_and_retain_local_epochs() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?;
+        db_checkpoint_handler.verify_after_upload = true;
+        db_checkpoint_handler.num_local_epochs_to_retain = 1;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        for epoch in 0..3 {
+            let remote_checkpoint = remote_checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            assert!(remote_checkpoint.join(SUCCESS_MARKER).exists());
+        }
+
+        // Epoch 0 was garbage collected during the catch-up, as soon as epoch 1 was uploaded
+        assert!(!checkpoint_dir_path.join("epoch_0").exists());
+        assert!(checkpoint_dir_path.join("epoch_1").join("file1").exists());
+        let deleted = db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+        assert_eq!(deleted, vec![1]);
+        assert!(!checkpoint_dir_path.join("epoch_1").exists());
+        assert!(checkpoint_dir_path.join("epoch_2").join("file1").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bundled_epochs_are_not_missing() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        for epoch in 0..4 {
+            let remote_checkpoint = remote_checkpoint_dir.path().join(format!("epoch_{epoch}"));
+            fs::create_dir(&remote_checkpoint)?;
+            fs::write(remote_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(remote_checkpoint.join(SUCCESS_MARKER), b"success")?;
+        }
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        let report = bundle_old_epochs(output_store_config.make()?, 2, 2, false).await?;
+        assert_eq!(report.bundled.get(&0), Some(&vec![0, 1]));
+        assert!(!remote_checkpoint_dir.path().join("epoch_0").exists());
+
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![4]
+        );
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .unexpected_remote_entries
+                .get(),
+            0
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gc_dry_run() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+            // Epoch 2 isn't uploaded yet
+            if epoch < 2 {
+                fs::write(local_checkpoint.join(UPLOAD_COMPLETED_MARKER), b"success")?;
+            }
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.gc_dry_run = true;
+        assert!(db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?
+            .is_empty());
+        for epoch in 0..3 {
+            assert!(checkpoint_dir_path
+                .join(format!("epoch_{}", epoch))
+                .join("file1")
+                .exists());
+        }
+        assert_eq!(
+            db_checkpoint_handler.status.status().gc_dry_run_epochs,
+            vec![0, 1]
+        );
+        assert_eq!(db_checkpoint_handler.metrics.gc_dry_run_epochs.get(), 2);
+        assert_eq!(
+            db_checkpoint_handler.metrics.gc_deleted_epochs_total.get(),
+            0
+        );
+
+        db_checkpoint_handler.gc_dry_run = false;
+        assert_eq!(
+            db_checkpoint_handler
+                .garbage_collect_old_db_checkpoints()
+                .await?,
+            vec![0, 1]
+        );
+        assert!(!checkpoint_dir_path.join("epoch_0").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gc_readiness() -> anyhow::Result<()> {
+        // E.g. a state snapshot uploader, done with the epochs up to `done_up_to`
+        struct EpochsDone {
+            done_up_to: u32,
+        }
+        #[async_trait::async_trait]
+        impl GcReadiness for EpochsDone {
+            fn name(&self) -> &str {
+                "epochs done"
+            }
+            async fn is_ready(&self, local: &LocalCheckpoint) -> anyhow::Result<bool> {
+                Ok(local.epoch <= self.done_up_to)
+            }
+        }
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(UPLOAD_COMPLETED_MARKER), b"success")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?
+                .with_gc_readiness(Arc::new(EpochsDone { done_up_to: 0 }));
+        // Epochs 1 and 2 are uploaded but held back by the condition
+        assert_eq!(
+            db_checkpoint_handler
+                .garbage_collect_old_db_checkpoints()
+                .await?,
+            vec![0]
+        );
+        assert!(checkpoint_dir_path.join("epoch_1").exists());
+        assert!(checkpoint_dir_path.join("epoch_2").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_relieve_disk_pressure() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+            // Epoch 2 isn't uploaded yet
+            if epoch < 2 {
+                fs::write(local_checkpoint.join(UPLOAD_COMPLETED_MARKER), b"success")?;
+            }
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.num_local_epochs_to_retain = 5;
+        assert!(db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?
+            .is_empty());
+        let mut events = db_checkpoint_handler.events.subscribe();
+
+        // More free bytes than any disk has, every uploaded epoch goes despite the retention
+        db_checkpoint_handler.disk_pressure_min_free_bytes = Some(u64::MAX);
+        db_checkpoint_handler.relieve_disk_pressure().await;
+        assert!(!checkpoint_dir_path.join("epoch_0").exists());
+        assert!(!checkpoint_dir_path.join("epoch_1").exists());
+        assert!(checkpoint_dir_path.join("epoch_2").join("file1").exists());
+        assert_eq!(
+            db_checkpoint_handler
+                .metrics
+                .disk_pressure_deleted_epochs_total
+                .get(),
+            2
+        );
+        // Still not enough, with nothing left to delete
+        assert_eq!(db_checkpoint_handler.metrics.disk_pressure.get(), 1);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(BackupEvent::GcPerformed { .. })
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(BackupEvent::Error { epoch: None, .. })
+        ));
+        // Skipped by the upload pass which listed them before
+        assert_eq!(
+            *db_checkpoint_handler.deleted_under_pressure.lock(),
+            BTreeSet::from([0, 1])
+        );
+
+        db_checkpoint_handler.disk_pressure_min_free_bytes = Some(0);
+        db_checkpoint_handler.relieve_disk_pressure().await;
+        assert_eq!(db_checkpoint_handler.metrics.disk_pressure.get(), 0);
+        assert!(checkpoint_dir_path.join("epoch_2").join("file1").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remote_retention() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..4 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?;
+        db_checkpoint_handler.audit_log = Some(AuditLog::new(
+            db_checkpoint_handler.output_object_store.clone(),
+            "node-a".to_string(),
+        ));
+        // Nothing is deleted without a policy
+        assert!(db_checkpoint_handler
+            .apply_remote_retention()
+            .await?
+            .is_empty());
+        db_checkpoint_handler.remote_retention = Some(RetentionPolicy {
+            keep_last: Some(1),
+            pinned: BTreeSet::from([1]),
+            ..Default::default()
+        });
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(
+            db_checkpoint_handler.apply_remote_retention().await?,
+            vec![0, 2]
+        );
+        for (epoch, kept) in [(0, false), (1, true), (2, false), (3, true)] {
+            let remote_checkpoint = remote_checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            assert_eq!(remote_checkpoint.exists(), kept);
+        }
+        let deletes: Vec<u32> =
+            read_audit_log(db_checkpoint_handler.output_object_store.clone(), None)
+                .await?
+                .into_iter()
+                .filter(|record| record.action == AuditAction::RemoteRetentionDelete)
+                .map(|record| record.epoch)
+                .collect();
+        assert_eq!(deletes, vec![0, 2]);
+        // Deleted epochs are not uploaded again
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![4]
+        );
+        // Nor are the ones a wider policy keeps once the audit log records them as deleted
+        db_checkpoint_handler.remote_retention = Some(RetentionPolicy {
+            keep_last: Some(3),
+            ..Default::default()
+        });
+        db_checkpoint_handler
+            .remote_retention_deleted
+            .lock()
+            .clear();
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![4]
+        );
+        // An epoch lost from the remote store otherwise is uploaded again
+        fs::remove_dir_all(remote_checkpoint_dir_path.join("epoch_1"))?;
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![1, 4]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_staged_restore() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("store"))?;
+        fs::write(
+            local_epoch0_checkpoint.join("store").join("file1"),
+            b"Lorem ipsum",
+        )?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+
+        let db_dir = TempDir::new()?;
+        let staging_area = StagingArea::new(db_dir.path().join("staging"));
+        let restorer =
+            DBCheckpointRestorer::new(&[output_store_config], NonZeroUsize::new(1).unwrap())?;
+        // Staging dirs are capped, the restore fails before downloading anything
+        let capped = restorer.with_staging_area(staging_area.clone().with_max_bytes(10));
+        assert!(capped
+            .restore_epoch(0, &db_dir.path().join("live"))
+            .await
+            .is_err());
+        assert!(!db_dir.path().join("live").exists());
+
+        let restorer = capped.with_staging_area(staging_area.clone());
+        restorer
+            .restore_epoch(0, &db_dir.path().join("live"))
+            .await?;
+        assert_eq!(
+            fs::read(db_dir.path().join("live").join("store").join("file1"))?,
+            b"Lorem ipsum"
+        );
+        // Nothing is left behind in the staging area
+        assert_eq!(staging_area.used_bytes()?, 0);
+        assert_eq!(fs::read_dir(staging_area.root())?.count(), 0);
+
+        // Scratch restores may skip syncing to disk
+        restorer
+            .with_fsync(false)
+            .restore_epoch(0, &db_dir.path().join("scratch"))
+            .await?;
+        assert_eq!(
+            fs::read(db_dir.path().join("scratch").join("store").join("file1"))?,
+            b"Lorem ipsum"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.audit_log = Some(AuditLog::new(
+            db_checkpoint_handler.output_object_store.clone(),
+            "node-a".to_string(),
+        ));
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+        let restore_dir = TempDir::new()?;
+        DBCheckpointRestorer::new(&[output_store_config], NonZeroUsize::new(1).unwrap())?
+            .with_audit_actor("operator".to_string())
+            .restore_epoch(0, restore_dir.path())
+            .await?;
+
+        let records =
+            read_audit_log(db_checkpoint_handler.output_object_store.clone(), Some(0)).await?;
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.action, r.actor.as_str()))
+                // Records written within the same millisecond have no defined order
+                .sorted_by_key(|(action, _)| *action as u8)
+                .collect_vec(),
+            vec![
+                (AuditAction::Upload, "node-a"),
+                (AuditAction::RetentionDelete, "node-a"),
+                (AuditAction::Restore, "operator")
+            ]
+        );
+        let digest = manifest_sha3_digest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert!(records
+            .iter()
+            .all(|r| r.manifest_sha3_digest.as_ref() == Some(&digest)));
+        // The audit log is not mistaken for an epoch
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![1]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_consolidated_epoch_metadata() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.consolidate_epoch_metadata = true;
+        db_checkpoint_handler.audit_log = Some(AuditLog::new(
+            db_checkpoint_handler.output_object_store.clone(),
+            "node-a".to_string(),
+        ));
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        // The success marker is the only metadata object of the epoch
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(!remote_epoch0_checkpoint.join(MANIFEST_FILENAME).exists());
+        assert!(!remote_checkpoint_dir.path().join(AUDIT_DIR).exists());
+        let store = db_checkpoint_handler.output_object_store.clone();
+        let metadata = read_epoch_metadata(&Path::from("epoch_0"), store.clone()).await?;
+        assert_eq!(
+            read_manifest(&Path::from("epoch_0"), store.clone()).await?,
+            metadata.manifest
+        );
+        assert_eq!(
+            manifest_sha3_digest(&Path::from("epoch_0"), store.clone()).await?,
+            metadata.manifest_sha3_digest
+        );
+        // Uploading the epoch again keeps the record of the first upload
+        db_checkpoint_handler
+            .upload_db_checkpoint(&LocalCheckpoint {
+                epoch: 0,
+                data_dir: Path::from("epoch_0"),
+                state_dir: Path::from("epoch_0"),
+            })
+            .await?;
+        assert_eq!(read_audit_log(store.clone(), Some(0)).await?.len(), 2);
+
+        db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+        let restore_dir = TempDir::new()?;
+        DBCheckpointRestorer::new(&[output_store_config], NonZeroUsize::new(1).unwrap())?
+            .restore_epoch(0, restore_dir.path())
+            .await?;
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, b"Lorem ipsum");
+
+        let records = read_audit_log(store.clone(), None).await?;
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| r.action)
+                .sorted_by_key(|action| *action as u8)
+                .collect_vec(),
+            vec![
+                AuditAction::Upload,
+                AuditAction::Upload,
+                AuditAction::RetentionDelete
+            ]
+        );
+        assert!(records
+            .iter()
+            .all(|r| r.manifest_sha3_digest.as_ref() == Some(&metadata.manifest_sha3_digest)));
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![1]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sharded_manifest() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        for i in 1..=6 {
+            fs::write(
+                local_epoch0_checkpoint.join(format!("file{i}")),
+                format!("Lorem ipsum {i}"),
+            )?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.manifest_shard_size = NonZeroUsize::new(2).unwrap();
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        for shard in ["00001-of-00003", "00002-of-00003", "00003-of-00003"] {
+            assert!(remote_epoch0_checkpoint
+                .join(format!("{MANIFEST_FILENAME}.{shard}"))
+                .exists());
+        }
+        let store = db_checkpoint_handler.output_object_store.clone();
+        let epoch_dir = Path::from("epoch_0");
+        let index = read_manifest_index(&epoch_dir, store.clone()).await?;
+        assert!(index.files.is_empty());
+        assert_eq!(index.shards.len(), 3);
+        assert_eq!(index.num_files(), 6);
+        let manifest = read_manifest(&epoch_dir, store.clone()).await?;
+        assert_eq!(manifest.files.len(), 6);
+        assert!(manifest.shards.is_empty());
+        assert_eq!(manifest.total_size(), index.total_size());
+
+        let restore_dir = TempDir::new()?;
+        DBCheckpointRestorer::new(&[output_store_config], NonZeroUsize::new(2).unwrap())?
+            .restore_epoch(0, restore_dir.path())
+            .await?;
+        for i in 1..=6 {
+            assert_eq!(
+                fs::read(restore_dir.path().join(format!("file{i}")))?,
+                format!("Lorem ipsum {i}").into_bytes()
+            );
+        }
+
+        // A shard which doesn't match the digest in the index is rejected
+        fs::write(
+            remote_epoch0_checkpoint.join(format!("{MANIFEST_FILENAME}.00002-of-00003")),
+            b"[]",
+        )?;
+        assert!(read_manifest(&epoch_dir, store).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_telemetry_events() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?.with_telemetry(sender);
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+
+        assert_eq!(
+            receiver.recv().await,
+            Some(BackupTelemetryEvent::EpochUploadStarted { epoch: 0 })
+        );
+        assert!(matches!(
+            receiver.recv().await,
+            Some(BackupTelemetryEvent::EpochUploadCompleted { epoch: 0, .. })
+        ));
+        assert_eq!(
+            receiver.recv().await,
+            Some(BackupTelemetryEvent::GcCompleted { epochs: vec![0] })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expected_files_guard() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("data"))?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(
+            local_epoch0_checkpoint.join("data").join("file2"),
+            b"Lorem ipsum",
+        )?;
+        ExpectedFiles::write(&local_epoch0_checkpoint)?;
+        // Simulate a checkpoint which was only partially copied into place
+        fs::remove_file(local_epoch0_checkpoint.join("data").join("file2"))?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await
+            .is_err());
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+
+        fs::write(
+            local_epoch0_checkpoint.join("data").join("file2"),
+            b"Lorem ipsum",
+        )?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(remote_epoch0_checkpoint.join("data").join("file2").exists());
+        // The listing is local only
+        assert!(!remote_epoch0_checkpoint.join(EXPECTED_FILENAME).exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_quarantine() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsum")?;
+        ExpectedFiles::write(&local_epoch0_checkpoint)?;
+        // Fails every upload of epoch 0
+        fs::remove_file(local_epoch0_checkpoint.join("file2"))?;
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::create_dir_all(&local_epoch1_checkpoint)?;
+        fs::write(local_epoch1_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.quarantine_threshold = Some(2);
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        let remote_epoch1_checkpoint = remote_checkpoint_dir.path().join("epoch_1");
+
+        // Retried as usual below the threshold, holding back the newer epoch
+        assert!(db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await
+            .is_err());
+        assert!(!remote_epoch1_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(db_checkpoint_handler
+            .status
+            .status()
+            .quarantined_epochs
+            .is_empty());
+
+        // Quarantined on reaching it, the newer epoch is uploaded in the same pass
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(remote_epoch1_checkpoint.join(SUCCESS_MARKER).exists());
+        assert_eq!(
+            db_checkpoint_handler.status.status().quarantined_epochs,
+            vec![0]
+        );
+        assert_eq!(db_checkpoint_handler.metrics.quarantined_epochs.get(), 1);
+        assert_eq!(db_checkpoint_handler.metrics.upload_failures_total.get(), 2);
+
+        // Skipped by the following passes, even once it could be uploaded
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsum")?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0, 2])
+            .await?;
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert_eq!(db_checkpoint_handler.metrics.upload_failures_total.get(), 2);
+
+        // Until the retry delay elapses
+        db_checkpoint_handler.quarantine_retry = Duration::ZERO;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0, 2])
+            .await?;
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(db_checkpoint_handler
+            .status
+            .status()
+            .quarantined_epochs
+            .is_empty());
+        assert_eq!(db_checkpoint_handler.metrics.quarantined_epochs.get(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_failures_do_not_quarantine() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.quarantine_threshold = Some(1);
+
+        let store_error = anyhow::Error::new(object_store::Error::NotImplemented)
+            .context("Failed to upload epoch 0");
+        assert!(!super::is_epoch_specific(&store_error));
+        let epoch_error = anyhow!("Missing expected files of epoch 0");
+        assert!(super::is_epoch_specific(&epoch_error));
+
+        assert!(db_checkpoint_handler.record_upload_failure(0));
+        assert!(db_checkpoint_handler.is_quarantined(0));
+        assert!(!db_checkpoint_handler.is_quarantined(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_probe_destination() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir_all(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_root = remote_checkpoint_dir.path().join("remote");
+
+        let input_store_config = file_store_config(checkpoint_dir.path());
+        let output_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_root.clone()),
+            ..Default::default()
+        };
+        let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+            &input_store_config,
+            &output_store_config,
+            10,
+            false,
+        )?;
+        // The store can't be read once its root is no longer a directory
+        fs::remove_dir(&remote_root)?;
+        fs::write(&remote_root, b"Lorem ipsum")?;
+        let mut events = db_checkpoint_handler.events.subscribe();
+        db_checkpoint_handler.scan_and_upload().await;
+        assert_eq!(
+            db_checkpoint_handler.metrics.destination_unreachable.get(),
+            1
+        );
+        // Only the probe failed, the pass was skipped
+        assert_eq!(db_checkpoint_handler.metrics.upload_failures_total.get(), 0);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(BackupEvent::Error { epoch: None, .. })
+        ));
+        db_checkpoint_handler.scan_and_upload().await;
+        assert!(events.try_recv().is_err());
+
+        fs::remove_file(&remote_root)?;
+        fs::create_dir_all(&remote_root)?;
+        db_checkpoint_handler.scan_and_upload().await;
+        assert_eq!(
+            db_checkpoint_handler.metrics.destination_unreachable.get(),
+            0
+        );
+        assert!(remote_root.join("epoch_0").join(SUCCESS_MARKER).exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recheck_recent_uploads() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.consistency_grace_period = Some(Duration::from_secs(2));
+        // Epoch 1 was just uploaded but a lagging listing still reports it as missing
+        let remote_epoch1_checkpoint = remote_checkpoint_dir.path().join("epoch_1");
+        fs::create_dir_all(&remote_epoch1_checkpoint)?;
+        fs::write(remote_epoch1_checkpoint.join(SUCCESS_MARKER), b"success")?;
+        db_checkpoint_handler
+            .recent_uploads
+            .lock()
+            .insert(1, Instant::now());
+        assert_eq!(
+            db_checkpoint_handler
+                .recheck_recent_uploads(vec![0, 1])
+                .await,
+            vec![0, 2]
+        );
+
+        // Epoch 3 is reported once its grace period ran out without a visible marker
+        db_checkpoint_handler
+            .recent_uploads
+            .lock()
+            .insert(3, Instant::now());
+        let start = Instant::now();
+        assert_eq!(
+            db_checkpoint_handler.recheck_recent_uploads(vec![3]).await,
+            vec![3]
+        );
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_events() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        let mut events = db_checkpoint_handler.subscribe();
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?;
+
+        assert_eq!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::EpochDiscovered { epoch: 0 })
+        );
+        assert_eq!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::UploadStarted { epoch: 0 })
+        );
+        assert!(matches!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::UploadCompleted { epoch: 0, .. })
+        ));
+        assert_eq!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::GcPerformed { epochs: vec![0] })
+        );
+
+        // Failures are published with the epoch they relate to
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::create_dir(&local_epoch1_checkpoint)?;
+        fs::write(local_epoch1_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(
+            local_epoch1_checkpoint.join(LOCAL_CHECKSUMS_FILENAME),
+            b"not a manifest",
+        )?;
+        assert!(db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![1])
+            .await
+            .is_err());
+        assert_eq!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::EpochDiscovered { epoch: 1 })
+        );
+        assert_eq!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::UploadStarted { epoch: 1 })
+        );
+        assert!(matches!(
+            events.next().await.transpose()?,
+            Some(BackupEvent::Error { epoch: Some(1), .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_newest_first() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.upload_order = DBCheckpointUploadOrder::NewestFirst;
+        let mut events = db_checkpoint_handler.subscribe();
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        let mut started = vec![];
+        while started.len() < 3 {
+            if let Some(BackupEvent::UploadStarted { epoch }) = events.next().await.transpose()? {
+                started.push(epoch);
+            }
+        }
+        assert_eq!(started, vec![2, 1, 0]);
+        // Older epochs are backfilled all the same
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![3]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resume_newest_first_backfill() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join("file2"), b"Lorem ipsum")?;
+            fs::write(local_checkpoint.join(TEST_MARKER), b"Lorem ipsum")?;
+            ExpectedFiles::write(&local_checkpoint)?;
+        }
+        // Interrupts the backfill once the latest epoch is uploaded
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::remove_file(local_epoch1_checkpoint.join("file2"))?;
+        let remote_checkpoint_dir = TempDir::new()?;
+
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.upload_order = DBCheckpointUploadOrder::NewestFirst;
+        let mut events = db_checkpoint_handler.subscribe();
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert!(db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await
+            .is_err());
+        assert_eq!(next_upload_started(&mut events).await?, 2);
+        assert_eq!(next_upload_started(&mut events).await?, 1);
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![0, 1, 3]
+        );
+
+        // Resumes with the epochs left, newest first, without uploading the latest one again
+        fs::write(local_epoch1_checkpoint.join("file2"), b"Lorem ipsum")?;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(next_upload_started(&mut events).await?, 1);
+        assert_eq!(next_upload_started(&mut events).await?, 0);
+        assert_eq!(
+            db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?,
+            vec![3]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hashed_sharded_layout() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let nested_dir = local_epoch0_checkpoint.join("data");
+        fs::create_dir(&nested_dir)?;
+        fs::write(nested_dir.join("file3"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?;
+        db_checkpoint_handler.upload_layout = DBCheckpointUploadLayout::HashedSharded;
+        db_checkpoint_handler.verify_after_upload = true;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        let remote_epoch0_checkpoint = remote_checkpoint_dir_path.join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(!remote_epoch0_checkpoint.join("file1").exists());
+        let manifest = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(
+            manifest.files.iter().map(|f| f.path.as_str()).collect_vec(),
+            vec!["data/file3", "file1"]
+        );
+        for file in manifest.files.iter() {
+            let remote_file = path_to_filesystem(
+                remote_checkpoint_dir_path.to_path_buf(),
+                &Path::from(file.remote_path.as_str()),
+            )?;
+            assert_eq!(fs::read(remote_file)?, b"Lorem ipsum");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_remote_checksums() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("data"))?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(
+            local_epoch0_checkpoint.join("data").join("file3"),
+            b"Lorem ipsum",
+        )?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.compression = DBCheckpointCompression::Zstd;
+        db_checkpoint_handler.verify_after_upload = true;
+        db_checkpoint_handler.verify_remote_checksums = true;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+
+        // Corrupted contents of the same size pass the listing, but not the checksums
+        let remote_dir = Path::from("epoch_0");
+        let manifest = read_manifest(
+            &remote_dir,
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        let compressed = fs::read(remote_epoch0_checkpoint.join("file1.zst"))?;
+        fs::write(
+            remote_epoch0_checkpoint.join("file1.zst"),
+            compress_file(
+                DBCheckpointCompression::Zstd,
+                Bytes::from_static(b"Lorem ipsuM"),
+            )?,
+        )?;
+        assert_eq!(
+            fs::metadata(remote_epoch0_checkpoint.join("file1.zst"))?.len() as usize,
+            compressed.len()
+        );
+        db_checkpoint_handler.verify_remote_checksums = false;
+        db_checkpoint_handler
+            .verify_remote_checkpoint(&remote_dir, &manifest)
+            .await?;
+        db_checkpoint_handler.verify_remote_checksums = true;
+        let err = db_checkpoint_handler
+            .verify_remote_checkpoint(&remote_dir, &manifest)
+            .await
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("Checksum mismatch for file1"));
+
+        // Contents expanding beyond the recorded size stop the decompression as they stream
+        fs::write(
+            remote_epoch0_checkpoint.join("file1.zst"),
+            compress_file(
+                DBCheckpointCompression::Zstd,
+                Bytes::from(vec![0u8; 1 << 20]),
+            )?,
+        )?;
+        let err = stream_verify_file(
+            manifest.file("file1").unwrap(),
+            Some(DBCheckpointCompression::Zstd),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await
+        .unwrap_err();
+        assert!(format!("{:?}", err).contains("larger than the 11 bytes expected"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mirrored_with_sums_layout() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("data"))?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(
+            local_epoch0_checkpoint.join("data").join("file2"),
+            b"dolor sit",
+        )?;
+        fs::write(local_epoch0_checkpoint.join("data").join("empty"), b"")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.upload_layout = DBCheckpointUploadLayout::MirroredWithSums;
+        db_checkpoint_handler.verify_after_upload = true;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        // The epoch directory is a plain copy of the db checkpoint, empty files included
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join("file1").exists());
+        assert!(remote_epoch0_checkpoint.join("data").join("file2").exists());
+        assert!(remote_epoch0_checkpoint.join("data").join("empty").exists());
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        let sums = fs::read_to_string(remote_epoch0_checkpoint.join(SUMS_FILENAME))?;
+        assert_eq!(
+            sums,
+            format!(
+                "{}  data/empty\n{}  data/file2\n{}  file1\n",
+                sha256_hex(b""),
+                sha256_hex(b"dolor sit"),
+                sha256_hex(b"Lorem ipsum")
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tar_archives_layout() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("data"))?;
+        let contents = b"Lorem ipsum ".repeat(1000);
+        fs::write(local_epoch0_checkpoint.join("file1"), &contents)?;
+        fs::write(
+            local_epoch0_checkpoint.join("data").join("file2"),
+            b"dolor sit amet",
+        )?;
+        fs::write(local_epoch0_checkpoint.join("data").join("empty"), b"")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.upload_layout = DBCheckpointUploadLayout::TarArchives;
+        db_checkpoint_handler.archive_size_bytes = 100;
+        db_checkpoint_handler.verify_after_upload = true;
+        db_checkpoint_handler.verify_remote_checksums = true;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        // The small files share an archive, the one larger than an archive is uploaded as is
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(!remote_epoch0_checkpoint.join("data").exists());
+        let archive = fs::read(remote_epoch0_checkpoint.join("archive_00000000.tar.zst"))?;
+        assert!(!remote_epoch0_checkpoint
+            .join("archive_00000002.tar.zst")
+            .exists());
+        assert_eq!(fs::read(remote_epoch0_checkpoint.join("file1"))?, contents);
+        let manifest = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(manifest.layout, DBCheckpointUploadLayout::TarArchives);
+        let file2 = manifest.file("data/file2").unwrap();
+        assert_eq!(file2.remote_path, "epoch_0/archive_00000000.tar.zst");
+        assert!(file2.archive_offset.unwrap() > 0);
+        let file1 = manifest.file("file1").unwrap();
+        assert_eq!(file1.remote_path, "epoch_0/file1");
+        assert_eq!(file1.archive_offset, None);
+        assert_eq!(file1.compressed_size, None);
+        assert_eq!(file1.sha3_digest, sha3_hex(&contents));
+
+        let restore_dir = TempDir::new()?;
+        let restorer =
+            DBCheckpointRestorer::new(&[output_store_config], NonZeroUsize::new(2).unwrap())?;
+        restorer.restore_epoch(0, restore_dir.path()).await?;
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, contents);
+        assert_eq!(
+            fs::read(restore_dir.path().join("data").join("file2"))?,
+            b"dolor sit amet"
+        );
+        assert!(restore_dir.path().join("data").join("empty").exists());
+
+        // A member damaged in the archive fails the restore
+        let mut archive = archive;
+        archive[file2.archive_offset.unwrap()] ^= 0xff;
+        fs::write(
+            remote_epoch0_checkpoint.join("archive_00000000.tar.zst"),
+            &archive,
+        )?;
+        let restore_dir = TempDir::new()?;
+        assert!(restorer.restore_epoch(0, restore_dir.path()).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_zstd_compression() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("data"))?;
+        let contents = b"Lorem ipsum ".repeat(1000);
+        fs::write(local_epoch0_checkpoint.join("file1"), &contents)?;
+        fs::write(local_epoch0_checkpoint.join("data").join("empty"), b"")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.compression = DBCheckpointCompression::Zstd;
+        db_checkpoint_handler.verify_after_upload = true;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(!remote_epoch0_checkpoint.join("file1").exists());
+        let compressed = fs::read(remote_epoch0_checkpoint.join("file1.zst"))?;
+        assert!(compressed.len() < contents.len());
+        let manifest = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(manifest.compression, Some(DBCheckpointCompression::Zstd));
+        let file1 = manifest.file("file1").unwrap();
+        assert_eq!(file1.remote_path, "epoch_0/file1.zst");
+        assert_eq!(file1.size, contents.len());
+        assert_eq!(file1.compressed_size, Some(compressed.len()));
+        assert_eq!(file1.sha3_digest, sha3_hex(&contents));
+        assert_eq!(manifest.compressed_size, Some(compressed.len()));
+        assert!(manifest.compression_ratio().unwrap() > 1.0);
+        // Contents expanding past the recorded size are rejected
+        let compression = Some(DBCheckpointCompression::Zstd);
+        let compressed = Bytes::from(compressed);
+        assert_eq!(
+            decompress_file(compression, compressed.clone(), contents.len())?,
+            contents
+        );
+        assert!(decompress_file(compression, compressed, contents.len() - 1).is_err());
+
+        let restore_dir = TempDir::new()?;
+        let restorer =
+            DBCheckpointRestorer::new(&[output_store_config], NonZeroUsize::new(2).unwrap())?;
+        restorer.restore_epoch(0, restore_dir.path()).await?;
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, contents);
+        assert!(restore_dir.path().join("data").join("empty").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_zstd_seekable_compression() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(&local_epoch0_checkpoint)?;
+        let contents: Vec<u8> = (0..2 * SEEKABLE_FRAME_SIZE + 100)
+            .map(|i| (i / 1000) as u8)
+            .collect();
+        fs::write(local_epoch0_checkpoint.join("file1"), &contents)?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.compression = DBCheckpointCompression::ZstdSeekable;
+        db_checkpoint_handler.verify_after_upload = true;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        let store = db_checkpoint_handler.output_object_store.clone();
+        let manifest = read_manifest(&Path::from("epoch_0"), store.clone()).await?;
+        let file1 = manifest.file("file1").unwrap();
+        assert_eq!(file1.remote_path, "epoch_0/file1.zst");
+        assert_eq!(file1.frames.len(), 3);
+        assert_eq!(file1.frames[2].size, 100);
+        // Across the boundary of the first two frames
+        let range = SEEKABLE_FRAME_SIZE - 10..SEEKABLE_FRAME_SIZE + 10;
+        assert_eq!(
+            read_file_range(store.clone(), file1, range.clone()).await?,
+            contents[range].to_vec()
+        );
+        assert!(read_file_range(store.clone(), file1, 0..contents.len() + 1)
+            .await
+            .is_err());
+
+        // A corrupted frame only fails the reads overlapping it
+        let remote_file1 = remote_checkpoint_dir
+            .path()
+            .join("epoch_0")
+            .join("file1.zst");
+        let mut compressed = fs::read(&remote_file1)?;
+        let frame = &file1.frames[1];
+        compressed[frame.offset + frame.compressed_size / 2] ^= 0xff;
+        fs::write(&remote_file1, compressed)?;
+        assert_eq!(
+            read_file_range(store.clone(), file1, 0..10).await?,
+            contents[0..10].to_vec()
+        );
+        assert!(read_file_range(
+            store.clone(),
+            file1,
+            SEEKABLE_FRAME_SIZE..SEEKABLE_FRAME_SIZE + 10
+        )
+        .await
+        .is_err());
+
+        // Restores verify every frame, and resume after the frames an interrupted restore
+        // verified, here past the corrupted one
+        let restorer = DBCheckpointRestorer::new(
+            &[file_store_config(remote_checkpoint_dir.path())],
+            NonZeroUsize::new(2).unwrap(),
+        )?;
+        let restore_dir = TempDir::new()?;
+        assert!(restorer.restore_epoch(0, restore_dir.path()).await.is_err());
+        let mut interrupted = contents[..2 * SEEKABLE_FRAME_SIZE].to_vec();
+        interrupted.extend_from_slice(b"garbage");
+        fs::write(restore_dir.path().join("file1.decoded"), interrupted)?;
+        restorer.restore_epoch(0, restore_dir.path()).await?;
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, contents);
+        assert!(!restore_dir.path().join("file1.decoded").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_side_encryption() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir_all(local_epoch0_checkpoint.join("data"))?;
+        let contents = b"Lorem ipsum ".repeat(1000);
+        fs::write(local_epoch0_checkpoint.join("file1"), &contents)?;
+        fs::write(local_epoch0_checkpoint.join("data").join("empty"), b"")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let key_dir = TempDir::new()?;
+        let key_path = key_dir.path().join("key");
+        fs::write(&key_path, "ab".repeat(32))?;
+
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        let encryption = DBCheckpointEncryptionConfig {
+            key_id: "key-1".to_string(),
+            key_path: Some(key_path),
+            kms_encrypted_key: None,
+        };
+        db_checkpoint_handler.compression = DBCheckpointCompression::Zstd;
+        db_checkpoint_handler.encryption = Some(encryption.clone());
+        db_checkpoint_handler.verify_after_upload = true;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        let encrypted = fs::read(remote_epoch0_checkpoint.join("file1.zst.enc"))?;
+        assert!(!encrypted
+            .windows(b"Lorem ipsum".len())
+            .any(|window| window == b"Lorem ipsum"));
+        let manifest = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(manifest.encryption_key_id.as_deref(), Some("key-1"));
+        let file1 = manifest.file("file1").unwrap();
+        assert_eq!(file1.remote_path, "epoch_0/file1.zst.enc");
+        assert_eq!(
+            file1.compressed_size.map(|size| size + ENCRYPTION_OVERHEAD),
+            Some(encrypted.len())
+        );
+        assert!(file1.sha3_digest.starts_with(SEALED_DIGEST_PREFIX));
+        let mut opened = file1.clone();
+        opened.open_digest(&EncryptionKey::load(&encryption).await?)?;
+        assert_eq!(opened.sha3_digest, sha3_hex(&contents));
+
+        // Restores need the key the epoch was encrypted with
+        let restore_dir = TempDir::new()?;
+        let restorer = DBCheckpointRestorer::new(
+            &[output_store_config.clone()],
+            NonZeroUsize::new(2).unwrap(),
+        )?;
+        assert!(restorer.restore_epoch(0, restore_dir.path()).await.is_err());
+        let restorer =
+            DBCheckpointRestorer::new(&[output_store_config], NonZeroUsize::new(2).unwrap())?
+                .with_encryption_key(EncryptionKey::load(&encryption).await?);
+        restorer.restore_epoch(0, restore_dir.path()).await?;
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, contents);
+        assert!(restore_dir.path().join("data").join("empty").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_content_defined_chunks_reuse_unchanged_files() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("000001.sst"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?;
+        db_checkpoint_handler.upload_layout = DBCheckpointUploadLayout::ContentDefinedChunks;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        let manifest0 = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        let reused_chunk = path_to_filesystem(
+            remote_checkpoint_dir_path.to_path_buf(),
+            &chunk_path(&manifest0.files[0].chunks[0].sha3_digest),
+        )?;
+        // Lost from the chunk area, e.g. by a lifecycle rule, so it can't be reused as is
+        fs::remove_file(&reused_chunk)?;
+
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::create_dir(&local_epoch1_checkpoint)?;
+        fs::write(local_epoch1_checkpoint.join("000001.sst"), b"Lorem ipsum")?;
+        fs::write(
+            local_epoch1_checkpoint.join("000002.sst"),
+            b"dolor sit amet",
+        )?;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![1]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        let manifest1 = read_manifest(
+            &Path::from("epoch_1"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(manifest1.files.len(), 2);
+        let reused = manifest1
+            .files
+            .iter()
+            .find(|file| file.path == "000001.sst")
+            .unwrap();
+        assert_eq!(reused.chunks, manifest0.files[0].chunks);
+        assert_eq!(fs::read(&reused_chunk)?, b"Lorem ipsum");
+        let changed = manifest1
+            .files
+            .iter()
+            .find(|file| file.path == "000002.sst")
+            .unwrap();
+        assert_eq!(
+            fs::read(path_to_filesystem(
+                remote_checkpoint_dir_path.to_path_buf(),
+                &chunk_path(&changed.chunks[0].sha3_digest),
+            )?)?,
+            b"dolor sit amet"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_content_defined_chunks_dedup_across_epochs() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..2 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            // Same contents under a different file name, as after an SST rewrite
+            fs::write(
+                local_checkpoint.join(format!("00000{}.sst", epoch)),
+                b"Lorem ipsum",
+            )?;
+        }
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir_path = remote_checkpoint_dir.path();
+
+        let output_store_config = file_store_config(remote_checkpoint_dir_path);
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir_path)?;
+        db_checkpoint_handler.upload_layout = DBCheckpointUploadLayout::ContentDefinedChunks;
+        db_checkpoint_handler.verify_after_upload = true;
+        db_checkpoint_handler.verify_remote_checksums = true;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![0, 1]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        // Both epochs reference the same single chunk
+        let manifest0 = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        let manifest1 = read_manifest(
+            &Path::from("epoch_1"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(manifest0.files[0].chunks.len(), 1);
+        assert_eq!(manifest0.files[0].chunks, manifest1.files[0].chunks);
+        assert!(!remote_checkpoint_dir_path
+            .join("epoch_1")
+            .join("000001.sst")
+            .exists());
+        let chunk_path = path_to_filesystem(
+            remote_checkpoint_dir_path.to_path_buf(),
+            &chunk_path(&manifest1.files[0].chunks[0].sha3_digest),
+        )?;
+        assert_eq!(fs::read(&chunk_path)?, b"Lorem ipsum");
+
+        let restore_dir = TempDir::new()?;
+        let restorer = DBCheckpointRestorer::new(
+            &[output_store_config.clone()],
+            NonZeroUsize::new(2).unwrap(),
+        )?;
+        restorer.restore_epoch(1, restore_dir.path()).await?;
+        assert_eq!(
+            fs::read(restore_dir.path().join("000001.sst"))?,
+            b"Lorem ipsum"
+        );
+
+        // The verify-only role checks the contents of the files through their chunks
+        let verifier_dir = TempDir::new()?;
+        let mut verifier = DBCheckpointHandler::new_for_test(
+            &file_store_config(verifier_dir.path()),
+            &output_store_config.read_only(),
+            10,
+            false,
+        )?;
+        verifier.verify_only = true;
+        verifier.verify_remote_checksums = true;
+        // Corrupted contents of the same size pass the chunk sizes, but not the checksums
+        fs::write(&chunk_path, b"Lorem ipsuM")?;
+        let report = verifier.verify_remote_epochs().await?;
+        assert!(report.verified_epochs.is_empty());
+        assert_eq!(
+            report.failed_epochs.into_keys().collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        fs::write(&chunk_path, b"Lorem ipsum")?;
+        let report = verifier.verify_remote_epochs().await?;
+        assert_eq!(report.verified_epochs, vec![0, 1]);
+        assert!(report.failed_epochs.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_planner() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..2 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{}", epoch));
+            fs::create_dir(&local_checkpoint)?;
+            fs::write(local_checkpoint.join("file1"), b"Lorem ipsum")?;
+        }
+        let input_store_config = file_store_config(checkpoint_dir_path);
+        // The first bucket holds both epochs, the second one only epoch 0
+        let remote_dirs = vec![TempDir::new()?, TempDir::new()?];
+        let mut bucket_configs = vec![];
+        for remote_dir in remote_dirs.iter() {
+            let output_store_config = file_store_config(remote_dir.path());
+            let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+                &input_store_config,
+                &output_store_config,
+                10,
+                false,
+            )?;
+            db_checkpoint_handler
+                .upload_db_checkpoints_to_object_store(vec![0, 1])
+                .await?;
+            bucket_configs.push(output_store_config);
+        }
+        fs::remove_dir_all(remote_dirs[1].path().join("epoch_1"))?;
+
+        let peer_sync = PeerSyncEstimate {
+            current_epoch: 3,
+            per_epoch_sync_duration_ms: 3_600_000,
+        };
+        let planner = BootstrapPlanner::new(
+            bucket_configs.clone(),
+            Some(peer_sync),
+            2,
+            NonZeroUsize::new(2).unwrap(),
+        );
+        let plan = planner.plan().await?;
+        assert_eq!(plan.candidates.len(), 3);
+        // Epoch 0 is too far behind the network to be a safe starting point
+        assert_eq!(
+            plan.candidates
+                .iter()
+                .filter(|c| c.rejected.is_some())
+                .count(),
+            1
+        );
+        let BootstrapSource::DbCheckpoint { epoch, .. } = &plan.chosen else {
+            panic!("Expected a db checkpoint, got: {:?}", plan.chosen);
+        };
+        assert_eq!(*epoch, 1);
+
+        let db_dir = TempDir::new()?;
+        planner.execute(&plan, db_dir.path()).await?;
+        assert_eq!(fs::read(db_dir.path().join("file1"))?, b"Lorem ipsum");
+        let recorded: BootstrapPlan =
+            serde_json::from_slice(&fs::read(db_dir.path().join(BOOTSTRAP_DECISION_FILENAME))?)?;
+        assert_eq!(recorded, plan);
+
+        // Without any safe db checkpoint, fall back to syncing from peers
+        let planner = BootstrapPlanner::new(
+            bucket_configs[1..].to_vec(),
+            Some(peer_sync),
+            2,
+            NonZeroUsize::new(2).unwrap(),
+        );
+        assert_eq!(planner.plan().await?.chosen, BootstrapSource::StateSync);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_repairs_corrupted_replica() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let nested_dir = local_epoch0_checkpoint.join("data");
+        fs::create_dir(&nested_dir)?;
+        fs::write(nested_dir.join("file3"), b"Lorem ipsum")?;
+        fs::write(nested_dir.join("empty"), b"")?;
+
+        let input_store_config = file_store_config(checkpoint_dir_path);
+        let remote_dirs = vec![TempDir::new()?, TempDir::new()?];
+        let mut replica_configs = vec![];
+        for remote_dir in remote_dirs.iter() {
+            let output_store_config = file_store_config(remote_dir.path());
+            let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+                &input_store_config,
+                &output_store_config,
+                10,
+                false,
+            )?;
+            let missing_epochs = db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?;
+            db_checkpoint_handler
+                .upload_db_checkpoints_to_object_store(missing_epochs)
+                .await?;
+            replica_configs.push(output_store_config);
+        }
+        // Silently corrupt a file in the first replica
+        fs::write(
+            remote_dirs[0].path().join("epoch_0").join("file1"),
+            b"Lorem ipsuM",
+        )?;
+
+        let progress = RestoreProgress::default();
+        let restorer = DBCheckpointRestorer::new(&replica_configs, NonZeroUsize::new(2).unwrap())?
+            .with_progress(progress.clone());
+        assert!(progress.report().is_none());
+        let second_replica_name = replica_configs[1].make()?.to_string();
+        let restore_dir = TempDir::new()?;
+        let report = restorer.restore_epoch(0, restore_dir.path()).await?;
+
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, b"Lorem ipsum");
+        assert_eq!(
+            fs::read(restore_dir.path().join("data").join("file3"))?,
+            b"Lorem ipsum"
+        );
+        assert!(restore_dir.path().join("data").join("empty").exists());
+        assert_eq!(report.served_by["file1"], second_replica_name);
+        let progress = progress.report().unwrap();
+        assert!(progress.is_complete());
+        assert_eq!(progress.restored_bytes, progress.total_bytes);
+        assert_eq!(progress.percent(), 100.0);
+        assert_eq!(progress.eta(), Some(Duration::ZERO));
+        assert!(report.resumed.is_empty());
+
+        // Restoring again resumes, only the file truncated locally is downloaded
+        fs::write(restore_dir.path().join("file1"), b"Lorem")?;
+        let report = restorer.restore_epoch(0, restore_dir.path()).await?;
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, b"Lorem ipsum");
+        assert_eq!(report.served_by.keys().collect::<Vec<_>>(), vec!["file1"]);
+        assert!(report.resumed.contains("data/file3"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_latest_epoch() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        for epoch in 0..3 {
+            let local_checkpoint = checkpoint_dir_path.join(format!("epoch_{epoch}"));
+            fs::create_dir_all(local_checkpoint.join("data"))?;
+            fs::write(
+                local_checkpoint.join("data").join("file1"),
+                format!("Lorem ipsum {epoch}"),
+            )?;
+        }
+        fs::write(
+            checkpoint_dir_path.join("epoch_2").join("data").join("file2"),
+            b"Lorem ipsum",
+        )?;
+        let remote_dirs = vec![TempDir::new()?, TempDir::new()?];
+        let replica_configs: Vec<_> = remote_dirs
+            .iter()
+            .map(|remote_dir| file_store_config(remote_dir.path()))
+            .collect();
+        let input_store_config = file_store_config(checkpoint_dir_path);
+        let db_checkpoint_handler =
+            DBCheckpointHandler::new_for_test(&input_store_config, &replica_configs[0], 10, false)?;
+        let restorer = DBCheckpointRestorer::new(&replica_configs, NonZeroUsize::new(2).unwrap())?;
+        assert_eq!(restorer.latest_complete_epoch().await?, None);
+        assert!(restorer
+            .restore_latest_epoch(TempDir::new()?.path())
+            .await
+            .is_err());
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        // The upload of the latest epoch was interrupted before its success marker
+        let remote_epoch2 = remote_dirs[0].path().join("epoch_2");
+        let marker = fs::read(remote_epoch2.join(SUCCESS_MARKER))?;
+        fs::remove_file(remote_epoch2.join(SUCCESS_MARKER))?;
+        assert_eq!(restorer.latest_complete_epoch().await?, Some(1));
+        assert_eq!(restorer.latest_complete_epochs(5).await?, vec![1, 0]);
+
+        let restore_dir = TempDir::new()?;
+        let report = restorer.restore_latest_epoch(restore_dir.path()).await?;
+        assert_eq!(report.epoch, 1);
+        assert_eq!(
+            fs::read(restore_dir.path().join("data").join("file1"))?,
+            b"Lorem ipsum 1"
+        );
+
+        // A latest epoch which can't be restored falls back to the previous one, without
+        // leaving files of the latest epoch behind
+        fs::write(remote_epoch2.join(SUCCESS_MARKER), marker)?;
+        fs::write(remote_epoch2.join("data").join("file1"), b"Lorem ipsum X")?;
+        assert_eq!(restorer.latest_complete_epochs(2).await?, vec![2, 1]);
+        let restore_dir = TempDir::new()?;
+        let report = restorer.restore_latest_epoch(restore_dir.path()).await?;
+        assert_eq!(report.epoch, 1);
+        assert_eq!(
+            fs::read(restore_dir.path().join("data").join("file1"))?,
+            b"Lorem ipsum 1"
+        );
+        assert!(!restore_dir.path().join("data").join("file2").exists());
+        // Files of an interrupted restore are never removed
+        let restore_dir = TempDir::new()?;
+        fs::write(restore_dir.path().join("file3"), b"Lorem ipsum")?;
+        assert!(restorer
+            .restore_latest_epoch(restore_dir.path())
+            .await
+            .is_err());
+        assert!(restore_dir.path().join("file3").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_epoch_published_by_success_marker() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir.path(), remote_checkpoint_dir.path())?;
+        let store = output_store_config.make()?;
+        let restorer = DBCheckpointRestorer::new(
+            &[output_store_config.clone()],
+            NonZeroUsize::new(1).unwrap(),
+        )?;
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert!(
+            read_published_manifest(&Path::from("epoch_0"), store.clone())
+                .await?
+                .is_some()
+        );
+        assert_eq!(restorer.latest_complete_epoch().await?, Some(0));
+
+        // Copied again, e.g. after its success marker wasn't visible yet when it was found
+        // missing. A MANIFEST left over by the earlier upload doesn't publish the epoch.
+        let manifest = fs::read(remote_epoch0_checkpoint.join(MANIFEST_FILENAME))?;
+        unpublish_epoch(&Path::from("epoch_0"), store.clone()).await?;
+        unpublish_epoch(&Path::from("epoch_0"), store.clone()).await?;
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(remote_epoch0_checkpoint.join("file1").exists());
+        fs::write(remote_epoch0_checkpoint.join(MANIFEST_FILENAME), manifest)?;
+        assert!(
+            read_published_manifest(&Path::from("epoch_0"), store.clone())
+                .await?
+                .is_none()
+        );
+        assert_eq!(restorer.latest_complete_epoch().await?, None);
+        assert!(list_epoch(store.clone(), 0, None).await.is_err());
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![0, 1]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(restorer.latest_complete_epoch().await?, Some(0));
+        assert_eq!(list_epoch(store, 0, None).await?.num_files, 1);
+
+        // Copied again with the same contents while still published, which it stays
+        fs::write(remote_epoch0_checkpoint.join(SUCCESS_MARKER), b"kept")?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(vec![0])
+            .await?;
+        assert_eq!(
+            fs::read(remote_epoch0_checkpoint.join(SUCCESS_MARKER))?,
+            b"kept"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bitrot_detected_between_phases() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsum")?;
+        let local_epoch1_checkpoint = checkpoint_dir_path.join("epoch_1");
+        fs::create_dir(&local_epoch1_checkpoint)?;
+        fs::write(local_epoch1_checkpoint.join("file1"), b"Lorem ipsum")?;
+
+        let remote_checkpoint_dir = TempDir::new()?;
+        let db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        // Digests are recorded once, right after the (skipped) compaction
+        db_checkpoint_handler
+            .recorded_checksums(&LocalCheckpoint {
+                epoch: 0,
+                data_dir: Path::from("epoch_0"),
+                state_dir: Path::from("epoch_0"),
+            })
+            .await?;
+        assert!(local_epoch0_checkpoint
+            .join(LOCAL_CHECKSUMS_FILENAME)
+            .exists());
+        // Flip a bit on disk before the upload phase
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsuM")?;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        // The corrupted epoch is skipped, the pass goes on with the next one
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(db_checkpoint_handler.metrics.bitrot_detected_total.get(), 1);
+        assert!(remote_checkpoint_dir
+            .path()
+            .join("epoch_1")
+            .join(SUCCESS_MARKER)
+            .exists());
+        let remote_epoch0_checkpoint = remote_checkpoint_dir.path().join("epoch_0");
+        assert!(!remote_epoch0_checkpoint.join("file2").exists());
+        assert!(!remote_epoch0_checkpoint.join(SUCCESS_MARKER).exists());
+        assert!(!remote_epoch0_checkpoint
+            .join(LOCAL_CHECKSUMS_FILENAME)
+            .exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_single_pass_digests() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let checkpoint_dir_path = checkpoint_dir.path();
+        let local_epoch0_checkpoint = checkpoint_dir_path.join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join("file3"), b"")?;
+
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(checkpoint_dir_path, remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.single_pass_digests = true;
+        db_checkpoint_handler.verify_after_upload = true;
+        // No digest is taken before the upload read
+        let recorded = db_checkpoint_handler
+            .recorded_checksums(&LocalCheckpoint {
+                epoch: 0,
+                data_dir: Path::from("epoch_0"),
+                state_dir: Path::from("epoch_0"),
+            })
+            .await?
+            .unwrap();
+        assert!(recorded.files.iter().all(|f| f.sha3_digest.is_empty()));
+        // Not detected in this mode, the digest is taken from what gets uploaded
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsuM")?;
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+        assert_eq!(db_checkpoint_handler.metrics.bitrot_detected_total.get(), 0);
+        let manifest = read_manifest(
+            &Path::from("epoch_0"),
+            db_checkpoint_handler.output_object_store.clone(),
+        )
+        .await?;
+        assert_eq!(
+            manifest.file("file1").unwrap().sha3_digest,
+            sha3_hex(b"Lorem ipsum")
+        );
+        assert_eq!(
+            manifest.file("file2").unwrap().sha3_digest,
+            sha3_hex(b"Lorem ipsuM")
+        );
+        assert_eq!(manifest.file("file3").unwrap().sha3_digest, sha3_hex(b""));
+        assert!(remote_checkpoint_dir
+            .path()
+            .join("epoch_0")
+            .join(SUCCESS_MARKER)
+            .exists());
+        // The local copy is checked against the digests of the uploaded MANIFEST
+        let report = db_checkpoint_handler.scrub_local_db_checkpoints().await?;
+        assert_eq!(report.num_epochs, 1);
+        assert_eq!(report.verified_files, 2);
+        assert!(report.corrupted.is_empty());
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsuM")?;
+        let report = db_checkpoint_handler.scrub_local_db_checkpoints().await?;
+        assert_eq!(report.corrupted, vec![(0, "file1".to_string())]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_only_snapshot_source() -> anyhow::Result<()> {
+        let snapshot_dir = TempDir::new()?;
+        let local_epoch3_db = snapshot_dir.path().join("sui-epoch-3").join("db");
+        fs::create_dir_all(&local_epoch3_db)?;
+        fs::write(local_epoch3_db.join("file1"), b"Lorem ipsum")?;
+        // Snapshots that don't match the pattern are ignored
+        fs::create_dir_all(snapshot_dir.path().join("manual-backup").join("db"))?;
+
+        let state_dir = TempDir::new()?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let mut db_checkpoint_handler =
+            test_handler(snapshot_dir.path(), remote_checkpoint_dir.path())?;
+        db_checkpoint_handler.source = CheckpointSource::new(&DBCheckpointSourceLayout {
+            dir_pattern: r"^sui-epoch-(\d+)$".to_string(),
+            db_subdir: Some("db".into()),
+            state_path: Some(state_dir.path().to_path_buf()),
+        })?;
+        db_checkpoint_handler.state_object_store = db_checkpoint_handler
+            .source
+            .state_store(&db_checkpoint_handler.input_object_store);
+
+        let missing_epochs = db_checkpoint_handler
+            .find_all_missing_checkpoint_epochs()
+            .await?;
+        assert_eq!(missing_epochs, vec![0]);
+        db_checkpoint_handler
+            .upload_db_checkpoints_to_object_store(missing_epochs)
+            .await?;
+
+        // Remote keys follow the `epoch_N` convention regardless of the local layout
+        let remote_epoch3_checkpoint = remote_checkpoint_dir.path().join("epoch_3");
+        assert!(remote_epoch3_checkpoint.join("file1").exists());
+        assert!(remote_epoch3_checkpoint.join(SUCCESS_MARKER).exists());
+        // Markers and checksums are kept out of the read-only snapshot
+        let state_epoch3 = state_dir.path().join("epoch_3");
+        assert!(state_epoch3.join(UPLOAD_COMPLETED_MARKER).exists());
+        assert!(state_epoch3.join(LOCAL_CHECKSUMS_FILENAME).exists());
+        assert!(!local_epoch3_db.join(UPLOAD_COMPLETED_MARKER).exists());
+        assert!(!local_epoch3_db.join(LOCAL_CHECKSUMS_FILENAME).exists());
+
+        // Snapshots are never garbage collected by the handler
+        fs::write(state_epoch3.join(TEST_MARKER), b"Lorem ipsum")?;
+        assert!(db_checkpoint_handler
+            .garbage_collect_old_db_checkpoints()
+            .await?
+            .is_empty());
+        assert!(local_epoch3_db.join("file1").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_metrics_registered_with_node_registry() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let output_store_config = file_store_config(remote_checkpoint_dir.path());
+        let registry = Registry::new();
+        // Registered by the pruner of the live db before the handler starts
+        let live_pruning_metrics = AuthorityStorePruningMetrics::new(&registry);
+        let db_checkpoint_handler = DBCheckpointHandler::new(
+            checkpoint_dir.path(),
+            &output_store_config,
+            &DBCheckpointConfig::default(),
+            0,
+            AuthorityStorePruningConfig::default(),
+            &registry,
+        )?;
+        db_checkpoint_handler
+            .pruning_metrics
+            .num_pruned_objects
+            .inc_by(3);
+        let values: BTreeMap<String, f64> = registry
+            .gather()
+            .iter()
+            .filter_map(|family| {
+                let metric = family.get_metric().first()?;
+                Some((
+                    family.get_name().to_string(),
+                    metric.get_counter().get_value(),
+                ))
+            })
+            .collect();
+        assert_eq!(values.get("db_checkpoint_num_pruned_objects"), Some(&3.0));
+        assert_eq!(values.get("num_pruned_objects"), Some(&0.0));
+        assert!(values.contains_key("db_checkpoint_upload_concurrency"));
+        assert_eq!(live_pruning_metrics.num_pruned_objects.get(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive_size_validated() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let remote_checkpoint_dir = TempDir::new()?;
+        let new_handler = |archive_size_bytes| {
+            DBCheckpointHandler::new(
+                checkpoint_dir.path(),
+                &file_store_config(remote_checkpoint_dir.path()),
+                &DBCheckpointConfig {
+                    archive_size_bytes: Some(archive_size_bytes),
+                    ..Default::default()
+                },
+                0,
+                AuthorityStorePruningConfig::default(),
+                &Registry::new(),
+            )
+        };
+        assert!(new_handler(0).is_err());
+        assert!(new_handler(4 << 30).is_err());
+        assert_eq!(new_handler(1 << 20)?.archive_size_bytes, 1 << 20);
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pruning and compaction of db checkpoints before upload, run on a thread of their own rather
+//! than in the upload task, since compacting a large db can take hours. A job outlives the upload
+//! attempt that started it: when the attempt gives up waiting, the job keeps running and a later
+//! attempt of the same epoch picks up its result. The thread is not a blocking task of the
+//! runtime, whose shutdown would otherwise wait for the compaction to finish, and dropping the
+//! job stops it before its next phase.
+
+use crate::authority::authority_store_pruner::{
+    AuthorityStorePruner, AuthorityStorePruningMetrics,
+};
+use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+use crate::checkpoints::CheckpointStore;
+use crate::db_checkpoint_handler::DBCheckpointMetrics;
+use anyhow::{anyhow, Result};
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sui_config::node::AuthorityStorePruningConfig;
+use sui_storage::mutex_table::RwLockTable;
+use tokio::sync::oneshot;
+use tracing::info;
+use typed_store::rocks::MetricConf;
+
+/// Phases of a job, the `phase` label of `DBCheckpointMetrics::prune_and_compact_phase`
+const PHASES: &[&str] = &["objects", "checkpoints", "compaction"];
+
+/// How many bytes every table of the perpetual db shrank by, errors are formatted so that the
+/// result can be shared by the attempts waiting for it
+type PruneResult = std::result::Result<BTreeMap<String, i64>, String>;
+
+/// Settings of the pruning of db checkpoints, see `DBCheckpointConfig::pre_upload_pruning_config`
+#[derive(Clone)]
+pub(crate) struct PruneSettings {
+    pub db_options: Option<rocksdb::Options>,
+    pub pruning_config: AuthorityStorePruningConfig,
+    pub indirect_objects_threshold: usize,
+    pub skip_tables: HashSet<String>,
+    pub pruning_metrics: Arc<AuthorityStorePruningMetrics>,
+    pub metrics: Arc<DBCheckpointMetrics>,
+}
+
+/// Pruning and compaction of the db checkpoint of an epoch, running or finished
+pub(crate) struct PruneJob {
+    pub epoch: u32,
+    started: Instant,
+    result: Shared<BoxFuture<'static, PruneResult>>,
+    /// Set once an attempt gave up waiting for the job
+    timed_out: bool,
+    /// Set once the job returned, whether or not an attempt took its result
+    finished: Arc<AtomicBool>,
+    /// Set when the job is dropped, stops it before its next phase
+    cancelled: Arc<AtomicBool>,
+}
+
+impl PruneJob {
+    /// Starts pruning and compacting the db checkpoint at `db_path` on a thread of its own
+    pub fn spawn(settings: PruneSettings, db_path: PathBuf, epoch: u32) -> Self {
+        let finished = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = oneshot::channel();
+        let usage = settings.metrics.usage.clone();
+        let (job_finished, job_cancelled) = (finished.clone(), cancelled.clone());
+        let spawned = std::thread::Builder::new()
+            .name(format!("db-checkpoint-prune-{epoch}"))
+            .spawn(move || {
+                let result = usage.measure(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|runtime| {
+                            runtime.block_on(settings.run(db_path, epoch, &job_cancelled))
+                        })
+                });
+                job_finished.store(true, Ordering::Release);
+                let _ = sender.send(result.map_err(|err| format!("{:?}", err)));
+            });
+        let spawn_error = spawned
+            .err()
+            .map(|err| format!("Failed to start pruning thread: {:?}", err));
+        let result = async move {
+            if let Some(err) = spawn_error {
+                return Err(err);
+            }
+            receiver
+                .await
+                .unwrap_or_else(|_| Err("Pruning thread panicked".to_string()))
+        }
+        .boxed()
+        .shared();
+        PruneJob {
+            epoch,
+            started: Instant::now(),
+            result,
+            timed_out: false,
+            finished,
+            cancelled,
+        }
+    }
+    /// Whether the job returned, so that it no longer holds back the jobs of other epochs
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+    /// Waits for the job until `timeout` after it started, `None` if it is still running then
+    pub fn wait(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl std::future::Future<Output = Option<Result<BTreeMap<String, i64>>>> {
+        let result = self.result.clone();
+        let remaining = timeout.map(|timeout| timeout.saturating_sub(self.started.elapsed()));
+        async move {
+            let result = match remaining {
+                // Polled once even when no time remains, so that a finished job is picked up
+                Some(remaining) => tokio::time::timeout(remaining, result).await.ok()?,
+                None => result.await,
+            };
+            Some(result.map_err(|err| anyhow!(err)))
+        }
+    }
+    /// Records that an attempt gave up waiting, returns whether it is the first one
+    pub fn mark_timed_out(&mut self) -> bool {
+        !std::mem::replace(&mut self.timed_out, true)
+    }
+}
+
+impl Drop for PruneJob {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+impl PruneSettings {
+    async fn run(
+        self,
+        db_path: PathBuf,
+        epoch: u32,
+        cancelled: &AtomicBool,
+    ) -> Result<BTreeMap<String, i64>> {
+        let start = Instant::now();
+        let result = self.prune_and_compact(db_path, epoch, cancelled).await;
+        self.set_phase(None);
+        self.metrics
+            .prune_and_compact_duration_seconds
+            .observe(start.elapsed().as_secs_f64());
+        result
+    }
+    fn set_phase(&self, current: Option<&str>) {
+        for phase in PHASES {
+            self.metrics
+                .prune_and_compact_phase
+                .with_label_values(&[phase])
+                .set((Some(*phase) == current) as i64);
+        }
+    }
+    async fn prune_and_compact(
+        &self,
+        db_path: PathBuf,
+        epoch: u32,
+        cancelled: &AtomicBool,
+    ) -> Result<BTreeMap<String, i64>> {
+        let check_cancelled = || {
+            if cancelled.load(Ordering::Acquire) {
+                return Err(anyhow!(
+                    "Pruning of db checkpoint for epoch: {epoch} was cancelled"
+                ));
+            }
+            Ok(())
+        };
+        let perpetual_db = Arc::new(AuthorityPerpetualTables::open(
+            &db_path.join("store"),
+            self.db_options.clone(),
+        ));
+        let sizes_before = table_sizes(&perpetual_db)?;
+        let checkpoint_store = Arc::new(CheckpointStore::open_tables_read_write(
+            db_path.join("checkpoints"),
+            MetricConf::default(),
+            None,
+            None,
+        ));
+        let lock_table = Arc::new(RwLockTable::new(1));
+        info!(
+            "Pruning db checkpoint in {:?} for epoch: {epoch}",
+            db_path.display()
+        );
+        if !self.skip_tables.contains("objects") {
+            check_cancelled()?;
+            self.set_phase(Some("objects"));
+            AuthorityStorePruner::prune_objects_for_eligible_epochs(
+                &perpetual_db,
+                &checkpoint_store,
+                &lock_table,
+                self.pruning_config,
+                self.pruning_metrics.clone(),
+                self.indirect_objects_threshold,
+                None,
+            )
+            .await?;
+        }
+        check_cancelled()?;
+        self.set_phase(Some("checkpoints"));
+        AuthorityStorePruner::prune_db_checkpoint_checkpoints(
+            &perpetual_db,
+            &checkpoint_store,
+            &lock_table,
+            self.pruning_config,
+            self.pruning_metrics.clone(),
+            &self.skip_tables,
+        )
+        .await?;
+        info!(
+            "Compacting db checkpoint in {:?} for epoch: {epoch}",
+            db_path.display()
+        );
+        check_cancelled()?;
+        self.set_phase(Some("compaction"));
+        AuthorityStorePruner::compact(&perpetual_db)?;
+        let sizes_after = table_sizes(&perpetual_db)?;
+        let pruned_bytes: BTreeMap<String, i64> = sizes_before
+            .iter()
+            .map(|(table, before)| {
+                let after = sizes_after.get(table).cloned().unwrap_or(0);
+                (table.clone(), *before as i64 - after as i64)
+            })
+            .collect();
+        for (table, bytes) in pruned_bytes.iter() {
+            self.metrics
+                .pruned_bytes_by_table
+                .with_label_values(&[table])
+                .set(*bytes);
+        }
+        info!(
+            "Pruning and compaction shrank db checkpoint for epoch: {epoch} by {} bytes, by table: {:?}",
+            pruned_bytes.values().sum::<i64>(),
+            pruned_bytes
+        );
+        Ok(pruned_bytes)
+    }
+}
+
+fn table_sizes(perpetual_db: &AuthorityPerpetualTables) -> Result<BTreeMap<String, u64>> {
+    let mut sizes = BTreeMap::new();
+    for file in perpetual_db.objects.rocksdb.live_files()? {
+        *sizes.entry(file.column_family_name).or_insert(0) += file.size as u64;
+    }
+    Ok(sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{table_sizes, PruneJob, PruneSettings, PHASES};
+    use crate::authority::authority_store_pruner::AuthorityStorePruningMetrics;
+    use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+    use crate::db_checkpoint_handler::epoch_window::DEFAULT_EPOCH_METRICS_WINDOW;
+    use crate::db_checkpoint_handler::DBCheckpointMetrics;
+    use prometheus::Registry;
+    use std::collections::HashSet;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use sui_types::base_types::ObjectID;
+    use sui_types::object::Object;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_table_sizes() -> anyhow::Result<()> {
+        let db_dir = TempDir::new()?;
+        let perpetual_db = AuthorityPerpetualTables::open(&db_dir.path().join("store"), None);
+        // Writes only count once they are flushed into live files
+        for _ in 0..10 {
+            let object = Object::immutable_with_id_for_testing(ObjectID::random());
+            perpetual_db.insert_object_test_only(object)?;
+        }
+        assert!(!table_sizes(&perpetual_db)?.contains_key("objects"));
+        perpetual_db.objects.flush()?;
+        let sizes = table_sizes(&perpetual_db)?;
+        assert!(sizes["objects"] > 0);
+        let live_bytes: u64 = perpetual_db
+            .objects
+            .rocksdb
+            .live_files()?
+            .iter()
+            .map(|file| file.size as u64)
+            .sum();
+        assert_eq!(sizes.values().sum::<u64>(), live_bytes);
+        Ok(())
+    }
+
+    fn test_settings(metrics: Arc<DBCheckpointMetrics>) -> PruneSettings {
+        PruneSettings {
+            db_options: None,
+            pruning_config: Default::default(),
+            indirect_objects_threshold: 0,
+            skip_tables: HashSet::from(["objects".to_string()]),
+            pruning_metrics: AuthorityStorePruningMetrics::new_for_test(),
+            metrics,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prune_job() -> anyhow::Result<()> {
+        let db_dir = TempDir::new()?;
+        let metrics = DBCheckpointMetrics::new(&Registry::default(), DEFAULT_EPOCH_METRICS_WINDOW);
+        let settings = test_settings(metrics.clone());
+        let mut job = PruneJob::spawn(settings, db_dir.path().to_path_buf(), 0);
+        // Giving up on the job leaves it running
+        if job.wait(Some(Duration::ZERO)).await.is_none() {
+            assert!(job.mark_timed_out());
+            assert!(!job.mark_timed_out());
+        }
+        let pruned_bytes = job.wait(None).await.unwrap()?;
+        assert!(job.is_finished());
+        // Every later attempt gets the result, even with no time left
+        assert_eq!(job.wait(Some(Duration::ZERO)).await.unwrap()?, pruned_bytes);
+        assert_eq!(
+            metrics
+                .prune_and_compact_duration_seconds
+                .get_sample_count(),
+            1
+        );
+        for phase in PHASES {
+            assert_eq!(
+                metrics
+                    .prune_and_compact_phase
+                    .with_label_values(&[phase])
+                    .get(),
+                0
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_prune_job() -> anyhow::Result<()> {
+        let db_dir = TempDir::new()?;
+        let metrics = DBCheckpointMetrics::new(&Registry::default(), DEFAULT_EPOCH_METRICS_WINDOW);
+        let settings = test_settings(metrics.clone());
+        // Stops before its next phase, as when the job is dropped on shutdown
+        let cancelled = AtomicBool::new(true);
+        assert!(settings
+            .run(db_dir.path().to_path_buf(), 0, &cancelled)
+            .await
+            .is_err());
+        assert_eq!(
+            metrics
+                .prune_and_compact_phase
+                .with_label_values(&["checkpoints"])
+                .get(),
+            0
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recovery pass run when the handler starts, which reconciles the local state left behind by
+//! a node that crashed or was killed in the middle of an operation. Markers, progress and
+//! checksums are written through the local object store, which stages every write in a file
+//! suffixed with `#` before renaming it into place: a staged file left behind would otherwise
+//! fail the check of the db checkpoint against its expected files on every later attempt.
+
+use crate::db_checkpoint_handler::manifest::{LOCAL_CHECKSUMS_FILENAME, UPLOAD_PROGRESS_FILENAME};
+use crate::db_checkpoint_handler::source::CheckpointSource;
+use crate::db_checkpoint_handler::staging::StagingArea;
+use crate::db_checkpoint_handler::UPLOAD_COMPLETED_MARKER;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Separator of the suffix of the files staged by the local object store
+const STAGED_FILE_SEPARATOR: char = '#';
+/// Extension of the directory the node takes a db checkpoint into before renaming it
+const TMP_EXTENSION: &str = "tmp";
+
+/// What the recovery pass removed
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Files of interrupted writes of markers, progress or checksums
+    pub staged_files: Vec<PathBuf>,
+    /// Empty upload markers and progress or checksums which don't parse, written by a node
+    /// which lost power before flushing them
+    pub corrupted_files: Vec<PathBuf>,
+    /// Directories of db checkpoints whose creation was interrupted, superseded since
+    pub tmp_dirs: Vec<PathBuf>,
+    /// Staging directories of restores, smoke tests and benchmarks whose process is gone
+    pub staging_dirs: Vec<PathBuf>,
+}
+
+impl RecoveryReport {
+    pub fn is_empty(&self) -> bool {
+        self.staged_files.is_empty()
+            && self.corrupted_files.is_empty()
+            && self.tmp_dirs.is_empty()
+            && self.staging_dirs.is_empty()
+    }
+}
+
+/// Removes the state of the operations interrupted by the previous run of the node, in the db
+/// checkpoints under `input_root` and their state under `state_root`
+pub fn recover_interrupted_operations(
+    input_root: &Path,
+    state_root: &Path,
+    source: &CheckpointSource,
+    staging_area: Option<&StagingArea>,
+) -> Result<RecoveryReport> {
+    let mut report = RecoveryReport::default();
+    if state_root.exists() {
+        for entry in fs::read_dir(state_root)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            // A dedicated state root holds nothing but state directories
+            if !source.is_read_only() && !matches!(source.parse_epoch(&name), Ok(Some(_))) {
+                continue;
+            }
+            recover_state_dir(&entry.path(), &mut report)?;
+        }
+    }
+    // Read-only db checkpoints are taken by external tooling, which cleans up after itself
+    if !source.is_read_only() && input_root.exists() {
+        report.tmp_dirs = remove_superseded_tmp_dirs(input_root, source)?;
+    }
+    if let Some(staging_area) = staging_area {
+        report.staging_dirs = staging_area.cleanup_abandoned()?;
+    }
+    Ok(report)
+}
+
+fn recover_state_dir(dir: &Path, report: &mut RecoveryReport) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        if name.contains(STAGED_FILE_SEPARATOR) {
+            remove_file(&path, &mut report.staged_files);
+            continue;
+        }
+        let corrupted = if name.starts_with(UPLOAD_COMPLETED_MARKER) {
+            entry.metadata()?.len() == 0
+        } else if name.starts_with(UPLOAD_PROGRESS_FILENAME) || name == LOCAL_CHECKSUMS_FILENAME {
+            serde_json::from_slice::<serde_json::Value>(&fs::read(&path)?).is_err()
+        } else {
+            false
+        };
+        if corrupted {
+            remove_file(&path, &mut report.corrupted_files);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the temporary directories of db checkpoints of epochs for which a later db
+/// checkpoint was completed. The node clears the one of an epoch before taking it again, but
+/// the directory of an epoch it never took again would stay forever.
+fn remove_superseded_tmp_dirs(
+    input_root: &Path,
+    source: &CheckpointSource,
+) -> Result<Vec<PathBuf>> {
+    let mut latest_epoch = None;
+    let mut tmp_dirs = vec![];
+    for entry in fs::read_dir(input_root)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Ok(Some(epoch)) = source.parse_epoch(&name) {
+            latest_epoch = latest_epoch.max(Some(epoch));
+        } else if path
+            .extension()
+            .map_or(false, |extension| extension == TMP_EXTENSION)
+        {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            if let Ok(Some(epoch)) = source.parse_epoch(&stem) {
+                tmp_dirs.push((epoch, path));
+            }
+        }
+    }
+    let mut removed = vec![];
+    for (epoch, path) in tmp_dirs {
+        // The db checkpoint of the latest epoch may still be being taken
+        if Some(epoch) >= latest_epoch {
+            continue;
+        }
+        match fs::remove_dir_all(&path) {
+            Ok(()) => {
+                info!("Removed interrupted db checkpoint {}", path.display());
+                removed.push(path);
+            }
+            Err(err) => warn!(
+                "Failed to remove interrupted db checkpoint {}: {:?}",
+                path.display(),
+                err
+            ),
+        }
+    }
+    Ok(removed)
+}
+
+fn remove_file(path: &Path, removed: &mut Vec<PathBuf>) {
+    match fs::remove_file(path) {
+        Ok(()) => {
+            info!("Removed file of interrupted operation {}", path.display());
+            removed.push(path.to_path_buf());
+        }
+        Err(err) => warn!("Failed to remove {}: {:?}", path.display(), err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recover_interrupted_operations;
+    use crate::db_checkpoint_handler::manifest::{
+        LOCAL_CHECKSUMS_FILENAME, UPLOAD_PROGRESS_FILENAME,
+    };
+    use crate::db_checkpoint_handler::source::CheckpointSource;
+    use crate::db_checkpoint_handler::{REPLICA_SUFFIX, UPLOAD_COMPLETED_MARKER};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_recover_interrupted_operations() -> anyhow::Result<()> {
+        let input_root = TempDir::new()?;
+        let source = CheckpointSource::default();
+        let epoch0 = input_root.path().join("epoch_0");
+        fs::create_dir(&epoch0)?;
+        fs::write(epoch0.join("file1"), b"Lorem ipsum")?;
+        fs::write(epoch0.join(UPLOAD_COMPLETED_MARKER), b"success")?;
+        fs::write(epoch0.join(LOCAL_CHECKSUMS_FILENAME), b"{}")?;
+        let staged = epoch0.join(format!("{UPLOAD_COMPLETED_MARKER}#1"));
+        fs::write(&staged, b"succ")?;
+        let epoch1 = input_root.path().join("epoch_1");
+        fs::create_dir(&epoch1)?;
+        // Replicas are numbered from 1
+        let empty_marker = epoch1.join(format!("{UPLOAD_COMPLETED_MARKER}{REPLICA_SUFFIX}1"));
+        fs::write(&empty_marker, b"")?;
+        let truncated_progress = epoch1.join(UPLOAD_PROGRESS_FILENAME);
+        fs::write(&truncated_progress, b"{\"uploaded_files\": 3")?;
+        // Superseded by the db checkpoint of epoch 1, unlike the one being taken of epoch 2
+        let tmp0 = input_root.path().join("epoch_0.tmp");
+        fs::create_dir(&tmp0)?;
+        let tmp2 = input_root.path().join("epoch_2.tmp");
+        fs::create_dir(&tmp2)?;
+
+        let report =
+            recover_interrupted_operations(input_root.path(), input_root.path(), &source, None)?;
+        assert_eq!(report.staged_files, vec![staged]);
+        let mut corrupted_files = report.corrupted_files.clone();
+        corrupted_files.sort();
+        assert_eq!(
+            corrupted_files,
+            vec![empty_marker.clone(), truncated_progress]
+        );
+        assert_eq!(report.tmp_dirs, vec![tmp0]);
+        assert!(epoch0.join(UPLOAD_COMPLETED_MARKER).exists());
+        assert!(epoch0.join(LOCAL_CHECKSUMS_FILENAME).exists());
+        assert!(epoch0.join("file1").exists());
+        assert!(!empty_marker.exists());
+        assert!(tmp2.exists());
+
+        // Nothing is left to recover
+        assert!(recover_interrupted_operations(
+            input_root.path(),
+            input_root.path(),
+            &source,
+            None
+        )?
+        .is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host level resource guards for backup work. Niceness and IO priority are per thread on
+//! Linux and inherited by threads spawned afterwards, so they can be applied to the backup
+//! worker thread of a node without affecting the rest of it. Memory and open file limits
+//! apply to the whole process and are only meant for the standalone `sui-db-backup` binary.
+//! Memory is capped with the `memory.max` of the cgroup v2 of the process, which reclaims the
+//! page cache of the process before it kills it. An `RLIMIT_AS` would instead cap the address
+//! space, which RocksDB mappings and allocator arenas exhaust well below the memory in use.
+//! The write rate limit is shared with the live db through the RocksDB options of both.
+
+use anyhow::Result;
+use sui_config::node::BackupResourceLimits;
+#[cfg(target_os = "linux")]
+use sui_config::node::IoPriorityClass;
+use tracing::{info, warn};
+
+/// Lowers the scheduling priorities of the calling thread, and of the threads it spawns
+/// afterwards, according to `limits`
+#[cfg(target_os = "linux")]
+pub fn apply_thread_priorities(limits: &BackupResourceLimits) -> Result<()> {
+    if let Some(nice) = limits.nice {
+        // On Linux, PRIO_PROCESS with a thread id only changes the niceness of that thread
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        info!("Set niceness of backup worker to {nice}");
+    }
+    if let Some(class) = limits.io_priority_class {
+        const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+        const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+        let (class_value, level) = match class {
+            IoPriorityClass::BestEffort => (2, limits.io_priority_level.unwrap_or(7).min(7)),
+            IoPriorityClass::Idle => (3, 0),
+        };
+        let ioprio = (class_value << IOPRIO_CLASS_SHIFT) | level as libc::c_long;
+        // Who 0 is the calling thread
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        info!(
+            "Set io priority of backup worker to {:?}, level: {level}",
+            class
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply_thread_priorities(limits: &BackupResourceLimits) -> Result<()> {
+    if limits.has_thread_priorities() {
+        warn!("Backup thread priorities are only supported on Linux, ignoring them");
+    }
+    Ok(())
+}
+
+/// Refill period and fairness of the shared rate limiter, the RocksDB defaults
+const RATE_LIMITER_REFILL_PERIOD_US: i64 = 100_000;
+const RATE_LIMITER_FAIRNESS: i32 = 10;
+
+/// Installs a rate limiter on `options` if `limits` sets a shared write rate. Clones of
+/// `options` share the same limiter, so every db opened with one of them stays under the
+/// ceiling together. Returns whether a limiter was installed.
+pub fn set_shared_write_rate_limit(
+    options: &mut rocksdb::Options,
+    limits: &BackupResourceLimits,
+) -> bool {
+    let Some(rate) = limits.shared_write_rate_bytes_per_sec else {
+        return false;
+    };
+    options.set_ratelimiter(
+        rate as i64,
+        RATE_LIMITER_REFILL_PERIOD_US,
+        RATE_LIMITER_FAIRNESS,
+    );
+    info!("Limited combined db and backup writes to {rate} bytes/s");
+    true
+}
+
+/// Applies the process wide memory and open file limits of `limits` to the current process.
+/// Only the soft limit of open files is lowered so it can be raised again without privileges.
+#[cfg(unix)]
+pub fn apply_process_limits(limits: &BackupResourceLimits) -> Result<()> {
+    if let Some(max_memory_bytes) = limits.max_memory_bytes {
+        set_cgroup_memory_max(max_memory_bytes)?;
+        info!("Limited backup process memory to {max_memory_bytes} bytes");
+    }
+    if let Some(max_open_files) = limits.max_open_files {
+        set_soft_rlimit(libc::RLIMIT_NOFILE, max_open_files)?;
+        info!("Limited backup process open files to {max_open_files}");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply_process_limits(limits: &BackupResourceLimits) -> Result<()> {
+    if limits.has_process_limits() {
+        warn!("Backup process limits are only supported on unix, ignoring them");
+    }
+    Ok(())
+}
+
+/// Root of the cgroup v2 hierarchy
+#[cfg(target_os = "linux")]
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Sets the `memory.max` of the cgroup of the current process. The cgroup must be delegated to
+/// the user of the process, e.g. with `Delegate=memory` in its systemd unit, and should hold
+/// nothing but the process since the limit applies to all of its members.
+#[cfg(target_os = "linux")]
+fn set_cgroup_memory_max(bytes: u64) -> Result<()> {
+    use anyhow::{anyhow, Context};
+    let proc_cgroup = std::fs::read_to_string("/proc/self/cgroup")?;
+    let cgroup = cgroup_v2_path(&proc_cgroup).ok_or_else(|| {
+        anyhow!("max-memory-bytes requires cgroup v2, not found in {proc_cgroup}")
+    })?;
+    let memory_max = std::path::Path::new(CGROUP_ROOT)
+        .join(cgroup.trim_start_matches('/'))
+        .join("memory.max");
+    std::fs::write(&memory_max, bytes.to_string()).with_context(|| {
+        format!(
+            "Failed to set max-memory-bytes in {}, is the memory controller delegated to the process?",
+            memory_max.display()
+        )
+    })
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn set_cgroup_memory_max(_bytes: u64) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "max-memory-bytes is only supported on Linux"
+    ))
+}
+
+/// Path of the cgroup v2 in the contents of `/proc/self/cgroup`, the line of hierarchy 0
+#[cfg(target_os = "linux")]
+fn cgroup_v2_path(proc_cgroup: &str) -> Option<&str> {
+    proc_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+}
+
+#[cfg(unix)]
+fn set_soft_rlimit(
+    #[cfg(all(target_os = "linux", target_env = "gnu"))] resource: libc::__rlimit_resource_t,
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))] resource: libc::c_int,
+    value: u64,
+) -> Result<()> {
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(resource, &mut rlimit) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let value = value as libc::rlim_t;
+    if value > rlimit.rlim_max {
+        warn!(
+            "Requested limit {value} is above the hard limit {}, using the hard limit",
+            rlimit.rlim_max
+        );
+    }
+    rlimit.rlim_cur = std::cmp::min(value, rlimit.rlim_max);
+    if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::cgroup_v2_path;
+
+    #[test]
+    fn test_cgroup_v2_path() {
+        assert_eq!(
+            cgroup_v2_path("0::/system.slice/sui-db-backup.service\n"),
+            Some("/system.slice/sui-db-backup.service")
+        );
+        // Hybrid hierarchies list the v1 controllers first
+        assert_eq!(
+            cgroup_v2_path("4:memory:/user.slice\n0::/user.slice/session-1.scope\n"),
+            Some("/user.slice/session-1.scope")
+        );
+        assert_eq!(cgroup_v2_path("4:memory:/user.slice\n"), None);
+    }
+}
@@ -0,0 +1,1043 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::db_checkpoint_handler::archive::{member_reader, read_member};
+use crate::db_checkpoint_handler::audit::{AuditAction, AuditLog};
+use crate::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use crate::db_checkpoint_handler::chunking::download_chunks;
+use crate::db_checkpoint_handler::destination_health::{
+    DestinationHealth, DestinationHealthTracker,
+};
+use crate::db_checkpoint_handler::digest_pool::DigestPool;
+use crate::db_checkpoint_handler::encryption::EncryptionKey;
+use crate::db_checkpoint_handler::manifest::{
+    copy_verified, read_file_range, read_published_manifest_index, sha3_hex, CompressedFrame,
+    EpochManifest, FileEntry, ManifestLookup,
+};
+use crate::db_checkpoint_handler::migration::{migrate_restored_db, needs_migration};
+use crate::db_checkpoint_handler::staging::StagingArea;
+use crate::db_checkpoint_handler::telemetry::{BackupTelemetry, BackupTelemetryEvent};
+use anyhow::{anyhow, Context, Result};
+use bytes::Buf;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sui_config::node::DBCheckpointCompression;
+use sui_storage::object_store::util::list_epoch_dirs;
+use sui_storage::object_store::ObjectStoreConfig;
+use sui_storage::FileCompression;
+use sui_types::digests::ChainIdentifier;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Minimum time between two progress logs of a restore
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(30);
+/// Suffix of a file being downloaded as it is stored in the remote store
+const STORED_SUFFIX: &str = ".stored";
+/// Suffix of a file being decoded from its stored copy, renamed to the file once verified
+const DECODED_SUFFIX: &str = ".decoded";
+/// Number of the latest published epochs `restore_latest_epoch` tries before giving up
+const RESTORE_LATEST_ATTEMPTS: usize = 3;
+
+/// Syncs the file or directory at `path` to disk
+fn sync_path(path: &std::path::Path) -> Result<()> {
+    std::fs::File::open(path)
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("Failed to sync {}", path.display()))
+}
+
+/// Syncs every file and directory under `dir`, and `dir` itself, to disk
+fn sync_dir_all(dir: &std::path::Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            sync_dir_all(&entry.path())?;
+        } else {
+            sync_path(&entry.path())?;
+        }
+    }
+    sync_path(dir)
+}
+
+/// A remote store holding a copy of the db checkpoints.
+struct Replica {
+    name: String,
+    store: Arc<DynObjectStore>,
+}
+
+/// Replica which holds a complete copy of the epoch being restored.
+struct RankedReplica {
+    index: usize,
+    manifest: ManifestLookup,
+    latency: Duration,
+}
+
+/// Outcome of restoring an epoch: which replica served each file, and which replicas returned
+/// corrupted contents that had to be repaired from another copy.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RestoreReport {
+    pub epoch: u32,
+    /// File path (relative to the epoch directory) -> name of the replica it was restored from
+    pub served_by: BTreeMap<String, String>,
+    /// File path -> names of the replicas which returned contents not matching the manifest
+    pub corrupted: BTreeMap<String, Vec<String>>,
+    /// Table -> number of entries rewritten to the storage format of this binary, empty when
+    /// the db checkpoint was already in that format
+    pub migrated: BTreeMap<String, u64>,
+    /// Files already in the local directory with the contents recorded in the manifest, left
+    /// there by an interrupted restore and not downloaded again
+    pub resumed: BTreeSet<String>,
+}
+
+/// Progress of the restore in flight, shared with whoever reports it, e.g. a readiness
+/// endpoint or logs of a node bootstrapping from a bucket
+#[derive(Clone, Default)]
+pub struct RestoreProgress {
+    inner: Arc<Mutex<Option<RestoreProgressInner>>>,
+}
+
+struct RestoreProgressInner {
+    epoch: u32,
+    total_files: usize,
+    total_bytes: usize,
+    restored_files: usize,
+    restored_bytes: usize,
+    started_at: Instant,
+}
+
+/// Snapshot of the progress of a restore. Files count as restored once their contents are
+/// downloaded and verified against the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreProgressReport {
+    pub epoch: u32,
+    pub total_files: usize,
+    pub total_bytes: usize,
+    pub restored_files: usize,
+    pub restored_bytes: usize,
+    pub elapsed: Duration,
+}
+
+impl RestoreProgress {
+    fn start(&self, epoch: u32, manifest: &EpochManifest) {
+        *self.inner.lock() = Some(RestoreProgressInner {
+            epoch,
+            total_files: manifest.num_files(),
+            total_bytes: manifest.total_size(),
+            restored_files: 0,
+            restored_bytes: 0,
+            started_at: Instant::now(),
+        });
+    }
+    fn file_restored(&self, size: usize) {
+        if let Some(inner) = self.inner.lock().as_mut() {
+            inner.restored_files += 1;
+            inner.restored_bytes += size;
+        }
+    }
+    /// Progress of the latest restore, `None` before any restore started
+    pub fn report(&self) -> Option<RestoreProgressReport> {
+        self.inner
+            .lock()
+            .as_ref()
+            .map(|inner| RestoreProgressReport {
+                epoch: inner.epoch,
+                total_files: inner.total_files,
+                total_bytes: inner.total_bytes,
+                restored_files: inner.restored_files,
+                restored_bytes: inner.restored_bytes,
+                elapsed: inner.started_at.elapsed(),
+            })
+    }
+}
+
+impl RestoreProgressReport {
+    pub fn is_complete(&self) -> bool {
+        self.restored_files == self.total_files
+    }
+    pub fn percent(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return if self.is_complete() { 100.0 } else { 0.0 };
+        }
+        self.restored_bytes as f64 * 100.0 / self.total_bytes as f64
+    }
+    /// Remaining time at the throughput observed so far, `None` until some bytes are restored
+    pub fn eta(&self) -> Option<Duration> {
+        if self.restored_bytes == 0 {
+            return None;
+        }
+        let remaining = self.total_bytes.saturating_sub(self.restored_bytes) as f64;
+        Some(Duration::from_secs_f64(
+            self.elapsed.as_secs_f64() * remaining / self.restored_bytes as f64,
+        ))
+    }
+}
+
+impl fmt::Display for RestoreProgressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "epoch: {}, {:.1}% ({} of {} bytes, {} of {} files)",
+            self.epoch,
+            self.percent(),
+            self.restored_bytes,
+            self.total_bytes,
+            self.restored_files,
+            self.total_files
+        )?;
+        match self.eta() {
+            Some(eta) => write!(f, ", ETA: {}s", eta.as_secs()),
+            None => write!(f, ", ETA: unknown"),
+        }
+    }
+}
+
+/// Downloads db checkpoints uploaded by `DBCheckpointHandler` back to local disk. When more
+/// than one replica holds the epoch, files are fetched from the healthiest replica, scored from
+/// the outcomes and latencies of the reads it served, and fall back to the other replicas
+/// whenever a read fails or the contents don't match the manifest.
+pub struct DBCheckpointRestorer {
+    replicas: Vec<Replica>,
+    /// Health of every replica, by index
+    health: DestinationHealthTracker,
+    download_concurrency: NonZeroUsize,
+    telemetry: BackupTelemetry,
+    /// Identity recorded in the audit log of the serving replica, if set
+    audit_actor: Option<String>,
+    progress: RestoreProgress,
+    /// Chain of the node restored into, checked against the labels of the db checkpoint
+    chain_identifier: Option<ChainIdentifier>,
+    /// Threads verifying the digests of downloaded files
+    digest_pool: DigestPool,
+    /// Where files are downloaded before the restored db is moved into place, if set
+    staging_area: Option<StagingArea>,
+    /// Key decrypting the files of encrypted replicas
+    encryption_key: Option<EncryptionKey>,
+    /// Bandwidth limit of the downloads
+    bandwidth_limiter: BandwidthLimiter,
+    /// Whether the restored files and directories are synced to disk before the restore returns
+    fsync: bool,
+}
+
+impl DBCheckpointRestorer {
+    pub fn new(
+        replica_configs: &[ObjectStoreConfig],
+        download_concurrency: NonZeroUsize,
+    ) -> Result<Self> {
+        let replicas = replica_configs
+            .iter()
+            .map(|config| {
+                let store = config.make()?;
+                Ok(Replica {
+                    name: store.to_string(),
+                    store,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if replicas.is_empty() {
+            return Err(anyhow!("At least one replica is needed for restore"));
+        }
+        Ok(DBCheckpointRestorer {
+            health: DestinationHealthTracker::new(replicas.len()),
+            replicas,
+            download_concurrency,
+            telemetry: BackupTelemetry::default(),
+            audit_actor: None,
+            progress: RestoreProgress::default(),
+            chain_identifier: None,
+            digest_pool: DigestPool::default(),
+            staging_area: None,
+            encryption_key: None,
+            bandwidth_limiter: BandwidthLimiter::default(),
+            fsync: true,
+        })
+    }
+    /// Forward restore lifecycle events to the node's telemetry subsystem
+    pub fn with_telemetry(mut self, sender: mpsc::Sender<BackupTelemetryEvent>) -> Self {
+        self.telemetry = BackupTelemetry::new(sender);
+        self
+    }
+    /// Record every restore in the audit log of the replica it was served from. Requires
+    /// write access to the replica.
+    pub fn with_audit_actor(mut self, actor: String) -> Self {
+        self.audit_actor = Some(actor);
+        self
+    }
+    /// Report restore progress through `progress`
+    pub fn with_progress(mut self, progress: RestoreProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+    /// Refuse to restore db checkpoints labeled with a chain other than `chain_identifier`, or
+    /// with a protocol version this binary doesn't support
+    pub fn with_chain_identifier(mut self, chain_identifier: ChainIdentifier) -> Self {
+        self.chain_identifier = Some(chain_identifier);
+        self
+    }
+    /// Verify the digests of downloaded files on the threads of `digest_pool`
+    pub fn with_digest_pool(mut self, digest_pool: DigestPool) -> Self {
+        self.digest_pool = digest_pool;
+        self
+    }
+    /// Download into a directory of `staging_area` and only move the db into place once it is
+    /// fully restored, so that an interrupted restore leaves no partial db behind. Restores into
+    /// a directory which isn't empty, e.g. resumed restores made without a staging area, are
+    /// still made in place.
+    pub fn with_staging_area(mut self, staging_area: StagingArea) -> Self {
+        self.staging_area = Some(staging_area);
+        self
+    }
+    /// Decrypt the files of replicas encrypted with `encryption_key`. Replicas encrypted with
+    /// another key are skipped.
+    pub fn with_encryption_key(mut self, encryption_key: EncryptionKey) -> Self {
+        self.encryption_key = Some(encryption_key);
+        self
+    }
+    /// Draw the bandwidth of downloads from `bandwidth_limiter`, which may be adjusted while
+    /// the restore runs
+    pub fn with_bandwidth_limiter(mut self, bandwidth_limiter: BandwidthLimiter) -> Self {
+        self.bandwidth_limiter = bandwidth_limiter;
+        self
+    }
+    /// Whether to sync the restored files and directories to disk before returning, so that
+    /// the restored db survives a crash of the host right after the restore. On by default,
+    /// turning it off speeds up scratch restores, e.g. restore drills.
+    pub fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+    /// Health of every replica, from the reads it served so far
+    pub fn destination_health(&self) -> Vec<(String, DestinationHealth)> {
+        self.replicas
+            .iter()
+            .enumerate()
+            .map(|(index, replica)| (replica.name.clone(), self.health.health(index)))
+            .collect()
+    }
+    /// Latest epoch published in any of the replicas, `None` if none holds a complete epoch
+    pub async fn latest_complete_epoch(&self) -> Result<Option<u32>> {
+        Ok(self.latest_complete_epochs(1).await?.first().copied())
+    }
+    /// Up to `limit` of the latest epochs published in any of the replicas, newest first
+    pub async fn latest_complete_epochs(&self, limit: usize) -> Result<Vec<u32>> {
+        let mut latest = BTreeSet::new();
+        for (index, replica) in self.replicas.iter().enumerate() {
+            let epoch_dirs = match list_epoch_dirs(replica.store.clone(), None).await {
+                Ok(epoch_dirs) => epoch_dirs,
+                Err(err) => {
+                    self.health.record_failure(index);
+                    warn!(
+                        "Failed to list epochs of replica {}: {:?}",
+                        replica.name, err
+                    );
+                    continue;
+                }
+            };
+            // Newest first, epochs below the `limit` latest found so far are of no interest
+            for (epoch, epoch_dir) in epoch_dirs.iter().rev() {
+                if latest.len() >= limit && latest.first().map_or(false, |first| epoch <= first) {
+                    break;
+                }
+                if latest.contains(epoch) {
+                    continue;
+                }
+                if let Ok(Some(_)) =
+                    read_published_manifest_index(epoch_dir, replica.store.clone()).await
+                {
+                    latest.insert(*epoch);
+                    if latest.len() > limit {
+                        latest.pop_first();
+                    }
+                }
+            }
+        }
+        Ok(latest.into_iter().rev().collect())
+    }
+    /// Restores the latest epoch complete in any of the replicas into `local_dir`, e.g. the db
+    /// path of a fresh node. When the restore fails, e.g. on files corrupted in every replica,
+    /// the previous published epochs are tried in turn, up to `RESTORE_LATEST_ATTEMPTS` epochs.
+    /// Falling back requires `local_dir` to be empty or missing before the failed restore, so
+    /// that what it wrote there can be removed and files of two epochs never mix. The restore
+    /// of an interrupted restore found in `local_dir` is never given up on.
+    pub async fn restore_latest_epoch(&self, local_dir: &std::path::Path) -> Result<RestoreReport> {
+        let epochs = self.latest_complete_epochs(RESTORE_LATEST_ATTEMPTS).await?;
+        if epochs.is_empty() {
+            return Err(anyhow!("No replica holds a complete db checkpoint"));
+        }
+        let mut last_err = None;
+        for epoch in epochs {
+            let started_empty = is_empty_dir(local_dir)?;
+            match self.restore_epoch(epoch, local_dir).await {
+                Ok(report) => return Ok(report),
+                // Everything in `local_dir` was written by the failed restore
+                Err(err) if started_empty => {
+                    warn!(
+                        "Failed to restore db checkpoint for epoch: {epoch}, falling back to the previous published epoch: {:?}",
+                        err
+                    );
+                    clear_dir(local_dir)?;
+                    last_err = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_err.unwrap())
+    }
+    /// Restores the db checkpoint for `epoch` into `local_dir`
+    pub async fn restore_epoch(
+        &self,
+        epoch: u32,
+        local_dir: &std::path::Path,
+    ) -> Result<RestoreReport> {
+        let start = Instant::now();
+        let epoch_dir = Path::from(format!("epoch_{}", epoch));
+        let ranked = self.rank_replicas(&epoch_dir).await;
+        let primary = ranked.first().ok_or_else(|| {
+            anyhow!("No replica holds a complete db checkpoint for epoch: {epoch}")
+        })?;
+        if let Some(chain_identifier) = &self.chain_identifier {
+            match &primary.manifest.index().labels {
+                Some(labels) => labels.verify(chain_identifier).with_context(|| {
+                    format!("Refusing to restore db checkpoint for epoch: {epoch}")
+                })?,
+                // Uploaded before labels were recorded
+                None => warn!("Db checkpoint for epoch: {epoch} has no chain labels to check"),
+            }
+        }
+        // Fail before downloading a db this binary can't open
+        let migrate = needs_migration(primary.manifest.index().schema_version)
+            .with_context(|| format!("Refusing to restore db checkpoint for epoch: {epoch}"))?;
+        info!(
+            "Restoring db checkpoint for epoch: {epoch} from {} replica(s), healthiest: {} ({:?})",
+            ranked.len(),
+            self.replicas[primary.index].name,
+            primary.latency
+        );
+        if let Some(producer) = &primary.manifest.index().producer {
+            info!("Db checkpoint for epoch: {epoch} was uploaded by {producer}");
+        }
+        self.progress.start(epoch, primary.manifest.index());
+        let staging_dir = match &self.staging_area {
+            // Files left in `local_dir` by an interrupted restore are only kept in place
+            Some(staging_area) if is_empty_dir(local_dir)? => Some(staging_area.create(
+                &format!("restore-epoch_{epoch}"),
+                primary.manifest.index().total_size() as u64,
+            )?),
+            Some(_) => {
+                info!(
+                    "Resuming restore of db checkpoint for epoch: {epoch} in place in {}",
+                    local_dir.display()
+                );
+                None
+            }
+            None => None,
+        };
+        let restore_dir = staging_dir.as_ref().map_or(local_dir, |dir| dir.path());
+        let ranked: &[RankedReplica] = &ranked;
+        // Files of sharded manifests are read one shard at a time
+        let mut results = primary
+            .manifest
+            .files()
+            .map(|file| async move {
+                let mut file = file?;
+                // Checked to be the key of encrypted epochs by `check_encryption_key`
+                if let Some(key) = &self.encryption_key {
+                    file.open_digest(key)?;
+                }
+                let result = if self.is_restored(&file, restore_dir).await? {
+                    None
+                } else {
+                    Some(self.restore_file(&file, ranked, restore_dir).await)
+                };
+                Ok::<_, anyhow::Error>((file.size, file.path, result))
+            })
+            .buffer_unordered(self.download_concurrency.get());
+        let mut report = RestoreReport {
+            epoch,
+            ..Default::default()
+        };
+        let mut last_log = Instant::now();
+        while let Some(result) = results.next().await {
+            let (size, path, result) = result?;
+            match result {
+                Some(result) => {
+                    let (_, served_by, corrupted) = result?;
+                    if !corrupted.is_empty() {
+                        report.corrupted.insert(path.clone(), corrupted);
+                    }
+                    report.served_by.insert(path, served_by);
+                }
+                None => {
+                    report.resumed.insert(path);
+                }
+            }
+            self.progress.file_restored(size);
+            if last_log.elapsed() >= PROGRESS_LOG_INTERVAL {
+                if let Some(progress) = self.progress.report() {
+                    info!("Restoring db checkpoint, {progress}");
+                }
+                last_log = Instant::now();
+            }
+        }
+        if migrate {
+            let restore_dir = restore_dir.to_path_buf();
+            report.migrated =
+                tokio::task::spawn_blocking(move || migrate_restored_db(&restore_dir))
+                    .await?
+                    .with_context(|| {
+                        format!("Failed to migrate restored db checkpoint for epoch: {epoch}")
+                    })?;
+        }
+        if self.fsync {
+            // Also covers the files resumed from an interrupted restore and rewritten by the
+            // migration
+            let restore_dir = restore_dir.to_path_buf();
+            tokio::task::spawn_blocking(move || sync_dir_all(&restore_dir))
+                .await?
+                .with_context(|| {
+                    format!("Failed to sync restored db checkpoint for epoch: {epoch}")
+                })?;
+        }
+        if let Some(staging_dir) = staging_dir {
+            staging_dir.persist_to(local_dir)?;
+        }
+        if self.fsync {
+            // Makes the entry of the restored db in its parent durable, e.g. after the move
+            // out of the staging area
+            if let Some(parent) = local_dir.parent() {
+                sync_path(parent)?;
+            }
+        }
+        self.telemetry.emit(BackupTelemetryEvent::RestoreCompleted {
+            epoch,
+            duration: start.elapsed(),
+        });
+        if self.replicas.len() > 1 {
+            for (name, health) in self.destination_health() {
+                info!(
+                    "Replica {name} served {} reads, failed {}, average latency: {:?}s",
+                    health.successes, health.failures, health.latency_s
+                );
+            }
+        }
+        if let Some(actor) = &self.audit_actor {
+            let replica = &self.replicas[primary.index];
+            let audit_log = AuditLog::new(replica.store.clone(), actor.clone());
+            if let Err(err) = audit_log.record(AuditAction::Restore, epoch).await {
+                warn!(
+                    "Failed to record restore of epoch: {epoch} in audit log of {}: {:?}",
+                    replica.name, err
+                );
+            }
+        }
+        Ok(report)
+    }
+    /// Returns the replicas holding a complete copy of the epoch, healthiest first
+    async fn rank_replicas(&self, epoch_dir: &Path) -> Vec<RankedReplica> {
+        let mut ranked = vec![];
+        for (index, replica) in self.replicas.iter().enumerate() {
+            let start = Instant::now();
+            let manifest =
+                match read_published_manifest_index(epoch_dir, replica.store.clone()).await {
+                    Ok(Some(manifest)) => manifest,
+                    Ok(None) => {
+                        warn!(
+                            "Skipping replica {} without success marker in {epoch_dir}",
+                            replica.name
+                        );
+                        continue;
+                    }
+                    Err(err) => {
+                        self.health.record_failure(index);
+                        warn!(
+                            "Skipping replica {} without readable manifest in {epoch_dir}: {:?}",
+                            replica.name, err
+                        );
+                        continue;
+                    }
+                };
+            let latency = start.elapsed();
+            self.health.record_success(index, latency);
+            if let Err(err) = self.check_encryption_key(&manifest) {
+                warn!(
+                    "Skipping replica {} in {epoch_dir}: {:?}",
+                    replica.name, err
+                );
+                continue;
+            }
+            ranked.push(RankedReplica {
+                index,
+                manifest: ManifestLookup::new(epoch_dir, manifest, replica.store.clone()),
+                latency,
+            });
+        }
+        self.health
+            .sort_by_health(&mut ranked, |replica| replica.index);
+        ranked
+    }
+    /// Fails when the files of `manifest` are encrypted with a key the restorer doesn't hold
+    fn check_encryption_key(&self, manifest: &EpochManifest) -> Result<()> {
+        match (&manifest.encryption_key_id, &self.encryption_key) {
+            (None, _) => Ok(()),
+            (Some(key_id), Some(key)) if key.key_id() == key_id => Ok(()),
+            (Some(key_id), _) => Err(anyhow!(
+                "Files are encrypted with key {key_id}, which the restorer doesn't hold"
+            )),
+        }
+    }
+    /// Whether `file` is already in `local_dir` with the size and digest recorded in the
+    /// manifest, so that an interrupted restore resumes without downloading it again
+    async fn is_restored(&self, file: &FileEntry, local_dir: &std::path::Path) -> Result<bool> {
+        let local_path = local_file_path(local_dir, &file.path)?;
+        match tokio::fs::metadata(&local_path).await {
+            Ok(metadata) if metadata.is_file() && metadata.len() == file.size as u64 => {}
+            _ => return Ok(false),
+        }
+        let entry = file.clone();
+        self.digest_pool
+            .run(move || {
+                std::fs::File::open(&local_path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|mut local| copy_verified(&entry, &mut local, &mut std::io::sink()))
+                    .is_ok()
+            })
+            .await
+    }
+    async fn restore_file(
+        &self,
+        file: &FileEntry,
+        ranked: &[RankedReplica],
+        local_dir: &std::path::Path,
+    ) -> Result<(String, String, Vec<String>)> {
+        let local_path = local_file_path(local_dir, &file.path)?;
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if file.size == 0 {
+            // Empty files are never uploaded
+            tokio::fs::write(&local_path, b"").await?;
+            return Ok((
+                file.path.clone(),
+                self.replicas[ranked[0].index].name.clone(),
+                vec![],
+            ));
+        }
+        let stored_path = with_suffix(&local_path, STORED_SUFFIX);
+        let mut corrupted = vec![];
+        let mut replicas: Vec<&RankedReplica> = ranked.iter().collect();
+        // Scores move along the restore, e.g. when a replica starts failing
+        self.health
+            .sort_by_health(&mut replicas, |replica| replica.index);
+        for replica in replicas {
+            let name = &self.replicas[replica.index].name;
+            // Every replica has its own manifest since replicas may use different layouts
+            let entry = match replica.manifest.file(&file.path).await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => {
+                    self.health.record_failure(replica.index);
+                    warn!("Replica {name} has no entry for {}", file.path);
+                    continue;
+                }
+                Err(err) => {
+                    self.health.record_failure(replica.index);
+                    warn!(
+                        "Failed to read manifest entry for {} from replica {name}: {:?}",
+                        file.path, err
+                    );
+                    continue;
+                }
+            };
+            let store = self.replicas[replica.index].store.clone();
+            if !entry.frames.is_empty() {
+                let restored = self.restore_frames(file, &entry, store, &local_path).await;
+                match restored {
+                    Ok(Ok(latency)) => {
+                        self.health.record_success(replica.index, latency);
+                        return Ok((file.path.clone(), name.clone(), corrupted));
+                    }
+                    // The frames verified so far are kept for the next replica to resume from
+                    Err(err) => {
+                        self.health.record_failure(replica.index);
+                        warn!(
+                            "Failed to read {} from replica {name}: {:?}",
+                            file.path, err
+                        );
+                    }
+                    Ok(Err(err)) => {
+                        self.health.record_failure(replica.index);
+                        warn!("Corrupted copy in replica {name}: {:?}", err);
+                        corrupted.push(name.clone());
+                    }
+                }
+                continue;
+            }
+            let latency = match download(&entry, store, &self.bandwidth_limiter, &stored_path).await
+            {
+                Ok(latency) => latency,
+                Err(err) => {
+                    let _ = tokio::fs::remove_file(&stored_path).await;
+                    self.health.record_failure(replica.index);
+                    warn!(
+                        "Failed to read {} from replica {name}: {:?}",
+                        file.path, err
+                    );
+                    continue;
+                }
+            };
+            // Replicas may differ in compression and encryption like in layout
+            let index = replica.manifest.index();
+            let compression = index.compression;
+            let key = index
+                .encryption_key_id
+                .as_ref()
+                .and(self.encryption_key.clone());
+            let (expected, stored, local) = (file.clone(), stored_path.clone(), local_path.clone());
+            let decoded = self
+                .digest_pool
+                .run(move || decode_file(&expected, &entry, compression, key, &stored, &local))
+                .await?;
+            if let Err(err) = decoded {
+                let _ = tokio::fs::remove_file(&stored_path).await;
+                self.health.record_failure(replica.index);
+                warn!("Corrupted copy in replica {name}: {:?}", err);
+                corrupted.push(name.clone());
+                continue;
+            }
+            self.health.record_success(replica.index, latency);
+            return Ok((file.path.clone(), name.clone(), corrupted));
+        }
+        Err(anyhow!(
+            "No replica holds a valid copy of {}, corrupted in: {:?}",
+            file.path,
+            corrupted
+        ))
+    }
+    /// Restores `file` from `entry`, its copy in `store` compressed in seekable frames, one
+    /// frame at a time: every frame is read with a ranged request and verified against its
+    /// digest before it is appended to the decoded file, so that only a frame is held in memory
+    /// and an interrupted restore resumes after the last frame it verified. Returns the time the
+    /// first request took to respond, or the error of contents which failed to verify.
+    async fn restore_frames(
+        &self,
+        file: &FileEntry,
+        entry: &FileEntry,
+        store: Arc<DynObjectStore>,
+        local_path: &std::path::Path,
+    ) -> Result<Result<Duration>> {
+        let decoded_path = with_suffix(local_path, DECODED_SUFFIX);
+        let (frames, path) = (entry.frames.clone(), decoded_path.clone());
+        let verified = self
+            .digest_pool
+            .run(move || verified_frames(&frames, &path))
+            .await??;
+        let mut frame_start: usize = entry.frames[..verified].iter().map(|f| f.size).sum();
+        let mut decoded = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&decoded_path)
+            .await?;
+        let mut latency = None;
+        for frame in entry.frames[verified..].iter() {
+            self.bandwidth_limiter.consume(frame.compressed_size).await;
+            let start = Instant::now();
+            let range = frame_start..frame_start + frame.size;
+            let contents = match read_file_range(store.clone(), entry, range).await {
+                Ok(contents) => contents,
+                // Requests which failed, unlike frames which failed to verify, don't make the
+                // copy corrupted
+                Err(err) if err.downcast_ref::<object_store::Error>().is_some() => return Err(err),
+                Err(err) => return Ok(Err(err)),
+            };
+            latency.get_or_insert(start.elapsed());
+            decoded.write_all(&contents).await?;
+            frame_start += frame.size;
+        }
+        decoded.flush().await?;
+        drop(decoded);
+        // Frames are verified against the manifest of the replica, the whole file against the
+        // one of the epoch
+        let (expected, path) = (file.clone(), decoded_path.clone());
+        let verified = self
+            .digest_pool
+            .run(move || {
+                copy_verified(
+                    &expected,
+                    &mut std::fs::File::open(&path)?,
+                    &mut std::io::sink(),
+                )
+            })
+            .await?;
+        if let Err(err) = verified {
+            tokio::fs::remove_file(&decoded_path).await?;
+            return Ok(Err(err));
+        }
+        tokio::fs::rename(&decoded_path, local_path).await?;
+        Ok(Ok(latency.unwrap_or_default()))
+    }
+}
+
+/// Number of the leading `frames` of a file found with their recorded contents in
+/// `decoded_path`, the decoded file left by an interrupted restore, which is truncated after
+/// them
+fn verified_frames(frames: &[CompressedFrame], decoded_path: &std::path::Path) -> Result<usize> {
+    let mut decoded = match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(decoded_path)
+    {
+        Ok(decoded) => decoded,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let mut verified = 0;
+    let mut verified_size = 0;
+    let mut contents = vec![];
+    for frame in frames {
+        contents.clear();
+        (&mut decoded)
+            .take(frame.size as u64)
+            .read_to_end(&mut contents)?;
+        if contents.len() != frame.size || sha3_hex(&contents) != frame.sha3_digest {
+            break;
+        }
+        verified += 1;
+        verified_size += frame.size;
+    }
+    decoded.set_len(verified_size as u64)?;
+    Ok(verified)
+}
+
+/// Whether `dir` is missing or holds nothing
+fn is_empty_dir(dir: &std::path::Path) -> Result<bool> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Removes everything in `dir`, keeping the directory itself
+fn clear_dir(dir: &std::path::Path) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Path of `path`, a path of a file in a manifest read from the remote store, under
+/// `local_dir`. Only plain relative paths are accepted, so that a tampered manifest can't
+/// write outside of `local_dir`.
+fn local_file_path(local_dir: &std::path::Path, path: &str) -> Result<PathBuf> {
+    let relative = std::path::Path::new(path);
+    let mut components = relative.components().peekable();
+    if components.peek().is_none()
+        || !components.all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(anyhow!(
+            "Refusing to restore {path:?}, which is not a relative path within the epoch"
+        ));
+    }
+    Ok(local_dir.join(relative))
+}
+
+fn with_suffix(path: &std::path::Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Downloads the copy of `entry` in `store` as it is stored, e.g. compressed, to
+/// `stored_path`, one part at a time. Returns the time the first request took to respond,
+/// without the waits for the bandwidth limit, so that replicas compare the same way whatever
+/// the size and layout of the file.
+async fn download(
+    entry: &FileEntry,
+    store: Arc<DynObjectStore>,
+    bandwidth_limiter: &BandwidthLimiter,
+    stored_path: &std::path::Path,
+) -> Result<Duration> {
+    let mut stored = tokio::fs::File::create(stored_path).await?;
+    let latency = if entry.archive_offset.is_some() {
+        // A ranged read of a single compressed tar member, members are at most the size of
+        // an archive
+        bandwidth_limiter
+            .consume(entry.compressed_size.unwrap_or(entry.size))
+            .await;
+        let start = Instant::now();
+        let frame = read_member(entry, store).await?;
+        let latency = start.elapsed();
+        stored.write_all(&frame).await?;
+        latency
+    } else if entry.chunks.is_empty() {
+        let remote_path = Path::from(entry.remote_path.as_str());
+        let start = Instant::now();
+        let mut parts = store.get(&remote_path).await?.into_stream();
+        let latency = start.elapsed();
+        // Drawn as the parts arrive, rather than for the whole file up front, so that large
+        // files are spread over the limit instead of bursting past it
+        while let Some(part) = parts.next().await {
+            let part = part?;
+            bandwidth_limiter.consume(part.len()).await;
+            stored.write_all(&part).await?;
+        }
+        latency
+    } else {
+        download_chunks(&entry.chunks, store, bandwidth_limiter, &mut stored).await?
+    };
+    stored.flush().await?;
+    Ok(latency)
+}
+
+/// Decodes the copy of `entry` downloaded to `stored_path` into `local_path`, verifying its
+/// contents against `file`. Files which are stored as they are on local disk are verified in
+/// place and renamed, encrypted files are decrypted in memory since they are sealed whole.
+fn decode_file(
+    file: &FileEntry,
+    entry: &FileEntry,
+    compression: Option<DBCheckpointCompression>,
+    key: Option<EncryptionKey>,
+    stored_path: &std::path::Path,
+    local_path: &std::path::Path,
+) -> Result<()> {
+    let compressed = entry.archive_offset.is_some()
+        || matches!(
+            compression,
+            Some(DBCheckpointCompression::Zstd | DBCheckpointCompression::ZstdSeekable)
+        );
+    if key.is_none() && !compressed {
+        copy_verified(
+            file,
+            &mut std::fs::File::open(stored_path)?,
+            &mut std::io::sink(),
+        )?;
+        std::fs::rename(stored_path, local_path)?;
+        return Ok(());
+    }
+    let stored: Box<dyn Read> = match &key {
+        Some(key) => {
+            let bytes = key.decrypt(&entry.remote_path, &std::fs::read(stored_path)?)?;
+            if compressed {
+                FileCompression::Zstd.bytes_decompress(bytes)?
+            } else {
+                Box::new(bytes.reader())
+            }
+        }
+        None => FileCompression::Zstd.decompress(&stored_path.to_path_buf())?,
+    };
+    let mut contents: Box<dyn Read> = match entry.archive_offset {
+        Some(_) => Box::new(member_reader(entry, stored)?),
+        None => stored,
+    };
+    let decoded_path = with_suffix(local_path, DECODED_SUFFIX);
+    let mut decoded = std::io::BufWriter::new(std::fs::File::create(&decoded_path)?);
+    let copied = copy_verified(file, &mut contents, &mut decoded)
+        .and_then(|_| Ok(decoded.flush()?))
+        .and_then(|_| Ok(std::fs::rename(&decoded_path, local_path)?));
+    if copied.is_err() {
+        let _ = std::fs::remove_file(&decoded_path);
+    }
+    std::fs::remove_file(stored_path)?;
+    copied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{local_file_path, DBCheckpointRestorer};
+    use crate::db_checkpoint_handler::DBCheckpointHandler;
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::path::Path;
+    use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_local_file_path() {
+        let local_dir = Path::new("/opt/sui/db");
+        assert_eq!(
+            local_file_path(local_dir, "store/perpetual/000123.sst").unwrap(),
+            local_dir.join("store/perpetual/000123.sst")
+        );
+        for path in [
+            "",
+            "../authorities_db",
+            "store/../../etc",
+            "/etc/passwd",
+            "./CURRENT",
+        ] {
+            assert!(local_file_path(local_dir, path).is_err(), "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_reorders_replicas() -> anyhow::Result<()> {
+        let checkpoint_dir = TempDir::new()?;
+        let local_epoch0_checkpoint = checkpoint_dir.path().join("epoch_0");
+        fs::create_dir(&local_epoch0_checkpoint)?;
+        fs::write(local_epoch0_checkpoint.join("file1"), b"Lorem ipsum")?;
+        fs::write(local_epoch0_checkpoint.join("file2"), b"Lorem ipsum")?;
+        let input_store_config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(checkpoint_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let remote_dirs = vec![TempDir::new()?, TempDir::new()?];
+        let mut replica_configs = vec![];
+        for remote_dir in remote_dirs.iter() {
+            let output_store_config = ObjectStoreConfig {
+                object_store: Some(ObjectStoreType::File),
+                directory: Some(remote_dir.path().to_path_buf()),
+                ..Default::default()
+            };
+            let db_checkpoint_handler = DBCheckpointHandler::new_for_test(
+                &input_store_config,
+                &output_store_config,
+                10,
+                false,
+            )?;
+            let missing_epochs = db_checkpoint_handler
+                .find_all_missing_checkpoint_epochs()
+                .await?;
+            db_checkpoint_handler
+                .upload_db_checkpoints_to_object_store(missing_epochs)
+                .await?;
+            replica_configs.push(output_store_config);
+        }
+        // The first replica lost a file, the second one failed a read before
+        fs::remove_file(remote_dirs[0].path().join("epoch_0").join("file1"))?;
+        let restorer = DBCheckpointRestorer::new(&replica_configs, NonZeroUsize::new(1).unwrap())?;
+        restorer.health.record_failure(1);
+        let second_replica_name = replica_configs[1].make()?.to_string();
+
+        let restore_dir = TempDir::new()?;
+        let report = restorer.restore_epoch(0, restore_dir.path()).await?;
+        assert_eq!(fs::read(restore_dir.path().join("file1"))?, b"Lorem ipsum");
+        assert_eq!(fs::read(restore_dir.path().join("file2"))?, b"Lorem ipsum");
+        // Tried first, the first replica failed over on the missing file and then ranked
+        // below the second one for the file it still holds
+        assert_eq!(report.served_by["file1"], second_replica_name);
+        assert_eq!(report.served_by["file2"], second_replica_name);
+        let health = restorer.destination_health();
+        assert_eq!(health[0].1.failures, 1);
+        assert_eq!(health[1].1.successes, 3);
+        assert!(health[1].1.score() > health[0].1.score());
+        Ok(())
+    }
+}
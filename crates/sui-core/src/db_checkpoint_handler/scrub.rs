@@ -0,0 +1,123 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Periodic checks of the uploaded db checkpoints kept on local disk against the digests
+//! recorded in their local checksums, so that disk corruption is caught long before the files
+//! are needed for an emergency restore. Every check reads a random sample of files across the
+//! kept epochs, which spreads the reads of a full pass over many intervals. Digests taken while
+//! uploading, with `single-pass-digests`, are only recorded in the uploaded MANIFEST, which the
+//! files are checked against instead.
+
+use crate::db_checkpoint_handler::events::BackupEvent;
+use crate::db_checkpoint_handler::manifest::{
+    copy_verified, logical_path, read_published_manifest, EpochManifest, FileEntry,
+    LOCAL_CHECKSUMS_FILENAME,
+};
+use crate::db_checkpoint_handler::source::remote_epoch_dir;
+use crate::db_checkpoint_handler::{DBCheckpointHandler, UPLOAD_COMPLETED_MARKER};
+use anyhow::Result;
+use rand::seq::SliceRandom;
+use std::{fs, io};
+use sui_storage::object_store::util::path_to_filesystem;
+use tracing::{error, info};
+
+/// Number of files checked by every local scrub when not set
+pub const DEFAULT_LOCAL_SCRUB_SAMPLE_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// Number of local epochs with recorded digests
+    pub num_epochs: usize,
+    pub verified_files: usize,
+    pub verified_bytes: u64,
+    /// Epoch and path of every file found missing or altered
+    pub corrupted: Vec<(u32, String)>,
+}
+
+impl DBCheckpointHandler {
+    /// Checks a random sample of the files of the uploaded db checkpoints kept on local disk
+    /// against the digests recorded after compaction
+    pub async fn scrub_local_db_checkpoints(&self) -> Result<ScrubReport> {
+        let local_checkpoints = self.source.list(self.input_object_store.clone()).await?;
+        let mut report = ScrubReport::default();
+        let mut candidates: Vec<(u32, FileEntry)> = vec![];
+        for (epoch, local) in local_checkpoints.iter() {
+            // Epochs waiting for upload are verified by the upload itself
+            let marker = local.state_dir.child(UPLOAD_COMPLETED_MARKER);
+            if self.state_object_store.head(&marker).await.is_err() {
+                continue;
+            }
+            let checksums_path = local.state_dir.child(LOCAL_CHECKSUMS_FILENAME);
+            let Ok(result) = self.state_object_store.get(&checksums_path).await else {
+                continue;
+            };
+            let mut manifest: EpochManifest = serde_json::from_slice(&result.bytes().await?)?;
+            if manifest
+                .files
+                .iter()
+                .any(|file| file.sha3_digest.is_empty())
+            {
+                let remote_dir = remote_epoch_dir(*epoch);
+                match read_published_manifest(&remote_dir, self.output_object_store.clone()).await?
+                {
+                    Some(published) => manifest = published,
+                    None => continue,
+                }
+                // Digests of encrypted epochs are sealed with their key
+                if let Some(key_id) = &manifest.encryption_key_id {
+                    match self.encryption_key().await? {
+                        Some(key) if key.key_id() == key_id => manifest.open_digests(key)?,
+                        _ => continue,
+                    }
+                }
+            }
+            report.num_epochs += 1;
+            candidates.extend(
+                manifest
+                    .files
+                    .into_iter()
+                    .filter(|file| file.size > 0 && !file.sha3_digest.is_empty())
+                    .map(|file| (*epoch, file)),
+            );
+        }
+        let sample: Vec<(u32, FileEntry)> = candidates
+            .choose_multiple(&mut rand::thread_rng(), self.local_scrub_sample_size)
+            .cloned()
+            .collect();
+        for (epoch, file) in sample {
+            let location = logical_path(&local_checkpoints[&epoch].data_dir, &file.path);
+            let fs_path = path_to_filesystem(self.input_root_path.clone(), &location)?;
+            let entry = file.clone();
+            // Read a buffer at a time, so that large SST files aren't held in memory
+            let verified = self
+                .digest_pool
+                .run(move || -> Result<()> {
+                    let mut reader = fs::File::open(fs_path)?;
+                    copy_verified(&entry, &mut reader, &mut io::sink())
+                })
+                .await?;
+            self.metrics.local_scrub_verified_files.inc();
+            report.verified_files += 1;
+            match verified {
+                Ok(()) => report.verified_bytes += file.size as u64,
+                Err(err) => {
+                    self.metrics.local_corruption_detected_total.inc();
+                    error!(
+                        "Local db checkpoint file {location} of epoch: {epoch} is corrupted, it can't be used for a restore: {:?}",
+                        err
+                    );
+                    self.publish(BackupEvent::LocalCorruptionDetected {
+                        epoch,
+                        path: file.path.clone(),
+                    });
+                    report.corrupted.push((epoch, file.path));
+                }
+            }
+        }
+        info!(
+            "Scrubbed {} files, {} bytes, of {} local db checkpoints",
+            report.verified_files, report.verified_bytes, report.num_epochs
+        );
+        Ok(report)
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host resources used by the handler itself, as opposed to the rest of the node it runs in:
+//! CPU time and disk IO of the threads while they run backup work, and the files of db
+//! checkpoints held open. Thread usage is read around every poll of the handler and around
+//! the blocking jobs it runs itself, i.e. digests, chunking, pruning and the reads of the files
+//! it uploads, so it is attributed to backups even on the runtime shared with the node.
+//!
+//! The counts are a lower bound. Work the handler hands to threads it doesn't run is missed:
+//! the blocking threads of the object stores, e.g. those writing markers and state files to
+//! the local file system, and the background threads of RocksDB compacting pruned db
+//! checkpoints. Reads served from the page cache never reach storage and aren't counted
+//! either. Comparing the counts with the usage of the whole process still hints at whether
+//! backups are worth moving to the standalone `sui-db-backup` sidecar.
+
+use anyhow::Result;
+use prometheus::{
+    register_counter_with_registry, register_int_counter_with_registry, Counter, IntCounter,
+    Registry,
+};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Resources used by the calling thread since it started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ThreadUsage {
+    cpu: Duration,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+#[cfg(target_os = "linux")]
+fn thread_usage() -> Option<ThreadUsage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `usage` points to writable memory of the size of a `rusage`, which getrusage
+    // only writes to
+    if unsafe { libc::getrusage(libc::RUSAGE_THREAD, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: getrusage succeeded, so it initialized every field of `usage`
+    let usage = unsafe { usage.assume_init() };
+    let seconds = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    // Block counts are the bytes read from and written to storage in units of 512 bytes
+    Some(ThreadUsage {
+        cpu: seconds(usage.ru_utime) + seconds(usage.ru_stime),
+        read_bytes: usage.ru_inblock as u64 * 512,
+        write_bytes: usage.ru_oublock as u64 * 512,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_usage() -> Option<ThreadUsage> {
+    None
+}
+
+/// Counts the resources used while running backup work. Clones record into the same metrics.
+#[derive(Clone)]
+pub struct UsageRecorder {
+    cpu_seconds: Counter,
+    read_bytes: IntCounter,
+    write_bytes: IntCounter,
+}
+
+impl UsageRecorder {
+    pub fn new(registry: &Registry) -> Self {
+        UsageRecorder {
+            cpu_seconds: register_counter_with_registry!(
+                "db_checkpoint_handler_cpu_seconds_total",
+                "CPU time spent by the db checkpoint handler, on the async executors and in its blocking jobs",
+                registry
+            )
+            .unwrap(),
+            read_bytes: register_int_counter_with_registry!(
+                "db_checkpoint_handler_read_bytes_total",
+                "Bytes read from storage by the threads of the db checkpoint handler while running it",
+                registry
+            )
+            .unwrap(),
+            write_bytes: register_int_counter_with_registry!(
+                "db_checkpoint_handler_write_bytes_total",
+                "Bytes written to storage by the threads of the db checkpoint handler while running it",
+                registry
+            )
+            .unwrap(),
+        }
+    }
+    /// Runs `job` on the calling thread and records the resources it used
+    pub fn measure<T>(&self, job: impl FnOnce() -> T) -> T {
+        let before = thread_usage();
+        let result = job();
+        if let (Some(before), Some(after)) = (before, thread_usage()) {
+            self.cpu_seconds
+                .inc_by(after.cpu.saturating_sub(before.cpu).as_secs_f64());
+            self.read_bytes
+                .inc_by(after.read_bytes.saturating_sub(before.read_bytes));
+            self.write_bytes
+                .inc_by(after.write_bytes.saturating_sub(before.write_bytes));
+        }
+        result
+    }
+    /// Records the resources used by every poll of `future`
+    pub fn measure_future<F: Future>(&self, future: F) -> Measured<F> {
+        Measured {
+            future: Box::pin(future),
+            recorder: self.clone(),
+        }
+    }
+}
+
+/// Future recording the resources used by its polls, see `UsageRecorder::measure_future`
+pub struct Measured<F> {
+    future: Pin<Box<F>>,
+    recorder: UsageRecorder,
+}
+
+impl<F: Future> Future for Measured<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let future = &mut this.future;
+        this.recorder.measure(|| future.as_mut().poll(cx))
+    }
+}
+
+/// Number of files under `roots` the process holds open, `None` where open files can't be
+/// listed
+#[cfg(target_os = "linux")]
+pub fn open_files_under(roots: &[PathBuf]) -> Result<Option<usize>> {
+    // Open files are listed with their canonical paths
+    let roots: Vec<PathBuf> = roots
+        .iter()
+        .map(|root| std::fs::canonicalize(root).unwrap_or_else(|_| root.clone()))
+        .collect();
+    let mut count = 0;
+    for entry in std::fs::read_dir("/proc/self/fd")? {
+        // Closed since listed
+        let Ok(target) = std::fs::read_link(entry?.path()) else {
+            continue;
+        };
+        if roots.iter().any(|root| target.starts_with(root)) {
+            count += 1;
+        }
+    }
+    Ok(Some(count))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_files_under(_roots: &[PathBuf]) -> Result<Option<usize>> {
+    Ok(None)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{open_files_under, UsageRecorder};
+    use prometheus::Registry;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_usage_recorder() -> anyhow::Result<()> {
+        let recorder = UsageRecorder::new(&Registry::default());
+        let sum = recorder
+            .measure_future(async {
+                let mut sum = 0u64;
+                for i in 0..50_000_000u64 {
+                    sum = sum.wrapping_add(std::hint::black_box(i));
+                }
+                sum
+            })
+            .await;
+        assert!(sum > 0);
+        assert!(recorder.cpu_seconds.get() > 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_files_under() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let other_dir = TempDir::new()?;
+        fs::write(dir.path().join("file1"), b"Lorem ipsum")?;
+        fs::write(other_dir.path().join("file1"), b"Lorem ipsum")?;
+        let roots = [dir.path().to_path_buf()];
+        assert_eq!(open_files_under(&roots)?, Some(0));
+        let file = fs::File::open(dir.path().join("file1"))?;
+        let _other_file = fs::File::open(other_dir.path().join("file1"))?;
+        assert_eq!(open_files_under(&roots)?, Some(1));
+        drop(file);
+        assert_eq!(open_files_under(&roots)?, Some(0));
+        Ok(())
+    }
+}
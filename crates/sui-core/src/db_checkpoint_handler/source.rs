@@ -0,0 +1,193 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Discovery of the local db checkpoints to upload. By default they are the `epoch_N`
+//! directories written by the node at epoch end. A `DBCheckpointSourceLayout` adapts the
+//! handler to directories written by external snapshot tooling such as ZFS or LVM, whose names
+//! follow their own pattern and which may be read-only.
+
+use anyhow::{anyhow, Context, Result};
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use sui_config::node::DBCheckpointSourceLayout;
+use sui_storage::object_store::util::parse_epoch_dir;
+use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+use tracing::debug;
+
+/// A db checkpoint found on local disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalCheckpoint {
+    pub epoch: u32,
+    /// Directory of the db files in the input store
+    pub data_dir: Path,
+    /// Directory of the upload markers and checksums in the state store
+    pub state_dir: Path,
+}
+
+/// Directory of an epoch in the remote store, independent of the local layout
+pub fn remote_epoch_dir(epoch: u32) -> Path {
+    Path::from(format!("epoch_{epoch}"))
+}
+
+#[derive(Clone, Default)]
+pub struct CheckpointSource {
+    dir_pattern: Option<Regex>,
+    db_subdir: Option<Path>,
+    /// Store of markers and checksums when the db checkpoints are read-only
+    read_only_state_store: Option<Arc<DynObjectStore>>,
+    /// Local directory of `read_only_state_store`
+    state_path: Option<PathBuf>,
+}
+
+impl CheckpointSource {
+    pub fn new(layout: &DBCheckpointSourceLayout) -> Result<Self> {
+        let dir_pattern = Regex::new(&layout.dir_pattern).with_context(|| {
+            format!("Invalid db checkpoint dir pattern: {}", layout.dir_pattern)
+        })?;
+        if dir_pattern.captures_len() < 2 {
+            return Err(anyhow!(
+                "Db checkpoint dir pattern must capture the epoch: {}",
+                layout.dir_pattern
+            ));
+        }
+        let db_subdir = layout
+            .db_subdir
+            .as_ref()
+            .map(|subdir| Path::from(subdir.to_string_lossy().as_ref()));
+        let read_only_state_store = layout
+            .state_path
+            .as_ref()
+            .map(|state_path| {
+                std::fs::create_dir_all(state_path)?;
+                ObjectStoreConfig {
+                    object_store: Some(ObjectStoreType::File),
+                    directory: Some(state_path.clone()),
+                    ..Default::default()
+                }
+                .make()
+            })
+            .transpose()?;
+        Ok(CheckpointSource {
+            dir_pattern: Some(dir_pattern),
+            db_subdir,
+            read_only_state_store,
+            state_path: layout.state_path.clone(),
+        })
+    }
+    /// Whether the db checkpoints must not be modified or deleted
+    pub fn is_read_only(&self) -> bool {
+        self.read_only_state_store.is_some()
+    }
+    /// Store holding the markers and checksums, `input_store` unless read-only
+    pub fn state_store(&self, input_store: &Arc<DynObjectStore>) -> Arc<DynObjectStore> {
+        self.read_only_state_store
+            .clone()
+            .unwrap_or_else(|| input_store.clone())
+    }
+    /// Local directory of the markers and checksums, `input_root` unless read-only
+    pub fn state_root(&self, input_root: &std::path::Path) -> PathBuf {
+        self.state_path
+            .clone()
+            .unwrap_or_else(|| input_root.to_path_buf())
+    }
+    /// Epoch of the db checkpoint in directory `name`, `None` if it isn't one
+    pub fn parse_epoch(&self, name: &str) -> Result<Option<u32>> {
+        match &self.dir_pattern {
+            None => {
+                if !name.starts_with("epoch_") {
+                    return Ok(None);
+                }
+                let epoch = parse_epoch_dir(name)
+                    .with_context(|| format!("Failed to parse epoch of dir {name}"))?;
+                Ok(Some(epoch))
+            }
+            Some(pattern) => {
+                let Some(captures) = pattern.captures(name) else {
+                    return Ok(None);
+                };
+                let epoch = captures
+                    .get(1)
+                    .map(|epoch| epoch.as_str())
+                    .unwrap_or_default();
+                match epoch.parse::<u32>() {
+                    Ok(epoch) => Ok(Some(epoch)),
+                    Err(_) => {
+                        debug!("Skipping {name}, captured epoch is not a number: {epoch}");
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+    /// Lists the db checkpoints present at the root of `input_store`
+    pub async fn list(
+        &self,
+        input_store: Arc<DynObjectStore>,
+    ) -> Result<BTreeMap<u32, LocalCheckpoint>> {
+        let mut checkpoints = BTreeMap::new();
+        let entries = input_store.list_with_delimiter(None).await?;
+        for entry in entries.common_prefixes {
+            let Some(filename) = entry.filename() else {
+                continue;
+            };
+            let Some(epoch) = self.parse_epoch(filename)? else {
+                continue;
+            };
+            let data_dir = match &self.db_subdir {
+                Some(subdir) => Path::from_iter(entry.parts().chain(subdir.parts())),
+                None => entry.clone(),
+            };
+            let state_dir = if self.is_read_only() {
+                remote_epoch_dir(epoch)
+            } else {
+                entry
+            };
+            checkpoints.insert(
+                epoch,
+                LocalCheckpoint {
+                    epoch,
+                    data_dir,
+                    state_dir,
+                },
+            );
+        }
+        Ok(checkpoints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CheckpointSource;
+    use sui_config::node::DBCheckpointSourceLayout;
+
+    #[test]
+    fn test_parse_epoch() -> anyhow::Result<()> {
+        let default = CheckpointSource::default();
+        assert_eq!(default.parse_epoch("epoch_12")?, Some(12));
+        assert_eq!(default.parse_epoch("chunks")?, None);
+        assert!(default.parse_epoch("epoch_x").is_err());
+        assert!(default.parse_epoch("epoch_007").is_err());
+
+        let zfs = CheckpointSource::new(&DBCheckpointSourceLayout {
+            dir_pattern: r"^sui-epoch-(\d+)$".to_string(),
+            db_subdir: Some("db/live".into()),
+            state_path: None,
+        })?;
+        assert_eq!(zfs.parse_epoch("sui-epoch-7")?, Some(7));
+        assert_eq!(zfs.parse_epoch("sui-epoch-7-partial")?, None);
+        assert_eq!(zfs.parse_epoch("epoch_7")?, None);
+        assert!(!zfs.is_read_only());
+
+        assert!(CheckpointSource::new(&DBCheckpointSourceLayout {
+            dir_pattern: r"^sui-epoch-\d+$".to_string(),
+            db_subdir: None,
+            state_path: None,
+        })
+        .is_err());
+        Ok(())
+    }
+}
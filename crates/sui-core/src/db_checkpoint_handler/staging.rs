@@ -0,0 +1,353 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Managed area for the temporary directories of restores, smoke tests and benchmarks of db
+//! checkpoints. Every directory records the process which owns it and is removed when dropped.
+//! Directories left behind by a process that crashed or was killed, e.g. half restored dbs, are
+//! removed by the next cleanup, and the size of the area is capped so that these features can't
+//! fill up the disk of the node. Cleanups only ever remove directories named and owned as
+//! created by a staging area, anything else under the root is left alone.
+
+use crate::db_checkpoint_handler::headroom::available_space;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sui_config::NodeConfig;
+use tracing::{info, warn};
+
+/// Records the owner of a staging directory
+pub const STAGING_OWNER_FILENAME: &str = "_STAGING_OWNER";
+/// Age past which a staging directory is removed even if its owner looks alive, since process
+/// ids get reused
+pub const DEFAULT_STAGING_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct StagingOwner {
+    pid: u32,
+    created_at_ms: u64,
+    purpose: String,
+}
+
+impl StagingOwner {
+    /// Name of the staging directory owned
+    fn dir_name(&self) -> String {
+        format!("{}-{}-{}", self.purpose, self.pid, self.created_at_ms)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StagingArea {
+    root: PathBuf,
+    /// Maximum number of bytes held by all the staging directories together
+    max_bytes: Option<u64>,
+    max_age: Duration,
+}
+
+impl StagingArea {
+    pub fn new(root: PathBuf) -> Self {
+        StagingArea {
+            root,
+            max_bytes: None,
+            max_age: DEFAULT_STAGING_MAX_AGE,
+        }
+    }
+    /// Refuse new staging directories which would take the area past `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+    /// Remove staging directories older than `max_age` regardless of their owner
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+    /// Staging area configured for the node, see `DBCheckpointConfig::staging`
+    pub fn from_node_config(config: &NodeConfig) -> Self {
+        let mut area = StagingArea::new(config.staging_path());
+        if let Some(staging) = &config.db_checkpoint_config.staging {
+            if let Some(max_bytes) = staging.max_bytes {
+                area = area.with_max_bytes(max_bytes);
+            }
+            if let Some(max_age_s) = staging.max_age_s {
+                area = area.with_max_age(Duration::from_secs(max_age_s));
+            }
+        }
+        area
+    }
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    /// Creates a staging directory for `purpose`, expected to grow to `expected_bytes`.
+    /// Abandoned directories are cleaned up first, and creation fails if the area or the disk
+    /// can't hold `expected_bytes` more.
+    pub fn create(&self, purpose: &str, expected_bytes: u64) -> Result<StagingDir> {
+        fs::create_dir_all(&self.root)?;
+        self.cleanup_abandoned()?;
+        if let Some(max_bytes) = self.max_bytes {
+            let used_bytes = self.used_bytes()?;
+            if used_bytes.saturating_add(expected_bytes) > max_bytes {
+                return Err(anyhow!(
+                    "Staging area {} holds {used_bytes} bytes, {expected_bytes} more for {purpose} would exceed its cap of {max_bytes} bytes",
+                    self.root.display()
+                ));
+            }
+        }
+        if let Ok(available) = available_space(&self.root) {
+            if expected_bytes > available {
+                return Err(anyhow!(
+                    "Only {available} bytes are free on disk for staging area {}, {purpose} needs {expected_bytes}",
+                    self.root.display()
+                ));
+            }
+        }
+        let owner = StagingOwner {
+            pid: std::process::id(),
+            created_at_ms: now_ms(),
+            purpose: purpose.to_string(),
+        };
+        let path = self.root.join(owner.dir_name());
+        fs::create_dir(&path)
+            .with_context(|| format!("Failed to create staging dir {}", path.display()))?;
+        let staging_dir = StagingDir { path, keep: false };
+        fs::write(
+            staging_dir.path.join(STAGING_OWNER_FILENAME),
+            serde_json::to_vec(&owner)?,
+        )?;
+        Ok(staging_dir)
+    }
+    /// Total size of the files in the area, in bytes
+    pub fn used_bytes(&self) -> Result<u64> {
+        if !self.root.exists() {
+            return Ok(0);
+        }
+        dir_size(&self.root)
+    }
+    /// Removes the staging directories whose owner is gone, or which are older than the max
+    /// age, and returns their paths. Directories without an owner file matching their name
+    /// were not created by a staging area and are never removed.
+    pub fn cleanup_abandoned(&self) -> Result<Vec<PathBuf>> {
+        let mut removed = vec![];
+        if !self.root.exists() {
+            return Ok(removed);
+        }
+        let now_ms = now_ms();
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+            let Some(owner) = read_owner(&path) else {
+                continue;
+            };
+            if entry.file_name().to_str() != Some(owner.dir_name().as_str()) {
+                continue;
+            }
+            let age = Duration::from_millis(now_ms.saturating_sub(owner.created_at_ms));
+            if is_process_alive(owner.pid) && age <= self.max_age {
+                continue;
+            }
+            match fs::remove_dir_all(&path) {
+                Ok(()) => {
+                    info!("Removed abandoned staging dir {}", path.display());
+                    removed.push(path);
+                }
+                Err(err) => warn!(
+                    "Failed to remove abandoned staging dir {}: {:?}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Temporary directory in a `StagingArea`, removed when dropped unless persisted
+#[derive(Debug)]
+pub struct StagingDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl StagingDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Moves the contents of the staging directory to `dst`, which must not exist or be an
+    /// empty directory. `dst` has to be on the same filesystem as the staging area.
+    pub fn persist_to(mut self, dst: &Path) -> Result<()> {
+        if dst.exists() {
+            if fs::read_dir(dst)?.next().is_some() {
+                return Err(anyhow!(
+                    "Refusing to move staging dir into non empty dir: {}",
+                    dst.display()
+                ));
+            }
+            fs::remove_dir(dst)?;
+        } else if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::remove_file(self.path.join(STAGING_OWNER_FILENAME))?;
+        fs::rename(&self.path, dst).with_context(|| {
+            format!(
+                "Failed to move staging dir {} to {}",
+                self.path.display(),
+                dst.display()
+            )
+        })?;
+        self.keep = true;
+        Ok(())
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        if let Err(err) = fs::remove_dir_all(&self.path) {
+            warn!(
+                "Failed to remove staging dir {}: {:?}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+fn read_owner(dir: &Path) -> Option<StagingOwner> {
+    let bytes = fs::read(dir.join(STAGING_OWNER_FILENAME)).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Total size of the files under `path`, in bytes
+pub fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(unix)]
+fn is_process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    // Zero and negative ids address process groups
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // Signal 0 only checks whether the process exists and can be signaled
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_process_alive(_pid: u32) -> bool {
+    // Left to the max age
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StagingArea, StagingOwner, STAGING_OWNER_FILENAME};
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_staging_area() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let area = StagingArea::new(dir.path().join("staging")).with_max_bytes(1024);
+
+        let staging_dir = area.create("restore-epoch_3", 1000)?;
+        fs::write(staging_dir.path().join("file1"), b"Lorem ipsum")?;
+        assert!(area.used_bytes()? >= 11);
+        // The cap accounts for what the other staging dirs already hold
+        assert!(area.create("smoke-test", 1000).is_err());
+        let path = staging_dir.path().to_path_buf();
+        drop(staging_dir);
+        assert!(!path.exists());
+
+        let staging_dir = area.create("restore-epoch_3", 1000)?;
+        fs::create_dir_all(staging_dir.path().join("store"))?;
+        fs::write(
+            staging_dir.path().join("store").join("file1"),
+            b"Lorem ipsum",
+        )?;
+        let restored = dir.path().join("live");
+        fs::create_dir(&restored)?;
+        staging_dir.persist_to(&restored)?;
+        assert_eq!(
+            fs::read(restored.join("store").join("file1"))?,
+            b"Lorem ipsum"
+        );
+        assert!(!restored.join(STAGING_OWNER_FILENAME).exists());
+        assert_eq!(fs::read_dir(area.root())?.count(), 0);
+        // Never merged into an existing db
+        let staging_dir = area.create("restore-epoch_4", 0)?;
+        assert!(staging_dir.persist_to(&restored).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_abandoned_staging_dirs() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let area = StagingArea::new(dir.path().to_path_buf());
+        let live = area.create("restore-epoch_3", 0)?;
+        // Left behind by a restore killed half way
+        let abandoned = dir.path().join("restore-epoch_2-1-0");
+        fs::create_dir_all(abandoned.join("store"))?;
+        fs::write(abandoned.join("store").join("file1"), b"Lorem ipsum")?;
+        fs::write(
+            abandoned.join(STAGING_OWNER_FILENAME),
+            serde_json::to_vec(&StagingOwner {
+                pid: u32::MAX,
+                created_at_ms: 0,
+                purpose: "restore-epoch_2".to_string(),
+            })?,
+        )?;
+        // Just created by another process, which hasn't recorded itself yet
+        fs::create_dir(dir.path().join("smoke-test-2-0"))?;
+        // Not created by a staging area, even with an owner file
+        let foreign = dir.path().join("manual-copy");
+        fs::create_dir(&foreign)?;
+        fs::copy(
+            abandoned.join(STAGING_OWNER_FILENAME),
+            foreign.join(STAGING_OWNER_FILENAME),
+        )?;
+
+        assert_eq!(area.cleanup_abandoned()?, vec![abandoned.clone()]);
+        assert!(!abandoned.exists());
+        assert!(live.path().exists());
+        assert!(dir.path().join("smoke-test-2-0").exists());
+        assert!(foreign.exists());
+
+        // Past the max age, the owner is presumed gone
+        let area = area.with_max_age(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(area.cleanup_abandoned()?, vec![live.path().to_path_buf()]);
+        Ok(())
+    }
+}
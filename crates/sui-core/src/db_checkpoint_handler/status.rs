@@ -0,0 +1,122 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Status of the uploads of the handler, shared with the admin server of the node. The first
+//! missing epoch alone doesn't tell a single gap in the remote store from a hundred, so the
+//! missing epochs are listed too, up to `MAX_LISTED_MISSING_EPOCHS` of them.
+
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Missing epochs listed in the status, a bucket missing more is reported by their number
+pub const MAX_LISTED_MISSING_EPOCHS: usize = 100;
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct BackupStatus {
+    /// First epoch without a db checkpoint in the remote store, the next one to upload when
+    /// there are no gaps
+    pub first_missing_epoch: Option<u32>,
+    /// Number of epochs missing from the remote store below the latest uploaded one
+    pub num_missing_epochs: usize,
+    /// The first `MAX_LISTED_MISSING_EPOCHS` of the epochs missing below the latest uploaded
+    /// one, in order
+    pub missing_epochs: Vec<u32>,
+    /// Epochs whose uploads failed too many times in a row, skipped until the handler restarts
+    pub quarantined_epochs: Vec<u32>,
+    /// Whether uploads were paused by an operator, see `BackupCommander::pause`
+    pub paused: bool,
+    /// Whether garbage collection was paused by an operator, see `BackupCommander::pause_gc`
+    pub gc_paused: bool,
+    /// Epochs of the local db checkpoints the last garbage collection would have deleted, in
+    /// dry-run mode
+    pub gc_dry_run_epochs: Vec<u32>,
+}
+
+/// Handle to the status of a handler, updated by the handler on every upload pass
+#[derive(Clone, Default)]
+pub struct BackupStatusHandle {
+    status: Arc<RwLock<BackupStatus>>,
+}
+
+impl BackupStatusHandle {
+    pub fn status(&self) -> BackupStatus {
+        self.status.read().clone()
+    }
+    /// Records the missing epochs found by an upload pass, which end with the next epoch to
+    /// upload
+    pub fn set_missing_epochs(&self, missing_epochs: &[u32]) {
+        let gaps = missing_epochs
+            .split_last()
+            .map_or(&[][..], |(_, gaps)| gaps);
+        let mut status = self.status.write();
+        status.first_missing_epoch = missing_epochs.first().cloned();
+        status.num_missing_epochs = gaps.len();
+        status.missing_epochs = gaps
+            .iter()
+            .take(MAX_LISTED_MISSING_EPOCHS)
+            .cloned()
+            .collect();
+    }
+    pub fn set_quarantined_epochs(&self, quarantined_epochs: Vec<u32>) {
+        self.status.write().quarantined_epochs = quarantined_epochs;
+    }
+    pub fn set_paused(&self, paused: bool) {
+        self.status.write().paused = paused;
+    }
+    pub fn is_paused(&self) -> bool {
+        self.status.read().paused
+    }
+    pub fn set_gc_paused(&self, gc_paused: bool) {
+        self.status.write().gc_paused = gc_paused;
+    }
+    pub fn is_gc_paused(&self) -> bool {
+        self.status.read().gc_paused
+    }
+    /// Records the epochs a garbage collection in dry-run mode would have deleted, returns
+    /// whether they changed since the previous one
+    pub fn set_gc_dry_run_epochs(&self, epochs: Vec<u32>) -> bool {
+        let mut status = self.status.write();
+        let changed = status.gc_dry_run_epochs != epochs;
+        status.gc_dry_run_epochs = epochs;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackupStatus, BackupStatusHandle, MAX_LISTED_MISSING_EPOCHS};
+
+    #[test]
+    fn test_missing_epochs_status() {
+        let handle = BackupStatusHandle::default();
+        assert_eq!(handle.status(), BackupStatus::default());
+
+        handle.set_missing_epochs(&[7]);
+        assert_eq!(
+            handle.status(),
+            BackupStatus {
+                first_missing_epoch: Some(7),
+                num_missing_epochs: 0,
+                missing_epochs: vec![],
+                quarantined_epochs: vec![],
+                paused: false,
+                gc_paused: false,
+                gc_dry_run_epochs: vec![],
+            }
+        );
+        // Quarantined epochs are kept along with the missing epochs of later passes
+        handle.set_quarantined_epochs(vec![3]);
+        handle.set_missing_epochs(&[3, 8]);
+        assert_eq!(handle.status().quarantined_epochs, vec![3]);
+        assert_eq!(handle.status().missing_epochs, vec![3]);
+
+        let missing_epochs: Vec<u32> = (0..200).step_by(2).chain([500, 501]).collect();
+        handle.clone().set_missing_epochs(&missing_epochs);
+        let status = handle.status();
+        assert_eq!(status.first_missing_epoch, Some(0));
+        assert_eq!(status.num_missing_epochs, 101);
+        assert_eq!(status.missing_epochs.len(), MAX_LISTED_MISSING_EPOCHS);
+        assert_eq!(status.missing_epochs.last(), Some(&198));
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export of a single table (column family) of a db checkpoint and its import into another db
+//! checkpoint, for surgical repair of a backup in which only one table is corrupt. A db
+//! checkpoint altered by an import no longer matches its recorded checksums and must be
+//! uploaded again.
+
+use anyhow::{anyhow, Context, Result};
+use rocksdb::{IteratorMode, Options, SstFileWriter, DB};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+/// Description of the export, written next to the exported entries
+pub const TABLE_EXPORT_FILENAME: &str = "TABLE_EXPORT.json";
+const TABLE_SST_FILENAME: &str = "table.sst";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TableExport {
+    /// Path of the db relative to the db checkpoint, e.g. `store/perpetual`
+    pub db: String,
+    pub table: String,
+    /// Tables of the exported db. Imports are only allowed into a db with the same tables,
+    /// as a different set means a different schema version.
+    pub column_families: Vec<String>,
+    pub num_entries: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Entries of the export overwrite the entries with the same keys, others are kept
+    Overlay,
+    /// The table holds exactly the entries of the export afterwards
+    Replace,
+}
+
+/// Sorted names of the tables of the db at `db_path`
+fn list_tables(db_path: &Path) -> Result<Vec<String>> {
+    let mut tables = DB::list_cf(&Options::default(), db_path)
+        .with_context(|| format!("Failed to list tables of db at {}", db_path.display()))?;
+    tables.sort();
+    Ok(tables)
+}
+
+/// Writes the entries of `table` of the db at `db` under `checkpoint_dir` into `export_dir`.
+/// The db checkpoint is opened read-only.
+pub fn export_table(
+    checkpoint_dir: &Path,
+    db: &str,
+    table: &str,
+    export_dir: &Path,
+) -> Result<TableExport> {
+    let db_path = checkpoint_dir.join(db);
+    let column_families = list_tables(&db_path)?;
+    if !column_families.iter().any(|cf| cf == table) {
+        return Err(anyhow!(
+            "No table {table} in db at {}, tables: {:?}",
+            db_path.display(),
+            column_families
+        ));
+    }
+    let rocksdb =
+        DB::open_cf_for_read_only(&Options::default(), &db_path, &column_families, false)?;
+    let cf = rocksdb
+        .cf_handle(table)
+        .with_context(|| format!("Failed to open table {table}"))?;
+    fs::create_dir_all(export_dir)?;
+    let sst_path = export_dir.join(TABLE_SST_FILENAME);
+    let options = Options::default();
+    let mut writer = SstFileWriter::create(&options);
+    writer.open(&sst_path)?;
+    let mut num_entries = 0;
+    for item in rocksdb.iterator_cf(&cf, IteratorMode::Start) {
+        let (key, value) = item?;
+        writer.put(key, value)?;
+        num_entries += 1;
+    }
+    if num_entries > 0 {
+        writer.finish()?;
+    } else {
+        // RocksDB refuses to finish an empty sst file
+        drop(writer);
+        let _ = fs::remove_file(&sst_path);
+    }
+    let export = TableExport {
+        db: db.to_string(),
+        table: table.to_string(),
+        column_families,
+        num_entries,
+    };
+    fs::write(
+        export_dir.join(TABLE_EXPORT_FILENAME),
+        serde_json::to_vec_pretty(&export)?,
+    )?;
+    info!(
+        "Exported {num_entries} entries of table {table} of db at {}",
+        db_path.display()
+    );
+    Ok(export)
+}
+
+/// Imports the table exported into `export_dir` into the same db of the db checkpoint at
+/// `checkpoint_dir`, after checking that both dbs have the same tables
+pub fn import_table(export_dir: &Path, checkpoint_dir: &Path, mode: ImportMode) -> Result<()> {
+    let export: TableExport =
+        serde_json::from_slice(&fs::read(export_dir.join(TABLE_EXPORT_FILENAME))?)?;
+    let db_path = checkpoint_dir.join(&export.db);
+    let column_families = list_tables(&db_path)?;
+    if column_families != export.column_families {
+        return Err(anyhow!(
+            "Schema mismatch, db at {} has tables {:?} but the export was taken from a db with tables {:?}",
+            db_path.display(),
+            column_families,
+            export.column_families
+        ));
+    }
+    let rocksdb = DB::open_cf(&Options::default(), &db_path, &column_families)?;
+    let cf = rocksdb
+        .cf_handle(&export.table)
+        .with_context(|| format!("Failed to open table {}", export.table))?;
+    if mode == ImportMode::Replace {
+        let first = rocksdb.iterator_cf(&cf, IteratorMode::Start).next();
+        let last = rocksdb.iterator_cf(&cf, IteratorMode::End).next();
+        if let (Some(first), Some(last)) = (first, last) {
+            let ((first, _), (last, _)) = (first?, last?);
+            // The range end is exclusive
+            rocksdb.delete_range_cf(&cf, &first, &last)?;
+            rocksdb.delete_cf(&cf, &last)?;
+        }
+    }
+    // Ingested entries take precedence over the existing ones with the same keys
+    if export.num_entries > 0 {
+        rocksdb.ingest_external_file_cf(&cf, vec![export_dir.join(TABLE_SST_FILENAME)])?;
+    }
+    rocksdb.flush_cf(&cf)?;
+    info!(
+        "Imported {} entries into table {} of db at {} with mode: {:?}",
+        export.num_entries,
+        export.table,
+        db_path.display(),
+        mode
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export_table, import_table, ImportMode};
+    use rocksdb::{IteratorMode, Options, DB};
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn create_db(path: &Path, tables: &[&str], entries: &[(&str, &[u8], &[u8])]) {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = DB::open_cf(&options, path, tables).unwrap();
+        for (table, key, value) in entries {
+            db.put_cf(&db.cf_handle(table).unwrap(), key, value)
+                .unwrap();
+        }
+    }
+
+    fn read_table(path: &Path, table: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let tables = DB::list_cf(&Options::default(), path).unwrap();
+        let db = DB::open_cf_for_read_only(&Options::default(), path, &tables, false).unwrap();
+        let cf = db.cf_handle(table).unwrap();
+        let entries: Vec<_> = db
+            .iterator_cf(&cf, IteratorMode::Start)
+            .map(|item| {
+                let (key, value) = item.unwrap();
+                (key.to_vec(), value.to_vec())
+            })
+            .collect();
+        entries
+    }
+
+    #[test]
+    fn test_export_import_table() -> anyhow::Result<()> {
+        let source = TempDir::new()?;
+        let target = TempDir::new()?;
+        let other_schema = TempDir::new()?;
+        let export_dir = TempDir::new()?;
+        let db = "store/perpetual";
+        create_db(
+            &source.path().join(db),
+            &["objects", "transactions"],
+            &[
+                ("objects", b"a", b"1"),
+                ("objects", b"b", b"2"),
+                ("transactions", b"t", b"3"),
+            ],
+        );
+        // Corrupted value of b and stale entry c
+        create_db(
+            &target.path().join(db),
+            &["objects", "transactions"],
+            &[
+                ("objects", b"b", b"X"),
+                ("objects", b"c", b"4"),
+                ("transactions", b"u", b"5"),
+            ],
+        );
+        create_db(&other_schema.path().join(db), &["objects"], &[]);
+
+        assert!(export_table(source.path(), db, "missing", export_dir.path()).is_err());
+        let export = export_table(source.path(), db, "objects", export_dir.path())?;
+        assert_eq!(export.num_entries, 2);
+        // Including the default table
+        assert_eq!(export.column_families.len(), 3);
+
+        import_table(export_dir.path(), target.path(), ImportMode::Overlay)?;
+        assert_eq!(
+            read_table(&target.path().join(db), "objects"),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+                (b"c".to_vec(), b"4".to_vec()),
+            ]
+        );
+        import_table(export_dir.path(), target.path(), ImportMode::Replace)?;
+        assert_eq!(
+            read_table(&target.path().join(db), "objects"),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec())
+            ]
+        );
+        // Other tables are left alone
+        assert_eq!(
+            read_table(&target.path().join(db), "transactions"),
+            vec![(b"u".to_vec(), b"5".to_vec())]
+        );
+        assert!(import_table(export_dir.path(), other_schema.path(), ImportMode::Replace).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::db_checkpoint_handler::diagnosis::ProbableCause;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Lifecycle milestones of the db checkpoint backup pipeline. Every event is logged as a
+/// structured tracing event and, when a sender is installed, forwarded to the node's telemetry
+/// subsystem so that backup behavior can be analyzed across the fleet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupTelemetryEvent {
+    EpochUploadStarted {
+        epoch: u32,
+    },
+    EpochUploadCompleted {
+        epoch: u32,
+        duration: Duration,
+    },
+    /// `error` is only logged locally, it holds the paths, bucket and keys of the upload. Only
+    /// its probable cause is forwarded.
+    EpochUploadFailed {
+        epoch: u32,
+        cause: ProbableCause,
+        error: String,
+    },
+    GcCompleted {
+        epochs: Vec<u32>,
+    },
+    RestoreCompleted {
+        epoch: u32,
+        duration: Duration,
+    },
+    FirstMissingEpochRegressed {
+        previous: u32,
+        current: u32,
+    },
+}
+
+impl BackupTelemetryEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackupTelemetryEvent::EpochUploadStarted { .. } => "epoch_upload_started",
+            BackupTelemetryEvent::EpochUploadCompleted { .. } => "epoch_upload_completed",
+            BackupTelemetryEvent::EpochUploadFailed { .. } => "epoch_upload_failed",
+            BackupTelemetryEvent::GcCompleted { .. } => "gc_completed",
+            BackupTelemetryEvent::RestoreCompleted { .. } => "restore_completed",
+            BackupTelemetryEvent::FirstMissingEpochRegressed { .. } => {
+                "first_missing_epoch_regressed"
+            }
+        }
+    }
+    pub fn params(&self) -> BTreeMap<String, String> {
+        match self {
+            BackupTelemetryEvent::EpochUploadStarted { epoch } => {
+                BTreeMap::from([("epoch".into(), epoch.to_string())])
+            }
+            BackupTelemetryEvent::EpochUploadCompleted { epoch, duration }
+            | BackupTelemetryEvent::RestoreCompleted { epoch, duration } => BTreeMap::from([
+                ("epoch".into(), epoch.to_string()),
+                ("duration_ms".into(), duration.as_millis().to_string()),
+            ]),
+            BackupTelemetryEvent::EpochUploadFailed { epoch, cause, .. } => BTreeMap::from([
+                ("epoch".into(), epoch.to_string()),
+                ("cause".into(), cause.to_string()),
+            ]),
+            BackupTelemetryEvent::GcCompleted { epochs } => BTreeMap::from([
+                ("num_epochs".into(), epochs.len().to_string()),
+                ("epochs".into(), format!("{:?}", epochs)),
+            ]),
+            BackupTelemetryEvent::FirstMissingEpochRegressed { previous, current } => {
+                BTreeMap::from([
+                    ("previous".into(), previous.to_string()),
+                    ("current".into(), current.to_string()),
+                    ("severity".into(), "critical".to_string()),
+                ])
+            }
+        }
+    }
+}
+
+/// Emits backup lifecycle events. Emission never blocks the backup pipeline: if the
+/// telemetry consumer falls behind, events are dropped after being logged.
+#[derive(Clone, Default)]
+pub struct BackupTelemetry {
+    sender: Option<mpsc::Sender<BackupTelemetryEvent>>,
+}
+
+impl BackupTelemetry {
+    pub fn new(sender: mpsc::Sender<BackupTelemetryEvent>) -> Self {
+        Self {
+            sender: Some(sender),
+        }
+    }
+    pub fn emit(&self, event: BackupTelemetryEvent) {
+        let error = match &event {
+            BackupTelemetryEvent::EpochUploadFailed { error, .. } => Some(error.as_str()),
+            _ => None,
+        };
+        info!(
+            target: "backup_telemetry",
+            event = event.name(),
+            params = ?event.params(),
+            error,
+            "Backup lifecycle event"
+        );
+        if let Some(sender) = &self.sender {
+            if let Err(err) = sender.try_send(event) {
+                warn!("Dropped backup telemetry event: {:?}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BackupTelemetryEvent;
+    use crate::db_checkpoint_handler::diagnosis::ProbableCause;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_failure_params_omit_error() {
+        let event = BackupTelemetryEvent::EpochUploadFailed {
+            epoch: 3,
+            cause: ProbableCause::AccessDenied,
+            error: "Failed to put s3://private-bucket/epoch_3/MANIFEST".to_string(),
+        };
+        assert_eq!(
+            event.params(),
+            BTreeMap::from([
+                ("epoch".to_string(), "3".to_string()),
+                ("cause".to_string(), "access denied".to_string()),
+            ])
+        );
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Daily budget of the bytes downloaded by the content checks of the verify-only role, see
+//! `DBCheckpointConfig::verification_budget_bytes_per_day`. Checks which don't fit in the
+//! budget of the day are resumed by the next passes, so that the GET costs of continuous
+//! verification stay predictable however large the epochs grow.
+
+use parking_lot::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const BUDGET_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct BudgetState {
+    window_start: Instant,
+    /// Bytes consumed since `window_start`
+    consumed: u64,
+}
+
+pub struct VerificationBudget {
+    bytes_per_day: Option<u64>,
+    state: Mutex<BudgetState>,
+}
+
+impl Default for VerificationBudget {
+    /// No limit
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl VerificationBudget {
+    pub fn new(bytes_per_day: Option<u64>) -> Self {
+        VerificationBudget {
+            bytes_per_day: bytes_per_day.filter(|budget| *budget > 0),
+            state: Mutex::new(BudgetState {
+                window_start: Instant::now(),
+                consumed: 0,
+            }),
+        }
+    }
+    pub fn bytes_per_day(&self) -> Option<u64> {
+        self.bytes_per_day
+    }
+    /// Bytes consumed in the current day, also counted when the budget is unlimited
+    pub fn consumed(&self) -> u64 {
+        let mut state = self.state.lock();
+        Self::roll_window(&mut state);
+        state.consumed
+    }
+    /// Consumes `bytes` of the budget of the day if they fit. A download larger than the whole
+    /// budget only fits as the first of a day, so that no file is left unchecked forever.
+    pub fn try_consume(&self, bytes: u64) -> bool {
+        let mut state = self.state.lock();
+        Self::roll_window(&mut state);
+        let fits = match self.bytes_per_day {
+            Some(budget) => state.consumed == 0 || state.consumed + bytes <= budget,
+            None => true,
+        };
+        if fits {
+            state.consumed += bytes;
+        }
+        fits
+    }
+    fn roll_window(state: &mut BudgetState) {
+        let now = Instant::now();
+        if now.duration_since(state.window_start) >= BUDGET_WINDOW {
+            state.window_start = now;
+            state.consumed = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VerificationBudget, BUDGET_WINDOW};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn test_daily_budget() {
+        let budget = VerificationBudget::new(Some(100));
+        assert!(budget.try_consume(60));
+        assert!(budget.try_consume(40));
+        assert!(!budget.try_consume(1));
+        assert_eq!(budget.consumed(), 100);
+
+        // The budget is renewed a day after the window started
+        tokio::time::advance(BUDGET_WINDOW - Duration::from_secs(1)).await;
+        assert!(!budget.try_consume(1));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(budget.consumed(), 0);
+        // A download larger than the budget only fits first
+        assert!(budget.try_consume(150));
+        assert!(!budget.try_consume(1));
+
+        let unlimited = VerificationBudget::new(Some(0));
+        assert_eq!(unlimited.bytes_per_day(), None);
+        assert!(unlimited.try_consume(u64::MAX / 2));
+        assert!(unlimited.try_consume(u64::MAX / 2));
+    }
+}
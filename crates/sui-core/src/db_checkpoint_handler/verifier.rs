@@ -0,0 +1,216 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verify-only role of the handler, see `DBCheckpointConfig::verify_only`. The handler never
+//! uploads and checks the epochs uploaded by another node instead, e.g. the other node of an
+//! HA pair, so that the completeness and integrity of its backups are asserted independently
+//! of the node producing them. With `verify-remote-checksums`, the contents of the files are
+//! checked too, within the daily verification budget, over several passes for the epochs which
+//! don't fit in a single day.
+
+use crate::db_checkpoint_handler::encryption::ENCRYPTION_OVERHEAD;
+use crate::db_checkpoint_handler::events::BackupEvent;
+use crate::db_checkpoint_handler::manifest::{read_published_manifest, EpochManifest};
+use crate::db_checkpoint_handler::source::remote_epoch_dir;
+use crate::db_checkpoint_handler::{DBCheckpointHandler, SUCCESS_MARKER};
+use anyhow::{anyhow, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use sui_storage::object_store::util::{missing_epochs_of, scan_epoch_dirs, EpochCompleteness};
+use tracing::{error, info};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// Latest epoch with a success marker in the remote store
+    pub latest_complete_epoch: Option<u32>,
+    /// Epochs below the latest complete one without a success marker
+    pub missing_epochs: Vec<u32>,
+    /// Complete epochs verified for the first time by this pass
+    pub verified_epochs: Vec<u32>,
+    /// Complete epochs failing verification, with the reason
+    pub failed_epochs: BTreeMap<u32, String>,
+    /// Complete epochs whose content checks ran out of verification budget, resumed by the
+    /// next passes
+    pub pending_epochs: Vec<u32>,
+}
+
+/// Progress of the content checks of an epoch, which may span several passes
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ContentCoverage {
+    /// Files whose contents were checked, cleared once the whole epoch is
+    checked_files: BTreeSet<String>,
+    checked_bytes: u64,
+    total_bytes: u64,
+}
+
+impl DBCheckpointHandler {
+    /// Checks that no epoch is missing from the remote store below the latest complete one,
+    /// and that the files of every complete epoch match its manifest. Epochs which passed are
+    /// not checked again, failed ones are retried on every pass.
+    pub async fn verify_remote_epochs(&self) -> Result<VerificationReport> {
+        let listing = scan_epoch_dirs(self.output_object_store.clone(), None).await?;
+        let mut missing_epochs = missing_epochs_of(
+            self.output_object_store.clone(),
+            &listing.epoch_dirs,
+            &EpochCompleteness::Marker(SUCCESS_MARKER.to_string()),
+            0,
+        )
+        .await?;
+        // The epoch after the latest one is not uploaded yet, it is not a gap
+        missing_epochs.pop();
+        let latest_complete_epoch = listing
+            .epoch_dirs
+            .keys()
+            .rev()
+            .find(|epoch| !missing_epochs.contains(epoch))
+            .cloned();
+        missing_epochs.retain(|epoch| Some(*epoch) < latest_complete_epoch);
+        // Gaps below the oldest epoch are left by remote retention
+        if let (Some(policy), Some(oldest)) =
+            (&self.remote_retention, listing.epoch_dirs.keys().next())
+        {
+            if policy.is_enabled() {
+                missing_epochs.retain(|epoch| epoch > oldest);
+            }
+        }
+        let mut report = VerificationReport {
+            latest_complete_epoch,
+            missing_epochs,
+            ..Default::default()
+        };
+        for epoch in listing.epoch_dirs.keys() {
+            if report.missing_epochs.contains(epoch)
+                || Some(*epoch) > latest_complete_epoch
+                || self.verified_epochs.lock().contains(epoch)
+            {
+                continue;
+            }
+            match self.verify_remote_epoch(*epoch).await {
+                Ok(false) => report.pending_epochs.push(*epoch),
+                Ok(true) => {
+                    self.verified_epochs.lock().insert(*epoch);
+                    self.metrics.verifier_verified_epochs_total.inc();
+                    report.verified_epochs.push(*epoch);
+                }
+                Err(err) => {
+                    error!(
+                        "Remote db checkpoint for epoch: {epoch} failed verification: {:?}",
+                        err
+                    );
+                    self.publish(BackupEvent::Error {
+                        epoch: Some(*epoch),
+                        error: format!("{:?}", err),
+                    });
+                    report.failed_epochs.insert(*epoch, format!("{:?}", err));
+                }
+            }
+        }
+        self.metrics
+            .verifier_latest_complete_epoch
+            .set(latest_complete_epoch.map_or(-1, |epoch| epoch as i64));
+        self.metrics
+            .verifier_missing_epochs
+            .set(report.missing_epochs.len() as i64);
+        self.metrics
+            .verifier_failed_epochs
+            .set(report.failed_epochs.len() as i64);
+        self.record_content_coverage(|epoch| listing.epoch_dirs.contains_key(&epoch));
+        info!(
+            "Verified remote db checkpoints up to epoch: {:?}, missing: {:?}, failed: {:?}, pending: {:?}",
+            latest_complete_epoch,
+            report.missing_epochs,
+            report.failed_epochs.keys().collect::<Vec<_>>(),
+            report.pending_epochs
+        );
+        Ok(report)
+    }
+    /// Returns whether the epoch was verified completely, or its content checks ran out of
+    /// budget
+    async fn verify_remote_epoch(&self, epoch: u32) -> Result<bool> {
+        let remote_dir = remote_epoch_dir(epoch);
+        let manifest = read_published_manifest(&remote_dir, self.output_object_store.clone())
+            .await
+            .with_context(|| format!("Unreadable manifest for epoch: {epoch}"))?
+            .ok_or_else(|| anyhow!("Epoch: {epoch} is no longer published"))?;
+        if let (Some(chain_identifier), Some(labels)) = (&self.chain_identifier, &manifest.labels) {
+            labels.verify(chain_identifier)?;
+        }
+        self.verify_remote_checkpoint(&remote_dir, &manifest)
+            .await?;
+        if !self.verify_remote_checksums {
+            return Ok(true);
+        }
+        self.verify_remote_contents_within_budget(&manifest).await
+    }
+    /// Checks the contents of the files of `manifest` not checked by the previous passes, as
+    /// long as they fit in the verification budget. Returns whether every file is checked.
+    async fn verify_remote_contents_within_budget(&self, manifest: &EpochManifest) -> Result<bool> {
+        let epoch = manifest.epoch;
+        let remote_size = |size: usize| {
+            let overhead = manifest
+                .encryption_key_id
+                .as_ref()
+                .map_or(0, |_| ENCRYPTION_OVERHEAD);
+            (size + overhead) as u64
+        };
+        let files: Vec<_> = manifest.files.iter().filter(|file| file.size > 0).collect();
+        let checked_files = {
+            let mut coverage = self.content_coverage.lock();
+            let coverage = coverage.entry(epoch).or_default();
+            coverage.total_bytes = files
+                .iter()
+                .map(|file| remote_size(file.compressed_size.unwrap_or(file.size)))
+                .sum();
+            coverage.checked_files.clone()
+        };
+        let mut selected = vec![];
+        let mut selected_bytes = 0;
+        let mut complete = true;
+        for file in files
+            .iter()
+            .filter(|file| !checked_files.contains(&file.path))
+        {
+            let size = remote_size(file.compressed_size.unwrap_or(file.size));
+            if !self.verification_budget.try_consume(size) {
+                complete = false;
+                break;
+            }
+            selected.push(*file);
+            selected_bytes += size;
+        }
+        self.metrics
+            .verifier_budget_consumed_bytes
+            .set(self.verification_budget.consumed() as i64);
+        self.verify_remote_contents(manifest, &selected).await?;
+        let mut coverage = self.content_coverage.lock();
+        let coverage = coverage.entry(epoch).or_default();
+        if complete {
+            coverage.checked_files.clear();
+            coverage.checked_bytes = coverage.total_bytes;
+        } else {
+            coverage
+                .checked_files
+                .extend(selected.iter().map(|file| file.path.clone()));
+            coverage.checked_bytes += selected_bytes;
+            info!(
+                "Verification budget exhausted, checked {} of {} bytes of remote db checkpoint for epoch: {epoch}",
+                coverage.checked_bytes, coverage.total_bytes
+            );
+        }
+        Ok(complete)
+    }
+    /// Updates the coverage metrics of the content checks, forgetting the epochs for which
+    /// `is_present` is false, e.g. removed by remote retention
+    fn record_content_coverage(&self, is_present: impl Fn(u32) -> bool) {
+        let mut coverage = self.content_coverage.lock();
+        coverage.retain(|epoch, _| is_present(*epoch));
+        self.metrics
+            .verifier_content_checked_bytes
+            .set(coverage.values().map(|c| c.checked_bytes).sum::<u64>() as i64);
+        self.metrics
+            .verifier_content_total_bytes
+            .set(coverage.values().map(|c| c.total_bytes).sum::<u64>() as i64);
+        self.metrics
+            .verifier_budget_consumed_bytes
+            .set(self.verification_budget.consumed() as i64);
+    }
+}
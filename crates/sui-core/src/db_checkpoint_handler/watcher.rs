@@ -0,0 +1,160 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Watch of the directory of the local db checkpoints with inotify, so that a new epoch is
+//! uploaded within seconds of its db checkpoint being taken rather than on the next upload
+//! interval, which is set long where listing the directory is costly. Db checkpoints are
+//! renamed into place once complete, so their directory shows up in a single event.
+
+use crate::db_checkpoint_handler::source::CheckpointSource;
+use anyhow::Result;
+use std::path::Path;
+
+#[cfg(target_os = "linux")]
+pub struct CheckpointWatcher {
+    fd: tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>,
+}
+
+#[cfg(target_os = "linux")]
+impl CheckpointWatcher {
+    /// Starts watching the entries created in or moved into `dir`
+    pub fn new(dir: &Path) -> Result<Self> {
+        use anyhow::Context;
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        use std::os::unix::ffi::OsStrExt;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to initialize inotify");
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let path = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+        let mask = libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_ONLYDIR;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), mask) } < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to watch {}", dir.display()));
+        }
+        Ok(CheckpointWatcher {
+            fd: tokio::io::unix::AsyncFd::new(fd)?,
+        })
+    }
+    /// Names of the next entries created in or moved into the watched directory
+    async fn changed(&mut self) -> Result<Vec<String>> {
+        use std::os::fd::AsRawFd;
+
+        // Fits several events, each with a name of up to NAME_MAX bytes
+        let mut buffer = [0u8; 4096];
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let read = guard.try_io(|fd| {
+                let read = unsafe {
+                    libc::read(
+                        fd.as_raw_fd(),
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                    )
+                };
+                if read < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(read as usize)
+            });
+            // Spurious wakeup
+            let Ok(read) = read else {
+                continue;
+            };
+            return Ok(event_names(&buffer[..read?]));
+        }
+    }
+}
+
+/// Names of the entries of the inotify events in `buffer`
+#[cfg(target_os = "linux")]
+fn event_names(mut buffer: &[u8]) -> Vec<String> {
+    const HEADER_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+    let mut names = vec![];
+    while buffer.len() >= HEADER_SIZE {
+        // The name length is the last field of the header, the name is padded with NULs
+        let len = u32::from_ne_bytes(buffer[HEADER_SIZE - 4..HEADER_SIZE].try_into().unwrap());
+        let end = HEADER_SIZE + len as usize;
+        if end > buffer.len() {
+            break;
+        }
+        let name = buffer[HEADER_SIZE..end].split(|b| *b == 0).next();
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            names.push(String::from_utf8_lossy(name).into_owned());
+        }
+        buffer = &buffer[end..];
+    }
+    names
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct CheckpointWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl CheckpointWatcher {
+    pub fn new(_dir: &Path) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "Watching the db checkpoint directory needs inotify, which is only available on Linux"
+        ))
+    }
+    async fn changed(&mut self) -> Result<Vec<String>> {
+        std::future::pending().await
+    }
+}
+
+/// Next epoch whose db checkpoint shows up in the directory watched by `watcher`. Never
+/// resolves without a watcher.
+pub async fn next_new_epoch(
+    watcher: Option<&mut CheckpointWatcher>,
+    source: &CheckpointSource,
+) -> Result<u32> {
+    let Some(watcher) = watcher else {
+        return std::future::pending().await;
+    };
+    loop {
+        // Temporary directories of db checkpoints being taken don't parse as epochs
+        let epoch = watcher
+            .changed()
+            .await?
+            .iter()
+            .filter_map(|name| source.parse_epoch(name).ok().flatten())
+            .max();
+        if let Some(epoch) = epoch {
+            return Ok(epoch);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{next_new_epoch, CheckpointWatcher};
+    use crate::db_checkpoint_handler::source::CheckpointSource;
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_checkpoint_watcher() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut watcher = CheckpointWatcher::new(dir.path())?;
+        let source = CheckpointSource::default();
+        fs::write(dir.path().join("file1"), b"Lorem ipsum")?;
+        fs::create_dir(dir.path().join("epoch_1.tmp"))?;
+        fs::rename(dir.path().join("epoch_1.tmp"), dir.path().join("epoch_1"))?;
+        let epoch = tokio::time::timeout(
+            Duration::from_secs(10),
+            next_new_epoch(Some(&mut watcher), &source),
+        )
+        .await??;
+        assert_eq!(epoch, 1);
+        // Nothing shows up without a watcher
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), next_new_epoch(None, &source))
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+}
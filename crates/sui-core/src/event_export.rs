@@ -0,0 +1,236 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export of the events emitted in a range of checkpoints to an object store, so that indexers
+//! which fell behind can catch up from the exported files instead of through JSON-RPC.
+//! Exported files are laid out as:
+//!
+//! ```text
+//! events/
+//!  ├─ 0_1000.evt
+//!  ├─ 1000_2000.evt
+//!  └─ ...
+//! ```
+//!
+//! where each file covers the checkpoints `[start, end)` named in its file name. A file starts
+//! with a magic number, storage format and compression byte (see `sui_storage::read`) followed
+//! by a zstd compressed sequence of length prefixed bcs blobs, one `CheckpointEvents` per
+//! checkpoint in the range.
+
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::ops::Range;
+use std::sync::Arc;
+use sui_storage::blob::{Blob, BlobEncoding};
+use sui_storage::object_store::util::{get, put};
+use sui_storage::{compress, make_iterator, FileCompression, StorageFormat};
+use sui_types::base_types::TransactionDigest;
+use sui_types::committee::EpochId;
+use sui_types::effects::{TransactionEffectsAPI, TransactionEvents};
+use sui_types::messages_checkpoint::CheckpointSequenceNumber;
+use sui_types::storage::ReadStore;
+
+pub const EVENTS_DIR: &str = "events";
+pub const EVENTS_FILE_SUFFIX: &str = "evt";
+const EVENTS_FILE_MAGIC: u32 = 0x00E7E175;
+
+/// Events emitted by the transactions of a checkpoint, in execution order. Transactions that
+/// emitted no events are omitted.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CheckpointEvents {
+    pub sequence_number: CheckpointSequenceNumber,
+    pub epoch: EpochId,
+    pub timestamp_ms: u64,
+    pub transactions: Vec<(TransactionDigest, TransactionEvents)>,
+}
+
+fn events_file_path(range: &Range<CheckpointSequenceNumber>) -> Path {
+    Path::from(format!(
+        "{}/{}_{}.{}",
+        EVENTS_DIR, range.start, range.end, EVENTS_FILE_SUFFIX
+    ))
+}
+
+fn parse_events_file_name(name: &str) -> Option<Range<CheckpointSequenceNumber>> {
+    let (start, end) = name
+        .strip_suffix(EVENTS_FILE_SUFFIX)?
+        .strip_suffix('.')?
+        .split_once('_')?;
+    Some(start.parse().ok()?..end.parse().ok()?)
+}
+
+/// Collects the events of checkpoint `sequence_number` from `store`
+pub fn read_checkpoint_events<S>(
+    store: &S,
+    sequence_number: CheckpointSequenceNumber,
+) -> Result<CheckpointEvents>
+where
+    S: ReadStore,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let checkpoint = store
+        .get_checkpoint_by_sequence_number(sequence_number)?
+        .with_context(|| format!("Missing checkpoint: {sequence_number}"))?;
+    let contents = store
+        .get_full_checkpoint_contents(&checkpoint.content_digest)?
+        .with_context(|| format!("Missing contents of checkpoint: {sequence_number}"))?;
+    let mut transactions = vec![];
+    for execution_data in contents.iter() {
+        let Some(events_digest) = execution_data.effects.events_digest() else {
+            continue;
+        };
+        let events = store
+            .get_transaction_events(events_digest)?
+            .with_context(|| format!("Missing events: {events_digest}"))?;
+        transactions.push((*execution_data.effects.transaction_digest(), events));
+    }
+    Ok(CheckpointEvents {
+        sequence_number,
+        epoch: checkpoint.epoch(),
+        timestamp_ms: checkpoint.timestamp_ms,
+        transactions,
+    })
+}
+
+/// Serializes `checkpoints` in the events file format
+pub fn encode_events_file(checkpoints: &[CheckpointEvents]) -> Result<Bytes> {
+    let mut uncompressed = vec![];
+    uncompressed.extend_from_slice(&EVENTS_FILE_MAGIC.to_be_bytes());
+    uncompressed.push(StorageFormat::Blob.into());
+    uncompressed.push(FileCompression::Zstd.into());
+    for checkpoint in checkpoints {
+        Blob::encode(checkpoint, BlobEncoding::Bcs)?.write(&mut uncompressed)?;
+    }
+    let mut compressed = vec![];
+    compress(&mut Cursor::new(uncompressed), &mut compressed)?;
+    Ok(Bytes::from(compressed))
+}
+
+pub fn decode_events_file(bytes: Bytes) -> Result<Vec<CheckpointEvents>> {
+    Ok(make_iterator::<CheckpointEvents, _>(EVENTS_FILE_MAGIC, Cursor::new(bytes))?.collect())
+}
+
+/// Exports the events of the checkpoints in `range` to `remote_store`, in files of at most
+/// `checkpoints_per_file` checkpoints. Returns the paths of the written files.
+pub async fn export_events<S>(
+    store: &S,
+    range: Range<CheckpointSequenceNumber>,
+    checkpoints_per_file: u64,
+    remote_store: Arc<DynObjectStore>,
+) -> Result<Vec<Path>>
+where
+    S: ReadStore,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    if checkpoints_per_file == 0 {
+        return Err(anyhow!("checkpoints_per_file must be positive"));
+    }
+    let mut paths = vec![];
+    let mut start = range.start;
+    while start < range.end {
+        let end = std::cmp::min(start.saturating_add(checkpoints_per_file), range.end);
+        let checkpoints = (start..end)
+            .map(|seq| read_checkpoint_events(store, seq))
+            .collect::<Result<Vec<_>>>()?;
+        let path = events_file_path(&(start..end));
+        put(
+            &path,
+            encode_events_file(&checkpoints)?,
+            remote_store.clone(),
+        )
+        .await?;
+        paths.push(path);
+        start = end;
+    }
+    Ok(paths)
+}
+
+/// Reads events previously exported with `export_events`
+pub struct EventExportReader {
+    remote_store: Arc<DynObjectStore>,
+}
+
+impl EventExportReader {
+    pub fn new(remote_store: Arc<DynObjectStore>) -> Self {
+        EventExportReader { remote_store }
+    }
+    /// Checkpoint ranges covered by the exported files, sorted by start
+    pub async fn list_ranges(&self) -> Result<Vec<Range<CheckpointSequenceNumber>>> {
+        let mut ranges = vec![];
+        let mut entries = self
+            .remote_store
+            .list(Some(&Path::from(EVENTS_DIR)))
+            .await?;
+        while let Some(entry) = entries.next().await {
+            let object_metadata = entry?;
+            if let Some(range) = object_metadata
+                .location
+                .filename()
+                .and_then(parse_events_file_name)
+            {
+                ranges.push(range);
+            }
+        }
+        ranges.sort_by_key(|range| (range.start, range.end));
+        Ok(ranges)
+    }
+    /// Returns the events of every checkpoint in `range`, failing if part of the range was
+    /// never exported
+    pub async fn read_range(
+        &self,
+        range: Range<CheckpointSequenceNumber>,
+    ) -> Result<Vec<CheckpointEvents>> {
+        let mut result = vec![];
+        let mut next = range.start;
+        for file_range in self.list_ranges().await? {
+            if next >= range.end {
+                break;
+            }
+            if file_range.end <= next || file_range.start > next {
+                continue;
+            }
+            let bytes = get(&events_file_path(&file_range), self.remote_store.clone()).await?;
+            for checkpoint in decode_events_file(bytes)? {
+                if checkpoint.sequence_number == next && next < range.end {
+                    result.push(checkpoint);
+                    next += 1;
+                }
+            }
+        }
+        if next < range.end {
+            return Err(anyhow!(
+                "Events of checkpoint {next} are missing from the export"
+            ));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_events_file, encode_events_file, parse_events_file_name, CheckpointEvents};
+    use sui_types::base_types::TransactionDigest;
+    use sui_types::effects::TransactionEvents;
+
+    #[test]
+    fn test_events_file_roundtrip() -> anyhow::Result<()> {
+        let checkpoints: Vec<_> = (10..13)
+            .map(|seq| CheckpointEvents {
+                sequence_number: seq,
+                epoch: 1,
+                timestamp_ms: seq * 1000,
+                transactions: vec![(TransactionDigest::random(), TransactionEvents::default())],
+            })
+            .collect();
+        let bytes = encode_events_file(&checkpoints)?;
+        assert_eq!(decode_events_file(bytes)?, checkpoints);
+        assert_eq!(parse_events_file_name("10_13.evt"), Some(10..13));
+        assert_eq!(parse_events_file_name("10_13.chk"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Point in time object queries ("state time travel") for debugging and audits. Restored db
+//! checkpoints are opened read-only next to the live store: the db checkpoint of epoch N holds
+//! the object state as of the end of epoch N, while queries for the current epoch are served
+//! by the live store.
+
+use crate::authority::authority_store_tables::{
+    AuthorityPerpetualTables, AuthorityPerpetualTablesReadOnly,
+};
+use crate::authority::authority_store_types::{
+    try_construct_object, StoreData, StoreObject, StoreObjectWrapper,
+};
+use anyhow::{anyhow, Result};
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use sui_types::base_types::{ObjectID, VersionNumber};
+use sui_types::committee::EpochId;
+use sui_types::error::SuiError;
+use sui_types::object::Object;
+use sui_types::storage::{ObjectKey, ObjectStore};
+use tracing::info;
+use typed_store::rocks::MetricConf;
+use typed_store::traits::Map;
+
+/// Read-only handle on the perpetual tables of a restored db checkpoint
+pub struct HistoricalSnapshot {
+    epoch: EpochId,
+    tables: AuthorityPerpetualTablesReadOnly,
+}
+
+impl HistoricalSnapshot {
+    /// Opens the db checkpoint of `epoch` restored at `snapshot_path`, i.e. the directory
+    /// holding the `store/perpetual` db
+    pub fn open(epoch: EpochId, snapshot_path: &Path) -> Result<Self> {
+        let perpetual_path = AuthorityPerpetualTables::path(&snapshot_path.join("store"));
+        if !perpetual_path.exists() {
+            return Err(anyhow!(
+                "No perpetual store found in snapshot: {}",
+                snapshot_path.display()
+            ));
+        }
+        let tables = AuthorityPerpetualTables::get_read_only_handle(
+            perpetual_path,
+            None,
+            None,
+            MetricConf::default(),
+        );
+        Ok(HistoricalSnapshot { epoch, tables })
+    }
+    pub fn epoch(&self) -> EpochId {
+        self.epoch
+    }
+    fn object(
+        &self,
+        object_key: &ObjectKey,
+        store_object: StoreObjectWrapper,
+    ) -> Result<Option<Object>, SuiError> {
+        let StoreObject::Value(store_object) = store_object.migrate().into_inner() else {
+            return Ok(None);
+        };
+        let indirect_object = match &store_object.data {
+            StoreData::IndirectObject(metadata) => self
+                .tables
+                .indirect_move_objects
+                .get(&metadata.digest)?
+                .map(|o| o.migrate().into_inner()),
+            _ => None,
+        };
+        Ok(Some(try_construct_object(
+            object_key,
+            store_object,
+            indirect_object,
+        )?))
+    }
+}
+
+impl ObjectStore for HistoricalSnapshot {
+    fn get_object(&self, object_id: &ObjectID) -> Result<Option<Object>, SuiError> {
+        let obj_entry = self
+            .tables
+            .objects
+            .unbounded_iter()
+            .skip_prior_to(&ObjectKey::max_for_id(object_id))?
+            .next();
+        match obj_entry {
+            Some((ObjectKey(obj_id, version), obj)) if obj_id == *object_id => {
+                self.object(&ObjectKey(obj_id, version), obj)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn get_object_by_key(
+        &self,
+        object_id: &ObjectID,
+        version: VersionNumber,
+    ) -> Result<Option<Object>, SuiError> {
+        Ok(self
+            .tables
+            .objects
+            .get(&ObjectKey(*object_id, version))?
+            .map(|object| self.object(&ObjectKey(*object_id, version), object))
+            .transpose()?
+            .flatten())
+    }
+}
+
+/// Answers object queries as of the end of a given epoch, from the live store and the
+/// historical snapshots opened next to it.
+pub struct HistoricalStateReader {
+    live: Arc<AuthorityPerpetualTables>,
+    snapshots: RwLock<BTreeMap<EpochId, Arc<HistoricalSnapshot>>>,
+}
+
+impl HistoricalStateReader {
+    pub fn new(live: Arc<AuthorityPerpetualTables>) -> Self {
+        HistoricalStateReader {
+            live,
+            snapshots: RwLock::new(BTreeMap::new()),
+        }
+    }
+    /// Opens the db checkpoint of `epoch` restored at `snapshot_path` for queries
+    pub fn add_snapshot(&self, epoch: EpochId, snapshot_path: &Path) -> Result<()> {
+        let snapshot = HistoricalSnapshot::open(epoch, snapshot_path)?;
+        info!(
+            "Opened historical snapshot for epoch: {epoch} at {}",
+            snapshot_path.display()
+        );
+        self.snapshots.write().insert(epoch, Arc::new(snapshot));
+        Ok(())
+    }
+    pub fn remove_snapshot(&self, epoch: EpochId) -> bool {
+        self.snapshots.write().remove(&epoch).is_some()
+    }
+    pub fn available_epochs(&self) -> Vec<EpochId> {
+        self.snapshots.read().keys().cloned().collect()
+    }
+    /// Returns the object as of the end of `epoch`, or its latest state if `epoch` is the
+    /// `current_epoch` of the live store
+    pub fn get_object_at_epoch(
+        &self,
+        object_id: &ObjectID,
+        epoch: EpochId,
+        current_epoch: EpochId,
+    ) -> Result<Option<Object>> {
+        if epoch >= current_epoch {
+            return Ok(self.live.get_object(object_id)?);
+        }
+        let snapshot = self.snapshots.read().get(&epoch).cloned();
+        let snapshot = snapshot.ok_or_else(|| {
+            anyhow!(
+                "No snapshot open for epoch: {epoch}, available: {:?}",
+                self.available_epochs()
+            )
+        })?;
+        Ok(snapshot.get_object(object_id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HistoricalStateReader;
+    use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+    use std::sync::Arc;
+    use sui_types::base_types::{ObjectID, SequenceNumber, SuiAddress};
+    use sui_types::object::Object;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_object_at_epoch() -> anyhow::Result<()> {
+        let live_dir = TempDir::new()?;
+        let live = Arc::new(AuthorityPerpetualTables::open(
+            &live_dir.path().join("store"),
+            None,
+        ));
+        let id = ObjectID::random();
+        let owner = SuiAddress::random_for_testing_only();
+        live.insert_object_test_only(Object::with_id_owner_version_for_testing(
+            id,
+            SequenceNumber::from(1),
+            owner,
+        ))?;
+        // Db checkpoint taken at the end of epoch 0
+        let snapshot_dir = TempDir::new()?;
+        std::fs::create_dir(snapshot_dir.path().join("store"))?;
+        live.checkpoint_db(&AuthorityPerpetualTables::path(
+            &snapshot_dir.path().join("store"),
+        ))?;
+        live.insert_object_test_only(Object::with_id_owner_version_for_testing(
+            id,
+            SequenceNumber::from(2),
+            owner,
+        ))?;
+
+        let reader = HistoricalStateReader::new(live);
+        assert!(reader.get_object_at_epoch(&id, 0, 1).is_err());
+        reader.add_snapshot(0, snapshot_dir.path())?;
+        assert_eq!(reader.available_epochs(), vec![0]);
+        let historical = reader.get_object_at_epoch(&id, 0, 1)?.unwrap();
+        assert_eq!(historical.version(), SequenceNumber::from(1));
+        let latest = reader.get_object_at_epoch(&id, 1, 1)?.unwrap();
+        assert_eq!(latest.version(), SequenceNumber::from(2));
+        Ok(())
+    }
+}
@@ -14,8 +14,10 @@ pub mod consensus_handler;
 pub mod consensus_validator;
 pub mod db_checkpoint_handler;
 pub mod epoch;
+pub mod event_export;
 pub mod event_handler;
 mod execution_driver;
+pub mod historical_state;
 mod math;
 pub mod metrics;
 pub mod module_cache_metrics;
@@ -26,7 +28,9 @@ mod scoring_decision;
 mod stake_aggregator;
 pub mod state_accumulator;
 pub mod storage;
+pub mod store_health;
 pub mod streamer;
+pub mod task_handle;
 #[cfg(any(test, feature = "test-utils"))]
 pub mod test_utils;
 pub mod transaction_input_checker;
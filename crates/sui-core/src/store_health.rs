@@ -0,0 +1,349 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Periodic sampling of the write stall and slow write indicators of the stores of the node,
+//! so that dips in node performance can be correlated with backups, pruning and compaction.
+//! RocksDB delays writes when flushes or compactions fall behind, and stops them altogether
+//! past its hard limits.
+
+use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+use crate::checkpoints::CheckpointStore;
+use crate::task_handle::TaskHandle;
+use anyhow::Result;
+use mysten_metrics::spawn_monitored_task;
+use parking_lot::RwLock;
+use prometheus::{
+    register_int_counter_vec_with_registry, register_int_gauge_vec_with_registry, IntCounterVec,
+    IntGaugeVec, Registry,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+use typed_store::rocks::RocksDB;
+
+/// Time between two samples of the stores
+pub const STORE_HEALTH_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Latest sample of the health of a store. Flags and counts are aggregated over the column
+/// families of the store.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoreHealth {
+    pub store: String,
+    pub sampled_at_ms: u64,
+    /// Time it took to open the store at startup
+    pub open_latency_ms: u64,
+    /// Writes are stopped until flushes or compactions catch up
+    pub write_stopped: bool,
+    /// Rate in bytes per second writes are throttled to, 0 when they are not delayed
+    pub delayed_write_rate: u64,
+    pub memtable_flush_pending: bool,
+    pub compaction_pending: bool,
+    /// Highest number of level 0 files of a column family, which delays and then stops writes
+    /// past `level0_slowdown_writes_trigger` and `level0_stop_writes_trigger`
+    pub max_level0_files: u64,
+    /// Bytes compaction has to rewrite to bring every level back under its target size
+    pub pending_compaction_bytes: u64,
+    pub num_immutable_memtables: u64,
+    /// Number of samples so far which found writes stopped or delayed
+    pub stalled_samples: u64,
+}
+
+impl StoreHealth {
+    pub fn is_stalled(&self) -> bool {
+        self.write_stopped || self.delayed_write_rate > 0
+    }
+}
+
+struct MonitoredStore {
+    name: String,
+    rocksdb: Arc<RocksDB>,
+    column_families: Vec<String>,
+    open_latency: Duration,
+}
+
+struct StoreHealthMetrics {
+    open_latency_ms: IntGaugeVec,
+    write_stopped: IntGaugeVec,
+    delayed_write_rate: IntGaugeVec,
+    max_level0_files: IntGaugeVec,
+    pending_compaction_bytes: IntGaugeVec,
+    num_immutable_memtables: IntGaugeVec,
+    stalled_samples: IntCounterVec,
+}
+
+impl StoreHealthMetrics {
+    fn new(registry: &Registry) -> Self {
+        Self {
+            open_latency_ms: register_int_gauge_vec_with_registry!(
+                "store_health_open_latency_ms",
+                "Time it took to open the store at startup",
+                &["store"],
+                registry
+            )
+            .unwrap(),
+            write_stopped: register_int_gauge_vec_with_registry!(
+                "store_health_write_stopped",
+                "1 if writes to the store are stopped until flushes or compactions catch up",
+                &["store"],
+                registry
+            )
+            .unwrap(),
+            delayed_write_rate: register_int_gauge_vec_with_registry!(
+                "store_health_delayed_write_rate",
+                "Rate in bytes per second writes to the store are throttled to, 0 when not delayed",
+                &["store"],
+                registry
+            )
+            .unwrap(),
+            max_level0_files: register_int_gauge_vec_with_registry!(
+                "store_health_max_level0_files",
+                "Highest number of level 0 files of a column family of the store",
+                &["store"],
+                registry
+            )
+            .unwrap(),
+            pending_compaction_bytes: register_int_gauge_vec_with_registry!(
+                "store_health_pending_compaction_bytes",
+                "Estimated bytes compaction has to rewrite in the store",
+                &["store"],
+                registry
+            )
+            .unwrap(),
+            num_immutable_memtables: register_int_gauge_vec_with_registry!(
+                "store_health_num_immutable_memtables",
+                "Number of memtables of the store waiting to be flushed",
+                &["store"],
+                registry
+            )
+            .unwrap(),
+            stalled_samples: register_int_counter_vec_with_registry!(
+                "store_health_stalled_samples",
+                "Number of samples which found writes to the store stopped or delayed",
+                &["store"],
+                registry
+            )
+            .unwrap(),
+        }
+    }
+}
+
+pub struct StoreHealthMonitor {
+    stores: RwLock<Vec<MonitoredStore>>,
+    latest: RwLock<BTreeMap<String, StoreHealth>>,
+    metrics: StoreHealthMetrics,
+}
+
+impl StoreHealthMonitor {
+    pub fn new(registry: &Registry) -> Arc<Self> {
+        Arc::new(Self {
+            stores: RwLock::new(vec![]),
+            latest: RwLock::new(BTreeMap::new()),
+            metrics: StoreHealthMetrics::new(registry),
+        })
+    }
+
+    /// Samples the store of `rocksdb` as `name` from now on
+    pub fn add_store(
+        &self,
+        name: &str,
+        rocksdb: Arc<RocksDB>,
+        open_latency: Duration,
+    ) -> Result<()> {
+        let column_families = rocksdb::DB::list_cf(&rocksdb::Options::default(), rocksdb.path())?;
+        self.metrics
+            .open_latency_ms
+            .with_label_values(&[name])
+            .set(open_latency.as_millis() as i64);
+        self.stores.write().push(MonitoredStore {
+            name: name.to_string(),
+            rocksdb,
+            column_families,
+            open_latency,
+        });
+        Ok(())
+    }
+
+    pub fn add_perpetual_store(
+        &self,
+        perpetual_tables: &AuthorityPerpetualTables,
+        open_latency: Duration,
+    ) -> Result<()> {
+        self.add_store(
+            "perpetual",
+            perpetual_tables.objects.rocksdb.clone(),
+            open_latency,
+        )
+    }
+
+    pub fn add_checkpoint_store(
+        &self,
+        checkpoint_store: &CheckpointStore,
+        open_latency: Duration,
+    ) -> Result<()> {
+        self.add_store(
+            "checkpoints",
+            checkpoint_store.checkpoint_content.rocksdb.clone(),
+            open_latency,
+        )
+    }
+
+    /// Samples every store once, updating the metrics and the latest samples
+    pub fn sample(&self) {
+        let sampled_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        for store in self.stores.read().iter() {
+            let mut health = match sample_store(store) {
+                Ok(health) => health,
+                Err(err) => {
+                    warn!("Failed to sample health of store: {}: {err:?}", store.name);
+                    continue;
+                }
+            };
+            health.sampled_at_ms = sampled_at_ms;
+            let previous_stalled_samples = self
+                .latest
+                .read()
+                .get(&store.name)
+                .map_or(0, |previous| previous.stalled_samples);
+            health.stalled_samples = previous_stalled_samples;
+            if health.is_stalled() {
+                health.stalled_samples += 1;
+                self.metrics
+                    .stalled_samples
+                    .with_label_values(&[&store.name])
+                    .inc();
+                warn!(
+                    "Writes to store: {} are {}, level 0 files: {}, pending compaction bytes: {}",
+                    store.name,
+                    if health.write_stopped {
+                        "stopped".to_string()
+                    } else {
+                        format!("delayed to {} bytes/s", health.delayed_write_rate)
+                    },
+                    health.max_level0_files,
+                    health.pending_compaction_bytes
+                );
+            }
+            let labels = [store.name.as_str()];
+            self.metrics
+                .write_stopped
+                .with_label_values(&labels)
+                .set(health.write_stopped as i64);
+            self.metrics
+                .delayed_write_rate
+                .with_label_values(&labels)
+                .set(health.delayed_write_rate as i64);
+            self.metrics
+                .max_level0_files
+                .with_label_values(&labels)
+                .set(health.max_level0_files as i64);
+            self.metrics
+                .pending_compaction_bytes
+                .with_label_values(&labels)
+                .set(health.pending_compaction_bytes as i64);
+            self.metrics
+                .num_immutable_memtables
+                .with_label_values(&labels)
+                .set(health.num_immutable_memtables as i64);
+            self.latest.write().insert(store.name.clone(), health);
+        }
+    }
+
+    /// Latest sample of every store, by name
+    pub fn latest(&self) -> Vec<StoreHealth> {
+        self.latest.read().values().cloned().collect()
+    }
+
+    /// Samples the stores every `interval` until the returned handle is dropped
+    pub fn start(self: &Arc<Self>, interval: Duration) -> TaskHandle {
+        let (handle, mut signal) = TaskHandle::new();
+        let monitor = self.clone();
+        spawn_monitored_task!(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => monitor.sample(),
+                    _ = signal.recv() => break,
+                }
+            }
+        });
+        handle
+    }
+}
+
+fn int_property(rocksdb: &RocksDB, cf_name: &str, property: &str) -> Result<u64> {
+    let Some(cf) = rocksdb.cf_handle(cf_name) else {
+        return Ok(0);
+    };
+    Ok(rocksdb.property_int_value_cf(&cf, property)?.unwrap_or(0))
+}
+
+fn sample_store(store: &MonitoredStore) -> Result<StoreHealth> {
+    let mut health = StoreHealth {
+        store: store.name.clone(),
+        open_latency_ms: store.open_latency.as_millis() as u64,
+        ..Default::default()
+    };
+    let rocksdb = store.rocksdb.as_ref();
+    // Write stops and delays apply to the whole db, every column family reports them
+    if let Some(cf_name) = store.column_families.first() {
+        health.write_stopped = int_property(rocksdb, cf_name, "rocksdb.is-write-stopped")? > 0;
+        health.delayed_write_rate =
+            int_property(rocksdb, cf_name, "rocksdb.actual-delayed-write-rate")?;
+    }
+    for cf_name in store.column_families.iter() {
+        health.memtable_flush_pending |=
+            int_property(rocksdb, cf_name, "rocksdb.mem-table-flush-pending")? > 0;
+        health.compaction_pending |=
+            int_property(rocksdb, cf_name, "rocksdb.compaction-pending")? > 0;
+        health.max_level0_files = health.max_level0_files.max(int_property(
+            rocksdb,
+            cf_name,
+            "rocksdb.num-files-at-level0",
+        )?);
+        health.pending_compaction_bytes += int_property(
+            rocksdb,
+            cf_name,
+            "rocksdb.estimate-pending-compaction-bytes",
+        )?;
+        health.num_immutable_memtables +=
+            int_property(rocksdb, cf_name, "rocksdb.num-immutable-mem-table")?;
+    }
+    Ok(health)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StoreHealthMonitor;
+    use crate::authority::authority_store_tables::AuthorityPerpetualTables;
+    use prometheus::Registry;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_sample_store_health() -> anyhow::Result<()> {
+        let db_dir = TempDir::new()?;
+        let perpetual_tables = AuthorityPerpetualTables::open(db_dir.path(), None);
+        let registry = Registry::new();
+        let monitor = StoreHealthMonitor::new(&registry);
+        monitor.add_perpetual_store(&perpetual_tables, Duration::from_millis(42))?;
+        assert!(monitor.latest().is_empty());
+
+        monitor.sample();
+        monitor.sample();
+        let latest = monitor.latest();
+        assert_eq!(latest.len(), 1);
+        let health = &latest[0];
+        assert_eq!(health.store, "perpetual");
+        assert_eq!(health.open_latency_ms, 42);
+        // A fresh db has nothing to flush or compact
+        assert!(!health.is_stalled());
+        assert_eq!(health.stalled_samples, 0);
+        assert_eq!(health.max_level0_files, 0);
+        assert!(health.sampled_at_ms > 0);
+        Ok(())
+    }
+}
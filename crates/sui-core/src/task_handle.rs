@@ -0,0 +1,105 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Handle to a background task started by `start()`, such as the db checkpoint handler or the
+//! store health monitor. Dropping the handle stops the task, like the bare oneshot sender it
+//! replaces, and the handle can also stop the task and wait for it to return.
+
+use tokio::sync::oneshot;
+
+pub struct TaskHandle {
+    /// Sending, or dropping the handle, stops the task
+    shutdown: oneshot::Sender<()>,
+    /// Closed once the task returned
+    exited: oneshot::Receiver<()>,
+}
+
+/// Held by the task for as long as it runs
+pub struct ShutdownSignal {
+    shutdown: oneshot::Receiver<()>,
+    _exited: oneshot::Sender<()>,
+}
+
+impl TaskHandle {
+    /// Handle to a task which is passed the returned signal
+    pub fn new() -> (Self, ShutdownSignal) {
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let (exited_sender, exited_receiver) = oneshot::channel();
+        (
+            TaskHandle {
+                shutdown: shutdown_sender,
+                exited: exited_receiver,
+            },
+            ShutdownSignal {
+                shutdown: shutdown_receiver,
+                _exited: exited_sender,
+            },
+        )
+    }
+    /// Whether the task still holds its signal, i.e. it hasn't returned nor panicked
+    pub fn is_running(&self) -> bool {
+        !self.shutdown.is_closed()
+    }
+    /// Stops the task and waits for it to return
+    pub async fn shutdown(self) {
+        let TaskHandle { shutdown, exited } = self;
+        let _ = shutdown.send(());
+        let _ = exited.await;
+    }
+    /// Waits for the task to return on its own, without stopping it
+    pub async fn join(self) {
+        let TaskHandle { shutdown, exited } = self;
+        let _ = exited.await;
+        drop(shutdown);
+    }
+}
+
+impl ShutdownSignal {
+    /// Resolves once the task is asked to stop or its handle is dropped. Must not be awaited
+    /// again after it resolved.
+    pub async fn recv(&mut self) {
+        let _ = (&mut self.shutdown).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TaskHandle;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_task_handle() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (handle, mut signal) = TaskHandle::new();
+        let task_stopped = stopped.clone();
+        tokio::spawn(async move {
+            signal.recv().await;
+            // Still cleaning up when shutdown is called
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            task_stopped.store(true, Ordering::SeqCst);
+        });
+        assert!(handle.is_running());
+        handle.shutdown().await;
+        assert!(stopped.load(Ordering::SeqCst));
+
+        // Dropping the handle stops the task too
+        let (handle, mut signal) = TaskHandle::new();
+        let (joined_handle, joined_signal) = TaskHandle::new();
+        tokio::spawn(async move {
+            signal.recv().await;
+            drop(joined_signal);
+        });
+        drop(handle);
+        assert!(
+            tokio::time::timeout(Duration::from_secs(5), joined_handle.join())
+                .await
+                .is_ok()
+        );
+
+        let (handle, signal) = TaskHandle::new();
+        drop(signal);
+        assert!(!handle.is_running());
+    }
+}
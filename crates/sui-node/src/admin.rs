@@ -4,16 +4,21 @@
 use crate::SuiNode;
 use axum::{
     extract::{Query, State},
-    http::StatusCode,
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
     routing::{get, post},
-    Router,
+    Json, Router,
 };
 use serde::Deserialize;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
+use sui_config::node::StorageAdminAction;
+use sui_core::db_checkpoint_handler::admin_auth::{AccessDenied, StorageAdminAuth};
+use sui_core::db_checkpoint_handler::commands::BackupCommander;
+use sui_core::db_checkpoint_handler::status::BackupStatus;
+use sui_core::store_health::StoreHealth;
 use sui_types::error::SuiError;
 use telemetry_subscribers::FilterHandle;
-use tracing::info;
+use tracing::{debug, info};
 
 // Example commands:
 //
@@ -37,6 +42,41 @@ use tracing::info;
 // View the node config (private keys will be masked):
 //
 //   $ curl 'http://127.0.0.1:1337/node-config'
+//
+// The storage admin endpoints below are only served when `admin-tokens` are configured in the
+// db-checkpoint-config, each request needs a token granted the action.
+//
+// View the latest write stall and slow write indicators of the perpetual and checkpoint stores,
+// with a token granted `read-status`:
+//
+//   $ curl -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/store-health'
+//
+// View the epochs missing from the bucket of the db checkpoints uploaded by this node, with a
+// token granted `read-status`:
+//
+//   $ curl -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/backup-status'
+//
+// Scan the bucket and upload the missing db checkpoints right away rather than at the next
+// upload interval, with a token granted `trigger-upload`:
+//
+//   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/backup-upload'
+//
+// Pause the uploads of db checkpoints, e.g. during network maintenance, and resume them with a
+// pass for the epochs missed meanwhile, with a token granted `pause-uploads`:
+//
+//   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/backup-pause'
+//   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/backup-resume'
+//
+// Delete the db checkpoint of epoch 2 from the bucket, without uploading it again until the node
+// restarts, with a token granted `delete-remote-epochs`:
+//
+//   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/backup-delete?epoch=2'
+//
+// Pause the garbage collection of local db checkpoints and the remote retention, and resume
+// it, with a token granted `pause-gc`:
+//
+//   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/gc-pause'
+//   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/gc-resume'
 
 const LOGGING_ROUTE: &str = "/logging";
 const SET_BUFFER_STAKE_ROUTE: &str = "/set-override-buffer-stake";
@@ -44,21 +84,34 @@ const CLEAR_BUFFER_STAKE_ROUTE: &str = "/clear-override-buffer-stake";
 const FORCE_CLOSE_EPOCH: &str = "/force-close-epoch";
 const CAPABILITIES: &str = "/capabilities";
 const NODE_CONFIG: &str = "/node-config";
+const STORE_HEALTH: &str = "/store-health";
+const BACKUP_STATUS: &str = "/backup-status";
+const BACKUP_UPLOAD: &str = "/backup-upload";
+const BACKUP_PAUSE: &str = "/backup-pause";
+const BACKUP_RESUME: &str = "/backup-resume";
+const BACKUP_DELETE: &str = "/backup-delete";
+const GC_PAUSE: &str = "/gc-pause";
+const GC_RESUME: &str = "/gc-resume";
 
 struct AppState {
     node: Arc<SuiNode>,
     filter_handle: FilterHandle,
+    storage_admin_auth: StorageAdminAuth,
 }
 
 pub async fn run_admin_server(node: Arc<SuiNode>, port: u16, filter_handle: FilterHandle) {
     let filter = filter_handle.get().unwrap();
 
+    let storage_admin_auth =
+        StorageAdminAuth::new(node.config.db_checkpoint_config.admin_tokens.clone());
     let app_state = AppState {
         node,
         filter_handle,
+        storage_admin_auth,
     };
 
-    let app = Router::new()
+    let storage_admin_enabled = app_state.storage_admin_auth.is_enabled();
+    let mut app = Router::new()
         .route(LOGGING_ROUTE, get(get_filter))
         .route(CAPABILITIES, get(capabilities))
         .route(NODE_CONFIG, get(node_config))
@@ -71,8 +124,21 @@ pub async fn run_admin_server(node: Arc<SuiNode>, port: u16, filter_handle: Filt
             CLEAR_BUFFER_STAKE_ROUTE,
             post(clear_override_protocol_upgrade_buffer_stake),
         )
-        .route(FORCE_CLOSE_EPOCH, post(force_close_epoch))
-        .with_state(Arc::new(app_state));
+        .route(FORCE_CLOSE_EPOCH, post(force_close_epoch));
+    if storage_admin_enabled {
+        app = app
+            .route(STORE_HEALTH, get(store_health))
+            .route(BACKUP_STATUS, get(backup_status))
+            .route(BACKUP_UPLOAD, post(backup_upload))
+            .route(BACKUP_PAUSE, post(backup_pause))
+            .route(BACKUP_RESUME, post(backup_resume))
+            .route(BACKUP_DELETE, post(backup_delete))
+            .route(GC_PAUSE, post(gc_pause))
+            .route(GC_RESUME, post(gc_resume));
+    } else {
+        info!("No admin tokens configured, not serving storage admin endpoints");
+    }
+    let app = app.with_state(Arc::new(app_state));
 
     let socket_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
     info!(
@@ -126,6 +192,154 @@ async fn node_config(State(state): State<Arc<AppState>>) -> (StatusCode, String)
     (StatusCode::OK, format!("{:#?}\n", node_config))
 }
 
+/// Checks the admin token of a request to a storage admin endpoint
+fn authorize_storage_action(
+    state: &AppState,
+    headers: &HeaderMap,
+    action: StorageAdminAction,
+) -> Result<(), (StatusCode, String)> {
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    match state.storage_admin_auth.authorize(authorization, action) {
+        Ok(holder) => {
+            debug!(holder, ?action, "Storage admin action authorized");
+            Ok(())
+        }
+        Err(denied) => {
+            let status = match denied {
+                AccessDenied::Unauthenticated => StatusCode::UNAUTHORIZED,
+                AccessDenied::Forbidden => StatusCode::FORBIDDEN,
+            };
+            Err((status, format!("{denied}\n")))
+        }
+    }
+}
+
+/// Commander of the uploads of the node, `404` when the node doesn't upload db checkpoints
+fn backup_commander(state: &AppState) -> Result<&BackupCommander, (StatusCode, String)> {
+    state.node.backup_commander().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "db checkpoints are not uploaded by this node\n".to_string(),
+        )
+    })
+}
+
+async fn store_health(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<StoreHealth>>, (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::ReadStatus)?;
+    Ok(Json(state.node.store_health()))
+}
+
+async fn backup_status(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<BackupStatus>, (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::ReadStatus)?;
+    state.node.backup_status().map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "db checkpoints are not uploaded by this node\n".to_string(),
+        )
+    })
+}
+
+async fn backup_upload(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, String), (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::TriggerUpload)?;
+    let commander = backup_commander(&state)?;
+    commander
+        .upload_now()
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}\n")))?;
+    Ok((
+        StatusCode::OK,
+        "db checkpoint upload triggered\n".to_string(),
+    ))
+}
+
+async fn backup_pause(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, String), (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::PauseUploads)?;
+    let commander = backup_commander(&state)?;
+    commander.pause();
+    info!("Paused db checkpoint uploads");
+    Ok((StatusCode::OK, "db checkpoint uploads paused\n".to_string()))
+}
+
+async fn backup_resume(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, String), (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::PauseUploads)?;
+    let commander = backup_commander(&state)?;
+    commander
+        .resume()
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}\n")))?;
+    info!("Resumed db checkpoint uploads");
+    Ok((
+        StatusCode::OK,
+        "db checkpoint uploads resumed\n".to_string(),
+    ))
+}
+
+async fn backup_delete(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    epoch: Query<RemoteEpoch>,
+) -> Result<(StatusCode, String), (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::DeleteRemoteEpochs)?;
+    let Query(RemoteEpoch { epoch }) = epoch;
+    let commander = backup_commander(&state)?;
+    commander
+        .delete_remote_epoch(epoch)
+        .map_err(|err| (StatusCode::SERVICE_UNAVAILABLE, format!("{err}\n")))?;
+    info!("Requested delete of remote db checkpoint of epoch: {epoch}");
+    Ok((
+        StatusCode::OK,
+        format!("delete of remote db checkpoint of epoch '{epoch}' requested\n"),
+    ))
+}
+
+async fn gc_pause(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, String), (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::PauseGc)?;
+    let commander = backup_commander(&state)?;
+    commander.pause_gc();
+    info!("Paused garbage collection of db checkpoints");
+    Ok((
+        StatusCode::OK,
+        "db checkpoint garbage collection paused\n".to_string(),
+    ))
+}
+
+async fn gc_resume(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, String), (StatusCode, String)> {
+    authorize_storage_action(&state, &headers, StorageAdminAction::PauseGc)?;
+    let commander = backup_commander(&state)?;
+    commander.resume_gc();
+    info!("Resumed garbage collection of db checkpoints");
+    Ok((
+        StatusCode::OK,
+        "db checkpoint garbage collection resumed\n".to_string(),
+    ))
+}
+
+#[derive(Deserialize)]
+struct RemoteEpoch {
+    epoch: u32,
+}
+
 #[derive(Deserialize)]
 struct Epoch {
     epoch: u64,
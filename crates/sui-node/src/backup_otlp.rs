@@ -0,0 +1,669 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export of the spans and key metrics of the db checkpoint handler to the OpenTelemetry
+//! collector of `DBCheckpointConfig::otlp_exporter`, so that backups can be observed without
+//! scraping Prometheus. Every upload is a span of its own trace, from its start to its
+//! completion or failure, and garbage collections, local corruptions and regressions of the
+//! first missing epoch are spans without duration. Both are sent over OTLP/HTTP with the JSON
+//! encoding of the protocol. Only binaries built with the `backup-otlp` feature export them.
+
+use futures::Stream;
+use prometheus::Registry;
+use std::fmt::Display;
+use sui_config::node::DBCheckpointOtlpConfig;
+use sui_core::db_checkpoint_handler::events::BackupEvent;
+
+/// Exports the events of `events`, from `DBCheckpointHandler::subscribe`, as spans and the key
+/// metrics of the handler in `registries` to the collector of `config`, until the handler stops.
+/// `registries` holds the registry of the handler along with the ones of its replicas, from
+/// `DBCheckpointHandler::replica_registries`. Spans the collector doesn't accept are sent again
+/// with the next export.
+#[cfg(feature = "backup-otlp")]
+pub async fn export_backup_telemetry_to_otlp<E: Display>(
+    events: impl Stream<Item = Result<BackupEvent, E>> + Unpin,
+    registries: Vec<Registry>,
+    config: DBCheckpointOtlpConfig,
+    service_name: &'static str,
+    producer_name: Option<String>,
+) {
+    exporter::run(events, registries, config, service_name, producer_name).await
+}
+
+#[cfg(not(feature = "backup-otlp"))]
+pub async fn export_backup_telemetry_to_otlp<E: Display>(
+    _events: impl Stream<Item = Result<BackupEvent, E>> + Unpin,
+    _registries: Vec<Registry>,
+    config: DBCheckpointOtlpConfig,
+    _service_name: &'static str,
+    _producer_name: Option<String>,
+) {
+    tracing::warn!(
+        "Not exporting backup telemetry to {}, the binary was built without the backup-otlp feature",
+        config.endpoint
+    );
+}
+
+#[cfg(feature = "backup-otlp")]
+mod exporter {
+    use futures::{Stream, StreamExt};
+    use prometheus::proto::MetricType;
+    use prometheus::Registry;
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::fmt::Display;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use sui_config::node::DBCheckpointOtlpConfig;
+    use sui_core::db_checkpoint_handler::events::BackupEvent;
+    use tracing::{debug, warn};
+
+    /// Time allowed to the collector to respond
+    const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+    /// Spans kept while the collector is unreachable, the oldest ones are dropped past it
+    const MAX_PENDING_SPANS: usize = 2048;
+    const SCOPE_NAME: &str = "sui-db-checkpoint";
+    /// Metrics of the handler exported, the ones summing up the state of the backups
+    const EXPORTED_METRICS: &[&str] = &[
+        "first_missing_db_checkpoint_epoch",
+        "missing_db_checkpoint_epochs_count",
+        "first_missing_db_checkpoint_epoch_regressions_total",
+        "db_checkpoint_last_uploaded_epoch",
+        "db_checkpoint_bytes_uploaded_total",
+        "db_checkpoint_upload_failures_total",
+        "db_checkpoint_gc_deleted_epochs_total",
+        "db_checkpoint_local_corruption_detected_total",
+        "bitrot_detected_total",
+    ];
+    const SPAN_KIND_INTERNAL: u8 = 1;
+    const STATUS_CODE_OK: u8 = 1;
+    const STATUS_CODE_ERROR: u8 = 2;
+    const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    enum AnyValue {
+        StringValue(String),
+        /// 64 bit integers are strings in the JSON encoding
+        IntValue(String),
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    struct KeyValue {
+        key: String,
+        value: AnyValue,
+    }
+
+    impl KeyValue {
+        fn string(key: &str, value: impl Into<String>) -> Self {
+            KeyValue {
+                key: key.to_string(),
+                value: AnyValue::StringValue(value.into()),
+            }
+        }
+        fn int(key: &str, value: u64) -> Self {
+            KeyValue {
+                key: key.to_string(),
+                value: AnyValue::IntValue(value.to_string()),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    struct Resource {
+        attributes: Vec<KeyValue>,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    struct Scope {
+        name: String,
+        version: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    struct Status {
+        code: u8,
+        #[serde(skip_serializing_if = "String::is_empty")]
+        message: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    struct Span {
+        /// Hex encoded, 16 bytes
+        trace_id: String,
+        /// Hex encoded, 8 bytes
+        span_id: String,
+        name: String,
+        kind: u8,
+        start_time_unix_nano: String,
+        end_time_unix_nano: String,
+        attributes: Vec<KeyValue>,
+        status: Status,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ExportTraceServiceRequest {
+        resource_spans: Vec<ResourceSpans>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResourceSpans {
+        resource: Resource,
+        scope_spans: Vec<ScopeSpans>,
+    }
+
+    #[derive(Serialize)]
+    struct ScopeSpans {
+        scope: Scope,
+        spans: Vec<Span>,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    struct NumberDataPoint {
+        attributes: Vec<KeyValue>,
+        start_time_unix_nano: String,
+        time_unix_nano: String,
+        as_double: f64,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    struct Sum {
+        data_points: Vec<NumberDataPoint>,
+        aggregation_temporality: u8,
+        is_monotonic: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    struct Gauge {
+        data_points: Vec<NumberDataPoint>,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    enum MetricData {
+        Sum(Sum),
+        Gauge(Gauge),
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    struct Metric {
+        name: String,
+        description: String,
+        #[serde(flatten)]
+        data: MetricData,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ExportMetricsServiceRequest {
+        resource_metrics: Vec<ResourceMetrics>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResourceMetrics {
+        resource: Resource,
+        scope_metrics: Vec<ScopeMetrics>,
+    }
+
+    #[derive(Serialize)]
+    struct ScopeMetrics {
+        scope: Scope,
+        metrics: Vec<Metric>,
+    }
+
+    /// Upload started and not finished yet
+    struct OpenUpload {
+        trace_id: String,
+        span_id: String,
+        start: SystemTime,
+    }
+
+    /// Turns the events of the handler into finished spans
+    #[derive(Default)]
+    struct SpanRecorder {
+        uploads: HashMap<u32, OpenUpload>,
+        finished: Vec<Span>,
+    }
+
+    impl SpanRecorder {
+        fn record(&mut self, event: &BackupEvent, now: SystemTime) {
+            match event {
+                BackupEvent::EpochDiscovered { .. } => {}
+                BackupEvent::UploadStarted { epoch } => {
+                    self.uploads.insert(
+                        *epoch,
+                        OpenUpload {
+                            trace_id: trace_id(),
+                            span_id: span_id(),
+                            start: now,
+                        },
+                    );
+                }
+                BackupEvent::UploadCompleted { epoch, duration } => {
+                    let attributes = vec![
+                        KeyValue::int("db_checkpoint.epoch", *epoch as u64),
+                        KeyValue::int("db_checkpoint.duration_ms", duration.as_millis() as u64),
+                    ];
+                    // Spans of uploads started before the exporter are rebuilt from the duration
+                    let upload = self.uploads.remove(epoch).unwrap_or_else(|| OpenUpload {
+                        trace_id: trace_id(),
+                        span_id: span_id(),
+                        start: now.checked_sub(*duration).unwrap_or(now),
+                    });
+                    self.finish(upload, "db_checkpoint.upload", now, attributes, None);
+                }
+                BackupEvent::Error {
+                    epoch: Some(epoch),
+                    error,
+                } if self.uploads.contains_key(epoch) => {
+                    let upload = self.uploads.remove(epoch).unwrap();
+                    let attributes = vec![KeyValue::int("db_checkpoint.epoch", *epoch as u64)];
+                    self.finish(
+                        upload,
+                        "db_checkpoint.upload",
+                        now,
+                        attributes,
+                        Some(error.clone()),
+                    );
+                }
+                BackupEvent::Error { epoch, error } => {
+                    let attributes = epoch
+                        .iter()
+                        .map(|epoch| KeyValue::int("db_checkpoint.epoch", *epoch as u64))
+                        .collect();
+                    self.instant("db_checkpoint.error", now, attributes, Some(error.clone()));
+                }
+                BackupEvent::GcPerformed { epochs } => {
+                    let attributes = vec![
+                        KeyValue::int("db_checkpoint.num_epochs", epochs.len() as u64),
+                        KeyValue::string("db_checkpoint.epochs", format!("{epochs:?}")),
+                    ];
+                    self.instant("db_checkpoint.gc", now, attributes, None);
+                }
+                BackupEvent::LocalCorruptionDetected { epoch, path } => {
+                    let attributes = vec![
+                        KeyValue::int("db_checkpoint.epoch", *epoch as u64),
+                        KeyValue::string("db_checkpoint.path", path.as_str()),
+                    ];
+                    let error = format!("Local db checkpoint file {path} is corrupted");
+                    self.instant(
+                        "db_checkpoint.local_corruption",
+                        now,
+                        attributes,
+                        Some(error),
+                    );
+                }
+                BackupEvent::FirstMissingEpochRegressed { previous, current } => {
+                    let attributes = vec![
+                        KeyValue::int("db_checkpoint.previous", *previous as u64),
+                        KeyValue::int("db_checkpoint.current", *current as u64),
+                    ];
+                    let error =
+                        format!("First missing epoch regressed from {previous} to {current}");
+                    self.instant(
+                        "db_checkpoint.first_missing_epoch_regressed",
+                        now,
+                        attributes,
+                        Some(error),
+                    );
+                }
+            }
+        }
+        fn instant(
+            &mut self,
+            name: &str,
+            now: SystemTime,
+            attributes: Vec<KeyValue>,
+            error: Option<String>,
+        ) {
+            let span = OpenUpload {
+                trace_id: trace_id(),
+                span_id: span_id(),
+                start: now,
+            };
+            self.finish(span, name, now, attributes, error);
+        }
+        fn finish(
+            &mut self,
+            span: OpenUpload,
+            name: &str,
+            end: SystemTime,
+            attributes: Vec<KeyValue>,
+            error: Option<String>,
+        ) {
+            if self.finished.len() >= MAX_PENDING_SPANS {
+                self.finished.remove(0);
+            }
+            let status = match error {
+                Some(message) => Status {
+                    code: STATUS_CODE_ERROR,
+                    message,
+                },
+                None => Status {
+                    code: STATUS_CODE_OK,
+                    message: String::new(),
+                },
+            };
+            self.finished.push(Span {
+                trace_id: span.trace_id,
+                span_id: span.span_id,
+                name: name.to_string(),
+                kind: SPAN_KIND_INTERNAL,
+                start_time_unix_nano: unix_nanos(span.start),
+                end_time_unix_nano: unix_nanos(end),
+                attributes,
+                status,
+            });
+        }
+    }
+
+    pub(super) async fn run<E: Display>(
+        mut events: impl Stream<Item = Result<BackupEvent, E>> + Unpin,
+        registries: Vec<Registry>,
+        config: DBCheckpointOtlpConfig,
+        service_name: &'static str,
+        producer_name: Option<String>,
+    ) {
+        let client = match reqwest::Client::builder().timeout(EXPORT_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("Failed to create the client of the backup OTLP exporter: {err}");
+                return;
+            }
+        };
+        let mut attributes = vec![KeyValue::string("service.name", service_name)];
+        if let Some(producer_name) = producer_name {
+            attributes.push(KeyValue::string("service.instance.id", producer_name));
+        }
+        let exporter = Exporter {
+            client,
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            headers: config.headers.clone(),
+            resource: Resource { attributes },
+            start: SystemTime::now(),
+        };
+        let mut recorder = SpanRecorder::default();
+        let mut interval = tokio::time::interval(config.export_interval());
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(Ok(event)) => recorder.record(&event, SystemTime::now()),
+                    // The subscription fell behind a slow collector
+                    Some(Err(err)) => warn!("Skipped backup events for the OTLP exporter: {err}"),
+                    None => break,
+                },
+                _ = interval.tick() => {
+                    exporter.export_spans(&mut recorder).await;
+                    exporter.export_metrics(&registries).await;
+                }
+            }
+        }
+        exporter.export_spans(&mut recorder).await;
+        exporter.export_metrics(&registries).await;
+    }
+
+    struct Exporter {
+        client: reqwest::Client,
+        endpoint: String,
+        headers: std::collections::BTreeMap<String, String>,
+        resource: Resource,
+        /// Start of the cumulative sums
+        start: SystemTime,
+    }
+
+    impl Exporter {
+        fn scope() -> Scope {
+            Scope {
+                name: SCOPE_NAME.to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            }
+        }
+        /// Sends the finished spans of `recorder`, which keeps them for the next export when
+        /// the collector can't be reached
+        async fn export_spans(&self, recorder: &mut SpanRecorder) {
+            if recorder.finished.is_empty() {
+                return;
+            }
+            let request = ExportTraceServiceRequest {
+                resource_spans: vec![ResourceSpans {
+                    resource: self.resource.clone(),
+                    scope_spans: vec![ScopeSpans {
+                        scope: Self::scope(),
+                        spans: recorder.finished.clone(),
+                    }],
+                }],
+            };
+            if self.post("v1/traces", &request).await {
+                recorder.finished.clear();
+            }
+        }
+        async fn export_metrics(&self, registries: &[Registry]) {
+            let metrics = collect_metrics(registries, self.start, SystemTime::now());
+            if metrics.is_empty() {
+                return;
+            }
+            let request = ExportMetricsServiceRequest {
+                resource_metrics: vec![ResourceMetrics {
+                    resource: self.resource.clone(),
+                    scope_metrics: vec![ScopeMetrics {
+                        scope: Self::scope(),
+                        metrics,
+                    }],
+                }],
+            };
+            self.post("v1/metrics", &request).await;
+        }
+        /// Whether the collector accepted `request`
+        async fn post(&self, path: &str, request: &impl Serialize) -> bool {
+            let url = format!("{}/{path}", self.endpoint);
+            let mut builder = self.client.post(url.as_str()).json(request);
+            for (name, value) in self.headers.iter() {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+            match builder
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+            {
+                Ok(_) => {
+                    debug!(url, "Exported backup telemetry");
+                    true
+                }
+                Err(err) => {
+                    warn!(url, "Failed to export backup telemetry: {err}");
+                    false
+                }
+            }
+        }
+    }
+
+    /// Counters of `EXPORTED_METRICS` in `registries` as cumulative sums since `start`, and
+    /// gauges as gauges. The data points of a metric registered in several registries, such as
+    /// the ones of the replicas told apart by their `destination` label, make a single metric.
+    fn collect_metrics(registries: &[Registry], start: SystemTime, now: SystemTime) -> Vec<Metric> {
+        let mut metrics: Vec<Metric> = vec![];
+        for family in registries.iter().flat_map(Registry::gather) {
+            if !EXPORTED_METRICS.contains(&family.get_name()) {
+                continue;
+            }
+            let data_points = family
+                .get_metric()
+                .iter()
+                .map(|metric| NumberDataPoint {
+                    attributes: metric
+                        .get_label()
+                        .iter()
+                        .map(|label| KeyValue::string(label.get_name(), label.get_value()))
+                        .collect(),
+                    start_time_unix_nano: unix_nanos(start),
+                    time_unix_nano: unix_nanos(now),
+                    as_double: match family.get_field_type() {
+                        MetricType::COUNTER => metric.get_counter().get_value(),
+                        _ => metric.get_gauge().get_value(),
+                    },
+                })
+                .collect();
+            let data = match family.get_field_type() {
+                MetricType::COUNTER => MetricData::Sum(Sum {
+                    data_points,
+                    aggregation_temporality: AGGREGATION_TEMPORALITY_CUMULATIVE,
+                    is_monotonic: true,
+                }),
+                MetricType::GAUGE => MetricData::Gauge(Gauge { data_points }),
+                _ => continue,
+            };
+            if let Some(metric) = metrics
+                .iter_mut()
+                .find(|metric| metric.name == family.get_name())
+            {
+                match (&mut metric.data, data) {
+                    (MetricData::Sum(sum), MetricData::Sum(other)) => {
+                        sum.data_points.extend(other.data_points)
+                    }
+                    (MetricData::Gauge(gauge), MetricData::Gauge(other)) => {
+                        gauge.data_points.extend(other.data_points)
+                    }
+                    (_, data) => warn!(
+                        name = family.get_name(),
+                        ?data,
+                        "Not exporting metric registered with different types"
+                    ),
+                }
+                continue;
+            }
+            metrics.push(Metric {
+                name: family.get_name().to_string(),
+                description: family.get_help().to_string(),
+                data,
+            });
+        }
+        metrics
+    }
+
+    fn unix_nanos(time: SystemTime) -> String {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_string()
+    }
+
+    /// Random id of a trace, 16 bytes hex encoded
+    fn trace_id() -> String {
+        hex::encode(rand::random::<[u8; 16]>())
+    }
+
+    /// Random id of a span, 8 bytes hex encoded
+    fn span_id() -> String {
+        hex::encode(rand::random::<[u8; 8]>())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{
+            collect_metrics, AnyValue, MetricData, SpanRecorder, STATUS_CODE_ERROR, STATUS_CODE_OK,
+        };
+        use prometheus::{IntCounter, IntGauge, Opts, Registry};
+        use std::time::{Duration, SystemTime};
+        use sui_core::db_checkpoint_handler::events::BackupEvent;
+
+        #[test]
+        fn test_span_recorder() {
+            let mut recorder = SpanRecorder::default();
+            let start = SystemTime::now();
+            recorder.record(&BackupEvent::UploadStarted { epoch: 1 }, start);
+            recorder.record(&BackupEvent::UploadStarted { epoch: 2 }, start);
+            assert!(recorder.finished.is_empty());
+            recorder.record(
+                &BackupEvent::UploadCompleted {
+                    epoch: 1,
+                    duration: Duration::from_secs(3),
+                },
+                start + Duration::from_secs(3),
+            );
+            recorder.record(
+                &BackupEvent::Error {
+                    epoch: Some(2),
+                    error: "Remote store unreachable".to_string(),
+                },
+                start + Duration::from_secs(4),
+            );
+            recorder.record(
+                &BackupEvent::GcPerformed { epochs: vec![1] },
+                start + Duration::from_secs(5),
+            );
+            assert!(recorder.uploads.is_empty());
+            let spans = &recorder.finished;
+            assert_eq!(spans.len(), 3);
+            assert_eq!(spans[0].name, "db_checkpoint.upload");
+            assert_eq!(spans[0].status.code, STATUS_CODE_OK);
+            let elapsed: u128 = spans[0].end_time_unix_nano.parse::<u128>().unwrap()
+                - spans[0].start_time_unix_nano.parse::<u128>().unwrap();
+            assert_eq!(elapsed, Duration::from_secs(3).as_nanos());
+            assert_eq!(spans[0].trace_id.len(), 32);
+            assert_eq!(spans[0].span_id.len(), 16);
+            assert_ne!(spans[0].trace_id, spans[1].trace_id);
+            assert_eq!(spans[1].status.code, STATUS_CODE_ERROR);
+            assert_eq!(spans[1].status.message, "Remote store unreachable");
+            assert_eq!(spans[2].name, "db_checkpoint.gc");
+            assert_eq!(spans[2].start_time_unix_nano, spans[2].end_time_unix_nano);
+        }
+
+        #[test]
+        fn test_collect_metrics() {
+            let registry = Registry::new();
+            let bytes = IntCounter::new("db_checkpoint_bytes_uploaded_total", "Bytes").unwrap();
+            let epoch = IntGauge::new("db_checkpoint_last_uploaded_epoch", "Epoch").unwrap();
+            let other = IntCounter::new("other_total", "Other").unwrap();
+            registry.register(Box::new(bytes.clone())).unwrap();
+            registry.register(Box::new(epoch.clone())).unwrap();
+            registry.register(Box::new(other)).unwrap();
+            bytes.inc_by(42);
+            epoch.set(7);
+
+            // Replicas register the same metrics, labelled with their destination
+            let replica_registry = Registry::new();
+            let replica_bytes = IntCounter::with_opts(
+                Opts::new("db_checkpoint_bytes_uploaded_total", "Bytes")
+                    .const_label("destination", "replica"),
+            )
+            .unwrap();
+            replica_registry
+                .register(Box::new(replica_bytes.clone()))
+                .unwrap();
+            replica_bytes.inc_by(5);
+
+            let metrics = collect_metrics(
+                &[registry, replica_registry],
+                SystemTime::now(),
+                SystemTime::now(),
+            );
+            assert_eq!(metrics.len(), 2);
+            let MetricData::Sum(sum) = &metrics[0].data else {
+                panic!("Counter exported as {:?}", metrics[0].data);
+            };
+            assert!(sum.is_monotonic);
+            assert_eq!(sum.data_points.len(), 2);
+            assert_eq!(sum.data_points[0].as_double, 42.0);
+            assert!(sum.data_points[0].attributes.is_empty());
+            assert_eq!(sum.data_points[1].as_double, 5.0);
+            assert_eq!(
+                sum.data_points[1].attributes,
+                vec![super::KeyValue::string("destination", "replica")]
+            );
+            let MetricData::Gauge(gauge) = &metrics[1].data else {
+                panic!("Gauge exported as {:?}", metrics[1].data);
+            };
+            assert_eq!(gauge.data_points[0].as_double, 7.0);
+            assert_eq!(
+                super::KeyValue::int("epoch", 7).value,
+                AnyValue::IntValue("7".to_string())
+            );
+        }
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Notifies the webhooks of `DBCheckpointConfig::webhooks` of the events of the db checkpoint
+//! handler and of its replicas, with an HTTP POST of a JSON `BackupNotification` per event.
+//! Every webhook is posted to from a task of its own, so that a slow endpoint neither delays
+//! the others nor makes the subscription fall behind.
+
+use anyhow::{anyhow, Result};
+use futures::{Stream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Url};
+use std::fmt::Display;
+use std::time::Duration;
+use sui_config::node::DBCheckpointWebhookConfig;
+use sui_core::db_checkpoint_handler::events::{BackupEvent, BackupNotification};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::{debug, warn};
+
+/// Time allowed to a webhook to respond
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Notifications queued for a webhook while it responds, later ones are dropped
+const WEBHOOK_QUEUE_CAPACITY: usize = 256;
+
+/// Endpoint of a webhook, validated from its config
+struct Webhook {
+    config: DBCheckpointWebhookConfig,
+    url: Url,
+    headers: HeaderMap,
+}
+
+impl Webhook {
+    fn new(config: DBCheckpointWebhookConfig) -> Result<Self> {
+        let url = Url::parse(&config.url)?;
+        if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+            return Err(anyhow!("Not an http or https URL"));
+        }
+        let mut headers = HeaderMap::new();
+        for (name, value) in config.headers.iter() {
+            let mut value = HeaderValue::from_str(value)
+                .map_err(|err| anyhow!("Invalid value of header {name}: {err}"))?;
+            // Kept out of the logs, the headers usually carry credentials
+            value.set_sensitive(true);
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, value);
+        }
+        Ok(Webhook {
+            config,
+            url,
+            headers,
+        })
+    }
+}
+
+/// Sends the events of `events`, from `DBCheckpointHandler::subscribe_with_replicas`, to
+/// `webhooks` until the handler stops. Webhooks with an invalid URL or header are ignored.
+/// Failed requests are logged and not retried.
+pub async fn forward_backup_events_to_webhooks<E: Display>(
+    mut events: impl Stream<Item = (Option<String>, Result<BackupEvent, E>)> + Unpin,
+    webhooks: Vec<DBCheckpointWebhookConfig>,
+    producer_name: Option<String>,
+) {
+    let client = match Client::builder().timeout(WEBHOOK_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(err) => {
+            warn!("Failed to create the client of backup webhooks: {err}");
+            return;
+        }
+    };
+    let mut queues = vec![];
+    for config in webhooks {
+        let url = config.url.clone();
+        let webhook = match Webhook::new(config) {
+            Ok(webhook) => webhook,
+            Err(err) => {
+                warn!(%url, "Ignoring invalid backup webhook: {err}");
+                continue;
+            }
+        };
+        let (sender, receiver) = mpsc::channel(WEBHOOK_QUEUE_CAPACITY);
+        queues.push((webhook.config.clone(), sender));
+        tokio::spawn(post_notifications(client.clone(), webhook, receiver));
+    }
+    if queues.is_empty() {
+        return;
+    }
+    while let Some((destination, event)) = events.next().await {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("Skipped backup events for webhooks: {err}");
+                continue;
+            }
+        };
+        let Some(mut notification) = event.notification(producer_name.clone()) else {
+            continue;
+        };
+        notification.destination = destination;
+        for (config, sender) in queues
+            .iter()
+            .filter(|(config, _)| config.notifies(notification.event))
+        {
+            if let Err(TrySendError::Full(notification)) = sender.try_send(notification.clone()) {
+                warn!(
+                    url = %config.url,
+                    ?notification,
+                    "Dropped backup webhook, the endpoint is falling behind"
+                );
+            }
+        }
+    }
+}
+
+/// Posts the notifications queued for `webhook` one at a time, until the forwarder stops
+async fn post_notifications(
+    client: Client,
+    webhook: Webhook,
+    mut notifications: mpsc::Receiver<BackupNotification>,
+) {
+    while let Some(notification) = notifications.recv().await {
+        match client
+            .post(webhook.url.clone())
+            .headers(webhook.headers.clone())
+            .json(&notification)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => debug!(url = %webhook.url, ?notification, "Sent backup webhook"),
+            Err(err) => warn!(
+                url = %webhook.url,
+                ?notification,
+                "Failed to send backup webhook: {err}"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::forward_backup_events_to_webhooks;
+    use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+    use sui_config::node::{BackupNotificationKind, DBCheckpointWebhookConfig};
+    use sui_core::db_checkpoint_handler::events::BackupEvent;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_webhooks_notified() {
+        let (sender, mut requests) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/backups",
+            post(move |headers: HeaderMap, body: String| {
+                let sender = sender.clone();
+                async move {
+                    let authorization = headers
+                        .get(AUTHORIZATION)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string);
+                    sender.send((authorization, body)).unwrap();
+                    StatusCode::OK
+                }
+            }),
+        );
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        let webhooks = vec![
+            DBCheckpointWebhookConfig {
+                url: format!("http://{address}/backups"),
+                events: vec![BackupNotificationKind::UploadCompleted],
+                headers: BTreeMap::from([(
+                    "Authorization".to_string(),
+                    "Bearer secret".to_string(),
+                )]),
+            },
+            DBCheckpointWebhookConfig {
+                url: format!("ftp://{address}/backups"),
+                ..Default::default()
+            },
+        ];
+        let events = futures::stream::iter(vec![
+            (None, Ok::<_, String>(BackupEvent::UploadStarted { epoch: 3 })),
+            (
+                Some("replica".to_string()),
+                Ok(BackupEvent::UploadCompleted {
+                    epoch: 3,
+                    duration: Duration::from_secs(2),
+                }),
+            ),
+        ]);
+        forward_backup_events_to_webhooks(events, webhooks, Some("node-a".to_string())).await;
+
+        let (authorization, body) = requests.recv().await.unwrap();
+        assert_eq!(authorization.as_deref(), Some("Bearer secret"));
+        assert_eq!(
+            body,
+            r#"{"event":"upload-completed","producer_name":"node-a","destination":"replica","epoch":3,"duration_ms":2000}"#
+        );
+        // Neither the filtered event nor the webhook without an http URL is sent
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(requests.try_recv().is_err());
+    }
+}
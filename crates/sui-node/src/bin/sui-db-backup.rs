@@ -0,0 +1,382 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs the db checkpoint upload handler of a node out-of-process, so that crashes or OOMs in
+//! backup code can't take down the node. The node keeps taking db checkpoints at epoch end and
+//! this binary uploads them from the node's checkpoint directory. Set `run-out-of-process` in
+//! the `db-checkpoint-config` of the node so that it doesn't upload them as well.
+//!
+//! Resource limits configured in `resource-limits` apply to the whole process, including the
+//! background threads of RocksDB used for compaction. The shared write rate limit can't be
+//! shared with the node from here and only caps the writes of pruning in this process.
+//!
+//! The process serves its own `/metrics` and `/health` endpoints on `--metrics-port`, so it can
+//! be scraped independently of the node.
+//!
+//! With `--tenants-config-path`, one process uploads the db checkpoints of several nodes on
+//! the same host. Each tenant names the config file of its node and optionally a key prefix,
+//! so that the nodes can share a bucket. The metrics of each tenant carry a `tenant` label.
+//! Process wide resource limits then come from the tenants config, while the limits of each
+//! node only apply to its own upload worker and pruning writes.
+//!
+//! The state archive writer reads checkpoints from the live db of the node and keeps running
+//! in-process.
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use mysten_metrics::RegistryService;
+use prometheus::Registry;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use sui_config::node::BackupResourceLimits;
+use sui_config::{Config, NodeConfig};
+use sui_core::db_checkpoint_handler::manifest::BackupProducer;
+use sui_core::db_checkpoint_handler::resource_guard::{
+    apply_process_limits, apply_thread_priorities, set_shared_write_rate_limit,
+};
+use sui_core::db_checkpoint_handler::staging::StagingArea;
+use sui_core::db_checkpoint_handler::DBCheckpointHandler;
+use sui_core::task_handle::TaskHandle;
+use sui_node::backup_otlp::export_backup_telemetry_to_otlp;
+use sui_node::backup_webhooks::forward_backup_events_to_webhooks;
+use sui_node::metrics::start_standalone_metrics_server;
+use sui_types::digests::ChainIdentifier;
+use tracing::{info, warn};
+use typed_store::rocks::default_db_options;
+
+#[derive(Parser)]
+#[clap(rename_all = "kebab-case")]
+#[clap(name = env!("CARGO_BIN_NAME"))]
+struct Args {
+    /// Config file of the node whose db checkpoints are uploaded
+    #[clap(
+        long,
+        required_unless_present = "tenants-config-path",
+        conflicts_with = "tenants-config-path"
+    )]
+    pub config_path: Option<PathBuf>,
+
+    /// Config file listing the nodes whose db checkpoints are uploaded by this process
+    #[clap(long)]
+    pub tenants_config_path: Option<PathBuf>,
+
+    /// Port serving the /metrics and /health endpoints of the backup process
+    #[clap(long, default_value_t = 9185)]
+    pub metrics_port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct BackupTenantsConfig {
+    tenants: Vec<BackupTenantConfig>,
+    /// Limits of the whole process, shared by every tenant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource_limits: Option<BackupResourceLimits>,
+}
+
+impl Config for BackupTenantsConfig {}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct BackupTenantConfig {
+    /// Name of the tenant, set as the `tenant` label of its metrics
+    name: String,
+    /// Config file of the node whose db checkpoints are uploaded
+    config_path: PathBuf,
+    /// Key prefix of the uploads of the node in its bucket, overriding the one of the object
+    /// store config of the node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_prefix: Option<String>,
+}
+
+struct Tenant {
+    /// None when the process uploads for a single node
+    name: Option<String>,
+    config: NodeConfig,
+}
+
+impl Tenant {
+    fn checkpoint_path(&self) -> PathBuf {
+        self.config
+            .db_checkpoint_config
+            .checkpoint_path
+            .clone()
+            .unwrap_or_else(|| self.config.db_checkpoint_path())
+    }
+
+    /// Constant labels of the metrics of the tenant, also set on the ones of its replicas
+    fn metric_labels(&self) -> HashMap<String, String> {
+        self.name
+            .iter()
+            .map(|name| ("tenant".to_string(), name.clone()))
+            .collect()
+    }
+}
+
+fn load_tenants(args: &Args) -> Result<(Vec<Tenant>, Option<BackupResourceLimits>)> {
+    let Some(tenants_config_path) = &args.tenants_config_path else {
+        let config_path = args
+            .config_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("Either --config-path or --tenants-config-path is needed"))?;
+        let config = NodeConfig::load(config_path)?;
+        let resource_limits = config.db_checkpoint_config.resource_limits.clone();
+        return Ok((vec![Tenant { name: None, config }], resource_limits));
+    };
+    let tenants_config = BackupTenantsConfig::load(tenants_config_path)?;
+    if tenants_config.tenants.is_empty() {
+        return Err(anyhow!("No tenants in {}", tenants_config_path.display()));
+    }
+    let mut names = HashSet::new();
+    let mut checkpoint_paths = HashSet::new();
+    let mut destinations = HashSet::new();
+    let mut tenants = vec![];
+    for tenant_config in tenants_config.tenants {
+        if !names.insert(tenant_config.name.clone()) {
+            return Err(anyhow!("Duplicate tenant: {}", tenant_config.name));
+        }
+        let mut config = NodeConfig::load(&tenant_config.config_path)?;
+        let db_checkpoint_config = &mut config.db_checkpoint_config;
+        if let Some(object_store_config) = db_checkpoint_config.object_store_config.as_mut() {
+            if tenant_config.key_prefix.is_some() {
+                object_store_config.key_prefix = tenant_config.key_prefix.clone();
+            }
+            // Two tenants uploading to the same place would overwrite each other's epochs
+            let destination = format!(
+                "{:?}/{:?}/{:?}/{:?}",
+                object_store_config.object_store,
+                object_store_config.bucket,
+                object_store_config.directory,
+                object_store_config.key_prefix
+            );
+            if !destinations.insert(destination) {
+                return Err(anyhow!(
+                    "Tenant: {} uploads to the same bucket and key prefix as another tenant",
+                    tenant_config.name
+                ));
+            }
+        }
+        if db_checkpoint_config
+            .resource_limits
+            .as_ref()
+            .map_or(false, |limits| limits.has_process_limits())
+        {
+            warn!(
+                "Memory and open file limits of tenant: {} are ignored, set them in the tenants config",
+                tenant_config.name
+            );
+        }
+        let tenant = Tenant {
+            name: Some(tenant_config.name),
+            config,
+        };
+        if !checkpoint_paths.insert(tenant.checkpoint_path()) {
+            return Err(anyhow!(
+                "Tenant: {} uploads from the same checkpoint directory as another tenant",
+                tenant.name.unwrap_or_default()
+            ));
+        }
+        tenants.push(tenant);
+    }
+    Ok((tenants, tenants_config.resource_limits))
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let (tenants, resource_limits) = load_tenants(&args)?;
+    let (_guard, _filter_handle) = telemetry_subscribers::TelemetryConfig::new()
+        .with_env()
+        .init();
+
+    // Applied before the runtime starts so that every thread spawned afterwards inherits them
+    if let Some(limits) = &resource_limits {
+        apply_process_limits(limits)?;
+        apply_thread_priorities(limits)?;
+    }
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(tenants, args.metrics_port))
+}
+
+fn start_tenant(
+    tenant: &Tenant,
+    registry: &Registry,
+    registry_service: &RegistryService,
+) -> Result<TaskHandle> {
+    let config = &tenant.config;
+    let db_checkpoint_config = &config.db_checkpoint_config;
+    let checkpoint_path = tenant.checkpoint_path();
+    let object_store_config = db_checkpoint_config
+        .object_store_config
+        .as_ref()
+        .ok_or_else(|| anyhow!("No object store configured in db-checkpoint-config"))?;
+    if !db_checkpoint_config.run_out_of_process.unwrap_or(false) {
+        warn!("run-out-of-process is not set, db checkpoints are also uploaded by the node");
+    }
+
+    let chain_identifier = ChainIdentifier::from(*config.genesis()?.checkpoint().digest());
+    let mut handler = DBCheckpointHandler::new_with_metric_labels(
+        &checkpoint_path,
+        object_store_config,
+        db_checkpoint_config,
+        config.indirect_objects_threshold,
+        config.authority_store_pruning_config,
+        registry,
+        tenant.metric_labels(),
+    )?
+    .with_chain_identifier(chain_identifier)
+    .with_producer(BackupProducer::from_node_config(config))
+    .with_staging_area(StagingArea::from_node_config(config));
+    for registry in handler.replica_registries() {
+        registry_service.add(registry);
+    }
+    if !db_checkpoint_config.webhooks.is_empty() {
+        tokio::spawn(forward_backup_events_to_webhooks(
+            handler.subscribe_with_replicas(),
+            db_checkpoint_config.webhooks.clone(),
+            db_checkpoint_config.producer_name.clone(),
+        ));
+    }
+    if let Some(otlp_exporter) = &db_checkpoint_config.otlp_exporter {
+        let mut registries = vec![registry.clone()];
+        registries.extend(handler.replica_registries());
+        tokio::spawn(export_backup_telemetry_to_otlp(
+            handler.subscribe(),
+            registries,
+            otlp_exporter.clone(),
+            "sui-db-backup",
+            db_checkpoint_config.producer_name.clone(),
+        ));
+    }
+    if let Some(limits) = &db_checkpoint_config.resource_limits {
+        let mut pruning_db_options = default_db_options().options;
+        if set_shared_write_rate_limit(&mut pruning_db_options, limits) {
+            handler = handler.with_pruning_db_options(pruning_db_options);
+        }
+    }
+    info!(
+        "Uploading db checkpoints from {}",
+        checkpoint_path.display()
+    );
+    Ok(handler.start())
+}
+
+async fn run(tenants: Vec<Tenant>, metrics_port: u16) -> Result<()> {
+    let registry_service = RegistryService::new(Registry::new());
+    let mut handles = vec![];
+    for tenant in tenants.iter() {
+        let registry = match &tenant.name {
+            Some(name) => {
+                let registry = Registry::new_custom(None, Some(tenant.metric_labels()))?;
+                registry_service.add(registry.clone());
+                info!("Starting tenant: {name}");
+                registry
+            }
+            None => registry_service.default_registry(),
+        };
+        // Dropping a handle stops the upload loop of its tenant, which is then no longer running
+        handles.push(start_tenant(tenant, &registry, &registry_service)?);
+    }
+    let handles = Arc::new(handles);
+    let metrics_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), metrics_port);
+    start_standalone_metrics_server(
+        metrics_address,
+        registry_service,
+        Arc::new(move || handles.iter().all(|handle| handle.is_running())),
+    )?;
+    info!("Started metrics and health endpoints at {metrics_address}");
+
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_tenants, Args};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    const NODE_CONFIG: &str = include_str!("../../../sui-config/data/fullnode-template.yaml");
+
+    /// Writes the config of a node uploading from `checkpoint_path` to the local `bucket`
+    fn write_node_config(dir: &Path, name: &str, checkpoint_path: &str, bucket: &str) -> PathBuf {
+        let path = dir.join(format!("{name}.yaml"));
+        let config = format!(
+            "{NODE_CONFIG}
+db-checkpoint-config:
+  checkpoint-path: {}
+  object-store-config:
+    object-store: File
+    directory: {}
+",
+            dir.join(checkpoint_path).display(),
+            dir.join(bucket).display()
+        );
+        fs::write(&path, config).unwrap();
+        path
+    }
+
+    fn load(dir: &Path, tenants: &[(&str, &PathBuf, Option<&str>)]) -> anyhow::Result<Vec<String>> {
+        let mut config = if tenants.is_empty() {
+            "tenants: []\n".to_string()
+        } else {
+            "tenants:\n".to_string()
+        };
+        for (name, config_path, key_prefix) in tenants {
+            config += &format!(
+                "  - name: {name}\n    config-path: {}\n",
+                config_path.display()
+            );
+            if let Some(key_prefix) = key_prefix {
+                config += &format!("    key-prefix: {key_prefix}\n");
+            }
+        }
+        let tenants_config_path = dir.join("tenants.yaml");
+        fs::write(&tenants_config_path, config)?;
+        let args = Args {
+            config_path: None,
+            tenants_config_path: Some(tenants_config_path),
+            metrics_port: 0,
+        };
+        let (tenants, _) = load_tenants(&args)?;
+        Ok(tenants
+            .into_iter()
+            .map(|tenant| {
+                let object_store_config = tenant.config.db_checkpoint_config.object_store_config;
+                format!(
+                    "{}:{}",
+                    tenant.name.unwrap_or_default(),
+                    object_store_config
+                        .and_then(|config| config.key_prefix)
+                        .unwrap_or_default()
+                )
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_load_tenants() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let node_a = write_node_config(dir, "node-a", "checkpoints-a", "bucket");
+        let node_b = write_node_config(dir, "node-b", "checkpoints-b", "bucket");
+        let node_c = write_node_config(dir, "node-c", "checkpoints-a", "other");
+
+        // Tenants sharing a bucket under their own key prefixes
+        assert_eq!(
+            load(dir, &[("a", &node_a, Some("a")), ("b", &node_b, Some("b"))]).unwrap(),
+            vec!["a:a".to_string(), "b:b".to_string()]
+        );
+        assert!(load(dir, &[]).is_err());
+        assert!(load(dir, &[("a", &node_a, Some("a")), ("a", &node_b, Some("b"))]).is_err());
+        // Same bucket without key prefixes
+        assert!(load(dir, &[("a", &node_a, None), ("b", &node_b, None)]).is_err());
+        // Same checkpoint directory
+        assert!(load(dir, &[("a", &node_a, None), ("c", &node_c, None)]).is_err());
+    }
+}
@@ -7,7 +7,7 @@ use std::path::PathBuf;
 #[cfg(msim)]
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anemo::Network;
 use anemo_tower::callback::CallbackLayer;
@@ -30,8 +30,7 @@ use sui_types::sui_system_state::SuiSystemState;
 use tap::tap::TapFallible;
 use tokio::runtime::Handle;
 use tokio::sync::broadcast;
-use tokio::sync::oneshot;
-use tokio::sync::{watch, Mutex};
+use tokio::sync::{mpsc, watch, Mutex};
 use tokio::task::JoinHandle;
 use tower::ServiceBuilder;
 use tracing::{debug, warn};
@@ -64,6 +63,12 @@ use sui_core::consensus_adapter::{
 };
 use sui_core::consensus_handler::ConsensusHandler;
 use sui_core::consensus_validator::{SuiTxValidator, SuiTxValidatorMetrics};
+use sui_core::db_checkpoint_handler::backup_watermark::BackupWatermark;
+use sui_core::db_checkpoint_handler::commands::BackupCommander;
+use sui_core::db_checkpoint_handler::manifest::BackupProducer;
+use sui_core::db_checkpoint_handler::resource_guard::set_shared_write_rate_limit;
+use sui_core::db_checkpoint_handler::staging::StagingArea;
+use sui_core::db_checkpoint_handler::status::{BackupStatus, BackupStatusHandle};
 use sui_core::db_checkpoint_handler::DBCheckpointHandler;
 use sui_core::epoch::committee_store::CommitteeStore;
 use sui_core::epoch::data_removal::EpochDataRemover;
@@ -74,6 +79,8 @@ use sui_core::narwhal_manager::{NarwhalConfiguration, NarwhalManager, NarwhalMan
 use sui_core::signature_verifier::SignatureVerifierMetrics;
 use sui_core::state_accumulator::StateAccumulator;
 use sui_core::storage::RocksDbStore;
+use sui_core::store_health::{StoreHealth, StoreHealthMonitor, STORE_HEALTH_SAMPLE_INTERVAL};
+use sui_core::task_handle::TaskHandle;
 use sui_core::transaction_orchestrator::TransactiondOrchestrator;
 use sui_core::{
     authority::{AuthorityState, AuthorityStore},
@@ -107,9 +114,13 @@ use sui_types::sui_system_state::SuiSystemStateTrait;
 use typed_store::rocks::default_db_options;
 use typed_store::DBMetrics;
 
+use crate::backup_otlp::export_backup_telemetry_to_otlp;
+use crate::backup_webhooks::forward_backup_events_to_webhooks;
 use crate::metrics::GrpcMetrics;
 
 pub mod admin;
+pub mod backup_otlp;
+pub mod backup_webhooks;
 mod handle;
 pub mod metrics;
 
@@ -153,12 +164,17 @@ pub struct SuiNode {
     /// Broadcast channel to notify state-sync for new validator peers.
     trusted_peer_change_tx: watch::Sender<TrustedPeerChangeEvent>,
 
-    _db_checkpoint_handle: Option<oneshot::Sender<()>>,
+    _db_checkpoint_handle: Option<TaskHandle>,
+    backup_status: Option<BackupStatusHandle>,
+    backup_commander: Option<BackupCommander>,
 
     #[cfg(msim)]
     sim_state: SimState,
 
     _state_archive_handle: Option<broadcast::Sender<()>>,
+
+    store_health: Arc<StoreHealthMonitor>,
+    _store_health_handle: TaskHandle,
 }
 
 impl fmt::Debug for SuiNode {
@@ -264,11 +280,25 @@ impl SuiNode {
             None,
         ));
 
-        let perpetual_options = default_db_options().optimize_db_for_write_throughput(4);
+        let mut perpetual_options = default_db_options().optimize_db_for_write_throughput(4);
+        let shares_write_rate_limit = config
+            .db_checkpoint_config
+            .resource_limits
+            .as_ref()
+            .map_or(false, |limits| {
+                set_shared_write_rate_limit(&mut perpetual_options.options, limits)
+            });
+        let store_health = StoreHealthMonitor::new(&prometheus_registry);
+        let open_start = Instant::now();
         let perpetual_tables = Arc::new(AuthorityPerpetualTables::open(
             &config.db_path().join("store"),
-            Some(perpetual_options.options),
+            Some(perpetual_options.options.clone()),
         ));
+        // The node runs without health samples of a store it can't monitor
+        if let Err(err) = store_health.add_perpetual_store(&perpetual_tables, open_start.elapsed())
+        {
+            warn!("Failed to monitor health of the perpetual store: {err:?}");
+        }
         let is_genesis = perpetual_tables
             .database_is_empty()
             .expect("Database read should not fail at init.");
@@ -335,7 +365,13 @@ impl SuiNode {
             );
         }
 
+        let open_start = Instant::now();
         let checkpoint_store = CheckpointStore::new(&config.db_path().join("checkpoints"));
+        if let Err(err) = store_health.add_checkpoint_store(&checkpoint_store, open_start.elapsed())
+        {
+            warn!("Failed to monitor health of the checkpoint store: {err:?}");
+        }
+        let store_health_handle = store_health.start(STORE_HEALTH_SAMPLE_INTERVAL);
         checkpoint_store.insert_genesis_checkpoint(
             genesis.checkpoint(),
             genesis.checkpoint_contents().clone(),
@@ -414,28 +450,101 @@ impl SuiNode {
         } else {
             config.db_checkpoint_config.clone()
         };
+        let db_checkpoint_config = if db_checkpoint_config.verify_only()
+            && db_checkpoint_config.perform_db_checkpoints_at_epoch_end
+        {
+            warn!("verify-only is set, not taking db checkpoints at epoch end");
+            DBCheckpointConfig {
+                perform_db_checkpoints_at_epoch_end: false,
+                ..db_checkpoint_config
+            }
+        } else {
+            db_checkpoint_config
+        };
 
-        let db_checkpoint_handle = match db_checkpoint_config
+        let backup_status = BackupStatusHandle::default();
+        let db_checkpoint_tasks = match db_checkpoint_config
             .checkpoint_path
             .as_ref()
             .zip(db_checkpoint_config.object_store_config.as_ref())
+            .filter(|_| !db_checkpoint_config.run_out_of_process.unwrap_or(false))
         {
             Some((path, object_store_config)) => {
+                if db_checkpoint_config
+                    .resource_limits
+                    .as_ref()
+                    .map_or(false, |limits| limits.has_process_limits())
+                {
+                    warn!("Backup memory and open file limits only apply to sui-db-backup, ignoring them");
+                }
                 let handler = DBCheckpointHandler::new(
                     path,
                     object_store_config,
-                    60,
-                    db_checkpoint_config
-                        .prune_and_compact_before_upload
-                        .unwrap_or(true),
+                    &db_checkpoint_config,
                     config.indirect_objects_threshold,
                     config.authority_store_pruning_config,
                     &prometheus_registry,
                 )?;
-                Some(handler.start())
+                for registry in handler.replica_registries() {
+                    registry_service.add(registry);
+                }
+                let (backup_telemetry_tx, backup_telemetry_rx) = mpsc::channel(1000);
+                spawn_monitored_task!(sui_telemetry::forward_backup_telemetry_events(
+                    backup_telemetry_rx,
+                    chain_identifier.to_string(),
+                    is_validator,
+                ));
+                let handler = if shares_write_rate_limit {
+                    handler.with_pruning_db_options(perpetual_options.options.clone())
+                } else {
+                    handler
+                };
+                // Only confirmed by a handler which uploads, pruning would never resume otherwise
+                let backup_watermark = (db_checkpoint_config.use_for_pruning_watermark()
+                    && db_checkpoint_config.perform_db_checkpoints_at_epoch_end
+                    && !db_checkpoint_config.verify_only())
+                .then(BackupWatermark::default);
+                let handler = match backup_watermark.as_ref() {
+                    Some(backup_watermark) => {
+                        handler.with_backup_watermark(backup_watermark.clone())
+                    }
+                    None => handler,
+                };
+                let handler = handler
+                    .with_chain_identifier(chain_identifier)
+                    .with_producer(BackupProducer::from_node_config(config))
+                    .with_staging_area(StagingArea::from_node_config(config))
+                    .with_telemetry(backup_telemetry_tx)
+                    .with_status(backup_status.clone());
+                if !db_checkpoint_config.webhooks.is_empty() {
+                    spawn_monitored_task!(forward_backup_events_to_webhooks(
+                        handler.subscribe_with_replicas(),
+                        db_checkpoint_config.webhooks.clone(),
+                        db_checkpoint_config.producer_name.clone(),
+                    ));
+                }
+                if let Some(otlp_exporter) = &db_checkpoint_config.otlp_exporter {
+                    let mut registries = vec![prometheus_registry.clone()];
+                    registries.extend(handler.replica_registries());
+                    spawn_monitored_task!(export_backup_telemetry_to_otlp(
+                        handler.subscribe(),
+                        registries,
+                        otlp_exporter.clone(),
+                        "sui-node",
+                        db_checkpoint_config.producer_name.clone(),
+                    ));
+                }
+                let backup_commander = handler.commander();
+                Some((handler.start(), backup_commander, backup_watermark))
             }
             None => None,
         };
+        let (db_checkpoint_handle, backup_commander, backup_watermark) = match db_checkpoint_tasks {
+            Some((handle, commander, watermark)) => (Some(handle), Some(commander), watermark),
+            None => (None, None, None),
+        };
+        // Only known when db checkpoints are uploaded by the node itself
+        let backup_status = db_checkpoint_handle.as_ref().map(|_| backup_status);
 
         let state = AuthorityState::new(
             config.protocol_public_key(),
@@ -456,6 +565,7 @@ impl SuiNode {
             config.indirect_objects_threshold,
             config.state_debug_dump_config.clone(),
             archive_readers,
+            backup_watermark,
         )
         .await;
         // ensure genesis txn was executed
@@ -580,6 +690,8 @@ impl SuiNode {
             trusted_peer_change_tx,
 
             _db_checkpoint_handle: db_checkpoint_handle,
+            backup_status,
+            backup_commander,
 
             #[cfg(msim)]
             sim_state: SimState {
@@ -589,6 +701,9 @@ impl SuiNode {
             },
 
             _state_archive_handle: state_archive_handle,
+
+            store_health,
+            _store_health_handle: store_health_handle,
         };
 
         info!("SuiNode started!");
@@ -622,6 +737,20 @@ impl SuiNode {
         self.config.db_checkpoint_path()
     }
 
+    pub fn store_health(&self) -> Vec<StoreHealth> {
+        self.store_health.latest()
+    }
+
+    /// Status of the uploads of db checkpoints, `None` when the node doesn't upload them
+    pub fn backup_status(&self) -> Option<BackupStatus> {
+        self.backup_status.as_ref().map(BackupStatusHandle::status)
+    }
+
+    /// Sends commands to the upload of db checkpoints, `None` when the node doesn't upload them
+    pub fn backup_commander(&self) -> Option<&BackupCommander> {
+        self.backup_commander.as_ref()
+    }
+
     // Init reconfig process by starting to reject user certs
     pub async fn close_epoch(&self, epoch_store: &Arc<AuthorityPerEpochStore>) -> SuiResult {
         info!("close_epoch (current epoch = {})", epoch_store.epoch());
@@ -13,7 +13,7 @@ use sui_protocol_config::SupportedProtocolVersions;
 use sui_telemetry::send_telemetry_event;
 use sui_types::multiaddr::Multiaddr;
 use tokio::time::sleep;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 const GIT_REVISION: &str = {
     if let Some(revision) = option_env!("GIT_REVISION") {
@@ -74,6 +74,9 @@ fn main() {
         .init();
 
     info!("Sui Node version: {VERSION}");
+    for conflict in config.retention_conflicts() {
+        warn!("Pruning config is incompatible with backup retention: {conflict}");
+    }
     info!(
         "Supported protocol versions: {:?}",
         config.supported_protocol_versions
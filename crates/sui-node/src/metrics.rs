@@ -8,13 +8,16 @@ use axum::{
 };
 
 use mysten_network::metrics::MetricsCallbackProvider;
+use prometheus::proto::MetricFamily;
 use prometheus::{
     register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
     register_int_gauge_vec_with_registry, Encoder, HistogramVec, IntCounterVec, IntGaugeVec,
     Registry, TextEncoder, PROTOBUF_FORMAT,
 };
 
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use sui_network::tonic::Code;
 
@@ -22,6 +25,10 @@ use mysten_metrics::RegistryService;
 use tracing::{error, warn};
 
 const METRICS_ROUTE: &str = "/metrics";
+const HEALTH_ROUTE: &str = "/health";
+
+/// Liveness check of a process served on the health route
+pub type HealthCheck = Arc<dyn Fn() -> bool + Send + Sync>;
 
 // Creates a new http server that has as a sole purpose to expose
 // and endpoint that prometheus agent can use to poll for the metrics.
@@ -52,6 +59,38 @@ pub fn start_prometheus_server(addr: SocketAddr) -> RegistryService {
     registry_service
 }
 
+// Starts a http server for processes running next to the node, such as sui-db-backup, which
+// exposes their metrics along with a health route answering 200 while `health_check` holds.
+// Fails when `addr` can't be bound, e.g. when the port is already taken.
+pub fn start_standalone_metrics_server(
+    addr: SocketAddr,
+    registry_service: RegistryService,
+    health_check: HealthCheck,
+) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route(METRICS_ROUTE, get(standalone_metrics))
+        .route(HEALTH_ROUTE, get(health))
+        .layer(Extension(registry_service))
+        .layer(Extension(health_check));
+
+    let server = axum::Server::try_bind(&addr)
+        .map_err(|err| anyhow::anyhow!("Failed to bind metrics server to {addr}: {err}"))?;
+    tokio::spawn(async move {
+        if let Err(err) = server.serve(app.into_make_service()).await {
+            error!("Metrics server failed: {err}");
+        }
+    });
+    Ok(())
+}
+
+async fn health(Extension(health_check): Extension<HealthCheck>) -> (StatusCode, &'static str) {
+    if health_check() {
+        (StatusCode::OK, "up")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "down")
+    }
+}
+
 async fn metrics(Extension(registry_service): Extension<RegistryService>) -> (StatusCode, String) {
     let metrics_families = registry_service.gather_all();
     match TextEncoder.encode_to_string(&metrics_families) {
@@ -63,6 +102,40 @@ async fn metrics(Extension(registry_service): Extension<RegistryService>) -> (St
     }
 }
 
+// Serves the metrics of a standalone process, whose registries may register the same metric
+// under different constant labels, e.g. one registry per tenant of sui-db-backup
+async fn standalone_metrics(
+    Extension(registry_service): Extension<RegistryService>,
+) -> (StatusCode, String) {
+    let metrics_families = merge_metric_families(registry_service.gather_all());
+    match TextEncoder.encode_to_string(&metrics_families) {
+        Ok(metrics) => (StatusCode::OK, metrics),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unable to encode metrics: {error}"),
+        ),
+    }
+}
+
+/// Merges the families of the same name gathered from several registries into one, as the
+/// exposition format allows a single family per name
+pub fn merge_metric_families(families: Vec<MetricFamily>) -> Vec<MetricFamily> {
+    let mut merged: BTreeMap<String, MetricFamily> = BTreeMap::new();
+    for mut family in families {
+        match merged.get_mut(family.get_name()) {
+            Some(existing) => {
+                for metric in family.take_metric().into_iter() {
+                    existing.mut_metric().push(metric);
+                }
+            }
+            None => {
+                merged.insert(family.get_name().to_string(), family);
+            }
+        }
+    }
+    merged.into_values().collect()
+}
+
 pub struct MetricsPushClient {
     certificate: std::sync::Arc<sui_tls::SelfSignedCertificate>,
     client: reqwest::Client,
@@ -254,8 +327,9 @@ impl MetricsCallbackProvider for GrpcMetrics {
 
 #[cfg(test)]
 mod tests {
-    use crate::metrics::start_prometheus_server;
+    use crate::metrics::{merge_metric_families, start_prometheus_server};
     use prometheus::{IntCounter, Registry};
+    use std::collections::HashMap;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
     #[tokio::test]
@@ -319,6 +393,26 @@ sui_counter_2 1"
         ));
     }
 
+    #[test]
+    fn test_merge_metric_families() {
+        let mut families = vec![];
+        for tenant in ["node-1", "node-2"] {
+            let labels = HashMap::from([("tenant".to_string(), tenant.to_string())]);
+            let registry = Registry::new_custom(None, Some(labels)).unwrap();
+            let counter = IntCounter::new("uploads", "a sample counter").unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            let other =
+                IntCounter::new(format!("{}_only", tenant.replace('-', "_")), "other").unwrap();
+            registry.register(Box::new(other)).unwrap();
+            counter.inc();
+            families.extend(registry.gather());
+        }
+        let merged = merge_metric_families(families);
+        let names: Vec<&str> = merged.iter().map(|family| family.get_name()).collect();
+        assert_eq!(names, vec!["node_1_only", "node_2_only", "uploads"]);
+        assert_eq!(merged[2].get_metric().len(), 2);
+    }
+
     async fn get_metrics(port: u16) -> String {
         let client = reqwest::Client::new();
         let response = client
@@ -0,0 +1,417 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use flate2::read::MultiGzDecoder;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::{
+    DynObjectStore, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
+};
+use std::fmt;
+use std::io::Read;
+use std::ops::Range;
+use std::sync::Arc;
+use tokio::io::AsyncWrite;
+
+/// Suffix of the keys of objects compressed with zstd, as written by the compression of db
+/// checkpoint uploads
+pub const ZSTD_SUFFIX: &str = ".zst";
+/// Suffix of the keys of objects compressed with gzip, e.g. by tools copying backups around
+pub const GZIP_SUFFIX: &str = ".gz";
+
+/// Largest zstd frame header, magic number included
+const ZSTD_MAX_FRAME_HEADER_SIZE: usize = 18;
+/// Magic number of skippable zstd frames, any value of the low 4 bits
+const ZSTD_SKIPPABLE_MAGIC: u32 = 0x184D2A50;
+const ZSTD_BLOCK_HEADER_SIZE: usize = 3;
+/// Type of the blocks holding a single byte repeated
+const ZSTD_BLOCK_TYPE_RLE: u32 = 1;
+const ZSTD_CHECKSUM_SIZE: usize = 4;
+
+/// Format of a compressed copy, told by the suffix of its key
+#[derive(Debug, Clone, Copy)]
+enum Compression {
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    /// Formats the copies of a missing object are looked for in, in order
+    const ALL: [Compression; 2] = [Compression::Zstd, Compression::Gzip];
+
+    fn path(self, location: &Path) -> Path {
+        let suffix = match self {
+            Compression::Zstd => ZSTD_SUFFIX,
+            Compression::Gzip => GZIP_SUFFIX,
+        };
+        Path::from(format!("{location}{suffix}"))
+    }
+
+    fn decoder<'a>(self, compressed: &'a [u8]) -> std::io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(compressed)?),
+            Compression::Gzip => Box::new(MultiGzDecoder::new(compressed)),
+        })
+    }
+
+    fn decompress(self, compressed: &[u8]) -> Result<Bytes> {
+        let mut decompressed = vec![];
+        self.decoder(compressed)
+            .and_then(|mut decoder| decoder.read_to_end(&mut decompressed))
+            .map_err(|err| generic(err.into()))?;
+        Ok(Bytes::from(decompressed))
+    }
+}
+
+/// Little endian integer of the `N` bytes of `bytes` at `offset`, an error if `bytes` is
+/// truncated
+fn read_le<const N: usize>(bytes: &[u8], offset: usize, location: &Path) -> Result<u32> {
+    let field = bytes
+        .get(offset..offset + N)
+        .ok_or_else(|| generic(format!("Truncated zstd frame in {location}").into()))?;
+    Ok(field
+        .iter()
+        .rev()
+        .fold(0, |value, byte| (value << 8) | u32::from(*byte)))
+}
+
+/// Object store which serves a read of a missing object from its compressed copy under the
+/// same key with a `.zst` or `.gz` suffix, decompressed, so that tooling can read a local copy
+/// of a compressed backup as if it wasn't. Objects present under their own key, including the
+/// compressed copies themselves, are served as stored, and listings are left unchanged.
+#[derive(Debug)]
+pub struct DecompressingStore {
+    inner: Arc<DynObjectStore>,
+}
+
+impl DecompressingStore {
+    pub fn new(inner: Arc<DynObjectStore>) -> Self {
+        DecompressingStore { inner }
+    }
+
+    /// Format and metadata of the compressed copy of `location`, `None` if there is none
+    async fn compressed_copy(&self, location: &Path) -> Result<Option<(Compression, ObjectMeta)>> {
+        for compression in Compression::ALL {
+            match self.inner.head(&compression.path(location)).await {
+                Ok(meta) => return Ok(Some((compression, meta))),
+                Err(object_store::Error::NotFound { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decompressed contents of the compressed copy of `location`, `None` if there is none
+    async fn decompressed(&self, location: &Path) -> Result<Option<Bytes>> {
+        let Some((compression, meta)) = self.compressed_copy(location).await? else {
+            return Ok(None);
+        };
+        let bytes = self.inner.get(&meta.location).await?.bytes().await?;
+        tokio::task::spawn_blocking(move || compression.decompress(&bytes))
+            .await
+            .map_err(|err| generic(err.into()))?
+            .map(Some)
+    }
+
+    /// Size of the contents of the zstd compressed copy `meta`, summed from the headers of its
+    /// frames. The frames are walked with ranged reads of their headers and of the headers of
+    /// their blocks, without downloading nor decompressing their contents. `None` if a frame
+    /// doesn't record the size of its contents.
+    async fn zstd_content_size(&self, meta: &ObjectMeta) -> Result<Option<usize>> {
+        let location = &meta.location;
+        let mut size = 0;
+        let mut offset = 0;
+        while offset < meta.size {
+            let header_end = meta.size.min(offset + ZSTD_MAX_FRAME_HEADER_SIZE);
+            let header = self.inner.get_range(location, offset..header_end).await?;
+            if read_le::<4>(&header, 0, location)? & !0xF == ZSTD_SKIPPABLE_MAGIC {
+                offset += 8 + read_le::<4>(&header, 4, location)? as usize;
+                continue;
+            }
+            let content_size = match zstd::zstd_safe::get_frame_content_size(&header) {
+                Ok(Some(content_size)) => content_size as usize,
+                Ok(None) => return Ok(None),
+                Err(_) => {
+                    return Err(generic(
+                        format!("Invalid zstd frame header in {location}").into(),
+                    ))
+                }
+            };
+            let descriptor = read_le::<1>(&header, 4, location)?;
+            let single_segment = descriptor & 0x20 != 0;
+            let content_size_field = match descriptor >> 6 {
+                0 => usize::from(single_segment),
+                1 => 2,
+                2 => 4,
+                _ => 8,
+            };
+            let dictionary_id_field = [0, 1, 2, 4][(descriptor & 0x3) as usize];
+            let mut position = offset
+                + 5
+                + usize::from(!single_segment)
+                + dictionary_id_field
+                + content_size_field;
+            loop {
+                let block_end = position + ZSTD_BLOCK_HEADER_SIZE;
+                let block = self.inner.get_range(location, position..block_end).await?;
+                let block_header = read_le::<ZSTD_BLOCK_HEADER_SIZE>(&block, 0, location)?;
+                position = block_end
+                    + match (block_header >> 1) & 0x3 {
+                        ZSTD_BLOCK_TYPE_RLE => 1,
+                        _ => (block_header >> 3) as usize,
+                    };
+                if block_header & 0x1 != 0 {
+                    break;
+                }
+            }
+            if descriptor & 0x4 != 0 {
+                position += ZSTD_CHECKSUM_SIZE;
+            }
+            if position > meta.size {
+                return Err(generic(
+                    format!("Truncated zstd frame in {location}").into(),
+                ));
+            }
+            size += content_size;
+            offset = position;
+        }
+        Ok(Some(size))
+    }
+
+    /// Contents of `location`, decompressed from its compressed copy if it is missing
+    async fn contents(&self, location: &Path) -> Result<Bytes> {
+        match self.inner.get(location).await {
+            Err(object_store::Error::NotFound { path, source }) => {
+                match self.decompressed(location).await? {
+                    Some(bytes) => Ok(bytes),
+                    None => Err(object_store::Error::NotFound { path, source }),
+                }
+            }
+            result => result?.bytes().await,
+        }
+    }
+}
+
+fn generic(source: Box<dyn std::error::Error + Send + Sync>) -> object_store::Error {
+    object_store::Error::Generic {
+        store: "Decompressing",
+        source,
+    }
+}
+
+fn slice(bytes: &Bytes, range: Range<usize>) -> Result<Bytes> {
+    if range.start > range.end || range.end > bytes.len() {
+        return Err(generic(
+            format!(
+                "Range {range:?} out of bounds of decompressed object of {} bytes",
+                bytes.len()
+            )
+            .into(),
+        ));
+    }
+    Ok(bytes.slice(range))
+}
+
+impl fmt::Display for DecompressingStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Decompressing({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for DecompressingStore {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.inner.put(location, bytes).await
+    }
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.inner.put_multipart(location).await
+    }
+    async fn abort_multipart(&self, location: &Path, multipart_id: &MultipartId) -> Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+    async fn get(&self, location: &Path) -> Result<GetResult> {
+        match self.inner.get(location).await {
+            Err(object_store::Error::NotFound { path, source }) => {
+                match self.decompressed(location).await? {
+                    Some(bytes) => Ok(GetResult::Stream(
+                        futures::stream::once(async move { Ok(bytes) }).boxed(),
+                    )),
+                    None => Err(object_store::Error::NotFound { path, source }),
+                }
+            }
+            result => result,
+        }
+    }
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        match self.inner.get_range(location, range.clone()).await {
+            Err(object_store::Error::NotFound { .. }) => {
+                slice(&self.contents(location).await?, range)
+            }
+            result => result,
+        }
+    }
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        match self.inner.get_ranges(location, ranges).await {
+            Err(object_store::Error::NotFound { .. }) => {
+                let bytes = self.contents(location).await?;
+                ranges
+                    .iter()
+                    .map(|range| slice(&bytes, range.clone()))
+                    .collect()
+            }
+            result => result,
+        }
+    }
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        match self.inner.head(location).await {
+            Err(object_store::Error::NotFound { path, source }) => {
+                let Some((compression, meta)) = self.compressed_copy(location).await? else {
+                    return Err(object_store::Error::NotFound { path, source });
+                };
+                // Only sized from metadata, counting the size by decompressing would download
+                // the copy once more on the next read
+                let size = match compression {
+                    Compression::Zstd => self.zstd_content_size(&meta).await?,
+                    Compression::Gzip => None,
+                };
+                let size = size.ok_or_else(|| {
+                    generic(
+                        format!(
+                            "Size of {location} isn't recorded in its compressed copy {}, read it instead",
+                            meta.location
+                        )
+                        .into(),
+                    )
+                })?;
+                Ok(ObjectMeta {
+                    location: location.clone(),
+                    size,
+                    ..meta
+                })
+            }
+            result => result,
+        }
+    }
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.inner.delete(location).await
+    }
+    async fn list(&self, prefix: Option<&Path>) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        self.inner.list(prefix).await
+    }
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy(from, to).await
+    }
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.rename(from, to).await
+    }
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.rename_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use bytes::Bytes;
+    use flate2::write::GzEncoder;
+    use object_store::path::Path;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    pub async fn test_decompressing_store() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        fs::write(
+            dir.path().join("file1.zst"),
+            zstd::encode_all(&b"Lorem ipsum"[..], 1)?,
+        )?;
+        fs::write(dir.path().join("file2"), b"dolor")?;
+        let mut gzip = GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(b"sit amet")?;
+        fs::write(dir.path().join("file4.gz"), gzip.finish()?)?;
+        // Frames recording their content size, sized from their headers alone
+        let mut frames = zstd::bulk::compress(b"Lorem ", 1)?;
+        frames.extend(zstd::bulk::compress(b"ipsum", 1)?);
+        fs::write(dir.path().join("file5.zst"), frames)?;
+        // Incompressible contents spanning several blocks, followed by the checksum of the frame
+        let mut state: u64 = 1;
+        let contents: Vec<u8> = (0..300_000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (state >> 56) as u8
+            })
+            .collect();
+        let mut compressor = zstd::bulk::Compressor::new(1)?;
+        compressor.include_checksum(true)?;
+        let mut frames = compressor.compress(&contents)?;
+        frames.extend(zstd::bulk::compress(b"Lorem ipsum", 1)?);
+        fs::write(dir.path().join("file6.zst"), frames)?;
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(dir.path().to_path_buf()),
+            decompress_reads: true,
+            read_only: true,
+            ..Default::default()
+        }
+        .make()?;
+        let file1 = Path::from("file1");
+        assert_eq!(
+            store.get(&file1).await?.bytes().await?,
+            Bytes::from_static(b"Lorem ipsum")
+        );
+        // Streamed frames don't record their content size
+        assert!(store.head(&file1).await.is_err());
+        assert_eq!(
+            store.get_range(&file1, 6..11).await?,
+            Bytes::from_static(b"ipsum")
+        );
+        assert!(store.get_range(&file1, 6..12).await.is_err());
+        // Compressed copies and uncompressed objects are served as stored
+        assert_ne!(
+            store.get(&Path::from("file1.zst")).await?.bytes().await?,
+            Bytes::from_static(b"Lorem ipsum")
+        );
+        assert_eq!(
+            store.get(&Path::from("file2")).await?.bytes().await?,
+            Bytes::from_static(b"dolor")
+        );
+        assert!(matches!(
+            store.get(&Path::from("file3")).await,
+            Err(object_store::Error::NotFound { .. })
+        ));
+        assert!(store.head(&Path::from("file3")).await.is_err());
+        let file4 = Path::from("file4");
+        assert_eq!(
+            store.get(&file4).await?.bytes().await?,
+            Bytes::from_static(b"sit amet")
+        );
+        assert!(store.head(&file4).await.is_err());
+        let file5 = Path::from("file5");
+        assert_eq!(store.head(&file5).await?.size, 11);
+        assert_eq!(store.head(&file5).await?.location, file5);
+        let file6 = Path::from("file6");
+        assert_eq!(store.head(&file6).await?.size, contents.len() + 11);
+        assert_eq!(
+            store.get_range(&file6, 0..10).await?,
+            Bytes::copy_from_slice(&contents[..10])
+        );
+        assert_eq!(
+            store.get(&file5).await?.bytes().await?,
+            Bytes::from_static(b"Lorem ipsum")
+        );
+        Ok(())
+    }
+}
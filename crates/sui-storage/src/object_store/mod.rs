@@ -3,14 +3,22 @@
 
 use anyhow::{anyhow, Context};
 use clap::*;
+use decompressing::DecompressingStore;
 use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
 use object_store::DynObjectStore;
+use prefixed::PrefixedStore;
+use read_only::ReadOnlyStore;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::info;
 
+pub mod decompressing;
+pub mod prefixed;
+pub mod read_only;
+pub mod retention;
 pub mod util;
 
 /// Object-store type.
@@ -82,6 +90,22 @@ pub struct ObjectStoreConfig {
     #[serde(default = "default_object_store_connection_limit")]
     #[clap(long, default_value_t = 20)]
     pub object_store_connection_limit: usize,
+    /// Reject every write and delete to the object store, e.g. when pointing restore or
+    /// verification tooling at the canonical backup bucket
+    #[serde(default)]
+    #[clap(long, default_value_t = false)]
+    pub read_only: bool,
+    /// Keep every object under this key prefix of the bucket or directory, e.g. for several
+    /// nodes backing up to the same bucket
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[clap(long)]
+    pub key_prefix: Option<String>,
+    /// Read objects missing from the object store from their zstd compressed copy with a
+    /// `.zst` suffix, or their gzip compressed copy with a `.gz` suffix, e.g. when pointing local
+    /// tooling at a copy of a backup uploaded with compression
+    #[serde(default)]
+    #[clap(long, default_value_t = false)]
+    pub decompress_reads: bool,
 }
 
 fn default_object_store_connection_limit() -> usize {
@@ -177,12 +201,34 @@ impl ObjectStoreConfig {
         )))
     }
     pub fn make(&self) -> Result<Arc<DynObjectStore>, anyhow::Error> {
-        match &self.object_store {
+        let store = match &self.object_store {
             Some(ObjectStoreType::File) => self.new_local_fs(),
             Some(ObjectStoreType::S3) => self.new_s3(),
             Some(ObjectStoreType::GCS) => self.new_gcs(),
             Some(ObjectStoreType::Azure) => self.new_azure(),
             _ => Err(anyhow!("At least one storage backend should be provided")),
+        }?;
+        let store: Arc<DynObjectStore> = match self.key_prefix.as_deref().map(Path::from) {
+            Some(prefix) if prefix.parts().next().is_some() => {
+                Arc::new(PrefixedStore::new(store, prefix))
+            }
+            _ => store,
+        };
+        let store: Arc<DynObjectStore> = if self.decompress_reads {
+            Arc::new(DecompressingStore::new(store))
+        } else {
+            store
+        };
+        if self.read_only {
+            return Ok(Arc::new(ReadOnlyStore::new(store)));
+        }
+        Ok(store)
+    }
+    /// Same config, rejecting writes and deletes
+    pub fn read_only(&self) -> Self {
+        ObjectStoreConfig {
+            read_only: true,
+            ..self.clone()
         }
     }
 }
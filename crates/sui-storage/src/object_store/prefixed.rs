@@ -0,0 +1,195 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path;
+use object_store::{
+    DynObjectStore, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
+};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+use tokio::io::AsyncWrite;
+
+/// Object store which keeps every object of the wrapped store under a key prefix, so that
+/// several writers can share a bucket without seeing each other's objects
+#[derive(Debug)]
+pub struct PrefixedStore {
+    inner: Arc<DynObjectStore>,
+    prefix: Path,
+}
+
+impl PrefixedStore {
+    pub fn new(inner: Arc<DynObjectStore>, prefix: Path) -> Self {
+        PrefixedStore { inner, prefix }
+    }
+
+    fn full_path(&self, location: &Path) -> Path {
+        Path::from_iter(self.prefix.parts().chain(location.parts()))
+    }
+}
+
+fn strip_prefix(prefix: &Path, path: Path) -> Path {
+    match path.prefix_match(prefix) {
+        Some(parts) => Path::from_iter(parts),
+        None => path,
+    }
+}
+
+fn strip_meta_prefix(prefix: &Path, meta: ObjectMeta) -> ObjectMeta {
+    ObjectMeta {
+        location: strip_prefix(prefix, meta.location.clone()),
+        ..meta
+    }
+}
+
+impl fmt::Display for PrefixedStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Prefixed({}, {})", self.prefix, self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for PrefixedStore {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<()> {
+        self.inner.put(&self.full_path(location), bytes).await
+    }
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.inner.put_multipart(&self.full_path(location)).await
+    }
+    async fn abort_multipart(&self, location: &Path, multipart_id: &MultipartId) -> Result<()> {
+        self.inner
+            .abort_multipart(&self.full_path(location), multipart_id)
+            .await
+    }
+    async fn get(&self, location: &Path) -> Result<GetResult> {
+        self.inner.get(&self.full_path(location)).await
+    }
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.inner.get_range(&self.full_path(location), range).await
+    }
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.inner
+            .get_ranges(&self.full_path(location), ranges)
+            .await
+    }
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        let meta = self.inner.head(&self.full_path(location)).await?;
+        Ok(strip_meta_prefix(&self.prefix, meta))
+    }
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.inner.delete(&self.full_path(location)).await
+    }
+    async fn list(&self, prefix: Option<&Path>) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        let full_prefix = match prefix {
+            Some(prefix) => self.full_path(prefix),
+            None => self.prefix.clone(),
+        };
+        let stream = self.inner.list(Some(&full_prefix)).await?;
+        Ok(stream
+            .map_ok(|meta| strip_meta_prefix(&self.prefix, meta))
+            .boxed())
+    }
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let full_prefix = match prefix {
+            Some(prefix) => self.full_path(prefix),
+            None => self.prefix.clone(),
+        };
+        let result = self.inner.list_with_delimiter(Some(&full_prefix)).await?;
+        Ok(ListResult {
+            common_prefixes: result
+                .common_prefixes
+                .into_iter()
+                .map(|path| strip_prefix(&self.prefix, path))
+                .collect(),
+            objects: result
+                .objects
+                .into_iter()
+                .map(|meta| strip_meta_prefix(&self.prefix, meta))
+                .collect(),
+        })
+    }
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner
+            .copy(&self.full_path(from), &self.full_path(to))
+            .await
+    }
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner
+            .rename(&self.full_path(from), &self.full_path(to))
+            .await
+    }
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner
+            .copy_if_not_exists(&self.full_path(from), &self.full_path(to))
+            .await
+    }
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner
+            .rename_if_not_exists(&self.full_path(from), &self.full_path(to))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object_store::util::list_epoch_dirs;
+    use crate::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use bytes::Bytes;
+    use futures::TryStreamExt;
+    use object_store::path::Path;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    pub async fn test_prefixed_store() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        fs::create_dir_all(dir.path().join("epoch_0"))?;
+        fs::write(dir.path().join("epoch_0").join("file1"), b"foreign")?;
+        let config = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(dir.path().to_path_buf()),
+            key_prefix: Some("tenants/node-1".to_string()),
+            ..Default::default()
+        };
+        let store = config.make()?;
+        let file1 = Path::from("epoch_3/file1");
+        store
+            .put(&file1, Bytes::from_static(b"Lorem ipsum"))
+            .await?;
+        assert_eq!(
+            fs::read(dir.path().join("tenants/node-1/epoch_3/file1"))?,
+            b"Lorem ipsum"
+        );
+        assert_eq!(
+            store.get(&file1).await?.bytes().await?,
+            Bytes::from_static(b"Lorem ipsum")
+        );
+        // Listings only see objects under the prefix, keyed relative to it
+        let listed: Vec<Path> = store
+            .list(None)
+            .await?
+            .map_ok(|meta| meta.location)
+            .try_collect()
+            .await?;
+        assert_eq!(listed, vec![file1.clone()]);
+        assert_eq!(store.head(&file1).await?.location, file1);
+        assert_eq!(
+            list_epoch_dirs(store.clone(), None)
+                .await?
+                .into_keys()
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        store.delete(&file1).await?;
+        assert!(!dir.path().join("tenants/node-1/epoch_3/file1").exists());
+        assert!(dir.path().join("epoch_0").join("file1").exists());
+        Ok(())
+    }
+}
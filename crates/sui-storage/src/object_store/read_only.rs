@@ -0,0 +1,128 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use object_store::path::Path;
+use object_store::{
+    DynObjectStore, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, Result,
+};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+use tokio::io::AsyncWrite;
+
+/// Object store which serves reads from the wrapped store and rejects every write, copy and
+/// delete, so that tooling pointed at a canonical bucket can never mutate it
+#[derive(Debug)]
+pub struct ReadOnlyStore {
+    inner: Arc<DynObjectStore>,
+}
+
+impl ReadOnlyStore {
+    pub fn new(inner: Arc<DynObjectStore>) -> Self {
+        ReadOnlyStore { inner }
+    }
+}
+
+fn rejected(operation: &str, location: &Path) -> object_store::Error {
+    object_store::Error::Generic {
+        store: "ReadOnly",
+        source: format!("Refusing to {operation} {location} in read-only object store").into(),
+    }
+}
+
+impl fmt::Display for ReadOnlyStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ReadOnly({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ReadOnlyStore {
+    async fn put(&self, location: &Path, _bytes: Bytes) -> Result<()> {
+        Err(rejected("put", location))
+    }
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        Err(rejected("put", location))
+    }
+    async fn abort_multipart(&self, location: &Path, _multipart_id: &MultipartId) -> Result<()> {
+        Err(rejected("abort upload of", location))
+    }
+    async fn get(&self, location: &Path) -> Result<GetResult> {
+        self.inner.get(location).await
+    }
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.inner.get_range(location, range).await
+    }
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.inner.get_ranges(location, ranges).await
+    }
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+    async fn delete(&self, location: &Path) -> Result<()> {
+        Err(rejected("delete", location))
+    }
+    async fn list(&self, prefix: Option<&Path>) -> Result<BoxStream<'_, Result<ObjectMeta>>> {
+        self.inner.list(prefix).await
+    }
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+    async fn copy(&self, _from: &Path, to: &Path) -> Result<()> {
+        Err(rejected("copy to", to))
+    }
+    async fn rename(&self, from: &Path, _to: &Path) -> Result<()> {
+        Err(rejected("rename", from))
+    }
+    async fn copy_if_not_exists(&self, _from: &Path, to: &Path) -> Result<()> {
+        Err(rejected("copy to", to))
+    }
+    async fn rename_if_not_exists(&self, from: &Path, _to: &Path) -> Result<()> {
+        Err(rejected("rename", from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use bytes::Bytes;
+    use object_store::path::Path;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    pub async fn test_read_only_store() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        fs::write(dir.path().join("file1"), b"Lorem ipsum")?;
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(dir.path().to_path_buf()),
+            read_only: true,
+            ..Default::default()
+        }
+        .make()?;
+        let file1 = Path::from("file1");
+        assert_eq!(
+            store.get(&file1).await?.bytes().await?,
+            Bytes::from_static(b"Lorem ipsum")
+        );
+        assert_eq!(store.list_with_delimiter(None).await?.objects.len(), 1);
+        assert!(store
+            .put(&Path::from("file2"), Bytes::from_static(b"dolor"))
+            .await
+            .is_err());
+        assert!(store.delete(&file1).await.is_err());
+        assert!(store.copy(&file1, &Path::from("file2")).await.is_err());
+        assert!(store.rename(&file1, &Path::from("file2")).await.is_err());
+        assert!(store.put_multipart(&Path::from("file2")).await.is_err());
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+        assert_eq!(fs::read(dir.path().join("file1"))?, b"Lorem ipsum");
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retention of the published `epoch_N` directories uploaded to a remote store.
+
+use crate::object_store::util::list_epoch_dirs;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::DynObjectStore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Which epochs of a remote store to keep. An epoch is kept if any of the rules keeps it, then
+/// the oldest kept epochs are deleted until the byte budget is met. The latest epoch and the
+/// pinned epochs are never deleted. A policy without any rule keeps everything. Only published
+/// epochs count, epochs still being uploaded are neither deleted nor counted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetentionPolicy {
+    /// Keep the most recent N epochs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<usize>,
+    /// Keep every epoch which is a multiple of K, e.g. to thin out older history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_every: Option<u32>,
+    /// Epochs which are never deleted
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub pinned: BTreeSet<u32>,
+    /// Total size in bytes of the kept epochs above which the oldest ones are deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_total_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy can delete anything
+    pub fn is_enabled(&self) -> bool {
+        self.keep_last.is_some() || self.keep_every.is_some() || self.max_total_bytes.is_some()
+    }
+
+    /// Epochs of `epoch_sizes`, the size in bytes of each epoch present, which the policy
+    /// deletes, oldest first. Sizes are only read with a byte budget.
+    pub fn select_for_deletion(&self, epoch_sizes: &BTreeMap<u32, u64>) -> Vec<u32> {
+        let Some(latest) = epoch_sizes.keys().next_back().cloned() else {
+            return vec![];
+        };
+        if !self.is_enabled() {
+            return vec![];
+        }
+        let protected = |epoch: u32| epoch == latest || self.pinned.contains(&epoch);
+        let mut kept: BTreeSet<u32> = if self.keep_last.is_none() && self.keep_every.is_none() {
+            epoch_sizes.keys().cloned().collect()
+        } else {
+            let recent = epoch_sizes
+                .keys()
+                .rev()
+                .take(self.keep_last.unwrap_or(0))
+                .cloned();
+            let every = epoch_sizes
+                .keys()
+                .filter(|epoch| matches!(self.keep_every, Some(k) if k > 0 && *epoch % k == 0))
+                .cloned();
+            epoch_sizes
+                .keys()
+                .filter(|epoch| protected(**epoch))
+                .cloned()
+                .chain(recent)
+                .chain(every)
+                .collect()
+        };
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            let mut total_bytes: u64 = kept.iter().map(|epoch| epoch_sizes[epoch]).sum();
+            let evictable: Vec<u32> = kept
+                .iter()
+                .filter(|epoch| !protected(**epoch))
+                .cloned()
+                .collect();
+            for epoch in evictable {
+                if total_bytes <= max_total_bytes {
+                    break;
+                }
+                total_bytes -= epoch_sizes[&epoch];
+                kept.remove(&epoch);
+            }
+        }
+        epoch_sizes
+            .keys()
+            .filter(|epoch| !kept.contains(epoch))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Total size in bytes of the objects under `dir`
+pub async fn dir_size(store: Arc<DynObjectStore>, dir: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    let mut entries = store.list(Some(dir)).await?;
+    while let Some(entry) = entries.next().await {
+        size += entry?.size as u64;
+    }
+    Ok(size)
+}
+
+/// The `epoch_N` directories under `prefix`, or at the root of `store`, which `policy` deletes.
+/// Only the directories holding `success_marker` are published. `epoch_size` gives the bytes of
+/// a published epoch charged against the byte budget, e.g. the `dir_size` of its directory.
+pub async fn epochs_to_delete<F, Fut>(
+    store: Arc<DynObjectStore>,
+    prefix: Option<&Path>,
+    policy: &RetentionPolicy,
+    success_marker: &str,
+    epoch_size: F,
+) -> anyhow::Result<BTreeMap<u32, Path>>
+where
+    F: Fn(u32, Path) -> Fut,
+    Fut: Future<Output = anyhow::Result<u64>>,
+{
+    if !policy.is_enabled() {
+        return Ok(BTreeMap::new());
+    }
+    let mut epoch_dirs = list_epoch_dirs(store.clone(), prefix).await?;
+    let mut epoch_sizes = BTreeMap::new();
+    for (epoch, dir) in epoch_dirs.iter() {
+        match store.head(&dir.child(success_marker)).await {
+            Ok(_) => {}
+            Err(object_store::Error::NotFound { .. }) => continue,
+            Err(err) => return Err(err.into()),
+        }
+        let size = match policy.max_total_bytes {
+            Some(_) => epoch_size(*epoch, dir.clone()).await?,
+            None => 0,
+        };
+        epoch_sizes.insert(*epoch, size);
+    }
+    let to_delete: BTreeSet<u32> = policy
+        .select_for_deletion(&epoch_sizes)
+        .into_iter()
+        .collect();
+    epoch_dirs.retain(|epoch, _| to_delete.contains(epoch));
+    Ok(epoch_dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dir_size, epochs_to_delete, RetentionPolicy};
+    use crate::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn sizes(epochs: impl IntoIterator<Item = u32>, size: u64) -> BTreeMap<u32, u64> {
+        epochs.into_iter().map(|epoch| (epoch, size)).collect()
+    }
+
+    #[test]
+    fn test_select_for_deletion() {
+        let epochs = sizes(0..10, 100);
+        // Without any rule everything is kept
+        assert!(RetentionPolicy::default()
+            .select_for_deletion(&epochs)
+            .is_empty());
+        let pinned_only = RetentionPolicy {
+            pinned: BTreeSet::from([3]),
+            ..Default::default()
+        };
+        assert!(pinned_only.select_for_deletion(&epochs).is_empty());
+
+        let keep_last = RetentionPolicy {
+            keep_last: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            keep_last.select_for_deletion(&epochs),
+            vec![0, 1, 2, 3, 4, 5, 6]
+        );
+        // The latest epoch is kept even by an empty window
+        let keep_none = RetentionPolicy {
+            keep_last: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            keep_none.select_for_deletion(&epochs),
+            (0..9).collect::<Vec<_>>()
+        );
+
+        let thinned = RetentionPolicy {
+            keep_last: Some(2),
+            keep_every: Some(4),
+            pinned: BTreeSet::from([3]),
+            ..Default::default()
+        };
+        assert_eq!(thinned.select_for_deletion(&epochs), vec![1, 2, 5, 6, 7]);
+
+        // The oldest kept epochs go first to meet the budget, pinned ones never do
+        let budget = RetentionPolicy {
+            max_total_bytes: Some(350),
+            pinned: BTreeSet::from([0]),
+            ..Default::default()
+        };
+        assert_eq!(
+            budget.select_for_deletion(&epochs),
+            (1..8).collect::<Vec<_>>()
+        );
+        let tiny_budget = RetentionPolicy {
+            max_total_bytes: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(tiny_budget.select_for_deletion(&sizes([4], 100)), vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_epochs_to_delete() -> anyhow::Result<()> {
+        let remote_dir = TempDir::new()?;
+        for epoch in 0..6 {
+            let epoch_dir = remote_dir.path().join(format!("epoch_{epoch}"));
+            fs::create_dir_all(&epoch_dir)?;
+            fs::write(epoch_dir.join("data"), vec![0u8; 10])?;
+            // The latest epoch is still being uploaded
+            if epoch < 5 {
+                fs::write(epoch_dir.join("_SUCCESS"), b"")?;
+            }
+        }
+        fs::create_dir_all(remote_dir.path().join("other"))?;
+        fs::write(remote_dir.path().join("other").join("data"), b"foreign")?;
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(remote_dir.path().to_path_buf()),
+            ..Default::default()
+        }
+        .make()?;
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            max_total_bytes: Some(15),
+            pinned: BTreeSet::from([1]),
+            ..Default::default()
+        };
+        let to_delete = epochs_to_delete(store.clone(), None, &policy, "_SUCCESS", |_, dir| {
+            let store = store.clone();
+            async move { dir_size(store, &dir).await }
+        })
+        .await?;
+        // Entries other than published epoch directories are left alone
+        assert_eq!(to_delete.keys().cloned().collect::<Vec<_>>(), vec![0, 2, 3]);
+        assert_eq!(to_delete[&2].to_string(), "epoch_2");
+        Ok(())
+    }
+}
@@ -7,6 +7,7 @@ use bytes::Bytes;
 use futures::StreamExt;
 use object_store::path::Path;
 use object_store::DynObjectStore;
+use std::collections::BTreeMap;
 use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -148,6 +149,113 @@ pub async fn delete_recursively(
     delete_files(&paths_to_delete, store.clone(), concurrency).await
 }
 
+/// How to tell whether an `epoch_N` directory holds a complete copy of the epoch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpochCompleteness {
+    /// Any epoch directory is complete
+    Present,
+    /// Only epoch directories holding this marker file are complete, e.g. `_SUCCESS`
+    Marker(String),
+}
+
+/// Entries directly under a prefix, split into epoch directories and everything else
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EpochDirListing {
+    pub epoch_dirs: BTreeMap<u32, Path>,
+    /// Files and directories which aren't `epoch_N` directories, e.g. lifecycle markers or
+    /// the data of other tools sharing the bucket
+    pub other_entries: Vec<Path>,
+}
+
+/// Epoch of the directory `name`, `None` unless it is exactly `epoch_N` with `N` in canonical
+/// form, so that e.g. `epoch_latest` or `epoch_007` can't be mistaken for epochs
+pub fn parse_epoch_dir(name: &str) -> Option<u32> {
+    let epoch = name.strip_prefix("epoch_")?;
+    epoch
+        .parse::<u32>()
+        .ok()
+        .filter(|parsed| parsed.to_string() == epoch)
+}
+
+/// Lists the entries directly under `prefix`, or at the root of `store`
+pub async fn scan_epoch_dirs(
+    store: Arc<DynObjectStore>,
+    prefix: Option<&Path>,
+) -> anyhow::Result<EpochDirListing> {
+    let mut listing = EpochDirListing::default();
+    // Follows continuation tokens across pages for the cloud stores, so that listings beyond
+    // the provider page limit (1000 keys on S3) are complete
+    let entries = store.list_with_delimiter(prefix).await?;
+    for entry in entries.common_prefixes {
+        match entry.filename().and_then(parse_epoch_dir) {
+            Some(epoch) => {
+                listing.epoch_dirs.insert(epoch, entry);
+            }
+            None => listing.other_entries.push(entry),
+        }
+    }
+    listing
+        .other_entries
+        .extend(entries.objects.into_iter().map(|object| object.location));
+    Ok(listing)
+}
+
+/// Lists the `epoch_N` directories directly under `prefix`, or at the root of `store`. Other
+/// entries are skipped, so that foreign objects in the prefix can't hide the real epochs.
+pub async fn list_epoch_dirs(
+    store: Arc<DynObjectStore>,
+    prefix: Option<&Path>,
+) -> anyhow::Result<BTreeMap<u32, Path>> {
+    Ok(scan_epoch_dirs(store, prefix).await?.epoch_dirs)
+}
+
+/// Returns the epochs from `start_epoch` on which are missing or incomplete under `prefix` in
+/// `store`, followed by the epoch after the last one present. Epochs whose completeness can't
+/// be determined because of transient errors are not reported as missing.
+pub async fn find_missing_epochs(
+    store: Arc<DynObjectStore>,
+    prefix: Option<&Path>,
+    completeness: &EpochCompleteness,
+    start_epoch: u32,
+) -> anyhow::Result<Vec<u32>> {
+    let epoch_dirs = list_epoch_dirs(store.clone(), prefix).await?;
+    missing_epochs_of(store, &epoch_dirs, completeness, start_epoch).await
+}
+
+/// Same as `find_missing_epochs`, for the already listed `epoch_dirs`
+pub async fn missing_epochs_of(
+    store: Arc<DynObjectStore>,
+    epoch_dirs: &BTreeMap<u32, Path>,
+    completeness: &EpochCompleteness,
+    start_epoch: u32,
+) -> anyhow::Result<Vec<u32>> {
+    let mut candidate_epoch = start_epoch;
+    let mut missing_epochs = Vec::new();
+    for (epoch, path) in epoch_dirs.range(start_epoch..) {
+        while candidate_epoch < *epoch {
+            // The whole epoch directory is missing
+            missing_epochs.push(candidate_epoch);
+            candidate_epoch += 1;
+        }
+        if let EpochCompleteness::Marker(marker) = completeness {
+            match store.head(&path.child(marker.as_str())).await {
+                Err(object_store::Error::NotFound { .. }) => {
+                    error!("No {marker} marker found in {path} for epoch: {epoch}");
+                    missing_epochs.push(*epoch);
+                }
+                Err(err) => {
+                    // Probably a transient error
+                    warn!("Failed to read {marker} marker in {path} for epoch: {epoch}: {err}");
+                }
+                Ok(_) => {}
+            }
+        }
+        candidate_epoch += 1;
+    }
+    missing_epochs.push(candidate_epoch);
+    Ok(missing_epochs)
+}
+
 pub fn path_to_filesystem(local_dir_path: PathBuf, location: &Path) -> anyhow::Result<PathBuf> {
     // Convert an `object_store::path::Path` to `std::path::PathBuf`
     let path = std::fs::canonicalize(local_dir_path)?;
@@ -165,12 +273,23 @@ pub fn path_to_filesystem(local_dir_path: PathBuf, location: &Path) -> anyhow::R
 
 #[cfg(test)]
 mod tests {
-    use crate::object_store::util::{copy_recursively, delete_recursively};
+    use crate::object_store::util::{
+        copy_recursively, delete_recursively, find_missing_epochs, list_epoch_dirs,
+        scan_epoch_dirs, EpochCompleteness,
+    };
     use crate::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use itertools::Itertools;
+    use object_store::aws::AmazonS3Builder;
     use object_store::path::Path;
+    use object_store::DynObjectStore;
     use std::fs;
+    use std::net::SocketAddr;
     use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     use tempfile::TempDir;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
 
     #[tokio::test]
     pub async fn test_copy_recursively() -> anyhow::Result<()> {
@@ -261,4 +380,190 @@ mod tests {
             .exists());
         Ok(())
     }
+
+    #[tokio::test]
+    pub async fn test_find_missing_epochs() -> anyhow::Result<()> {
+        let root = TempDir::new()?;
+        let backups = root.path().join("backups");
+        for epoch in [0, 1, 3, 4] {
+            fs::create_dir_all(backups.join(format!("epoch_{epoch}")))?;
+            fs::write(
+                backups.join(format!("epoch_{epoch}")).join("file1"),
+                b"Lorem",
+            )?;
+        }
+        for epoch in [0, 3] {
+            fs::write(backups.join(format!("epoch_{epoch}")).join("_SUCCESS"), b"")?;
+        }
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(root.path().to_path_buf()),
+            ..Default::default()
+        }
+        .make()?;
+        let prefix = Path::from("backups");
+        let success = EpochCompleteness::Marker("_SUCCESS".to_string());
+
+        assert_eq!(
+            find_missing_epochs(store.clone(), Some(&prefix), &success, 0).await?,
+            vec![1, 2, 4, 5]
+        );
+        assert_eq!(
+            find_missing_epochs(store.clone(), Some(&prefix), &success, 3).await?,
+            vec![4, 5]
+        );
+        assert_eq!(
+            find_missing_epochs(store.clone(), Some(&prefix), &EpochCompleteness::Present, 0)
+                .await?,
+            vec![2, 5]
+        );
+        // Nothing uploaded yet from the start epoch on
+        assert_eq!(
+            find_missing_epochs(store, Some(&prefix), &success, 7).await?,
+            vec![7]
+        );
+        Ok(())
+    }
+
+    /// Serves S3 ListObjectsV2 responses for the `epoch_N` directories below `num_epochs`, at
+    /// most `page_size` per page. Returns the address of the server and the count of pages served.
+    async fn serve_paged_listing(
+        num_epochs: usize,
+        page_size: usize,
+    ) -> anyhow::Result<(SocketAddr, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let pages = Arc::new(AtomicUsize::new(0));
+        let served = pages.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let served = served.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    loop {
+                        let mut request_line = String::new();
+                        if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        // List requests have no body
+                        loop {
+                            let mut header = String::new();
+                            if stream.read_line(&mut header).await.unwrap_or(0) == 0 {
+                                return;
+                            }
+                            if header == "\r\n" {
+                                break;
+                            }
+                        }
+                        let start = request_line
+                            .split_once("continuation-token=")
+                            .and_then(|(_, rest)| rest.split(['&', ' ']).next())
+                            .map_or(0, |token| token.parse::<usize>().unwrap());
+                        let end = (start + page_size).min(num_epochs);
+                        let prefixes: String = (start..end)
+                            .map(|epoch| {
+                                format!("<CommonPrefixes><Prefix>epoch_{epoch}/</Prefix></CommonPrefixes>")
+                            })
+                            .collect();
+                        let next_token = if end < num_epochs {
+                            format!("<NextContinuationToken>{end}</NextContinuationToken>")
+                        } else {
+                            String::new()
+                        };
+                        let body = format!(
+                            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><ListBucketResult><IsTruncated>{}</IsTruncated>{prefixes}{next_token}</ListBucketResult>",
+                            end < num_epochs
+                        );
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/xml\r\nContent-Length: {}\r\n\r\n{body}",
+                            body.len()
+                        );
+                        served.fetch_add(1, Ordering::SeqCst);
+                        if stream
+                            .get_mut()
+                            .write_all(response.as_bytes())
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        Ok((address, pages))
+    }
+
+    #[tokio::test]
+    pub async fn test_list_epoch_dirs_across_pages() -> anyhow::Result<()> {
+        // More directories than fit in a single page of the S3 list API
+        let (address, pages) = serve_paged_listing(2500, 1000).await?;
+        let store: Arc<DynObjectStore> = Arc::new(
+            AmazonS3Builder::new()
+                .with_endpoint(format!("http://{address}"))
+                .with_allow_http(true)
+                .with_bucket_name("bucket")
+                .with_region("us-east-1")
+                .with_access_key_id("access-key")
+                .with_secret_access_key("secret-key")
+                .build()?,
+        );
+        let epoch_dirs = list_epoch_dirs(store, None).await?;
+        assert_eq!(pages.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            epoch_dirs.keys().cloned().collect::<Vec<_>>(),
+            (0..2500).collect::<Vec<_>>()
+        );
+        assert_eq!(epoch_dirs[&2499], Path::from("epoch_2499"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_scan_epoch_dirs_skips_foreign_entries() -> anyhow::Result<()> {
+        let root = TempDir::new()?;
+        for dir in [
+            "epoch_0",
+            "epoch_2",
+            "epoch_latest",
+            "epoch_",
+            "epoch_01",
+            "other-tool",
+        ] {
+            fs::create_dir_all(root.path().join(dir))?;
+            fs::write(root.path().join(dir).join("file1"), b"")?;
+        }
+        fs::write(root.path().join("epoch_1"), b"lifecycle marker")?;
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(root.path().to_path_buf()),
+            ..Default::default()
+        }
+        .make()?;
+        let listing = scan_epoch_dirs(store.clone(), None).await?;
+        assert_eq!(
+            listing.epoch_dirs.keys().cloned().collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(
+            listing
+                .other_entries
+                .iter()
+                .map(|p| p.to_string())
+                .sorted()
+                .collect::<Vec<_>>(),
+            vec![
+                "epoch_",
+                "epoch_01",
+                "epoch_1",
+                "epoch_latest",
+                "other-tool"
+            ]
+        );
+        // The file named like an epoch doesn't make epoch 1 present
+        assert_eq!(
+            find_missing_epochs(store, None, &EpochCompleteness::Present, 0).await?,
+            vec![1, 3]
+        );
+        Ok(())
+    }
 }
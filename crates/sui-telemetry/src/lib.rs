@@ -6,6 +6,8 @@ use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use sui_core::authority::AuthorityState;
+use sui_core::db_checkpoint_handler::telemetry::BackupTelemetryEvent;
+use tokio::sync::mpsc;
 use tracing::trace;
 
 pub(crate) const GA_API_SECRET: &str = "zeq-aYEzS0aGdRJ8kNZTEg";
@@ -77,6 +79,45 @@ pub async fn send_telemetry_event(state: Arc<AuthorityState>, is_validator: bool
     send_telemetry_event_impl(telemetry_payload).await
 }
 
+/// Forwards db checkpoint backup lifecycle events until the sending side is dropped.
+pub async fn forward_backup_telemetry_events(
+    mut receiver: mpsc::Receiver<BackupTelemetryEvent>,
+    chain_identifier: String,
+    is_validator: bool,
+) {
+    let git_rev = env!("CARGO_PKG_VERSION").to_string();
+    while let Some(event) = receiver.recv().await {
+        let since_the_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Now should be later than epoch!");
+        let mut params = event.params();
+        params.extend([
+            ("chain_identifier".into(), chain_identifier.clone()),
+            (
+                "node_type".into(),
+                if is_validator {
+                    "validator".into()
+                } else {
+                    "full_node".into()
+                },
+            ),
+            ("git_rev".into(), git_rev.clone()),
+            (
+                "seconds_since_epoch".into(),
+                since_the_epoch.as_secs().to_string(),
+            ),
+        ]);
+        let telemetry_payload = TelemetryPayload {
+            client_id: HARDCODED_CLIENT_ID.into(),
+            events: vec![TelemetryEvent {
+                name: event.name().into(),
+                params,
+            }],
+        };
+        send_telemetry_event_impl(telemetry_payload).await
+    }
+}
+
 async fn get_ip() -> String {
     let resp = reqwest::get(IPLOOKUP_URL).await;
     match resp {
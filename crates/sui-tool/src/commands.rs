@@ -2,15 +2,24 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    benchmark_db_checkpoint_upload, bootstrap_db, compare_db_checkpoint_with_live,
     db_tool::{execute_db_tool_command, print_db_all_tables, DbToolCommand},
-    get_object, get_transaction_block, make_clients, restore_from_db_checkpoint,
+    get_object, get_transaction_block, make_clients, rehearse_restore, restore_db_checkpoint,
+    restore_from_db_checkpoint, restore_to_checkpoint, smoke_test_restored_db,
     state_sync_from_archive, verify_archive, ConciseObjectOutput, GroupedObjectOutput,
     VerboseObjectOutput,
 };
 use anyhow::Result;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::time::Duration;
 use sui_config::genesis::Genesis;
 use sui_core::authority_client::AuthorityAPI;
+use sui_core::db_checkpoint_handler::backfill::backfill_legacy_epochs;
+use sui_core::db_checkpoint_handler::benchmark::DEFAULT_BENCHMARK_EPOCH;
+use sui_core::db_checkpoint_handler::bootstrap::PeerSyncEstimate;
+use sui_core::db_checkpoint_handler::bundles::{bundle_old_epochs, unbundle_epoch};
+use sui_core::db_checkpoint_handler::listing::list_epoch;
 use sui_replay::{execute_replay_command, ReplayToolCommand};
 
 use sui_types::{base_types::*, object::Owner};
@@ -19,6 +28,7 @@ use clap::*;
 use fastcrypto::encoding::Encoding;
 use sui_config::Config;
 use sui_core::authority_aggregator::AuthorityAggregatorBuilder;
+use sui_storage::object_store::util::{find_missing_epochs, EpochCompleteness};
 use sui_storage::object_store::ObjectStoreConfig;
 use sui_types::messages_checkpoint::{
     CheckpointRequest, CheckpointResponse, CheckpointSequenceNumber,
@@ -127,8 +137,8 @@ pub enum ToolCommand {
         db_path: PathBuf,
         #[clap(flatten)]
         object_store_config: ObjectStoreConfig,
-        #[clap(default_value_t = 5)]
-        download_concurrency: usize,
+        #[clap(default_value = "5")]
+        download_concurrency: NonZeroUsize,
     },
 
     /// Tool to verify the archive store
@@ -191,6 +201,218 @@ pub enum ToolCommand {
         db_checkpoint_path: PathBuf,
     },
 
+    /// Restore the db checkpoint of the latest complete epoch in the node's bucket into its
+    /// empty db path, so that a fresh node starts from it instead of genesis
+    #[clap(name = "restore-db-checkpoint")]
+    RestoreDbCheckpoint {
+        #[clap(long = "config-path")]
+        config_path: PathBuf,
+        /// Epoch to restore instead of the latest complete one
+        #[clap(long = "epoch")]
+        epoch: Option<u32>,
+        #[clap(long = "download-concurrency", default_value = "5")]
+        download_concurrency: NonZeroUsize,
+        /// Resume an interrupted restore, keeping the files already in the db path which
+        /// match the manifest of the epoch
+        #[clap(long = "resume")]
+        resume: bool,
+    },
+
+    /// Restore the db checkpoint of an epoch from the node's bucket and replay the state
+    /// archive up to a checkpoint, writing a node config which executes exactly up to it
+    #[clap(name = "restore-to-checkpoint")]
+    RestoreToCheckpoint {
+        #[clap(long = "config-path")]
+        config_path: PathBuf,
+        #[clap(long = "epoch")]
+        epoch: u32,
+        /// Last checkpoint to execute, at or after the end of `epoch`
+        #[clap(long = "checkpoint")]
+        checkpoint: CheckpointSequenceNumber,
+        /// Where to write the node config which stops execution at the checkpoint
+        #[clap(long = "output-config-path")]
+        output_config_path: PathBuf,
+        #[clap(long = "download-concurrency", default_value = "5")]
+        download_concurrency: NonZeroUsize,
+    },
+
+    /// Start a sandboxed fullnode against a restored db checkpoint and check its RPC responses
+    /// against the expectations recorded in the epoch manifest
+    #[clap(name = "smoke-test-restored-db")]
+    SmokeTestRestoredDb {
+        /// Fullnode config of the network, used as a template for the sandboxed node
+        #[clap(long = "config-path")]
+        config_path: PathBuf,
+        #[clap(long = "db-checkpoint-path")]
+        db_checkpoint_path: PathBuf,
+        #[clap(long = "epoch")]
+        epoch: u32,
+        /// Bucket the db checkpoint was restored from
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        #[clap(long = "node-binary", default_value = "sui-node")]
+        node_binary: PathBuf,
+        #[clap(long = "startup-timeout-secs", default_value_t = 600)]
+        startup_timeout_secs: u64,
+    },
+
+    /// Restore a db checkpoint from the node's bucket into a staging directory and diff its
+    /// checkpoint watermark, sampled objects and recent transactions against a live reference
+    /// node of the same network
+    #[clap(name = "rehearse-restore")]
+    RehearseRestore {
+        #[clap(long = "config-path")]
+        config_path: PathBuf,
+        /// Epoch to restore instead of the latest complete one
+        #[clap(long = "epoch")]
+        epoch: Option<u32>,
+        /// Json rpc url of a healthy fullnode of the network
+        #[clap(long = "reference-rpc-url")]
+        reference_rpc_url: String,
+        #[clap(long = "num-samples", default_value_t = 100)]
+        num_samples: usize,
+        #[clap(long = "download-concurrency", default_value = "5")]
+        download_concurrency: NonZeroUsize,
+        /// Where to write the divergence report as json
+        #[clap(long = "report-path")]
+        report_path: Option<PathBuf>,
+    },
+
+    /// Select the fastest safe source to bootstrap the db of a node and restore from it
+    #[clap(name = "bootstrap-db")]
+    BootstrapDb {
+        #[clap(long = "config-path")]
+        config_path: PathBuf,
+        /// Additional bucket to consider besides the one in the node's db checkpoint config
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        /// Current epoch of the network, enables state sync from peers as a candidate
+        #[clap(long = "current-epoch")]
+        current_epoch: Option<u32>,
+        #[clap(long = "per-epoch-sync-secs", default_value_t = 3600)]
+        per_epoch_sync_secs: u64,
+        #[clap(long = "max-epoch-lag", default_value_t = 5)]
+        max_epoch_lag: u32,
+        /// Only print the plan without restoring anything
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+        #[clap(long = "download-concurrency", default_value = "5")]
+        download_concurrency: NonZeroUsize,
+    },
+
+    /// Print the epochs missing or incomplete in a bucket of `epoch_N` directories, followed by
+    /// the next epoch to be uploaded
+    #[clap(name = "find-missing-epochs")]
+    FindMissingEpochs {
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        /// Directory holding the epoch directories, the root of the bucket if unset
+        #[clap(long = "prefix")]
+        prefix: Option<String>,
+        /// Marker file present in complete epoch directories, e.g. `_SUCCESS`. Every epoch
+        /// directory is considered complete if unset.
+        #[clap(long = "marker")]
+        marker: Option<String>,
+        #[clap(long = "start-epoch", default_value_t = 0)]
+        start_epoch: u32,
+    },
+
+    /// List the files of an uploaded epoch with their sizes and digests, from its manifest
+    /// without downloading any of them
+    #[clap(name = "list-db-checkpoint")]
+    ListDbCheckpoint {
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        #[clap(long = "epoch")]
+        epoch: u32,
+        /// Only list the files under this directory of the epoch, e.g. `store/perpetual`
+        #[clap(long = "prefix")]
+        prefix: Option<String>,
+        /// Print the listing as json instead of a tree
+        #[clap(long = "json")]
+        json: bool,
+    },
+
+    /// Compare tables of the latest db checkpoint of a node against its live db, which should
+    /// hold every entry of the db checkpoint. Only pass tables which are never modified nor
+    /// pruned, e.g. transactions, effects and certified checkpoints.
+    #[clap(name = "compare-db-checkpoint")]
+    CompareDbCheckpoint {
+        #[clap(long = "config-path")]
+        config_path: PathBuf,
+        /// Path of the db relative to the db checkpoint
+        #[clap(long = "db", default_value = "store/perpetual")]
+        db: String,
+        #[clap(long = "table", required = true)]
+        tables: Vec<String>,
+    },
+
+    /// Write the missing MANIFEST of epochs uploaded by older versions, after checking their
+    /// files against the local db checkpoints in `--local-path` or computing their digests from
+    /// the bucket. Epochs without a _SUCCESS marker get one when all their files match the
+    /// local copy, and are reported as unverified otherwise.
+    #[clap(name = "backfill-db-checkpoint-manifests")]
+    BackfillDbCheckpointManifests {
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        /// Directory holding the local `epoch_N` db checkpoints of the node
+        #[clap(long = "local-path")]
+        local_path: Option<PathBuf>,
+        /// Only print which epochs would be backfilled
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Move the complete epochs of the bucket older than the latest `--keep-recent-epochs` ones
+    /// into range bundles of `--epochs-per-bundle` epochs under `bundles/`, one object per epoch
+    /// with a combined manifest per range, and delete their `epoch_N` directories
+    #[clap(name = "bundle-db-checkpoints")]
+    BundleDbCheckpoints {
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        #[clap(long = "epochs-per-bundle", default_value_t = 100)]
+        epochs_per_bundle: u32,
+        #[clap(long = "keep-recent-epochs", default_value_t = 100)]
+        keep_recent_epochs: u32,
+        /// Only print which epochs would be bundled
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Copy a bundled epoch back into its `epoch_N` directory of the bucket, from which it can
+    /// be restored. The next `bundle-db-checkpoints` deletes the copy again.
+    #[clap(name = "unbundle-db-checkpoint")]
+    UnbundleDbCheckpoint {
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        #[clap(long = "epoch")]
+        epoch: u32,
+    },
+
+    /// Upload a synthetic epoch of random files through the db checkpoint upload pipeline and
+    /// report the throughput, to size bandwidth and tune upload settings before real epochs
+    /// arrive. The synthetic epoch is deleted from the bucket afterwards unless `--keep` is set.
+    #[clap(name = "benchmark-db-checkpoint-upload")]
+    BenchmarkDbCheckpointUpload {
+        /// Node config to take the upload settings from, the defaults are used if unset
+        #[clap(long = "config-path")]
+        config_path: Option<PathBuf>,
+        #[clap(flatten)]
+        object_store_config: ObjectStoreConfig,
+        /// Epoch of the synthetic db checkpoint, which must not exist in the bucket
+        #[clap(long = "epoch", default_value_t = DEFAULT_BENCHMARK_EPOCH)]
+        epoch: u32,
+        #[clap(long = "num-files", default_value_t = 100)]
+        num_files: usize,
+        #[clap(long = "total-size-mb", default_value_t = 1024)]
+        total_size_mb: u64,
+        /// Overrides the upload concurrency of the config
+        #[clap(long = "upload-concurrency")]
+        upload_concurrency: Option<usize>,
+        #[clap(long = "keep")]
+        keep: bool,
+    },
+
     #[clap(name = "replay")]
     Replay {
         #[clap(long = "rpc")]
@@ -377,6 +599,193 @@ impl ToolCommand {
                 let config = sui_config::NodeConfig::load(config_path)?;
                 restore_from_db_checkpoint(&config, &db_checkpoint_path).await?;
             }
+            ToolCommand::RestoreDbCheckpoint {
+                config_path,
+                epoch,
+                download_concurrency,
+                resume,
+            } => {
+                let config = sui_config::NodeConfig::load(config_path)?;
+                restore_db_checkpoint(&config, epoch, download_concurrency, resume).await?;
+            }
+            ToolCommand::RestoreToCheckpoint {
+                config_path,
+                epoch,
+                checkpoint,
+                output_config_path,
+                download_concurrency,
+            } => {
+                let config = sui_config::NodeConfig::load(config_path)?;
+                restore_to_checkpoint(
+                    &config,
+                    epoch,
+                    checkpoint,
+                    download_concurrency,
+                    &output_config_path,
+                )
+                .await?;
+            }
+            ToolCommand::SmokeTestRestoredDb {
+                config_path,
+                db_checkpoint_path,
+                epoch,
+                object_store_config,
+                node_binary,
+                startup_timeout_secs,
+            } => {
+                let config = sui_config::NodeConfig::load(config_path)?;
+                smoke_test_restored_db(
+                    config,
+                    &db_checkpoint_path,
+                    epoch,
+                    object_store_config,
+                    node_binary,
+                    Duration::from_secs(startup_timeout_secs),
+                )
+                .await?;
+            }
+            ToolCommand::RehearseRestore {
+                config_path,
+                epoch,
+                reference_rpc_url,
+                num_samples,
+                download_concurrency,
+                report_path,
+            } => {
+                let config = sui_config::NodeConfig::load(config_path)?;
+                rehearse_restore(
+                    &config,
+                    epoch,
+                    &reference_rpc_url,
+                    num_samples,
+                    download_concurrency,
+                    report_path.as_deref(),
+                )
+                .await?;
+            }
+            ToolCommand::BootstrapDb {
+                config_path,
+                object_store_config,
+                current_epoch,
+                per_epoch_sync_secs,
+                max_epoch_lag,
+                dry_run,
+                download_concurrency,
+            } => {
+                let config = sui_config::NodeConfig::load(config_path)?;
+                let peer_sync = current_epoch.map(|current_epoch| PeerSyncEstimate {
+                    current_epoch,
+                    per_epoch_sync_duration_ms: per_epoch_sync_secs * 1000,
+                });
+                bootstrap_db(
+                    &config,
+                    object_store_config,
+                    peer_sync,
+                    max_epoch_lag,
+                    download_concurrency,
+                    dry_run,
+                )
+                .await?;
+            }
+            ToolCommand::FindMissingEpochs {
+                object_store_config,
+                prefix,
+                marker,
+                start_epoch,
+            } => {
+                let prefix = prefix.map(|prefix| object_store::path::Path::from(prefix.as_str()));
+                let completeness =
+                    marker.map_or(EpochCompleteness::Present, EpochCompleteness::Marker);
+                let missing_epochs = find_missing_epochs(
+                    object_store_config.read_only().make()?,
+                    prefix.as_ref(),
+                    &completeness,
+                    start_epoch,
+                )
+                .await?;
+                println!("{}", serde_json::to_string(&missing_epochs)?);
+            }
+            ToolCommand::ListDbCheckpoint {
+                object_store_config,
+                epoch,
+                prefix,
+                json,
+            } => {
+                let listing = list_epoch(
+                    object_store_config.read_only().make()?,
+                    epoch,
+                    prefix.as_deref(),
+                )
+                .await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&listing)?);
+                } else {
+                    print!("{}", listing.tree());
+                }
+            }
+            ToolCommand::CompareDbCheckpoint {
+                config_path,
+                db,
+                tables,
+            } => {
+                let config = sui_config::NodeConfig::load(config_path)?;
+                compare_db_checkpoint_with_live(&config, &db, &tables)?;
+            }
+            ToolCommand::BackfillDbCheckpointManifests {
+                object_store_config,
+                local_path,
+                dry_run,
+            } => {
+                let report = backfill_legacy_epochs(
+                    object_store_config.make()?,
+                    local_path.as_deref(),
+                    dry_run,
+                )
+                .await?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+            ToolCommand::BundleDbCheckpoints {
+                object_store_config,
+                epochs_per_bundle,
+                keep_recent_epochs,
+                dry_run,
+            } => {
+                let report = bundle_old_epochs(
+                    object_store_config.make()?,
+                    epochs_per_bundle,
+                    keep_recent_epochs,
+                    dry_run,
+                )
+                .await?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+            ToolCommand::UnbundleDbCheckpoint {
+                object_store_config,
+                epoch,
+            } => {
+                let num_files = unbundle_epoch(object_store_config.make()?, epoch).await?;
+                println!("Unbundled {num_files} files of epoch {epoch}");
+            }
+            ToolCommand::BenchmarkDbCheckpointUpload {
+                config_path,
+                object_store_config,
+                epoch,
+                num_files,
+                total_size_mb,
+                upload_concurrency,
+                keep,
+            } => {
+                benchmark_db_checkpoint_upload(
+                    config_path,
+                    object_store_config,
+                    epoch,
+                    num_files,
+                    total_size_mb * 1024 * 1024,
+                    upload_concurrency,
+                    keep,
+                )
+                .await?;
+            }
             ToolCommand::Replay {
                 rpc_url,
                 safety_checks,
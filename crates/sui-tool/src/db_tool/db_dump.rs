@@ -222,6 +222,7 @@ pub async fn prune_objects(db_path: PathBuf) -> anyhow::Result<()> {
         pruning_config,
         metrics,
         usize::MAX,
+        None,
     )
     .await?;
     Ok(())
@@ -252,6 +253,7 @@ pub async fn prune_checkpoints(db_path: PathBuf) -> anyhow::Result<()> {
         metrics,
         usize::MAX,
         archive_readers,
+        None,
     )
     .await?;
     Ok(())
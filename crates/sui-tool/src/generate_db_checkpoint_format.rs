@@ -0,0 +1,51 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::{Parser, ValueEnum};
+use pretty_assertions::assert_str_eq;
+use std::fs::File;
+use std::io::Write;
+use sui_core::db_checkpoint_handler::format::DBCheckpointFormat;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Action {
+    Print,
+    Test,
+    Record,
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "Db checkpoint format generator",
+    about = "Generate the description of the files and JSON formats of db checkpoint uploads"
+)]
+struct Options {
+    #[clap(value_enum, default_value = "record", ignore_case = true)]
+    action: Action,
+}
+
+const FILE_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/spec/db-checkpoint-format.json"
+);
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn main() {
+    let options = Options::parse();
+    let format = DBCheckpointFormat::new(VERSION);
+    let content = serde_json::to_string_pretty(&format).unwrap();
+    match options.action {
+        Action::Print => {
+            println!("{content}");
+        }
+        Action::Record => {
+            let mut f = File::create(FILE_PATH).unwrap();
+            writeln!(f, "{content}").unwrap();
+        }
+        Action::Test => {
+            let reference = std::fs::read_to_string(FILE_PATH).unwrap();
+            assert_str_eq!(&reference, &(content + "\n"));
+        }
+    }
+}
@@ -14,11 +14,13 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use std::{fs, io};
-use sui_config::{genesis::Genesis, NodeConfig};
+use sui_config::{genesis::Genesis, Config, NodeConfig};
 use sui_core::authority_client::{AuthorityAPI, NetworkAuthorityClient};
 use sui_network::default_mysten_network_config;
 use sui_sdk::SuiClientBuilder;
 use sui_types::crypto::AuthorityPublicKeyBytes;
+use sui_types::digests::ChainIdentifier;
+use sui_types::messages_checkpoint::CheckpointSequenceNumber;
 use sui_types::multiaddr::Multiaddr;
 use sui_types::object::ObjectFormatOptions;
 use sui_types::{base_types::*, object::Owner};
@@ -28,12 +30,25 @@ use anyhow::anyhow;
 use eyre::ContextCompat;
 use indicatif::{ProgressBar, ProgressStyle};
 use prometheus::Registry;
+use restore_admin::spawn_restore_admin_server;
+use restore_rehearsal::diff_against_reference;
+use restore_smoke_test::RestoreSmokeTest;
 use sui_archival::reader::ArchiveReader;
 use sui_archival::verify_archive_with_genesis_config;
-use sui_config::node::ArchiveReaderConfig;
+use sui_config::node::{ArchiveReaderConfig, DBCheckpointConfig};
 use sui_core::authority::authority_store_tables::AuthorityPerpetualTables;
 use sui_core::authority::AuthorityStore;
 use sui_core::checkpoints::CheckpointStore;
+use sui_core::db_checkpoint_handler::admin_auth::StorageAdminAuth;
+use sui_core::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use sui_core::db_checkpoint_handler::benchmark::run_upload_benchmark;
+use sui_core::db_checkpoint_handler::bootstrap::{BootstrapPlanner, PeerSyncEstimate};
+use sui_core::db_checkpoint_handler::digest_pool::DigestPool;
+use sui_core::db_checkpoint_handler::divergence::{compare_with_live, latest_epoch_checkpoint};
+use sui_core::db_checkpoint_handler::encryption::EncryptionKey;
+use sui_core::db_checkpoint_handler::manifest::read_published_manifest;
+use sui_core::db_checkpoint_handler::restorer::{DBCheckpointRestorer, RestoreProgress};
+use sui_core::db_checkpoint_handler::staging::StagingArea;
 use sui_core::epoch::committee_store::CommitteeStore;
 use sui_core::storage::RocksDbStore;
 use sui_storage::object_store::ObjectStoreConfig;
@@ -46,6 +61,9 @@ use typed_store::rocks::MetricConf;
 
 pub mod commands;
 pub mod db_tool;
+pub mod restore_admin;
+pub mod restore_rehearsal;
+pub mod restore_smoke_test;
 
 // This functions requires at least one of genesis or fullnode_rpc to be `Some`.
 async fn make_clients(
@@ -634,20 +652,243 @@ pub async fn restore_from_db_checkpoint(
     Ok(())
 }
 
+pub async fn bootstrap_db(
+    config: &NodeConfig,
+    object_store_config: ObjectStoreConfig,
+    peer_sync: Option<PeerSyncEstimate>,
+    max_epoch_lag: u32,
+    download_concurrency: NonZeroUsize,
+    dry_run: bool,
+) -> Result<()> {
+    let buckets: Vec<ObjectStoreConfig> = config
+        .db_checkpoint_config
+        .object_store_config
+        .clone()
+        .into_iter()
+        .chain(
+            object_store_config
+                .object_store
+                .is_some()
+                .then_some(object_store_config),
+        )
+        // Bootstrapping only reads from the buckets, which may hold the canonical backups
+        .map(|bucket| bucket.read_only())
+        .collect();
+    let chain_identifier = ChainIdentifier::from(*config.genesis()?.checkpoint().digest());
+    let bandwidth_limiter = restore_bandwidth_limiter(config);
+    let progress = RestoreProgress::default();
+    let mut planner = BootstrapPlanner::new(
+        buckets,
+        peer_sync,
+        max_epoch_lag,
+        download_concurrency,
+    )
+    .with_progress(progress.clone())
+    .with_chain_identifier(chain_identifier)
+    .with_staging_area(StagingArea::from_node_config(config))
+    .with_bandwidth_limiter(bandwidth_limiter.clone())
+    .with_fsync(config.db_checkpoint_config.restore_fsync());
+    if let Some(encryption) = &config.db_checkpoint_config.encryption {
+        planner = planner.with_encryption_key(EncryptionKey::load(encryption).await?);
+    }
+    let plan = planner.plan().await?;
+    println!("{}", serde_json::to_string_pretty(&plan)?);
+    if !dry_run {
+        let admin_server = spawn_restore_admin_server(
+            bandwidth_limiter,
+            progress,
+            restore_admin_auth(config),
+            config.admin_interface_port,
+        );
+        let result = planner.execute(&plan, &config.db_path()).await;
+        admin_server.abort();
+        result?;
+    }
+    Ok(())
+}
+
+/// Starts a sandboxed fullnode against the db checkpoint of `epoch` restored at
+/// `db_checkpoint_path`, and checks its RPC responses against the expectations recorded in the
+/// manifest of the epoch in the bucket
+pub async fn smoke_test_restored_db(
+    config: NodeConfig,
+    db_checkpoint_path: &Path,
+    epoch: u32,
+    object_store_config: ObjectStoreConfig,
+    node_binary: PathBuf,
+    startup_timeout: Duration,
+) -> Result<()> {
+    let store = object_store_config.read_only().make()?;
+    let manifest = read_published_manifest(
+        &object_store::path::Path::from(format!("epoch_{epoch}")),
+        store,
+    )
+    .await?
+    .ok_or_else(|| anyhow!("Epoch: {epoch} is not complete in the bucket"))?;
+    let expectations = manifest
+        .expectations
+        .ok_or_else(|| anyhow!("No restore expectations recorded for epoch: {epoch}"))?;
+    let report = RestoreSmokeTest::new(node_binary, config, startup_timeout)
+        .run(db_checkpoint_path, &expectations)
+        .await?;
+    for failure in report.failures.iter() {
+        println!("FAILED: {failure}");
+    }
+    if !report.passed() {
+        return Err(anyhow!(
+            "{} of {} smoke test checks failed",
+            report.failures.len(),
+            report.num_checks
+        ));
+    }
+    println!("All {} smoke test checks passed", report.num_checks);
+    Ok(())
+}
+
+/// Compares `tables` of `db` in the latest db checkpoint of the node against its live db, and
+/// fails if any entry of the db checkpoint is missing or different in the live db
+pub fn compare_db_checkpoint_with_live(
+    config: &NodeConfig,
+    db: &str,
+    tables: &[String],
+) -> Result<()> {
+    let checkpoint_path = config
+        .db_checkpoint_config
+        .checkpoint_path
+        .clone()
+        .unwrap_or_else(|| config.db_checkpoint_path());
+    let (epoch, checkpoint_dir) = latest_epoch_checkpoint(&checkpoint_path)?
+        .ok_or_else(|| anyhow!("No db checkpoint found in {}", checkpoint_path.display()))?;
+    let divergences = compare_with_live(&checkpoint_dir, &config.db_path(), db, tables)?;
+    println!("{}", serde_json::to_string_pretty(&divergences)?);
+    let diverged: Vec<&str> = divergences
+        .iter()
+        .filter(|divergence| divergence.is_diverged())
+        .map(|divergence| divergence.table.as_str())
+        .collect();
+    if !diverged.is_empty() {
+        return Err(anyhow!(
+            "Db checkpoint of epoch: {epoch} diverges from the live db in tables {:?}",
+            diverged
+        ));
+    }
+    println!("Db checkpoint of epoch: {epoch} is consistent with the live db");
+    Ok(())
+}
+
+/// Restores the db checkpoint of `epoch`, or of the latest epoch complete in the bucket of the
+/// node, into a staging directory and diffs it against the live reference node serving json rpc
+/// at `reference_rpc_url`. The divergence report is printed, and written to `report_path` if
+/// set, and the rehearsal fails if any compared entry diverged.
+pub async fn rehearse_restore(
+    config: &NodeConfig,
+    epoch: Option<u32>,
+    reference_rpc_url: &str,
+    num_samples: usize,
+    download_concurrency: NonZeroUsize,
+    report_path: Option<&Path>,
+) -> Result<()> {
+    let client = SuiClientBuilder::default()
+        .build(reference_rpc_url)
+        .await
+        .map_err(|err| anyhow!("Failed to connect to reference node {reference_rpc_url}: {err}"))?;
+    let restorer = node_restorer(
+        config,
+        download_concurrency,
+        restore_bandwidth_limiter(config),
+    )
+    .await?;
+    // The size of the db checkpoint is only known to the restorer, which checks the disk space
+    let staging_dir = StagingArea::from_node_config(config).create("rehearsal", 0)?;
+    let restored_db_path = staging_dir.path().join("db");
+    let restored = match epoch {
+        Some(epoch) => restorer.restore_epoch(epoch, &restored_db_path).await?,
+        None => restorer.restore_latest_epoch(&restored_db_path).await?,
+    };
+    let report =
+        diff_against_reference(&client, &restored_db_path, restored.epoch, num_samples).await?;
+    let json = serde_json::to_string_pretty(&report)?;
+    println!("{json}");
+    if let Some(report_path) = report_path {
+        fs::write(report_path, &json)?;
+    }
+    if report.is_diverged() {
+        return Err(anyhow!(
+            "{} of {} entries of the restored db of epoch: {} diverge from the reference node",
+            report.divergences.len(),
+            report.num_compared,
+            report.epoch
+        ));
+    }
+    println!(
+        "Restored db of epoch: {} matches the reference node on all {} compared entries, {} could not be verified",
+        report.epoch,
+        report.num_compared,
+        report.unverifiable.len()
+    );
+    Ok(())
+}
+
+/// Uploads a synthetic epoch to the bucket with the upload settings of the node at
+/// `config_path`, or the default ones, and prints the observed throughput
+pub async fn benchmark_db_checkpoint_upload(
+    config_path: Option<PathBuf>,
+    object_store_config: ObjectStoreConfig,
+    epoch: u32,
+    num_files: usize,
+    total_bytes: u64,
+    upload_concurrency: Option<usize>,
+    keep: bool,
+) -> Result<()> {
+    let (mut db_checkpoint_config, staging_area) = match config_path {
+        Some(config_path) => {
+            let config = NodeConfig::load(config_path)?;
+            let staging_area = StagingArea::from_node_config(&config);
+            (config.db_checkpoint_config, staging_area)
+        }
+        None => (
+            DBCheckpointConfig::default(),
+            StagingArea::new(std::env::temp_dir().join("sui-staging")),
+        ),
+    };
+    if upload_concurrency.is_some() {
+        db_checkpoint_config.upload_concurrency = upload_concurrency;
+    }
+    let work_dir = staging_area.create("upload-benchmark", total_bytes)?;
+    let report = run_upload_benchmark(
+        work_dir.path(),
+        &object_store_config,
+        &db_checkpoint_config,
+        epoch,
+        num_files,
+        total_bytes,
+        keep,
+    )
+    .await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
 pub async fn verify_archive(
     genesis: &Path,
     remote_store_config: ObjectStoreConfig,
     concurrency: usize,
     interactive: bool,
 ) -> Result<()> {
-    verify_archive_with_genesis_config(genesis, remote_store_config, concurrency, interactive).await
+    verify_archive_with_genesis_config(
+        genesis,
+        remote_store_config.read_only(),
+        concurrency,
+        interactive,
+    )
+    .await
 }
 
 pub async fn state_sync_from_archive(
     path: &Path,
     genesis: &Path,
     remote_store_config: ObjectStoreConfig,
-    concurrency: usize,
+    concurrency: NonZeroUsize,
 ) -> Result<()> {
     let genesis = Genesis::load(genesis).unwrap();
     let genesis_committee = genesis.committee()?;
@@ -694,7 +935,7 @@ pub async fn state_sync_from_archive(
     let state_sync_store = RocksDbStore::new(store, committee_store, checkpoint_store.clone());
     let archive_reader_config = ArchiveReaderConfig {
         remote_store_config,
-        download_concurrency: NonZeroUsize::new(concurrency).unwrap(),
+        download_concurrency: concurrency,
         use_for_pruning_watermark: false,
     };
     let archive_reader = ArchiveReader::new(archive_reader_config)?;
@@ -757,3 +998,327 @@ pub async fn state_sync_from_archive(
     info!("Highest synced checkpoint after sync: {end}");
     Ok(())
 }
+
+/// Bandwidth limit of restores from the config of the node
+fn restore_bandwidth_limiter(config: &NodeConfig) -> BandwidthLimiter {
+    BandwidthLimiter::new(config.db_checkpoint_config.restore_rate_bytes_per_sec)
+}
+
+/// Authorization of the admin endpoint of restores, with the admin tokens of the node
+fn restore_admin_auth(config: &NodeConfig) -> StorageAdminAuth {
+    StorageAdminAuth::new(config.db_checkpoint_config.admin_tokens.clone())
+}
+
+/// Restorer of db checkpoints from the bucket of the node, refusing those of other chains
+async fn node_restorer(
+    config: &NodeConfig,
+    download_concurrency: NonZeroUsize,
+    bandwidth_limiter: BandwidthLimiter,
+) -> Result<DBCheckpointRestorer> {
+    let chain_identifier = ChainIdentifier::from(*config.genesis()?.checkpoint().digest());
+    Ok(
+        bucket_restorer(config, download_concurrency, bandwidth_limiter)
+            .await?
+            .with_chain_identifier(chain_identifier),
+    )
+}
+
+/// Restorer of the buckets of the db checkpoint config of the node, set up like the node
+/// restores them except for the check of the chain of the restored epochs
+async fn bucket_restorer(
+    config: &NodeConfig,
+    download_concurrency: NonZeroUsize,
+    bandwidth_limiter: BandwidthLimiter,
+) -> Result<DBCheckpointRestorer> {
+    let bucket = config
+        .db_checkpoint_config
+        .object_store_config
+        .as_ref()
+        .ok_or_else(|| anyhow!("No object store configured in db-checkpoint-config"))?;
+    // Replicas of the bucket are restored from when the bucket is missing or lagging epochs
+    let replica_configs: Vec<_> = std::iter::once(bucket)
+        .chain(
+            config
+                .db_checkpoint_config
+                .replica_object_store_configs
+                .iter(),
+        )
+        .map(|config| config.read_only())
+        .collect();
+    let mut restorer = DBCheckpointRestorer::new(&replica_configs, download_concurrency)?
+        .with_staging_area(StagingArea::from_node_config(config))
+        .with_bandwidth_limiter(bandwidth_limiter)
+        .with_fsync(config.db_checkpoint_config.restore_fsync());
+    if let Some(encryption) = &config.db_checkpoint_config.encryption {
+        restorer = restorer.with_encryption_key(EncryptionKey::load(encryption).await?);
+    }
+    if let Some(parallelism) = config
+        .db_checkpoint_config
+        .digest_parallelism
+        .and_then(NonZeroUsize::new)
+    {
+        restorer = restorer.with_digest_pool(DigestPool::new(parallelism));
+    }
+    Ok(restorer)
+}
+
+fn ensure_empty_db_path(db_path: &Path) -> Result<()> {
+    if db_path.exists() && fs::read_dir(db_path)?.next().is_some() {
+        return Err(anyhow!(
+            "Refusing to restore into non empty db path: {}",
+            db_path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Restores the db checkpoint of `epoch`, or of the latest epoch complete in the bucket of the
+/// node, into the db path of the node so that a fresh node starts from it instead of genesis.
+/// With `resume`, files left in the db path by an interrupted restore are kept when they match
+/// the manifest of the epoch.
+pub async fn restore_db_checkpoint(
+    config: &NodeConfig,
+    epoch: Option<u32>,
+    download_concurrency: NonZeroUsize,
+    resume: bool,
+) -> Result<()> {
+    let db_path = config.db_path();
+    if !resume {
+        ensure_empty_db_path(&db_path)?;
+    }
+    let bandwidth_limiter = restore_bandwidth_limiter(config);
+    let progress = RestoreProgress::default();
+    let restorer = node_restorer(config, download_concurrency, bandwidth_limiter.clone())
+        .await?
+        .with_progress(progress.clone());
+    let admin_server = spawn_restore_admin_server(
+        bandwidth_limiter,
+        progress,
+        restore_admin_auth(config),
+        config.admin_interface_port,
+    );
+    let report = match epoch {
+        Some(epoch) => restorer.restore_epoch(epoch, &db_path).await,
+        None => restorer.restore_latest_epoch(&db_path).await,
+    };
+    admin_server.abort();
+    let report = report?;
+    info!(
+        "Restored db checkpoint for epoch: {} into {}, files: {}, repaired: {}, resumed: {}",
+        report.epoch,
+        db_path.display(),
+        report.served_by.len(),
+        report.corrupted.len(),
+        report.resumed.len()
+    );
+    Ok(())
+}
+
+/// Restores the db checkpoint of `epoch` into the db of the node and syncs the checkpoints
+/// after it from the state archive up to `checkpoint`. The node config stopping execution at
+/// `checkpoint` is written to `output_config_path`, so that the node started with it holds the
+/// state as of exactly that checkpoint.
+pub async fn restore_to_checkpoint(
+    config: &NodeConfig,
+    epoch: u32,
+    checkpoint: CheckpointSequenceNumber,
+    download_concurrency: NonZeroUsize,
+    output_config_path: &Path,
+) -> Result<()> {
+    let db_path = config.db_path();
+    ensure_empty_db_path(&db_path)?;
+    let genesis = config.genesis()?;
+    let bandwidth_limiter = restore_bandwidth_limiter(config);
+    let progress = RestoreProgress::default();
+    let restorer = node_restorer(config, download_concurrency, bandwidth_limiter.clone())
+        .await?
+        .with_progress(progress.clone());
+    let admin_server = spawn_restore_admin_server(
+        bandwidth_limiter,
+        progress,
+        restore_admin_auth(config),
+        config.admin_interface_port,
+    );
+    let result = restorer.restore_epoch(epoch, &db_path).await;
+    admin_server.abort();
+    result?;
+
+    let checkpoint_store = Arc::new(CheckpointStore::open_tables_read_write(
+        db_path.join("checkpoints"),
+        MetricConf::default(),
+        None,
+        None,
+    ));
+    let last_executed = checkpoint_store
+        .get_highest_executed_checkpoint()?
+        .ok_or_else(|| anyhow!("Db checkpoint for epoch: {epoch} has no executed checkpoint"))?;
+    if last_executed.epoch() != epoch as u64 {
+        return Err(anyhow!(
+            "Db checkpoint for epoch: {epoch} was executed up to epoch: {}",
+            last_executed.epoch()
+        ));
+    }
+    if checkpoint < *last_executed.sequence_number() {
+        return Err(anyhow!(
+            "Checkpoint: {checkpoint} precedes the end of epoch: {epoch} at checkpoint: {}, restore an earlier epoch",
+            last_executed.sequence_number()
+        ));
+    }
+    let highest_synced = checkpoint_store
+        .get_highest_synced_checkpoint()?
+        .map(|c| c.sequence_number)
+        .unwrap_or(0);
+    info!(
+        "Restored epoch: {epoch} executed up to checkpoint: {}, synced up to checkpoint: {highest_synced}",
+        last_executed.sequence_number()
+    );
+    if checkpoint > highest_synced {
+        let archive_reader_config = config
+            .archive_reader_config()
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No state archive in state-archive-read-config to replay"))?;
+        let archive_reader = ArchiveReader::new(archive_reader_config)?;
+        archive_reader.sync_manifest_once().await?;
+        let latest_checkpoint_in_archive = archive_reader.latest_available_checkpoint().await?;
+        if latest_checkpoint_in_archive < checkpoint {
+            return Err(anyhow!(
+                "Checkpoint: {checkpoint} is past the latest checkpoint in archive: {latest_checkpoint_in_archive}"
+            ));
+        }
+        let perpetual_db = Arc::new(AuthorityPerpetualTables::open(&db_path.join("store"), None));
+        let committee_store = Arc::new(CommitteeStore::new(
+            db_path.join("epochs"),
+            &genesis.committee()?,
+            None,
+        ));
+        let store = AuthorityStore::open(
+            perpetual_db,
+            genesis,
+            &committee_store,
+            usize::MAX,
+            false,
+            &Registry::default(),
+        )
+        .await?;
+        let state_sync_store = RocksDbStore::new(store, committee_store, checkpoint_store.clone());
+        info!(
+            "Replaying checkpoints {}..={checkpoint} from archive",
+            highest_synced + 1
+        );
+        archive_reader
+            .read(
+                state_sync_store,
+                highest_synced + 1..checkpoint + 1,
+                Arc::new(AtomicU64::new(0)),
+                Arc::new(AtomicU64::new(0)),
+            )
+            .await?;
+    }
+
+    let mut config = config.clone();
+    config.checkpoint_executor_config.stop_at_checkpoint = Some(checkpoint);
+    config.save(output_config_path)?;
+    println!(
+        "Restored epoch: {epoch} and synced up to checkpoint: {checkpoint}, start the node with {} to execute up to it",
+        output_config_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bucket_restorer;
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use sui_config::node::DBCheckpointUploadLayout;
+    use sui_config::{Config, NodeConfig};
+    use sui_core::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+    use sui_core::db_checkpoint_handler::digest_pool::DigestPool;
+    use sui_core::db_checkpoint_handler::manifest::{write_manifest, EpochManifest};
+    use sui_core::db_checkpoint_handler::SUCCESS_MARKER;
+    use sui_storage::object_store::{ObjectStoreConfig, ObjectStoreType};
+    use tempfile::TempDir;
+
+    const NODE_CONFIG: &str = include_str!("../../sui-config/data/fullnode-template.yaml");
+
+    #[tokio::test]
+    async fn test_resume_restore_with_staging_area() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let bucket = dir.path().join("bucket");
+        fs::create_dir_all(bucket.join("epoch_0").join("data"))?;
+        fs::write(bucket.join("epoch_0").join("file1"), b"Lorem ipsum")?;
+        fs::write(
+            bucket.join("epoch_0").join("data").join("file2"),
+            b"Lorem ipsum",
+        )?;
+        let store = ObjectStoreConfig {
+            object_store: Some(ObjectStoreType::File),
+            directory: Some(bucket.clone()),
+            ..Default::default()
+        }
+        .make()?;
+        let epoch_dir = object_store::path::Path::from("epoch_0");
+        let mut manifest = EpochManifest::from_local_dir(
+            0,
+            &epoch_dir,
+            &epoch_dir,
+            store.clone(),
+            DBCheckpointUploadLayout::Mirrored,
+            &[],
+        )
+        .await?;
+        manifest
+            .compute_sha3_digests(&epoch_dir, &bucket, &DigestPool::default())
+            .await?;
+        write_manifest(&manifest, &epoch_dir, store).await?;
+        fs::write(bucket.join("epoch_0").join(SUCCESS_MARKER), b"success")?;
+
+        // The staging area of the node defaults to a directory next to its db
+        let config_path = dir.path().join("fullnode.yaml");
+        let node_config = NODE_CONFIG.replace(
+            "db-path: \"suidb\"",
+            &format!("db-path: {}", dir.path().join("db").display()),
+        );
+        fs::write(
+            &config_path,
+            format!(
+                "{node_config}
+db-checkpoint-config:
+  object-store-config:
+    object-store: File
+    directory: {}
+",
+                bucket.display()
+            ),
+        )?;
+        let config = NodeConfig::load(&config_path)?;
+        let restorer = bucket_restorer(
+            &config,
+            NonZeroUsize::new(2).unwrap(),
+            BandwidthLimiter::default(),
+        )
+        .await?;
+
+        // Interrupted after restoring a file, with another one half written
+        let db_path = config.db_path();
+        fs::create_dir_all(db_path.join("data"))?;
+        fs::write(db_path.join("file1"), b"Lorem ipsum")?;
+        fs::write(db_path.join("data").join("file2"), b"Lorem")?;
+        let report = restorer.restore_epoch(0, &db_path).await?;
+        assert!(report.resumed.contains("file1"));
+        assert_eq!(
+            fs::read(db_path.join("data").join("file2"))?,
+            b"Lorem ipsum"
+        );
+
+        // Fresh restores go through the staging area, which is left empty
+        fs::remove_dir_all(&db_path)?;
+        let report = restorer.restore_epoch(0, &db_path).await?;
+        assert!(report.resumed.is_empty());
+        assert_eq!(fs::read(db_path.join("file1"))?, b"Lorem ipsum");
+        assert!(config.staging_path().exists());
+        assert!(fs::read_dir(config.staging_path())?.next().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Admin endpoint of the restores run by sui-tool. It is served on the admin interface port of
+//! the node being restored, which isn't running while its db is restored.
+//!
+//! Requests need a token granted the action by the `admin-tokens` of the db-checkpoint-config of
+//! the node. Without `admin-tokens` the endpoint isn't served, so that no local client can lift
+//! the bandwidth limit of the restore.
+//!
+//! Example commands:
+//!
+//! View the progress of the restore, as a readiness probe which only succeeds once the restore
+//! completed, with `read-status`:
+//!
+//!   $ curl -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/restore-progress'
+//!
+//! View the bandwidth limit of the restore, empty when unlimited, with `read-status`:
+//!
+//!   $ curl -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/restore-rate-limit'
+//!
+//! Limit the restore to 50 MB/s, or lift the limit, with `set-restore-rate`:
+//!
+//!   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' \
+//!       'http://127.0.0.1:1337/restore-rate-limit?bytes_per_sec=50000000'
+//!   $ curl -X POST -H 'Authorization: Bearer <TOKEN>' 'http://127.0.0.1:1337/restore-rate-limit'
+
+use axum::{
+    extract::{Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use sui_config::node::StorageAdminAction;
+use sui_core::db_checkpoint_handler::admin_auth::{AccessDenied, StorageAdminAuth};
+use sui_core::db_checkpoint_handler::bandwidth::BandwidthLimiter;
+use sui_core::db_checkpoint_handler::restorer::{RestoreProgress, RestoreProgressReport};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+const RESTORE_RATE_LIMIT_ROUTE: &str = "/restore-rate-limit";
+const RESTORE_PROGRESS_ROUTE: &str = "/restore-progress";
+
+struct AppState {
+    limiter: BandwidthLimiter,
+    progress: RestoreProgress,
+    auth: StorageAdminAuth,
+}
+
+/// Serves the admin endpoint adjusting `limiter` and reporting `progress` on localhost at
+/// `port`, for the holders of tokens of `auth`, until the returned task is aborted. The restore
+/// goes on without it when the port is taken or `auth` has no tokens.
+pub fn spawn_restore_admin_server(
+    limiter: BandwidthLimiter,
+    progress: RestoreProgress,
+    auth: StorageAdminAuth,
+    port: u16,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        if !auth.is_enabled() {
+            info!("No admin tokens configured, not starting restore admin server");
+            return;
+        }
+        let app = Router::new()
+            .route(RESTORE_RATE_LIMIT_ROUTE, get(get_rate_limit))
+            .route(RESTORE_RATE_LIMIT_ROUTE, post(set_rate_limit))
+            .route(RESTORE_PROGRESS_ROUTE, get(get_progress))
+            .with_state(Arc::new(AppState {
+                limiter,
+                progress,
+                auth,
+            }));
+        let socket_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+        let server = match axum::Server::try_bind(&socket_address) {
+            Ok(server) => server,
+            Err(err) => {
+                warn!("Failed to start restore admin server on {socket_address}: {err}");
+                return;
+            }
+        };
+        info!(address =% socket_address, "starting restore admin server");
+        if let Err(err) = server.serve(app.into_make_service()).await {
+            warn!("Restore admin server failed: {err}");
+        }
+    })
+}
+
+/// Checks the admin token of a request, returns the name of its holder
+fn authorize(
+    state: &AppState,
+    headers: &HeaderMap,
+    action: StorageAdminAction,
+) -> Result<String, (StatusCode, String)> {
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    state
+        .auth
+        .authorize(authorization, action)
+        .map(str::to_string)
+        .map_err(|denied| {
+            let status = match denied {
+                AccessDenied::Unauthenticated => StatusCode::UNAUTHORIZED,
+                AccessDenied::Forbidden => StatusCode::FORBIDDEN,
+            };
+            (status, format!("{denied}\n"))
+        })
+}
+
+async fn get_rate_limit(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> (StatusCode, String) {
+    if let Err(denied) = authorize(&state, &headers, StorageAdminAction::ReadStatus) {
+        return denied;
+    }
+    let limit = state
+        .limiter
+        .limit()
+        .map(|limit| limit.to_string())
+        .unwrap_or_default();
+    (StatusCode::OK, format!("{limit}\n"))
+}
+
+async fn get_progress(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> (StatusCode, String) {
+    if let Err(denied) = authorize(&state, &headers, StorageAdminAction::ReadStatus) {
+        return denied;
+    }
+    progress_response(state.progress.report())
+}
+
+/// Percent, ETA and files of the restore, with a `503` until the restore completed so that
+/// the endpoint serves as a readiness probe
+fn progress_response(report: Option<RestoreProgressReport>) -> (StatusCode, String) {
+    match report {
+        Some(report) if report.is_complete() => (StatusCode::OK, format!("{report}\n")),
+        Some(report) => (StatusCode::SERVICE_UNAVAILABLE, format!("{report}\n")),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "restore not started yet\n".to_string(),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct RateLimit {
+    bytes_per_sec: Option<u64>,
+}
+
+async fn set_rate_limit(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    rate_limit: Query<RateLimit>,
+) -> (StatusCode, String) {
+    let holder = match authorize(&state, &headers, StorageAdminAction::SetRestoreRate) {
+        Ok(holder) => holder,
+        Err(denied) => return denied,
+    };
+    let Query(RateLimit { bytes_per_sec }) = rate_limit;
+    state.limiter.set_limit(bytes_per_sec);
+    info!(
+        bytes_per_sec =? state.limiter.limit(),
+        holder =% holder,
+        "Restore rate limit updated"
+    );
+    match state.limiter.limit() {
+        Some(limit) => (
+            StatusCode::OK,
+            format!("restore rate limit set to '{limit}' bytes/s\n"),
+        ),
+        None => (StatusCode::OK, "restore rate limit lifted\n".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::progress_response;
+    use axum::http::StatusCode;
+    use std::time::Duration;
+    use sui_core::db_checkpoint_handler::restorer::RestoreProgressReport;
+
+    #[test]
+    fn test_progress_response() {
+        let (status, body) = progress_response(None);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, "restore not started yet\n");
+
+        let mut report = RestoreProgressReport {
+            epoch: 3,
+            total_files: 4,
+            total_bytes: 400,
+            restored_files: 1,
+            restored_bytes: 100,
+            elapsed: Duration::from_secs(10),
+        };
+        let (status, body) = progress_response(Some(report.clone()));
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body,
+            "epoch: 3, 25.0% (100 of 400 bytes, 1 of 4 files), ETA: 30s\n"
+        );
+
+        report.restored_files = 4;
+        report.restored_bytes = 400;
+        let (status, body) = progress_response(Some(report));
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("epoch: 3, 100.0%"));
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Restore rehearsal: a db checkpoint restored from the bucket is diffed against a live,
+//! healthy reference node of the same network. Checkpoint watermarks, sampled objects and
+//! recent transactions of the restored db are looked up on the reference node over json rpc.
+//! The reference node has moved past the restored state, so objects are compared at their
+//! restored version rather than at their latest one. Entries the reference node can't serve,
+//! e.g. object versions it pruned or lookups that failed, are reported as unverifiable rather
+//! than as divergences.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::path::Path;
+use sui_core::db_checkpoint_handler::expectations::RestoreExpectations;
+use sui_json_rpc_types::{
+    CheckpointId, SuiObjectDataOptions, SuiPastObjectResponse, SuiTransactionBlockResponseOptions,
+};
+use sui_sdk::SuiClient;
+use sui_types::messages_checkpoint::CheckpointSequenceNumber;
+use tracing::info;
+
+/// An entry of the restored db which the reference node doesn't agree with
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Divergence {
+    /// Table of the restored db the entry was read from
+    pub table: String,
+    pub key: String,
+    pub restored: String,
+    /// Value served by the reference node, `None` if it didn't serve one
+    pub reference: Option<String>,
+}
+
+/// An entry of the restored db the reference node couldn't be asked about
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Unverifiable {
+    pub table: String,
+    pub key: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RehearsalReport {
+    pub epoch: u32,
+    /// Highest executed checkpoint of the restored db
+    pub restored_checkpoint: CheckpointSequenceNumber,
+    /// Latest checkpoint of the reference node when the rehearsal started
+    pub reference_checkpoint: CheckpointSequenceNumber,
+    pub num_compared: usize,
+    pub divergences: Vec<Divergence>,
+    pub unverifiable: Vec<Unverifiable>,
+}
+
+impl RehearsalReport {
+    pub fn is_diverged(&self) -> bool {
+        !self.divergences.is_empty()
+    }
+    fn compare(
+        &mut self,
+        table: &str,
+        key: impl ToString,
+        restored: impl ToString,
+        reference: Option<String>,
+    ) {
+        self.num_compared += 1;
+        let restored = restored.to_string();
+        if reference.as_ref() != Some(&restored) {
+            self.divergences.push(Divergence {
+                table: table.to_string(),
+                key: key.to_string(),
+                restored,
+                reference,
+            });
+        }
+    }
+    fn unverifiable(&mut self, table: &str, key: impl ToString, reason: impl ToString) {
+        self.unverifiable.push(Unverifiable {
+            table: table.to_string(),
+            key: key.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// Samples up to `num_samples` objects and transactions of the db restored at
+/// `restored_db_path` and diffs them, along with its checkpoint watermark, against the
+/// reference node served by `client`
+pub async fn diff_against_reference(
+    client: &SuiClient,
+    restored_db_path: &Path,
+    epoch: u32,
+    num_samples: usize,
+) -> Result<RehearsalReport> {
+    let restored = RestoreExpectations::sample(restored_db_path, num_samples)?
+        .ok_or_else(|| anyhow!("Restored db of epoch: {epoch} holds no executed checkpoint"))?;
+    let read_api = client.read_api();
+    let mut report = RehearsalReport {
+        epoch,
+        restored_checkpoint: restored.latest_checkpoint,
+        reference_checkpoint: read_api.get_latest_checkpoint_sequence_number().await?,
+        ..Default::default()
+    };
+
+    // A reference node behind the restored db can't vouch for it
+    if report.reference_checkpoint < report.restored_checkpoint {
+        return Err(anyhow!(
+            "Reference node is at checkpoint {}, behind the restored db at {}",
+            report.reference_checkpoint,
+            report.restored_checkpoint
+        ));
+    }
+    match read_api
+        .get_checkpoint(CheckpointId::SequenceNumber(restored.latest_checkpoint))
+        .await
+    {
+        Ok(checkpoint) => report.compare(
+            "certified_checkpoints",
+            restored.latest_checkpoint,
+            restored.latest_checkpoint_digest,
+            Some(checkpoint.digest.to_string()),
+        ),
+        Err(err) => report.unverifiable("certified_checkpoints", restored.latest_checkpoint, err),
+    }
+
+    for (object_id, version, digest) in restored.objects.iter() {
+        let key = format!("{object_id}@{version}");
+        match read_api
+            .try_get_parsed_past_object(*object_id, *version, SuiObjectDataOptions::new())
+            .await
+        {
+            Ok(SuiPastObjectResponse::VersionFound(object)) => {
+                report.compare("objects", key, digest, Some(object.digest.to_string()))
+            }
+            // Pruned by the reference node, or deleted at a version it kept in place of this one
+            Ok(
+                response @ (SuiPastObjectResponse::VersionNotFound(..)
+                | SuiPastObjectResponse::ObjectDeleted(_)),
+            ) => report.unverifiable("objects", key, format!("{response:?}")),
+            Ok(response) => report.compare("objects", key, digest, Some(format!("{response:?}"))),
+            Err(err) => report.unverifiable("objects", key, err),
+        }
+    }
+
+    for (digest, checkpoint) in restored.transactions.iter() {
+        match read_api
+            .get_transaction_with_options(*digest, SuiTransactionBlockResponseOptions::new())
+            .await
+        {
+            Ok(response) => report.compare(
+                "executed_transactions_to_checkpoint",
+                digest,
+                checkpoint,
+                response.checkpoint.map(|checkpoint| checkpoint.to_string()),
+            ),
+            Err(err) => report.unverifiable("executed_transactions_to_checkpoint", digest, err),
+        }
+    }
+    info!(
+        "Compared {} entries of the restored db of epoch: {epoch} with the reference node, {} diverged, {} could not be verified",
+        report.num_compared,
+        report.divergences.len(),
+        report.unverifiable.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Divergence, RehearsalReport, Unverifiable};
+
+    #[test]
+    fn test_compare() {
+        let mut report = RehearsalReport::default();
+        report.compare("objects", "0x1@3", "digest", Some("digest".to_string()));
+        assert!(!report.is_diverged());
+
+        report.compare("objects", "0x2@4", "digest", Some("other".to_string()));
+        report.compare("executed_transactions_to_checkpoint", "tx", 7, None);
+        report.unverifiable("objects", "0x3@5", "VersionNotFound");
+        assert!(report.is_diverged());
+        assert_eq!(report.num_compared, 3);
+        assert_eq!(
+            report.divergences,
+            vec![
+                Divergence {
+                    table: "objects".to_string(),
+                    key: "0x2@4".to_string(),
+                    restored: "digest".to_string(),
+                    reference: Some("other".to_string()),
+                },
+                Divergence {
+                    table: "executed_transactions_to_checkpoint".to_string(),
+                    key: "tx".to_string(),
+                    restored: "7".to_string(),
+                    reference: None,
+                },
+            ]
+        );
+        assert_eq!(
+            report.unverifiable,
+            vec![Unverifiable {
+                table: "objects".to_string(),
+                key: "0x3@5".to_string(),
+                reason: "VersionNotFound".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unverifiable_entries_do_not_diverge() {
+        let mut report = RehearsalReport::default();
+        report.unverifiable("certified_checkpoints", 12, "connection refused");
+        assert!(!report.is_diverged());
+        assert_eq!(report.num_compared, 0);
+        assert_eq!(report.unverifiable.len(), 1);
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Smoke test of a restored db checkpoint: a fullnode is started in a sandbox against a copy of
+//! the restored db, with no peers so its state can't move, and a suite of read RPCs is checked
+//! against the expectations recorded in the epoch manifest at upload time.
+
+use crate::copy_dir_all;
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use sui_config::local_ip_utils::{localhost_for_testing, new_local_tcp_socket_for_testing};
+use sui_config::node::DBCheckpointConfig;
+use sui_config::{Config, NodeConfig};
+use sui_core::db_checkpoint_handler::expectations::RestoreExpectations;
+use sui_core::db_checkpoint_handler::staging::{dir_size, StagingArea};
+use sui_json_rpc_types::{CheckpointId, SuiObjectDataOptions, SuiTransactionBlockResponseOptions};
+use sui_sdk::{SuiClient, SuiClientBuilder};
+use sui_types::multiaddr::Multiaddr;
+use tokio::process::Command;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+const SANDBOX_CONFIG_FILENAME: &str = "fullnode.yaml";
+
+#[derive(Debug, Default)]
+pub struct SmokeTestReport {
+    pub num_checks: usize,
+    /// Description of every check that failed
+    pub failures: Vec<String>,
+}
+
+impl SmokeTestReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+    fn check(&mut self, passed: bool, failure: impl FnOnce() -> String) {
+        self.num_checks += 1;
+        if !passed {
+            self.failures.push(failure());
+        }
+    }
+}
+
+pub struct RestoreSmokeTest {
+    /// `sui-node` binary started in the sandbox
+    node_binary: PathBuf,
+    /// Fullnode config of the network the db belongs to, used as a template
+    template_config: NodeConfig,
+    startup_timeout: Duration,
+}
+
+impl RestoreSmokeTest {
+    pub fn new(
+        node_binary: PathBuf,
+        template_config: NodeConfig,
+        startup_timeout: Duration,
+    ) -> Self {
+        RestoreSmokeTest {
+            node_binary,
+            template_config,
+            startup_timeout,
+        }
+    }
+    /// Starts a fullnode against a copy of the db restored at `restored_db_path` and checks the
+    /// responses of its RPCs against `expectations`
+    pub async fn run(
+        &self,
+        restored_db_path: &Path,
+        expectations: &RestoreExpectations,
+    ) -> Result<SmokeTestReport> {
+        // The sandbox holds a full copy of the db, which must fit the staging area
+        let sandbox = StagingArea::from_node_config(&self.template_config)
+            .create("smoke-test", dir_size(restored_db_path)?)?;
+        let config = self.sandbox_config(sandbox.path())?;
+        // The node writes to its db, so it gets a copy to keep the restored db pristine
+        copy_dir_all(restored_db_path, config.db_path(), vec![])?;
+        let config_path = sandbox.path().join(SANDBOX_CONFIG_FILENAME);
+        config.save(&config_path)?;
+
+        info!(
+            "Starting sandboxed fullnode with json rpc at {}",
+            config.json_rpc_address
+        );
+        let mut node = Command::new(&self.node_binary)
+            .arg("--config-path")
+            .arg(&config_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.node_binary.display()))?;
+        let result = async {
+            let client = self
+                .wait_for_rpc(&format!("http://{}", config.json_rpc_address))
+                .await?;
+            check_expectations(&client, expectations).await
+        }
+        .await;
+        if let Err(err) = node.kill().await {
+            warn!("Failed to stop sandboxed fullnode: {:?}", err);
+        }
+        result
+    }
+    /// Template config rewritten to run in `sandbox` on local ports without peers, archives or
+    /// db checkpoints
+    fn sandbox_config(&self, sandbox: &Path) -> Result<NodeConfig> {
+        let mut config = self.template_config.clone();
+        if config.consensus_config.is_some() {
+            return Err(anyhow!("Smoke tests need a fullnode config"));
+        }
+        let localhost = localhost_for_testing();
+        config.db_path = sandbox.join("db");
+        config.network_address = format!(
+            "/ip4/{localhost}/tcp/{}/http",
+            new_local_tcp_socket_for_testing().port()
+        )
+        .parse::<Multiaddr>()?;
+        config.json_rpc_address = new_local_tcp_socket_for_testing();
+        config.metrics_address = new_local_tcp_socket_for_testing();
+        config.admin_interface_port = new_local_tcp_socket_for_testing().port();
+        config.p2p_config.listen_address = new_local_tcp_socket_for_testing();
+        config.p2p_config.external_address = None;
+        config.p2p_config.seed_peers = vec![];
+        config.db_checkpoint_config = DBCheckpointConfig::default();
+        config.state_archive_write_config = Default::default();
+        config.state_archive_read_config = vec![];
+        config.metrics = None;
+        Ok(config)
+    }
+    async fn wait_for_rpc(&self, url: &str) -> Result<SuiClient> {
+        let deadline = Instant::now() + self.startup_timeout;
+        loop {
+            if let Ok(client) = SuiClientBuilder::default().build(url).await {
+                return Ok(client);
+            }
+            if Instant::now() > deadline {
+                return Err(anyhow!(
+                    "Sandboxed fullnode didn't serve json rpc at {url} within {:?}",
+                    self.startup_timeout
+                ));
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// Runs the read RPC suite against `client`
+pub async fn check_expectations(
+    client: &SuiClient,
+    expectations: &RestoreExpectations,
+) -> Result<SmokeTestReport> {
+    let mut report = SmokeTestReport::default();
+    let read_api = client.read_api();
+
+    let latest = read_api.get_latest_checkpoint_sequence_number().await?;
+    report.check(latest >= expectations.latest_checkpoint, || {
+        format!(
+            "Latest checkpoint {latest} is behind the restored one {}",
+            expectations.latest_checkpoint
+        )
+    });
+    match read_api
+        .get_checkpoint(CheckpointId::SequenceNumber(expectations.latest_checkpoint))
+        .await
+    {
+        Ok(checkpoint) => report.check(
+            checkpoint.digest == expectations.latest_checkpoint_digest,
+            || {
+                format!(
+                    "Checkpoint {} has digest {}, expected {}",
+                    expectations.latest_checkpoint,
+                    checkpoint.digest,
+                    expectations.latest_checkpoint_digest
+                )
+            },
+        ),
+        Err(err) => report.check(false, || {
+            format!(
+                "Failed to fetch checkpoint {}: {err}",
+                expectations.latest_checkpoint
+            )
+        }),
+    }
+
+    for expected in expectations.objects.iter() {
+        let response = read_api
+            .get_object_with_options(expected.0, SuiObjectDataOptions::new())
+            .await;
+        let actual = response
+            .as_ref()
+            .ok()
+            .and_then(|r| r.object_ref_if_exists());
+        report.check(actual.as_ref() == Some(expected), || {
+            format!(
+                "Object {} is {:?}, expected {:?}",
+                expected.0, actual, expected
+            )
+        });
+    }
+
+    for (digest, checkpoint) in expectations.transactions.iter() {
+        match read_api
+            .get_transaction_with_options(*digest, SuiTransactionBlockResponseOptions::new())
+            .await
+        {
+            Ok(response) => report.check(
+                response.digest == *digest && response.checkpoint == Some(*checkpoint),
+                || {
+                    format!(
+                        "Transaction {digest} is in checkpoint {:?}, expected {checkpoint}",
+                        response.checkpoint
+                    )
+                },
+            ),
+            Err(err) => report.check(false, || {
+                format!("Failed to fetch transaction {digest}: {err}")
+            }),
+        }
+    }
+    info!(
+        "Ran {} smoke test checks, {} failed",
+        report.num_checks,
+        report.failures.len()
+    );
+    Ok(report)
+}
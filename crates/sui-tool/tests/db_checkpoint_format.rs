@@ -0,0 +1,23 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+#[test]
+#[cfg_attr(msim, ignore)]
+fn test_db_checkpoint_format() {
+    // If this test breaks and you intended a db checkpoint format change, or bumped the version,
+    // you need to run to get the fresh description:
+    // # cargo -q run --example generate-db-checkpoint-format -- record
+    let status = std::process::Command::new("cargo")
+        .current_dir("..")
+        .args(["run", "--example", "generate-db-checkpoint-format", "--"])
+        .arg("test")
+        .status()
+        .expect("failed to execute process");
+    assert!(
+        status.success(),
+        "\n\
+If this test breaks and you intended a db checkpoint format change, you need to run to get the fresh description:\n\
+cargo -q run --example generate-db-checkpoint-format -- record\n\
+        "
+    );
+}
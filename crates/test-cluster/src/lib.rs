@@ -636,6 +636,7 @@ impl TestClusterBuilder {
             object_store_config: None,
             perform_index_db_checkpoints_at_epoch_end: None,
             prune_and_compact_before_upload: None,
+            ..Default::default()
         };
         self
     }
@@ -647,6 +648,7 @@ impl TestClusterBuilder {
             object_store_config: None,
             perform_index_db_checkpoints_at_epoch_end: None,
             prune_and_compact_before_upload: Some(true),
+            ..Default::default()
         };
         self
     }